@@ -0,0 +1,95 @@
+// src/acl.rs
+
+use std::collections::{HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use crate::crypto::{PublicKey, Signature};
+use log::info;
+
+pub const CLIENTS_FILE: &str = "clients.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientConfig {
+    pub client_id: String,
+    pub public_key: String, // 十六进制编码的ed25519公钥
+    pub allowed_operations: Vec<String>, // 允许的操作类型，"*"表示全部
+}
+
+#[derive(Debug)]
+pub enum AclError {
+    UnknownClient(String),
+    InvalidSignature(String),
+    Forbidden { client_id: String, operation_type: String },
+}
+
+impl std::fmt::Display for AclError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AclError::UnknownClient(id) => write!(f, "未注册的客户端: {}", id),
+            AclError::InvalidSignature(id) => write!(f, "客户端{}的签名无效", id),
+            AclError::Forbidden { client_id, operation_type } => {
+                write!(f, "客户端{}无权提交{}类型的操作", client_id, operation_type)
+            }
+        }
+    }
+}
+
+struct ClientEntry {
+    public_key: PublicKey,
+    allowed_operations: HashSet<String>,
+}
+
+pub struct ClientRegistry {
+    clients: HashMap<String, ClientEntry>,
+}
+
+impl ClientRegistry {
+    // 没有客户端配置文件时ACL关闭，接受匿名请求
+    pub fn load() -> Self {
+        let mut clients = HashMap::new();
+        if let Ok(data) = std::fs::read_to_string(CLIENTS_FILE) {
+            let configs: Vec<ClientConfig> = serde_json::from_str(&data).unwrap();
+            for config in configs {
+                let public_key = PublicKey::from_hex(&config.public_key).unwrap();
+                clients.insert(config.client_id, ClientEntry {
+                    public_key,
+                    allowed_operations: config.allowed_operations.into_iter().collect(),
+                });
+            }
+            info!("从{}加载了{}个客户端身份", CLIENTS_FILE, clients.len());
+        }
+        ClientRegistry { clients }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.clients.is_empty()
+    }
+
+    pub fn verify(&self, client_id: &str, payload: &[u8], signature: &Signature) -> Result<(), AclError> {
+        let entry = self.clients.get(client_id)
+            .ok_or_else(|| AclError::UnknownClient(client_id.to_string()))?;
+        if entry.public_key.verify(payload, signature) {
+            Ok(())
+        } else {
+            Err(AclError::InvalidSignature(client_id.to_string()))
+        }
+    }
+
+    pub fn authorize(&self, client_id: &str, operation: &str) -> Result<(), AclError> {
+        let entry = self.clients.get(client_id)
+            .ok_or_else(|| AclError::UnknownClient(client_id.to_string()))?;
+        let operation_type = operation_type(operation);
+        if entry.allowed_operations.contains("*") || entry.allowed_operations.contains(operation_type) {
+            Ok(())
+        } else {
+            Err(AclError::Forbidden {
+                client_id: client_id.to_string(),
+                operation_type: operation_type.to_string(),
+            })
+        }
+    }
+}
+
+// 操作类型为操作字符串的第一个单词，例如 "SET key value" 的类型为 "SET"
+pub fn operation_type(operation: &str) -> &str {
+    operation.split_whitespace().next().unwrap_or("")
+}
@@ -0,0 +1,212 @@
+// src/address_book.rs
+
+// 对等节点地址簿：节点为每个对等节点记录拨号地址、最近一次握手成功的时间、最近收到其消息的时间、
+// 最近看到的高度（检查点）和握手时告知的协议版本，保存在node_<ID>_peers.json中（先写临时文件再改名）。
+// 重启后按最近握手成功的先后重新发起握手，doctor先拨地址簿中的地址；peers list子命令离线查看
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use chrono::{Local, TimeZone};
+use log::warn;
+use serde::{Serialize, Deserialize};
+use crate::storage;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PeerRecord {
+    pub addresses: Vec<String>, // 节点目录中登记的地址，未登记时为默认地址
+    pub last_dialed: Option<i64>, // 最近一次握手成功的Unix毫秒
+    pub last_seen: Option<i64>, // 最近一次收到其消息的Unix毫秒
+    pub height: Option<u64>, // 最近一次检查点的高度
+    pub protocol_version: Option<u32>, // 旧版本节点握手时不告知
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AddressBook {
+    pub peers: BTreeMap<usize, PeerRecord>,
+    #[serde(skip)]
+    dirty: bool, // 上次保存后有变化
+}
+
+pub fn filename(directory: &Path, node_id: usize) -> PathBuf {
+    directory.join(format!("node_{}_peers.json", node_id))
+}
+
+impl AddressBook {
+    // 文件不存在或损坏时从空的地址簿开始，握手后重新建立
+    pub fn load(directory: &Path, node_id: usize) -> Self {
+        let path = filename(directory, node_id);
+        match std::fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                warn!("节点{}的地址簿{}无法解析，重新建立: {}", node_id, path.display(), e);
+                AddressBook::default()
+            }),
+            Err(_) => AddressBook::default(),
+        }
+    }
+
+    // 只在有变化时写盘
+    pub fn save(&mut self, directory: &Path, node_id: usize) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let data = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        storage::write_atomically(&filename(directory, node_id), &data, false)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    // 握手成功：更新地址、握手时间和协议版本
+    pub fn dialed(&mut self, node_id: usize, addresses: Vec<String>, now_ms: i64, protocol_version: Option<u32>) {
+        let record = self.peers.entry(node_id).or_default();
+        record.addresses = addresses;
+        record.last_dialed = Some(now_ms);
+        record.last_seen = Some(now_ms);
+        record.protocol_version = protocol_version;
+        self.dirty = true;
+    }
+
+    pub fn seen(&mut self, node_id: usize, now_ms: i64) {
+        self.peers.entry(node_id).or_default().last_seen = Some(now_ms);
+        self.dirty = true;
+    }
+
+    pub fn seen_height(&mut self, node_id: usize, height: u64) {
+        let record = self.peers.entry(node_id).or_default();
+        record.height = Some(record.height.map_or(height, |known| known.max(height)));
+        self.dirty = true;
+    }
+
+    // 重新连接的顺序：最近握手成功的在前，从未握手成功的按节点ID排在最后
+    pub fn reconnect_order(&self, peers: impl IntoIterator<Item = usize>) -> Vec<usize> {
+        let mut peers: Vec<usize> = peers.into_iter().collect();
+        peers.sort_by_key(|peer| (std::cmp::Reverse(self.peers.get(peer).and_then(|record| record.last_dialed)), *peer));
+        peers
+    }
+
+    // 拨号地址：地址簿中的地址在前，其后是给定的默认地址
+    pub fn dial_addresses(&self, node_id: usize, defaults: Vec<String>) -> Vec<String> {
+        let mut addresses = self.peers.get(&node_id).map(|record| record.addresses.clone()).unwrap_or_default();
+        addresses.extend(defaults.into_iter().filter(|address| !addresses.contains(address)).collect::<Vec<_>>());
+        addresses
+    }
+}
+
+fn format_time(ms: Option<i64>) -> String {
+    ms.and_then(|ms| Local.timestamp_millis_opt(ms).single())
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+pub fn render(node_id: usize, book: &AddressBook) -> String {
+    let mut lines = vec![format!("节点{}的地址簿：{}个对等节点", node_id, book.peers.len())];
+    lines.push(format!("{:<6}{:<22}{:<22}{:>8}{:>6}  {}", "节点", "最近握手", "最近消息", "高度", "协议", "地址"));
+    for (peer, record) in &book.peers {
+        let height = record.height.map(|height| height.to_string()).unwrap_or_else(|| "-".to_string());
+        let version = record.protocol_version.map(|version| version.to_string()).unwrap_or_else(|| "-".to_string());
+        lines.push(format!("{:<6}{:<22}{:<22}{:>8}{:>6}  {}", peer, format_time(record.last_dialed), format_time(record.last_seen), height, version, record.addresses.join(", ")));
+    }
+    lines.join("\n")
+}
+
+// 命令行入口：peers list [--node 节点ID] [--json]，缺省列出当前目录中全部节点的地址簿
+pub fn run(args: &[String]) -> i32 {
+    if args.first().map(String::as_str) != Some("list") {
+        eprintln!("用法: pbft-blockchain peers list [--node 节点ID] [--json]");
+        return 2;
+    }
+    let node = match args.iter().position(|s| s == "--node").map(|i| args.get(i + 1).and_then(|id| id.parse::<usize>().ok())) {
+        Some(Some(node)) => Some(node),
+        Some(None) => {
+            eprintln!("--node必须是节点ID");
+            return 2;
+        }
+        None => None,
+    };
+    let nodes: Vec<usize> = match node {
+        Some(node) => vec![node],
+        None => match std::fs::read_dir(".") {
+            Ok(entries) => {
+                let mut nodes: Vec<usize> = entries.flatten()
+                    .filter_map(|entry| entry.file_name().to_str()?.strip_prefix("node_")?.strip_suffix("_peers.json")?.parse().ok())
+                    .collect();
+                nodes.sort_unstable();
+                nodes
+            }
+            Err(e) => {
+                eprintln!("无法读取当前目录: {}", e);
+                return 1;
+            }
+        },
+    };
+    if nodes.is_empty() || !nodes.iter().all(|node| filename(Path::new("."), *node).exists()) {
+        eprintln!("当前目录中没有地址簿（node_<ID>_peers.json）");
+        return 1;
+    }
+    let json = args.iter().any(|s| s == "--json");
+    for node in nodes {
+        let book = AddressBook::load(Path::new("."), node);
+        if json {
+            println!("{}", serde_json::json!({ "node_id": node, "peers": book.peers }));
+        } else {
+            println!("{}\n", render(node, &book));
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    // 地址簿保存后能读回，只在有变化时写盘；重新连接时最近握手成功的节点在前
+    #[test]
+    fn persists_and_orders_peers() {
+        let directory = std::env::temp_dir().join(format!("pbft-peers-{}-{}", std::process::id(), rand::random::<u32>()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut book = AddressBook::load(&directory, 0);
+        assert!(book.peers.is_empty());
+        book.dialed(2, vec!["10.0.0.2:8002".to_string()], 1_000, Some(1));
+        book.dialed(1, vec!["10.0.0.1:8001".to_string()], 2_000, None);
+        book.seen(3, 3_000);
+        book.seen_height(2, 10);
+        book.seen_height(2, 7);
+        book.save(&directory, 0).unwrap();
+
+        let mut loaded = AddressBook::load(&directory, 0);
+        assert_eq!(loaded, book);
+        assert_eq!(loaded.peers[&2].height, Some(10));
+        assert_eq!(loaded.reconnect_order([3, 2, 1]), vec![1, 2, 3]);
+        assert_eq!(loaded.dial_addresses(2, vec!["127.0.0.1:8002".to_string(), "10.0.0.2:8002".to_string()]), vec!["10.0.0.2:8002", "127.0.0.1:8002"]);
+        assert!(render(0, &loaded).contains("10.0.0.2:8002"));
+
+        std::fs::remove_file(filename(&directory, 0)).unwrap();
+        loaded.save(&directory, 0).unwrap();
+        assert!(!filename(&directory, 0).exists(), "没有变化时不应写盘");
+        std::fs::write(filename(&directory, 0), "{").unwrap();
+        assert!(AddressBook::load(&directory, 0).peers.is_empty());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // 集群中的节点握手后把对等节点记入地址簿，关闭时写盘，重启后从地址簿恢复
+    #[tokio::test]
+    async fn cluster_records_handshakes_in_the_address_book() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut cluster = TestCluster::builder().build().await;
+            cluster.submit("SET booked yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, "SET booked yes").is_some())).await;
+            assert!(committed, "请求未提交");
+            cluster.restart(0).await;
+
+            let book = AddressBook::load(Path::new("."), 0);
+            assert_eq!(book.peers.keys().copied().collect::<Vec<_>>(), (1..N).collect::<Vec<_>>());
+            assert!(book.peers.values().all(|record| record.last_dialed.is_some() && record.last_seen.is_some() && !record.addresses.is_empty()));
+            assert!(book.peers.values().all(|record| record.protocol_version == Some(crate::config::PROTOCOL_VERSION)));
+
+            cluster.submit("SET rebooked yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| c.committed_view(0, "SET rebooked yes").is_some()).await;
+            assert!(committed, "重启的节点未能重新连上对等节点");
+        }).await;
+    }
+}
@@ -0,0 +1,36 @@
+// src/admission.rs
+
+use crate::config::MAX_OPERATION_SIZE;
+use crate::node::NodeState;
+
+// 应用层交易准入策略：在进入待处理队列时检查一次，
+// 副本在验证PrePrepare时对批次中的每个操作再检查一次
+pub trait AdmissionPolicy: Send {
+    // 无状态检查：只依赖操作本身
+    fn check_stateless(&self, operation: &str) -> Result<(), String>;
+
+    // 有状态检查：可以读取节点当前状态
+    fn check_stateful(&self, _operation: &str, _state: &NodeState) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn check(&self, operation: &str, state: &NodeState) -> Result<(), String> {
+        self.check_stateless(operation)?;
+        self.check_stateful(operation, state)
+    }
+}
+
+// 默认策略：拒绝空操作和超长操作
+pub struct DefaultAdmissionPolicy;
+
+impl AdmissionPolicy for DefaultAdmissionPolicy {
+    fn check_stateless(&self, operation: &str) -> Result<(), String> {
+        if operation.trim().is_empty() {
+            return Err("操作为空".to_string());
+        }
+        if operation.len() > MAX_OPERATION_SIZE {
+            return Err(format!("操作长度{}超过上限{}", operation.len(), MAX_OPERATION_SIZE));
+        }
+        Ok(())
+    }
+}
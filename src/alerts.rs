@@ -0,0 +1,382 @@
+// src/alerts.rs
+
+// 运维告警：无人值守的测试集群出现严重问题时主动通知运维人员。以下事件触发告警：
+// 节点被拉黑、短时间内反复切换视图、执行状态与法定人数分叉、磁盘将满、能联系到的验证者不足法定人数。
+// 告警以JSON POST到node_config.json中配置的webhook，也可以经SMTP发送邮件；
+// 同一类事件（对同一节点）在ALERT_COOLDOWN_SECS内只告警一次，投递失败只记录日志，不影响共识
+use std::collections::{HashMap, VecDeque};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, Instant};
+use log::{error, warn};
+use crate::config::{
+    N, ALERT_COOLDOWN_SECS, ALERT_VIEW_CHANGES, ALERT_VIEW_CHANGE_WINDOW_SECS, ALERT_DISK_FREE_PERCENT, ALERT_PEER_SILENCE_MS,
+};
+use crate::metrics;
+use crate::quorum::COMMIT_QUORUM;
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    #[serde(default)]
+    pub webhooks: Vec<String>, // http://主机:端口/路径，每条告警POST一个JSON对象
+    #[serde(default)]
+    pub email: Option<EmailConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub smtp: String, // 主机:端口，须允许本机不经认证转发
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl AlertConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(webhook) = self.webhooks.iter().find(|webhook| !webhook.starts_with("http://")) {
+            return Err(format!("告警webhook'{}'无效，只支持http://地址", webhook));
+        }
+        if self.email.as_ref().is_some_and(|email| email.to.is_empty()) {
+            return Err("告警邮件没有收件人".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    PeerBlacklisted,
+    RepeatedViewChanges,
+    StateDivergence,
+    DiskNearlyFull,
+    PeerQuorumLost,
+}
+
+pub struct Alerts {
+    node_id: usize,
+    config: AlertConfig,
+    raised: HashMap<(AlertKind, Option<usize>), Instant>, // 每类告警（及涉及的节点）最近一次发出的时间
+    view_changes: VecDeque<Instant>, // 窗口内的视图切换时间
+    peers_seen: HashMap<usize, Instant>, // 各验证者最近一次应答Ping的时间
+    since: Instant, // 启动时间，尚未应答过的验证者从此时开始计算静默
+}
+
+impl Alerts {
+    pub fn new(node_id: usize, now: Instant) -> Self {
+        Alerts {
+            node_id,
+            config: AlertConfig::default(),
+            raised: HashMap::new(),
+            view_changes: VecDeque::new(),
+            peers_seen: HashMap::new(),
+            since: now,
+        }
+    }
+
+    pub fn configure(&mut self, config: AlertConfig) {
+        self.config = config;
+    }
+
+    // 发出告警，返回是否发出（冷却期内的重复告警被抑制）；subject为告警涉及的节点
+    pub fn raise(&mut self, kind: AlertKind, subject: Option<usize>, message: String, now: Instant) -> bool {
+        let cooldown = Duration::from_secs(ALERT_COOLDOWN_SECS);
+        if self.raised.get(&(kind, subject)).is_some_and(|last| now.duration_since(*last) < cooldown) {
+            return false;
+        }
+        self.raised.insert((kind, subject), now);
+        warn!("节点{}告警 {:?}: {}", self.node_id, kind, message);
+        metrics::inc_counter(&format!("alerts_raised_total{{kind=\"{:?}\"}}", kind), 1);
+        let alert = json!({
+            "node_id": self.node_id,
+            "kind": kind,
+            "subject": subject,
+            "message": message,
+            "time": chrono::Local::now().to_rfc3339(),
+        });
+        for webhook in &self.config.webhooks {
+            tokio::spawn(deliver(webhook.clone(), post(webhook.clone(), alert.to_string())));
+        }
+        if let Some(email) = &self.config.email {
+            let subject = format!("[pbft] 节点{} {:?}", self.node_id, kind);
+            tokio::spawn(deliver(email.smtp.clone(), send_mail(email.clone(), subject, message)));
+        }
+        true
+    }
+
+    // 进入新视图；窗口内的切换达到ALERT_VIEW_CHANGES次时告警
+    pub fn view_changed(&mut self, view: u64, now: Instant) {
+        let window = Duration::from_secs(ALERT_VIEW_CHANGE_WINDOW_SECS);
+        self.view_changes.push_back(now);
+        while self.view_changes.front().is_some_and(|at| now.duration_since(*at) > window) {
+            self.view_changes.pop_front();
+        }
+        if self.view_changes.len() >= ALERT_VIEW_CHANGES {
+            let message = format!("{}秒内切换了{}次视图，当前视图{}", ALERT_VIEW_CHANGE_WINDOW_SECS, self.view_changes.len(), view);
+            self.raise(AlertKind::RepeatedViewChanges, None, message, now);
+        }
+    }
+
+    pub fn peer_alive(&mut self, peer: usize, now: Instant) {
+        self.peers_seen.insert(peer, now);
+    }
+
+    // 定期检查：能联系到的验证者（含本节点）不足法定人数，或工作目录所在磁盘将满
+    pub fn check_health(&mut self, now: Instant) {
+        let silence = Duration::from_millis(ALERT_PEER_SILENCE_MS);
+        let silent: Vec<usize> = (0..N)
+            .filter(|peer| *peer != self.node_id)
+            .filter(|peer| now.duration_since(*self.peers_seen.get(peer).unwrap_or(&self.since)) > silence)
+            .collect();
+        if N - silent.len() < COMMIT_QUORUM {
+            let message = format!("验证者{:?}超过{}ms没有应答，能联系到的验证者不足法定人数{}", silent, ALERT_PEER_SILENCE_MS, COMMIT_QUORUM);
+            self.raise(AlertKind::PeerQuorumLost, None, message, now);
+        }
+        if let Some(free) = disk_free_percent().filter(|free| *free < ALERT_DISK_FREE_PERCENT) {
+            self.raise(AlertKind::DiskNearlyFull, None, format!("工作目录所在磁盘只剩{}%可用空间", free), now);
+        }
+    }
+}
+
+async fn deliver(destination: String, delivery: impl std::future::Future<Output = Result<(), String>>) {
+    match tokio::time::timeout(DELIVERY_TIMEOUT, delivery).await {
+        Ok(Ok(())) => metrics::inc_counter("alerts_delivered_total", 1),
+        Ok(Err(e)) => {
+            error!("告警投递到{}失败: {}", destination, e);
+            metrics::inc_counter("alert_delivery_failures_total", 1);
+        }
+        Err(_) => {
+            error!("告警投递到{}超时", destination);
+            metrics::inc_counter("alert_delivery_failures_total", 1);
+        }
+    }
+}
+
+async fn post(webhook: String, body: String) -> Result<(), String> {
+    let rest = webhook.strip_prefix("http://").ok_or("只支持http://地址")?;
+    let (authority, path) = rest.split_once('/').map_or((rest, "/".to_string()), |(authority, path)| (authority, format!("/{}", path)));
+    let mut stream = TcpStream::connect(authority).await.map_err(|e| e.to_string())?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, authority, body.len(), body
+    );
+    stream.write_all(request.as_bytes()).await.map_err(|e| e.to_string())?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.map_err(|e| e.to_string())?;
+    let status_line = String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string();
+    if status_line.split_whitespace().nth(1).is_some_and(|code| code.starts_with('2')) {
+        Ok(())
+    } else {
+        Err(format!("webhook返回: {}", status_line))
+    }
+}
+
+// 最简单的SMTP会话：HELO、MAIL FROM、RCPT TO、DATA，每一步都须得到2xx或3xx应答
+async fn send_mail(email: EmailConfig, subject: String, body: String) -> Result<(), String> {
+    let stream = TcpStream::connect(&email.smtp).await.map_err(|e| e.to_string())?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    expect_reply(&mut reader).await?;
+    let mut commands = vec!["HELO pbft-node".to_string(), format!("MAIL FROM:<{}>", email.from)];
+    commands.extend(email.to.iter().map(|to| format!("RCPT TO:<{}>", to)));
+    commands.push("DATA".to_string());
+    commands.push(format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n.",
+        email.from, email.to.join(", "), encode_subject(&subject), dot_stuff(&body)
+    ));
+    commands.push("QUIT".to_string());
+    for command in commands {
+        writer.write_all(format!("{}\r\n", command).as_bytes()).await.map_err(|e| e.to_string())?;
+        expect_reply(&mut reader).await?;
+    }
+    Ok(())
+}
+
+// 邮件头只能是ASCII：非ASCII的主题按RFC 2047编码为UTF-8的Base64编码字，
+// 每个编码字不超过75个字符且不拆开多字节字符，编码字之间折行
+fn encode_subject(subject: &str) -> String {
+    if subject.bytes().all(|b| (b' '..=b'~').contains(&b)) {
+        return subject.to_string();
+    }
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in subject.chars() {
+        // 45字节编码后是60个字符，加上"=?UTF-8?B?"和"?="共72个
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(format!("=?UTF-8?B?{}?=", base64(chunk.as_bytes())));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(format!("=?UTF-8?B?{}?=", base64(chunk.as_bytes())));
+    words.join("\r\n ")
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for group in data.chunks(3) {
+        let bits = group.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// DATA中单独一行"."表示邮件结束：正文中以"."开头的行前面再加一个"."，换行统一为CRLF
+fn dot_stuff(body: &str) -> String {
+    body.lines()
+        .map(|line| if line.starts_with('.') { format!(".{}", line) } else { line.to_string() })
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+// 读取一条（可能多行的）SMTP应答
+async fn expect_reply(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>) -> Result<(), String> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.map_err(|e| e.to_string())? == 0 {
+            return Err("SMTP服务器关闭了连接".to_string());
+        }
+        if !line.starts_with('2') && !line.starts_with('3') {
+            return Err(format!("SMTP服务器返回: {}", line.trim_end()));
+        }
+        // 多行应答除最后一行外，状态码后是'-'
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn disk_free_percent() -> Option<u64> {
+    let path = std::ffi::CString::new(".").ok()?;
+    // 安全性：statvfs只写入传入的结构体，路径是以NUL结尾的C字符串
+    let stat = unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return None;
+        }
+        stat
+    };
+    (stat.f_blocks > 0).then(|| stat.f_bavail as u64 * 100 / stat.f_blocks as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn disk_free_percent() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use serde_json::Value;
+    use tokio::net::TcpListener;
+    use crate::byzantine::Strategy;
+    use crate::testing::{NodeSetup, TestCluster};
+
+    #[tokio::test]
+    async fn thresholds_and_cooldown() {
+        let start = Instant::now();
+        let mut alerts = Alerts::new(0, start);
+        assert!(alerts.raise(AlertKind::PeerBlacklisted, Some(3), "拉黑".to_string(), start));
+        assert!(!alerts.raise(AlertKind::PeerBlacklisted, Some(3), "拉黑".to_string(), start + Duration::from_secs(1)));
+        assert!(alerts.raise(AlertKind::PeerBlacklisted, Some(2), "拉黑".to_string(), start + Duration::from_secs(1)));
+        assert!(alerts.raise(AlertKind::PeerBlacklisted, Some(3), "拉黑".to_string(), start + Duration::from_secs(ALERT_COOLDOWN_SECS)));
+
+        for view in 1..ALERT_VIEW_CHANGES as u64 {
+            alerts.view_changed(view, start + Duration::from_secs(view));
+        }
+        assert!(!alerts.raised.contains_key(&(AlertKind::RepeatedViewChanges, None)));
+        alerts.view_changed(ALERT_VIEW_CHANGES as u64, start + Duration::from_secs(ALERT_VIEW_CHANGES as u64));
+        assert!(alerts.raised.contains_key(&(AlertKind::RepeatedViewChanges, None)));
+
+        // 一个验证者静默仍有法定人数，两个静默则告警
+        let later = start + Duration::from_millis(ALERT_PEER_SILENCE_MS + 1);
+        alerts.peer_alive(1, later);
+        alerts.peer_alive(2, later);
+        alerts.check_health(later);
+        assert!(!alerts.raised.contains_key(&(AlertKind::PeerQuorumLost, None)));
+        alerts.peer_alive(2, start);
+        alerts.check_health(later);
+        assert!(alerts.raised.contains_key(&(AlertKind::PeerQuorumLost, None)));
+
+        assert!(AlertConfig { webhooks: vec!["https://example.com".to_string()], email: None }.validate().is_err());
+    }
+
+    #[test]
+    fn mail_subject_and_body_are_encoded_for_smtp() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode_subject("[pbft] PeerBlacklisted"), "[pbft] PeerBlacklisted");
+        assert_eq!(encode_subject("节点0被拉黑"), format!("=?UTF-8?B?{}?=", base64("节点0被拉黑".as_bytes())));
+
+        // 长主题拆成多个编码字，每个都不超过75个字符，解码后拼接即原文
+        let long = "视图切换".repeat(10);
+        let encoded = encode_subject(&long);
+        let words: Vec<&str> = encoded.split("\r\n ").collect();
+        assert!(words.len() > 1);
+        assert!(words.iter().all(|word| word.len() <= 75 && word.starts_with("=?UTF-8?B?") && word.ends_with("?=")));
+
+        assert_eq!(dot_stuff("第一行\n.\n..两个点\r\n末行"), "第一行\r\n..\r\n...两个点\r\n末行");
+    }
+
+    // 分叉的主节点被诚实节点拉黑，每个诚实节点都向webhook发出一条涉及节点0的告警
+    #[tokio::test]
+    async fn blacklisting_fires_webhook() {
+        tokio::task::LocalSet::new().run_until(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let webhook = format!("http://{}/alerts", listener.local_addr().unwrap());
+            let received: Arc<Mutex<Vec<Value>>> = Arc::default();
+            let sink = received.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut reader = BufReader::new(reader);
+                    let mut length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).await.unwrap();
+                        if let Some(value) = line.strip_prefix("Content-Length: ") {
+                            length = value.trim().parse().unwrap();
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    let mut body = vec![0u8; length];
+                    reader.read_exact(&mut body).await.unwrap();
+                    sink.lock().unwrap().push(serde_json::from_slice(&body).unwrap());
+                    writer.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+                }
+            });
+
+            let alerts = AlertConfig { webhooks: vec![webhook], email: None };
+            let mut builder = TestCluster::builder().byzantine(0, Strategy::EquivocatingPrimary).timeout(Duration::from_millis(300));
+            for id in 1..N {
+                builder = builder.setup(id, NodeSetup { alerts: alerts.clone(), ..NodeSetup::default() });
+            }
+            let cluster = builder.build().await;
+            cluster.submit("SET k v").await;
+            let alerted = cluster.wait_until(Duration::from_secs(10), |_| {
+                let received = received.lock().unwrap();
+                (1..N).all(|id| received.iter().any(|alert| {
+                    alert["node_id"] == json!(id) && alert["kind"] == json!("PeerBlacklisted") && alert["subject"] == json!(0)
+                }))
+            }).await;
+            assert!(alerted, "诚实节点未发出拉黑告警: {:?}", received.lock().unwrap());
+        }).await;
+    }
+}
@@ -0,0 +1,73 @@
+// src/app_counter.rs
+
+// 计数器应用：每个键保存一个十进制的有符号整数，不存在的键视为0。
+//   INCR <键> [增量]   增加（缺省为1），返回新值
+//   DECR <键> [减量]   减少（缺省为1），返回新值
+//   GET <键>           读取当前值
+// 溢出或键中不是整数时操作失败，状态不变
+use crate::application::Application;
+use crate::execution::ExecutionStatus;
+use crate::message::Transaction;
+use crate::quota::Sandbox;
+
+pub const NAME: &str = "counter";
+
+pub struct Counter;
+
+impl Application for Counter {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn apply(&self, store: &mut Sandbox, _tx: &Transaction, operation: &str) -> ExecutionStatus {
+        let parts: Vec<&str> = operation.split(' ').collect();
+        let (sign, key, amount) = match parts[..] {
+            ["GET", key] => return ExecutionStatus::Success(Some(store.get(key).cloned().unwrap_or_else(|| "0".to_string()))),
+            ["INCR", key] => (1, key, Some("1")),
+            ["DECR", key] => (-1, key, Some("1")),
+            ["INCR", key, amount] => (1, key, Some(amount)),
+            ["DECR", key, amount] => (-1, key, Some(amount)),
+            _ => (0, "", None),
+        };
+        let amount = match amount.map(str::parse::<i64>) {
+            Some(Ok(amount)) => amount,
+            Some(Err(_)) => return ExecutionStatus::Failed(format!("增量必须是整数: {}", operation)),
+            None => return ExecutionStatus::Failed(format!("无法识别的操作: {}", operation)),
+        };
+        let current = match store.get(key).map(|value| value.parse::<i64>()) {
+            Some(Ok(current)) => current,
+            Some(Err(_)) => return ExecutionStatus::Failed(format!("键{}的值不是整数", key)),
+            None => 0,
+        };
+        match amount.checked_mul(sign).and_then(|delta| current.checked_add(delta)) {
+            Some(value) => {
+                store.insert(key.to_string(), value.to_string());
+                ExecutionStatus::Success(Some(value.to_string()))
+            }
+            None => ExecutionStatus::Failed(format!("键{}的计数溢出", key)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::quota::Quota;
+
+    #[test]
+    fn counts_and_rejects_overflow() {
+        let mut state = BTreeMap::new();
+        let mut store = Sandbox::new(&mut state, Quota::default());
+        let tx = Transaction { operation: String::new(), client_id: None, session: None, timestamp: None };
+        let mut run = |operation: &str| Counter.apply(&mut store, &tx, operation);
+        assert_eq!(run("INCR hits"), ExecutionStatus::Success(Some("1".to_string())));
+        assert_eq!(run("INCR hits 41"), ExecutionStatus::Success(Some("42".to_string())));
+        assert_eq!(run("DECR hits 50"), ExecutionStatus::Success(Some("-8".to_string())));
+        assert_eq!(run("GET misses"), ExecutionStatus::Success(Some("0".to_string())));
+        assert!(matches!(run("INCR hits x"), ExecutionStatus::Failed(_)));
+        assert!(matches!(run(&format!("DECR hits {}", i64::MAX)), ExecutionStatus::Failed(_)));
+        assert!(matches!(run("SET hits 1"), ExecutionStatus::Failed(_)));
+        assert_eq!(run("GET hits"), ExecutionStatus::Success(Some("-8".to_string())));
+    }
+}
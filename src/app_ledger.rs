@@ -0,0 +1,105 @@
+// src/app_ledger.rs
+
+// 代币账本应用：余额保存在 ledger/<账户> 下，账户即客户端ID，匿名请求不能动用余额。
+//   MINT <账户> <数量>       发行：第一个发行的客户端成为发行者（记在 ledger.issuer），之后只有它能发行
+//   TRANSFER <账户> <数量>   从发起请求的客户端转给账户，余额不足时失败，返回转出方的余额
+//   BALANCE <账户>           查询余额
+// 数量为非负整数，余额溢出时操作失败。失败的操作不写入任何键
+use crate::application::Application;
+use crate::execution::ExecutionStatus;
+use crate::message::Transaction;
+use crate::quota::Sandbox;
+
+pub const NAME: &str = "ledger";
+pub const KEY_PREFIX: &str = "ledger/";
+const ISSUER_KEY: &str = "ledger.issuer";
+
+pub struct Ledger;
+
+fn balance_key(account: &str) -> String {
+    format!("{}{}", KEY_PREFIX, account)
+}
+
+fn balance(store: &Sandbox, account: &str) -> u64 {
+    store.get(&balance_key(account)).and_then(|value| value.parse().ok()).unwrap_or(0)
+}
+
+fn mint(store: &mut Sandbox, client: &str, account: &str, amount: u64) -> Result<u64, String> {
+    if let Some(issuer) = store.get(ISSUER_KEY).filter(|issuer| *issuer != client) {
+        return Err(format!("只有发行者{}能发行", issuer));
+    }
+    let minted = balance(store, account).checked_add(amount).ok_or_else(|| format!("账户{}的余额溢出", account))?;
+    store.insert(ISSUER_KEY.to_string(), client.to_string());
+    store.insert(balance_key(account), minted.to_string());
+    Ok(minted)
+}
+
+// 先检查两边的余额再写入，转给自己时余额不变
+fn transfer(store: &mut Sandbox, from: &str, to: &str, amount: u64) -> Result<u64, String> {
+    let available = balance(store, from);
+    let remaining = available.checked_sub(amount).ok_or_else(|| format!("账户{}的余额{}不足{}", from, available, amount))?;
+    if from == to {
+        return Ok(available);
+    }
+    let received = balance(store, to).checked_add(amount).ok_or_else(|| format!("账户{}的余额溢出", to))?;
+    store.insert(balance_key(from), remaining.to_string());
+    store.insert(balance_key(to), received.to_string());
+    Ok(remaining)
+}
+
+impl Application for Ledger {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn apply(&self, store: &mut Sandbox, tx: &Transaction, operation: &str) -> ExecutionStatus {
+        let parts: Vec<&str> = operation.split(' ').collect();
+        if let ["BALANCE", account] = parts[..] {
+            return ExecutionStatus::Success(Some(balance(store, account).to_string()));
+        }
+        let (command, account, amount) = match parts[..] {
+            [command @ ("MINT" | "TRANSFER"), account, amount] => (command, account, amount),
+            _ => return ExecutionStatus::Failed(format!("无法识别的操作: {}", operation)),
+        };
+        let amount = match amount.parse::<u64>() {
+            Ok(amount) => amount,
+            Err(_) => return ExecutionStatus::Failed(format!("数量必须是非负整数: {}", amount)),
+        };
+        let client = match tx.client_id.as_deref() {
+            Some(client) => client,
+            None => return ExecutionStatus::Failed("匿名请求不能发行或转账".to_string()),
+        };
+        let result = if command == "MINT" { mint(store, client, account, amount) } else { transfer(store, client, account, amount) };
+        match result {
+            Ok(balance) => ExecutionStatus::Success(Some(balance.to_string())),
+            Err(reason) => ExecutionStatus::Failed(reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::quota::Quota;
+
+    fn from(client: Option<&str>) -> Transaction {
+        Transaction { operation: String::new(), client_id: client.map(str::to_string), session: None, timestamp: None }
+    }
+
+    #[test]
+    fn only_the_issuer_mints_and_balances_cover_transfers() {
+        let mut state = BTreeMap::new();
+        let mut store = Sandbox::new(&mut state, Quota::default());
+        let mut run = |client: Option<&str>, operation: &str| Ledger.apply(&mut store, &from(client), operation);
+        assert_eq!(run(Some("bank"), "MINT alice 100"), ExecutionStatus::Success(Some("100".to_string())));
+        assert!(matches!(run(Some("alice"), "MINT alice 100"), ExecutionStatus::Failed(_)));
+        assert_eq!(run(Some("alice"), "TRANSFER bob 30"), ExecutionStatus::Success(Some("70".to_string())));
+        assert!(matches!(run(Some("bob"), "TRANSFER alice 31"), ExecutionStatus::Failed(_)));
+        assert!(matches!(run(None, "TRANSFER alice 1"), ExecutionStatus::Failed(_)));
+        assert!(matches!(run(Some("bank"), &format!("MINT bob {}", u64::MAX)), ExecutionStatus::Failed(_)));
+        assert_eq!(run(None, "BALANCE bob"), ExecutionStatus::Success(Some("30".to_string())));
+        assert_eq!(run(Some("alice"), "TRANSFER alice 70"), ExecutionStatus::Success(Some("70".to_string())));
+        assert_eq!(run(None, "BALANCE alice"), ExecutionStatus::Success(Some("70".to_string())));
+    }
+}
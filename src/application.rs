@@ -0,0 +1,136 @@
+// src/application.rs
+
+// 复制应用：执行引擎先处理系统操作（目录登记、治理、跨链、多签、命名空间、定时交易和随机信标），
+// 检查保留前缀和命名空间权限，其余操作交给创世配置的application选定的应用执行。应用是trait对象，
+// 编译进节点程序的应用登记在MODULES中，同一个节点程序不必重新编译共识代码就能运行不同的复制应用：
+//   kv       键值存储：SET、GET、DEL、APPEND（缺省，总是可用）
+//   counter  计数器：INCR、DECR、GET（cargo特性app-counter）
+//   ledger   代币账本：MINT、TRANSFER、BALANCE（cargo特性app-ledger）
+// 应用只能经Sandbox读写状态，写入按配额计量；执行结果必须只取决于状态和操作内容，各副本才能一致
+use crate::execution::ExecutionStatus;
+use crate::message::Transaction;
+use crate::quota::Sandbox;
+
+pub const DEFAULT_APPLICATION: &str = "kv";
+
+pub trait Application: Send {
+    fn name(&self) -> &'static str;
+    // 在沙箱中执行一个操作；tx是发起操作的交易，定时交易到期执行时仍是登记它的交易
+    fn apply(&self, store: &mut Sandbox, tx: &Transaction, operation: &str) -> ExecutionStatus;
+}
+
+type Constructor = fn() -> Box<dyn Application>;
+
+// 编译进节点程序的应用：名称 -> 构造函数
+const MODULES: &[(&str, Constructor)] = &[
+    (DEFAULT_APPLICATION, || Box::new(KvStore)),
+    #[cfg(feature = "app-counter")]
+    (crate::app_counter::NAME, || Box::new(crate::app_counter::Counter)),
+    #[cfg(feature = "app-ledger")]
+    (crate::app_ledger::NAME, || Box::new(crate::app_ledger::Ledger)),
+];
+
+pub fn names() -> Vec<&'static str> {
+    MODULES.iter().map(|(name, _)| *name).collect()
+}
+
+pub fn create(name: &str) -> Result<Box<dyn Application>, String> {
+    MODULES.iter()
+        .find(|(module, _)| *module == name)
+        .map(|(_, constructor)| constructor())
+        .ok_or_else(|| format!("节点程序中没有应用{}，可用的应用: {}", name, names().join(", ")))
+}
+
+pub struct KvStore;
+
+impl Application for KvStore {
+    fn name(&self) -> &'static str {
+        DEFAULT_APPLICATION
+    }
+
+    fn apply(&self, store: &mut Sandbox, _tx: &Transaction, operation: &str) -> ExecutionStatus {
+        apply_kv(store, operation)
+    }
+}
+
+// 普通的键值操作。多签账户授权的操作不论运行哪个应用都由此执行
+pub fn apply_kv(store: &mut Sandbox, operation: &str) -> ExecutionStatus {
+    let mut parts = operation.splitn(3, ' ');
+    let command = parts.next().unwrap_or("");
+    let key = parts.next();
+    let value = parts.next();
+    match (command, key, value) {
+        ("SET", Some(key), Some(value)) => {
+            store.insert(key.to_string(), value.to_string());
+            ExecutionStatus::Success(None)
+        }
+        ("GET", Some(key), None) => ExecutionStatus::Success(store.get(key).cloned()),
+        ("DEL", Some(key), None) => ExecutionStatus::Success(store.remove(key)),
+        ("APPEND", Some(key), Some(value)) => {
+            // 先检查拼接后的大小，超出上限时不分配新值
+            let current = store.get(key).map(String::as_str).unwrap_or("");
+            let size = current.len() + value.len();
+            if size > store.quota().value_size {
+                return ExecutionStatus::QuotaExceeded(format!("键{}的值将达到{}字节，超过上限{}", key, size, store.quota().value_size));
+            }
+            let appended = format!("{}{}", current, value);
+            store.insert(key.to_string(), appended);
+            ExecutionStatus::Success(None)
+        }
+        _ => ExecutionStatus::Failed(format!("无法识别的操作: {}", operation)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "app-ledger")]
+    use std::time::Duration;
+    #[cfg(feature = "app-ledger")]
+    use crate::config::N;
+    #[cfg(feature = "app-ledger")]
+    use crate::message::PBFTMessage;
+    #[cfg(feature = "app-ledger")]
+    use crate::qos::Priority;
+    #[cfg(feature = "app-ledger")]
+    use crate::testing::TestCluster;
+
+    #[test]
+    fn modules_are_selected_by_name() {
+        assert_eq!(names()[0], DEFAULT_APPLICATION);
+        assert_eq!(names().contains(&"ledger"), cfg!(feature = "app-ledger"));
+        for name in names() {
+            assert_eq!(create(name).unwrap().name(), name);
+        }
+        let error = create("chess").err().unwrap();
+        assert!(error.contains(&names().join(", ")), "{}", error);
+    }
+
+    // 同一个节点程序按创世配置运行代币账本：各副本的余额一致，键值操作不再可用
+    #[cfg(feature = "app-ledger")]
+    #[tokio::test]
+    async fn cluster_runs_the_configured_application() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().application("ledger").build().await;
+            for (timestamp, operation) in ["MINT alice 100", "SET alice 1000000"].iter().enumerate() {
+                cluster.submit_request(PBFTMessage::Request {
+                    operation: operation.to_string(),
+                    priority: Priority::Normal,
+                    client_id: Some("bank".to_string()),
+                    expires_at: None,
+                    session: None,
+                    timestamp: Some(timestamp as u64 + 1),
+                }).await;
+            }
+            let executed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET alice 1000000").is_some() && c.executions[id].lock().unwrap().applied_height() >= 1)
+            }).await;
+            assert!(executed, "操作未提交");
+            for id in 0..N {
+                let execution = cluster.executions[id].lock().unwrap();
+                assert_eq!(execution.get("ledger/alice"), Some(&"100".to_string()));
+                assert_eq!(execution.get("alice"), None, "账本应用不执行键值操作");
+            }
+        }).await;
+    }
+}
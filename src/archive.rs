@@ -0,0 +1,42 @@
+// src/archive.rs
+
+use std::collections::BTreeMap;
+use crate::acl::operation_type;
+use crate::chain::{Block, Chain};
+use crate::chain_index::TxLocation;
+
+// 归档节点在完整历史上维护的二级索引；按摘要和客户端ID的索引由所有节点的链维护（见chain_index）
+#[derive(Default)]
+pub struct ArchiveIndex {
+    by_operation_type: BTreeMap<String, Vec<TxLocation>>,
+}
+
+impl ArchiveIndex {
+    // 启动时从链上全部区块重建索引
+    pub fn build(chain: &Chain) -> Self {
+        let mut index = ArchiveIndex::default();
+        for block in &chain.blocks {
+            index.index_block(block);
+        }
+        index
+    }
+
+    pub fn index_block(&mut self, block: &Block) {
+        for (i, tx) in block.transactions.iter().enumerate() {
+            let location = TxLocation { height: block.header.height, index: i };
+            self.by_operation_type
+                .entry(operation_type(&tx.operation).to_string())
+                .or_default()
+                .push(location);
+        }
+    }
+
+    pub fn by_operation_type(&self, operation_type: &str) -> Vec<TxLocation> {
+        self.by_operation_type.get(operation_type).cloned().unwrap_or_default()
+    }
+
+    // 各操作类型的交易数量统计
+    pub fn operation_type_counts(&self) -> BTreeMap<String, usize> {
+        self.by_operation_type.iter().map(|(k, v)| (k.clone(), v.len())).collect()
+    }
+}
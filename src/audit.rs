@@ -0,0 +1,224 @@
+// src/audit.rs
+
+// 防篡改的审计日志：按时间顺序记录节点做出的每个共识决策，用于事后取证。
+// 每条记录包含前一条记录的哈希并由节点签名，修改、删除或插入任何一条都会使校验失败。
+// 节点每次启动都会生成新密钥，因此本次运行的第一条记录公布签名所用的公钥
+use std::fs::OpenOptions;
+use std::io::Write;
+use crate::crypto::{PublicKey, Signature, SigningKey};
+use serde::{Serialize, Deserialize};
+use log::error;
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event")]
+pub enum AuditEvent {
+    // 之后的记录由该公钥签名
+    Started { public_key: String },
+    AcceptedPrePrepare { view: u64, sequence_number: u64, digest: String, primary: usize },
+    // 本节点签名发出的PrePrepare、Prepare或Commit；to为空表示广播
+    SentVote { kind: String, view: u64, sequence_number: u64, digest: String, to: Option<usize> },
+    ViewChangeTriggered { from_view: u64, to_view: u64, reason: String },
+    Blacklisted { node_id: usize, voters: Vec<usize> },
+    // 被指控者的申诉成立，这些投票者对它的指控撤销
+    AccusationWithdrawn { node_id: usize, voters: Vec<usize> },
+    // 本节点在检查点的状态摘要与法定人数不一致
+    StateDivergence { height: u64, local_digest: String, quorum_digest: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub index: u64,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    pub prev_hash: String,
+    pub hash: String,
+    pub signature: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(index: u64, timestamp: &str, event: &AuditEvent, prev_hash: &str) -> String {
+        let mut data = prev_hash.as_bytes().to_vec();
+        data.extend_from_slice(&index.to_be_bytes());
+        data.extend_from_slice(timestamp.as_bytes());
+        data.extend_from_slice(&serde_json::to_vec(event).unwrap());
+        hex::encode(ring::digest::digest(&ring::digest::SHA256, &data).as_ref())
+    }
+}
+
+pub struct AuditLog {
+    path: String,
+    next_index: u64,
+    last_hash: String,
+    announced: bool, // 本次运行是否已公布签名公钥
+}
+
+impl AuditLog {
+    pub fn open(node_id: usize) -> Self {
+        AuditLog::at(format!("node_{}_audit.jsonl", node_id))
+    }
+
+    // 在已有日志之后继续追加；日志是否完好由verify检查，这里只取最后一条记录
+    pub fn at(path: String) -> Self {
+        let last = std::fs::read_to_string(&path).ok()
+            .and_then(|data| data.lines().last().and_then(|line| serde_json::from_str::<AuditEntry>(line).ok()));
+        let (next_index, last_hash) = match last {
+            Some(entry) => (entry.index + 1, entry.hash),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+        AuditLog { path, next_index, last_hash, announced: false }
+    }
+
+    pub fn record(&mut self, event: AuditEvent, signing_key: &SigningKey, timestamp: String) {
+        if !self.announced {
+            self.announced = true;
+            let started = AuditEvent::Started { public_key: signing_key.public_key().to_hex() };
+            self.append(started, signing_key, timestamp.clone());
+        }
+        self.append(event, signing_key, timestamp);
+    }
+
+    fn append(&mut self, event: AuditEvent, signing_key: &SigningKey, timestamp: String) {
+        let hash = AuditEntry::compute_hash(self.next_index, &timestamp, &event, &self.last_hash);
+        let signature = signing_key.sign(hash.as_bytes()).to_hex();
+        let entry = AuditEntry {
+            index: self.next_index,
+            timestamp,
+            event,
+            prev_hash: std::mem::replace(&mut self.last_hash, hash.clone()),
+            hash,
+            signature,
+        };
+        self.next_index += 1;
+
+        let line = serde_json::to_string(&entry).unwrap() + "\n";
+        let written = OpenOptions::new().create(true).append(true).open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(e) = written {
+            error!("写入审计日志{}失败: {}", self.path, e);
+        }
+    }
+}
+
+// 逐条检查哈希链和签名，返回记录条数；发现篡改时指出第一条有问题的记录
+pub fn verify(data: &str) -> Result<usize, String> {
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut signer: Option<PublicKey> = None;
+    let mut count = 0;
+    for (line_number, line) in data.lines().enumerate() {
+        let entry: AuditEntry = serde_json::from_str(line)
+            .map_err(|e| format!("第{}行无法解析: {}", line_number + 1, e))?;
+        if entry.index != line_number as u64 {
+            return Err(format!("第{}行的序号为{}，记录被删除或插入", line_number + 1, entry.index));
+        }
+        if entry.prev_hash != prev_hash {
+            return Err(format!("记录{}的前驱哈希与上一条记录不符", entry.index));
+        }
+        if AuditEntry::compute_hash(entry.index, &entry.timestamp, &entry.event, &entry.prev_hash) != entry.hash {
+            return Err(format!("记录{}的内容与哈希不符", entry.index));
+        }
+        if let AuditEvent::Started { public_key } = &entry.event {
+            let key = PublicKey::from_hex(public_key).map_err(|_| format!("记录{}的公钥无效", entry.index))?;
+            signer = Some(key);
+        }
+        let key = signer.ok_or_else(|| format!("记录{}之前没有公布签名公钥", entry.index))?;
+        let signed = Signature::from_hex(&entry.signature)
+            .is_ok_and(|signature| key.verify(entry.hash.as_bytes(), &signature));
+        if !signed {
+            return Err(format!("记录{}的签名无效", entry.index));
+        }
+        prev_hash = entry.hash;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub fn verify_file(node_id: usize) -> Result<usize, String> {
+    let path = format!("node_{}_audit.jsonl", node_id);
+    let data = std::fs::read_to_string(&path).map_err(|e| format!("无法读取{}: {}", path, e))?;
+    verify(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    fn temp_log() -> String {
+        let dir = std::env::temp_dir().join(format!("pbft-audit-{}-{}", std::process::id(), rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("audit.jsonl").to_string_lossy().to_string()
+    }
+
+    fn vote(sequence_number: u64) -> AuditEvent {
+        AuditEvent::SentVote { kind: "Prepare".to_string(), view: 0, sequence_number, digest: "ab".to_string(), to: None }
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let path = temp_log();
+        let signing_key = SigningKey::generate();
+        let mut log = AuditLog::at(path.clone());
+        for seq in 1..=3 {
+            log.record(vote(seq), &signing_key, format!("t{}", seq));
+        }
+        log.record(AuditEvent::Blacklisted { node_id: 3, voters: vec![0, 1, 2] }, &signing_key, "t4".to_string());
+
+        // 重启后用新密钥继续追加，哈希链不中断
+        let restarted = SigningKey::generate();
+        let mut log = AuditLog::at(path.clone());
+        log.record(AuditEvent::ViewChangeTriggered { from_view: 0, to_view: 1, reason: "超时".to_string() }, &restarted, "t5".to_string());
+
+        let data = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(verify(&data), Ok(7));
+
+        let lines: Vec<&str> = data.lines().collect();
+        let edited = data.replace("\"voters\":[0,1,2]", "\"voters\":[0,1]");
+        assert!(verify(&edited).unwrap_err().contains("记录4"));
+        let dropped = [&lines[..2], &lines[3..]].concat().join("\n");
+        assert!(verify(&dropped).is_err());
+        // 截掉末尾的记录无法由日志本身发现，需要与其他节点的日志或已提交的区块对照
+        let truncated = lines[..5].join("\n");
+        assert_eq!(verify(&truncated), Ok(5));
+
+        // 攻击者重新计算哈希也无法伪造签名
+        let mut entry: AuditEntry = serde_json::from_str(lines[6]).unwrap();
+        entry.event = AuditEvent::ViewChangeTriggered { from_view: 0, to_view: 1, reason: "主节点作恶".to_string() };
+        entry.hash = AuditEntry::compute_hash(entry.index, &entry.timestamp, &entry.event, &entry.prev_hash);
+        let forged = [&lines[..6], &[serde_json::to_string(&entry).unwrap().as_str()]].concat().join("\n");
+        assert!(verify(&forged).unwrap_err().contains("签名无效"));
+
+        let _ = std::fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn cluster_logs_verify_after_commit() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET k v").is_some())
+            }).await;
+            assert!(committed);
+
+            for id in 0..N {
+                let data = std::fs::read_to_string(format!("node_{}_audit.jsonl", id)).unwrap();
+                assert!(verify(&data).is_ok(), "节点{}的审计日志校验失败", id);
+                let events: Vec<AuditEvent> = data.lines().map(|line| serde_json::from_str::<AuditEntry>(line).unwrap().event).collect();
+                assert!(matches!(events[0], AuditEvent::Started { .. }));
+                let sent = |kind: &str| events.iter().any(|e| matches!(e, AuditEvent::SentVote { kind: k, .. } if k == kind));
+                if id == 0 {
+                    assert!(sent("PrePrepare"));
+                } else {
+                    assert!(events.iter().any(|e| matches!(e, AuditEvent::AcceptedPrePrepare { primary: 0, .. })));
+                    assert!(sent("Prepare"));
+                }
+            }
+        }).await;
+    }
+}
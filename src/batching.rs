@@ -0,0 +1,60 @@
+// src/batching.rs
+
+use tokio::time::Duration;
+use log::info;
+use crate::config::{
+    MIN_BATCH_SIZE, MAX_BATCH_SIZE, MIN_BATCH_TIMEOUT_MS, MAX_BATCH_TIMEOUT_MS,
+    COMMIT_LATENCY_TARGET_MS, MAX_QUEUE_DEPTH,
+};
+
+// 加性增、乘性减（AIMD）的批大小控制器：
+// 提交延迟低于目标时逐步增大批次，延迟超标或队列积压时减半
+pub struct BatchController {
+    batch_size: usize,
+    max_batch_size: usize, // 批大小上限，可由链上治理调整
+    batch_timeout_ms: u64,
+    latency_target: Duration,
+    max_queue_depth: usize,
+}
+
+impl BatchController {
+    pub fn new() -> Self {
+        BatchController {
+            batch_size: MIN_BATCH_SIZE,
+            max_batch_size: MAX_BATCH_SIZE,
+            batch_timeout_ms: MIN_BATCH_TIMEOUT_MS,
+            latency_target: Duration::from_millis(COMMIT_LATENCY_TARGET_MS),
+            max_queue_depth: MAX_QUEUE_DEPTH,
+        }
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn set_max_batch_size(&mut self, max_batch_size: usize) {
+        self.max_batch_size = max_batch_size.max(MIN_BATCH_SIZE);
+        self.batch_size = self.batch_size.min(self.max_batch_size);
+    }
+
+    pub fn batch_timeout(&self) -> Duration {
+        Duration::from_millis(self.batch_timeout_ms)
+    }
+
+    pub fn observe_commit(&mut self, latency: Duration, queue_depth: usize) {
+        let (old_size, old_timeout) = (self.batch_size, self.batch_timeout_ms);
+
+        if latency > self.latency_target || queue_depth > self.max_queue_depth {
+            self.batch_size = (self.batch_size / 2).max(MIN_BATCH_SIZE);
+            self.batch_timeout_ms = (self.batch_timeout_ms / 2).max(MIN_BATCH_TIMEOUT_MS);
+        } else {
+            self.batch_size = (self.batch_size + 1).min(self.max_batch_size);
+            self.batch_timeout_ms = (self.batch_timeout_ms + MIN_BATCH_TIMEOUT_MS).min(MAX_BATCH_TIMEOUT_MS);
+        }
+
+        if (old_size, old_timeout) != (self.batch_size, self.batch_timeout_ms) {
+            info!("批处理参数调整: 批大小 {} -> {}，批超时 {}ms -> {}ms（提交延迟{:?}，队列深度{}）",
+                old_size, self.batch_size, old_timeout, self.batch_timeout_ms, latency, queue_depth);
+        }
+    }
+}
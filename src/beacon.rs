@@ -0,0 +1,166 @@
+// src/beacon.rs
+
+// 随机信标：每个区块产生一个32字节的随机值，保存在复制状态中，键为 beacon/<高度>。
+// 主节点提议批次时把 BEACON <证明> 放在批次的第一笔交易，证明是它用签名私钥对上一个信标值做的
+// VRF（crypto::SigningKey::vrf_prove）。同一私钥对同一输入只有一个有效证明，主节点无法挑选输出；
+// 其他节点在证明公开前也无法预测。各副本用节点目录中的公钥验证证明，本区块的信标为
+//   SHA-256("pbft-beacon" || 上一个信标值 || VRF输出)
+// 证明缺失或无效时（例如主节点尚未在目录中登记）信标为 SHA-256("pbft-beacon" || 上一个信标值)，
+// 链照常推进，proposer记为空。主节点能做的只是在两个值之间选择：给出证明或不给，
+// 对随机性要求高的应用应先提交承诺，再使用之后某个高度的信标。创世信标由链ID导出
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::config::BEACON_HISTORY;
+use crate::crypto::{PublicKey, SigningKey, VrfProof};
+use crate::directory;
+use crate::quota::Sandbox;
+
+pub const KEY_PREFIX: &str = "beacon/";
+pub const BEACON_COMMAND: &str = "BEACON";
+const DOMAIN: &[u8] = b"pbft-beacon\0";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BeaconEntry {
+    pub height: u64,
+    pub value: String, // 十六进制
+    pub proposer: Option<usize>, // 提供有效VRF证明的验证者
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BeaconProof {
+    pub proposer: usize,
+    pub proof: String, // 十六进制的VRF证明
+}
+
+pub fn key(height: u64) -> String {
+    format!("{}{:020}", KEY_PREFIX, height)
+}
+
+fn sha256(parts: &[&[u8]]) -> String {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    for part in parts {
+        context.update(part);
+    }
+    hex::encode(context.finish().as_ref())
+}
+
+fn alpha(previous: &str) -> Vec<u8> {
+    let mut alpha = DOMAIN.to_vec();
+    alpha.extend_from_slice(previous.as_bytes());
+    alpha
+}
+
+pub fn get(store: &BTreeMap<String, String>, height: u64) -> Option<BeaconEntry> {
+    store.get(&key(height)).and_then(|data| serde_json::from_str(data).ok())
+}
+
+// 最新的信标；还没有区块时为由链ID导出的创世信标
+pub fn latest(store: &BTreeMap<String, String>, chain_id: &str) -> BeaconEntry {
+    store.range(KEY_PREFIX.to_string()..format!("{}~", KEY_PREFIX))
+        .next_back()
+        .and_then(|(_, data)| serde_json::from_str(data).ok())
+        .unwrap_or_else(|| BeaconEntry { height: 0, value: sha256(&[DOMAIN, chain_id.as_bytes()]), proposer: None })
+}
+
+// 主节点为下一个区块生成的BEACON交易
+pub fn operation(proposer: usize, signing_key: &SigningKey, previous: &BeaconEntry) -> String {
+    let proof = BeaconProof { proposer, proof: signing_key.vrf_prove(&alpha(&previous.value)).to_hex() };
+    format!("{} {}", BEACON_COMMAND, serde_json::to_string(&proof).unwrap())
+}
+
+pub fn proposer(payload: &str) -> Option<usize> {
+    serde_json::from_str::<BeaconProof>(payload).ok().map(|proof| proof.proposer)
+}
+
+fn verify(store: &BTreeMap<String, String>, validators: &[usize], previous: &BeaconEntry, payload: &str) -> Result<(usize, [u8; 64]), String> {
+    let proof: BeaconProof = serde_json::from_str(payload).map_err(|e| format!("无法解析信标证明: {}", e))?;
+    if !validators.contains(&proof.proposer) {
+        return Err(format!("节点{}不是验证者", proof.proposer));
+    }
+    let entry = directory::lookup(store, proof.proposer).ok_or_else(|| format!("节点{}未在目录中登记", proof.proposer))?;
+    let public_key = PublicKey::from_hex(&entry.public_key)?;
+    let output = public_key.vrf_verify(&alpha(&previous.value), &VrfProof::from_hex(&proof.proof)?)
+        .ok_or_else(|| format!("节点{}的VRF证明无效", proof.proposer))?;
+    Ok((proof.proposer, output))
+}
+
+// 执行高度height的区块前推进信标；payload为区块第一笔交易中BEACON的参数。返回证明的校验结果
+pub fn advance(store: &mut Sandbox, height: u64, chain_id: &str, validators: &[usize], payload: Option<&str>) -> Result<BeaconEntry, String> {
+    let previous = latest(store, chain_id);
+    let verified = payload.ok_or_else(|| "区块没有信标证明".to_string()).and_then(|payload| verify(store, validators, &previous, payload));
+    let entry = match &verified {
+        Ok((proposer, output)) => BeaconEntry { height, value: sha256(&[DOMAIN, previous.value.as_bytes(), output]), proposer: Some(*proposer) },
+        Err(_) => BeaconEntry { height, value: sha256(&[DOMAIN, previous.value.as_bytes()]), proposer: None },
+    };
+    store.insert(key(height), serde_json::to_string(&entry).unwrap());
+    if height > BEACON_HISTORY {
+        store.remove(&key(height - BEACON_HISTORY));
+    }
+    verified.map(|_| entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::directory::{DirectoryEntry, SignedEntry};
+    use crate::execution::{ExecutionEngine, ExecutionStatus};
+    use crate::message::Transaction;
+    use crate::node::Role;
+    use crate::testing::TestCluster;
+
+    fn transaction(operation: &str) -> Transaction {
+        Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None }
+    }
+
+    #[test]
+    fn beacon_chains_vrf_outputs() {
+        let keys: Vec<SigningKey> = (0..2).map(|_| SigningKey::generate()).collect();
+        let mut engine = ExecutionEngine::new();
+        let registrations: Vec<Transaction> = keys.iter().enumerate().map(|(id, key)| {
+            let entry = DirectoryEntry { node_id: id, addresses: Vec::new(), public_key: key.public_key().to_hex(), role: Role::Validator, sequence: 0 };
+            transaction(&SignedEntry::sign(entry, key).registration_operation())
+        }).collect();
+        engine.execute_block(1, &registrations);
+        let first = latest(engine.state(), "");
+        assert_eq!((first.height, first.proposer), (1, None));
+
+        // 有效证明参与信标；同一输入的证明唯一，与哪个副本验证无关
+        let proof = operation(0, &keys[0], &first);
+        assert_eq!(proof, operation(0, &keys[0], &first));
+        let results = engine.execute_block(2, &[transaction(&proof), transaction("SET k v")]);
+        assert!(matches!(&results[0].status, ExecutionStatus::Success(Some(value)) if *value == latest(engine.state(), "").value));
+        assert_eq!(get(engine.state(), 2).unwrap().proposer, Some(0));
+
+        // 冒用其他验证者身份的证明、对旧信标的证明和不在第一笔的证明都不被采用
+        let previous = latest(engine.state(), "");
+        let forged = operation(1, &keys[0], &previous);
+        assert!(matches!(engine.execute_block(3, &[transaction(&forged)])[0].status, ExecutionStatus::Failed(_)));
+        assert_eq!(get(engine.state(), 3).unwrap().proposer, None);
+        assert_ne!(get(engine.state(), 3).unwrap().value, previous.value);
+        let replayed = operation(0, &keys[0], &previous);
+        let results = engine.execute_block(4, &[transaction("SET k w"), transaction(&replayed)]);
+        assert!(matches!(results[1].status, ExecutionStatus::Failed(_)));
+        assert!(matches!(engine.execute_block(5, &[transaction(&replayed)])[0].status, ExecutionStatus::Failed(_)));
+        assert!(matches!(engine.execute_block(6, &[transaction(&format!("SET {} x", key(6)))])[0].status, ExecutionStatus::Failed(_)));
+    }
+
+    // 验证者登记后，主节点为每个区块提供VRF证明，各节点得到相同的信标
+    #[tokio::test]
+    async fn validators_agree_on_vrf_beacon() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            cluster.register_validators().await;
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.executions[id].lock().unwrap().get("k").is_some())
+            }).await;
+            assert!(committed, "请求未执行");
+
+            let beacons: Vec<BeaconEntry> = (0..N).map(|id| latest(cluster.executions[id].lock().unwrap().state(), "test-cluster")).collect();
+            assert!(beacons.iter().all(|beacon| *beacon == beacons[0]), "各节点的信标不一致: {:?}", beacons);
+            assert_eq!(beacons[0].proposer, Some(0), "主节点的VRF证明未被采用");
+        }).await;
+    }
+}
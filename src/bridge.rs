@@ -0,0 +1,154 @@
+// src/bridge.rs
+
+// 跨链桥：其他链（本程序的另一个实例）用EMIT操作发出消息，中继者把该区块的提交证明
+// 作为BRIDGE操作提交到本链，本链按创世配置中跟踪的对方验证者集合验证后，
+// 把消息内容写入 bridge/<链ID>/<高度>/<序号>，执行引擎和客户端可以像普通键一样读取。
+// 提交证书签署的是批次摘要而不是区块头，区块头中的Merkle根没有签名，
+// 所以证明带上整个批次：批次摘要与证书一致，才能确定其中的交易确实已被对方提交
+use serde::{Serialize, Deserialize};
+use crate::chain::{self, BlockHeader, Chain, CommitCertificate, ValidatorSet};
+use crate::merkle;
+use crate::message::Transaction;
+use crate::quota::Sandbox;
+
+// 已接收的跨链消息保存在复制状态机中，键为 bridge/<链ID>/<高度>/<序号>
+pub const KEY_PREFIX: &str = "bridge/";
+pub const BRIDGE_COMMAND: &str = "BRIDGE";
+// 源链上发出跨链消息的操作：EMIT <消息内容>
+pub const EMIT_COMMAND: &str = "EMIT";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommitmentProof {
+    pub chain_id: String, // 源链的链ID
+    pub header: BlockHeader,
+    pub certificate: CommitCertificate,
+    pub transactions: Vec<Transaction>, // 区块内的全部交易
+    pub index: usize,                   // EMIT交易在区块内的位置
+}
+
+impl CommitmentProof {
+    // 跨链操作：BRIDGE <证明JSON>，由任意中继者提交，随普通请求进入共识
+    pub fn operation(&self) -> String {
+        format!("{} {}", BRIDGE_COMMAND, serde_json::to_string(self).unwrap())
+    }
+}
+
+// 源链节点为中继者生成区块内第index笔交易的证明
+pub fn prove(chain: &Chain, chain_id: &str, height: u64, index: usize) -> Option<CommitmentProof> {
+    let block = chain.get_block(height)?;
+    block.transactions.get(index)?;
+    Some(CommitmentProof {
+        chain_id: chain_id.to_string(),
+        header: block.header.clone(),
+        certificate: block.certificate.clone(),
+        transactions: block.transactions.clone(),
+        index,
+    })
+}
+
+pub fn key(chain_id: &str, height: u64, index: usize) -> String {
+    format!("{}{}/{}/{}", KEY_PREFIX, chain_id, height, index)
+}
+
+// 验证证明并保存消息内容，返回保存的键；同一条消息只能接收一次
+pub fn apply(store: &mut Sandbox, foreign: &[ValidatorSet], payload: &str) -> Result<String, String> {
+    let proof: CommitmentProof = serde_json::from_str(payload).map_err(|e| format!("无法解析跨链证明: {}", e))?;
+    let validator_set = foreign.iter()
+        .find(|set| set.chain_id == proof.chain_id)
+        .ok_or_else(|| format!("未跟踪链{}的验证者集合", proof.chain_id))?;
+    let message = verify(&proof, validator_set)?;
+
+    let key = key(&proof.chain_id, proof.header.height, proof.index);
+    if store.contains_key(&key) {
+        return Err(format!("链{}高度{}的第{}条交易已经接收过", proof.chain_id, proof.header.height, proof.index));
+    }
+    store.insert(key.clone(), message);
+    Ok(key)
+}
+
+// 轻客户端验证：证书由对方的法定人数签名，批次与证书摘要和区块头一致，返回EMIT的消息内容
+pub fn verify(proof: &CommitmentProof, validator_set: &ValidatorSet) -> Result<String, String> {
+    chain::verify_commit_certificate(&proof.header, &proof.certificate, validator_set)?;
+    let hasher = validator_set.hash_function.hasher();
+    if chain::digest_transactions(hasher, &proof.transactions) != proof.header.digest {
+        return Err("批次与提交证书的摘要不符".to_string());
+    }
+    if merkle::merkle_root(hasher, &chain::encode_transactions(&proof.transactions)) != proof.header.merkle_root {
+        return Err("批次与区块头的Merkle根不符".to_string());
+    }
+    let transaction = proof.transactions.get(proof.index)
+        .ok_or_else(|| format!("区块只有{}笔交易，没有第{}笔", proof.transactions.len(), proof.index))?;
+    transaction.operation.strip_prefix(EMIT_COMMAND)
+        .and_then(|rest| rest.strip_prefix(' '))
+        .map(|message| message.to_string())
+        .ok_or_else(|| format!("交易'{}'不是跨链消息", transaction.operation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::quota::Quota;
+    use crate::chain::Chain;
+    use crate::config::N;
+    use crate::crypto::SigningKey;
+    use crate::genesis::Genesis;
+    use crate::message::PBFTMessage;
+    use crate::quorum::COMMIT_QUORUM;
+
+    fn transaction(operation: &str) -> Transaction {
+        Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None }
+    }
+
+    // 源链提交一个含EMIT交易的区块，返回其证明和源链的验证者集合
+    fn foreign_block() -> (CommitmentProof, ValidatorSet) {
+        let keys: Vec<SigningKey> = (0..N).map(|_| SigningKey::generate()).collect();
+        let genesis = Genesis { chain_id: "foreign".to_string(), ..Genesis::default() };
+        let transactions = vec![transaction("SET k v"), transaction("EMIT pay alice 10")];
+        let digest = chain::digest_transactions(genesis.hasher(), &transactions);
+        let commit = PBFTMessage::Commit { view: 0, sequence_number: 1, digest: digest.clone() };
+        let payload = genesis.message_payload(&commit);
+        let signatures = keys.iter().enumerate().take(COMMIT_QUORUM).map(|(id, key)| (id, key.sign(&payload))).collect();
+        let certificate = CommitCertificate { view: 0, sequence_number: 1, digest: digest.clone(), signatures };
+        let header = Chain::default().append(0, 1, digest, transactions.clone(), certificate.clone()).header.clone();
+        let validator_set = ValidatorSet {
+            chain_id: genesis.chain_id.clone(),
+            validators: keys.iter().enumerate().map(|(id, key)| (id, key.public_key().to_hex())).collect(),
+            hash_function: genesis.hash_function,
+        };
+        (CommitmentProof { chain_id: genesis.chain_id, header, certificate, transactions, index: 1 }, validator_set)
+    }
+
+    #[test]
+    fn accepts_verified_messages_once() {
+        let (proof, validator_set) = foreign_block();
+        let foreign = vec![validator_set];
+        let mut state = BTreeMap::new();
+        let mut store = Sandbox::new(&mut state, Quota::default());
+        let payload = serde_json::to_string(&proof).unwrap();
+        assert_eq!(apply(&mut store, &foreign, &payload), Ok(key("foreign", 1, 1)));
+        assert_eq!(store.get(&key("foreign", 1, 1)), Some(&"pay alice 10".to_string()));
+        assert!(apply(&mut store, &foreign, &payload).unwrap_err().contains("接收过"));
+        assert!(apply(&mut store, &[], &payload).unwrap_err().contains("未跟踪"));
+        assert!(proof.operation().starts_with("BRIDGE {"));
+    }
+
+    #[test]
+    fn rejects_forged_proofs() {
+        let (proof, validator_set) = foreign_block();
+        let mut forged = proof.clone();
+        forged.transactions[1] = transaction("EMIT pay mallory 1000");
+        assert!(verify(&forged, &validator_set).unwrap_err().contains("摘要"));
+
+        let mut not_emitted = proof.clone();
+        not_emitted.index = 0;
+        assert!(verify(&not_emitted, &validator_set).unwrap_err().contains("不是跨链消息"));
+
+        let mut unsigned = proof.clone();
+        unsigned.certificate.signatures.truncate(COMMIT_QUORUM - 1);
+        assert!(verify(&unsigned, &validator_set).is_err());
+
+        let impostor = ValidatorSet { validators: (0..N).map(|id| (id, SigningKey::generate().public_key().to_hex())).collect(), ..validator_set };
+        assert!(verify(&proof, &impostor).is_err(), "签名须来自跟踪的验证者集合");
+    }
+}
@@ -0,0 +1,365 @@
+// src/byzantine.rs
+
+use std::time::Duration;
+use crate::config::{BYZANTINE_DELAY_PERCENT, N};
+use crate::message::Transaction;
+
+// 模拟拜占庭节点的行为策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    #[default]
+    Honest,
+    WrongDigest,         // 副本发送错误的Prepare摘要
+    EquivocatingPrimary, // 主节点在同一序列号上向不同副本发送不同的批次，之后不参与投票
+    Silent,              // 模拟崩溃：不处理也不发送任何消息
+    DropMessages(u8),    // 按百分比随机丢弃发出的消息
+    DelayMessages(u8),   // 把发出的消息推迟到超时阈值的该百分比，拖慢共识又不触发超时
+    WithholdPayload,     // 摘要模式下的主节点只发交易摘要，不应答副本索取内容的请求
+}
+
+impl Strategy {
+    // 命令行参数：byzantine（兼容旧用法）、wrong-digest、equivocate、silent、withhold、drop:<百分比>、delay[:<百分比>]
+    pub fn parse(arg: &str) -> Option<Self> {
+        match arg {
+            "byzantine" | "wrong-digest" => Some(Strategy::WrongDigest),
+            "equivocate" => Some(Strategy::EquivocatingPrimary),
+            "silent" => Some(Strategy::Silent),
+            "withhold" => Some(Strategy::WithholdPayload),
+            "delay" => Some(Strategy::DelayMessages(BYZANTINE_DELAY_PERCENT)),
+            _ => {
+                if let Some(percent) = arg.strip_prefix("delay:") {
+                    let percent: u8 = percent.parse().ok()?;
+                    return (percent < 100).then_some(Strategy::DelayMessages(percent));
+                }
+                let percent: u8 = arg.strip_prefix("drop:")?.parse().ok()?;
+                (percent <= 100).then_some(Strategy::DropMessages(percent))
+            }
+        }
+    }
+
+    // 是否丢弃即将发出的一条消息
+    pub fn drops_message(&self) -> bool {
+        match self {
+            Strategy::Silent => true,
+            Strategy::DropMessages(percent) => rand::random::<u32>() % 100 < *percent as u32,
+            _ => false,
+        }
+    }
+
+    // 发出消息前的人为延迟，按节点的超时阈值换算
+    pub fn message_delay(&self, timeout: Duration) -> Option<Duration> {
+        match self {
+            Strategy::DelayMessages(percent) => Some(timeout * *percent as u32 / 100),
+            _ => None,
+        }
+    }
+}
+
+// 分叉的批次：在原批次后追加一笔标记交易，使摘要不同但内容仍能通过副本的校验
+pub fn conflicting_batch(transactions: &[Transaction], sequence_number: u64) -> Vec<Transaction> {
+    let mut conflicting = transactions.to_vec();
+    conflicting.push(Transaction {
+        operation: format!("EQUIVOCATION {}", sequence_number),
+        client_id: None,
+        session: None,
+        timestamp: None,
+    });
+    conflicting
+}
+
+// 作恶主节点把副本分成两组：前一半收到原批次，其余收到分叉的批次
+pub fn equivocation_groups(primary: usize) -> (Vec<usize>, Vec<usize>) {
+    let mut replicas: Vec<usize> = (0..N).filter(|id| *id != primary).collect();
+    let second = replicas.split_off(replicas.len() - replicas.len() / 2);
+    (replicas, second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, VecDeque};
+    use std::time::Duration;
+    use crate::chain;
+    use crate::config::F;
+    use crate::consensus::{Action, ConsensusCore, Input};
+    use crate::hash::{HashFunction, Sha256};
+    use crate::message::PBFTMessage;
+    use crate::audit::{AuditEntry, AuditEvent};
+    use crate::beacon::BEACON_COMMAND;
+    use crate::testing::TestCluster;
+
+    // from为发送消息的核心，对应节点外壳中签名确认的发送者
+    fn input(msg: PBFTMessage, from: usize) -> Option<Input> {
+        match msg {
+            PBFTMessage::PrePrepare { view, sequence_number, digest, transactions } => {
+                Some(Input::PrePrepare { view, sequence_number, digest, transactions })
+            }
+            PBFTMessage::Prepare { view, sequence_number, digest, sender_id } => {
+                Some(Input::Prepare { view, sequence_number, digest, sender_id })
+            }
+            PBFTMessage::Commit { view, sequence_number, digest } => Some(Input::Commit { view, sequence_number, digest, sender_id: from }),
+            _ => None,
+        }
+    }
+
+    // 在共识核心之间可靠地投递消息直到没有新消息，返回每个节点产生的全部动作
+    fn run_instance(strategies: &[Strategy], transactions: Vec<Transaction>) -> Vec<Vec<Action>> {
+        let mut cores: Vec<ConsensusCore> = (0..N).map(|id| ConsensusCore::new(id, 0, 0, strategies[id], HashFunction::default())).collect();
+        let mut outputs = vec![Vec::new(); N];
+        let digest = chain::digest_transactions(&Sha256, &transactions);
+        let mut queue = VecDeque::from([(0, Input::Propose { digest, transactions })]);
+
+        while let Some((node, event)) = queue.pop_front() {
+            for action in cores[node].handle(event) {
+                match &action {
+                    Action::Broadcast(msg) => {
+                        for to in (0..N).filter(|to| *to != node) {
+                            queue.extend(input(msg.clone(), node).map(|event| (to, event)));
+                        }
+                    }
+                    Action::Send(to, msg) => queue.extend(input(msg.clone(), node).map(|event| (*to, event))),
+                    _ => {}
+                }
+                outputs[node].push(action);
+            }
+        }
+        outputs
+    }
+
+    fn batch() -> Vec<Transaction> {
+        vec![Transaction { operation: "SET k v".to_string(), client_id: None, session: None, timestamp: None }]
+    }
+
+    #[test]
+    fn equivocating_primary_sends_conflicting_preprepares() {
+        let (first, second) = equivocation_groups(0);
+        assert_eq!(first.len() + second.len(), N - 1);
+        assert!(!second.is_empty());
+
+        let mut strategies = vec![Strategy::Honest; N];
+        strategies[0] = Strategy::EquivocatingPrimary;
+        let outputs = run_instance(&strategies, batch());
+
+        let mut digests = HashMap::new();
+        for action in &outputs[0] {
+            if let Action::Send(to, PBFTMessage::PrePrepare { digest, transactions, .. }) = action {
+                assert_eq!(*digest, chain::digest_transactions(&Sha256, transactions));
+                digests.insert(*to, digest.clone());
+            }
+        }
+        assert_eq!(digests.len(), N - 1);
+        assert!(first.iter().all(|id| digests[id] == digests[&first[0]]));
+        assert!(second.iter().all(|id| digests[id] != digests[&first[0]]));
+        assert!(!outputs[0].iter().any(|action| matches!(action, Action::Broadcast(_))));
+    }
+
+    #[test]
+    fn replicas_detect_equivocation_without_committing() {
+        let mut strategies = vec![Strategy::Honest; N];
+        strategies[0] = Strategy::EquivocatingPrimary;
+        let outputs = run_instance(&strategies, batch());
+        let (first, second) = equivocation_groups(0);
+
+        // 少数一组看到f+1个节点为另一个摘要发送Prepare，据此认定主节点作恶
+        for id in &second {
+            assert!(outputs[*id].iter().any(|action| matches!(action, Action::ViewChange(1))), "节点{}未发现分叉", id);
+            assert!(outputs[*id].iter().any(|action| matches!(action, Action::Suspect(0))));
+        }
+        // 任何一组都凑不齐提交所需的Commit，分叉的两个批次都不会被执行
+        for (id, actions) in outputs.iter().enumerate() {
+            assert!(!actions.iter().any(|action| matches!(action, Action::Execute { .. })), "节点{}提交了分叉的批次", id);
+        }
+        // 多数一组只看到一个节点的不同摘要，不足以证明主节点作恶
+        for id in &first {
+            assert!(!outputs[*id].iter().any(|action| matches!(action, Action::ViewChange(_))));
+        }
+    }
+
+    #[test]
+    fn honest_primary_commits_on_every_replica() {
+        let outputs = run_instance(&[Strategy::Honest; N], batch());
+        for (id, actions) in outputs.iter().enumerate() {
+            assert!(actions.iter().any(|action| matches!(action, Action::Execute { .. })), "节点{}未提交", id);
+            assert!(!actions.iter().any(|action| matches!(action, Action::ViewChange(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn cluster_changes_view_around_equivocating_primary() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut strategies = vec![Strategy::Honest; N];
+            strategies[0] = Strategy::EquivocatingPrimary;
+            let cluster = TestCluster::start(&strategies, Duration::from_millis(300)).await;
+            let detected_before = crate::metrics::snapshot().get("primary_equivocation_detected_total").copied().unwrap_or(0);
+
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(30), |c| {
+                (1..N).all(|id| c.committed_view(id, "SET k v").is_some_and(|view| view >= 1))
+            }).await;
+            assert!(committed, "视图切换后请求未被提交");
+
+            let detected = crate::metrics::snapshot().get("primary_equivocation_detected_total").copied().unwrap_or(0);
+            assert!(detected > detected_before, "副本未发现主节点的分叉");
+            assert!((1..N).all(|id| cluster.view(id) >= 1));
+        }).await;
+    }
+
+    #[test]
+    fn parses_fault_strategies() {
+        assert_eq!(Strategy::parse("silent"), Some(Strategy::Silent));
+        assert_eq!(Strategy::parse("withhold"), Some(Strategy::WithholdPayload));
+        assert_eq!(Strategy::parse("drop:30"), Some(Strategy::DropMessages(30)));
+        assert_eq!(Strategy::parse("drop:250"), None);
+        assert_eq!(Strategy::parse("drop:x"), None);
+        assert!(Strategy::Silent.drops_message());
+        assert!(Strategy::DropMessages(100).drops_message());
+        assert!(!Strategy::DropMessages(0).drops_message());
+        assert!(!Strategy::Honest.drops_message());
+
+        assert_eq!(Strategy::parse("delay"), Some(Strategy::DelayMessages(BYZANTINE_DELAY_PERCENT)));
+        assert_eq!(Strategy::parse("delay:50"), Some(Strategy::DelayMessages(50)));
+        assert_eq!(Strategy::parse("delay:100"), None);
+        let timeout = Duration::from_millis(1000);
+        assert_eq!(Strategy::DelayMessages(90).message_delay(timeout), Some(Duration::from_millis(900)));
+        assert_eq!(Strategy::Honest.message_delay(timeout), None);
+    }
+
+    // 最后f个节点按给定策略运行，其余节点诚实
+    fn faulty_replicas(strategy: Strategy) -> Vec<Strategy> {
+        (0..N).map(|id| if id >= N - F { strategy } else { Strategy::Honest }).collect()
+    }
+
+    #[tokio::test]
+    async fn cluster_commits_with_f_silent_replicas() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&faulty_replicas(Strategy::Silent), Duration::from_millis(300)).await;
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(30), |c| {
+                (0..N - F).all(|id| c.committed_view(id, "SET k v").is_some())
+            }).await;
+            assert!(committed, "f个节点崩溃时请求未被提交");
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn cluster_commits_with_f_lossy_replicas() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&faulty_replicas(Strategy::DropMessages(50)), Duration::from_millis(300)).await;
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(30), |c| {
+                (0..N - F).all(|id| c.committed_view(id, "SET k v").is_some())
+            }).await;
+            assert!(committed, "f个节点丢包时请求未被提交");
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn cluster_changes_view_around_silent_primary() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut strategies = vec![Strategy::Honest; N];
+            strategies[0] = Strategy::Silent;
+            let cluster = TestCluster::start(&strategies, Duration::from_millis(300)).await;
+
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(30), |c| {
+                (1..N).all(|id| c.committed_view(id, "SET k v").is_some_and(|view| view >= 1))
+            }).await;
+            assert!(committed, "主节点崩溃后未能切换视图并提交请求");
+        }).await;
+    }
+
+    // 从提交请求到所有诚实节点都在视图0提交的耗时；视图切换说明延迟被当成了故障
+    async fn view0_commit_latency(strategies: &[Strategy], timeout: Duration) -> Duration {
+        let cluster = TestCluster::start(strategies, timeout).await;
+        let honest: Vec<usize> = (0..N).filter(|id| strategies[*id] == Strategy::Honest).collect();
+        let started = tokio::time::Instant::now();
+        cluster.submit("SET k v").await;
+        let committed = cluster.wait_until(Duration::from_secs(30), |c| {
+            honest.iter().all(|id| c.committed_view(*id, "SET k v").is_some())
+        }).await;
+        let latency = started.elapsed();
+        assert!(committed, "请求未被提交");
+        for id in &honest {
+            assert_eq!(cluster.committed_view(*id, "SET k v"), Some(0), "节点{}触发了视图切换", id);
+        }
+        latency
+    }
+
+    #[tokio::test]
+    async fn delaying_primary_slows_commits_without_view_change() {
+        tokio::task::LocalSet::new().run_until(async {
+            let timeout = Duration::from_millis(1000);
+            let baseline = view0_commit_latency(&[Strategy::Honest; N], timeout).await;
+
+            let mut strategies = vec![Strategy::Honest; N];
+            strategies[0] = Strategy::DelayMessages(BYZANTINE_DELAY_PERCENT);
+            let delayed_before = crate::metrics::snapshot().get("byzantine_delayed_messages_total").copied().unwrap_or(0);
+            let delayed = view0_commit_latency(&strategies, timeout).await;
+            let delayed_after = crate::metrics::snapshot().get("byzantine_delayed_messages_total").copied().unwrap_or(0);
+
+            // 主节点的每条消息都被推迟到超时阈值之下，提交延迟至少增加该延迟
+            let delay = strategies[0].message_delay(timeout).unwrap();
+            assert!(delayed >= delay, "延迟攻击下提交耗时{:?}，低于注入的延迟{:?}", delayed, delay);
+            assert!(delayed > baseline + delay / 2, "延迟攻击未拖慢提交：基线{:?}，攻击下{:?}", baseline, delayed);
+            assert!(delayed_after > delayed_before);
+        }).await;
+    }
+
+    // 链上的客户端交易数，不计主节点加入的信标证明
+    fn client_transactions(chain: &chain::Chain) -> usize {
+        chain.blocks.iter().flat_map(|block| &block.transactions).filter(|tx| !tx.operation.starts_with(BEACON_COMMAND)).count()
+    }
+
+    // 端到端正确性：节点3发送错误的Prepare摘要，1000个请求持续流入，
+    // 诚实节点的链完全一致、执行结果正确，并且都把节点3列入黑名单
+    #[tokio::test]
+    async fn cluster_commits_1000_requests_and_blacklists_wrong_digest_replica() {
+        tokio::task::LocalSet::new().run_until(async {
+            const REQUESTS: usize = 1000;
+            let byzantine = N - 1;
+            let honest: Vec<usize> = (0..N).filter(|id| *id != byzantine).collect();
+            let mut strategies = vec![Strategy::Honest; N];
+            strategies[byzantine] = Strategy::WrongDigest;
+            let cluster = TestCluster::start(&strategies, Duration::from_millis(1000)).await;
+
+            // 分批注入，每批提交后再发下一批，避免节点的入站队列溢出
+            let operation = |i: usize| format!("SET key{} value{}", i, i);
+            for chunk in (0..REQUESTS).collect::<Vec<_>>().chunks(100) {
+                for i in chunk {
+                    cluster.submit(&operation(*i)).await;
+                }
+                let last = operation(*chunk.last().unwrap());
+                let committed = cluster.wait_until(Duration::from_secs(30), |c| {
+                    honest.iter().all(|id| c.committed_view(*id, &last).is_some())
+                }).await;
+                assert!(committed, "请求{}未在所有诚实节点上提交", last);
+            }
+            let all_committed = cluster.wait_until(Duration::from_secs(30), |c| {
+                honest.iter().all(|id| {
+                    let chain = c.chains[*id].lock().unwrap();
+                    client_transactions(&chain) >= REQUESTS
+                })
+            }).await;
+            assert!(all_committed, "部分请求未被提交");
+
+            let hashes = |id: usize| -> Vec<String> {
+                cluster.chains[id].lock().unwrap().blocks.iter().map(|block| block.header.hash(&Sha256)).collect()
+            };
+            for id in &honest {
+                assert_eq!(hashes(*id), hashes(honest[0]), "节点{}的链与节点{}不一致", id, honest[0]);
+                let execution = cluster.executions[*id].lock().unwrap();
+                for i in 0..REQUESTS {
+                    assert_eq!(execution.get(&format!("key{}", i)), Some(&format!("value{}", i)), "节点{}的key{}执行结果错误", id, i);
+                }
+            }
+            let transactions = client_transactions(&cluster.chains[honest[0]].lock().unwrap());
+            assert_eq!(transactions, REQUESTS, "请求被重复提交");
+
+            let blacklisted = |id: usize| std::fs::read_to_string(format!("node_{}_audit.jsonl", id)).unwrap_or_default()
+                .lines()
+                .any(|line| matches!(serde_json::from_str::<AuditEntry>(line).unwrap().event, AuditEvent::Blacklisted { node_id, .. } if node_id == byzantine));
+            let all_blacklisted = cluster.wait_until(Duration::from_secs(10), |_| honest.iter().all(|id| blacklisted(*id))).await;
+            assert!(all_blacklisted, "诚实节点未把节点{}列入黑名单", byzantine);
+        }).await;
+    }
+}
@@ -0,0 +1,223 @@
+// src/capture.rs
+
+// 抓包模式：以--capture <文件>启动的节点把收发的每一帧写入类似pcap的抓包文件，用于排查跨节点的协议问题。
+// 文件以8字节的魔数PBFTCAP1开头，之后每条记录为：时间戳（Unix微秒，u64）、方向（0发送、1接收，u8）、
+// 对端节点ID（u32）、帧长度（u32），均为大端序，后接帧本身（消息的JSON编码）。
+// 每条记录单独写入，进程崩溃时最多丢失最后一条；capture子命令解码文件并逐帧打印其中的PBFT消息
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use chrono::{Local, TimeZone};
+use log::error;
+use crate::message::PBFTMessage;
+use crate::metrics;
+
+pub const MAGIC: &[u8; 8] = b"PBFTCAP1";
+const RECORD_HEADER_LEN: usize = 8 + 1 + 4 + 4;
+
+lazy_static::lazy_static! {
+    static ref CAPTURES: Mutex<HashMap<usize, File>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+// 开始抓取节点的帧；文件已存在时（例如节点重启）在末尾追加
+pub fn start(node_id: usize, path: &Path) -> Result<(), String> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| format!("无法打开抓包文件{}: {}", path.display(), e))?;
+    let empty = file.metadata().map_err(|e| e.to_string())?.len() == 0;
+    if empty {
+        file.write_all(MAGIC).map_err(|e| e.to_string())?;
+    }
+    CAPTURES.lock().unwrap().insert(node_id, file);
+    Ok(())
+}
+
+// 记录节点收发的一帧；节点未开启抓包时什么都不做
+pub fn record(node_id: usize, direction: Direction, peer: usize, frame: &[u8]) {
+    let mut captures = CAPTURES.lock().unwrap();
+    let file = match captures.get_mut(&node_id) {
+        Some(file) => file,
+        None => return,
+    };
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + frame.len());
+    record.extend_from_slice(&(Local::now().timestamp_micros() as u64).to_be_bytes());
+    record.push(match direction {
+        Direction::Sent => 0,
+        Direction::Received => 1,
+    });
+    record.extend_from_slice(&(peer as u32).to_be_bytes());
+    record.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+    record.extend_from_slice(frame);
+    match file.write_all(&record) {
+        Ok(()) => metrics::inc_counter("capture_frames_total", 1),
+        Err(e) => {
+            // 磁盘写满等情况下停止抓包，不影响节点运行
+            error!("节点{}写入抓包文件失败，停止抓包: {}", node_id, e);
+            captures.remove(&node_id);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub timestamp_us: u64,
+    pub direction: Direction,
+    pub peer: usize,
+    pub data: Vec<u8>,
+}
+
+// 解析抓包文件；写了一半的最后一条记录被忽略
+pub fn parse(data: &[u8]) -> Result<Vec<Frame>, String> {
+    let mut rest = data.strip_prefix(&MAGIC[..]).ok_or("不是抓包文件（魔数不符）")?;
+    let mut frames = Vec::new();
+    while rest.len() >= RECORD_HEADER_LEN {
+        let timestamp_us = u64::from_be_bytes(rest[0..8].try_into().unwrap());
+        let direction = match rest[8] {
+            0 => Direction::Sent,
+            1 => Direction::Received,
+            other => return Err(format!("第{}帧的方向{}无效", frames.len() + 1, other)),
+        };
+        let peer = u32::from_be_bytes(rest[9..13].try_into().unwrap()) as usize;
+        let len = u32::from_be_bytes(rest[13..17].try_into().unwrap()) as usize;
+        if rest.len() < RECORD_HEADER_LEN + len {
+            break;
+        }
+        frames.push(Frame { timestamp_us, direction, peer, data: rest[RECORD_HEADER_LEN..RECORD_HEADER_LEN + len].to_vec() });
+        rest = &rest[RECORD_HEADER_LEN + len..];
+    }
+    Ok(frames)
+}
+
+// 帧的类型；签名和认证消息取内部消息的类型，合并发送的消息列出其中各条的类型
+fn describe(message: &PBFTMessage) -> String {
+    match message {
+        PBFTMessage::Bundle { messages } => format!("Bundle[{}]", messages.iter().map(|m| m.kind()).collect::<Vec<_>>().join(", ")),
+        PBFTMessage::Relay { from, to, message } => format!("Relay({}→{}: {})", from, to, describe(message)),
+        other => other.kind().to_string(),
+    }
+}
+
+// 一帧的摘要行，例如 10:00:00.123456 → 节点2 Prepare 210字节
+pub fn summary(frame: &Frame) -> String {
+    let time = Local.timestamp_micros(frame.timestamp_us as i64).single()
+        .map(|time| time.format("%H:%M:%S%.6f").to_string())
+        .unwrap_or_else(|| frame.timestamp_us.to_string());
+    let arrow = match frame.direction {
+        Direction::Sent => "→",
+        Direction::Received => "←",
+    };
+    let kind = match serde_json::from_slice::<PBFTMessage>(&frame.data) {
+        Ok(message) => describe(&message),
+        Err(_) => "无法解码".to_string(),
+    };
+    format!("{} {} 节点{} {} {}字节", time, arrow, frame.peer, kind, frame.data.len())
+}
+
+// 命令行入口：capture <抓包文件> [--peer 节点ID] [--kind 消息类型] [--brief]。
+// 逐帧打印摘要行和消息内容，--brief只打印摘要行
+pub fn run(args: &[String]) -> i32 {
+    let path = match args.first() {
+        Some(path) if !path.starts_with("--") => path,
+        _ => {
+            eprintln!("用法: pbft-blockchain capture <抓包文件> [--peer 节点ID] [--kind 消息类型] [--brief]");
+            return 2;
+        }
+    };
+    let flag = |name: &str| args.iter().position(|s| s == name).and_then(|i| args.get(i + 1));
+    let peer = match flag("--peer").map(|peer| peer.parse::<usize>()) {
+        Some(Ok(peer)) => Some(peer),
+        Some(Err(_)) => {
+            eprintln!("--peer必须是节点ID");
+            return 2;
+        }
+        None => None,
+    };
+    let kind = flag("--kind");
+    let brief = args.iter().any(|s| s == "--brief");
+    let frames = match std::fs::read(path).map_err(|e| e.to_string()).and_then(|data| parse(&data)) {
+        Ok(frames) => frames,
+        Err(e) => {
+            eprintln!("无法读取抓包文件{}: {}", path, e);
+            return 1;
+        }
+    };
+    let mut shown = 0;
+    for frame in frames.iter().filter(|frame| peer.map_or(true, |peer| frame.peer == peer)) {
+        let message = serde_json::from_slice::<PBFTMessage>(&frame.data).ok();
+        if kind.is_some_and(|kind| message.as_ref().map_or(true, |message| !describe(message).contains(kind.as_str()))) {
+            continue;
+        }
+        shown += 1;
+        println!("{}", summary(frame));
+        if !brief {
+            match &message {
+                Some(message) => println!("{}\n", serde_json::to_string_pretty(message).unwrap()),
+                None => println!("{}\n", String::from_utf8_lossy(&frame.data)),
+            }
+        }
+    }
+    eprintln!("共{}帧，显示{}帧", frames.len(), shown);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    // 记录按格式写入并能读回，写了一半的最后一条被忽略，未开启抓包的节点不写入
+    #[test]
+    fn frames_round_trip() {
+        let path = std::env::temp_dir().join(format!("pbft-capture-{}-{}.pcap", std::process::id(), rand::random::<u32>()));
+        let node_id = 1000 + rand::random::<u16>() as usize;
+        let prepare = PBFTMessage::Prepare { view: 0, sequence_number: 1, digest: "d".to_string(), sender_id: 2 };
+        let frame = serde_json::to_vec(&prepare).unwrap();
+        record(node_id, Direction::Sent, 2, &frame);
+        assert!(!path.exists());
+
+        start(node_id, &path).unwrap();
+        record(node_id, Direction::Sent, 2, &frame);
+        record(node_id, Direction::Received, 3, b"garbage");
+        let mut data = std::fs::read(&path).unwrap();
+        data.extend_from_slice(&[0; RECORD_HEADER_LEN - 1]);
+
+        let frames = parse(&data).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].direction, frames[0].peer), (Direction::Sent, 2));
+        assert_eq!(frames[0].data, frame);
+        assert!(summary(&frames[0]).ends_with(&format!("→ 节点2 Prepare {}字节", frame.len())));
+        assert!(summary(&frames[1]).contains("← 节点3 无法解码"));
+        assert!(parse(b"not a capture").is_err());
+
+        CAPTURES.lock().unwrap().remove(&node_id);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // 抓包节点记录集群中发出和收到的共识消息
+    #[tokio::test]
+    async fn cluster_frames_are_captured() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let path = Path::new("node_1.pcap");
+            start(1, path).unwrap();
+            cluster.submit("SET captured yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, "SET captured yes").is_some())).await;
+            assert!(committed, "请求未提交");
+            CAPTURES.lock().unwrap().remove(&1);
+
+            let frames = parse(&std::fs::read(path).unwrap()).unwrap();
+            let summaries: Vec<String> = frames.iter().map(summary).collect();
+            assert!(summaries.iter().any(|s| s.contains("← 节点0 PrePrepare")), "{:?}", summaries);
+            assert!(summaries.iter().any(|s| s.contains("→") && s.contains("Commit")), "{:?}", summaries);
+        }).await;
+    }
+}
@@ -0,0 +1,341 @@
+// src/chain.rs
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use crate::crypto::{self, PublicKey, Signature};
+use crate::chain_index::ChainIndex;
+use crate::config::N;
+use crate::quorum::COMMIT_QUORUM;
+use crate::directory;
+use crate::domain;
+use crate::genesis::{self, Genesis};
+use crate::hash::{HashFunction, Hasher};
+use crate::merkle;
+use crate::message::{PBFTMessage, Transaction};
+use crate::node::Role;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockHeader {
+    pub height: u64,
+    pub view: u64,
+    pub sequence_number: u64,
+    pub digest: String,      // 批次摘要，与Commit消息中的摘要一致
+    pub merkle_root: String, // 批次内交易的Merkle根
+    pub prev_hash: String,
+}
+
+impl BlockHeader {
+    pub fn hash(&self, hasher: &dyn Hasher) -> String {
+        hasher.domain_digest(domain::BLOCK, &serde_json::to_vec(self).unwrap())
+    }
+}
+
+// 提交证书：2f+1个节点对同一Commit消息的签名
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommitCertificate {
+    pub view: u64,
+    pub sequence_number: u64,
+    pub digest: String,
+    pub signatures: Vec<(usize, Signature)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub transactions: Vec<Transaction>,
+    pub certificate: CommitCertificate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProofBundle {
+    pub transaction: Transaction,
+    pub index: usize,
+    pub merkle_proof: Vec<merkle::ProofStep>,
+    pub header: BlockHeader,
+    pub certificate: CommitCertificate,
+    pub hash_function: HashFunction, // 验证Merkle证明和区块哈希时使用
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Chain {
+    pub blocks: Vec<Block>,
+    // 通过状态同步加入的节点没有更早的区块，以快照的区块头作为链的起点
+    #[serde(default)]
+    pub base: Option<BlockHeader>,
+    // 区块哈希和Merkle根使用的哈希函数，由创世配置决定
+    #[serde(default)]
+    pub hash_function: HashFunction,
+    // 按交易摘要和客户端ID的索引，单独保存，由节点启动时加载
+    #[serde(skip)]
+    pub index: ChainIndex,
+}
+
+impl Chain {
+    pub fn load(node_id: usize) -> Self {
+        let filename = format!("node_{}_chain.json", node_id);
+        if let Ok(data) = std::fs::read_to_string(filename) {
+            serde_json::from_str(&data).unwrap()
+        } else {
+            Chain::default()
+        }
+    }
+
+    // 不验证签名地检查从下标from开始的本地区块：哈希链接、Merkle根和批次摘要
+    pub fn verify_local(&self, from: usize) -> Result<(), String> {
+        let hasher = self.hash_function.hasher();
+        let mut prev = match from {
+            0 => self.base.as_ref(),
+            _ => self.blocks.get(from - 1).map(|block| &block.header),
+        };
+        for block in self.blocks.iter().skip(from) {
+            let header = &block.header;
+            verify_link(header, prev, hasher).map_err(|reason| format!("高度{}: {}", header.height, reason))?;
+            if header.merkle_root != merkle::merkle_root(hasher, &encode_transactions(&block.transactions)) {
+                return Err(format!("高度{}: Merkle根与区块交易不符", header.height));
+            }
+            if header.digest != digest_transactions(hasher, &block.transactions) {
+                return Err(format!("高度{}: 批次摘要与区块交易不符", header.height));
+            }
+            prev = Some(header);
+        }
+        Ok(())
+    }
+
+    pub fn height(&self) -> u64 {
+        self.tip().map(|header| header.height).unwrap_or(0)
+    }
+
+    // 最新的区块头：本地最后一个区块，或状态同步的起点
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.blocks.last().map(|b| &b.header).or(self.base.as_ref())
+    }
+
+    // 指定高度的区块头，包括状态同步的起点
+    pub fn header(&self, height: u64) -> Option<&BlockHeader> {
+        self.get_block(height).map(|block| &block.header).or(self.base.as_ref().filter(|base| base.height == height))
+    }
+
+    fn base_height(&self) -> u64 {
+        self.base.as_ref().map(|header| header.height).unwrap_or(0)
+    }
+
+    // 从快照的区块头重新开始，丢弃本地已有的区块
+    pub fn reset_to(&mut self, header: BlockHeader) {
+        self.blocks.clear();
+        self.index.reset(self.hash_function, header.height);
+        self.base = Some(header);
+    }
+
+    pub fn append(&mut self, view: u64, sequence_number: u64, digest: String, transactions: Vec<Transaction>, certificate: CommitCertificate) -> &Block {
+        let prev_hash = self.tip()
+            .map(|header| header.hash(self.hash_function.hasher()))
+            .unwrap_or_else(|| "0".repeat(64));
+        let header = BlockHeader {
+            height: self.height() + 1,
+            view,
+            sequence_number,
+            digest,
+            merkle_root: merkle::merkle_root(self.hash_function.hasher(), &encode_transactions(&transactions)),
+            prev_hash,
+        };
+        self.blocks.push(Block { header, transactions, certificate });
+        self.index.index_block(self.blocks.last().unwrap());
+        self.blocks.last().unwrap()
+    }
+
+    // 全节点保存从验证者处收到并已验证的区块
+    pub fn push_verified(&mut self, block: Block) {
+        self.index.index_block(&block);
+        self.blocks.push(block);
+    }
+
+    pub fn get_block(&self, height: u64) -> Option<&Block> {
+        if height <= self.base_height() {
+            return None;
+        }
+        self.blocks.get((height - self.base_height()) as usize - 1)
+    }
+
+    pub fn prove_operation(&self, height: u64, index: usize) -> Option<ProofBundle> {
+        let block = self.get_block(height)?;
+        let transaction = block.transactions.get(index)?.clone();
+        Some(ProofBundle {
+            transaction,
+            index,
+            merkle_proof: merkle::merkle_proof(self.hash_function.hasher(), &encode_transactions(&block.transactions), index),
+            header: block.header.clone(),
+            certificate: block.certificate.clone(),
+            hash_function: self.hash_function,
+        })
+    }
+}
+
+pub fn encode_transactions(transactions: &[Transaction]) -> Vec<String> {
+    transactions.iter().map(|tx| tx.encode()).collect()
+}
+
+// 批次摘要的输入：各交易规范编码按行拼接
+pub fn batch_payload(transactions: &[Transaction]) -> String {
+    encode_transactions(transactions).join("\n")
+}
+
+pub fn digest_transactions(hasher: &dyn Hasher, transactions: &[Transaction]) -> String {
+    hasher.domain_digest(domain::BATCH, batch_payload(transactions).as_bytes())
+}
+
+// 验证区块：哈希链接、Merkle根、批次摘要以及证书中验证者的签名
+pub fn verify_block(
+    block: &Block,
+    prev: Option<&BlockHeader>,
+    validators: &HashMap<usize, PublicKey>,
+    genesis: &Genesis,
+) -> Result<(), String> {
+    let header = &block.header;
+    let hasher = genesis.hasher();
+    verify_link(header, prev, hasher)?;
+    if header.merkle_root != merkle::merkle_root(hasher, &encode_transactions(&block.transactions)) {
+        return Err("Merkle根与区块交易不符".to_string());
+    }
+    if header.digest != digest_transactions(hasher, &block.transactions) {
+        return Err("批次摘要与区块交易不符".to_string());
+    }
+    genesis.features.check_all(&block.transactions, header.height)?;
+    verify_signatures(header, &block.certificate, validators, &genesis.chain_id)
+}
+
+// 区块头的高度和前一区块哈希须与prev衔接；prev为None时须是第一个区块
+fn verify_link(header: &BlockHeader, prev: Option<&BlockHeader>, hasher: &dyn Hasher) -> Result<(), String> {
+    let (expected_height, expected_prev_hash) = match prev {
+        Some(prev) => (prev.height + 1, prev.hash(hasher)),
+        None => (1, "0".repeat(64)),
+    };
+    if header.height != expected_height {
+        return Err(format!("区块高度{}不连续，期望{}", header.height, expected_height));
+    }
+    if header.prev_hash != expected_prev_hash {
+        return Err("前一区块哈希不匹配".to_string());
+    }
+    Ok(())
+}
+
+// 没有区块体时验证区块头：哈希链接和提交证书
+pub fn verify_header(
+    header: &BlockHeader,
+    certificate: &CommitCertificate,
+    prev: Option<&BlockHeader>,
+    validators: &HashMap<usize, PublicKey>,
+    genesis: &Genesis,
+) -> Result<(), String> {
+    verify_link(header, prev, genesis.hasher())?;
+    verify_signatures(header, certificate, validators, &genesis.chain_id)
+}
+
+// 外部审计方独立验证提交证书所需的全部信息：签名内容前缀的链ID和各验证者的公钥
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidatorSet {
+    pub chain_id: String,
+    pub validators: BTreeMap<usize, String>, // 验证者ID -> 十六进制ed25519公钥
+    #[serde(default)]
+    pub hash_function: HashFunction, // 该链的批次摘要和Merkle树使用的哈希函数
+}
+
+impl ValidatorSet {
+    // 节点目录中登记为验证者、且在创世验证者集合中的节点
+    pub fn from_directory(genesis: &Genesis, store: &BTreeMap<String, String>) -> Self {
+        let validators = directory::entries(store).into_iter()
+            .filter(|entry| entry.role == Role::Validator && genesis.validators.contains(&entry.node_id))
+            .map(|entry| (entry.node_id, entry.public_key))
+            .collect();
+        ValidatorSet { chain_id: genesis.chain_id.clone(), validators, hash_function: genesis.hash_function }
+    }
+}
+
+// 只凭区块头、证书和验证者集合判断区块是否由合法的法定人数提交，不需要访问任何节点
+pub fn verify_commit_certificate(header: &BlockHeader, certificate: &CommitCertificate, validator_set: &ValidatorSet) -> Result<(), String> {
+    let validators = validator_set.validators.iter()
+        .map(|(node_id, key)| PublicKey::from_hex(key).map(|key| (*node_id, key)).map_err(|e| format!("验证者{}的公钥无效: {}", node_id, e)))
+        .collect::<Result<HashMap<_, _>, String>>()?;
+    verify_signatures(header, certificate, &validators, &validator_set.chain_id)
+}
+
+// 证书须与区块头一致，且有法定人数的验证者签名
+fn verify_signatures(
+    header: &BlockHeader,
+    certificate: &CommitCertificate,
+    validators: &HashMap<usize, PublicKey>,
+    chain_id: &str,
+) -> Result<(), String> {
+    if certificate.view != header.view
+        || certificate.sequence_number != header.sequence_number
+        || certificate.digest != header.digest
+    {
+        return Err("提交证书与区块头不匹配".to_string());
+    }
+
+    let commit = PBFTMessage::Commit {
+        view: certificate.view,
+        sequence_number: certificate.sequence_number,
+        digest: certificate.digest.clone(),
+    };
+    let payload = genesis::message_payload(chain_id, &commit);
+    let candidates: Vec<(usize, &PublicKey, &Signature)> = certificate.signatures.iter()
+        .filter(|(node_id, _)| *node_id < N)
+        .filter_map(|(node_id, signature)| validators.get(node_id).map(|pubkey| (*node_id, pubkey, signature)))
+        .collect();
+    let items: Vec<(&PublicKey, &[u8], &Signature)> = candidates.iter()
+        .map(|(_, pubkey, signature)| (*pubkey, payload.as_slice(), *signature))
+        .collect();
+    let signers: HashSet<usize> = candidates.iter().zip(crypto::batch_verify(&items))
+        .filter(|(_, valid)| *valid)
+        .map(|((node_id, _, _), _)| *node_id)
+        .collect();
+
+    if signers.len() >= COMMIT_QUORUM {
+        Ok(())
+    } else {
+        Err(format!("提交证书只有{}个有效签名，需要{}个", signers.len(), COMMIT_QUORUM))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SigningKey;
+    use crate::directory::{DirectoryEntry, SignedEntry};
+    use crate::hash::Sha256;
+    use crate::quota::{Quota, Sandbox};
+
+    // 外部审计方只拿到区块头、证书和验证者集合也能独立验证
+    #[test]
+    fn auditors_verify_certificates_from_headers() {
+        let keys: Vec<SigningKey> = (0..N).map(|_| SigningKey::generate()).collect();
+        let genesis = Genesis { chain_id: "audit-test".to_string(), ..Genesis::default() };
+        let mut state = BTreeMap::new();
+        let mut store = Sandbox::new(&mut state, Quota::default());
+        for (node_id, key) in keys.iter().enumerate() {
+            let entry = DirectoryEntry { node_id, addresses: Vec::new(), public_key: key.public_key().to_hex(), role: Role::Validator, sequence: 0 };
+            directory::apply_registration(&mut store, &serde_json::to_string(&SignedEntry::sign(entry, key)).unwrap()).unwrap();
+        }
+        let validator_set = ValidatorSet::from_directory(&genesis, &store);
+        assert_eq!(validator_set.validators.len(), N);
+
+        let mut chain = Chain::default();
+        let transactions = vec![Transaction { operation: "SET k v".to_string(), client_id: None, session: None, timestamp: None }];
+        let digest = digest_transactions(&Sha256, &transactions);
+        let commit = PBFTMessage::Commit { view: 0, sequence_number: 1, digest: digest.clone() };
+        let payload = genesis.message_payload(&commit);
+        let signatures: Vec<(usize, Signature)> = keys.iter().enumerate().take(COMMIT_QUORUM).map(|(id, key)| (id, key.sign(&payload))).collect();
+        let certificate = CommitCertificate { view: 0, sequence_number: 1, digest: digest.clone(), signatures };
+        let header = chain.append(0, 1, digest, transactions, certificate.clone()).header.clone();
+        assert_eq!(verify_commit_certificate(&header, &certificate, &validator_set), Ok(()));
+
+        let mut short = certificate.clone();
+        short.signatures.pop();
+        assert!(verify_commit_certificate(&header, &short, &validator_set).unwrap_err().contains("有效签名"));
+        let mut other_header = header.clone();
+        other_header.sequence_number = 2;
+        assert!(verify_commit_certificate(&other_header, &certificate, &validator_set).is_err());
+        let other_chain = ValidatorSet { chain_id: "other".to_string(), ..validator_set.clone() };
+        assert!(verify_commit_certificate(&header, &certificate, &other_chain).is_err(), "其他链的签名域不同");
+    }
+}
@@ -0,0 +1,147 @@
+// src/chain_index.rs
+
+// 链上交易的二级索引：交易摘要 → 位置，客户端ID → 位置列表，客户端不必扫描区块就能知道
+// 自己的操作是否已提交、在哪个高度。交易摘要是交易规范编码（Transaction::encode）的哈希，
+// 使用创世配置选定的哈希函数。索引随链一起保存在node_{id}_chain_index.json，
+// 启动时索引的高度或哈希函数与链不一致（例如文件缺失或写到一半）就从区块重建
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use crate::chain::{Block, Chain};
+use crate::domain;
+use crate::hash::HashFunction;
+use crate::message::Transaction;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxLocation {
+    pub height: u64,
+    pub index: usize,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ChainIndex {
+    hash_function: HashFunction,
+    height: u64, // 已索引到的高度
+    by_digest: HashMap<String, Vec<TxLocation>>, // 内容相同的交易可能提交多次
+    by_client: HashMap<String, Vec<TxLocation>>,
+}
+
+pub fn filename(directory: &Path, node_id: usize) -> PathBuf {
+    directory.join(format!("node_{}_chain_index.json", node_id))
+}
+
+pub fn transaction_digest(hash_function: HashFunction, transaction: &Transaction) -> String {
+    hash_function.hasher().domain_digest(domain::TRANSACTION, transaction.encode().as_bytes())
+}
+
+impl ChainIndex {
+    pub fn build(chain: &Chain) -> Self {
+        let mut index = ChainIndex::default();
+        index.reset(chain.hash_function, chain.base.as_ref().map(|header| header.height).unwrap_or(0));
+        for block in &chain.blocks {
+            index.index_block(block);
+        }
+        index
+    }
+
+    // 读取保存的索引；与链不一致时重建
+    pub fn load(node_id: usize, chain: &Chain) -> Self {
+        let saved = std::fs::read_to_string(filename(Path::new("."), node_id)).ok()
+            .and_then(|data| serde_json::from_str::<ChainIndex>(&data).ok());
+        match saved {
+            Some(index) if index.height == chain.height() && index.hash_function == chain.hash_function => index,
+            _ => ChainIndex::build(chain),
+        }
+    }
+
+    // 清空索引，从高度height之后开始索引（状态同步的起点之前没有区块）
+    pub fn reset(&mut self, hash_function: HashFunction, height: u64) {
+        *self = ChainIndex { hash_function, height, ..ChainIndex::default() };
+    }
+
+    pub fn index_block(&mut self, block: &Block) {
+        for (i, tx) in block.transactions.iter().enumerate() {
+            let location = TxLocation { height: block.header.height, index: i };
+            self.by_digest.entry(transaction_digest(self.hash_function, tx)).or_default().push(location.clone());
+            if let Some(client_id) = &tx.client_id {
+                self.by_client.entry(client_id.clone()).or_default().push(location);
+            }
+        }
+        self.height = block.header.height;
+    }
+
+    pub fn by_digest(&self, digest: &str) -> Vec<TxLocation> {
+        self.by_digest.get(digest).cloned().unwrap_or_default()
+    }
+
+    pub fn by_client(&self, client_id: &str) -> Vec<TxLocation> {
+        self.by_client.get(client_id).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::chain::CommitCertificate;
+    use crate::config::N;
+    use crate::message::PBFTMessage;
+    use crate::qos::Priority;
+    use crate::testing::TestCluster;
+
+    fn append(chain: &mut Chain, operations: &[(&str, Option<&str>)]) {
+        let transactions = operations.iter()
+            .map(|(operation, client_id)| Transaction { operation: operation.to_string(), client_id: client_id.map(String::from), session: None, timestamp: None })
+            .collect();
+        let seq = chain.height() + 1;
+        let certificate = CommitCertificate { view: 0, sequence_number: seq, digest: String::new(), signatures: Vec::new() };
+        chain.append(0, seq, String::new(), transactions, certificate);
+    }
+
+    #[test]
+    fn indexes_follow_the_chain() {
+        let mut chain = Chain::default();
+        append(&mut chain, &[("SET a 1", Some("alice")), ("SET b 2", None)]);
+        append(&mut chain, &[("SET a 1", Some("alice")), ("SET c 3", Some("bob"))]);
+        let repeated = transaction_digest(chain.hash_function, &chain.blocks[0].transactions[0]);
+        assert_eq!(chain.index.by_digest(&repeated), vec![TxLocation { height: 1, index: 0 }, TxLocation { height: 2, index: 0 }]);
+        assert_eq!(chain.index.by_client("bob"), vec![TxLocation { height: 2, index: 1 }]);
+        assert!(chain.index.by_client("nobody").is_empty());
+        assert_eq!(ChainIndex::build(&chain).by_client("alice"), chain.index.by_client("alice"));
+
+        // 状态同步后从快照高度重新开始，之前的位置不再有效
+        let header = chain.blocks[1].header.clone();
+        chain.reset_to(header);
+        append(&mut chain, &[("SET d 4", Some("alice"))]);
+        assert_eq!(chain.index.by_client("alice"), vec![TxLocation { height: 3, index: 0 }]);
+        assert_eq!(ChainIndex::build(&chain).by_client("alice"), chain.index.by_client("alice"));
+    }
+
+    // 提交后各节点都能按摘要和客户端ID找到交易；索引文件丢失的节点重启时从区块重建
+    #[tokio::test]
+    async fn committed_transactions_are_indexed_across_restarts() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut cluster = TestCluster::builder().build().await;
+            let request = PBFTMessage::Request {
+                operation: "SET k v".to_string(),
+                priority: Priority::Normal,
+                client_id: Some("carol".to_string()),
+                expires_at: None,
+                session: None,
+                timestamp: Some(1),
+            };
+            let digest = transaction_digest(HashFunction::default(), &request.to_transaction().unwrap());
+            cluster.submit_request(request).await;
+            let indexed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.chains[id].lock().unwrap().index.by_digest(&digest).len() == 1)
+            }).await;
+            assert!(indexed, "提交的交易未进入索引");
+            let location = cluster.chains[1].lock().unwrap().index.by_client("carol");
+            assert_eq!(location, cluster.chains[1].lock().unwrap().index.by_digest(&digest));
+
+            std::fs::remove_file(filename(Path::new("."), 1)).unwrap();
+            cluster.restart(1).await;
+            assert_eq!(cluster.chains[1].lock().unwrap().index.by_client("carol"), location);
+        }).await;
+    }
+}
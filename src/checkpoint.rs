@@ -0,0 +1,151 @@
+// src/checkpoint.rs
+
+// 检查点：每隔CHECKPOINT_INTERVAL个区块，验证者广播执行该区块后的状态摘要。
+// 法定人数的摘要一致即为稳定检查点；本节点的摘要与法定人数不同说明执行状态已经分叉，
+// 这只能是本节点的状态出了问题（磁盘损坏、非确定性执行或被篡改），必须作为事件报告
+use std::collections::{BTreeMap, HashMap};
+use serde::{Serialize, Deserialize};
+use crate::config::MAX_PENDING_CHECKPOINTS;
+use crate::quorum::COMMIT_QUORUM;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointEvent {
+    Stable { height: u64, digest: String },
+    Diverged { height: u64, local: String, quorum: String },
+}
+
+pub struct CheckpointTracker {
+    node_id: usize,
+    interval: u64,
+    votes: BTreeMap<u64, HashMap<usize, String>>, // 高度 -> 节点 -> 状态摘要，含本节点
+    pub stable: Option<(u64, String)>,            // 最近的稳定检查点
+}
+
+impl CheckpointTracker {
+    pub fn new(node_id: usize, interval: u64) -> Self {
+        CheckpointTracker { node_id, interval, votes: BTreeMap::new(), stable: None }
+    }
+
+    pub fn is_checkpoint(&self, height: u64) -> bool {
+        self.interval > 0 && height > 0 && height % self.interval == 0
+    }
+
+    // 记录一个节点（可以是本节点）在某高度的状态摘要，法定人数形成且本节点已有摘要时给出结论
+    pub fn record(&mut self, height: u64, node_id: usize, digest: String) -> Option<CheckpointEvent> {
+        let stable_height = self.stable.as_ref().map(|(h, _)| *h).unwrap_or(0);
+        // 只接受检查点高度，且不超前太多，防止作恶节点用任意高度占满内存
+        if !self.is_checkpoint(height) || height <= stable_height || height > stable_height + self.interval * MAX_PENDING_CHECKPOINTS {
+            return None;
+        }
+        let votes = self.votes.entry(height).or_default();
+        votes.entry(node_id).or_insert(digest);
+
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        for digest in votes.values() {
+            *counts.entry(digest).or_default() += 1;
+        }
+        let quorum = counts.into_iter().find(|(_, count)| *count >= COMMIT_QUORUM)?.0.clone();
+        let local = votes.get(&self.node_id)?.clone();
+
+        self.stable = Some((height, quorum.clone()));
+        self.votes = self.votes.split_off(&(height + 1));
+        if local == quorum {
+            Some(CheckpointEvent::Stable { height, digest: quorum })
+        } else {
+            Some(CheckpointEvent::Diverged { height, local, quorum })
+        }
+    }
+}
+
+// 已稳定检查点的状态摘要，保存在磁盘上，供离线重放时比对
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StateRoots {
+    pub roots: BTreeMap<u64, String>, // 高度 -> 法定人数一致的状态摘要
+}
+
+impl StateRoots {
+    pub fn load(node_id: usize) -> Self {
+        std::fs::read_to_string(format!("node_{}_state_roots.json", node_id)).ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn record(node_id: usize, height: u64, digest: String) {
+        let mut state_roots = StateRoots::load(node_id);
+        state_roots.roots.insert(height, digest);
+        let data = serde_json::to_string(&state_roots).unwrap();
+        std::fs::write(format!("node_{}_state_roots.json", node_id), data).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::audit::{AuditEntry, AuditEvent};
+    use crate::byzantine::Strategy;
+    use crate::config::{CHECKPOINT_INTERVAL, N};
+    use crate::testing::TestCluster;
+
+    #[test]
+    fn reports_stable_and_diverged_checkpoints() {
+        let mut tracker = CheckpointTracker::new(0, 10);
+        assert_eq!(tracker.record(5, 1, "a".to_string()), None);
+        assert_eq!(tracker.record(10, 1, "a".to_string()), None);
+        assert_eq!(tracker.record(10, 2, "a".to_string()), None);
+        assert_eq!(tracker.record(10, 3, "b".to_string()), None);
+        // 法定人数已形成，本节点的摘要一到即可判断
+        assert_eq!(tracker.record(10, 0, "a".to_string()), Some(CheckpointEvent::Stable { height: 10, digest: "a".to_string() }));
+        assert_eq!(tracker.record(10, 3, "a".to_string()), None);
+
+        assert_eq!(tracker.record(20, 0, "x".to_string()), None);
+        assert_eq!(tracker.record(20, 1, "y".to_string()), None);
+        assert_eq!(tracker.record(20, 2, "y".to_string()), None);
+        let diverged = tracker.record(20, 3, "y".to_string());
+        assert_eq!(diverged, Some(CheckpointEvent::Diverged { height: 20, local: "x".to_string(), quorum: "y".to_string() }));
+        assert_eq!(tracker.stable, Some((20, "y".to_string())));
+
+        assert_eq!(tracker.record(20 + 10 * (MAX_PENDING_CHECKPOINTS + 1), 1, "z".to_string()), None);
+        assert!(tracker.votes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn corrupted_replica_detects_divergence_and_resyncs() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            // 节点3的执行状态被篡改，区块本身没有问题
+            cluster.executions[N - 1].lock().unwrap().restore(
+                0,
+                vec![("corrupted".to_string(), "1".to_string())].into_iter().collect(),
+            );
+            for i in 1..=CHECKPOINT_INTERVAL {
+                let operation = format!("SET k{} v", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(10), |c| {
+                    (0..N).all(|id| c.committed_view(id, &operation).is_some())
+                }).await;
+                assert!(committed, "操作{}未提交", operation);
+            }
+
+            let diverged = |id: usize| std::fs::read_to_string(format!("node_{}_audit.jsonl", id)).unwrap_or_default()
+                .lines()
+                .any(|line| matches!(serde_json::from_str::<AuditEntry>(line).unwrap().event, AuditEvent::StateDivergence { .. }));
+            let reported = cluster.wait_until(Duration::from_secs(10), |_| diverged(N - 1)).await;
+            assert!(reported, "被篡改的节点未发现状态分叉");
+            for id in 0..N - 1 {
+                assert!(!diverged(id), "节点{}误报状态分叉", id);
+            }
+
+            // 被篡改的节点从其他节点的快照恢复状态，之后照常执行新的区块
+            let state = |c: &TestCluster, id: usize| c.executions[id].lock().unwrap().state().clone();
+            let repaired = cluster.wait_until(Duration::from_secs(10), |c| state(c, N - 1) == state(c, 0)).await;
+            assert!(repaired, "被篡改的节点未恢复状态");
+            assert!(cluster.executions[N - 1].lock().unwrap().get("corrupted").is_none());
+            cluster.submit("SET after repair").await;
+            let executed = cluster.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|id| c.executions[id].lock().unwrap().get("after").is_some())
+            }).await;
+            assert!(executed, "恢复后的节点未执行新区块");
+        }).await;
+    }
+}
@@ -0,0 +1,714 @@
+// src/client.rs
+
+// 客户端SDK：经各验证者的JSON行RPC读写。写请求发给当前主节点（用Primary查询，提交失败时重新查询）；
+// 读请求分散到健康的副本上。客户端为每个副本记录调用延迟（指数移动平均）和最近报告的执行高度：
+// 最近调用失败的副本在CLIENT_RETRY_MS内不参与读，执行高度落后最高者超过CLIENT_MAX_STALENESS个区块的副本也不参与，
+// 其余副本中随机取两个、选延迟较低的一个（两选一），负载分散到整个集群又偏向较快的副本。
+// 管道（Pipeline）在一条连到主节点的RPC连接上同时提交多个请求：每次提交返回一个句柄，它是一个future，
+// 请求执行后得到执行状态，或者在CLIENT_REQUEST_TIMEOUT_MS内没有答复时得到超时（期间发生过视图切换时为视图切换）错误。
+// 管道只采信所连节点推送的答复。
+// 读自己的写：客户端记录本会话经管道写入、已执行的最高高度（答复中的height），之后的读带上该高度作为min_height，
+// 副本落后时等待执行追上或让客户端改读其他副本，客户端不会在写入之后从落后的副本读到旧值。
+// 已知执行到该高度的副本优先被选中
+// 命令行：pbft-blockchain client get <KEY> [--repeat 10] [--min-height H] | submit <OPERATION> [--wait] | replicas [--token TOKEN]
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use rand::seq::SliceRandom;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::oneshot;
+use tokio::time::{timeout, Instant};
+use crate::config::{N, CLIENT_MAX_STALENESS, CLIENT_REFRESH_MS, CLIENT_REQUEST_TIMEOUT_MS, CLIENT_RETRY_MS, CLIENT_RPC_TIMEOUT_MS};
+use crate::execution::ExecutionStatus;
+use crate::message::{PBFTMessage, ReplyOutcome};
+use crate::network;
+
+const LATENCY_SMOOTHING: f64 = 0.2; // 新样本在延迟均值中的权重
+const EXPIRY_CHECK_MS: u64 = 100; // 管道检查在途请求是否超时的间隔
+
+#[derive(Debug, Clone, Default)]
+pub struct Replica {
+    pub addresses: Vec<String>,
+    pub latency_ms: Option<f64>,
+    pub height: Option<u64>, // 最近一次报告的执行高度
+    pub failed_at: Option<Instant>,
+    pub reads: u64,
+}
+
+pub struct Client {
+    pub replicas: BTreeMap<usize, Replica>,
+    pub primary: Option<usize>,
+    token: Option<String>, // 每个连接先用该令牌认证，提交请求需要submitter角色
+    refreshed: Option<Instant>,
+    written: Arc<AtomicU64>, // 本会话写入已执行到的最高高度，与本客户端打开的管道共享
+}
+
+impl Client {
+    pub fn new(addresses: BTreeMap<usize, Vec<String>>, token: Option<String>) -> Self {
+        let replicas = addresses.into_iter()
+            .map(|(id, addresses)| (id, Replica { addresses, ..Replica::default() }))
+            .collect();
+        Client { replicas, primary: None, token, refreshed: None, written: Arc::default() }
+    }
+
+    // 连接全部验证者的默认RPC地址
+    pub fn for_validators(token: Option<String>) -> Self {
+        Client::new((0..N).map(|id| (id, network::default_addresses(id))).collect(), token)
+    }
+
+    // 读取一个键，返回值和答复的副本。本会话写入过时只从执行到写入高度的副本读
+    pub async fn get(&mut self, key: &str) -> Result<(Value, usize), String> {
+        let min_height = self.written_height();
+        let request = if min_height > 0 {
+            json!({ "method": "Get", "key": key, "min_height": min_height })
+        } else {
+            json!({ "method": "Get", "key": key })
+        };
+        let mut last_error = "没有可读的副本".to_string();
+        for _ in 0..self.replicas.len() {
+            if self.refreshed.map_or(true, |at| at.elapsed() >= Duration::from_millis(CLIENT_REFRESH_MS)) {
+                self.refresh().await;
+            }
+            let replica = match self.pick_reader(min_height) {
+                Some(replica) => replica,
+                None => break,
+            };
+            match self.call(replica, &request).await {
+                Ok(response) if response["redirect"] == true => {
+                    // 副本等不到执行追上，记下它的高度，改读其他副本
+                    let height = response["applied_height"].as_u64();
+                    if let Some(stats) = self.replicas.get_mut(&replica) {
+                        stats.height = height;
+                    }
+                    last_error = format!("副本{}执行到高度{:?}，低于本会话写入的高度{}", replica, height, min_height);
+                }
+                Ok(response) => {
+                    if let Some(stats) = self.replicas.get_mut(&replica) {
+                        stats.reads += 1;
+                        if let Some(height) = response["height"].as_u64() {
+                            stats.height = Some(stats.height.map_or(height, |known| known.max(height)));
+                        }
+                    }
+                    return Ok((response["value"].clone(), replica));
+                }
+                Err(e) => last_error = format!("副本{}: {}", replica, e),
+            }
+        }
+        Err(last_error)
+    }
+
+    // 把请求提交给当前主节点，返回接收请求的节点
+    pub async fn submit(&mut self, operation: &str) -> Result<usize, String> {
+        let request = json!({ "method": "Submit", "message": { "kind": "Request", "operation": operation } });
+        let mut last_error = "找不到主节点".to_string();
+        for _ in 0..self.replicas.len() {
+            let primary = match self.primary {
+                Some(primary) => primary,
+                None => match self.discover_primary().await {
+                    Some(primary) => primary,
+                    None => break,
+                },
+            };
+            match self.call(primary, &request).await {
+                Ok(_) => return Ok(primary),
+                Err(e) => {
+                    // 主节点可能已经切换或下线，下次重新查询
+                    last_error = format!("主节点{}: {}", primary, e);
+                    self.primary = None;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    // 连接当前主节点，得到可以同时提交多个请求的管道
+    pub async fn pipeline(&mut self) -> Result<Pipeline, String> {
+        let primary = match self.primary {
+            Some(primary) => primary,
+            None => self.discover_primary().await.ok_or("找不到主节点")?,
+        };
+        let addresses = self.replicas[&primary].addresses.clone();
+        let pipeline = Pipeline::connect(primary, &addresses, self.token.as_deref(), self.written.clone()).await;
+        if pipeline.is_err() {
+            self.primary = None;
+        }
+        pipeline
+    }
+
+    // 本会话写入已执行到的最高高度，0表示尚未写入
+    pub fn written_height(&self) -> u64 {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    // 记录在本会话之外得知的写入高度，例如另一个进程写入后交给本客户端读
+    pub fn wrote_at(&self, height: u64) {
+        self.written.fetch_max(height, Ordering::Relaxed);
+    }
+
+    // 向各副本查询执行高度，同时得到延迟样本
+    pub async fn refresh(&mut self) {
+        let ids: Vec<usize> = self.replicas.keys().copied().collect();
+        for id in ids {
+            if !self.healthy(id) {
+                continue;
+            }
+            if let Ok(response) = self.call(id, &json!({ "method": "AppliedHeight" })).await {
+                if let (Some(height), Some(replica)) = (response["applied_height"].as_u64(), self.replicas.get_mut(&id)) {
+                    replica.height = Some(height);
+                }
+            }
+        }
+        self.refreshed = Some(Instant::now());
+    }
+
+    // 问任一健康的副本当前视图的主节点
+    async fn discover_primary(&mut self) -> Option<usize> {
+        let ids: Vec<usize> = self.replicas.keys().copied().filter(|id| self.healthy(*id)).collect();
+        for id in ids {
+            if let Some(primary) = self.call(id, &json!({ "method": "Primary" })).await.ok().and_then(|response| response["primary"].as_u64()) {
+                let primary = primary as usize;
+                if self.replicas.contains_key(&primary) {
+                    self.primary = Some(primary);
+                    return Some(primary);
+                }
+            }
+        }
+        None
+    }
+
+    fn healthy(&self, id: usize) -> bool {
+        self.replicas.get(&id)
+            .is_some_and(|replica| replica.failed_at.map_or(true, |at| at.elapsed() >= Duration::from_millis(CLIENT_RETRY_MS)))
+    }
+
+    // 可以读的副本：健康，且执行高度不比最高者落后CLIENT_MAX_STALENESS个区块以上（尚未报告高度的副本也可以读）
+    pub fn readable(&self) -> Vec<usize> {
+        let healthy: Vec<usize> = self.replicas.keys().copied().filter(|id| self.healthy(*id)).collect();
+        let highest = healthy.iter().filter_map(|id| self.replicas[id].height).max().unwrap_or(0);
+        healthy.into_iter()
+            .filter(|id| self.replicas[id].height.map_or(true, |height| height + CLIENT_MAX_STALENESS >= highest))
+            .collect()
+    }
+
+    // 两选一：随机取两个可读的副本，选延迟较低的；没有延迟样本的副本优先，以便尽快得到样本。
+    // 已知执行到min_height的副本优先；一个也没有时仍从可读的副本中选，由副本等待执行追上
+    fn pick_reader(&self, min_height: u64) -> Option<usize> {
+        let readable = self.readable();
+        let caught_up: Vec<usize> = readable.iter().copied()
+            .filter(|id| self.replicas[id].height.is_some_and(|height| height >= min_height))
+            .collect();
+        let candidates = if caught_up.is_empty() { readable } else { caught_up };
+        let mut rng = rand::thread_rng();
+        let chosen: Vec<usize> = candidates.choose_multiple(&mut rng, 2).copied().collect();
+        chosen.into_iter().min_by(|a, b| {
+            let latency = |id: &usize| self.replicas[id].latency_ms.unwrap_or(0.0);
+            latency(a).total_cmp(&latency(b))
+        })
+    }
+
+    // 一次调用一个连接：认证（如配置了令牌）、发送请求、读取一行答复。答复中的error视为调用失败
+    async fn call(&mut self, id: usize, request: &Value) -> Result<Value, String> {
+        let addresses = self.replicas.get(&id).map(|replica| replica.addresses.clone()).ok_or_else(|| format!("未知的节点{}", id))?;
+        let started = Instant::now();
+        let result = match timeout(Duration::from_millis(CLIENT_RPC_TIMEOUT_MS), exchange(&addresses, self.token.as_deref(), request)).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("{}ms内没有答复", CLIENT_RPC_TIMEOUT_MS)),
+        };
+        let replica = self.replicas.get_mut(&id).ok_or_else(|| format!("未知的节点{}", id))?;
+        match &result {
+            Ok(_) => {
+                let sample = started.elapsed().as_secs_f64() * 1000.0;
+                replica.latency_ms = Some(replica.latency_ms.map_or(sample, |mean| mean + LATENCY_SMOOTHING * (sample - mean)));
+                replica.failed_at = None;
+            }
+            Err(_) => replica.failed_at = Some(Instant::now()),
+        }
+        result
+    }
+}
+
+async fn exchange(addresses: &[String], token: Option<&str>, request: &Value) -> Result<Value, String> {
+    let (_, stream) = network::dial(addresses).await.ok_or_else(|| format!("{:?}均无法连接", addresses))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut requests = Vec::new();
+    if let Some(token) = token {
+        requests.push(json!({ "method": "Authenticate", "token": token }));
+    }
+    requests.push(request.clone());
+    let mut response = Value::Null;
+    for request in requests {
+        response = request_line(&mut writer, &mut lines, &request).await?;
+    }
+    Ok(response)
+}
+
+// 发送一行请求、读取一行答复
+async fn request_line(writer: &mut OwnedWriteHalf, lines: &mut Lines<BufReader<OwnedReadHalf>>, request: &Value) -> Result<Value, String> {
+    writer.write_all(format!("{}\n", request).as_bytes()).await.map_err(|e| e.to_string())?;
+    let line = lines.next_line().await.map_err(|e| e.to_string())?.ok_or("连接被关闭")?;
+    let response: Value = serde_json::from_str(&line).map_err(|e| format!("无效的答复: {}", e))?;
+    match response["error"].as_str() {
+        Some(error) => Err(error.to_string()),
+        None => Ok(response),
+    }
+}
+
+// 经管道提交的请求没有得到执行状态的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestError {
+    Refused(String), // 节点没有接收请求，例如限流或角色不足
+    Rejected(String), // 请求未被接受排序
+    Expired, // 请求在被提议前已过期
+    TimedOut,
+    ViewChanged { view: u64 }, // 等待期间切换到了该视图，之后超时；请求仍可能被新主节点执行
+    Disconnected,
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Refused(reason) => write!(f, "节点拒绝接收请求: {}", reason),
+            RequestError::Rejected(reason) => write!(f, "请求未被接受: {}", reason),
+            RequestError::Expired => write!(f, "请求在被提议前已过期"),
+            RequestError::TimedOut => write!(f, "{}ms内没有执行结果", CLIENT_REQUEST_TIMEOUT_MS),
+            RequestError::ViewChanged { view } => write!(f, "等待期间切换到视图{}，之后没有执行结果", view),
+            RequestError::Disconnected => write!(f, "与节点的连接中断"),
+        }
+    }
+}
+
+type RequestResult = Result<ExecutionStatus, RequestError>;
+
+struct InFlight {
+    result: oneshot::Sender<RequestResult>,
+    deadline: Instant,
+    view_changed: Option<u64>,
+}
+
+#[derive(Default)]
+struct InFlightRequests {
+    requests: HashMap<u64, InFlight>, // 请求时间戳 -> 在途请求
+    unacknowledged: VecDeque<u64>, // 节点尚未确认的Submit，确认按发送顺序返回
+    written: Arc<AtomicU64>, // 已执行的请求答复中的最高执行高度
+}
+
+impl InFlightRequests {
+    fn finish(&mut self, timestamp: u64, result: RequestResult) {
+        if let Some(request) = self.requests.remove(&timestamp) {
+            let _ = request.result.send(result);
+        }
+    }
+
+    // 连接上的一行：Submit的确认、推送的Reply或者共识事件
+    fn handle(&mut self, client_id: &str, line: Value) {
+        if line["kind"] == "Reply" {
+            if let Ok(PBFTMessage::Reply { client_id: replied, timestamp: Some(timestamp), outcome, height, .. }) = serde_json::from_value(line) {
+                if replied == client_id {
+                    let result = match outcome {
+                        ReplyOutcome::Executed(status) => {
+                            if let Some(height) = height {
+                                self.written.fetch_max(height, Ordering::Relaxed);
+                            }
+                            Ok(status)
+                        }
+                        ReplyOutcome::Expired => Err(RequestError::Expired),
+                        ReplyOutcome::Rejected(reason) => Err(RequestError::Rejected(reason)),
+                    };
+                    self.finish(timestamp, result);
+                }
+            }
+        } else if line["event"] == "ViewChanged" {
+            let view = line["view"].as_u64().unwrap_or(0);
+            for request in self.requests.values_mut() {
+                request.view_changed = Some(view);
+            }
+        } else if line.get("event").is_none() {
+            if let (Some(timestamp), Some(error)) = (self.unacknowledged.pop_front(), line["error"].as_str()) {
+                self.finish(timestamp, Err(RequestError::Refused(error.to_string())));
+            }
+        }
+    }
+
+    // 超过时限的请求失败；句柄已被丢弃的请求不再等待
+    fn expire(&mut self, now: Instant) {
+        self.requests.retain(|_, request| !request.result.is_closed());
+        let expired: Vec<u64> = self.requests.iter().filter(|(_, request)| request.deadline <= now).map(|(timestamp, _)| *timestamp).collect();
+        for timestamp in expired {
+            let error = match self.requests[&timestamp].view_changed {
+                Some(view) => RequestError::ViewChanged { view },
+                None => RequestError::TimedOut,
+            };
+            self.finish(timestamp, Err(error));
+        }
+    }
+}
+
+// 一个在途请求的句柄，请求执行后得到执行状态
+pub struct PendingRequest {
+    result: oneshot::Receiver<RequestResult>,
+}
+
+impl Future for PendingRequest {
+    type Output = RequestResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<RequestResult> {
+        Pin::new(&mut self.result).poll(cx).map(|result| result.unwrap_or(Err(RequestError::Disconnected)))
+    }
+}
+
+pub struct Pipeline {
+    pub node_id: usize,
+    pub timeout: Duration, // 之后提交的请求等待执行结果的时限
+    client_id: String,
+    writer: OwnedWriteHalf,
+    in_flight: Arc<Mutex<InFlightRequests>>,
+    next_timestamp: u64,
+}
+
+impl Pipeline {
+    // 连接节点：认证（如配置了令牌）并订阅共识事件，之后由后台任务读取确认、答复和事件。
+    // 请求执行后，答复中的执行高度记入written
+    pub async fn connect(node_id: usize, addresses: &[String], token: Option<&str>, written: Arc<AtomicU64>) -> Result<Pipeline, String> {
+        let (_, stream) = network::dial(addresses).await.ok_or_else(|| format!("{:?}均无法连接", addresses))?;
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut requests = Vec::new();
+        if let Some(token) = token {
+            requests.push(json!({ "method": "Authenticate", "token": token }));
+        }
+        requests.push(json!({ "method": "SubscribeEvents" }));
+        for request in requests {
+            match timeout(Duration::from_millis(CLIENT_RPC_TIMEOUT_MS), request_line(&mut writer, &mut lines, &request)).await {
+                Ok(result) => result?,
+                Err(_) => return Err(format!("{}ms内没有答复", CLIENT_RPC_TIMEOUT_MS)),
+            };
+        }
+        // 每个管道是一个新客户端，时间戳从1开始
+        let client_id = format!("pipeline-{:016x}", rand::random::<u64>());
+        let in_flight = Arc::new(Mutex::new(InFlightRequests { written, ..InFlightRequests::default() }));
+        tokio::spawn(dispatch(lines, client_id.clone(), in_flight.clone()));
+        Ok(Pipeline { node_id, timeout: Duration::from_millis(CLIENT_REQUEST_TIMEOUT_MS), client_id, writer, in_flight, next_timestamp: 1 })
+    }
+
+    // 提交一个操作，不等待执行；返回的句柄在请求执行、被拒绝或超时后完成
+    pub async fn submit(&mut self, operation: &str) -> PendingRequest {
+        let timestamp = self.next_timestamp;
+        self.next_timestamp += 1;
+        let (sender, result) = oneshot::channel();
+        {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            in_flight.requests.insert(timestamp, InFlight { result: sender, deadline: Instant::now() + self.timeout, view_changed: None });
+            in_flight.unacknowledged.push_back(timestamp);
+        }
+        let request = json!({
+            "method": "Submit",
+            "message": { "kind": "Request", "operation": operation, "client_id": self.client_id, "timestamp": timestamp },
+        });
+        if self.writer.write_all(format!("{}\n", request).as_bytes()).await.is_err() {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            in_flight.unacknowledged.retain(|unacknowledged| *unacknowledged != timestamp);
+            in_flight.finish(timestamp, Err(RequestError::Disconnected));
+        }
+        PendingRequest { result }
+    }
+
+    // 尚未完成的请求数
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().requests.len()
+    }
+}
+
+// 管道的后台任务：按行分派确认、答复和事件，定期检查超时。连接关闭时在途请求全部以连接中断结束
+async fn dispatch(mut lines: Lines<BufReader<OwnedReadHalf>>, client_id: String, in_flight: Arc<Mutex<InFlightRequests>>) {
+    let mut expiry = tokio::time::interval(Duration::from_millis(EXPIRY_CHECK_MS));
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => {
+                    if let Ok(line) = serde_json::from_str(&line) {
+                        in_flight.lock().unwrap().handle(&client_id, line);
+                    }
+                }
+                _ => break,
+            },
+            _ = expiry.tick() => in_flight.lock().unwrap().expire(Instant::now()),
+        }
+    }
+    in_flight.lock().unwrap().requests.clear();
+}
+
+// 经管道提交全部操作，再逐个等待执行结果
+async fn submit_and_wait(client: &mut Client, operations: &[String]) -> Result<(), String> {
+    let mut pipeline = client.pipeline().await?;
+    let mut pending = Vec::new();
+    for operation in operations {
+        pending.push((operation, pipeline.submit(operation).await));
+    }
+    eprintln!("已向节点{}提交{}个请求，{}个在途", pipeline.node_id, operations.len(), pipeline.in_flight());
+    let mut failed = 0;
+    for (operation, request) in pending {
+        match request.await {
+            Ok(status) => println!("{}", json!({ "operation": operation, "status": status })),
+            Err(e) => {
+                failed += 1;
+                println!("{}", json!({ "operation": operation, "error": e.to_string() }));
+            }
+        }
+    }
+    eprintln!("本会话写入已执行到高度{}，之后的读可带上--min-height {}", client.written_height(), client.written_height());
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(format!("{}个请求没有执行结果", failed))
+    }
+}
+
+// client get <KEY> [--repeat 10] [--min-height H] | submit <OPERATION>... [--wait] | replicas [--token TOKEN]
+pub fn run(args: &[String]) -> i32 {
+    let token = args.iter().position(|s| s == "--token").and_then(|i| args.get(i + 1)).cloned();
+    let repeat = match args.iter().position(|s| s == "--repeat").and_then(|i| args.get(i + 1)) {
+        Some(value) => match value.parse::<usize>() {
+            Ok(repeat) if repeat > 0 => repeat,
+            _ => {
+                eprintln!("--repeat的值'{}'无效", value);
+                return 2;
+            }
+        },
+        None => 1,
+    };
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("无法创建tokio运行时: {}", e);
+            return 1;
+        }
+    };
+    let mut client = Client::for_validators(token);
+    match args.iter().position(|s| s == "--min-height").and_then(|i| args.get(i + 1)).map(|value| value.parse::<u64>()) {
+        Some(Ok(height)) => client.wrote_at(height),
+        Some(Err(_)) => {
+            eprintln!("--min-height必须是区块高度");
+            return 2;
+        }
+        None => {}
+    }
+    let command = (args.first().map(String::as_str), args.get(1));
+    runtime.block_on(async {
+        let result = match command {
+            (Some("get"), Some(key)) => {
+                let mut result = Ok(());
+                for _ in 0..repeat {
+                    match client.get(key).await {
+                        Ok((value, replica)) => println!("{}", json!({ "key": key, "value": value, "replica": replica })),
+                        Err(e) => {
+                            result = Err(e);
+                            break;
+                        }
+                    }
+                }
+                result
+            }
+            (Some("submit"), Some(_)) if args.iter().any(|s| s == "--wait") => {
+                let operations: Vec<String> = args[1..].iter().take_while(|s| !s.starts_with("--")).cloned().collect();
+                submit_and_wait(&mut client, &operations).await
+            }
+            (Some("submit"), Some(operation)) => client.submit(operation).await
+                .map(|primary| println!("{}", json!({ "submitted": true, "primary": primary }))),
+            (Some("replicas"), _) => {
+                client.refresh().await;
+                Ok(())
+            }
+            _ => {
+                eprintln!("用法: client get <KEY> [--repeat 10] [--min-height H] | submit <OPERATION>... [--wait] | replicas [--token TOKEN]");
+                return 2;
+            }
+        };
+        print_replicas(&client);
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        }
+    })
+}
+
+fn print_replicas(client: &Client) {
+    let readable = client.readable();
+    for (id, replica) in &client.replicas {
+        let latency = replica.latency_ms.map(|ms| format!("{:.1}ms", ms)).unwrap_or_else(|| "-".to_string());
+        let height = replica.height.map(|height| height.to_string()).unwrap_or_else(|| "-".to_string());
+        let state = if replica.failed_at.is_some() { "不可用" } else if readable.contains(id) { "可读" } else { "落后" };
+        eprintln!("副本{}: {}，延迟{}，执行高度{}，读取{}次", id, state, latency, height, replica.reads);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    // 模拟一个验证者的RPC：固定的执行高度和主节点，记录收到的提交
+    async fn replica(height: u64, primary: usize, submitted: Arc<Mutex<Vec<usize>>>, id: usize) -> Vec<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let submitted = submitted.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let request: Value = serde_json::from_str(&line).unwrap();
+                        let response = match request["method"].as_str() {
+                            Some("AppliedHeight") => json!({ "applied_height": height, "chain_height": height }),
+                            Some("Get") if request["min_height"].as_u64().is_some_and(|min_height| min_height > height) => {
+                                json!({ "key": request["key"], "redirect": true, "applied_height": height, "min_height": request["min_height"] })
+                            }
+                            Some("Get") => json!({ "key": request["key"], "value": format!("v{}", id), "height": height }),
+                            Some("Primary") => json!({ "view": 0, "primary": primary }),
+                            Some("Submit") => {
+                                submitted.lock().unwrap().push(id);
+                                json!({ "submitted": true })
+                            }
+                            _ => json!({ "error": "未知方法" }),
+                        };
+                        writer.write_all(format!("{}\n", response).as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        vec![address]
+    }
+
+    // 读分散到跟得上的副本，落后的和连不上的副本不参与读；写只发给主节点
+    #[tokio::test]
+    async fn reads_spread_and_writes_go_to_primary() {
+        let submitted = Arc::new(Mutex::new(Vec::new()));
+        let mut addresses = BTreeMap::new();
+        addresses.insert(0, replica(10, 1, submitted.clone(), 0).await);
+        addresses.insert(1, replica(10, 1, submitted.clone(), 1).await);
+        addresses.insert(2, replica(9, 1, submitted.clone(), 2).await);
+        addresses.insert(3, replica(10 - CLIENT_MAX_STALENESS - 1, 1, submitted.clone(), 3).await);
+        // 未监听的端口：连接被拒绝
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        addresses.insert(4, vec![closed]);
+        let mut client = Client::new(addresses, None);
+
+        for _ in 0..60 {
+            let (value, replica) = client.get("k").await.unwrap();
+            assert_eq!(value, json!(format!("v{}", replica)));
+        }
+        assert_eq!(client.readable(), vec![0, 1, 2]);
+        assert_eq!(client.replicas[&3].reads, 0, "落后的副本不应参与读");
+        assert!(client.replicas[&4].failed_at.is_some());
+        assert!([0, 1, 2].iter().filter(|id| client.replicas[id].reads > 0).count() >= 2, "读没有分散到多个副本");
+
+        assert_eq!(client.submit("SET k v").await, Ok(1));
+        assert_eq!(*submitted.lock().unwrap(), vec![1]);
+    }
+
+    // 会话写入高度10之后，读只由执行到10的副本答复；落后的副本让客户端改读，不返回旧值
+    #[tokio::test]
+    async fn reads_after_a_write_see_the_write() {
+        let submitted = Arc::new(Mutex::new(Vec::new()));
+        let mut addresses = BTreeMap::new();
+        addresses.insert(0, replica(9, 0, submitted.clone(), 0).await);
+        addresses.insert(1, replica(10, 0, submitted.clone(), 1).await);
+        addresses.insert(2, replica(9, 0, submitted.clone(), 2).await);
+        let mut client = Client::new(addresses, None);
+        for _ in 0..20 {
+            assert!(client.get("k").await.is_ok());
+        }
+        assert!(client.replicas[&0].reads + client.replicas[&2].reads > 0, "尚未写入时应从全部副本读");
+
+        client.wrote_at(10);
+        let reads = client.replicas[&1].reads;
+        for _ in 0..20 {
+            assert_eq!(client.get("k").await, Ok((json!("v1"), 1)));
+        }
+        assert_eq!(client.replicas[&1].reads, reads + 20);
+
+        client.wrote_at(11);
+        let error = client.get("k").await.unwrap_err();
+        assert!(error.contains("低于本会话写入的高度11"), "{}", error);
+    }
+
+    // 模拟主节点上的管道连接：Submit逐个确认，REFUSE被拒绝，HOLD不答复并推送一次视图切换事件，
+    // CLOSE使节点断开连接；其余操作收齐batch个后按相反顺序推送执行结果，并夹带一条其他客户端的答复
+    async fn pipelined_node(batch: usize) -> Vec<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut held = Vec::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let request: Value = serde_json::from_str(&line).unwrap();
+                let message = &request["message"];
+                let operation = message["operation"].as_str().unwrap_or_default().to_string();
+                let mut output = vec![match (request["method"].as_str(), operation.as_str()) {
+                    (Some("SubscribeEvents"), _) => json!({ "subscribed": true }),
+                    (Some("Submit"), "REFUSE") => json!({ "error": "请求过于频繁" }),
+                    (Some("Submit"), "CLOSE") => break,
+                    (Some("Submit"), _) => json!({ "submitted": true }),
+                    _ => json!({ "error": "未知方法" }),
+                }];
+                if operation == "HOLD" {
+                    output.push(json!({ "event": "ViewChanged", "view": 1, "primary": 1 }));
+                } else if !operation.is_empty() && operation != "REFUSE" {
+                    held.push((message["client_id"].as_str().unwrap().to_string(), operation, message["timestamp"].as_u64().unwrap()));
+                }
+                if held.len() == batch {
+                    let reply = |client_id: String, operation: String, timestamp: u64, outcome: ReplyOutcome| {
+                        let height = matches!(outcome, ReplyOutcome::Executed(_)).then_some(5);
+                        json!(PBFTMessage::Reply { node_id: 0, client_id, operation, session: None, timestamp: Some(timestamp), outcome, height })
+                    };
+                    output.push(reply("someone-else".to_string(), String::new(), 2, ReplyOutcome::Expired));
+                    for (client_id, operation, timestamp) in held.drain(..).rev() {
+                        let status = ExecutionStatus::Success(Some(operation.clone()));
+                        output.push(reply(client_id, operation, timestamp, ReplyOutcome::Executed(status)));
+                    }
+                }
+                for line in output {
+                    writer.write_all(format!("{}\n", line).as_bytes()).await.unwrap();
+                }
+            }
+        });
+        vec![address]
+    }
+
+    // 数百个请求同时在途，各句柄按时间戳得到自己的执行结果；拒绝、视图切换后超时和断开连接分别报告
+    #[tokio::test]
+    async fn pipeline_resolves_each_request_on_its_reply() {
+        let written = Arc::new(AtomicU64::new(0));
+        let mut pipeline = Pipeline::connect(0, &pipelined_node(200).await, None, written.clone()).await.unwrap();
+        let refused = pipeline.submit("REFUSE").await;
+        pipeline.timeout = Duration::from_millis(200);
+        let held = pipeline.submit("HOLD").await;
+        pipeline.timeout = Duration::from_millis(CLIENT_REQUEST_TIMEOUT_MS);
+        let mut pending = Vec::new();
+        for i in 0..200 {
+            pending.push(pipeline.submit(&format!("SET k{} v", i)).await);
+        }
+        for (i, request) in pending.into_iter().enumerate() {
+            assert_eq!(request.await, Ok(ExecutionStatus::Success(Some(format!("SET k{} v", i)))));
+        }
+        assert_eq!(refused.await, Err(RequestError::Refused("请求过于频繁".to_string())));
+        assert_eq!(held.await, Err(RequestError::ViewChanged { view: 1 }));
+        assert_eq!(pipeline.in_flight(), 0);
+        assert_eq!(written.load(Ordering::Relaxed), 5, "执行结果的高度应记入会话");
+
+        assert_eq!(pipeline.submit("CLOSE").await.await, Err(RequestError::Disconnected));
+    }
+}
@@ -0,0 +1,142 @@
+// src/clock.rs
+
+// 节点的本地时钟，用于模拟时钟偏移和频率漂移。
+// 偏移只影响墙上时间（日志时间戳），计时器只受漂移影响：漂移为正时本地时钟走得快，超时提前触发
+use chrono::{DateTime, Local};
+use tokio::time::{Duration, Instant, Sleep};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    pub offset_ms: i64, // 墙上时间相对真实时间的偏移
+    pub drift_ppm: i64, // 频率漂移，单位为百万分之一
+    anchor: Instant,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new(0, 0)
+    }
+}
+
+impl Clock {
+    pub fn new(offset_ms: i64, drift_ppm: i64) -> Self {
+        assert!(drift_ppm > -1_000_000, "时钟漂移必须大于-1000000ppm");
+        Clock { offset_ms, drift_ppm, anchor: Instant::now() }
+    }
+
+    // 本地时钟每走1秒对应的真实秒数的倒数
+    fn rate(&self) -> f64 {
+        1.0 + self.drift_ppm as f64 / 1_000_000.0
+    }
+
+    // 本地单调时钟的读数
+    pub fn now(&self) -> Instant {
+        self.anchor + self.anchor.elapsed().mul_f64(self.rate())
+    }
+
+    // 本地时钟走过local需要的真实时间
+    pub fn real_duration(&self, local: Duration) -> Duration {
+        local.div_f64(self.rate())
+    }
+
+    pub fn sleep(&self, local: Duration) -> Sleep {
+        tokio::time::sleep(self.real_duration(local))
+    }
+
+    // 等到本地时钟到达deadline
+    pub fn sleep_until(&self, deadline: Instant) -> Sleep {
+        self.sleep(deadline.saturating_duration_since(self.now()))
+    }
+
+    // 本地墙上时间的Unix纳秒数，用于span的时间戳
+    pub fn unix_nanos(&self) -> i64 {
+        self.wall_time().timestamp_nanos_opt().unwrap_or_default()
+    }
+
+    // 本地墙上时间：真实时间加上偏移和累积的漂移
+    pub fn wall_time(&self) -> DateTime<Local> {
+        let elapsed = self.anchor.elapsed();
+        let drifted = elapsed.mul_f64(self.rate()).as_millis() as i64 - elapsed.as_millis() as i64;
+        Local::now() + chrono::Duration::milliseconds(self.offset_ms + drifted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::testing::{TestCluster, NodeSetup};
+
+    #[test]
+    fn drift_scales_timers() {
+        let fast = Clock::new(0, 50_000);
+        let slow = Clock::new(0, -50_000);
+        let timeout = Duration::from_millis(1050);
+        assert_eq!(fast.real_duration(timeout), Duration::from_millis(1000));
+        assert!(slow.real_duration(timeout) > timeout);
+        assert_eq!(Clock::default().real_duration(timeout), timeout);
+    }
+
+    #[test]
+    fn offset_shifts_wall_time_only() {
+        let clock = Clock::new(-3_000, 0);
+        let skew = Local::now() - clock.wall_time();
+        assert!((2_900..=3_100).contains(&skew.num_milliseconds()), "偏移{}ms", skew.num_milliseconds());
+        assert!(clock.now().saturating_duration_since(Instant::now()) < Duration::from_millis(100));
+    }
+
+    // 各节点的时钟偏移几秒、漂移±5%（远超真实晶振的误差）
+    fn skewed(strategies: &[Strategy]) -> Vec<NodeSetup> {
+        let clocks = [(0, 0), (2_000, 50_000), (-2_000, -50_000), (500, 20_000)];
+        (0..N).map(|id| {
+            let (offset_ms, drift_ppm) = clocks[id % clocks.len()];
+            NodeSetup { strategy: strategies[id], clock: Clock::new(offset_ms, drift_ppm), ..NodeSetup::default() }
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn skewed_clocks_commit_without_spurious_view_change() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start_with(&skewed(&[Strategy::Honest; N]), Duration::from_millis(1000)).await;
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET k v").is_some())
+            }).await;
+            assert!(committed, "时钟偏移时请求未被提交");
+            assert!((0..N).all(|id| cluster.committed_view(id, "SET k v") == Some(0)), "时钟偏移触发了不必要的视图切换");
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn skewed_clocks_still_replace_silent_primary() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut strategies = vec![Strategy::Honest; N];
+            strategies[0] = Strategy::Silent;
+            let cluster = TestCluster::start_with(&skewed(&strategies), Duration::from_millis(300)).await;
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(30), |c| {
+                (1..N).all(|id| c.committed_view(id, "SET k v").is_some_and(|view| view >= 1))
+            }).await;
+            assert!(committed, "时钟偏移时未能替换崩溃的主节点");
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn injected_latency_stays_below_timeout() {
+        tokio::task::LocalSet::new().run_until(async {
+            let latency = Duration::from_millis(100);
+            let setups: Vec<NodeSetup> = (0..N).map(|_| NodeSetup { latency, ..NodeSetup::default() }).collect();
+            let cluster = TestCluster::start_with(&setups, Duration::from_millis(1000)).await;
+            let started = tokio::time::Instant::now();
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET k v").is_some())
+            }).await;
+            assert!(committed, "注入延迟后请求未被提交");
+            // 至少经过PrePrepare、Prepare和Commit三跳
+            assert!(started.elapsed() >= latency * 3);
+            assert!((0..N).all(|id| cluster.committed_view(id, "SET k v") == Some(0)));
+        }).await;
+    }
+}
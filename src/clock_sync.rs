@@ -0,0 +1,126 @@
+// src/clock_sync.rs
+
+// 节点间的时钟偏差估计。节点定期向对等节点发送带本地墙上时间的Ping，对方在Pong中回带自己的墙上时间。
+// 按NTP的做法假设往返路径对称：偏差 = 对方时间 - (发送时间 + 接收时间) / 2，误差不超过往返时间的一半。
+// 每个对等节点保留最近几个样本，取往返时间最短（排队最少、最可信）的一个作为估计
+use std::collections::{BTreeMap, VecDeque};
+use serde::Serialize;
+use crate::config::{CLOCK_SKEW_WARN_MS, CLOCK_SYNC_SAMPLES, F};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Estimate {
+    pub offset_ms: i64, // 对方时钟减本地时钟，为正说明对方走在前面
+    pub rtt_ms: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct ClockSync {
+    samples: BTreeMap<usize, VecDeque<Estimate>>,
+    warned: bool, // 已就当前这次越界告警过
+}
+
+impl ClockSync {
+    // 记录一次Ping往返：sent_ms和received_ms是本地墙上时间，peer_ms是对方应答时的墙上时间
+    pub fn record(&mut self, peer: usize, sent_ms: i64, peer_ms: i64, received_ms: i64) {
+        // 往返时间为负说明对方伪造了Ping的发送时间，或本地时钟被回拨
+        if received_ms < sent_ms {
+            return;
+        }
+        let sample = Estimate { offset_ms: peer_ms - (sent_ms + received_ms) / 2, rtt_ms: received_ms - sent_ms };
+        let samples = self.samples.entry(peer).or_default();
+        if samples.len() >= CLOCK_SYNC_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    pub fn estimates(&self) -> BTreeMap<usize, Estimate> {
+        self.samples.iter()
+            .filter_map(|(peer, samples)| samples.iter().min_by_key(|s| s.rtt_ms).map(|s| (*peer, *s)))
+            .collect()
+    }
+
+    // 本地时钟需要加上的修正量，使其与多数节点一致：本地（偏差0）和各对等节点偏差的中位数。
+    // 参与的节点不足2F+1个时不修正，否则F个谎报时间的拜占庭节点就能左右中位数
+    pub fn cluster_offset(&self) -> i64 {
+        let mut offsets: Vec<i64> = std::iter::once(0).chain(self.estimates().values().map(|e| e.offset_ms)).collect();
+        if offsets.len() < 2 * F + 1 {
+            return 0;
+        }
+        offsets.sort_unstable();
+        offsets[offsets.len() / 2]
+    }
+
+    // 本地时钟偏离多数节点超过CLOCK_SKEW_WARN_MS时返回修正量；每次越界只返回一次，恢复正常后重新计
+    pub fn dangerous_skew(&mut self) -> Option<i64> {
+        let offset = self.cluster_offset();
+        let dangerous = offset.abs() > CLOCK_SKEW_WARN_MS;
+        let first = dangerous && !self.warned;
+        self.warned = dangerous;
+        first.then_some(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::clock::Clock;
+    use crate::config::N;
+    use crate::message::PBFTMessage;
+    use crate::qos::Priority;
+    use crate::testing::{TestCluster, NodeSetup};
+
+    #[test]
+    fn estimates_use_fastest_round_trip_and_majority() {
+        let mut sync = ClockSync::default();
+        // 对等节点1快3000ms；排队严重的样本偏差算得不准，被往返更短的样本取代
+        sync.record(1, 1_000, 4_400, 1_600);
+        sync.record(1, 2_000, 5_010, 2_020);
+        sync.record(1, 3_000, 2_000, 2_900);
+        assert_eq!(sync.estimates()[&1], Estimate { offset_ms: 3_000, rtt_ms: 20 });
+        // 只有一个对等节点时不修正本地时钟
+        assert_eq!(sync.cluster_offset(), 0);
+        assert_eq!(sync.dangerous_skew(), None);
+
+        // 多数节点与本地一致，快3000ms的是对方
+        sync.record(2, 1_000, 1_005, 1_010);
+        sync.record(3, 1_000, 995, 1_010);
+        assert_eq!(sync.cluster_offset(), 0);
+
+        // 多数节点比本地快，本地时钟慢了，只告警一次
+        sync.record(2, 2_000, 4_990, 2_004);
+        sync.record(3, 2_000, 5_000, 2_004);
+        assert_eq!(sync.estimates()[&2].offset_ms, 2_988);
+        assert_eq!(sync.dangerous_skew(), Some(2_998));
+        assert_eq!(sync.dangerous_skew(), None);
+    }
+
+    // 主节点的时钟快3秒。修正前，客户端按真实时间1.5秒后过期的请求一到主节点就被当作已过期丢弃；
+    // 主节点从Pong得知自己比多数节点快后，按修正后的时间判断，请求正常提交
+    #[tokio::test]
+    async fn fast_primary_does_not_expire_fresh_requests() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().setup(0, NodeSetup { clock: Clock::new(3_000, 0), ..NodeSetup::default() }).build().await;
+            let synced = cluster.wait_until(Duration::from_secs(5), |c| c.clock_syncs[0].lock().unwrap().estimates().len() == N - 1).await;
+            assert!(synced, "主节点未收到所有对等节点的Pong");
+            let offset = cluster.clock_syncs[0].lock().unwrap().cluster_offset();
+            assert!((-3_100..=-2_900).contains(&offset), "主节点估计的修正量为{}ms", offset);
+            let peer = cluster.clock_syncs[1].lock().unwrap().estimates()[&0].offset_ms;
+            assert!((2_900..=3_100).contains(&peer), "节点1估计主节点快{}ms", peer);
+
+            cluster.submit_request(PBFTMessage::Request {
+                operation: "SET k v".to_string(),
+                priority: Priority::Normal,
+                client_id: None,
+                expires_at: Some(chrono::Local::now().timestamp_millis() + 1_500),
+                session: None,
+                timestamp: None,
+            }).await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET k v") == Some(0))
+            }).await;
+            assert!(committed, "时钟快的主节点丢弃了未过期的请求");
+        }).await;
+    }
+}
@@ -1,3 +1,152 @@
 // src/config.rs
+
 pub const F: usize = 1; // 拜占庭节点数量
 pub const N: usize = 3 * F + 1; // 总节点数量
+pub const RPC_BASE_PORT: u16 = 9000; // RPC端口 = 基础端口 + 节点ID
+pub const LISTEN_HOSTS: &[&str] = &["127.0.0.1", "::1"]; // 默认监听的IPv4和IPv6地址
+pub const LISTEN_ADDRESSES_ENV: &str = "PBFT_LISTEN_ADDRESSES"; // 覆盖监听地址，逗号分隔的host:port，可使用域名
+pub const DIAL_TIMEOUT_MS: u64 = 500; // 拨号时每个地址的连接超时
+pub const OTLP_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT"; // 设置后把共识实例的trace导出到该OTLP/HTTP地址
+pub const NETWORK_FAULTS_FILE: &str = "network_faults.json"; // 内存网络的丢包、重复和乱序概率，不存在时可靠投递
+pub const FIREWALL_FILE: &str = "firewall.json"; // 传输层的节点和IP准入规则，不存在时全部放行
+pub const NODE_CONFIG_FILE: &str = "node_config.json"; // 可热加载的非共识配置，不存在时使用默认值
+pub const CONFIG_POLL_MS: u64 = 2000; // 检查NODE_CONFIG_FILE是否修改的间隔
+pub const MAX_REORDER_DELAY_MS: u64 = 50; // 乱序投递的消息最多推迟的时间
+pub const HANDSHAKE_RETRY_MS: u64 = 500; // 握手挑战未得到应答时，至少间隔该时间才重发
+pub const HANDSHAKE_BUFFER_MS: u64 = 2000; // 握手完成前收到的签名消息最多缓存的时间
+pub const HANDSHAKE_BUFFER_SIZE: usize = 256; // 每个对等节点最多缓存的未认证消息数
+pub const PROTOCOL_VERSION: u32 = 1; // 节点间协议版本，握手应答时告知对端，记入对端的地址簿
+pub const PIPELINE_WORKERS: usize = 4; // 入站流水线的验签任务数，按发送者分配
+pub const PIPELINE_QUEUE_SIZE: usize = 256; // 流水线各阶段之间队列的容量，满时反压上游
+
+// tokio运行时，可用命令行参数覆盖
+pub const WORKER_THREADS: usize = 0; // 工作线程数，0表示CPU核心数
+pub const MAX_BLOCKING_THREADS: usize = 64; // 阻塞线程池上限，存储和签名校验等同步任务在其中运行
+pub const PIN_WORKER_THREADS: bool = false; // 是否把工作线程依次绑定到各个CPU核心
+
+// 批处理参数
+pub const MIN_BATCH_SIZE: usize = 1;
+pub const MAX_BATCH_SIZE: usize = 64;
+pub const MIN_BATCH_TIMEOUT_MS: u64 = 10;
+pub const MAX_BATCH_TIMEOUT_MS: u64 = 500;
+pub const COMMIT_LATENCY_TARGET_MS: u64 = 1000; // 提交延迟目标
+pub const MAX_QUEUE_DEPTH: usize = 256; // 超过该队列深度时缩小批次
+
+// 请求优先级QoS参数
+pub const HIGH_PRIORITY_BATCH_SHARE: f64 = 0.5; // 每个批次为高优先级请求预留的比例
+pub const HIGH_PRIORITY_RATE_LIMIT: f64 = 100.0; // 每秒允许的请求数
+pub const NORMAL_PRIORITY_RATE_LIMIT: f64 = 500.0;
+pub const BULK_PRIORITY_RATE_LIMIT: f64 = 1000.0;
+
+// 节点信誉
+pub const MAX_REPUTATION: i64 = 100; // 初始分数，也是上限
+pub const SUSPICION_THRESHOLD: i64 = 75; // 低于该分数的节点视为可疑：入站限流更严，加权选举中权重更低
+pub const INVALID_SIGNATURE_PENALTY: i64 = 10;
+pub const PROTOCOL_VIOLATION_PENALTY: i64 = 30;
+pub const CORRECT_VOTE_REWARD: i64 = 1; // 每张计入提交证书的签名恢复的分数
+pub const PEER_MESSAGE_RATE_LIMIT: f64 = 2000.0; // 满分节点每秒允许的入站消息数，按分数比例缩小
+pub const MIN_PEER_RATE_SHARE: f64 = 0.1; // 限流速率的下限（满分速率的比例）
+pub const SIGNED_PREPREPARE_HISTORY: usize = 1024; // 保留的主节点签名PrePrepare条数，用于组装拉黑证据和申诉
+
+pub const MAX_OPERATION_SIZE: usize = 64 * 1024; // 单个操作的最大字节数
+
+pub const LEADER_ELECTION: &str = "round-robin"; // 主节点选举策略："round-robin"、按表现加权的 "weighted" 或由随机信标决定的 "vrf"
+pub const LEADER_SCHEDULE_ROUNDS: u64 = 3; // 加权调度窗口为 N * 该值 个视图，每个窗口内每个节点至少担任一次主节点
+pub const LEADER_SEED_INTERVAL: u64 = 8; // VRF选举只采用高度为该值整数倍的信标作为种子，减少各节点观测不一致的机会
+pub const VOTE_AGGREGATION: bool = false; // 副本只把Prepare发给主节点，由主节点汇集成证书广播，Prepare阶段的消息数从O(N²)降到O(N)
+pub const VOTE_AGGREGATION_TIMEOUT_MS: u64 = 500; // 发出Prepare后超过该时间仍未进入Prepared，改为向所有节点广播Prepare
+pub const DIGEST_PREPREPARE: bool = false; // PrePrepare只带交易摘要，副本从本地待处理请求或对等节点补齐内容
+pub const PAYLOAD_FETCH_TIMEOUT_MS: u64 = 1000; // 超过该时间仍补不齐PrePrepare引用的交易，视为主节点作恶
+pub const MAX_VIEW_CHANGE_TIMEOUT_MS: u64 = 60_000; // 视图切换退避的上限
+pub const BYZANTINE_DELAY_PERCENT: u8 = 90; // delay策略默认把消息推迟到超时阈值的该百分比
+
+pub const PEER_DIRECTORY: bool = true; // 启动时把本节点的地址、公钥和角色登记到链上的节点目录
+pub const COALESCE_MESSAGES: bool = false; // 是否默认合并发往同一节点的消息，由事件循环显式刷新；也可用--coalesce-messages开启
+pub const EVENT_BUS_CAPACITY: usize = 1024; // 共识事件总线为每个订阅者缓存的事件数
+pub const SUPERVISOR_INITIAL_BACKOFF_MS: u64 = 1000; // 节点崩溃后首次重启前的等待时间，之后每次加倍
+pub const SUPERVISOR_MAX_BACKOFF_MS: u64 = 60_000; // 重启退避的上限
+pub const SUPERVISOR_STABLE_SECS: u64 = 300; // 连续运行超过该时间后再崩溃，退避从初始值重新开始
+pub const EXIT_DRAIN_TIMEOUT_MS: u64 = 10_000; // 计划退出时最多等待多久让进行中的实例提交、主节点完成交接
+
+// 执行引擎的gas计量参数
+pub const GAS_BASE_COST: u64 = 100; // 每个操作的固定开销
+pub const GAS_PER_BYTE: u64 = 1; // 操作读写的每个字节
+pub const OPERATION_GAS_LIMIT: u64 = 10_000; // 单个操作的gas上限
+pub const BLOCK_GAS_LIMIT: u64 = 1_000_000; // 单个区块的gas上限
+pub const OPERATION_WRITE_KEYS: usize = 16; // 单个操作最多修改的不同键数
+pub const OPERATION_WRITE_BYTES: usize = 128 * 1024; // 单个操作最多写入的字节数（键和新值）
+pub const MAX_VALUE_SIZE: usize = 64 * 1024; // 状态中单个值的最大字节数，限制APPEND等操作让值无限增长
+pub const MAX_SCHEDULE_DELAY: u64 = 1_000_000; // 定时交易最多推迟的区块数
+pub const BEACON_HISTORY: u64 = 1024; // 复制状态中保留最近多少个区块的随机信标
+pub const REPLY_CACHE_CLIENTS: usize = 10_000; // 答复缓存最多保存的客户端数，超出时淘汰最久未更新的
+pub const REPLY_MAILBOX_SIZE: usize = 256; // 轮询方式的客户端最多积压的答复数，超出时丢弃最早的
+pub const REPLY_MAILBOX_CLIENTS: usize = 10_000; // 最多为多少个轮询方式的客户端保存答复
+pub const SESSION_RESULT_WINDOW: usize = 128; // 每个客户端会话保留的最近执行结果数
+pub const REQUEST_STATUS_CAPACITY: usize = 100_000; // 最多跟踪的请求数，超出时淘汰最早记录的
+pub const STATE_LOG_WINDOW: u64 = 1024; // NodeState保留最近多少个序列号的Prepared/Committed记录，更早的按水位淘汰
+pub const VIEW_STATS_HISTORY: usize = 1000; // 内存中保留最近多少个视图的统计，更早的只留在文件和按主节点的汇总中
+pub const MAX_VIEW_CHANGE_MESSAGES: usize = 256; // 最多保存的ViewChange消息数，超出时淘汰视图最低的
+pub const MAX_TRACKED_SUSPECTS: usize = 64; // 最多记录拜占庭投票的被指控节点数，超出时淘汰票数最少的
+pub const BYZANTINE_VOTE_VIEWS: u64 = 8; // 拜占庭投票按证据所属的视图计数，与当前视图相差超过该视图数的投票不计入，已计入的过期删除
+pub const MAX_PENDING_REQUESTS: usize = 50_000; // 待处理队列的上限，超出时淘汰最早的请求
+
+// 状态视图与分页扫描
+pub const STATE_VIEW_HISTORY: usize = 8; // 执行引擎保留最近多少个高度的状态，分页扫描可以在此范围内读同一高度
+pub const SCAN_PAGE_SIZE: usize = 100; // Scan不指定limit时每页的键数
+pub const SCAN_MAX_PAGE_SIZE: usize = 1000; // Scan每页最多的键数
+
+// 检查点
+pub const CHECKPOINT_INTERVAL: u64 = 10; // 每隔多少个区块广播一次执行状态摘要
+pub const MAX_PENDING_CHECKPOINTS: u64 = 16; // 最多接受超前稳定检查点多少个间隔的摘要
+
+// 序列号缺口补齐
+pub const MAX_FETCH_RANGE: u64 = 64; // 一次FetchRange最多请求的区块数
+
+// 区块头优先同步
+pub const HEADER_SYNC_BATCH: u64 = 512; // 一次FetchHeaders最多返回的区块头数
+pub const HEADER_SYNC_TIMEOUT_MS: u64 = 2000; // 区块头请求超时后改向下一个对等节点请求
+
+// 状态同步
+pub const SNAPSHOT_CHUNK_SIZE: usize = 16 * 1024; // 快照分块大小（字节）
+pub const SNAPSHOT_CACHE_SIZE: usize = 2; // 提供方缓存的最近快照数量，保证下载途中快照不被替换
+pub const STATE_SYNC_MAX_IN_FLIGHT: usize = 8; // 同时在途的分块请求上限，分摊到各提供方
+pub const STATE_SYNC_CHUNK_TIMEOUT_MS: u64 = 2000; // 分块请求超时后改向其他提供方请求
+
+// 节点间时钟偏差估计
+pub const CLOCK_PING_INTERVAL_MS: u64 = 2000; // 向已认证的对等节点发送Ping的间隔
+pub const CLOCK_SYNC_SAMPLES: usize = 8; // 每个对等节点保留的最近样本数，取往返时间最短的样本
+pub const CLOCK_SKEW_WARN_MS: i64 = 1000; // 本地时钟与多数节点相差超过该值时告警
+
+// 运维告警，告警的去向在node_config.json中配置
+pub const ALERT_COOLDOWN_SECS: u64 = 300; // 同一类告警（对同一节点）在该时间内只发一次
+pub const ALERT_VIEW_CHANGES: usize = 3; // 窗口内视图切换达到该次数时告警
+pub const ALERT_VIEW_CHANGE_WINDOW_SECS: u64 = 60;
+pub const ALERT_DISK_FREE_PERCENT: u64 = 5; // 工作目录所在磁盘的可用空间低于该百分比时告警
+pub const ALERT_PEER_SILENCE_MS: u64 = 5 * CLOCK_PING_INTERVAL_MS; // 验证者超过该时间没有应答Ping即视为联系不上
+
+// 提交延迟预算
+pub const LATENCY_BUDGET_MS: u64 = 1000; // 共识实例从PrePrepare到执行完毕的缺省预算，超出时记录诊断；node_config.json的latency_budget_ms覆盖，0为不检查
+pub const LATENCY_TRACKED_INSTANCES: usize = 64; // 最多同时跟踪的实例数，超出时淘汰序列号最低的
+
+// 自检：node doctor 检查全部区块，节点启动时只检查最近的区块
+pub const DOCTOR_STARTUP_BLOCKS: usize = 100; // 启动自检重新校验的最近区块数
+
+// 存储流水线
+pub const STORAGE_PIPELINE_DEPTH: u64 = 16; // 已提交但链文件尚未持久的区块数上限，超过时提交等待写入任务
+pub const STORAGE_FLUSH_TIMEOUT_MS: u64 = 5000; // 正常关闭时等待链文件持久的最长时间
+
+// 指标快照与运行报告
+pub const METRICS_SNAPSHOT_SECS: u64 = 30; // 把指标快照写入node_<ID>_metrics.json的间隔，关闭时另写一次
+pub const METRICS_HISTOGRAM_SAMPLES: usize = 4096; // 每个直方图保留的最近观测值个数，分位数按这些值计算
+
+// 客户端SDK
+pub const CLIENT_RPC_TIMEOUT_MS: u64 = 2000; // 单次RPC调用（连接、认证、请求和答复）的超时
+pub const CLIENT_REFRESH_MS: u64 = 1000; // 读之前刷新各副本执行高度的最小间隔
+pub const CLIENT_MAX_STALENESS: u64 = 2; // 执行高度落后最高副本超过该区块数的副本不参与读
+pub const CLIENT_RETRY_MS: u64 = 5000; // 调用失败的副本在此期间不参与读
+pub const CLIENT_REQUEST_TIMEOUT_MS: u64 = 10_000; // 经管道提交的请求等待执行结果的时限
+pub const READ_MIN_HEIGHT_WAIT_MS: u64 = 500; // 副本尚未执行到读请求要求的高度时最多等待的时间，之后让客户端改读其他副本
+
+// 负载生成器
+pub const LOADGEN_DRAIN_MS: u64 = 5000; // 停止发送后等待未完成请求的最长时间
+pub const LOADGEN_REPLY_QUEUE_SIZE: usize = 65536; // 答复队列容量，满时节点丢弃答复，请求记为未完成
@@ -0,0 +1,548 @@
+// src/consensus.rs
+
+use std::collections::{HashMap, HashSet};
+use log::{debug, info, warn};
+use crate::byzantine::{self, Strategy};
+use crate::chain;
+use crate::hash::HashFunction;
+use crate::quorum::{COMMIT_QUORUM, PREPARE_QUORUM, WEAK_QUORUM};
+use crate::message::{PBFTMessage, Transaction};
+use crate::metrics;
+use crate::phase::{self, Phase, PhaseEvent};
+
+// 共识核心的输入事件。签名校验、PrePrepare内容校验等由节点外壳完成后再交给核心
+#[derive(Debug, Clone)]
+pub enum Input {
+    // 主节点提议新的批次（摘要由外壳计算）
+    Propose { digest: String, transactions: Vec<Transaction> },
+    PrePrepare { view: u64, sequence_number: u64, digest: String, transactions: Vec<Transaction> },
+    Prepare { view: u64, sequence_number: u64, digest: String, sender_id: usize },
+    Commit { view: u64, sequence_number: u64, digest: String, sender_id: usize },
+    // 线性投票时只发给主节点的Prepare迟迟没有换来证书
+    AggregationTimeout { view: u64, sequence_number: u64 },
+}
+
+// 核心产生的动作，由节点外壳负责执行所有I/O
+#[derive(Debug, Clone)]
+pub enum Action {
+    Broadcast(PBFTMessage),
+    Send(usize, PBFTMessage),
+    Persist(Record),
+    // 提交并执行当前实例，外壳据此组装证书、生成区块
+    Execute { view: u64, sequence_number: u64, digest: String },
+    SetTimer(Timer),
+    // 主节点收齐Prepare法定人数，外壳把收到的Prepare签名汇集成证书广播
+    AggregatePrepares { view: u64, sequence_number: u64, digest: String },
+    // 该节点的Prepare摘要与本节点接受的PrePrepare不同，外壳凭两条签名消息组装证据
+    DivergentPrepare { sender_id: usize, digest: String },
+    // 收到f+1个Commit的实例本节点还没有接受PrePrepare：其中至少一个诚实节点已经Prepared，
+    // 外壳向对等节点索取主节点签名的PrePrepare
+    FetchPrePrepare { view: u64, sequence_number: u64 },
+    // 主节点有作恶迹象，但没有可以转交其他节点的证据，只降低本地信誉
+    Suspect(usize),
+    // 主节点存在可证明的恶意行为，请求切换到指定视图
+    ViewChange(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Prepared(u64, String),
+    Committed(u64, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timer {
+    // 实例开始：外壳记录提议时间用于统计提交延迟
+    Proposal { sequence_number: u64 },
+    // 副本把Prepare只发给了主节点：到期仍未进入Prepared则改为广播
+    Aggregation { sequence_number: u64 },
+}
+
+// 纯粹的、同步的共识决策逻辑：不做网络、磁盘和时钟访问，
+// 同样的输入序列总是产生同样的动作序列，便于穷举测试和模型检验
+pub struct ConsensusCore {
+    pub id: usize,
+    pub view: u64,
+    pub primary: usize,
+    pub sequence_number: u64,
+    pub digest: String,
+    pub batch: Vec<Transaction>,
+    pub phase: Phase,
+    pub strategy: Strategy,
+    pub hash_function: HashFunction,
+    // 线性投票：Prepare只发给主节点，由主节点汇集成证书
+    pub aggregate_votes: bool,
+    equivocation_reported: bool,
+    // 本节点在当前实例发出的Prepare，线性投票超时后改为广播
+    prepare: Option<PBFTMessage>,
+    // (视图, 序列号) -> 摘要 -> 发送者，可能早于PrePrepare到达
+    prepares: HashMap<(u64, u64), HashMap<String, HashSet<usize>>>,
+    // 尚未接受PrePrepare时先行到达的Commit按摘要暂存，只有与接受的PrePrepare摘要一致的才计票；
+    // 和Prepare一样按发送者去重，重复投递的Commit不增加票数
+    commits: HashMap<(u64, u64, String), HashSet<usize>>,
+    // 已经索取过PrePrepare的实例，每个实例只索取一次
+    fetched: HashSet<(u64, u64)>,
+}
+
+impl ConsensusCore {
+    pub fn new(id: usize, view: u64, primary: usize, strategy: Strategy, hash_function: HashFunction) -> Self {
+        ConsensusCore {
+            id,
+            view,
+            primary,
+            sequence_number: 0,
+            digest: String::new(),
+            batch: Vec::new(),
+            phase: Phase::Idle,
+            strategy,
+            hash_function,
+            aggregate_votes: false,
+            equivocation_reported: false,
+            prepare: None,
+            prepares: HashMap::new(),
+            commits: HashMap::new(),
+            fetched: HashSet::new(),
+        }
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.id == self.primary
+    }
+
+    pub fn handle(&mut self, input: Input) -> Vec<Action> {
+        let mut actions = Vec::new();
+        match input {
+            Input::Propose { digest, transactions } => self.on_propose(digest, transactions, &mut actions),
+            Input::PrePrepare { view, sequence_number, digest, transactions } => {
+                self.on_preprepare(view, sequence_number, digest, transactions, &mut actions)
+            }
+            Input::Prepare { view, sequence_number, digest, sender_id } => {
+                self.on_prepare(view, sequence_number, digest, sender_id, &mut actions)
+            }
+            Input::Commit { view, sequence_number, digest, sender_id } => self.on_commit(view, sequence_number, digest, sender_id, &mut actions),
+            Input::AggregationTimeout { view, sequence_number } => {
+                if (view, sequence_number) != (self.view, self.sequence_number) || self.phase != Phase::PrePrepared {
+                    return actions;
+                }
+                if let Some(prepare) = self.prepare.take() {
+                    info!("节点{}在序列号{}上未等到主节点的Prepare证书，改为广播Prepare", self.id, sequence_number);
+                    metrics::inc_counter("vote_aggregation_fallback_total", 1);
+                    actions.push(Action::Broadcast(prepare));
+                }
+            }
+        }
+        actions
+    }
+
+    // 切换视图：丢弃当前实例和旧视图的投票
+    pub fn enter_view(&mut self, view: u64, primary: usize) {
+        self.view = view;
+        self.primary = primary;
+        self.sequence_number = 0;
+        self.digest.clear();
+        self.batch.clear();
+        self.phase = Phase::Idle;
+        self.equivocation_reported = false;
+        self.prepare = None;
+        self.prepares.retain(|(v, _), _| *v >= view);
+        self.commits.retain(|(v, _, _), _| *v >= view);
+        self.fetched.retain(|(v, _)| *v >= view);
+    }
+
+    fn on_propose(&mut self, digest: String, transactions: Vec<Transaction>, actions: &mut Vec<Action>) {
+        self.phase = Phase::Idle;
+        self.start_instance(self.sequence_number + 1, digest.clone(), transactions.clone());
+        self.advance(PhaseEvent::PrePrepare);
+        info!("节点{}（主节点）提议批次，序列号: {}，批大小: {}", self.id, self.sequence_number, transactions.len());
+
+        actions.push(Action::SetTimer(Timer::Proposal { sequence_number: self.sequence_number }));
+
+        if self.strategy == Strategy::EquivocatingPrimary {
+            let conflicting = byzantine::conflicting_batch(&transactions, self.sequence_number);
+            let conflicting_digest = chain::digest_transactions(self.hash_function.hasher(), &conflicting);
+            info!("拜占庭主节点{}在序列号{}上发送两个不同的批次", self.id, self.sequence_number);
+            let (first, second) = byzantine::equivocation_groups(self.id);
+            for (replicas, digest, transactions) in [(first, digest, transactions), (second, conflicting_digest, conflicting)] {
+                for replica in replicas {
+                    actions.push(Action::Send(replica, PBFTMessage::PrePrepare {
+                        view: self.view,
+                        sequence_number: self.sequence_number,
+                        digest: digest.clone(),
+                        transactions: transactions.clone(),
+                    }));
+                }
+            }
+            return;
+        }
+
+        actions.push(Action::Broadcast(PBFTMessage::PrePrepare {
+            view: self.view,
+            sequence_number: self.sequence_number,
+            digest,
+            transactions,
+        }));
+    }
+
+    fn on_preprepare(&mut self, view: u64, sequence_number: u64, digest: String, transactions: Vec<Transaction>, actions: &mut Vec<Action>) {
+        if view != self.view || self.is_primary() {
+            debug!("节点{}收到的PrePrepare消息视图不匹配或自身为主节点，忽略", self.id);
+            return;
+        }
+
+        // 新序列号开启新实例；同一实例的重复PrePrepare由转移函数拒绝，迟到的旧实例PrePrepare（例如副本转发的）直接忽略
+        if sequence_number < self.sequence_number {
+            debug!("节点{}收到序列号{}的PrePrepare，当前实例已是{}，忽略", self.id, sequence_number, self.sequence_number);
+            return;
+        }
+        if sequence_number != self.sequence_number {
+            self.phase = Phase::Idle;
+        }
+        if !self.advance(PhaseEvent::PrePrepare) {
+            return;
+        }
+        self.start_instance(sequence_number, digest.clone(), transactions);
+        actions.push(Action::SetTimer(Timer::Proposal { sequence_number }));
+
+        let prepare_digest = if self.strategy == Strategy::WrongDigest {
+            info!("拜占庭节点{}发送错误的Prepare摘要", self.id);
+            "错误的摘要".to_string()
+        } else {
+            digest
+        };
+        let prepare_msg = PBFTMessage::Prepare {
+            view,
+            sequence_number,
+            digest: prepare_digest.clone(),
+            sender_id: self.id,
+        };
+        if self.aggregate_votes {
+            debug!("节点{}把Prepare消息发给主节点{}: {:?}", self.id, self.primary, prepare_msg);
+            actions.push(Action::Send(self.primary, prepare_msg.clone()));
+            actions.push(Action::SetTimer(Timer::Aggregation { sequence_number }));
+            self.prepare = Some(prepare_msg);
+        } else {
+            debug!("节点{}广播Prepare消息: {:?}", self.id, prepare_msg);
+            actions.push(Action::Broadcast(prepare_msg));
+        }
+
+        // 自己的Prepare同样计入法定人数
+        self.on_prepare(view, sequence_number, prepare_digest, self.id, actions);
+    }
+
+    fn on_prepare(&mut self, view: u64, sequence_number: u64, digest: String, sender_id: usize, actions: &mut Vec<Action>) {
+        self.prepares.entry((view, sequence_number)).or_default().entry(digest.clone()).or_default().insert(sender_id);
+        if (view, sequence_number) != (self.view, self.sequence_number) {
+            return;
+        }
+
+        // 作恶的主节点只负责制造分叉，不参与后续投票
+        if self.is_primary() && self.strategy == Strategy::EquivocatingPrimary {
+            return;
+        }
+
+        // 只与本节点接受的PrePrepare比较，不按多数判断：分区或主节点分叉时多数一方的摘要未必正确
+        if self.phase != Phase::Idle && sender_id != self.id && digest != self.digest {
+            info!("节点{}收到节点{}与PrePrepare不符的Prepare摘要", self.id, sender_id);
+            actions.push(Action::DivergentPrepare { sender_id, digest });
+        }
+
+        let digests = &self.prepares[&(view, sequence_number)];
+        let matching = digests.get(&self.digest).map_or(0, |senders| senders.len());
+
+        // 已接受PrePrepare的副本看到f+1个节点对另一个摘要发送Prepare：
+        // 其中至少有一个诚实节点收到了该摘要的PrePrepare，说明主节点在同一序列号上发送了不同的批次
+        let equivocated = self.phase != Phase::Idle
+            && !self.is_primary()
+            && digests.iter().any(|(digest, senders)| *digest != self.digest && senders.len() >= WEAK_QUORUM);
+        if equivocated && !self.equivocation_reported {
+            self.equivocation_reported = true;
+            warn!("节点{}检测到主节点{}在视图{}序列号{}上发送了不同的PrePrepare", self.id, self.primary, self.view, self.sequence_number);
+            metrics::inc_counter("primary_equivocation_detected_total", 1);
+            actions.push(Action::Suspect(self.primary));
+            actions.push(Action::ViewChange(self.view + 1));
+        }
+
+        if matching >= PREPARE_QUORUM && self.phase == Phase::PrePrepared && self.advance(PhaseEvent::PrepareQuorum) {
+            info!("节点{}进入Prepared状态，序列号: {}", self.id, self.sequence_number);
+            actions.push(Action::Persist(Record::Prepared(self.sequence_number, self.digest.clone())));
+            if self.is_primary() && self.aggregate_votes {
+                actions.push(Action::AggregatePrepares { view, sequence_number, digest: self.digest.clone() });
+            }
+
+            let commit_msg = PBFTMessage::Commit {
+                view: self.view,
+                sequence_number: self.sequence_number,
+                digest: self.digest.clone(),
+            };
+            debug!("节点{}广播Commit消息: {:?}", self.id, commit_msg);
+            actions.push(Action::Broadcast(commit_msg));
+            // 自己的Commit同样计入法定人数：2f+1个Commit中包含本节点
+            self.commits.entry((self.view, self.sequence_number, self.digest.clone())).or_default().insert(self.id);
+
+            // 进入Prepared之前可能已收齐Commit消息
+            self.check_committed(actions);
+        }
+    }
+
+    // Commit只对本节点接受的PrePrepare计票：当前实例中摘要不符的直接拒绝；
+    // 还没有接受PrePrepare的实例先暂存，凑够f+1个时索取PrePrepare，而不是等待永远不会到来的提议
+    fn on_commit(&mut self, view: u64, sequence_number: u64, digest: String, sender_id: usize, actions: &mut Vec<Action>) {
+        if view < self.view || (view == self.view && sequence_number < self.sequence_number) {
+            debug!("节点{}忽略已经过去的实例的Commit（视图{}，序列号{}）", self.id, view, sequence_number);
+            return;
+        }
+        let accepted = (view, sequence_number) == (self.view, self.sequence_number) && self.phase != Phase::Idle;
+        if accepted && digest != self.digest {
+            warn!("节点{}收到与已接受的PrePrepare摘要不符的Commit（序列号{}），不计票", self.id, sequence_number);
+            metrics::inc_counter("commits_rejected_total", 1);
+            return;
+        }
+        let count = {
+            let senders = self.commits.entry((view, sequence_number, digest)).or_default();
+            senders.insert(sender_id);
+            senders.len()
+        };
+        if !accepted && !self.is_primary() && count >= WEAK_QUORUM && self.fetched.insert((view, sequence_number)) {
+            info!("节点{}收到序列号{}的{}个Commit，但没有接受对应的PrePrepare，向对等节点索取", self.id, sequence_number, count);
+            actions.push(Action::FetchPrePrepare { view, sequence_number });
+        }
+        self.check_committed(actions);
+    }
+
+    // 仅在Prepared阶段统计Commit消息，达到法定人数后提交
+    fn check_committed(&mut self, actions: &mut Vec<Action>) {
+        if self.phase != Phase::Prepared {
+            return;
+        }
+        let key = (self.view, self.sequence_number, self.digest.clone());
+        let commit_count = self.commits.get(&key).map_or(0, HashSet::len);
+        debug!("节点{}收到的匹配的Commit消息数量: {}", self.id, commit_count);
+
+        if commit_count >= COMMIT_QUORUM && self.advance(PhaseEvent::CommitQuorum) {
+            info!("节点{}已提交请求，序列号: {}", self.id, self.sequence_number);
+            actions.push(Action::Persist(Record::Committed(key.1, key.2.clone())));
+            actions.push(Action::Execute { view: key.0, sequence_number: key.1, digest: key.2 });
+        }
+    }
+
+    fn start_instance(&mut self, sequence_number: u64, digest: String, transactions: Vec<Transaction>) {
+        self.sequence_number = sequence_number;
+        self.digest = digest;
+        self.batch = transactions;
+        self.equivocation_reported = false;
+        self.prepare = None;
+        // 已经过去的实例不会再收到有效的投票
+        let view = self.view;
+        self.prepares.retain(|(v, s), _| *v > view || (*v == view && *s >= sequence_number));
+        self.commits.retain(|(v, s, _), _| *v > view || (*v == view && *s >= sequence_number));
+        self.fetched.retain(|(v, s)| *v > view || (*v == view && *s >= sequence_number));
+    }
+
+    // 唯一修改实例阶段的入口：非法转移只记录日志并被拒绝，阶段保持不变
+    fn advance(&mut self, event: PhaseEvent) -> bool {
+        match phase::transition(self.phase, event) {
+            Ok(next) => {
+                debug!("节点{}序列号{}的阶段: {:?} -> {:?}", self.id, self.sequence_number, self.phase, next);
+                self.phase = next;
+                true
+            }
+            Err(illegal) => {
+                warn!("节点{}拒绝非法的阶段转移（视图{}，序列号{}）: {}", self.id, self.view, self.sequence_number, illegal);
+                metrics::inc_counter("illegal_phase_transition_total", 1);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::N;
+
+    fn commit(sequence_number: u64, digest: &str, sender_id: usize) -> Input {
+        Input::Commit { view: 0, sequence_number, digest: digest.to_string(), sender_id }
+    }
+
+    fn prepare(view: u64, sequence_number: u64, digest: &str, sender_id: usize) -> Input {
+        Input::Prepare { view, sequence_number, digest: digest.to_string(), sender_id }
+    }
+
+    fn batch(operation: &str) -> (Vec<Transaction>, String) {
+        let transactions = vec![Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None }];
+        let digest = chain::digest_transactions(HashFunction::default().hasher(), &transactions);
+        (transactions, digest)
+    }
+
+    fn pre_prepare(view: u64, sequence_number: u64, operation: &str) -> (Input, String) {
+        let (transactions, digest) = batch(operation);
+        (Input::PrePrepare { view, sequence_number, digest: digest.clone(), transactions }, digest)
+    }
+
+    fn executed(actions: &[Action]) -> bool {
+        actions.iter().any(|action| matches!(action, Action::Execute { .. }))
+    }
+
+    // 主节点提议后依次经过PrePrepared、Prepared和Committed：2f个副本的Prepare进入Prepared并广播Commit，
+    // 连同自己的Commit凑够2f+1个后提交；下一次提议使用下一个序列号
+    #[test]
+    fn primary_drives_an_instance_to_commit() {
+        let mut core = ConsensusCore::new(0, 0, 0, Strategy::Honest, HashFunction::default());
+        let (transactions, digest) = batch("SET k v");
+        let actions = core.handle(Input::Propose { digest: digest.clone(), transactions });
+        assert!(matches!(actions[..], [Action::SetTimer(Timer::Proposal { sequence_number: 1 }), Action::Broadcast(PBFTMessage::PrePrepare { sequence_number: 1, .. })]));
+        assert_eq!((core.phase, core.sequence_number), (Phase::PrePrepared, 1));
+
+        for sender_id in 1..PREPARE_QUORUM {
+            assert!(core.handle(prepare(0, 1, &digest, sender_id)).is_empty());
+        }
+        assert_eq!(core.phase, Phase::PrePrepared, "不足2f个Prepare不应进入Prepared");
+        let actions = core.handle(prepare(0, 1, &digest, PREPARE_QUORUM));
+        assert!(matches!(&actions[..], [Action::Persist(Record::Prepared(1, _)), Action::Broadcast(PBFTMessage::Commit { sequence_number: 1, .. })]));
+        assert_eq!(core.phase, Phase::Prepared);
+
+        for sender_id in 1..COMMIT_QUORUM - 1 {
+            assert!(!executed(&core.handle(commit(1, &digest, sender_id))));
+        }
+        let actions = core.handle(commit(1, &digest, COMMIT_QUORUM - 1));
+        assert!(matches!(&actions[..], [Action::Persist(Record::Committed(1, _)), Action::Execute { view: 0, sequence_number: 1, .. }]));
+        assert_eq!(core.phase, Phase::Committed);
+        assert!(!executed(&core.handle(commit(1, &digest, N - 1))), "已提交的实例不应重复执行");
+
+        core.handle(Input::Propose { digest: "d2".to_string(), transactions: Vec::new() });
+        assert_eq!((core.phase, core.sequence_number), (Phase::PrePrepared, 2));
+    }
+
+    // 副本只接受本视图主节点对当前或更新序列号的PrePrepare，接受后广播自己的Prepare；
+    // 同一实例的第二个PrePrepare、旧序列号和其他视图的PrePrepare都被忽略
+    #[test]
+    fn replica_accepts_only_preprepares_for_the_current_instance() {
+        let mut core = ConsensusCore::new(1, 0, 0, Strategy::Honest, HashFunction::default());
+        let (other_view, _) = pre_prepare(1, 1, "SET k v");
+        assert!(core.handle(other_view).is_empty());
+        assert_eq!(core.phase, Phase::Idle);
+
+        let (first, digest) = pre_prepare(0, 2, "SET k v");
+        let actions = core.handle(first);
+        assert!(matches!(&actions[..], [Action::SetTimer(Timer::Proposal { sequence_number: 2 }), Action::Broadcast(PBFTMessage::Prepare { sequence_number: 2, sender_id: 1, .. })]));
+        assert_eq!((core.phase, core.sequence_number, core.digest.as_str()), (Phase::PrePrepared, 2, digest.as_str()));
+
+        let (conflicting, _) = pre_prepare(0, 2, "SET k other");
+        assert!(core.handle(conflicting).is_empty(), "同一实例只接受一个PrePrepare");
+        let (stale, _) = pre_prepare(0, 1, "SET k old");
+        assert!(core.handle(stale).is_empty());
+        assert_eq!((core.sequence_number, core.digest.as_str()), (2, digest.as_str()));
+
+        // 主节点不处理PrePrepare
+        let mut primary = ConsensusCore::new(0, 0, 0, Strategy::Honest, HashFunction::default());
+        let (own, _) = pre_prepare(0, 1, "SET k v");
+        assert!(primary.handle(own).is_empty());
+        assert_eq!(primary.phase, Phase::Idle);
+    }
+
+    // Prepare法定人数只统计当前实例、与PrePrepare摘要一致、来自不同节点的Prepare
+    #[test]
+    fn prepare_quorum_ignores_duplicates_and_other_instances() {
+        let mut core = ConsensusCore::new(1, 0, 0, Strategy::Honest, HashFunction::default());
+        let (input, digest) = pre_prepare(0, 1, "SET k v");
+        core.handle(input);
+
+        assert!(core.handle(prepare(0, 1, &digest, 1)).is_empty(), "自己的Prepare已经计过票");
+        assert!(core.handle(prepare(1, 1, &digest, 2)).is_empty());
+        assert!(core.handle(prepare(0, 2, &digest, 2)).is_empty());
+        let actions = core.handle(prepare(0, 1, "另一个摘要", 2));
+        assert!(matches!(&actions[..], [Action::DivergentPrepare { sender_id: 2, .. }]));
+        assert_eq!(core.phase, Phase::PrePrepared);
+
+        let actions = core.handle(prepare(0, 1, &digest, 3));
+        assert!(actions.iter().any(|action| matches!(action, Action::Persist(Record::Prepared(1, d)) if *d == digest)));
+        assert_eq!(core.phase, Phase::Prepared);
+    }
+
+    // 先于Prepared到达的Commit暂存；进入Prepared时连同自己的Commit凑够2f+1个，在同一次处理中提交
+    #[test]
+    fn early_commits_are_counted_once_prepared() {
+        let mut core = ConsensusCore::new(1, 0, 0, Strategy::Honest, HashFunction::default());
+        let (input, digest) = pre_prepare(0, 1, "SET k v");
+        core.handle(input);
+        for sender_id in [0, 2].iter() {
+            assert!(!executed(&core.handle(commit(1, &digest, *sender_id))), "未进入Prepared前不应提交");
+        }
+        assert!(!executed(&core.handle(Input::Commit { view: 1, sequence_number: 1, digest: digest.clone(), sender_id: 3 })), "其他视图的Commit不计票");
+        assert_eq!(core.phase, Phase::PrePrepared);
+
+        let actions = core.handle(prepare(0, 1, &digest, 2));
+        assert!(executed(&actions));
+        assert_eq!(core.phase, Phase::Committed);
+    }
+
+    // 进入新视图时丢弃当前实例和旧视图的投票，序列号从1重新开始；新视图中先到的投票保留
+    #[test]
+    fn enter_view_resets_the_instance() {
+        let mut core = ConsensusCore::new(1, 0, 0, Strategy::Honest, HashFunction::default());
+        let (input, digest) = pre_prepare(0, 3, "SET k v");
+        core.handle(input);
+        core.handle(commit(3, &digest, 0));
+        let (next, next_digest) = pre_prepare(1, 1, "SET k next");
+        core.handle(prepare(1, 1, &next_digest, 2));
+
+        core.enter_view(1, 1);
+        assert_eq!((core.view, core.primary, core.sequence_number, core.phase), (1, 1, 0, Phase::Idle));
+        assert!(core.digest.is_empty() && core.batch.is_empty());
+        assert!(!core.prepares.keys().any(|(view, _)| *view == 0));
+        assert!(!core.commits.keys().any(|(view, _, _)| *view == 0));
+
+        // 本节点成为主节点，不再处理PrePrepare；提议从序列号1开始，视图1中先到的Prepare计入法定人数
+        assert!(core.handle(next).is_empty());
+        core.handle(Input::Propose { digest: next_digest.clone(), transactions: Vec::new() });
+        assert_eq!(core.sequence_number, 1);
+        let actions = core.handle(prepare(1, 1, &next_digest, 3));
+        assert!(actions.iter().any(|action| matches!(action, Action::Persist(Record::Prepared(1, _)))));
+    }
+
+    // 还没有接受PrePrepare的实例凑够f+1个Commit时索取一次PrePrepare，接受后暂存的Commit计票；
+    // 与接受的PrePrepare摘要不符的Commit和已经过去的实例的Commit不计票
+    #[test]
+    fn commits_count_only_toward_the_accepted_preprepare() {
+        let mut core = ConsensusCore::new(3, 0, 0, Strategy::Honest, HashFunction::default());
+        let batch = vec![Transaction { operation: "SET k v".to_string(), client_id: None, session: None, timestamp: None }];
+        let digest = chain::digest_transactions(core.hash_function.hasher(), &batch);
+
+        assert!(core.handle(commit(1, "伪造的摘要", 0)).is_empty());
+        assert!(core.handle(commit(1, &digest, 1)).is_empty());
+        assert!(matches!(core.handle(commit(1, &digest, 2))[..], [Action::FetchPrePrepare { view: 0, sequence_number: 1 }]));
+        assert!(core.handle(commit(1, "伪造的摘要", 1)).is_empty(), "同一实例只索取一次");
+
+        core.handle(Input::PrePrepare { view: 0, sequence_number: 1, digest: digest.clone(), transactions: batch });
+        assert!(core.handle(commit(1, "另一个摘要", 0)).is_empty());
+        let actions = core.handle(Input::Prepare { view: 0, sequence_number: 1, digest: digest.clone(), sender_id: 1 });
+        assert!(actions.iter().any(|action| matches!(action, Action::Execute { digest: executed, .. } if *executed == digest)));
+        assert_eq!(core.commits.keys().filter(|(_, _, d)| *d != digest).count(), 1, "不符的Commit被计入");
+
+        assert!(core.handle(commit(0, &digest, 1)).is_empty());
+        assert!(!core.commits.keys().any(|(_, s, _)| *s == 0));
+    }
+
+    // 同一节点的Commit重复投递2f+1次仍只算一票：既不提交，也凑不成索取PrePrepare的f+1票
+    #[test]
+    fn duplicate_commits_count_once() {
+        let mut core = ConsensusCore::new(3, 0, 0, Strategy::Honest, HashFunction::default());
+        let batch = vec![Transaction { operation: "SET k v".to_string(), client_id: None, session: None, timestamp: None }];
+        let digest = chain::digest_transactions(core.hash_function.hasher(), &batch);
+
+        for _ in 0..COMMIT_QUORUM {
+            assert!(core.handle(commit(2, "未接受的摘要", 1)).is_empty(), "重复的Commit触发了索取");
+        }
+
+        core.handle(Input::PrePrepare { view: 0, sequence_number: 1, digest: digest.clone(), transactions: batch });
+        core.handle(Input::Prepare { view: 0, sequence_number: 1, digest: digest.clone(), sender_id: 1 });
+        assert_eq!(core.phase, Phase::Prepared);
+        for _ in 0..COMMIT_QUORUM {
+            let actions = core.handle(commit(1, &digest, 1));
+            assert!(!actions.iter().any(|action| matches!(action, Action::Execute { .. })), "重复的Commit凑成了法定人数");
+        }
+        assert_eq!(core.phase, Phase::Prepared);
+        let actions = core.handle(commit(1, &digest, 2));
+        assert!(actions.iter().any(|action| matches!(action, Action::Execute { digest: executed, .. } if *executed == digest)));
+    }
+}
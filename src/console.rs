@@ -0,0 +1,63 @@
+// src/console.rs
+
+// 节点内置的交互式控制台（console特性）：从标准输入读取命令，交给节点的事件循环执行并打印结果
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+
+const HELP: &str = "可用命令: status | mempool | submit <操作> | view-change | help";
+
+pub enum Command {
+    Status,         // 视图、主节点和当前共识实例的状态
+    Mempool,        // 待处理的请求和批处理队列
+    Submit(String), // 以客户端身份注入一个请求
+    ViewChange,     // 强制切换到下一个视图
+}
+
+pub struct ConsoleRequest {
+    pub command: Command,
+    pub reply: oneshot::Sender<String>,
+}
+
+// 无法识别的命令返回错误提示，help也以提示的形式返回
+pub fn parse(line: &str) -> Result<Command, String> {
+    let (name, arg) = match line.split_once(' ') {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (line, ""),
+    };
+    match name {
+        "status" => Ok(Command::Status),
+        "mempool" => Ok(Command::Mempool),
+        "submit" if !arg.is_empty() => Ok(Command::Submit(arg.to_string())),
+        "submit" => Err("用法: submit <操作>".to_string()),
+        "view-change" => Ok(Command::ViewChange),
+        "help" => Err(HELP.to_string()),
+        _ => Err(format!("未知命令'{}'，{}", name, HELP)),
+    }
+}
+
+pub async fn run(node_id: usize, sender: Sender<ConsoleRequest>) {
+    println!("节点{}的控制台已启动，{}", node_id, HELP);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let command = match parse(line) {
+            Ok(command) => command,
+            Err(message) => {
+                println!("{}", message);
+                continue;
+            }
+        };
+        let (reply, response) = oneshot::channel();
+        if sender.send(ConsoleRequest { command, reply }).await.is_err() {
+            break;
+        }
+        match response.await {
+            Ok(output) => println!("{}", output),
+            Err(_) => break,
+        }
+    }
+}
@@ -0,0 +1,445 @@
+// src/crypto.rs
+
+// 密钥与签名的统一入口，其余模块只使用这里的类型：
+// 公钥在构造时校验（拒绝无法解码的点和小阶点），签名固定为64字节，私钥只保存在SigningKey中，
+// 导出的私钥字节离开作用域时清零（SigningKey内部的私钥由ed25519-dalek在释放时清零）。
+// 单个验证与批量验证使用同一个带余因子的验证方程 [8](sB - kA - R) = 0，
+// 因此批量验证接受的签名集合与逐个验证完全一致，不会因验证方式不同导致节点间分歧
+use std::convert::TryInto;
+use std::fmt;
+use curve25519_dalek::constants::{ED25519_BASEPOINT_POINT, ED25519_BASEPOINT_TABLE};
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
+use ed25519_dalek::{ExpandedSecretKey, Keypair, SecretKey, Signer};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+pub const PUBLIC_KEY_LENGTH: usize = 32;
+pub const SIGNATURE_LENGTH: usize = 64;
+
+// 节点或客户端的签名私钥；不实现Debug和Clone，避免私钥被打印或复制
+pub struct SigningKey(Keypair);
+
+impl SigningKey {
+    pub fn generate() -> Self {
+        SigningKey(Keypair::generate(&mut OsRng))
+    }
+
+    pub fn from_secret_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let secret = SecretKey::from_bytes(bytes).map_err(|_| "私钥必须为32字节".to_string())?;
+        let public = (&secret).into();
+        Ok(SigningKey(Keypair { secret, public }))
+    }
+
+    // 导出私钥，返回值释放时清零
+    pub fn secret_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.0.secret.to_bytes())
+    }
+
+    // 从十六进制编码的密钥文件加载私钥；文件不存在时生成新私钥并写入（仅所有者可读）
+    pub fn load_or_generate(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(data) => {
+                let data = Zeroizing::new(data);
+                let bytes = Zeroizing::new(hex::decode(data.trim()).map_err(|_| format!("{}不是有效的十六进制", path))?);
+                SigningKey::from_secret_bytes(&bytes)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = SigningKey::generate();
+                let encoded = Zeroizing::new(hex::encode(&key.secret_bytes()[..]));
+                write_private(path, encoded.as_bytes()).map_err(|e| format!("无法写入{}: {}", path, e))?;
+                Ok(key)
+            }
+            Err(e) => Err(format!("无法读取{}: {}", path, e)),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_bytes(self.0.public.as_bytes()).unwrap()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        Signature(self.0.sign(message).to_bytes())
+    }
+
+    // 与对等节点共享的对称密钥：双方各用自己的私钥标量乘对方的公钥点（Diffie-Hellman），得到同一个点，
+    // 再加上域前缀取SHA-256。私钥标量已按Ed25519的规则钳位为8的倍数，对方公钥中的小阶分量不影响结果
+    pub fn shared_secret(&self, peer: &PublicKey) -> Zeroizing<[u8; 32]> {
+        let expanded = Zeroizing::new(ExpandedSecretKey::from(&self.0.secret).to_bytes());
+        let scalar = Scalar::from_bits(expanded[..32].try_into().unwrap());
+        let shared = (scalar * peer.point).compress();
+        let digest = Sha256::new().chain(b"pbft-shared-secret").chain(shared.as_bytes()).finalize();
+        Zeroizing::new(digest.into())
+    }
+
+    // 可验证随机函数（ECVRF-EDWARDS25519-SHA512-TAI，RFC 9381）：同一私钥对同一输入只有一个有效证明，
+    // 输出在证明公开前无法预测，任何人都能用公钥验证。私钥标量和nonce前缀与Ed25519签名相同
+    pub fn vrf_prove(&self, alpha: &[u8]) -> VrfProof {
+        let expanded = Zeroizing::new(ExpandedSecretKey::from(&self.0.secret).to_bytes());
+        let x = Scalar::from_bits(expanded[..32].try_into().unwrap());
+        let public_key = self.public_key();
+        let h = vrf_encode_to_curve(&public_key.bytes, alpha);
+        let h_bytes = h.compress().to_bytes();
+        let gamma = x * h;
+        let k = Scalar::from_hash(Sha512::new().chain(&expanded[32..]).chain(h_bytes));
+        let c = vrf_challenge(&[public_key.point, h, gamma, &k * &ED25519_BASEPOINT_TABLE, k * h]);
+        let s = k + c * x;
+
+        let mut proof = [0u8; VRF_PROOF_LENGTH];
+        proof[..32].copy_from_slice(gamma.compress().as_bytes());
+        proof[32..48].copy_from_slice(&c.to_bytes()[..16]);
+        proof[48..].copy_from_slice(s.as_bytes());
+        VrfProof(proof)
+    }
+}
+
+const VRF_SUITE: u8 = 0x03;
+pub const VRF_PROOF_LENGTH: usize = 80;
+
+// try-and-increment：哈希结果不是曲线上的点时递增计数器重试，得到的点乘以余因子
+fn vrf_encode_to_curve(public_key: &[u8; PUBLIC_KEY_LENGTH], alpha: &[u8]) -> EdwardsPoint {
+    (0u8..=255).find_map(|ctr| {
+        let hash = Sha512::new().chain([VRF_SUITE, 0x01]).chain(public_key).chain(alpha).chain([ctr, 0x00]).finalize();
+        let candidate: [u8; 32] = hash[..32].try_into().unwrap();
+        CompressedEdwardsY(candidate).decompress().map(|point| point.mul_by_cofactor())
+    }).expect("256次尝试内未能映射到曲线点")
+}
+
+fn vrf_challenge(points: &[EdwardsPoint]) -> Scalar {
+    let mut hasher = Sha512::new().chain([VRF_SUITE, 0x02]);
+    for point in points {
+        hasher.update(point.compress().as_bytes());
+    }
+    let hash = hasher.chain([0x00]).finalize();
+    let mut c = [0u8; 32];
+    c[..16].copy_from_slice(&hash[..16]);
+    Scalar::from_bits(c)
+}
+
+// VRF证明：Gamma（32字节）、c（16字节）、s（32字节）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct VrfProof([u8; VRF_PROOF_LENGTH]);
+
+impl VrfProof {
+    pub fn from_hex(hex_proof: &str) -> Result<Self, String> {
+        let bytes = hex::decode(hex_proof).map_err(|_| "VRF证明不是有效的十六进制".to_string())?;
+        Ok(VrfProof(bytes.try_into().map_err(|_| format!("VRF证明必须为{}字节", VRF_PROOF_LENGTH))?))
+    }
+
+    pub fn to_hex(self) -> String {
+        hex::encode(self.0)
+    }
+
+    // 证明对应的64字节输出；只有通过PublicKey::vrf_verify的证明，其输出才有意义
+    pub fn output(&self) -> Option<[u8; 64]> {
+        let gamma = CompressedEdwardsY(self.0[..32].try_into().unwrap()).decompress()?;
+        let hash = Sha512::new().chain([VRF_SUITE, 0x03]).chain(gamma.mul_by_cofactor().compress().as_bytes()).chain([0x00]).finalize();
+        Some(hash[..].try_into().unwrap())
+    }
+}
+
+impl fmt::Debug for VrfProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VrfProof({})", self.to_hex())
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &str, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?.write_all(data)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &str, data: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, data)
+}
+
+#[derive(Clone, Copy)]
+pub struct PublicKey {
+    bytes: [u8; PUBLIC_KEY_LENGTH],
+    point: EdwardsPoint,
+}
+
+impl PublicKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let bytes: [u8; PUBLIC_KEY_LENGTH] = bytes.try_into().map_err(|_| "公钥必须为32字节".to_string())?;
+        let point = CompressedEdwardsY(bytes).decompress().ok_or_else(|| "公钥不是曲线上的点".to_string())?;
+        // 小阶公钥在带余因子的验证方程下可以为任意消息“签名”
+        if point.is_small_order() {
+            return Err("公钥为小阶点".to_string());
+        }
+        Ok(PublicKey { bytes, point })
+    }
+
+    pub fn from_hex(hex_key: &str) -> Result<Self, String> {
+        let bytes = hex::decode(hex_key).map_err(|_| "公钥不是有效的十六进制".to_string())?;
+        PublicKey::from_bytes(&bytes)
+    }
+
+    pub fn to_hex(self) -> String {
+        hex::encode(self.bytes)
+    }
+
+    // 验证VRF证明，成立时返回输出
+    pub fn vrf_verify(&self, alpha: &[u8], proof: &VrfProof) -> Option<[u8; 64]> {
+        let gamma = CompressedEdwardsY(proof.0[..32].try_into().unwrap()).decompress()?;
+        let mut c = [0u8; 32];
+        c[..16].copy_from_slice(&proof.0[32..48]);
+        let c = Scalar::from_bits(c);
+        let s = Scalar::from_canonical_bytes(proof.0[48..].try_into().unwrap())?;
+        let h = vrf_encode_to_curve(&self.bytes, alpha);
+        let u = EdwardsPoint::vartime_double_scalar_mul_basepoint(&-c, &self.point, &s);
+        let v = EdwardsPoint::vartime_multiscalar_mul([s, -c], [h, gamma]);
+        if vrf_challenge(&[self.point, h, gamma, u, v]) != c {
+            return None;
+        }
+        proof.output()
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        match Equation::new(self, message, signature) {
+            Some(equation) => {
+                let residual = EdwardsPoint::vartime_double_scalar_mul_basepoint(&-equation.k, &self.point, &equation.s) - equation.r;
+                residual.mul_by_cofactor().is_identity()
+            }
+            None => false,
+        }
+    }
+}
+
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl Eq for PublicKey {}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicKey({})", self.to_hex())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature([u8; SIGNATURE_LENGTH]);
+
+impl Signature {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        bytes.try_into().map(Signature).map_err(|_| "签名必须为64字节".to_string())
+    }
+
+    pub fn from_hex(hex_signature: &str) -> Result<Self, String> {
+        let bytes = hex::decode(hex_signature).map_err(|_| "签名不是有效的十六进制".to_string())?;
+        Signature::from_bytes(&bytes)
+    }
+
+    pub fn to_hex(self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+impl fmt::Debug for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signature({})", self.to_hex())
+    }
+}
+
+// 线上格式与改造前的Vec<u8>相同（JSON中为字节数组），已有的链文件和对端无需迁移
+impl Serialize for PublicKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.bytes)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        PublicKey::from_bytes(&bytes).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        Signature::from_bytes(&bytes).map_err(de::Error::custom)
+    }
+}
+
+// 一个签名对应的验证方程 sB = R + kA 中的各项；s不是规范编码、R无法解码或为小阶点时不成立
+struct Equation {
+    s: Scalar,
+    r: EdwardsPoint,
+    k: Scalar,
+}
+
+impl Equation {
+    fn new(key: &PublicKey, message: &[u8], signature: &Signature) -> Option<Self> {
+        let r_bytes: [u8; 32] = signature.0[..32].try_into().unwrap();
+        let s = Scalar::from_canonical_bytes(signature.0[32..].try_into().unwrap())?;
+        let r = CompressedEdwardsY(r_bytes).decompress().filter(|r| !r.is_small_order())?;
+        let k = Scalar::from_hash(Sha512::new().chain(r_bytes).chain(key.bytes).chain(message));
+        Some(Equation { s, r, k })
+    }
+}
+
+// 批量验证，返回每个签名是否有效。先用随机线性组合一次检查整批签名，
+// 不成立时再逐个验证找出无效的签名，因此结果总与逐个调用verify相同
+pub fn batch_verify(items: &[(&PublicKey, &[u8], &Signature)]) -> Vec<bool> {
+    if items.len() > 1 && batch_equation_holds(items) {
+        return vec![true; items.len()];
+    }
+    items.iter().map(|(key, message, signature)| key.verify(message, signature)).collect()
+}
+
+// 检查 [8]·Σ z_i(s_i·B - R_i - k_i·A_i) = 0，z_i为128位随机数
+fn batch_equation_holds(items: &[(&PublicKey, &[u8], &Signature)]) -> bool {
+    let mut scalars = Vec::with_capacity(items.len() * 2 + 1);
+    let mut points = Vec::with_capacity(items.len() * 2 + 1);
+    let mut basepoint_scalar = Scalar::zero();
+    for (key, message, signature) in items {
+        let equation = match Equation::new(key, message, signature) {
+            Some(equation) => equation,
+            None => return false,
+        };
+        let mut z = [0u8; 32];
+        OsRng.fill_bytes(&mut z[..16]);
+        let z = Scalar::from_bits(z);
+        basepoint_scalar += z * equation.s;
+        scalars.push(-z);
+        points.push(equation.r);
+        scalars.push(-(z * equation.k));
+        points.push(key.point);
+    }
+    scalars.push(basepoint_scalar);
+    points.push(ED25519_BASEPOINT_POINT);
+    EdwardsPoint::vartime_multiscalar_mul(scalars, points).mul_by_cofactor().is_identity()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 8032 7.1 测试向量1（空消息）
+    const RFC_SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const RFC_PUBLIC: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const RFC_SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+
+    // RFC 9381 附录B.3 ECVRF-EDWARDS25519-SHA512-TAI 示例16（空输入）
+    #[test]
+    fn vrf_matches_rfc9381_vector() {
+        let key = SigningKey::from_secret_bytes(&hex::decode(RFC_SECRET).unwrap()).unwrap();
+        let proof = key.vrf_prove(b"");
+        assert_eq!(proof.to_hex(), "8657106690b5526245a92b003bb079ccd1a92130477671f6fc01ad16f26f723f26f8a57ccaed74ee1b190bed1f479d9727d2d0f9b005a6e456a35d4fb0daab1268a1b0db10836d9826a528ca76567805");
+        let output = key.public_key().vrf_verify(b"", &proof).unwrap();
+        assert_eq!(hex::encode(output), "90cf1df3b703cce59e2a35b925d411164068269d7b2d29f3301c03dd757876ff66b71dda49d2de59d03450451af026798e8f81cd2e333de5cdf4f3e140fdd8ae");
+        assert!(key.public_key().vrf_verify(b"x", &proof).is_none());
+        assert!(SigningKey::generate().public_key().vrf_verify(b"", &proof).is_none());
+    }
+
+    #[test]
+    fn matches_rfc8032_vector() {
+        let key = SigningKey::from_secret_bytes(&hex::decode(RFC_SECRET).unwrap()).unwrap();
+        assert_eq!(key.public_key().to_hex(), RFC_PUBLIC);
+        let signature = key.sign(b"");
+        assert_eq!(signature.to_hex(), RFC_SIGNATURE);
+        assert!(PublicKey::from_hex(RFC_PUBLIC).unwrap().verify(b"", &signature));
+        assert_eq!(&key.secret_bytes()[..], &hex::decode(RFC_SECRET).unwrap()[..]);
+    }
+
+    #[test]
+    fn rejects_wrong_message_key_and_malformed_signature() {
+        let key = SigningKey::generate();
+        let signature = key.sign(b"SET k v");
+        assert!(key.public_key().verify(b"SET k v", &signature));
+        assert!(!key.public_key().verify(b"SET k w", &signature));
+        assert!(!SigningKey::generate().public_key().verify(b"SET k v", &signature));
+
+        // s加上群的阶后不再是规范编码
+        let mut bytes = signature.0;
+        let order = hex::decode("edd3f55c1a631258d69cf7a2def9de1400000000000000000000000000000010").unwrap();
+        let mut carry = 0u16;
+        for i in 0..32 {
+            let sum = bytes[32 + i] as u16 + order[i] as u16 + carry;
+            bytes[32 + i] = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!key.public_key().verify(b"SET k v", &Signature::from_bytes(&bytes).unwrap()));
+
+        // 单位元是小阶点，不能作为公钥
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        assert!(PublicKey::from_bytes(&identity).is_err());
+        assert!(Signature::from_bytes(&[0u8; 63]).is_err());
+    }
+
+    #[test]
+    fn batch_flags_exactly_the_invalid_signatures() {
+        let keys: Vec<SigningKey> = (0..8).map(|_| SigningKey::generate()).collect();
+        let public_keys: Vec<PublicKey> = keys.iter().map(|key| key.public_key()).collect();
+        let messages: Vec<Vec<u8>> = (0..8).map(|i| format!("Commit {}", i).into_bytes()).collect();
+        let mut signatures: Vec<Signature> = keys.iter().zip(&messages).map(|(key, message)| key.sign(message)).collect();
+
+        let batch = |signatures: &[Signature]| {
+            let items: Vec<(&PublicKey, &[u8], &Signature)> = public_keys.iter()
+                .zip(&messages)
+                .zip(signatures)
+                .map(|((key, message), signature)| (key, message.as_slice(), signature))
+                .collect();
+            batch_verify(&items)
+        };
+        assert_eq!(batch(&signatures), vec![true; 8]);
+
+        signatures[3] = keys[3].sign(b"other");
+        signatures[6] = signatures[5];
+        let expected: Vec<bool> = (0..8).map(|i| i != 3 && i != 6).collect();
+        assert_eq!(batch(&signatures), expected);
+        assert!(batch_verify(&[]).is_empty());
+    }
+
+    #[test]
+    fn serde_keeps_byte_array_format() {
+        let key = SigningKey::generate();
+        let signature = key.sign(b"m");
+        let encoded = serde_json::to_value(signature).unwrap();
+        assert_eq!(encoded.as_array().unwrap().len(), SIGNATURE_LENGTH);
+        // 改造前以Vec<u8>序列化的签名和公钥可以直接读取
+        let legacy = serde_json::to_string(&signature.0.to_vec()).unwrap();
+        assert_eq!(serde_json::from_str::<Signature>(&legacy).unwrap(), signature);
+        let legacy_key = serde_json::to_string(&key.public_key().bytes.to_vec()).unwrap();
+        assert_eq!(serde_json::from_str::<PublicKey>(&legacy_key).unwrap(), key.public_key());
+        assert!(serde_json::from_str::<Signature>("[1,2,3]").is_err());
+    }
+
+    #[test]
+    fn shared_secret_is_symmetric() {
+        let (a, b, c) = (SigningKey::generate(), SigningKey::generate(), SigningKey::generate());
+        assert_eq!(*a.shared_secret(&b.public_key()), *b.shared_secret(&a.public_key()));
+        assert_ne!(*a.shared_secret(&b.public_key()), *a.shared_secret(&c.public_key()));
+    }
+
+    #[test]
+    fn key_file_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("pbft-key-{}-{}", std::process::id(), rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("node.key").to_string_lossy().to_string();
+        let created = SigningKey::load_or_generate(&path).unwrap();
+        let loaded = SigningKey::load_or_generate(&path).unwrap();
+        assert_eq!(created.public_key(), loaded.public_key());
+        std::fs::write(&path, "not hex").unwrap();
+        assert!(SigningKey::load_or_generate(&path).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
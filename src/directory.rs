@@ -0,0 +1,95 @@
+// src/directory.rs
+
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::crypto::{PublicKey, Signature, SigningKey};
+use crate::domain;
+use crate::node::Role;
+use crate::quota::Sandbox;
+
+// 目录条目保存在复制状态机中，键为 directory/<节点ID>
+pub const KEY_PREFIX: &str = "directory/";
+pub const REGISTER_COMMAND: &str = "REGISTER";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    pub node_id: usize,
+    pub addresses: Vec<String>, // 按优先级排列的地址，支持IPv4、IPv6和域名
+    pub public_key: String, // 十六进制编码的ed25519公钥
+    pub role: Role,
+    pub sequence: u64, // 每次更新递增，防止重放旧的地址公告
+}
+
+// 由条目中的公钥自签名，任何人都可以转发登记请求，但无法伪造内容
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedEntry {
+    pub entry: DirectoryEntry,
+    pub signature: String,
+}
+
+impl SignedEntry {
+    pub fn sign(entry: DirectoryEntry, signing_key: &SigningKey) -> Self {
+        let signature = signing_key.sign(&signing_payload(&entry));
+        SignedEntry {
+            entry,
+            signature: signature.to_hex(),
+        }
+    }
+
+    pub fn verify(&self) -> Result<PublicKey, String> {
+        let public_key = PublicKey::from_hex(&self.entry.public_key)?;
+        let signature = Signature::from_hex(&self.signature)?;
+        if !public_key.verify(&signing_payload(&self.entry), &signature) {
+            return Err("签名校验失败".to_string());
+        }
+        Ok(public_key)
+    }
+
+    // 登记操作：REGISTER <签名条目JSON>，随普通请求进入共识
+    pub fn registration_operation(&self) -> String {
+        format!("{} {}", REGISTER_COMMAND, serde_json::to_string(self).unwrap())
+    }
+}
+
+fn signing_payload(entry: &DirectoryEntry) -> Vec<u8> {
+    domain::tagged(domain::DIRECTORY, &serde_json::to_vec(entry).unwrap())
+}
+
+pub fn key(node_id: usize) -> String {
+    format!("{}{}", KEY_PREFIX, node_id)
+}
+
+// 执行登记：校验签名；已登记的节点只能用同一把公钥、更大的序号更新地址和角色
+pub fn apply_registration(store: &mut Sandbox, payload: &str) -> Result<(), String> {
+    let signed: SignedEntry = serde_json::from_str(payload).map_err(|e| format!("无法解析目录条目: {}", e))?;
+    signed.verify()?;
+
+    let key = key(signed.entry.node_id);
+    if let Some(existing) = lookup(store, signed.entry.node_id) {
+        if existing.public_key != signed.entry.public_key {
+            return Err(format!("节点{}已使用其他公钥登记", signed.entry.node_id));
+        }
+        if signed.entry.sequence <= existing.sequence {
+            return Err(format!("节点{}的目录条目序号{}不大于已登记的{}", signed.entry.node_id, signed.entry.sequence, existing.sequence));
+        }
+    }
+    store.insert(key, serde_json::to_string(&signed).unwrap());
+    Ok(())
+}
+
+// 读取时重新校验签名，状态中的条目即使被篡改也不会被采用
+pub fn lookup(store: &BTreeMap<String, String>, node_id: usize) -> Option<DirectoryEntry> {
+    let data = store.get(&key(node_id))?;
+    let signed: SignedEntry = serde_json::from_str(data).ok()?;
+    signed.verify().ok()?;
+    Some(signed.entry)
+}
+
+pub fn entries(store: &BTreeMap<String, String>) -> Vec<DirectoryEntry> {
+    store.range(KEY_PREFIX.to_string()..)
+        .take_while(|(key, _)| key.starts_with(KEY_PREFIX))
+        .filter_map(|(_, data)| serde_json::from_str::<SignedEntry>(data).ok())
+        .filter(|signed| signed.verify().is_ok())
+        .map(|signed| signed.entry)
+        .collect()
+}
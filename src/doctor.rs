@@ -0,0 +1,350 @@
+// src/doctor.rs
+
+// 节点自检：pbft-blockchain doctor <节点ID> 在节点的工作目录中检查配置和私钥文件（复用启动前的配置检查）、
+// 数据目录的完整性（区块链的哈希链接、Merkle根和批次摘要，共识日志与区块是否一致）、
+// 时钟是否被回拨，以及各验证者能否连通，打印报告，有失败项时以非零状态退出。
+// 节点启动时自动运行其中较快的部分：只校验最近DOCTOR_STARTUP_BLOCKS个区块，不拨号对等节点
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::address_book::AddressBook;
+use crate::chain::Chain;
+use crate::config::{CLOCK_SKEW_WARN_MS, DOCTOR_STARTUP_BLOCKS};
+use crate::genesis::Genesis;
+use crate::hash::HashFunction;
+use crate::network;
+use crate::node::{NodeState, Role};
+use crate::preflight;
+use crate::quorum::COMMIT_QUORUM;
+use crate::storage;
+
+// 早于该时间（2020-01-01）的系统时间显然没有校准过
+const EARLIEST_SANE_UNIX_SECS: u64 = 1_577_836_800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub check: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Finding {
+    fn new(check: &str, status: Status, detail: String) -> Self {
+        Finding { check: check.to_string(), status, detail }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    pub fn failed(&self) -> bool {
+        self.findings.iter().any(|finding| finding.status == Status::Fail)
+    }
+
+    pub fn print(&self, node_id: usize) {
+        println!("节点{}的自检报告:", node_id);
+        for finding in &self.findings {
+            let status = match finding.status {
+                Status::Pass => "通过",
+                Status::Warn => "警告",
+                Status::Fail => "失败",
+            };
+            println!("  [{}] {}: {}", status, finding.check, finding.detail);
+        }
+        let count = |status| self.findings.iter().filter(|finding| finding.status == status).count();
+        println!("{}项通过，{}项警告，{}项失败", count(Status::Pass), count(Status::Warn), count(Status::Fail));
+    }
+}
+
+// 节点启动时的快速自检：只校验最近的区块和时钟
+pub fn self_test(node_id: usize, genesis: &Genesis) -> Report {
+    let mut report = Report::default();
+    report.findings.extend(check_data(Path::new("."), node_id, genesis.hash_function, Some(DOCTOR_STARTUP_BLOCKS)));
+    report.findings.extend(check_clock(Path::new("."), node_id, SystemTime::now()));
+    report
+}
+
+// 命令行入口：doctor <节点ID> [full|archive|observer] [--key-file 路径]，参数与启动节点时一致
+pub fn run(args: &[String]) -> i32 {
+    let node_id: usize = match args.first().map(|id| id.parse()) {
+        Some(Ok(node_id)) => node_id,
+        _ => {
+            eprintln!("用法: pbft-blockchain doctor <节点ID> [full|archive|observer] [--key-file 路径]");
+            return 2;
+        }
+    };
+    let role = match args.get(1).map(String::as_str) {
+        Some("full") => Role::FullNode,
+        Some("archive") => Role::Archive,
+        Some("observer") => Role::Observer,
+        _ => Role::Validator,
+    };
+    let key_file = args.iter().position(|s| s == "--key-file").and_then(|i| args.get(i + 1));
+
+    let mut report = Report::default();
+    let genesis = match preflight::check(node_id, role, key_file.map(String::as_str)) {
+        Ok(genesis) => {
+            report.findings.push(Finding::new("配置", Status::Pass, "创世文件、配置文件、私钥和监听地址有效".to_string()));
+            Some(genesis)
+        }
+        Err(errors) => {
+            for error in errors {
+                report.findings.push(Finding::new("配置", Status::Fail, format!("{}（建议: {}）", error, error.suggestion())));
+            }
+            Genesis::load().ok()
+        }
+    };
+    let hash_function = genesis.as_ref().map(|genesis| genesis.hash_function).unwrap_or_default();
+    report.findings.extend(check_data(Path::new("."), node_id, hash_function, None));
+    report.findings.extend(check_clock(Path::new("."), node_id, SystemTime::now()));
+    if let Some(genesis) = &genesis {
+        // 先拨地址簿中上次握手成功时的地址，再试默认地址
+        let address_book = AddressBook::load(Path::new("."), node_id);
+        let peers: Vec<(usize, Vec<String>)> = address_book.reconnect_order(genesis.validators.iter().copied().filter(|&id| id != node_id))
+            .into_iter()
+            .map(|id| (id, address_book.dial_addresses(id, network::default_addresses(id))))
+            .collect();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build();
+        match runtime {
+            Ok(runtime) => report.findings.extend(runtime.block_on(check_peers(&peers, genesis.validators.contains(&node_id)))),
+            Err(e) => report.findings.push(Finding::new("对等节点", Status::Warn, format!("无法创建tokio运行时: {}", e))),
+        }
+    }
+    report.print(node_id);
+    if report.failed() { 1 } else { 0 }
+}
+
+// 数据目录的完整性：区块链能否解析、本地区块是否自洽，共识日志中的提交记录是否与区块一致。
+// recent为Some(n)时只校验最近n个区块
+fn check_data(directory: &Path, node_id: usize, hash_function: HashFunction, recent: Option<usize>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let chain_file = directory.join(format!("node_{}_chain.json", node_id));
+    let mut chain: Chain = match std::fs::read_to_string(&chain_file) {
+        Ok(data) => match serde_json::from_str(&data) {
+            Ok(chain) => chain,
+            Err(e) => {
+                findings.push(Finding::new("区块链", Status::Fail, format!("{}无法解析: {}", chain_file.display(), e)));
+                return findings;
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            findings.push(Finding::new("区块链", Status::Pass, "尚无本地区块".to_string()));
+            Chain::default()
+        }
+        Err(e) => {
+            findings.push(Finding::new("区块链", Status::Fail, format!("无法读取{}: {}", chain_file.display(), e)));
+            return findings;
+        }
+    };
+    if chain.blocks.is_empty() && chain.base.is_none() {
+        chain.hash_function = hash_function;
+    }
+    // 节点启动时同样会重放预写日志中尚未写入链文件的区块
+    let recovered = storage::recover(directory, node_id, &mut chain).len();
+    if recovered > 0 {
+        findings.push(Finding::new("预写日志", Status::Pass, format!("{}个已提交的区块尚未写入链文件，启动时从日志恢复", recovered)));
+    }
+    if !chain.blocks.is_empty() {
+        let from = recent.map(|n| chain.blocks.len().saturating_sub(n)).unwrap_or(0);
+        if chain.hash_function != hash_function {
+            findings.push(Finding::new("区块链", Status::Fail, format!("区块使用{:?}，创世配置为{:?}", chain.hash_function, hash_function)));
+        } else if let Err(reason) = chain.verify_local(from) {
+            findings.push(Finding::new("区块链", Status::Fail, reason));
+        } else {
+            findings.push(Finding::new("区块链", Status::Pass, format!("校验了高度{}到{}的{}个区块", chain.blocks[from].header.height, chain.height(), chain.blocks.len() - from)));
+        }
+    }
+    if let Some(base) = &chain.base {
+        if !directory.join(format!("node_{}_snapshot.json", node_id)).exists() {
+            findings.push(Finding::new("区块链", Status::Fail, format!("链从高度{}的快照开始，但找不到快照文件", base.height)));
+        }
+    }
+
+    let state_file = directory.join(format!("node_{}_state.json", node_id));
+    let state: NodeState = match std::fs::read_to_string(&state_file) {
+        Ok(data) => match serde_json::from_str(&data) {
+            Ok(state) => state,
+            Err(e) => {
+                findings.push(Finding::new("共识日志", Status::Fail, format!("{}无法解析: {}", state_file.display(), e)));
+                return findings;
+            }
+        },
+        Err(_) => return findings,
+    };
+    let highest = chain.blocks.last().map(|block| block.header.sequence_number).unwrap_or(0);
+    let mut pending = Vec::new();
+    for (seq, digest) in &state.committed {
+        match chain.blocks.iter().find(|block| block.header.sequence_number == *seq) {
+            Some(block) if &block.header.digest != digest => {
+                findings.push(Finding::new("共识日志", Status::Fail, format!("序号{}提交的摘要{}与区块中的{}不符", seq, digest, block.header.digest)));
+            }
+            None if *seq > highest => pending.push(*seq),
+            _ => {}
+        }
+    }
+    if !pending.is_empty() {
+        pending.sort_unstable();
+        findings.push(Finding::new("共识日志", Status::Warn, format!("序号{:?}已提交但区块未保存，启动后从对端补齐", pending)));
+    }
+    if !findings.iter().any(|finding| finding.check == "共识日志") {
+        findings.push(Finding::new("共识日志", Status::Pass, format!("{}条提交记录与区块一致", state.committed.len())));
+    }
+    findings
+}
+
+// 时钟是否合理：系统时间不能早于2020年，也不能早于本节点数据文件的修改时间（时钟被回拨）
+fn check_clock(directory: &Path, node_id: usize, now: SystemTime) -> Vec<Finding> {
+    let unix_secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    if unix_secs < EARLIEST_SANE_UNIX_SECS {
+        return vec![Finding::new("时钟", Status::Fail, format!("系统时间{}早于2020年，时钟未校准", unix_secs))];
+    }
+    let prefix = format!("node_{}_", node_id);
+    let tolerance = Duration::from_millis(CLOCK_SKEW_WARN_MS as u64);
+    let mut findings = Vec::new();
+    for entry in std::fs::read_dir(directory).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(&prefix) {
+            continue;
+        }
+        let modified = match entry.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(_) => continue,
+        };
+        if let Ok(ahead) = modified.duration_since(now) {
+            if ahead > tolerance {
+                findings.push(Finding::new("时钟", Status::Fail, format!("{}的修改时间比当前时间晚{}ms，时钟可能被回拨", name, ahead.as_millis())));
+            }
+        }
+    }
+    if findings.is_empty() {
+        findings.push(Finding::new("时钟", Status::Pass, "系统时间不早于本节点的数据文件".to_string()));
+    }
+    findings
+}
+
+// 逐个拨号其他验证者；连不上的节点只是警告，整个集群尚未启动时也可以运行自检
+async fn check_peers(peers: &[(usize, Vec<String>)], validator: bool) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut reachable = validator as usize;
+    for (peer, addresses) in peers {
+        let started = Instant::now();
+        match network::dial(addresses).await {
+            Some((address, _)) => {
+                reachable += 1;
+                findings.push(Finding::new(&format!("对等节点{}", peer), Status::Pass, format!("{}可连通，耗时{}ms", address, started.elapsed().as_millis())));
+            }
+            None => findings.push(Finding::new(&format!("对等节点{}", peer), Status::Warn, format!("{:?}均无法连接", addresses))),
+        }
+    }
+    if reachable < COMMIT_QUORUM {
+        findings.push(Finding::new("对等节点", Status::Warn, format!("只有{}个验证者可连通，不足法定人数{}", reachable, COMMIT_QUORUM)));
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use tokio::net::TcpListener;
+    use crate::chain::{digest_transactions, CommitCertificate};
+    use crate::config::N;
+    use crate::message::Transaction;
+    use crate::testing::TestCluster;
+
+    fn statuses(findings: &[Finding]) -> Vec<(&str, Status)> {
+        findings.iter().map(|finding| (finding.check.as_str(), finding.status)).collect()
+    }
+
+    // 篡改区块、提交记录与区块不符、文件修改时间在未来，各自报告为失败
+    #[tokio::test]
+    async fn detects_corrupted_data_and_clock() {
+        let directory = std::env::temp_dir().join(format!("pbft-doctor-{}-{}", std::process::id(), rand::random::<u32>()));
+        std::fs::create_dir(&directory).unwrap();
+        let hash_function = HashFunction::default();
+        let mut chain = Chain::default();
+        let mut state = NodeState { prepared: HashSet::new(), committed: HashSet::new(), view_change_messages: Vec::new(), byzantine_votes: HashMap::new() };
+        for seq in 1..=5 {
+            let transactions = vec![Transaction { operation: format!("SET k{} v", seq), client_id: None, session: None, timestamp: None }];
+            let digest = digest_transactions(hash_function.hasher(), &transactions);
+            let certificate = CommitCertificate { view: 0, sequence_number: seq, digest: digest.clone(), signatures: Vec::new() };
+            chain.append(0, seq, digest.clone(), transactions, certificate);
+            state.committed.insert((seq, digest));
+        }
+        state.committed.insert((6, "d6".to_string()));
+        let write = |chain: &Chain, state: &NodeState| {
+            std::fs::write(directory.join("node_0_chain.json"), serde_json::to_string(chain).unwrap()).unwrap();
+            std::fs::write(directory.join("node_0_state.json"), serde_json::to_string(state).unwrap()).unwrap();
+        };
+        write(&chain, &state);
+        let findings = check_data(&directory, 0, hash_function, None);
+        assert_eq!(statuses(&findings), vec![("区块链", Status::Pass), ("共识日志", Status::Warn)]);
+        assert!(check_clock(&directory, 0, SystemTime::now()).iter().all(|finding| finding.status == Status::Pass));
+
+        chain.blocks[1].transactions[0].operation = "SET k2 forged".to_string();
+        state.committed.insert((3, "forged".to_string()));
+        write(&chain, &state);
+        let findings = check_data(&directory, 0, hash_function, None);
+        assert!(findings[0].status == Status::Fail && findings[0].detail.contains("高度2"), "{:?}", findings);
+        assert!(findings.iter().any(|finding| finding.status == Status::Fail && finding.detail.contains("序号3")));
+        // 启动时只校验最近的区块，发现不了更早的篡改
+        assert_eq!(check_data(&directory, 0, hash_function, Some(3))[0].status, Status::Pass);
+        std::fs::write(directory.join("node_0_chain.json"), "{").unwrap();
+        assert_eq!(statuses(&check_data(&directory, 0, hash_function, None)), vec![("区块链", Status::Fail)]);
+
+        let file = std::fs::File::options().write(true).open(directory.join("node_0_state.json")).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(3600)).unwrap();
+        let findings = check_clock(&directory, 0, SystemTime::now());
+        assert!(findings[0].status == Status::Fail && findings[0].detail.contains("node_0_state.json"));
+        assert_eq!(check_clock(&directory, 0, UNIX_EPOCH)[0].status, Status::Fail);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().to_string();
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let findings = check_peers(&[(1, vec![closed.clone(), open]), (2, vec![closed])], true).await;
+        assert_eq!(statuses(&findings), vec![("对等节点1", Status::Pass), ("对等节点2", Status::Warn), ("对等节点", Status::Warn)]);
+    }
+
+    // 集群运行后各节点的数据目录通过自检；节点3崩溃后其区块文件被篡改，自检报告失败
+    #[tokio::test]
+    async fn cluster_data_passes_self_test() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            for i in 0..3 {
+                let operation = format!("SET k{} v", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, &operation).is_some())).await;
+                assert!(committed, "{}未提交", operation);
+            }
+            cluster.crash(3);
+            for id in 0..N {
+                let findings = check_data(Path::new("."), id, cluster.genesis.hash_function, None);
+                assert!(findings.iter().all(|finding| finding.status == Status::Pass), "节点{}: {:?}", id, findings);
+            }
+
+            // 节点3崩溃前排队的后台写入可能在篡改之后才落盘并覆盖篡改，被覆盖时重新篡改
+            let tamper = || {
+                let mut chain: Chain = serde_json::from_str(&std::fs::read_to_string("node_3_chain.json").unwrap()).unwrap();
+                if !chain.blocks[0].transactions.is_empty() {
+                    chain.blocks[0].transactions.clear();
+                    std::fs::write("node_3_chain.json", serde_json::to_string(&chain).unwrap()).unwrap();
+                }
+            };
+            let detected = cluster.wait_until(Duration::from_secs(5), |_| {
+                tamper();
+                check_data(Path::new("."), 3, cluster.genesis.hash_function, Some(DOCTOR_STARTUP_BLOCKS))[0].status == Status::Fail
+            }).await;
+            assert!(detected, "{:?}", check_data(Path::new("."), 3, cluster.genesis.hash_function, Some(DOCTOR_STARTUP_BLOCKS)));
+        }).await;
+    }
+}
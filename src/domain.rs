@@ -0,0 +1,89 @@
+// src/domain.rs
+
+// 签名和摘要的域分离：每种用途的签名内容和摘要输入都以域标签和一个0字节开头，
+// 一个上下文中有效的签名或摘要不能被当作另一种消息或另一种对象重放。
+// 共识消息的域标签是其类型名的小写形式（preprepare、prepare、commit、viewchange、checkpoint……），
+// 新增的消息类型自动得到自己的域；其余签名和摘要使用下面的固定标签
+use crate::message::PBFTMessage;
+
+// 签名
+pub const HANDSHAKE: &str = "handshake";
+pub const DIRECTORY: &str = "directory";
+pub const GOVERNANCE: &str = "governance";
+pub const MULTISIG: &str = "multisig";
+pub const KEY_BUNDLE: &str = "key-bundle";
+
+// 摘要
+pub const BATCH: &str = "batch";
+pub const BLOCK: &str = "block";
+pub const TRANSACTION: &str = "transaction";
+pub const SNAPSHOT: &str = "snapshot";
+pub const SNAPSHOT_CHUNK: &str = "snapshot-chunk";
+
+// 共识消息的域标签；签名消息和认证消息取内部消息的类型
+pub fn of(message: &PBFTMessage) -> String {
+    message.kind().to_ascii_lowercase()
+}
+
+// 带域标签的内容：域标签、0字节、数据。标签中不含0字节，不同标签的内容不会相同
+pub fn tagged(domain: &str, data: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(domain.len() + 1 + data.len());
+    payload.extend_from_slice(domain.as_bytes());
+    payload.push(0);
+    payload.extend_from_slice(data);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::crypto::SigningKey;
+    use crate::chain::{self, batch_payload, digest_transactions};
+    use crate::chain_index::transaction_digest;
+    use crate::genesis::{self, Genesis};
+    use crate::hash::HashFunction;
+    use crate::message::Transaction;
+    use crate::testing::TestCluster;
+
+    // 字段相同的Prepare和Commit、单交易批次和该交易、单分块快照和该分块，签名内容或摘要各不相同
+    #[test]
+    fn contexts_never_collide() {
+        let genesis: Genesis = serde_json::from_str(r#"{"chain_id": "domain-test"}"#).unwrap();
+        let prepare = PBFTMessage::Prepare { view: 0, sequence_number: 1, digest: "d".to_string(), sender_id: 0 };
+        let commit = PBFTMessage::Commit { view: 0, sequence_number: 1, digest: "d".to_string() };
+        assert_eq!(of(&prepare), "prepare");
+        let key = SigningKey::generate();
+        let signature = key.sign(&genesis.message_payload(&prepare));
+        assert!(key.public_key().verify(&genesis.message_payload(&prepare), &signature));
+        // 把Prepare的签名拿去冒充同样内容的其他类型消息，验签失败
+        let replayed = genesis::signing_payload(&genesis.chain_id, &of(&commit), &serde_json::to_vec(&prepare).unwrap());
+        assert!(!key.public_key().verify(&replayed, &signature));
+
+        let hasher = HashFunction::default().hasher();
+        let transaction = Transaction { operation: "SET k v".to_string(), client_id: None, session: None, timestamp: None };
+        assert_ne!(
+            digest_transactions(hasher, std::slice::from_ref(&transaction)),
+            transaction_digest(HashFunction::default(), &transaction),
+        );
+        assert_ne!(hasher.domain_digest(SNAPSHOT, b"data"), hasher.domain_digest(SNAPSHOT_CHUNK, b"data"));
+        assert_eq!(tagged("a", b"b"), b"a\0b");
+    }
+
+    // 整个集群使用带域标签的签名和摘要照常提交
+    #[tokio::test]
+    async fn cluster_commits_with_tagged_payloads() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            cluster.submit("SET tagged yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, "SET tagged yes").is_some())).await;
+            assert!(committed, "请求未提交");
+            let chain = cluster.chains[0].lock().unwrap();
+            let block = chain.blocks.last().unwrap();
+            let hasher = cluster.genesis.hasher();
+            assert_eq!(block.header.digest, hasher.domain_digest(BATCH, batch_payload(&block.transactions).as_bytes()));
+            assert!(chain::verify_block(block, chain.blocks.iter().rev().nth(1).map(|b| &b.header), &cluster.public_keys, &cluster.genesis).is_ok());
+        }).await;
+    }
+}
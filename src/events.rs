@@ -0,0 +1,108 @@
+// src/events.rs
+
+// 进程内的共识事件总线：节点在共识推进的关键点发布类型化的事件，RPC、监控等子系统各自订阅，
+// 不再需要读取Node的内部字段。总线基于tokio::sync::broadcast，发布不阻塞共识；
+// 订阅者处理太慢时丢失最早的事件（计入event_bus_lagged_total），没有订阅者时事件直接丢弃
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError, Receiver, Sender};
+use crate::config::EVENT_BUS_CAPACITY;
+use crate::metrics;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "event")]
+pub enum ConsensusEvent {
+    // 主节点提议或副本接受了某个序列号的批次
+    ProposalAccepted { view: u64, sequence_number: u64, digest: String, primary: usize },
+    Prepared { view: u64, sequence_number: u64, digest: String },
+    // 区块已追加到本地链并执行
+    Committed { view: u64, sequence_number: u64, height: u64, transactions: usize },
+    ViewChanged { view: u64, primary: usize },
+    PeerBlacklisted { node_id: usize },
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: Sender<ConsensusEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus { sender: broadcast::channel(EVENT_BUS_CAPACITY).0 }
+    }
+
+    pub fn publish(&self, event: ConsensusEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> Receiver<ConsensusEvent> {
+        self.sender.subscribe()
+    }
+}
+
+// 订阅者取下一个事件，跳过因处理太慢而丢失的部分；没有订阅时永不返回，总线关闭时返回None
+pub async fn next(subscription: &mut Option<Receiver<ConsensusEvent>>) -> Option<ConsensusEvent> {
+    let receiver = match subscription {
+        Some(receiver) => receiver,
+        None => return std::future::pending().await,
+    };
+    loop {
+        match receiver.recv().await {
+            Ok(event) => return Some(event),
+            Err(RecvError::Lagged(skipped)) => metrics::inc_counter("event_bus_lagged_total", skipped),
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    #[tokio::test]
+    async fn slow_subscriber_skips_lagged_events() {
+        let bus = EventBus::new();
+        let mut subscription = Some(bus.subscribe());
+        for view in 0..EVENT_BUS_CAPACITY as u64 + 2 {
+            bus.publish(ConsensusEvent::ViewChanged { view, primary: 0 });
+        }
+        assert_eq!(next(&mut subscription).await, Some(ConsensusEvent::ViewChanged { view: 2, primary: 0 }));
+        let idle = tokio::time::timeout(Duration::from_millis(10), next(&mut None)).await;
+        assert!(idle.is_err(), "没有订阅时不应返回事件");
+    }
+
+    // 订阅副本的事件总线，一次提交依次产生接受提议、Prepared和Committed事件
+    #[tokio::test]
+    async fn replica_publishes_commit_lifecycle() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let mut subscription = Some(cluster.events[1].subscribe());
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET k v").is_some())
+            }).await;
+            assert!(committed, "请求未提交");
+
+            let mut kinds = Vec::new();
+            while let Ok(Some(event)) = tokio::time::timeout(Duration::from_millis(100), next(&mut subscription)).await {
+                kinds.push(match event {
+                    ConsensusEvent::ProposalAccepted { primary, .. } => {
+                        assert_eq!(primary, 0);
+                        "ProposalAccepted"
+                    }
+                    ConsensusEvent::Prepared { .. } => "Prepared",
+                    ConsensusEvent::Committed { transactions, .. } => {
+                        assert!(transactions > 0);
+                        "Committed"
+                    }
+                    _ => "other",
+                });
+            }
+            let position = |kind: &str| kinds.iter().position(|k| *k == kind);
+            assert!(position("ProposalAccepted").is_some(), "没有收到事件: {:?}", kinds);
+            assert!(position("ProposalAccepted") < position("Prepared") && position("Prepared") < position("Committed"), "事件顺序不对: {:?}", kinds);
+        }).await;
+    }
+}
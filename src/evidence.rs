@@ -0,0 +1,216 @@
+// src/evidence.rs
+
+// 拉黑节点所需的密码学证据。拜占庭投票必须附带证据，接收方验签后才计票，不再凭"与多数摘要不同"定罪：
+// 网络分区或主节点分叉时，诚实的少数一方也会与多数不同。
+// - Equivocation：同一节点在同一视图、同一序列号上签名了两条摘要不同的同类共识消息，无可辩驳
+// - DivergentPrepare：副本签名的Prepare与主节点签名的PrePrepare摘要不同。主节点分叉时诚实副本也会如此，
+//   所以被指控者可以申诉：出示主节点为其Prepare摘要签名的另一条PrePrepare，两条PrePrepare
+//   合起来证明分叉的是主节点，投票随之撤销
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::crypto::PublicKey;
+use crate::genesis::Genesis;
+use crate::message::PBFTMessage;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum Evidence {
+    Equivocation { first: Box<PBFTMessage>, second: Box<PBFTMessage> },
+    DivergentPrepare { pre_prepare: Box<PBFTMessage>, prepare: Box<PBFTMessage> },
+}
+
+// 证据中一条签名投票的内容
+struct Vote<'a> {
+    signer: usize,
+    kind: &'static str,
+    view: u64,
+    sequence_number: u64,
+    digest: &'a str,
+}
+
+// 取出签名共识投票的内容，不验签
+fn vote(msg: &PBFTMessage) -> Option<Vote<'_>> {
+    let (message, signer) = match msg {
+        PBFTMessage::SignedMessage { message, sender_id, .. } => (message, *sender_id),
+        _ => return None,
+    };
+    let (view, sequence_number, digest) = match &**message {
+        PBFTMessage::PrePrepare { view, sequence_number, digest, .. } | PBFTMessage::Commit { view, sequence_number, digest } => (view, sequence_number, digest),
+        PBFTMessage::Prepare { view, sequence_number, digest, sender_id } if *sender_id == signer => (view, sequence_number, digest),
+        _ => return None,
+    };
+    Some(Vote { signer, kind: message.kind(), view: *view, sequence_number: *sequence_number, digest })
+}
+
+// 验签后取出投票内容
+fn open<'a>(msg: &'a PBFTMessage, keys: &HashMap<usize, PublicKey>, genesis: &Genesis) -> Result<Vote<'a>, String> {
+    let vote = vote(msg).ok_or("证据中的消息不是签名的共识投票")?;
+    let key = keys.get(&vote.signer).ok_or_else(|| format!("没有节点{}的公钥", vote.signer))?;
+    if let PBFTMessage::SignedMessage { message, signature, .. } = msg {
+        if !key.verify(&genesis.message_payload(message), signature) {
+            return Err(format!("节点{}的{}签名无效", vote.signer, vote.kind));
+        }
+    }
+    Ok(vote)
+}
+
+impl Evidence {
+    // 验证证据，返回被证明作恶的节点
+    pub fn verify(&self, keys: &HashMap<usize, PublicKey>, genesis: &Genesis, leader: impl Fn(u64) -> usize) -> Result<usize, String> {
+        match self {
+            Evidence::Equivocation { first, second } => {
+                let (a, b) = (open(first, keys, genesis)?, open(second, keys, genesis)?);
+                if a.signer != b.signer || a.kind != b.kind || (a.view, a.sequence_number) != (b.view, b.sequence_number) {
+                    return Err("两条消息不是同一节点对同一实例的投票".to_string());
+                }
+                if a.digest == b.digest {
+                    return Err("两条消息的摘要相同".to_string());
+                }
+                Ok(a.signer)
+            }
+            Evidence::DivergentPrepare { pre_prepare, prepare } => {
+                let (p, q) = (open(pre_prepare, keys, genesis)?, open(prepare, keys, genesis)?);
+                if p.kind != "PrePrepare" || p.signer != leader(p.view) {
+                    return Err("不是主节点签名的PrePrepare".to_string());
+                }
+                if q.kind != "Prepare" || (p.view, p.sequence_number) != (q.view, q.sequence_number) {
+                    return Err("Prepare与PrePrepare不属于同一实例".to_string());
+                }
+                if p.digest == q.digest {
+                    return Err("Prepare与PrePrepare的摘要相同".to_string());
+                }
+                Ok(q.signer)
+            }
+        }
+    }
+
+    // 证据所属的视图，拜占庭投票按(被指控节点, 视图)分别计数。未通过验证的证据为0
+    pub fn view(&self) -> u64 {
+        let offending = match self {
+            Evidence::Equivocation { first, .. } => first,
+            Evidence::DivergentPrepare { prepare, .. } => prepare,
+        };
+        vote(offending).map_or(0, |vote| vote.view)
+    }
+
+    // 被指控者可据以申诉的PrePrepare：(视图, 序列号, 其Prepare的摘要)。分叉的证据无从申诉
+    pub fn appealable(&self) -> Option<(u64, u64, String)> {
+        match self {
+            Evidence::DivergentPrepare { prepare, .. } => vote(prepare).map(|q| (q.view, q.sequence_number, q.digest.to_string())),
+            Evidence::Equivocation { .. } => None,
+        }
+    }
+
+    // 申诉出示的PrePrepare与证据中的PrePrepare合成主节点分叉的证据；两者不对应时返回None
+    pub fn answered_by(&self, pre_prepare: &PBFTMessage) -> Option<Evidence> {
+        let (view, sequence_number, digest) = self.appealable()?;
+        let shown = vote(pre_prepare)?;
+        if (shown.view, shown.sequence_number, shown.digest) != (view, sequence_number, digest.as_str()) {
+            return None;
+        }
+        match self {
+            Evidence::DivergentPrepare { pre_prepare: accused_with, .. } => Some(Evidence::Equivocation {
+                first: accused_with.clone(),
+                second: Box::new(pre_prepare.clone()),
+            }),
+            Evidence::Equivocation { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::audit::{AuditEntry, AuditEvent};
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::crypto::SigningKey;
+    use crate::testing::TestCluster;
+
+    fn genesis() -> Genesis {
+        Genesis { chain_id: "evidence-test".to_string(), validators: (0..4).collect(), ..Genesis::default() }
+    }
+
+    fn sign(key: &SigningKey, id: usize, msg: PBFTMessage) -> PBFTMessage {
+        let signature = key.sign(&genesis().message_payload(&msg));
+        PBFTMessage::SignedMessage { message: Box::new(msg), signature, sender_id: id, trace: None }
+    }
+
+    fn pre_prepare(digest: &str) -> PBFTMessage {
+        PBFTMessage::PrePrepare { view: 0, sequence_number: 1, digest: digest.to_string(), transactions: Vec::new() }
+    }
+
+    fn prepare(digest: &str, sender_id: usize) -> PBFTMessage {
+        PBFTMessage::Prepare { view: 0, sequence_number: 1, digest: digest.to_string(), sender_id }
+    }
+
+    // 副本3的Prepare与主节点0发给本节点的PrePrepare不符；3出示主节点为它的摘要签名的PrePrepare后，
+    // 指控转为主节点分叉的证据
+    #[test]
+    fn divergent_prepare_is_answered_by_the_primarys_other_preprepare() {
+        let keys: Vec<SigningKey> = (0..4).map(|_| SigningKey::generate()).collect();
+        let public_keys: HashMap<usize, PublicKey> = keys.iter().enumerate().map(|(id, k)| (id, k.public_key())).collect();
+        let genesis = genesis();
+        let leader = |_| 0;
+
+        let accusation = Evidence::DivergentPrepare {
+            pre_prepare: Box::new(sign(&keys[0], 0, pre_prepare("x"))),
+            prepare: Box::new(sign(&keys[3], 3, prepare("y", 3))),
+        };
+        assert_eq!(accusation.verify(&public_keys, &genesis, leader), Ok(3));
+
+        // 伪造的签名、冒名的Prepare、摘要一致的"证据"都不成立
+        let forged = Evidence::DivergentPrepare {
+            pre_prepare: Box::new(sign(&keys[0], 0, pre_prepare("x"))),
+            prepare: Box::new(sign(&keys[2], 3, prepare("y", 3))),
+        };
+        assert!(forged.verify(&public_keys, &genesis, leader).is_err());
+        let impersonated = Evidence::DivergentPrepare {
+            pre_prepare: Box::new(sign(&keys[0], 0, pre_prepare("x"))),
+            prepare: Box::new(sign(&keys[2], 2, prepare("y", 3))),
+        };
+        assert!(impersonated.verify(&public_keys, &genesis, leader).is_err());
+        let consistent = Evidence::DivergentPrepare {
+            pre_prepare: Box::new(sign(&keys[0], 0, pre_prepare("x"))),
+            prepare: Box::new(sign(&keys[3], 3, prepare("x", 3))),
+        };
+        assert!(consistent.verify(&public_keys, &genesis, leader).is_err());
+
+        assert!(accusation.answered_by(&sign(&keys[0], 0, pre_prepare("x"))).is_none());
+        let equivocation = accusation.answered_by(&sign(&keys[0], 0, pre_prepare("y"))).unwrap();
+        assert_eq!(equivocation.verify(&public_keys, &genesis, leader), Ok(0));
+        assert_eq!(equivocation.appealable(), None);
+        // 非主节点冒充主节点签名的PrePrepare不能为被指控者开脱
+        let fake = accusation.answered_by(&sign(&keys[3], 0, pre_prepare("y"))).unwrap();
+        assert!(fake.verify(&public_keys, &genesis, leader).is_err());
+    }
+
+    fn audit_events(id: usize) -> Vec<AuditEvent> {
+        std::fs::read_to_string(format!("node_{}_audit.jsonl", id)).unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str::<AuditEntry>(line).unwrap().event)
+            .collect()
+    }
+
+    // 分叉的主节点让两组诚实副本的Prepare互不相符，双方互相指控。被指控者出示各自收到的PrePrepare申诉后，
+    // 指控全部撤销，两条PrePrepare转而成为主节点分叉的证据，诚实节点一致拉黑主节点
+    #[tokio::test]
+    async fn equivocating_primary_cannot_frame_honest_replicas() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().byzantine(0, Strategy::EquivocatingPrimary).timeout(Duration::from_millis(300)).build().await;
+            cluster.submit("SET k v").await;
+            let blacklisted = cluster.wait_until(Duration::from_secs(10), |_| {
+                (1..N).all(|id| audit_events(id).iter().any(|event| matches!(event, AuditEvent::Blacklisted { node_id: 0, .. })))
+            }).await;
+            assert!(blacklisted, "诚实节点未凭证据拉黑分叉的主节点");
+
+            for id in 1..N {
+                let events = audit_events(id);
+                assert!(!events.iter().any(|event| matches!(event, AuditEvent::Blacklisted { node_id, .. } if *node_id != 0)), "节点{}拉黑了诚实节点", id);
+            }
+            let withdrawn = (1..N).any(|id| audit_events(id).iter().any(|event| matches!(event, AuditEvent::AccusationWithdrawn { .. })));
+            assert!(withdrawn, "没有指控因申诉而撤销");
+        }).await;
+    }
+}
@@ -0,0 +1,389 @@
+// src/execution.rs
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use crate::application::{self, Application};
+use crate::config::{GAS_BASE_COST, GAS_PER_BYTE, OPERATION_GAS_LIMIT, BLOCK_GAS_LIMIT, N, STATE_VIEW_HISTORY};
+use crate::message::Transaction;
+use crate::beacon::{self, BEACON_COMMAND};
+use crate::bridge::{self, BRIDGE_COMMAND, EMIT_COMMAND};
+use crate::chain::ValidatorSet;
+use crate::directory::{self, REGISTER_COMMAND};
+use crate::genesis::Genesis;
+use crate::governance::{self, GOVERN_COMMAND};
+use crate::hash::Hasher;
+use crate::merkle;
+use crate::metrics;
+use crate::multisig::{self, MULTISIG_COMMAND};
+use crate::namespace::{self, NAMESPACE_COMMAND};
+use crate::quota::{Quota, Sandbox};
+use crate::reply_cache::ReplyCache;
+use crate::schedule::{self, SCHEDULE_COMMAND};
+use crate::session;
+use crate::state_view::{self, Diff, StateView};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionStatus {
+    Success(Option<String>),
+    Failed(String),
+    OutOfGas,
+    BlockGasLimitExceeded,
+    QuotaExceeded(String), // 超出单个操作的写入配额，操作的修改全部撤销
+    Scheduled(u64), // 已登记为定时交易，在该高度执行
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExecutionResult {
+    pub status: ExecutionStatus,
+    pub gas_used: u64,
+}
+
+// 复制状态机：系统操作由引擎执行，其余操作交给创世配置选定的应用。所有计量只依赖操作内容，保证各副本结果一致
+pub struct ExecutionEngine {
+    store: Arc<BTreeMap<String, String>>, // 当前状态，区块原地执行；仍被视图引用时先复制一份
+    diffs: VecDeque<(u64, Diff)>, // 最近的高度各自修改的键及修改前的值，用于还原历史高度的视图
+    views: VecDeque<StateView>, // 读取过的历史高度的视图，由当前状态和diffs还原后缓存
+    application: Box<dyn Application>, // 执行非系统操作的复制应用，来自创世配置
+    operation_gas_limit: u64,
+    block_gas_limit: u64,
+    quota: Quota, // 单个操作的写入配额
+    chain_id: String, // 创世信标由链ID导出
+    validators: Vec<usize>, // 有治理投票权的验证者，来自创世配置
+    foreign_chains: Vec<ValidatorSet>, // 跨链桥跟踪的其他链，来自创世配置
+    applied_height: u64, // 最后一个完整执行的区块高度
+    pub reply_cache: ReplyCache, // 每个客户端最近一次请求的结果，不属于复制状态
+    pub deferred: Vec<(Transaction, ExecutionResult)>, // 最近一个区块执行的到期定时交易，供节点答复客户端
+}
+
+impl ExecutionEngine {
+    pub fn new() -> Self {
+        ExecutionEngine {
+            store: Arc::default(),
+            diffs: VecDeque::new(),
+            views: VecDeque::new(),
+            application: Box::new(application::KvStore),
+            operation_gas_limit: OPERATION_GAS_LIMIT,
+            block_gas_limit: BLOCK_GAS_LIMIT,
+            quota: Quota::default(),
+            chain_id: String::new(),
+            validators: (0..N).collect(),
+            foreign_chains: Vec::new(),
+            applied_height: 0,
+            reply_cache: ReplyCache::default(),
+            deferred: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.store.get(key)
+    }
+
+    pub fn state(&self) -> &BTreeMap<String, String> {
+        &self.store
+    }
+
+    // 某一高度的状态视图，不指定高度时为当前状态。取得视图后不必持有引擎的锁。
+    // 历史高度第一次被读取时复制当前状态，按diffs从新到旧退回到该高度，之后的读取（例如分页扫描的后续页）复用
+    pub fn view(&mut self, height: Option<u64>) -> Result<StateView, String> {
+        let height = height.unwrap_or(self.applied_height);
+        if height == self.applied_height {
+            return Ok(StateView::new(height, self.store.clone()));
+        }
+        if height > self.applied_height {
+            return Err(format!("尚未执行到高度{}，当前执行高度为{}", height, self.applied_height));
+        }
+        if let Some(view) = self.views.iter().find(|view| view.height() == height) {
+            return Ok(view.clone());
+        }
+        if height < self.oldest_view_height() {
+            return Err(format!("高度{}的状态已淘汰，只保留最近{}个高度", height, STATE_VIEW_HISTORY));
+        }
+        let mut store = (*self.store).clone();
+        for (_, diff) in self.diffs.iter().rev().take_while(|(diff_height, _)| *diff_height > height) {
+            state_view::revert(&mut store, diff);
+        }
+        let view = StateView::new(height, Arc::new(store));
+        self.views.push_back(view.clone());
+        Ok(view)
+    }
+
+    // 记下刚执行的区块的修改，淘汰超出STATE_VIEW_HISTORY个高度的差异和视图
+    fn remember_diff(&mut self, diff: Diff) {
+        self.diffs.push_back((self.applied_height, diff));
+        while self.diffs.len() >= STATE_VIEW_HISTORY {
+            self.diffs.pop_front();
+        }
+        let oldest = self.oldest_view_height();
+        self.views.retain(|view| view.height() >= oldest);
+    }
+
+    // 仍能还原的最低高度：最早的差异退回到的高度
+    fn oldest_view_height(&self) -> u64 {
+        self.diffs.front().map_or(self.applied_height, |(height, _)| height - 1)
+    }
+
+    // 状态对应的区块高度，重启或修复后从下一个区块开始重放
+    pub fn applied_height(&self) -> u64 {
+        self.applied_height
+    }
+
+    // 状态摘要：以每个键值对为叶子的Merkle根，各副本执行相同的区块后摘要相同
+    pub fn state_digest(&self, hasher: &dyn Hasher) -> String {
+        let leaves: Vec<String> = self.store.iter().map(|entry| serde_json::to_string(&entry).unwrap()).collect();
+        merkle::merkle_root(hasher, &leaves)
+    }
+
+    // 按创世配置设置链ID、验证者、跨链桥和复制应用；节点程序中没有配置的应用时返回错误
+    pub fn configure(&mut self, genesis: &Genesis) -> Result<(), String> {
+        self.application = application::create(genesis.application.as_deref().unwrap_or(application::DEFAULT_APPLICATION))?;
+        self.chain_id = genesis.chain_id.clone();
+        self.validators = genesis.validators.clone();
+        self.foreign_chains = genesis.bridges.clone();
+        Ok(())
+    }
+
+    pub fn application(&self) -> &'static str {
+        self.application.name()
+    }
+
+    // 用状态同步得到的快照（高度height处的状态）替换全部状态
+    pub fn restore(&mut self, height: u64, store: BTreeMap<String, String>) {
+        self.store = Arc::new(store);
+        self.applied_height = height;
+        // 此前的高度与快照之间没有连续的执行，不再提供
+        self.diffs.clear();
+        self.views.clear();
+    }
+
+    // 按顺序执行高度height的区块内的交易；累计gas超过区块上限后，剩余交易全部失败。
+    // 区块在当前状态上原地执行，只有仍被视图引用的状态才先复制，视图读到的状态因此不会被修改；
+    // 执行期间持有引擎的锁，其他读者看不到只执行了一部分的区块。已执行过的高度直接跳过，返回空结果
+    pub fn execute_block(&mut self, height: u64, transactions: &[Transaction]) -> Vec<ExecutionResult> {
+        if height <= self.applied_height {
+            return Vec::new();
+        }
+        let mut shared = std::mem::take(&mut self.store);
+        let store = Arc::make_mut(&mut shared);
+        let mut diff = Diff::new();
+        let mut block_gas = 0;
+        // 先推进随机信标，区块第一笔交易可以是主节点的VRF证明
+        let proof = transactions.first().and_then(|tx| tx.operation.strip_prefix(BEACON_COMMAND)).and_then(|rest| rest.strip_prefix(' '));
+        let beacon = system_write(store, &mut diff, |sandbox| beacon::advance(sandbox, height, &self.chain_id, &self.validators, proof));
+        // 到期的定时交易先于区块内的交易执行，其gas计入区块，但不会因区块gas用尽而失败
+        let due = system_write(store, &mut diff, |sandbox| schedule::take_due(sandbox, height));
+        let deferred: Vec<(Transaction, ExecutionResult)> = due.into_iter().map(|tx| {
+            let result = self.execute(store, &mut diff, &tx, &tx.operation, gas_cost(&tx.operation));
+            block_gas += result.gas_used;
+            (tx, result)
+        }).collect();
+        let results: Vec<ExecutionResult> = transactions.iter().enumerate().map(|(i, tx)| {
+            if i == 0 && proof.is_some() {
+                let status = match &beacon {
+                    Ok(entry) => ExecutionStatus::Success(Some(entry.value.clone())),
+                    Err(reason) => ExecutionStatus::Failed(reason.clone()),
+                };
+                return ExecutionResult { status, gas_used: 0 };
+            }
+            // 会话中已执行过的序号直接返回第一次执行的结果，不再修改状态
+            let mut session = tx.session.as_ref().map(|tag| (tag, session::load(store, &tag.session_id)));
+            if let Some(status) = session.as_ref().and_then(|(tag, state)| state.lookup(tag.sequence)) {
+                return ExecutionResult { status, gas_used: 0 };
+            }
+            let cost = gas_cost(&tx.operation);
+            // 因区块gas用尽而未执行的交易不计入会话，客户端可以重发
+            if block_gas + cost.min(self.operation_gas_limit) > self.block_gas_limit {
+                return ExecutionResult { status: ExecutionStatus::BlockGasLimitExceeded, gas_used: 0 };
+            }
+            let result = match tx.operation.strip_prefix(SCHEDULE_COMMAND).and_then(|rest| rest.strip_prefix(' ')) {
+                Some(payload) => self.schedule(store, &mut diff, height, tx, payload, cost),
+                None => self.execute(store, &mut diff, tx, &tx.operation, cost),
+            };
+            block_gas += result.gas_used;
+            if let Some((tag, state)) = session.as_mut() {
+                state.record(tag.sequence, result.status.clone());
+                system_write(store, &mut diff, |sandbox| session::save(sandbox, &tag.session_id, state));
+            }
+            result
+        }).collect();
+
+        self.store = shared;
+        self.applied_height = height;
+        self.remember_diff(diff);
+        self.deferred = deferred;
+        for (tx, result) in transactions.iter().zip(&results) {
+            self.reply_cache.record(tx, &result.status);
+        }
+        results
+    }
+
+    // 登记定时交易；指定的高度已到时立即执行
+    fn schedule(&self, store: &mut BTreeMap<String, String>, diff: &mut Diff, height: u64, tx: &Transaction, payload: &str, cost: u64) -> ExecutionResult {
+        if cost > self.operation_gas_limit {
+            return ExecutionResult { status: ExecutionStatus::OutOfGas, gas_used: self.operation_gas_limit };
+        }
+        let (at, operation) = match schedule::parse(payload) {
+            Ok(parsed) => parsed,
+            Err(reason) => return ExecutionResult { status: ExecutionStatus::Failed(reason), gas_used: cost },
+        };
+        if at <= height {
+            return self.execute(store, diff, tx, operation, cost);
+        }
+        // 到期执行时以登记者的身份答复，会话只记录登记的结果
+        let deferred = Transaction { operation: operation.to_string(), client_id: tx.client_id.clone(), session: None, timestamp: tx.timestamp };
+        let mut sandbox = Sandbox::new(store, self.quota);
+        let status = match schedule::enqueue(&mut sandbox, height, at, &deferred) {
+            Ok(()) => ExecutionStatus::Scheduled(at),
+            Err(reason) => ExecutionStatus::Failed(reason),
+        };
+        ExecutionResult { status: within_quota(sandbox, diff, status), gas_used: cost }
+    }
+
+    // tx是发起操作的交易，命名空间的权限按其客户端ID判断；定时交易到期执行时仍以登记者的身份
+    fn execute(&self, store: &mut BTreeMap<String, String>, diff: &mut Diff, tx: &Transaction, operation: &str, cost: u64) -> ExecutionResult {
+        // 超出预算的操作在执行前失败，不修改状态
+        if cost > self.operation_gas_limit {
+            return ExecutionResult { status: ExecutionStatus::OutOfGas, gas_used: self.operation_gas_limit };
+        }
+        let mut sandbox = Sandbox::new(store, self.quota);
+        let status = self.apply(&mut sandbox, tx, operation);
+        ExecutionResult { status: within_quota(sandbox, diff, status), gas_used: cost }
+    }
+
+    fn apply(&self, store: &mut Sandbox, tx: &Transaction, operation: &str) -> ExecutionStatus {
+        // 目录登记的参数是JSON，不按空格拆分
+        if let Some(payload) = operation.strip_prefix(REGISTER_COMMAND).and_then(|rest| rest.strip_prefix(' ')) {
+            return match directory::apply_registration(store, payload) {
+                Ok(()) => ExecutionStatus::Success(None),
+                Err(reason) => ExecutionStatus::Failed(reason),
+            };
+        }
+        if let Some(payload) = operation.strip_prefix(GOVERN_COMMAND).and_then(|rest| rest.strip_prefix(' ')) {
+            return match governance::apply(store, &self.validators, payload) {
+                Ok(output) => ExecutionStatus::Success(output),
+                Err(reason) => ExecutionStatus::Failed(reason),
+            };
+        }
+        if let Some(payload) = operation.strip_prefix(BRIDGE_COMMAND).and_then(|rest| rest.strip_prefix(' ')) {
+            return match bridge::apply(store, &self.foreign_chains, payload) {
+                Ok(key) => ExecutionStatus::Success(Some(key)),
+                Err(reason) => ExecutionStatus::Failed(reason),
+            };
+        }
+        if let Some(payload) = operation.strip_prefix(MULTISIG_COMMAND).and_then(|rest| rest.strip_prefix(' ')) {
+            // 签名够门限后，以账户的身份执行其名下的键值操作
+            return match multisig::apply(store, payload) {
+                Ok(Some(authorized)) => application::apply_kv(store, &authorized),
+                Ok(None) => ExecutionStatus::Success(None),
+                Err(reason) => ExecutionStatus::Failed(reason),
+            };
+        }
+        if let Some(payload) = operation.strip_prefix(NAMESPACE_COMMAND).and_then(|rest| rest.strip_prefix(' ')) {
+            return match namespace::apply(store, tx.client_id.as_deref(), payload) {
+                Ok(()) => ExecutionStatus::Success(None),
+                Err(reason) => ExecutionStatus::Failed(reason),
+            };
+        }
+        if operation.strip_prefix(BEACON_COMMAND).is_some_and(|rest| rest.starts_with(' ')) {
+            return ExecutionStatus::Failed("信标证明只能是区块的第一笔交易".to_string());
+        }
+        // 发往其他链的消息只需要进入区块，由中继者取走
+        if operation.strip_prefix(EMIT_COMMAND).is_some_and(|rest| rest.starts_with(' ')) {
+            return ExecutionStatus::Success(None);
+        }
+
+        let mut parts = operation.splitn(3, ' ');
+        let command = parts.next().unwrap_or("");
+        let key = parts.next();
+
+        // 会话、目录、治理、跨链消息、多签账户、定时交易、随机信标和命名空间登记的键只能由对应的操作修改。
+        // 各应用的操作同样以命令开头、第二个词为键或账户，按第二个词检查
+        let reserved = [
+            session::KEY_PREFIX, directory::KEY_PREFIX, governance::KEY_PREFIX, bridge::KEY_PREFIX,
+            multisig::KEY_PREFIX, multisig::CUSTODY_PREFIX, schedule::KEY_PREFIX, beacon::KEY_PREFIX,
+            namespace::KEY_PREFIX,
+        ];
+        if let Some(prefix) = key.and_then(|key| reserved.iter().find(|prefix| key.starts_with(*prefix))).filter(|_| command != "GET") {
+            return ExecutionStatus::Failed(format!("键前缀{}保留给专用操作", prefix));
+        }
+        // 命名空间下的键只有所有者和写入者能修改
+        if let Some(Err(reason)) = key.filter(|_| command != "GET").map(|key| namespace::check_write(store, tx.client_id.as_deref(), key)) {
+            return ExecutionStatus::Failed(reason);
+        }
+
+        self.application.apply(store, tx, operation)
+    }
+}
+
+// 操作超出配额时撤销其全部修改，结果改为QuotaExceeded；保留的修改记入区块的diff
+fn within_quota(sandbox: Sandbox, diff: &mut Diff, status: ExecutionStatus) -> ExecutionStatus {
+    match sandbox.finish(diff) {
+        Ok(()) => status,
+        Err(reason) => {
+            metrics::inc_counter("execution_quota_exceeded_total", 1);
+            ExecutionStatus::QuotaExceeded(reason)
+        }
+    }
+}
+
+// 执行引擎自己的写入不受配额限制，修改前的值同样记入区块的diff
+fn system_write<T>(store: &mut BTreeMap<String, String>, diff: &mut Diff, write: impl FnOnce(&mut Sandbox) -> T) -> T {
+    let mut sandbox = Sandbox::new(store, Quota::unlimited());
+    let output = write(&mut sandbox);
+    sandbox.finish(diff).expect("不限配额的写入不会超出配额");
+    output
+}
+
+// gas只由操作本身的字节数决定，与副本的本地状态无关
+pub fn gas_cost(operation: &str) -> u64 {
+    GAS_BASE_COST + operation.len() as u64 * GAS_PER_BYTE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(operation: &str) -> Transaction {
+        Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None }
+    }
+
+    // 区块整体应用后推进高度；已应用的高度再次执行时跳过，不会重复修改状态
+    #[test]
+    fn blocks_apply_once_in_height_order() {
+        let mut engine = ExecutionEngine::new();
+        let results = engine.execute_block(1, &[transaction("APPEND k a"), transaction("APPEND k b")]);
+        assert_eq!(results.len(), 2);
+        assert_eq!(engine.applied_height(), 1);
+        assert!(engine.execute_block(1, &[transaction("APPEND k a")]).is_empty());
+        assert_eq!(engine.get("k"), Some(&"ab".to_string()));
+
+        // 快照恢复到高度5，之后只接受更高的区块
+        engine.restore(5, BTreeMap::new());
+        assert!(engine.execute_block(5, &[transaction("SET k x")]).is_empty());
+        engine.execute_block(6, &[transaction("SET k y")]);
+        assert_eq!((engine.applied_height(), engine.get("k")), (6, Some(&"y".to_string())));
+    }
+
+    // 没有视图引用当前状态时区块原地执行，不复制整个状态；被视图引用时先复制，视图读到的状态不变。
+    // 历史高度的视图由各高度的差异还原，与当时的状态一致
+    #[test]
+    fn blocks_execute_in_place_and_views_are_rebuilt_from_diffs() {
+        let mut engine = ExecutionEngine::new();
+        let mut states = vec![engine.state().clone()];
+        for height in 1..=5 {
+            let before = Arc::as_ptr(&engine.store);
+            engine.execute_block(height, &[transaction(&format!("SET k{} v{}", height % 3, height)), transaction("APPEND log x")]);
+            assert_eq!(Arc::as_ptr(&engine.store), before, "高度{}复制了整个状态", height);
+            states.push(engine.state().clone());
+        }
+
+        let held = engine.view(None).unwrap();
+        engine.execute_block(6, &[transaction("DEL k1"), transaction("SET k9 new")]);
+        assert_eq!(held.store(), &states[5], "执行新区块修改了视图持有的状态");
+        assert_eq!(engine.get("k1"), None);
+        for height in 0..=5 {
+            assert_eq!(engine.view(Some(height)).unwrap().store(), &states[height as usize], "高度{}的视图与当时的状态不符", height);
+        }
+        assert!(engine.diffs.iter().all(|(_, diff)| diff.len() <= 4), "差异只应包含被修改的键");
+    }
+}
@@ -0,0 +1,110 @@
+// src/features.rs
+
+// 协议特性开关：每个特性在创世配置指定的区块高度激活，之前的区块不能使用它。
+// 各节点先升级到支持新特性的版本，到达激活高度后同时启用，集群无需同时停机重启
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::message::Transaction;
+use crate::namespace;
+use crate::schedule::SCHEDULE_COMMAND;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    ClientSessions,    // 交易携带客户端会话ID和序号
+    RequestTimestamps, // 交易携带客户端请求时间戳
+    ScheduledTransactions, // SCHEDULE操作，旧版本会把它当作无法识别的操作
+    Namespaces, // NAMESPACE操作和对ns/下的键的写入权限检查
+}
+
+impl Feature {
+    pub const ALL: [Feature; 4] = [Feature::ClientSessions, Feature::RequestTimestamps, Feature::ScheduledTransactions, Feature::Namespaces];
+
+    fn used_by(self, transaction: &Transaction) -> bool {
+        match self {
+            Feature::ClientSessions => transaction.session.is_some(),
+            Feature::RequestTimestamps => transaction.timestamp.is_some(),
+            Feature::ScheduledTransactions => transaction.operation.strip_prefix(SCHEDULE_COMMAND).is_some_and(|rest| rest.starts_with(' ')),
+            Feature::Namespaces => namespace::touches(&transaction.operation),
+        }
+    }
+}
+
+// 特性 -> 激活高度；未列出的特性从创世起即已激活
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureSchedule(pub BTreeMap<Feature, u64>);
+
+impl FeatureSchedule {
+    pub fn activation_height(&self, feature: Feature) -> u64 {
+        self.0.get(&feature).copied().unwrap_or(0)
+    }
+
+    // 链上治理提前激活特性，已经更早激活的不受影响
+    pub fn activate(&mut self, feature: Feature, height: u64) {
+        let activation = self.0.entry(feature).or_insert(height);
+        *activation = (*activation).min(height);
+    }
+
+    pub fn is_active(&self, feature: Feature, height: u64) -> bool {
+        height >= self.activation_height(feature)
+    }
+
+    // 检查交易能否进入指定高度的区块
+    pub fn check(&self, transaction: &Transaction, height: u64) -> Result<(), String> {
+        match Feature::ALL.iter().find(|feature| feature.used_by(transaction) && !self.is_active(**feature, height)) {
+            Some(feature) => Err(format!(
+                "操作'{}'使用了高度{}才激活的特性{:?}，当前区块高度{}",
+                transaction.operation, self.activation_height(*feature), feature, height,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn check_all(&self, transactions: &[Transaction], height: u64) -> Result<(), String> {
+        transactions.iter().try_for_each(|transaction| self.check(transaction, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::chain::{self, Chain, CommitCertificate};
+    use crate::genesis::Genesis;
+    use crate::hash::Sha256;
+    use crate::session::SessionTag;
+
+    fn sessioned(operation: &str) -> Transaction {
+        let session = Some(SessionTag { session_id: "s".to_string(), sequence: 1 });
+        Transaction { operation: operation.to_string(), client_id: None, session, timestamp: None }
+    }
+
+    #[test]
+    fn features_activate_at_configured_height() {
+        let schedule: FeatureSchedule = serde_json::from_str(r#"{"client-sessions": 2}"#).unwrap();
+        assert!(!schedule.is_active(Feature::ClientSessions, 1));
+        assert!(schedule.is_active(Feature::ClientSessions, 2));
+        assert!(schedule.is_active(Feature::RequestTimestamps, 0));
+        assert!(schedule.check(&sessioned("SET k v"), 1).is_err());
+        assert!(schedule.check(&sessioned("SET k v"), 2).is_ok());
+        let plain = Transaction { operation: "SET k v".to_string(), client_id: None, session: None, timestamp: None };
+        assert!(schedule.check_all(&[plain], 1).is_ok());
+    }
+
+    // 使用未激活特性的区块被拒绝，即使证书和哈希都正确
+    #[test]
+    fn blocks_with_inactive_features_are_rejected() {
+        let mut genesis = Genesis { chain_id: "feature-test".to_string(), ..Genesis::default() };
+        let mut chain = Chain::default();
+        let transactions = vec![sessioned("SET k v")];
+        let digest = chain::digest_transactions(&Sha256, &transactions);
+        let certificate = CommitCertificate { view: 0, sequence_number: 1, digest: digest.clone(), signatures: Vec::new() };
+        let block = chain.append(0, 1, digest, transactions, certificate).clone();
+
+        let reason = chain::verify_block(&block, None, &HashMap::new(), &genesis).unwrap_err();
+        assert!(reason.contains("提交证书"), "{}", reason);
+        genesis.features.0.insert(Feature::ClientSessions, 5);
+        let reason = chain::verify_block(&block, None, &HashMap::new(), &genesis).unwrap_err();
+        assert!(reason.contains("ClientSessions"), "{}", reason);
+    }
+}
@@ -0,0 +1,158 @@
+// src/firewall.rs
+
+// 传输层的准入控制，与协议层的黑名单相互独立：黑名单凭签名证据和投票拉黑作恶的验证者，
+// 防火墙则由运维配置，在消息进入入站流水线、RPC连接被处理之前就拒绝，不消耗验签和共识的资源。
+// 配置文件firewall.json（工作目录下，不存在时全部放行），例如：
+//   {"validators_only": true, "allow_peers": [7], "deny_peers": [5], "allow_addresses": ["10.0.0.8"], "deny_addresses": ["203.0.113.9"]}
+// - validators_only：只接受创世配置中的验证者和allow_peers中的节点发来的消息
+// - deny_peers：拒绝这些节点的消息，优先于任何放行规则
+// - allow_addresses：非空时RPC只接受来自这些IP的连接；deny_addresses中的IP总是被拒绝
+// 运维可通过RPC在运行时封禁或解封节点和IP，运行时的改动不写回配置文件
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use serde::{Serialize, Deserialize};
+use log::info;
+use crate::config::FIREWALL_FILE;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Firewall {
+    #[serde(default)]
+    pub validators_only: bool,
+    #[serde(default)]
+    pub allow_peers: BTreeSet<usize>,
+    #[serde(default)]
+    pub deny_peers: BTreeSet<usize>,
+    #[serde(default)]
+    pub allow_addresses: BTreeSet<IpAddr>,
+    #[serde(default)]
+    pub deny_addresses: BTreeSet<IpAddr>,
+    #[serde(skip)]
+    validators: BTreeSet<usize>, // 创世配置中的验证者
+}
+
+// 监听IPv6通配地址时，IPv4客户端表现为::ffff:a.b.c.d，按IPv4地址匹配规则
+fn canonical(address: IpAddr) -> IpAddr {
+    match address {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(address),
+        IpAddr::V4(_) => address,
+    }
+}
+
+impl Firewall {
+    pub fn load(validators: &[usize]) -> Self {
+        let firewall = match std::fs::read_to_string(FIREWALL_FILE) {
+            Ok(data) => {
+                let firewall: Firewall = serde_json::from_str(&data).unwrap();
+                info!("从{}加载防火墙规则: {:?}", FIREWALL_FILE, firewall);
+                firewall
+            }
+            Err(_) => Firewall::default(),
+        };
+        firewall.with_validators(validators)
+    }
+
+    pub fn with_validators(mut self, validators: &[usize]) -> Self {
+        self.validators = validators.iter().cloned().collect();
+        self
+    }
+
+    pub fn admits_peer(&self, peer: usize) -> bool {
+        if self.deny_peers.contains(&peer) {
+            return false;
+        }
+        !self.validators_only || self.validators.contains(&peer) || self.allow_peers.contains(&peer)
+    }
+
+    pub fn admits_address(&self, address: IpAddr) -> bool {
+        let address = canonical(address);
+        if self.deny_addresses.contains(&address) {
+            return false;
+        }
+        self.allow_addresses.is_empty() || self.allow_addresses.contains(&address)
+    }
+
+    // 以下返回规则是否有变化
+    pub fn block_peer(&mut self, peer: usize) -> bool {
+        self.deny_peers.insert(peer)
+    }
+
+    pub fn unblock_peer(&mut self, peer: usize) -> bool {
+        self.deny_peers.remove(&peer)
+    }
+
+    pub fn block_address(&mut self, address: IpAddr) -> bool {
+        self.deny_addresses.insert(canonical(address))
+    }
+
+    pub fn unblock_address(&mut self, address: IpAddr) -> bool {
+        self.deny_addresses.remove(&canonical(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::metrics;
+    use crate::network;
+    use crate::testing::TestCluster;
+
+    #[test]
+    fn rules_combine_allow_and_deny_lists() {
+        let mut firewall: Firewall = serde_json::from_str(r#"{"validators_only": true, "allow_peers": [7], "allow_addresses": ["10.0.0.8"]}"#).unwrap();
+        firewall = firewall.with_validators(&[0, 1, 2, 3]);
+        assert!(firewall.admits_peer(2));
+        assert!(firewall.admits_peer(7));
+        assert!(!firewall.admits_peer(9));
+
+        // 封禁优先于验证者身份和放行列表
+        assert!(firewall.block_peer(2));
+        assert!(!firewall.block_peer(2));
+        assert!(!firewall.admits_peer(2));
+        assert!(firewall.unblock_peer(2));
+        assert!(firewall.admits_peer(2));
+
+        let allowed: IpAddr = "10.0.0.8".parse().unwrap();
+        assert!(firewall.admits_address(allowed));
+        assert!(firewall.admits_address("::ffff:10.0.0.8".parse().unwrap()));
+        assert!(!firewall.admits_address("10.0.0.9".parse().unwrap()));
+        firewall.block_address("::ffff:10.0.0.8".parse().unwrap());
+        assert!(!firewall.admits_address(allowed));
+        assert!(firewall.unblock_address(allowed));
+
+        // 没有配置时全部放行
+        assert!(Firewall::default().admits_peer(9));
+        assert!(Firewall::default().admits_address("203.0.113.9".parse().unwrap()));
+    }
+
+    fn received_from(node_id: usize, peer: usize) -> u64 {
+        network::traffic_stats(node_id).get(&peer).map(|t| t.messages_received).unwrap_or(0)
+    }
+
+    // 节点3在运行时封禁主节点0：0的消息在传输层被丢弃，其余三个节点照常提交；解封后消息恢复送达
+    #[tokio::test]
+    async fn blocked_peer_is_dropped_at_the_transport() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let firewall = Arc::new(Mutex::new(Firewall::default()));
+            network::set_firewall(3, firewall.clone());
+            firewall.lock().unwrap().block_peer(0);
+            let before = received_from(3, 0);
+            let rejected = metrics::snapshot().get("firewall_rejected_total").copied().unwrap_or(0);
+
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N - 1).all(|id| c.committed_view(id, "SET k v") == Some(0))
+            }).await;
+            assert!(committed, "封禁一个节点后其余节点未能提交");
+            assert_eq!(received_from(3, 0), before, "被封禁节点的消息仍然送达");
+            assert!(metrics::snapshot().get("firewall_rejected_total").copied().unwrap_or(0) > rejected);
+
+            firewall.lock().unwrap().unblock_peer(0);
+            let restored = cluster.wait_until(Duration::from_secs(5), |_| received_from(3, 0) > before).await;
+            assert!(restored, "解封后节点0的消息仍未送达");
+        }).await;
+    }
+}
@@ -0,0 +1,221 @@
+// src/genesis.rs
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use log::info;
+use crate::config::{self, N};
+use crate::domain;
+use crate::hash::{HashFunction, Hasher};
+use crate::chain::ValidatorSet;
+use crate::features::FeatureSchedule;
+use crate::message::PBFTMessage;
+use crate::signing_policy::SigningPolicy;
+
+pub const DEFAULT_CHAIN_ID: &str = "pbft-devnet";
+pub const GENESIS_FILE: &str = "genesis.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Genesis {
+    pub chain_id: String,
+    #[serde(default = "default_validators")]
+    pub validators: Vec<usize>, // 验证者节点ID，缺省为0..N
+    #[serde(default)]
+    pub hash_function: HashFunction, // 请求摘要、区块哈希、Merkle树和快照使用的哈希函数
+    #[serde(default)]
+    pub features: FeatureSchedule, // 协议特性的激活高度
+    #[serde(default)]
+    pub bridges: Vec<ValidatorSet>, // 跨链桥跟踪的其他链的验证者集合
+    #[serde(default)]
+    pub signing_policy: SigningPolicy, // 各类消息的认证方式，缺省全部签名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>, // 执行引擎运行的复制应用，缺省为键值存储；不写入时共识参数的哈希与旧版本相同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator_key: Option<String>, // 签发验证者公钥清单的运维公钥（十六进制），配置后节点只认清单中的公钥
+}
+
+fn default_validators() -> Vec<usize> {
+    (0..N).collect()
+}
+
+// 没有genesis.json时的创世配置：默认链ID，验证者为0..N，其余各项取缺省值
+impl Default for Genesis {
+    fn default() -> Self {
+        Genesis {
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
+            validators: default_validators(),
+            hash_function: HashFunction::default(),
+            features: FeatureSchedule::default(),
+            bridges: Vec::new(),
+            signing_policy: SigningPolicy::default(),
+            application: None,
+            operator_key: None,
+        }
+    }
+}
+
+impl Genesis {
+    pub fn load() -> Result<Self, String> {
+        // 优先读取创世文件，不存在时使用默认链ID
+        if let Ok(data) = std::fs::read_to_string(GENESIS_FILE) {
+            let genesis: Genesis = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+            info!("从{}加载创世配置，链ID: {}", GENESIS_FILE, genesis.chain_id);
+            Ok(genesis)
+        } else {
+            Ok(Genesis::default())
+        }
+    }
+
+    // 网络魔数：链ID的SHA-256摘要前4个字节，用于传输层握手
+    pub fn network_magic(&self) -> [u8; 4] {
+        let digest = ring::digest::digest(&ring::digest::SHA256, self.chain_id.as_bytes());
+        let mut magic = [0u8; 4];
+        magic.copy_from_slice(&digest.as_ref()[..4]);
+        magic
+    }
+
+    pub fn hasher(&self) -> &'static dyn Hasher {
+        self.hash_function.hasher()
+    }
+
+    // 签名域：所有签名内容都以链ID和域标签为前缀，防止跨链重放和跨类型重放
+    pub fn signing_payload(&self, domain: &str, data: &[u8]) -> Vec<u8> {
+        signing_payload(&self.chain_id, domain, data)
+    }
+
+    // 共识消息的签名内容，域标签由消息类型决定
+    pub fn message_payload(&self, message: &PBFTMessage) -> Vec<u8> {
+        message_payload(&self.chain_id, message)
+    }
+
+    pub fn consensus_parameters(&self) -> ConsensusParameters {
+        ConsensusParameters {
+            genesis: self.clone(),
+            n: N,
+            f: config::F,
+            leader_election: config::LEADER_ELECTION.to_string(),
+            leader_schedule_rounds: config::LEADER_SCHEDULE_ROUNDS,
+            leader_seed_interval: config::LEADER_SEED_INTERVAL,
+            vote_aggregation: config::VOTE_AGGREGATION,
+            max_operation_size: config::MAX_OPERATION_SIZE,
+            gas_base_cost: config::GAS_BASE_COST,
+            gas_per_byte: config::GAS_PER_BYTE,
+            operation_gas_limit: config::OPERATION_GAS_LIMIT,
+            block_gas_limit: config::BLOCK_GAS_LIMIT,
+            operation_write_keys: config::OPERATION_WRITE_KEYS,
+            operation_write_bytes: config::OPERATION_WRITE_BYTES,
+            max_value_size: config::MAX_VALUE_SIZE,
+            max_schedule_delay: config::MAX_SCHEDULE_DELAY,
+            beacon_history: config::BEACON_HISTORY,
+            checkpoint_interval: config::CHECKPOINT_INTERVAL,
+        }
+    }
+}
+
+// 必须在所有节点上一致的配置：创世配置，以及config.rs中影响排序、执行结果或证书的常量。
+// 握手应答携带这份参数，不一致的节点互不认证，运维人员从日志中直接看到哪一项不同
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConsensusParameters {
+    #[serde(flatten)]
+    pub genesis: Genesis,
+    pub n: usize,
+    pub f: usize,
+    pub leader_election: String,
+    pub leader_schedule_rounds: u64,
+    pub leader_seed_interval: u64,
+    pub vote_aggregation: bool,
+    pub max_operation_size: usize,
+    pub gas_base_cost: u64,
+    pub gas_per_byte: u64,
+    pub operation_gas_limit: u64,
+    pub block_gas_limit: u64,
+    pub operation_write_keys: usize,
+    pub operation_write_bytes: usize,
+    pub max_value_size: usize,
+    pub max_schedule_delay: u64,
+    pub beacon_history: u64,
+    pub checkpoint_interval: u64,
+}
+
+impl ConsensusParameters {
+    fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap()
+    }
+
+    // 规范JSON编码的SHA-256摘要，启动日志打印，便于运维人员逐个节点比对
+    pub fn hash(&self) -> String {
+        hex::encode(ring::digest::digest(&ring::digest::SHA256, self.to_value().to_string().as_bytes()))
+    }
+
+    // 与对方参数不同的各项，格式为"名称: 本节点的值 ≠ 对方的值"
+    pub fn differences(&self, other: &ConsensusParameters) -> Vec<String> {
+        let (ours, theirs) = match (self.to_value(), other.to_value()) {
+            (Value::Object(ours), Value::Object(theirs)) => (ours, theirs),
+            _ => return Vec::new(),
+        };
+        let mut names: Vec<&String> = ours.keys().chain(theirs.keys()).collect();
+        names.sort();
+        names.dedup();
+        names.into_iter()
+            .filter(|name| ours.get(*name) != theirs.get(*name))
+            .map(|name| {
+                let show = |value: Option<&Value>| value.map(Value::to_string).unwrap_or_else(|| "缺失".to_string());
+                format!("{}: {} ≠ {}", name, show(ours.get(name)), show(theirs.get(name)))
+            })
+            .collect()
+    }
+}
+
+// 只知道链ID的外部验证方用它重建签名内容：链ID、0字节、域标签、0字节、数据
+pub fn signing_payload(chain_id: &str, domain: &str, data: &[u8]) -> Vec<u8> {
+    domain::tagged(chain_id, &domain::tagged(domain, data))
+}
+
+pub fn message_payload(chain_id: &str, message: &PBFTMessage) -> Vec<u8> {
+    signing_payload(chain_id, &domain::of(message), &serde_json::to_vec(message).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::metrics;
+    use crate::testing::{NodeSetup, TestCluster};
+
+    #[test]
+    fn differences_name_each_mismatched_parameter() {
+        let genesis = Genesis { chain_id: "params-test".to_string(), hash_function: HashFunction::Sha256, ..Genesis::default() };
+        let ours = genesis.consensus_parameters();
+        let mut theirs = ours.clone();
+        assert_eq!(ours.hash(), theirs.hash());
+        assert!(ours.differences(&theirs).is_empty());
+
+        theirs.genesis.hash_function = HashFunction::Blake3;
+        theirs.block_gas_limit += 1;
+        assert_ne!(ours.hash(), theirs.hash());
+        let differences = ours.differences(&theirs);
+        assert_eq!(differences.len(), 2, "{:?}", differences);
+        assert!(differences[0].starts_with("block_gas_limit: "), "{:?}", differences);
+        assert_eq!(differences[1], r#"hash_function: "sha256" ≠ "blake3""#);
+
+        // 经过握手消息的编解码后哈希不变
+        let decoded: ConsensusParameters = serde_json::from_str(&serde_json::to_string(&ours).unwrap()).unwrap();
+        assert_eq!(decoded.hash(), ours.hash());
+    }
+
+    // 节点3的genesis.json改用了另一种哈希函数：握手时双方都拒绝认证，其余节点照常提交，节点3被隔离在共识之外
+    #[tokio::test]
+    async fn peers_refuse_mismatched_parameters() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mismatches = || metrics::snapshot().get("handshake_config_mismatch_total").copied().unwrap_or(0);
+            let before = mismatches();
+            let cluster = TestCluster::builder()
+                .setup(3, NodeSetup { hash_function: Some(HashFunction::Blake3), ..NodeSetup::default() })
+                .build().await;
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..3).all(|id| c.committed_view(id, "SET k v").is_some())).await;
+            assert!(committed, "配置一致的节点未能提交");
+            assert!(mismatches() >= before + 2, "双方都应报告参数不一致");
+            assert!(cluster.committed_view(3, "SET k v").is_none());
+        }).await;
+    }
+}
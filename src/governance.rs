@@ -0,0 +1,237 @@
+// src/governance.rs
+
+// 链上治理：验证者以普通交易提交参数变更提案并投票，赞成的验证者超过2/3后提案通过，
+// 变更在提案指定的高度生效（通过时已超过该高度则立即生效）。提案和票数保存在复制状态中，
+// 各副本执行相同的区块后对哪些变更已生效的判断一致。
+// 投票者的公钥取自节点目录中角色为验证者的条目，验证者需先在目录中登记
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Serialize, Deserialize};
+use crate::crypto::{PublicKey, SigningKey, Signature};
+use crate::directory;
+use crate::domain;
+use crate::features::Feature;
+use crate::node::Role;
+use crate::quota::Sandbox;
+
+// 提案保存在复制状态机中，键为 governance/<提案ID>
+pub const KEY_PREFIX: &str = "governance/";
+pub const GOVERN_COMMAND: &str = "GOVERN";
+pub const MAX_PROPOSAL_ID_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterChange {
+    MaxBatchSize(usize),        // 主节点批大小的上限
+    RequestTimeoutMs(u64),      // 请求超时，也是视图切换的初始等待时间
+    ActivateFeature(Feature),   // 提前激活协议特性
+}
+
+impl ParameterChange {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            ParameterChange::MaxBatchSize(0) => Err("批大小上限必须大于0".to_string()),
+            ParameterChange::RequestTimeoutMs(ms) if *ms < 100 => Err(format!("请求超时{}ms过短，至少为100ms", ms)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Proposal {
+    pub id: String,
+    pub change: ParameterChange,
+    pub activation_height: u64, // 通过后从该高度起生效
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Propose(Proposal), // 提案者自动投赞成票
+    Vote { proposal_id: String },
+}
+
+// 由投票的验证者签名，任何人都可以转发，但无法冒充验证者投票
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedAction {
+    pub node_id: usize,
+    pub action: Action,
+    pub signature: String,
+}
+
+impl SignedAction {
+    pub fn sign(node_id: usize, action: Action, signing_key: &SigningKey) -> Self {
+        let signature = signing_key.sign(&signing_payload(node_id, &action));
+        SignedAction { node_id, action, signature: signature.to_hex() }
+    }
+
+    // 治理操作：GOVERN <签名动作JSON>，随普通请求进入共识
+    pub fn operation(&self) -> String {
+        format!("{} {}", GOVERN_COMMAND, serde_json::to_string(self).unwrap())
+    }
+}
+
+fn signing_payload(node_id: usize, action: &Action) -> Vec<u8> {
+    domain::tagged(domain::GOVERNANCE, &serde_json::to_vec(&(node_id, action)).unwrap())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProposalState {
+    pub proposal: Proposal,
+    pub voters: BTreeSet<usize>,
+    pub passed: bool,
+}
+
+pub fn key(proposal_id: &str) -> String {
+    format!("{}{}", KEY_PREFIX, proposal_id)
+}
+
+// 执行治理操作：校验签名和投票资格，记录提案或选票，票数超过2/3时标记通过
+pub fn apply(store: &mut Sandbox, validators: &[usize], payload: &str) -> Result<Option<String>, String> {
+    let signed: SignedAction = serde_json::from_str(payload).map_err(|e| format!("无法解析治理操作: {}", e))?;
+    if !validators.contains(&signed.node_id) {
+        return Err(format!("节点{}不是验证者，不能参与治理", signed.node_id));
+    }
+    let entry = directory::lookup(store, signed.node_id)
+        .filter(|entry| entry.role == Role::Validator)
+        .ok_or_else(|| format!("验证者{}尚未在节点目录中登记", signed.node_id))?;
+    let public_key = PublicKey::from_hex(&entry.public_key)?;
+    let signature = Signature::from_hex(&signed.signature)?;
+    if !public_key.verify(&signing_payload(signed.node_id, &signed.action), &signature) {
+        return Err("治理操作签名校验失败".to_string());
+    }
+
+    let mut state = match signed.action {
+        Action::Propose(proposal) => {
+            if proposal.id.is_empty() || proposal.id.len() > MAX_PROPOSAL_ID_LEN || proposal.id.contains(char::is_whitespace) {
+                return Err(format!("提案ID'{}'无效", proposal.id));
+            }
+            if store.contains_key(&key(&proposal.id)) {
+                return Err(format!("提案{}已存在", proposal.id));
+            }
+            proposal.change.validate()?;
+            ProposalState { proposal, voters: BTreeSet::new(), passed: false }
+        }
+        Action::Vote { proposal_id } => lookup(store, &proposal_id).ok_or_else(|| format!("提案{}不存在", proposal_id))?,
+    };
+    if !state.voters.insert(signed.node_id) {
+        return Err(format!("验证者{}已对提案{}投票", signed.node_id, state.proposal.id));
+    }
+    // 通过后继续接受投票，但不影响结果
+    state.passed |= state.voters.len() * 3 > validators.len() * 2;
+    store.insert(key(&state.proposal.id), serde_json::to_string(&state).unwrap());
+    Ok(Some(format!("{}/{}{}", state.voters.len(), validators.len(), if state.passed { "，已通过" } else { "" })))
+}
+
+pub fn lookup(store: &BTreeMap<String, String>, proposal_id: &str) -> Option<ProposalState> {
+    serde_json::from_str(store.get(&key(proposal_id))?).ok()
+}
+
+pub fn proposals(store: &BTreeMap<String, String>) -> Vec<ProposalState> {
+    store.range(KEY_PREFIX.to_string()..)
+        .take_while(|(key, _)| key.starts_with(KEY_PREFIX))
+        .filter_map(|(_, data)| serde_json::from_str(data).ok())
+        .collect()
+}
+
+// 在指定高度已生效的提案，按生效高度和提案ID排序，同一参数以后生效的为准
+pub fn active_proposals(store: &BTreeMap<String, String>, height: u64) -> Vec<Proposal> {
+    let mut active: Vec<Proposal> = proposals(store).into_iter()
+        .filter(|state| state.passed && state.proposal.activation_height <= height)
+        .map(|state| state.proposal)
+        .collect();
+    active.sort_by(|a, b| (a.activation_height, &a.id).cmp(&(b.activation_height, &b.id)));
+    active
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::directory::{DirectoryEntry, SignedEntry};
+    use crate::quota::Quota;
+    use crate::testing::TestCluster;
+
+    fn register(store: &mut Sandbox, node_id: usize, key: &SigningKey) {
+        let entry = DirectoryEntry { node_id, addresses: Vec::new(), public_key: key.public_key().to_hex(), role: Role::Validator, sequence: 0 };
+        let signed = SignedEntry::sign(entry, key);
+        directory::apply_registration(store, &serde_json::to_string(&signed).unwrap()).unwrap();
+    }
+
+    fn proposal(id: &str, activation_height: u64) -> Proposal {
+        Proposal { id: id.to_string(), change: ParameterChange::MaxBatchSize(4), activation_height }
+    }
+
+    fn vote(proposal_id: &str) -> Action {
+        Action::Vote { proposal_id: proposal_id.to_string() }
+    }
+
+    fn payload(node_id: usize, action: Action, key: &SigningKey) -> String {
+        serde_json::to_string(&SignedAction::sign(node_id, action, key)).unwrap()
+    }
+
+    #[test]
+    fn proposal_passes_with_two_thirds_of_validators() {
+        let validators: Vec<usize> = (0..4).collect();
+        let keys: Vec<SigningKey> = (0..5).map(|_| SigningKey::generate()).collect();
+        let mut state = BTreeMap::new();
+        let mut store = Sandbox::new(&mut state, Quota::default());
+        for (id, key) in keys.iter().enumerate().take(3) {
+            register(&mut store, id, key);
+        }
+
+        assert!(apply(&mut store, &validators, &payload(0, Action::Propose(proposal("p", 5)), &keys[0])).is_ok());
+        assert!(apply(&mut store, &validators, &payload(0, Action::Propose(proposal("p", 5)), &keys[0])).is_err());
+        assert!(apply(&mut store, &validators, &payload(0, vote("p"), &keys[0])).is_err(), "重复投票");
+        assert!(apply(&mut store, &validators, &payload(1, vote("p"), &keys[0])).is_err(), "签名与登记的公钥不符");
+        assert!(apply(&mut store, &validators, &payload(3, vote("p"), &keys[3])).is_err(), "未登记的验证者");
+        assert!(apply(&mut store, &validators, &payload(4, vote("p"), &keys[4])).is_err(), "非验证者");
+        assert!(apply(&mut store, &validators, &payload(1, vote("missing"), &keys[1])).is_err());
+
+        assert!(apply(&mut store, &validators, &payload(1, vote("p"), &keys[1])).is_ok());
+        assert!(!lookup(&store, "p").unwrap().passed, "2/4不足2/3");
+        assert_eq!(apply(&mut store, &validators, &payload(2, vote("p"), &keys[2])), Ok(Some("3/4，已通过".to_string())));
+        assert!(active_proposals(&store, 4).is_empty());
+        assert_eq!(active_proposals(&store, 5), vec![proposal("p", 5)]);
+
+        let invalid = Proposal { id: "bad".to_string(), change: ParameterChange::MaxBatchSize(0), activation_height: 0 };
+        assert!(apply(&mut store, &validators, &payload(0, Action::Propose(invalid), &keys[0])).is_err());
+    }
+
+    // 逐个提交操作并等待所有节点提交，保证目录登记先于投票执行
+    async fn commit(cluster: &TestCluster, operation: &str) {
+        cluster.submit(operation).await;
+        let committed = cluster.wait_until(Duration::from_secs(10), |c| {
+            (0..N).all(|id| c.committed_view(id, operation).is_some())
+        }).await;
+        assert!(committed, "操作{}未提交", operation);
+    }
+
+    #[tokio::test]
+    async fn validators_vote_a_parameter_change_through_consensus() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            let keys: Vec<SigningKey> = (0..N).map(|_| SigningKey::generate()).collect();
+            for (id, key) in keys.iter().enumerate() {
+                let entry = DirectoryEntry { node_id: id, addresses: Vec::new(), public_key: key.public_key().to_hex(), role: Role::Validator, sequence: 0 };
+                commit(&cluster, &SignedEntry::sign(entry, key).registration_operation()).await;
+            }
+
+            let change = Proposal { id: "timeout".to_string(), change: ParameterChange::RequestTimeoutMs(2000), activation_height: 0 };
+            commit(&cluster, &SignedAction::sign(0, Action::Propose(change.clone()), &keys[0]).operation()).await;
+            commit(&cluster, &SignedAction::sign(1, vote("timeout"), &keys[1]).operation()).await;
+            let state = |c: &TestCluster, id: usize| lookup(c.executions[id].lock().unwrap().state(), "timeout");
+            assert_eq!(state(&cluster, 0).map(|s| (s.voters.len(), s.passed)), Some((2, false)));
+
+            commit(&cluster, &SignedAction::sign(2, vote("timeout"), &keys[2]).operation()).await;
+            let passed = cluster.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|id| {
+                    let execution = c.executions[id].lock().unwrap();
+                    active_proposals(execution.state(), u64::MAX) == vec![change.clone()]
+                })
+            }).await;
+            assert!(passed, "提案未在所有节点上通过");
+        }).await;
+    }
+}
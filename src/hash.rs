@@ -0,0 +1,301 @@
+// src/hash.rs
+
+// 可替换的哈希函数，由创世配置选择。请求摘要、区块哈希、Merkle树和快照清单都使用同一个哈希函数，
+// 网络魔数和本地审计日志不随之改变。SHA3-256和BLAKE3按规范实现（只输出32字节，不支持带密钥模式）
+use std::convert::TryInto;
+use serde::{Serialize, Deserialize};
+use crate::domain;
+
+pub trait Hasher {
+    fn digest(&self, data: &[u8]) -> Vec<u8>;
+
+    fn hex_digest(&self, data: &[u8]) -> String {
+        hex::encode(self.digest(data))
+    }
+
+    // 带域标签的摘要，用途不同的摘要即使输入相同也不会相等
+    fn domain_digest(&self, domain: &str, data: &[u8]) -> String {
+        self.hex_digest(&domain::tagged(domain, data))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashFunction {
+    #[default]
+    #[serde(rename = "sha256")]
+    Sha256,
+    #[serde(rename = "sha3-256")]
+    Sha3_256,
+    #[serde(rename = "blake3")]
+    Blake3,
+}
+
+impl HashFunction {
+    pub fn hasher(self) -> &'static dyn Hasher {
+        match self {
+            HashFunction::Sha256 => &Sha256,
+            HashFunction::Sha3_256 => &Sha3_256,
+            HashFunction::Blake3 => &Blake3,
+        }
+    }
+}
+
+pub struct Sha256;
+
+impl Hasher for Sha256 {
+    fn digest(&self, data: &[u8]) -> Vec<u8> {
+        ring::digest::digest(&ring::digest::SHA256, data).as_ref().to_vec()
+    }
+}
+
+// FIPS 202 SHA3-256：Keccak-f[1600]海绵结构，速率136字节，填充后缀0x06
+pub struct Sha3_256;
+
+const SHA3_RATE: usize = 136;
+
+const KECCAK_ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808A, 0x8000000080008000,
+    0x000000000000808B, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008A, 0x0000000000000088, 0x0000000080008009, 0x000000008000000A,
+    0x000000008000808B, 0x800000000000008B, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800A, 0x800000008000000A,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+];
+const KECCAK_ROTATIONS: [u32; 24] = [1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44];
+const KECCAK_LANES: [usize; 24] = [10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1];
+
+fn keccak_f(state: &mut [u64; 25]) {
+    for round_constant in KECCAK_ROUND_CONSTANTS {
+        // θ
+        let mut columns = [0u64; 5];
+        for (x, column) in columns.iter_mut().enumerate() {
+            *column = state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20];
+        }
+        for x in 0..5 {
+            let t = columns[(x + 4) % 5] ^ columns[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[5 * y + x] ^= t;
+            }
+        }
+        // ρ和π
+        let mut carried = state[1];
+        for (lane, rotation) in KECCAK_LANES.iter().zip(KECCAK_ROTATIONS) {
+            let next = state[*lane];
+            state[*lane] = carried.rotate_left(rotation);
+            carried = next;
+        }
+        // χ
+        for y in 0..5 {
+            let row = [state[5 * y], state[5 * y + 1], state[5 * y + 2], state[5 * y + 3], state[5 * y + 4]];
+            for x in 0..5 {
+                state[5 * y + x] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+        // ι
+        state[0] ^= round_constant;
+    }
+}
+
+impl Hasher for Sha3_256 {
+    fn digest(&self, data: &[u8]) -> Vec<u8> {
+        let mut padded = data.to_vec();
+        padded.push(0x06);
+        let zeros = (SHA3_RATE - padded.len() % SHA3_RATE) % SHA3_RATE;
+        padded.resize(padded.len() + zeros, 0);
+        *padded.last_mut().unwrap() |= 0x80;
+
+        let mut state = [0u64; 25];
+        for block in padded.chunks(SHA3_RATE) {
+            for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+                *lane ^= u64::from_le_bytes(bytes.try_into().unwrap());
+            }
+            keccak_f(&mut state);
+        }
+        state[..4].iter().flat_map(|lane| lane.to_le_bytes()).collect()
+    }
+}
+
+// BLAKE3：1024字节的分块各自压缩为链值，再按二叉树两两合并，根节点加ROOT标志
+pub struct Blake3;
+
+const BLAKE3_IV: [u32; 8] = [0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19];
+const BLAKE3_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+const BLAKE3_CHUNK_LEN: usize = 1024;
+const BLAKE3_BLOCK_LEN: usize = 64;
+const CHUNK_START: u32 = 1;
+const CHUNK_END: u32 = 2;
+const PARENT: u32 = 4;
+const ROOT: u32 = 8;
+
+fn blake3_g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, mx: u32, my: u32) {
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(mx);
+    state[d] = (state[d] ^ state[a]).rotate_right(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(12);
+    state[a] = state[a].wrapping_add(state[b]).wrapping_add(my);
+    state[d] = (state[d] ^ state[a]).rotate_right(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_right(7);
+}
+
+fn blake3_compress(chaining_value: &[u32; 8], block: &[u8], counter: u64, flags: u32) -> [u32; 16] {
+    let mut words = [0u32; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+        let mut padded = [0u8; 4];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        *word = u32::from_le_bytes(padded);
+    }
+
+    let mut state = [0u32; 16];
+    state[..8].copy_from_slice(chaining_value);
+    state[8..12].copy_from_slice(&BLAKE3_IV[..4]);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = block.len() as u32;
+    state[15] = flags;
+
+    for _ in 0..7 {
+        blake3_g(&mut state, 0, 4, 8, 12, words[0], words[1]);
+        blake3_g(&mut state, 1, 5, 9, 13, words[2], words[3]);
+        blake3_g(&mut state, 2, 6, 10, 14, words[4], words[5]);
+        blake3_g(&mut state, 3, 7, 11, 15, words[6], words[7]);
+        blake3_g(&mut state, 0, 5, 10, 15, words[8], words[9]);
+        blake3_g(&mut state, 1, 6, 11, 12, words[10], words[11]);
+        blake3_g(&mut state, 2, 7, 8, 13, words[12], words[13]);
+        blake3_g(&mut state, 3, 4, 9, 14, words[14], words[15]);
+        let previous = words;
+        for (word, source) in words.iter_mut().zip(BLAKE3_PERMUTATION) {
+            *word = previous[source];
+        }
+    }
+    for i in 0..8 {
+        state[i] ^= state[i + 8];
+        state[i + 8] ^= chaining_value[i];
+    }
+    state
+}
+
+fn first_eight(words: [u32; 16]) -> [u32; 8] {
+    let mut chaining_value = [0u32; 8];
+    chaining_value.copy_from_slice(&words[..8]);
+    chaining_value
+}
+
+// 压缩一个分块，root为真时最后一个块带ROOT标志（整个输入只有一个分块）
+fn blake3_chunk(chunk: &[u8], counter: u64, root: bool) -> [u32; 8] {
+    let blocks: Vec<&[u8]> = if chunk.is_empty() { vec![&[]] } else { chunk.chunks(BLAKE3_BLOCK_LEN).collect() };
+    let mut chaining_value = BLAKE3_IV;
+    for (i, block) in blocks.iter().enumerate() {
+        let mut flags = if i == 0 { CHUNK_START } else { 0 };
+        if i == blocks.len() - 1 {
+            flags |= CHUNK_END;
+            if root {
+                flags |= ROOT;
+            }
+        }
+        chaining_value = first_eight(blake3_compress(&chaining_value, block, counter, flags));
+    }
+    chaining_value
+}
+
+// 左子树取严格小于分块总数的最大2的幂个分块
+fn blake3_subtree(chunk_values: &[[u32; 8]], root: bool) -> [u32; 8] {
+    if chunk_values.len() == 1 {
+        return chunk_values[0];
+    }
+    let mut left_len = 1;
+    while left_len * 2 < chunk_values.len() {
+        left_len *= 2;
+    }
+    let left = blake3_subtree(&chunk_values[..left_len], false);
+    let right = blake3_subtree(&chunk_values[left_len..], false);
+    let block: Vec<u8> = left.iter().chain(right.iter()).flat_map(|word| word.to_le_bytes()).collect();
+    let flags = if root { PARENT | ROOT } else { PARENT };
+    first_eight(blake3_compress(&BLAKE3_IV, &block, 0, flags))
+}
+
+impl Hasher for Blake3 {
+    fn digest(&self, data: &[u8]) -> Vec<u8> {
+        let output = if data.len() <= BLAKE3_CHUNK_LEN {
+            blake3_chunk(data, 0, true)
+        } else {
+            let chunk_values: Vec<[u32; 8]> = data.chunks(BLAKE3_CHUNK_LEN).enumerate()
+                .map(|(counter, chunk)| blake3_chunk(chunk, counter as u64, false))
+                .collect();
+            blake3_subtree(&chunk_values, true)
+        };
+        output.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::chain::{self, Chain, CommitCertificate};
+    use crate::genesis::Genesis;
+    use crate::message::Transaction;
+
+    #[test]
+    fn known_answers() {
+        let cases: [(HashFunction, &[u8], &str); 7] = [
+            (HashFunction::Sha256, b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (HashFunction::Sha3_256, b"", "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"),
+            (HashFunction::Sha3_256, b"abc", "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"),
+            (HashFunction::Sha3_256, &[0xa3; 200], "79f38adec5c20307a98ef76e8324afbfd46cfd81b22e3973c65fa1bd9de31787"),
+            (HashFunction::Blake3, b"", "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"),
+            (HashFunction::Blake3, b"abc", "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"),
+            (HashFunction::Sha256, b"", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+        ];
+        for (function, input, expected) in cases {
+            assert_eq!(function.hasher().hex_digest(input), expected, "{:?}({:?})", function, input);
+        }
+    }
+
+    // BLAKE3官方测试向量的输入：第i个字节为i % 251，覆盖多分块和不满的右子树
+    #[test]
+    fn blake3_multi_chunk() {
+        let cases = [
+            (1024, "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7"),
+            (1025, "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444"),
+            (3073, "7124b49501012f81cc7f11ca069ec9226cecb8a2c850cfe644e327d22d3e1cd3"),
+            (8193, "bab6c09cb8ce8cf459261398d2e7aef35700bf488116ceb94a36d0f5f1b7bc3b"),
+            (31745, "5c80ce0c3bbe9a6f432a1c6c2ccbde45923d23249386988a30f512d23919eb98"),
+        ];
+        for (len, expected) in cases {
+            let input: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            assert_eq!(Blake3.hex_digest(&input), expected, "输入长度{}", len);
+        }
+    }
+
+    #[test]
+    fn selected_by_name_in_genesis() {
+        let function: HashFunction = serde_json::from_str("\"blake3\"").unwrap();
+        assert_eq!(function, HashFunction::Blake3);
+        assert_eq!(serde_json::to_string(&HashFunction::Sha3_256).unwrap(), "\"sha3-256\"");
+        assert_eq!(HashFunction::default(), HashFunction::Sha256);
+    }
+
+    // 区块哈希、Merkle根和批次摘要都按创世配置的哈希函数计算，换用其他哈希函数的节点无法验证
+    #[test]
+    fn blocks_verify_only_under_genesis_hash_function() {
+        let genesis = |hash_function| Genesis { chain_id: "hash-test".to_string(), hash_function, ..Genesis::default() };
+        let mut chain = Chain { hash_function: HashFunction::Blake3, ..Chain::default() };
+        for seq in 1..=2 {
+            let transactions = vec![Transaction { operation: format!("SET k{} v", seq), client_id: None, session: None, timestamp: None }];
+            let digest = chain::digest_transactions(&Blake3, &transactions);
+            let certificate = CommitCertificate { view: 0, sequence_number: seq, digest: digest.clone(), signatures: Vec::new() };
+            chain.append(0, seq, digest, transactions, certificate);
+        }
+        let (first, second) = (&chain.blocks[0], &chain.blocks[1]);
+        assert_eq!(second.header.prev_hash, first.header.hash(&Blake3));
+
+        // 证书没有签名，哈希校验通过后才会在证书处失败
+        let no_signatures = chain::verify_block(second, Some(&first.header), &HashMap::new(), &genesis(HashFunction::Blake3)).unwrap_err();
+        assert!(no_signatures.contains("提交证书"), "{}", no_signatures);
+        for other in [HashFunction::Sha256, HashFunction::Sha3_256] {
+            let reason = chain::verify_block(second, Some(&first.header), &HashMap::new(), &genesis(other)).unwrap_err();
+            assert!(reason.contains("哈希") || reason.contains("Merkle"), "{:?}: {}", other, reason);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// src/header_sync.rs
+
+// 区块头优先同步：长时间离线的验证者先只下载区块头和提交证书，逐批校验哈希链接和证书，
+// 很快确定法定人数已提交到的高度和视图，随即进入该视图参与排序；区块体之后经FetchRange分批补齐并执行，
+// 补齐的区块除照常校验外还须与已校验的区块头一致。区块头和证书无需签名，作恶的对端最多拖慢同步，
+// 提供无效区块头时改向下一个对端请求
+use std::collections::{BTreeMap, HashMap};
+use tokio::time::{Duration, Instant};
+use crate::chain::{self, BlockHeader, CommitCertificate};
+use crate::config::{HEADER_SYNC_BATCH, HEADER_SYNC_TIMEOUT_MS, MAX_FETCH_RANGE, N};
+use crate::crypto::PublicKey;
+use crate::genesis::Genesis;
+use crate::hash::Hasher;
+
+pub struct HeaderSync {
+    headers: BTreeMap<u64, BlockHeader>, // 已校验、尚未补齐区块体的区块头
+    tip: Option<BlockHeader>, // 已校验的最高区块头
+    complete: bool, // 已下载到对端的链顶，进入补齐区块体阶段
+    request: Option<(usize, Instant)>, // 在途的区块头请求：对端及发出时间
+    peer: usize, // 下一个请求发给的对端；应答有效时继续向它请求
+    pub started: Instant,
+}
+
+impl HeaderSync {
+    pub fn new(node_id: usize) -> Self {
+        HeaderSync {
+            headers: BTreeMap::new(),
+            tip: None,
+            complete: false,
+            request: None,
+            peer: (node_id + 1) % N,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn tip(&self) -> Option<&BlockHeader> {
+        self.tip.as_ref()
+    }
+
+    // 最近提供有效区块头的对端，区块体先向它请求
+    pub fn peer(&self) -> usize {
+        self.peer
+    }
+
+    // 下一个区块头请求的对端和起始高度；已有请求在途且未超时时返回None
+    pub fn next_request(&mut self, node_id: usize, local_height: u64, now: Instant) -> Option<(usize, u64)> {
+        if self.complete {
+            return None;
+        }
+        if let Some((_, since)) = self.request {
+            if now.duration_since(since) < Duration::from_millis(HEADER_SYNC_TIMEOUT_MS) {
+                return None;
+            }
+            self.skip_peer();
+        }
+        if self.peer == node_id {
+            self.skip_peer();
+        }
+        self.request = Some((self.peer, now));
+        let from_height = self.tip.as_ref().map(|tip| tip.height).unwrap_or(0).max(local_height) + 1;
+        Some((self.peer, from_height))
+    }
+
+    // 对端超时或提供了无效的区块头，改向下一个对端请求
+    fn skip_peer(&mut self) {
+        self.peer = (self.peer + 1) % N;
+    }
+
+    // 校验一批区块头并接在已校验的区块头（或本地链顶）之后，返回接受的个数。
+    // 整批中任一区块头无效时全部丢弃；少于一批说明已到对端的链顶
+    pub fn accept(
+        &mut self,
+        headers: Vec<(BlockHeader, CommitCertificate)>,
+        local_tip: Option<&BlockHeader>,
+        validators: &HashMap<usize, PublicKey>,
+        genesis: &Genesis,
+    ) -> Result<usize, String> {
+        self.request = None;
+        let received = headers.len();
+        let mut prev = match (&self.tip, local_tip) {
+            (Some(tip), Some(local)) if local.height >= tip.height => Some(local.clone()),
+            (Some(tip), _) => Some(tip.clone()),
+            (None, local) => local.cloned(),
+        };
+        let mut verified = Vec::new();
+        for (header, certificate) in headers {
+            if let Err(reason) = chain::verify_header(&header, &certificate, prev.as_ref(), validators, genesis) {
+                self.skip_peer();
+                return Err(format!("高度{}的区块头无效: {}", header.height, reason));
+            }
+            prev = Some(header.clone());
+            verified.push(header);
+        }
+        for header in verified {
+            self.tip = Some(header.clone());
+            self.headers.insert(header.height, header);
+        }
+        if received < HEADER_SYNC_BATCH as usize {
+            self.complete = true;
+        }
+        Ok(received)
+    }
+
+    // 本地链高度为height时尚缺区块体的高度范围，每次最多MAX_FETCH_RANGE个
+    pub fn missing(&self, height: u64) -> Option<(u64, u64)> {
+        let tip = self.tip.as_ref()?.height;
+        (tip > height).then(|| (height + 1, tip.min(height + MAX_FETCH_RANGE)))
+    }
+
+    // 高度不超过height的区块体已补齐，不再保留其区块头
+    pub fn filled(&mut self, height: u64) {
+        self.headers = self.headers.split_off(&(height + 1));
+    }
+
+    // 补齐的区块头须与同一高度已校验的区块头一致
+    pub fn matches(&self, header: &BlockHeader, hasher: &dyn Hasher) -> bool {
+        self.headers.get(&header.height).map_or(true, |known| known.hash(hasher) == header.hash(hasher))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics;
+    use crate::testing::{NodeSetup, TestCluster};
+
+    // 用集群产生的真实区块头校验：篡改、断链的批次被整批拒绝，正常的批次被接受
+    #[tokio::test]
+    async fn verifies_header_batches() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            for i in 0..3 {
+                let operation = format!("SET k{} v", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(5), |c| c.committed_view(0, &operation).is_some()).await;
+                assert!(committed, "{}未提交", operation);
+            }
+            let headers: Vec<(BlockHeader, CommitCertificate)> = cluster.chains[0].lock().unwrap().blocks.iter()
+                .map(|block| (block.header.clone(), block.certificate.clone()))
+                .collect();
+            let validators: HashMap<usize, PublicKey> = cluster.public_keys.clone();
+            let genesis = cluster.genesis.clone();
+
+            let mut sync = HeaderSync::new(3);
+            let mut forged = headers.clone();
+            forged[1].0.digest = "伪造的摘要".to_string();
+            assert!(sync.accept(forged, None, &validators, &genesis).is_err());
+            assert!(sync.accept(headers[1..].to_vec(), None, &validators, &genesis).is_err(), "断链的批次应被拒绝");
+            assert!(sync.tip().is_none() && !sync.is_complete());
+
+            assert_eq!(sync.accept(headers.clone(), None, &validators, &genesis), Ok(headers.len()));
+            assert!(sync.is_complete(), "少于一批的应答说明已到链顶");
+            assert_eq!(sync.tip().map(|tip| tip.height), Some(headers.len() as u64));
+            assert_eq!(sync.missing(1), Some((2, headers.len() as u64)));
+            assert!(sync.matches(&headers[2].0, genesis.hasher()));
+            let mut conflicting = headers[2].0.clone();
+            conflicting.view += 1;
+            assert!(!sync.matches(&conflicting, genesis.hasher()));
+        }).await;
+    }
+
+    // 节点3离线期间集群提交了若干区块；它以--headers-first上线后先校验区块头，再补齐区块体并执行，之后照常参与共识
+    #[tokio::test]
+    async fn offline_validator_syncs_headers_then_bodies() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut cluster = TestCluster::builder()
+                .nodes(3)
+                .setup(3, NodeSetup { configure: Some(|node| node.header_sync = Some(HeaderSync::new(node.id))), ..NodeSetup::default() })
+                .build().await;
+            for i in 0..5 {
+                let operation = format!("SET k{} v", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..3).all(|id| c.committed_view(id, &operation).is_some())).await;
+                assert!(committed, "{}未提交", operation);
+            }
+            let completed = metrics::snapshot().get("header_sync_completed_total").copied().unwrap_or(0);
+
+            cluster.restart(3).await;
+            let synced = cluster.wait_until(Duration::from_secs(10), |c| {
+                c.chains[3].lock().unwrap().height() == c.chains[0].lock().unwrap().height()
+                    && c.executions[3].lock().unwrap().get("k4").is_some()
+            }).await;
+            assert!(synced, "节点3未能补齐区块");
+            let metrics = metrics::snapshot();
+            assert!(metrics.get("header_sync_headers_total").copied().unwrap_or(0) >= 5);
+            assert!(metrics.get("header_sync_completed_total").copied().unwrap_or(0) > completed);
+
+            cluster.submit("SET after sync").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| c.committed_view(3, "SET after sync").is_some()).await;
+            assert!(committed, "同步后的节点3未能提交新的请求");
+        }).await;
+    }
+}
@@ -0,0 +1,175 @@
+// src/key_registry.rs
+
+// 验证者公钥清单：运维人员用运维私钥签发 key_bundle.json，列出各节点ID的公钥，
+// 运维公钥写在genesis.json的operator_key中，随创世配置计入共识参数。
+// 配置了operator_key时，节点启动前核对清单的签名、链ID和是否列出全部验证者，自己的私钥须与清单一致；
+// 启动后预先知道各节点的公钥，握手时对方出示的公钥与清单不同即拒绝认证，不再信任运行时首次握手得到的公钥。
+// 清单在节点之外离线生成：pbft-blockchain keys public 输出私钥文件对应的公钥（文件不存在时生成），
+// keys sign 以运维私钥签发清单，keys verify 按当前目录的genesis.json检查清单
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use zeroize::Zeroizing;
+use crate::crypto::{PublicKey, Signature, SigningKey};
+use crate::domain;
+use crate::genesis::{Genesis, GENESIS_FILE};
+
+pub const KEY_BUNDLE_FILE: &str = "key_bundle.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeyBundle {
+    pub chain_id: String,
+    pub keys: BTreeMap<usize, String>, // 节点ID -> 十六进制公钥
+    pub signature: String, // 运维私钥对链ID和keys的签名
+}
+
+fn signed_content(chain_id: &str, keys: &BTreeMap<usize, String>) -> Vec<u8> {
+    crate::genesis::signing_payload(chain_id, domain::KEY_BUNDLE, &serde_json::to_vec(keys).unwrap())
+}
+
+impl KeyBundle {
+    pub fn sign(chain_id: &str, keys: BTreeMap<usize, String>, operator: &SigningKey) -> Self {
+        let signature = operator.sign(&signed_content(chain_id, &keys)).to_hex();
+        KeyBundle { chain_id: chain_id.to_string(), keys, signature }
+    }
+
+    // 按创世配置核对清单，通过时返回各节点的公钥
+    pub fn verify(&self, genesis: &Genesis) -> Result<HashMap<usize, PublicKey>, String> {
+        let operator = genesis.operator_key.as_deref().ok_or_else(|| format!("{}没有配置operator_key", GENESIS_FILE))?;
+        let operator = PublicKey::from_hex(operator).map_err(|e| format!("operator_key无效: {}", e))?;
+        if self.chain_id != genesis.chain_id {
+            return Err(format!("清单属于链{}，不是{}", self.chain_id, genesis.chain_id));
+        }
+        let signature = Signature::from_hex(&self.signature)?;
+        if !operator.verify(&signed_content(&self.chain_id, &self.keys), &signature) {
+            return Err("清单的签名与operator_key不符".to_string());
+        }
+        let mut keys = HashMap::new();
+        let mut seen = HashSet::new();
+        for (node_id, key) in &self.keys {
+            let key = PublicKey::from_hex(key).map_err(|e| format!("节点{}的公钥无效: {}", node_id, e))?;
+            if !seen.insert(key.to_hex()) {
+                return Err(format!("节点{}与其他节点登记了同一个公钥", node_id));
+            }
+            keys.insert(*node_id, key);
+        }
+        let missing: Vec<usize> = genesis.validators.iter().copied().filter(|id| !keys.contains_key(id)).collect();
+        if !missing.is_empty() {
+            return Err(format!("清单中没有验证者{:?}的公钥", missing));
+        }
+        Ok(keys)
+    }
+}
+
+// 创世配置没有operator_key时返回None，节点照旧在握手时得知对方的公钥
+pub fn load(genesis: &Genesis) -> Result<Option<HashMap<usize, PublicKey>>, String> {
+    if genesis.operator_key.is_none() {
+        return Ok(None);
+    }
+    let data = std::fs::read_to_string(KEY_BUNDLE_FILE).map_err(|e| format!("无法读取{}: {}", KEY_BUNDLE_FILE, e))?;
+    let bundle: KeyBundle = serde_json::from_str(&data).map_err(|e| format!("{}格式无效: {}", KEY_BUNDLE_FILE, e))?;
+    bundle.verify(genesis).map(Some)
+}
+
+// 私钥文件必须已存在，不像节点启动时那样自动生成
+fn read_key(path: &str) -> Result<SigningKey, String> {
+    let secret = Zeroizing::new(std::fs::read_to_string(path).map_err(|e| format!("无法读取{}: {}", path, e))?);
+    let bytes = Zeroizing::new(hex::decode(secret.trim()).map_err(|_| format!("{}不是有效的十六进制", path))?);
+    SigningKey::from_secret_bytes(&bytes)
+}
+
+// 命令行入口：keys public <私钥文件> | sign <清单文件> <运维私钥文件> <节点ID>=<公钥>... | verify [清单文件]
+pub fn run(args: &[String]) -> i32 {
+    match command(args) {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(reason) => {
+            eprintln!("{}", reason);
+            2
+        }
+    }
+}
+
+fn command(args: &[String]) -> Result<String, String> {
+    let usage = "用法: pbft-blockchain keys public <私钥文件> | sign <清单文件> <运维私钥文件> <节点ID>=<公钥>... | verify [清单文件]";
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        ["public", key_file] => Ok(SigningKey::load_or_generate(key_file)?.public_key().to_hex()),
+        ["sign", bundle_file, operator_file, ref entries @ ..] if !entries.is_empty() => {
+            let genesis = Genesis::load()?;
+            let operator = read_key(operator_file)?;
+            if genesis.operator_key.as_deref() != Some(operator.public_key().to_hex().as_str()) {
+                return Err(format!("{}的operator_key应为{}，即运维私钥的公钥", GENESIS_FILE, operator.public_key().to_hex()));
+            }
+            let mut keys = BTreeMap::new();
+            for entry in entries {
+                let (node_id, key) = entry.split_once('=').ok_or_else(|| format!("'{}'应为<节点ID>=<公钥>", entry))?;
+                let node_id = node_id.parse::<usize>().map_err(|_| format!("'{}'不是有效的节点ID", node_id))?;
+                keys.insert(node_id, PublicKey::from_hex(key).map_err(|e| format!("节点{}的公钥无效: {}", node_id, e))?.to_hex());
+            }
+            let bundle = KeyBundle::sign(&genesis.chain_id, keys, &operator);
+            bundle.verify(&genesis)?;
+            std::fs::write(bundle_file, serde_json::to_string_pretty(&bundle).unwrap()).map_err(|e| format!("无法写入{}: {}", bundle_file, e))?;
+            Ok(format!("已签发{}，登记了{}个节点的公钥", bundle_file, bundle.keys.len()))
+        }
+        ["verify"] | ["verify", _] => {
+            let bundle_file = args.get(1).copied().unwrap_or(KEY_BUNDLE_FILE);
+            let data = std::fs::read_to_string(bundle_file).map_err(|e| format!("无法读取{}: {}", bundle_file, e))?;
+            let bundle: KeyBundle = serde_json::from_str(&data).map_err(|e| format!("{}格式无效: {}", bundle_file, e))?;
+            let keys = bundle.verify(&Genesis::load()?)?;
+            Ok(format!("{}有效：链{}，{}个节点的公钥由运维密钥签发", bundle_file, bundle.chain_id, keys.len()))
+        }
+        _ => Err(usage.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    // 清单只在签名、链ID、验证者齐全和公钥互不相同时有效
+    #[test]
+    fn bundles_verify_against_the_genesis_operator_key() {
+        let operator = SigningKey::generate();
+        let nodes: Vec<SigningKey> = (0..N).map(|_| SigningKey::generate()).collect();
+        let keys: BTreeMap<usize, String> = nodes.iter().enumerate().map(|(id, key)| (id, key.public_key().to_hex())).collect();
+        let mut genesis: Genesis = serde_json::from_str(r#"{"chain_id": "keys-test"}"#).unwrap();
+        assert_eq!(load(&genesis), Ok(None), "没有operator_key时不读取清单");
+        genesis.operator_key = Some(operator.public_key().to_hex());
+
+        let bundle = KeyBundle::sign("keys-test", keys.clone(), &operator);
+        let verified = bundle.verify(&genesis).unwrap();
+        assert!((0..N).all(|id| verified[&id] == nodes[id].public_key()));
+
+        let mut tampered = bundle.clone();
+        tampered.keys.insert(1, SigningKey::generate().public_key().to_hex());
+        assert!(tampered.verify(&genesis).unwrap_err().contains("签名"));
+        let forged = KeyBundle::sign("keys-test", keys.clone(), &SigningKey::generate());
+        assert!(forged.verify(&genesis).unwrap_err().contains("签名"));
+        let other_chain = KeyBundle::sign("other-chain", keys.clone(), &operator);
+        assert!(other_chain.verify(&genesis).unwrap_err().contains("other-chain"));
+        let mut partial = keys.clone();
+        partial.remove(&2);
+        assert!(KeyBundle::sign("keys-test", partial, &operator).verify(&genesis).unwrap_err().contains("[2]"));
+        let mut duplicated = keys;
+        duplicated.insert(3, duplicated[&0].clone());
+        assert!(KeyBundle::sign("keys-test", duplicated, &operator).verify(&genesis).is_err());
+    }
+
+    // 清单中节点0登记的不是它实际使用的公钥：其余节点拒绝它的握手，它作为主节点提议不了任何请求，
+    // 其余节点切换视图后照常提交
+    #[tokio::test]
+    async fn cluster_refuses_keys_outside_the_bundle() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().key_bundle(&[0]).build().await;
+            cluster.submit("SET bundled yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(10), |c| (1..N).all(|id| c.committed_view(id, "SET bundled yes").is_some())).await;
+            assert!(committed, "清单中的节点未能提交");
+            assert!((1..N).all(|id| cluster.committed_view(id, "SET bundled yes") >= Some(1)), "节点0不应能以主节点身份提议");
+        }).await;
+    }
+}
@@ -0,0 +1,282 @@
+// src/latency_budget.rs
+
+// 提交延迟预算：记录每个共识实例（视图 + 序列号）各阶段的时间点——收到（或发出）PrePrepare、Prepared、
+// 提交和执行完毕——以及每个对等节点的Prepare和Commit到达的时间。实例从PrePrepare到执行完毕超过预算时，
+// 把各阶段耗时、凑齐法定票数的最后一票来自哪个节点、哪些节点的票在此之后才到或一直没到
+// 追加到 node_<ID>_slow.jsonl，并记入日志和指标。预算在node_config.json的latency_budget_ms中配置
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use log::{error, warn};
+use serde::{Serialize, Deserialize};
+use tokio::time::{Duration, Instant};
+use crate::config::{LATENCY_BUDGET_MS, LATENCY_TRACKED_INSTANCES};
+use crate::metrics;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vote {
+    Prepare,
+    Commit,
+}
+
+// 一个实例在本节点上的时间点，投票可能先于PrePrepare到达
+#[derive(Debug, Clone, Default)]
+struct InstanceTiming {
+    primary: usize,
+    accepted: Option<Instant>,
+    prepared: Option<Instant>,
+    committed: Option<Instant>,
+    prepares: BTreeMap<usize, Instant>,
+    commits: BTreeMap<usize, Instant>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VoteArrival {
+    pub node: usize,
+    pub ms: u64, // 相对阶段开始的到达时间，早于阶段开始到达的记为0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PhaseTiming {
+    pub phase: String, // Prepare、Commit或Execute
+    pub ms: u64,
+    pub quorum_by: Option<usize>, // 阶段结束前到达的最后一票，即凑齐法定票数的那一票
+    pub votes: Vec<VoteArrival>, // 按到达先后排列
+    pub missing: Vec<usize>, // 执行完毕时仍未到达的投票
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SlowInstance {
+    pub node_id: usize,
+    pub time: String, // RFC 3339
+    pub view: u64,
+    pub sequence_number: u64,
+    pub primary: usize,
+    pub budget_ms: u64,
+    pub total_ms: u64,
+    pub slowest_phase: String,
+    pub phases: Vec<PhaseTiming>,
+    pub late_peers: Vec<usize>, // 拖慢实例的节点：在耗时较多的阶段凑齐法定票数或票到得更晚、没有到的节点
+}
+
+fn elapsed_ms(from: Instant, to: Instant) -> u64 {
+    to.saturating_duration_since(from).as_millis() as u64
+}
+
+// 一个投票阶段：from到to之间，voters中每个节点的票到达的时间
+fn vote_phase(phase: &str, from: Instant, to: Instant, arrivals: &BTreeMap<usize, Instant>, voters: &[usize]) -> (PhaseTiming, Vec<usize>) {
+    let mut votes: Vec<(usize, Instant)> = voters.iter().filter_map(|node| arrivals.get(node).map(|at| (*node, *at))).collect();
+    votes.sort_by_key(|(node, at)| (*at, *node));
+    let quorum_by = votes.iter().rev().find(|(_, at)| *at <= to).map(|(node, _)| *node);
+    let missing: Vec<usize> = voters.iter().copied().filter(|node| !arrivals.contains_key(node)).collect();
+    let mut late: Vec<usize> = quorum_by.into_iter().collect();
+    late.extend(votes.iter().filter(|(_, at)| *at > to).map(|(node, _)| *node));
+    late.extend(&missing);
+    let timing = PhaseTiming {
+        phase: phase.to_string(),
+        ms: elapsed_ms(from, to),
+        quorum_by,
+        votes: votes.into_iter().map(|(node, at)| VoteArrival { node, ms: elapsed_ms(from, at) }).collect(),
+        missing,
+    };
+    (timing, late)
+}
+
+pub struct LatencyBudget {
+    node_id: usize,
+    path: PathBuf,
+    budget: Option<Duration>, // None为不检查
+    instances: BTreeMap<(u64, u64), InstanceTiming>, // (序列号, 视图) -> 时间点
+}
+
+impl LatencyBudget {
+    pub fn new(node_id: usize) -> Self {
+        LatencyBudget {
+            node_id,
+            path: PathBuf::from(format!("node_{}_slow.jsonl", node_id)),
+            budget: Some(Duration::from_millis(LATENCY_BUDGET_MS)),
+            instances: BTreeMap::new(),
+        }
+    }
+
+    // 0为不检查
+    pub fn set_budget(&mut self, ms: u64) {
+        self.budget = (ms > 0).then(|| Duration::from_millis(ms));
+    }
+
+    // 最多跟踪LATENCY_TRACKED_INSTANCES个实例，超出时淘汰序列号最低的
+    fn instance(&mut self, view: u64, sequence_number: u64) -> &mut InstanceTiming {
+        if !self.instances.contains_key(&(sequence_number, view)) && self.instances.len() >= LATENCY_TRACKED_INSTANCES {
+            self.instances.pop_first();
+        }
+        self.instances.entry((sequence_number, view)).or_default()
+    }
+
+    pub fn accepted(&mut self, view: u64, sequence_number: u64, primary: usize, now: Instant) {
+        let instance = self.instance(view, sequence_number);
+        instance.primary = primary;
+        instance.accepted.get_or_insert(now);
+    }
+
+    pub fn vote(&mut self, vote: Vote, view: u64, sequence_number: u64, sender_id: usize, now: Instant) {
+        let instance = self.instance(view, sequence_number);
+        let arrivals = match vote {
+            Vote::Prepare => &mut instance.prepares,
+            Vote::Commit => &mut instance.commits,
+        };
+        arrivals.entry(sender_id).or_insert(now);
+    }
+
+    pub fn prepared(&mut self, view: u64, sequence_number: u64, now: Instant) {
+        self.instance(view, sequence_number).prepared.get_or_insert(now);
+    }
+
+    pub fn committed(&mut self, view: u64, sequence_number: u64, now: Instant) {
+        self.instance(view, sequence_number).committed.get_or_insert(now);
+    }
+
+    // 实例执行完毕：记录各阶段耗时，超出预算时写入诊断。该序列号及更早的实例不再跟踪
+    pub fn executed(&mut self, view: u64, sequence_number: u64, validators: &[usize], now: Instant) {
+        let instance = self.instances.remove(&(sequence_number, view));
+        self.instances.retain(|(seq, _), _| *seq > sequence_number);
+        let instance = match instance {
+            Some(instance) if instance.accepted.is_some() => instance,
+            _ => return, // 经补齐或状态同步得到的区块，本节点没有参与投票
+        };
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return,
+        };
+        let slow = match diagnose(self.node_id, view, sequence_number, &instance, validators, budget, now) {
+            Some(slow) => slow,
+            None => return,
+        };
+        warn!("节点{}的实例（视图{}，序列号{}）耗时{}ms，超过预算{}ms，最慢的阶段是{}，拖慢的节点: {:?}",
+            self.node_id, view, sequence_number, slow.total_ms, slow.budget_ms, slow.slowest_phase, slow.late_peers);
+        metrics::inc_counter("slow_instances_total", 1);
+        metrics::inc_counter(&format!("slow_instance_phase_total{{phase=\"{}\"}}", slow.slowest_phase), 1);
+        for peer in &slow.late_peers {
+            metrics::inc_counter(&format!("late_votes_total{{peer=\"{}\"}}", peer), 1);
+        }
+        if let Err(e) = self.append(&slow) {
+            error!("保存序列号{}的慢实例诊断失败: {}", sequence_number, e);
+        }
+    }
+
+    fn append(&self, record: &SlowInstance) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record).unwrap())
+    }
+}
+
+// 各阶段耗时计入直方图；总耗时超出预算时生成诊断记录。
+// 不计本节点自己的票，主节点以PrePrepare代替Prepare
+fn diagnose(node_id: usize, view: u64, sequence_number: u64, instance: &InstanceTiming, validators: &[usize], budget: Duration, now: Instant) -> Option<SlowInstance> {
+    let accepted = instance.accepted?;
+    let prepared = instance.prepared.unwrap_or(accepted).max(accepted);
+    let committed = instance.committed.unwrap_or(now).max(prepared);
+    let peers: Vec<usize> = validators.iter().copied().filter(|node| *node != node_id).collect();
+    let preparers: Vec<usize> = peers.iter().copied().filter(|node| *node != instance.primary).collect();
+    let (prepare, prepare_late) = vote_phase("Prepare", accepted, prepared, &instance.prepares, &preparers);
+    let (commit, commit_late) = vote_phase("Commit", prepared, committed, &instance.commits, &peers);
+    let execute = PhaseTiming { phase: "Execute".to_string(), ms: elapsed_ms(committed, now), quorum_by: None, votes: Vec::new(), missing: Vec::new() };
+    for phase in [&prepare, &commit, &execute] {
+        metrics::observe(&format!("instance_phase_ms{{phase=\"{}\"}}", phase.phase), phase.ms);
+    }
+    let total = now.saturating_duration_since(accepted);
+    if total <= budget {
+        return None;
+    }
+    // 耗时不到预算三分之一的阶段不是变慢的原因，其中的投票不计为迟到
+    let significant = budget.as_millis() as u64 / 3;
+    let mut late_peers: Vec<usize> = Vec::new();
+    for (phase, late) in [(&prepare, prepare_late), (&commit, commit_late)] {
+        if phase.ms >= significant {
+            late_peers.extend(late);
+        }
+    }
+    late_peers.sort_unstable();
+    late_peers.dedup();
+    let slowest_phase = [&prepare, &commit, &execute].iter().max_by_key(|phase| phase.ms).map(|phase| phase.phase.clone()).unwrap_or_default();
+    Some(SlowInstance {
+        node_id,
+        time: chrono::Local::now().to_rfc3339(),
+        view,
+        sequence_number,
+        primary: instance.primary,
+        budget_ms: budget.as_millis() as u64,
+        total_ms: total.as_millis() as u64,
+        slowest_phase,
+        phases: vec![prepare, commit, execute],
+        late_peers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::N;
+    use crate::testing::{NodeSetup, TestCluster};
+
+    // 节点3的Prepare在法定票数凑齐后才到，节点2的Commit凑齐了法定票数，节点3的Commit一直没到
+    #[test]
+    fn diagnoses_the_votes_that_held_up_an_instance() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut budget = LatencyBudget::new(0);
+        budget.path = std::env::temp_dir().join(format!("pbft-slow-{}-{}.jsonl", std::process::id(), rand::random::<u32>()));
+        budget.vote(Vote::Prepare, 0, 1, 2, at(0));
+        budget.accepted(0, 1, 1, at(5));
+        budget.vote(Vote::Prepare, 0, 1, 3, at(400));
+        budget.prepared(0, 1, at(10));
+        budget.vote(Vote::Commit, 0, 1, 1, at(20));
+        budget.vote(Vote::Commit, 0, 1, 2, at(300));
+        budget.committed(0, 1, at(300));
+
+        let instance = budget.instances[&(1, 0)].clone();
+        let slow = diagnose(0, 0, 1, &instance, &[0, 1, 2, 3], Duration::from_millis(200), at(310)).unwrap();
+        assert_eq!(slow.total_ms, 305);
+        assert_eq!(slow.slowest_phase, "Commit");
+        assert_eq!(slow.phases[0].quorum_by, Some(2));
+        assert_eq!(slow.phases[0].votes, vec![VoteArrival { node: 2, ms: 0 }, VoteArrival { node: 3, ms: 395 }]);
+        assert_eq!(slow.phases[1].quorum_by, Some(2));
+        assert_eq!(slow.phases[1].missing, vec![3]);
+        assert_eq!(slow.late_peers, vec![2, 3], "Prepare阶段只用了5ms，其中的投票不计为迟到");
+        assert!(diagnose(0, 0, 1, &instance, &[0, 1, 2, 3], Duration::from_millis(400), at(310)).is_none());
+
+        budget.set_budget(200);
+        budget.accepted(0, 2, 1, at(320));
+        budget.executed(0, 1, &[0, 1, 2, 3], at(310));
+        assert_eq!(budget.instances.keys().copied().collect::<Vec<_>>(), vec![(2, 0)]);
+        let data = std::fs::read_to_string(&budget.path).unwrap();
+        let logged: SlowInstance = serde_json::from_str(data.trim()).unwrap();
+        assert_eq!(logged.late_peers, slow.late_peers);
+        std::fs::remove_file(&budget.path).unwrap();
+    }
+
+    // 节点2和3的消息都延迟200ms：主节点0上每个实例都要等它们之一的票才能凑齐法定票数，超出100ms的预算
+    #[tokio::test]
+    async fn cluster_records_instances_over_budget() {
+        tokio::task::LocalSet::new().run_until(async {
+            let slow = NodeSetup { latency: Duration::from_millis(200), ..Default::default() };
+            let cluster = TestCluster::builder()
+                .setup(0, NodeSetup { configure: Some(|node| node.latency_budget.set_budget(100)), ..Default::default() })
+                .setup(2, slow.clone())
+                .setup(3, slow)
+                .build().await;
+            cluster.submit("SET slow yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(10), |c| (0..N).all(|id| c.committed_view(id, "SET slow yes").is_some())).await;
+            assert!(committed, "请求未提交");
+            let recorded = cluster.wait_until(Duration::from_secs(2), |_| std::path::Path::new("node_0_slow.jsonl").exists()).await;
+            assert!(recorded, "超出预算的实例没有记录诊断");
+
+            let data = std::fs::read_to_string("node_0_slow.jsonl").unwrap();
+            let slow: SlowInstance = serde_json::from_str(data.lines().next().unwrap()).unwrap();
+            assert!(slow.total_ms > 100, "{:?}", slow);
+            assert_eq!(slow.primary, 0);
+            assert!(slow.late_peers.iter().any(|peer| *peer == 2 || *peer == 3), "{:?}", slow);
+            assert!(!slow.late_peers.contains(&1), "节点1的投票没有延迟: {:?}", slow);
+            assert!(!std::path::Path::new("node_1_slow.jsonl").exists(), "节点1的预算缺省为{}ms", LATENCY_BUDGET_MS);
+        }).await;
+    }
+}
@@ -0,0 +1,288 @@
+// src/leader.rs
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::beacon;
+use crate::config::{N, LEADER_ELECTION, LEADER_SCHEDULE_ROUNDS, LEADER_SEED_INTERVAL};
+use crate::execution::ExecutionEngine;
+
+// 每个节点作为主节点时的表现
+#[derive(Debug, Clone, Default)]
+pub struct LeaderStats {
+    pub commits: u64,
+    pub avg_latency_ms: f64, // 提议到提交的平均延迟（指数加权）
+    pub failures: u64,       // 担任主节点的视图在没有提交任何区块的情况下被切换
+}
+
+#[derive(Default)]
+pub struct PerformanceTracker {
+    stats: HashMap<usize, LeaderStats>,
+    blacklisted: HashSet<usize>,
+    departed: HashSet<usize>, // 宣布计划退出、尚未重新上线的验证者
+    reputation: HashMap<usize, f64>, // 信誉分数占满分的比例，未记录的节点为1
+    committed_in_view: bool,
+}
+
+impl PerformanceTracker {
+    pub fn record_commit(&mut self, leader: usize, latency: Duration) {
+        let stats = self.stats.entry(leader).or_default();
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        stats.avg_latency_ms = if stats.commits == 0 {
+            latency_ms
+        } else {
+            0.8 * stats.avg_latency_ms + 0.2 * latency_ms
+        };
+        stats.commits += 1;
+        self.committed_in_view = true;
+    }
+
+    // 离开某个视图时调用：该视图的主节点没有提交任何区块则记一次失败
+    pub fn view_ended(&mut self, leader: usize) {
+        if !self.committed_in_view {
+            self.stats.entry(leader).or_default().failures += 1;
+        }
+        self.committed_in_view = false;
+    }
+
+    pub fn mark_blacklisted(&mut self, node_id: usize) {
+        self.blacklisted.insert(node_id);
+    }
+
+    pub fn clear_blacklisted(&mut self, node_id: usize) {
+        self.blacklisted.remove(&node_id);
+    }
+
+    pub fn mark_departed(&mut self, node_id: usize) {
+        self.departed.insert(node_id);
+    }
+
+    pub fn clear_departed(&mut self, node_id: usize) {
+        self.departed.remove(&node_id);
+    }
+
+    pub fn set_reputation(&mut self, node_id: usize, share: f64) {
+        self.reputation.insert(node_id, share);
+    }
+
+    pub fn stats(&self, node_id: usize) -> LeaderStats {
+        self.stats.get(&node_id).cloned().unwrap_or_default()
+    }
+
+    // 调度权重：越慢、失败越多、信誉越低权重越低，黑名单节点和已退出的节点为0
+    pub fn weights(&self) -> Vec<f64> {
+        (0..N).map(|id| {
+            if self.blacklisted.contains(&id) || self.departed.contains(&id) {
+                return 0.0;
+            }
+            let stats = self.stats(id);
+            let reputation = self.reputation.get(&id).copied().unwrap_or(1.0);
+            reputation / ((1.0 + stats.avg_latency_ms / 1000.0) * (1.0 + stats.failures as f64))
+        }).collect()
+    }
+}
+
+pub trait LeaderElection: Send {
+    fn leader(&self, view: u64) -> usize;
+
+    // 进入新视图时调用，策略可根据观测到的表现更新调度
+    fn on_new_view(&mut self, _view: u64, _tracker: &PerformanceTracker) {}
+}
+
+// 按配置选择选举策略；VRF选举从执行状态读取随机信标
+pub fn from_config(chain_id: &str, execution: Arc<Mutex<ExecutionEngine>>) -> Box<dyn LeaderElection> {
+    match LEADER_ELECTION {
+        "weighted" => Box::new(PerformanceWeighted::new()),
+        "vrf" => Box::new(VrfElection::new(chain_id, execution)),
+        _ => Box::new(RoundRobin),
+    }
+}
+
+// 默认策略：视图编号对N取模
+pub struct RoundRobin;
+
+impl LeaderElection for RoundRobin {
+    fn leader(&self, view: u64) -> usize {
+        view as usize % N
+    }
+}
+
+// 按表现加权的调度。视图被划分为长度为 N * LEADER_SCHEDULE_ROUNDS 的窗口，
+// 每个窗口内每个节点至少担任一次主节点（有界的不公平），其余位置按权重分配。
+// 权重只在窗口边界更新，同一窗口内的调度保持不变；各节点须基于一致的观测才能得到相同的调度
+pub struct PerformanceWeighted {
+    window: u64,
+    weights: Vec<f64>,
+    schedule: Vec<usize>,
+}
+
+impl PerformanceWeighted {
+    pub fn new() -> Self {
+        let weights = vec![1.0; N];
+        PerformanceWeighted { window: 0, schedule: schedule(&weights), weights }
+    }
+}
+
+impl LeaderElection for PerformanceWeighted {
+    fn leader(&self, view: u64) -> usize {
+        let window_len = self.schedule.len() as u64;
+        if view / window_len == self.window {
+            self.schedule[(view % window_len) as usize]
+        } else {
+            schedule(&self.weights)[(view % window_len) as usize]
+        }
+    }
+
+    fn on_new_view(&mut self, view: u64, tracker: &PerformanceTracker) {
+        let window = view / self.schedule.len() as u64;
+        if window != self.window {
+            self.window = window;
+            self.weights = tracker.weights();
+            self.schedule = schedule(&self.weights);
+        }
+    }
+}
+
+// 由随机信标决定的调度。信标由各区块主节点的VRF输出串联而成（见beacon.rs），在对应区块提交前无法预测，
+// 攻击者不能提前很久锁定之后的主节点发起定向DoS。视图同样划分为长度为 N * LEADER_SCHEDULE_ROUNDS 的窗口，
+// 进入新窗口时取已执行的、高度为LEADER_SEED_INTERVAL整数倍的最近一个信标作为种子，视图v的主节点为
+//   SHA-256("pbft-leader" || 种子 || v) 对候选节点数取模
+// 候选节点为进入窗口时未被拉黑的验证者。与加权调度一样，各节点须在窗口边界执行到同一个种子高度才能得到相同的调度，
+// 不一致只影响活性（多一次视图切换），不影响安全性
+pub struct VrfElection {
+    chain_id: String,
+    execution: Arc<Mutex<ExecutionEngine>>,
+    window: u64,
+    seed: String,
+    candidates: Vec<usize>,
+}
+
+impl VrfElection {
+    pub fn new(chain_id: &str, execution: Arc<Mutex<ExecutionEngine>>) -> Self {
+        let seed = seed(execution.lock().unwrap().state(), chain_id);
+        VrfElection { chain_id: chain_id.to_string(), execution, window: 0, seed, candidates: (0..N).collect() }
+    }
+}
+
+fn window_len() -> u64 {
+    N as u64 * LEADER_SCHEDULE_ROUNDS
+}
+
+// 最近一个高度为LEADER_SEED_INTERVAL整数倍的信标；尚无这样的区块时为创世信标
+fn seed(store: &BTreeMap<String, String>, chain_id: &str) -> String {
+    let latest = beacon::latest(store, chain_id);
+    let epoch = latest.height / LEADER_SEED_INTERVAL * LEADER_SEED_INTERVAL;
+    match beacon::get(store, epoch) {
+        Some(entry) if epoch > 0 => entry.value,
+        _ => beacon::latest(&BTreeMap::new(), chain_id).value,
+    }
+}
+
+fn vrf_leader(seed: &str, view: u64, candidates: &[usize]) -> usize {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    context.update(b"pbft-leader\0");
+    context.update(seed.as_bytes());
+    context.update(&view.to_be_bytes());
+    let digest = context.finish();
+    let value = u64::from_be_bytes(digest.as_ref()[..8].try_into().unwrap());
+    candidates[(value % candidates.len() as u64) as usize]
+}
+
+impl LeaderElection for VrfElection {
+    // 窗口外的视图按当前种子预测，进入该窗口时可能改变
+    fn leader(&self, view: u64) -> usize {
+        vrf_leader(&self.seed, view, &self.candidates)
+    }
+
+    fn on_new_view(&mut self, view: u64, tracker: &PerformanceTracker) {
+        let window = view / window_len();
+        if window != self.window {
+            self.window = window;
+            self.seed = seed(self.execution.lock().unwrap().state(), &self.chain_id);
+            let candidates: Vec<usize> = tracker.weights().iter().enumerate().filter(|(_, w)| **w > 0.0).map(|(id, _)| id).collect();
+            self.candidates = if candidates.is_empty() { (0..N).collect() } else { candidates };
+        }
+    }
+}
+
+// 每个节点先保证一个位置，剩余位置按权重以最大余数法分配，
+// 再用平滑加权轮询交错排列，避免同一节点连续担任主节点
+pub fn schedule(weights: &[f64]) -> Vec<usize> {
+    let window_len = weights.len() * LEADER_SCHEDULE_ROUNDS as usize;
+    let extra = window_len - weights.len();
+    let total: f64 = weights.iter().sum();
+
+    let mut counts = vec![1usize; weights.len()];
+    if total > 0.0 {
+        let shares: Vec<f64> = weights.iter().map(|w| w / total * extra as f64).collect();
+        let mut assigned = 0;
+        for (count, share) in counts.iter_mut().zip(&shares) {
+            *count += share.floor() as usize;
+            assigned += share.floor() as usize;
+        }
+        let mut by_remainder: Vec<usize> = (0..weights.len()).collect();
+        by_remainder.sort_by(|a, b| {
+            let (ra, rb) = (shares[*a] - shares[*a].floor(), shares[*b] - shares[*b].floor());
+            rb.partial_cmp(&ra).unwrap().then(a.cmp(b))
+        });
+        for id in by_remainder.into_iter().take(extra - assigned) {
+            counts[id] += 1;
+        }
+    } else {
+        // 所有节点权重都为0时退化为轮换
+        for i in 0..extra {
+            counts[i % weights.len()] += 1;
+        }
+    }
+
+    let mut current = vec![0i64; counts.len()];
+    let mut order = Vec::with_capacity(window_len);
+    for _ in 0..window_len {
+        for (c, count) in current.iter_mut().zip(&counts) {
+            *c += *count as i64;
+        }
+        let mut best = 0;
+        for id in 1..current.len() {
+            if current[id] > current[best] {
+                best = id;
+            }
+        }
+        current[best] -= window_len as i64;
+        order.push(best);
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vrf_schedule_follows_epoch_beacon() {
+        let execution = Arc::new(Mutex::new(ExecutionEngine::new()));
+        let mut election = VrfElection::new("", execution.clone());
+        let replica = VrfElection::new("", execution.clone());
+        let schedule = |election: &VrfElection| (0..window_len()).map(|view| election.leader(view)).collect::<Vec<usize>>();
+        let initial = schedule(&election);
+        assert_eq!(initial, schedule(&replica), "相同的信标应得到相同的调度");
+        assert!(initial.iter().collect::<HashSet<_>>().len() > 1, "调度不应固定为同一节点");
+
+        // 种子只在窗口边界更新，且只采用高度为LEADER_SEED_INTERVAL整数倍的信标
+        for height in 1..LEADER_SEED_INTERVAL {
+            execution.lock().unwrap().execute_block(height, &[]);
+        }
+        let mut tracker = PerformanceTracker::default();
+        election.on_new_view(window_len(), &tracker);
+        assert_eq!(election.seed, replica.seed);
+        execution.lock().unwrap().execute_block(LEADER_SEED_INTERVAL, &[]);
+        election.on_new_view(window_len() + 1, &tracker);
+        assert_eq!(election.seed, replica.seed);
+        execution.lock().unwrap().execute_block(LEADER_SEED_INTERVAL + 1, &[]);
+        tracker.mark_blacklisted(1);
+        election.on_new_view(2 * window_len(), &tracker);
+        let epoch = beacon::get(execution.lock().unwrap().state(), LEADER_SEED_INTERVAL).unwrap();
+        assert_eq!(election.seed, epoch.value);
+        assert!((0..10 * window_len()).all(|view| election.leader(view) != 1), "黑名单节点不应担任主节点");
+    }
+}
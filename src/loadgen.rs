@@ -0,0 +1,362 @@
+// src/loadgen.rs
+
+// 负载生成器：在本进程内启动N个节点组成的集群，按负载配置提交请求，收到F+1个一致的答复即视为完成，
+// 最后报告吞吐量和延迟分位数。延迟从计划发送时刻算起，集群跟不上时排队的时间也计入延迟，
+// 不会因为发送被阻塞而少算（协调遗漏）。用于手工调优批处理参数，也用于CI中的性能基准
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use rand::Rng;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use crate::byzantine::Strategy;
+use crate::config::{F, LOADGEN_DRAIN_MS, LOADGEN_REPLY_QUEUE_SIZE, N};
+use crate::crypto::SigningKey;
+use crate::genesis::Genesis;
+use crate::message::{PBFTMessage, ReplyOutcome};
+use crate::network::{self, register_node};
+use crate::node::Node;
+use crate::qos::Priority;
+
+// 请求到达的时间分布
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    Constant,            // 固定间隔
+    Poisson,             // 间隔服从指数分布
+    Bursty { size: usize }, // 每次同时到达size个请求，突发之间的间隔保持平均速率
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workload {
+    pub profile: Profile,
+    pub rate: f64,               // 平均每秒请求数
+    pub duration: Duration,      // 发送请求的时长
+    pub payload: (usize, usize), // SET写入的值的字节数范围（含两端）
+    pub read_ratio: f64,         // GET请求的比例，读取此前写入过的键
+}
+
+impl Workload {
+    // loadgen [--profile constant|poisson|bursty] [--rate 100] [--duration-s 10] [--burst 20]
+    //         [--payload 16-256] [--read-ratio 0.2]
+    pub fn parse(args: &[String]) -> Result<Workload, String> {
+        let flag = |name: &str| args.iter().position(|s| s == name).and_then(|i| args.get(i + 1));
+        let number = |name: &str, default: f64| -> Result<f64, String> {
+            match flag(name) {
+                Some(value) => value.parse().map_err(|_| format!("{}的值'{}'无效", name, value)),
+                None => Ok(default),
+            }
+        };
+
+        let burst = number("--burst", 20.0)? as usize;
+        let profile = match flag("--profile").map(|s| s.as_str()).unwrap_or("constant") {
+            "constant" => Profile::Constant,
+            "poisson" => Profile::Poisson,
+            "bursty" if burst > 0 => Profile::Bursty { size: burst },
+            "bursty" => return Err("--burst必须大于0".to_string()),
+            other => return Err(format!("未知的负载配置'{}'，可选constant、poisson、bursty", other)),
+        };
+        let rate = number("--rate", 100.0)?;
+        let duration = number("--duration-s", 10.0)?;
+        if rate <= 0.0 || duration <= 0.0 {
+            return Err("--rate和--duration-s必须大于0".to_string());
+        }
+        let payload = match flag("--payload") {
+            Some(range) => {
+                let (min, max) = range.split_once('-').unwrap_or((range, range));
+                match (min.parse::<usize>(), max.parse::<usize>()) {
+                    (Ok(min), Ok(max)) if min <= max => (min, max),
+                    _ => return Err(format!("--payload的范围'{}'无效，格式为最小字节数-最大字节数", range)),
+                }
+            }
+            None => (16, 16),
+        };
+        let read_ratio = number("--read-ratio", 0.0)?;
+        if !(0.0..=1.0).contains(&read_ratio) {
+            return Err("--read-ratio必须在0到1之间".to_string());
+        }
+        Ok(Workload { profile, rate, duration: Duration::from_secs_f64(duration), payload, read_ratio })
+    }
+
+    // 各请求相对于开始时刻的计划发送时间，按时间先后排列
+    pub fn schedule(&self, rng: &mut impl Rng) -> Vec<Duration> {
+        let end = self.duration.as_secs_f64();
+        let mut arrivals = Vec::new();
+        match self.profile {
+            // 按序号计算到达时刻，不累加间隔，避免浮点误差多出或少掉一次到达
+            Profile::Constant | Profile::Bursty { .. } => {
+                let size = match self.profile {
+                    Profile::Bursty { size } => size,
+                    _ => 1,
+                };
+                let interval = size as f64 / self.rate;
+                let starts = (0..).map(|group| group as f64 * interval).take_while(|at| *at < end);
+                for at in starts {
+                    arrivals.resize(arrivals.len() + size, at);
+                }
+            }
+            Profile::Poisson => {
+                let mut at = -(1.0 - rng.gen::<f64>()).ln() / self.rate;
+                while at < end {
+                    arrivals.push(at);
+                    at += -(1.0 - rng.gen::<f64>()).ln() / self.rate;
+                }
+            }
+        }
+        arrivals.into_iter().map(Duration::from_secs_f64).collect()
+    }
+
+    // 第n个请求的操作：按比例读取此前写入的随机一个键，否则写入新键
+    fn operation(&self, n: usize, rng: &mut impl Rng) -> String {
+        if n > 0 && rng.gen::<f64>() < self.read_ratio {
+            return format!("GET loadgen{}", rng.gen_range(0, n));
+        }
+        let len = rng.gen_range(self.payload.0, self.payload.1 + 1);
+        let value: String = (0..len).map(|_| rng.sample(rand::distributions::Alphanumeric)).collect();
+        format!("SET loadgen{} {}", n, value)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub submitted: usize,
+    pub latencies: Vec<Duration>, // 已完成请求的延迟，升序
+    pub elapsed: Duration,        // 开始发送到最后一个请求完成
+}
+
+impl Report {
+    pub fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    // 每秒完成的请求数
+    pub fn throughput(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        json!({
+            "submitted": self.submitted,
+            "completed": self.latencies.len(),
+            "elapsed_ms": ms(self.elapsed),
+            "throughput": self.throughput(),
+            "latency_ms": {
+                "p50": ms(self.percentile(50.0)),
+                "p90": ms(self.percentile(90.0)),
+                "p99": ms(self.percentile(99.0)),
+                "max": ms(self.percentile(100.0)),
+            },
+        })
+    }
+}
+
+// 已发出、尚未收到F+1个一致答复的请求
+struct InFlight {
+    sent: Instant,                              // 计划发送时刻
+    votes: Vec<(ReplyOutcome, HashSet<usize>)>, // 各答复结果及给出该结果的节点
+}
+
+impl InFlight {
+    // 记录一个节点的答复，返回是否已有F+1个节点给出相同的结果
+    fn vote(&mut self, node_id: usize, outcome: ReplyOutcome) -> bool {
+        let position = self.votes.iter().position(|(o, _)| *o == outcome).unwrap_or_else(|| {
+            self.votes.push((outcome, HashSet::new()));
+            self.votes.len() - 1
+        });
+        self.votes[position].1.insert(node_id);
+        self.votes[position].1.len() > F
+    }
+}
+
+// 按负载向集群提交请求并收集答复。每个请求使用独立的客户端ID，答复据此对应到请求
+async fn drive(workload: &Workload, senders: &[mpsc::Sender<PBFTMessage>]) -> Report {
+    let mut rng = rand::thread_rng();
+    let schedule = workload.schedule(&mut rng);
+    let (reply_sender, mut replies) = mpsc::channel(LOADGEN_REPLY_QUEUE_SIZE);
+    let mut outstanding: HashMap<String, InFlight> = HashMap::new();
+    let mut report = Report { submitted: schedule.len(), ..Report::default() };
+
+    let started = Instant::now();
+    let drain_deadline = started + workload.duration + Duration::from_millis(LOADGEN_DRAIN_MS);
+    let mut next = 0;
+    while next < schedule.len() || !outstanding.is_empty() {
+        let wake = schedule.get(next).map(|at| started + *at).unwrap_or(drain_deadline);
+        tokio::select! {
+            _ = tokio::time::sleep_until(wake) => {
+                if next == schedule.len() {
+                    break;
+                }
+                let client_id = format!("loadgen-{}", next);
+                network::register_client(&client_id, reply_sender.clone());
+                outstanding.insert(client_id.clone(), InFlight { sent: wake, votes: Vec::new() });
+                let request = PBFTMessage::Request {
+                    operation: workload.operation(next, &mut rng),
+                    priority: Priority::Normal,
+                    client_id: Some(client_id),
+                    expires_at: None,
+                    session: None,
+                    timestamp: None,
+                };
+                // 像PBFT客户端一样发给所有节点，主节点切换后请求不会丢失
+                for sender in senders {
+                    let _ = sender.send(request.clone()).await;
+                }
+                next += 1;
+            }
+            Some(reply) = replies.recv() => {
+                if let PBFTMessage::Reply { node_id, client_id, outcome, .. } = reply {
+                    let done = match outstanding.get_mut(&client_id) {
+                        Some(pending) => pending.vote(node_id, outcome),
+                        None => false,
+                    };
+                    if done {
+                        let pending = outstanding.remove(&client_id).unwrap();
+                        report.latencies.push(pending.sent.elapsed());
+                        report.elapsed = started.elapsed();
+                    }
+                }
+            }
+        }
+    }
+    report.latencies.sort();
+    report
+}
+
+// 在临时目录中启动N个诚实节点，返回各节点的消息通道。须在tokio::task::LocalSet中调用
+async fn start_cluster() -> Vec<mpsc::Sender<PBFTMessage>> {
+    let genesis = Genesis { chain_id: "loadgen".to_string(), ..Genesis::default() };
+    let signing_keys: Vec<SigningKey> = (0..N).map(|_| SigningKey::generate()).collect();
+    let public_keys: HashMap<_, _> = signing_keys.iter().enumerate().map(|(id, k)| (id, k.public_key())).collect();
+
+    let mut senders = Vec::new();
+    for (id, signing_key) in signing_keys.into_iter().enumerate() {
+        let (tx, rx) = mpsc::channel(1000);
+        register_node(id, genesis.network_magic(), tx.clone());
+        senders.push(tx);
+        let mut node = Node::new(id, 0, signing_key, public_keys.clone(), rx, Strategy::Honest, genesis.clone());
+        // 目录登记请求会占用序列号，混入测量结果
+        node.peer_directory = false;
+        tokio::task::spawn_local(async move { node.run().await });
+    }
+    // 等节点之间完成握手认证
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    senders
+}
+
+// 命令行入口：loadgen [负载参数] [--json] [--min-throughput 请求/秒] [--max-p99-ms 毫秒]。
+// 未达到给定阈值时以状态1退出，供CI判断性能是否退化
+pub fn run(args: &[String]) -> i32 {
+    let workload = match Workload::parse(args) {
+        Ok(workload) => workload,
+        Err(reason) => {
+            eprintln!("负载参数无效: {}", reason);
+            return 2;
+        }
+    };
+    let threshold = |name: &str| args.iter().position(|s| s == name).and_then(|i| args.get(i + 1)).and_then(|v| v.parse::<f64>().ok());
+    let (min_throughput, max_p99_ms) = (threshold("--min-throughput"), threshold("--max-p99-ms"));
+
+    // 节点的数据文件写入临时目录，不影响当前目录中真实节点的状态
+    let dir = std::env::temp_dir().join(format!("pbft-loadgen-{}", std::process::id()));
+    if let Err(e) = std::fs::create_dir_all(&dir).and_then(|()| std::env::set_current_dir(&dir)) {
+        eprintln!("无法创建工作目录{}: {}", dir.display(), e);
+        return 2;
+    }
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let report = runtime.block_on(tokio::task::LocalSet::new().run_until(async {
+        let senders = start_cluster().await;
+        drive(&workload, &senders).await
+    }));
+    runtime.shutdown_background();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let summary = report.to_json();
+    if args.iter().any(|s| s == "--json") {
+        println!("{}", summary);
+    } else {
+        println!("负载{:?}，平均{}请求/秒，持续{:?}", workload.profile, workload.rate, workload.duration);
+        println!("提交{}个请求，完成{}个，吞吐量{:.1}请求/秒", report.submitted, report.latencies.len(), report.throughput());
+        println!("延迟(ms) p50 {:.1}  p90 {:.1}  p99 {:.1}  最大 {:.1}",
+            summary["latency_ms"]["p50"].as_f64().unwrap(), summary["latency_ms"]["p90"].as_f64().unwrap(),
+            summary["latency_ms"]["p99"].as_f64().unwrap(), summary["latency_ms"]["max"].as_f64().unwrap());
+    }
+
+    let p99_ms = summary["latency_ms"]["p99"].as_f64().unwrap();
+    let mut failed = report.latencies.len() < report.submitted;
+    if failed {
+        eprintln!("{}个请求未在结束后{}ms内完成", report.submitted - report.latencies.len(), LOADGEN_DRAIN_MS);
+    }
+    if let Some(min) = min_throughput.filter(|min| report.throughput() < *min) {
+        eprintln!("吞吐量{:.1}低于要求的{}", report.throughput(), min);
+        failed = true;
+    }
+    if let Some(max) = max_p99_ms.filter(|max| p99_ms > *max) {
+        eprintln!("p99延迟{:.1}ms高于要求的{}ms", p99_ms, max);
+        failed = true;
+    }
+    if failed { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn schedules_follow_the_profile() {
+        let mut rng = rand::thread_rng();
+        let constant = Workload::parse(&args("--rate 50 --duration-s 2")).unwrap();
+        let arrivals = constant.schedule(&mut rng);
+        assert_eq!(arrivals.len(), 100);
+        assert_eq!(arrivals[1], Duration::from_millis(20));
+
+        let bursty = Workload::parse(&args("--profile bursty --burst 10 --rate 50 --duration-s 2")).unwrap();
+        let arrivals = bursty.schedule(&mut rng);
+        assert_eq!(arrivals.len(), 100);
+        assert_eq!(arrivals[9], Duration::ZERO);
+        assert_eq!(arrivals[10], Duration::from_millis(200));
+
+        // 平均速率接近配置值，间隔并不固定
+        let poisson = Workload::parse(&args("--profile poisson --rate 1000 --duration-s 10")).unwrap();
+        let arrivals = poisson.schedule(&mut rng);
+        assert!((9000..11000).contains(&arrivals.len()), "泊松到达{}个请求", arrivals.len());
+        assert!(arrivals.windows(2).all(|w| w[0] <= w[1]));
+        assert!(arrivals.iter().all(|at| *at < Duration::from_secs(10)));
+
+        let mixed = Workload::parse(&args("--payload 4-8 --read-ratio 0.5")).unwrap();
+        let operations: Vec<String> = (0..200).map(|n| mixed.operation(n, &mut rng)).collect();
+        assert!(operations[0].starts_with("SET loadgen0 "));
+        let reads = operations.iter().filter(|op| op.starts_with("GET ")).count();
+        assert!((50..150).contains(&reads), "读请求{}个", reads);
+        assert!(operations.iter().filter_map(|op| op.strip_prefix("SET ")).all(|rest| (4..=8).contains(&rest.split(' ').nth(1).unwrap().len())));
+
+        assert!(Workload::parse(&args("--profile zipf")).is_err());
+        assert!(Workload::parse(&args("--payload 9-3")).is_err());
+        assert!(Workload::parse(&args("--read-ratio 2")).is_err());
+    }
+
+    #[test]
+    fn report_percentiles() {
+        let report = Report {
+            submitted: 100,
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+            elapsed: Duration::from_secs(2),
+        };
+        assert_eq!(report.percentile(50.0), Duration::from_millis(50));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(report.throughput(), 50.0);
+        assert_eq!(report.to_json()["latency_ms"]["p90"], 90.0);
+        assert_eq!(Report::default().percentile(99.0), Duration::ZERO);
+    }
+}
@@ -1,82 +1,483 @@
 // src/main.rs
 
+mod acl;
+mod address_book;
+mod admission;
+mod alerts;
+#[cfg(feature = "app-counter")]
+mod app_counter;
+#[cfg(feature = "app-ledger")]
+mod app_ledger;
+mod application;
+mod archive;
+mod audit;
+mod batching;
+mod beacon;
+mod bridge;
+mod byzantine;
+mod capture;
+mod chain;
+mod chain_index;
+mod checkpoint;
+mod client;
+mod clock;
+mod clock_sync;
 mod config;
+#[cfg(feature = "console")]
+mod console;
+mod consensus;
+mod crypto;
+mod directory;
+mod doctor;
+mod domain;
+mod events;
+mod evidence;
+mod execution;
+mod features;
+mod firewall;
+mod genesis;
+mod governance;
+mod hash;
+mod header_sync;
+mod key_registry;
+mod latency_budget;
+mod leader;
+mod loadgen;
+mod mempool;
+mod merkle;
 mod message;
+mod metrics;
+mod multisig;
+mod namespace;
 mod network;
 mod node;
+mod observer;
+mod payload;
+mod phase;
+mod pipeline;
+mod preflight;
+mod qos;
+mod quota;
+mod quorum;
+mod reload;
+mod reply_cache;
+mod reply_sink;
+mod replay;
+mod report;
+mod reputation;
+mod request_status;
+mod rpc;
+mod rpc_auth;
+mod runtime;
+mod schedule;
+mod session;
+mod signing_policy;
+mod state_sync;
+mod state_view;
+mod storage;
+mod supervisor;
+#[cfg(test)]
+mod testing;
+mod trace;
+mod view_stats;
+mod vote_aggregation;
+mod zones;
 
 use crate::node::Node;
+use crate::byzantine::Strategy;
+use crate::clock::Clock;
+use crate::genesis::Genesis;
+use crate::archive::ArchiveIndex;
 use crate::network::register_node;
+use crate::state_sync::StateSync;
+use crate::header_sync::HeaderSync;
+use crate::runtime::RuntimeConfig;
+use crate::preflight::ConfigError;
 use tokio::sync::mpsc;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use crate::node::NodeState;
-use log::info;
-use ed25519_dalek::Keypair;
-use rand::rngs::OsRng;
-use std::collections::HashMap;
+use crate::node::{NodeState, Role};
+use log::{info, warn, error};
+use crate::crypto::SigningKey;
 
-fn parse_args() -> (usize, bool) {
+struct Args {
+    node_id: usize,
+    strategy: Strategy,
+    role: Role,
+    state_sync: bool,
+    headers_first: bool,
+    relay: bool,
+    relay_via: Vec<usize>,
+    coalesce_messages: bool,
+    clock: Clock,
+    latency_ms: u64,
+    key_file: Option<String>,
+    runtime: RuntimeConfig,
+    governance: Vec<governance::Action>,
+    self_test: bool,
+    capture: Option<String>,
+}
+
+// 解析命令行参数，报告所有无效的参数
+fn parse_args() -> Result<Args, Vec<ConfigError>> {
     let args: Vec<String> = std::env::args().collect();
-    let node_id: usize = args.get(1).unwrap_or(&"0".to_string()).parse().unwrap();
-    let is_byzantine = args.get(2).map_or(false, |s| s == "byzantine");
-    (node_id, is_byzantine)
+    let mut errors = Vec::new();
+    let node_id = parse_value("节点ID", args.get(1).map(String::as_str).unwrap_or("0"), &mut errors).unwrap_or(0);
+    let strategy = args.get(2).and_then(|s| Strategy::parse(s)).unwrap_or_default();
+    let role = match args.get(2).map(|s| s.as_str()) {
+        Some("full") => Role::FullNode,
+        Some("archive") => Role::Archive,
+        Some("observer") => Role::Observer,
+        _ => Role::Validator,
+    };
+    let state_sync = args.iter().any(|s| s == "--state-sync");
+    // --headers-first：先下载并校验区块头找到链顶，进入当前视图后再补齐区块体
+    let headers_first = args.iter().any(|s| s == "--headers-first");
+    if headers_first && (state_sync || role != Role::Validator) {
+        errors.push(ConfigError::InvalidArgument {
+            flag: "--headers-first".to_string(),
+            reason: "只用于验证者，且不能与--state-sync同时使用".to_string(),
+        });
+    }
+    let relay = args.iter().any(|s| s == "--relay");
+    let flag = |name: &str| args.iter().position(|s| s == name).and_then(|i| args.get(i + 1));
+    // --relay-via 0,1：本节点没有公网地址，经由这些中继节点接收消息
+    let relay_via = flag("--relay-via")
+        .map(|ids| ids.split(',').filter_map(|id| parse_value("--relay-via", id.trim(), &mut errors)).collect())
+        .unwrap_or_default();
+    // --coalesce-messages：合并发往同一节点的消息，副本的Prepare搭载转发的PrePrepare
+    let coalesce_messages = args.iter().any(|s| s == "--coalesce-messages");
+    // 模拟时钟偏差和网络延迟：--clock-offset-ms -2000 --clock-drift-ppm 100 --latency-ms 50
+    let offset_ms = flag("--clock-offset-ms").and_then(|v| parse_value("--clock-offset-ms", v, &mut errors)).unwrap_or(0);
+    let drift_ppm = flag("--clock-drift-ppm").and_then(|v| parse_value("--clock-drift-ppm", v, &mut errors)).unwrap_or(0);
+    let latency_ms = flag("--latency-ms").and_then(|v| parse_value("--latency-ms", v, &mut errors)).unwrap_or(0);
+    // --key-file node.key：从文件加载签名私钥（不存在时生成并写入），重启后公钥保持不变
+    let key_file = flag("--key-file").cloned();
+    // 治理：--propose '{"id":"batch","change":{"max_batch_size":32},"activation_height":100}' 或 --vote batch
+    let mut governance = Vec::new();
+    if let Some(proposal) = flag("--propose") {
+        match serde_json::from_str(proposal) {
+            Ok(proposal) => governance.push(governance::Action::Propose(proposal)),
+            Err(e) => errors.push(ConfigError::InvalidArgument { flag: "--propose".to_string(), reason: e.to_string() }),
+        }
+    }
+    if let Some(proposal_id) = flag("--vote") {
+        governance.push(governance::Action::Vote { proposal_id: proposal_id.clone() });
+    }
+    // --skip-self-test：跳过启动时对最近区块和时钟的自检
+    let self_test = !args.iter().any(|s| s == "--skip-self-test");
+    // --capture node0.pcap：把本节点收发的每一帧写入抓包文件
+    let capture = flag("--capture").cloned();
+    let runtime = RuntimeConfig::from_args(&args).unwrap_or_else(|reason| {
+        errors.push(ConfigError::InvalidArgument { flag: "运行时".to_string(), reason });
+        RuntimeConfig::default()
+    });
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Args { node_id, strategy, role, state_sync, headers_first, relay, relay_via, coalesce_messages, clock: Clock::new(offset_ms, drift_ppm), latency_ms, key_file, runtime, governance, self_test, capture })
 }
 
-#[tokio::main]
-async fn main() {
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str, errors: &mut Vec<ConfigError>) -> Option<T> {
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            errors.push(ConfigError::InvalidArgument { flag: flag.to_string(), reason: format!("'{}'不是有效的数值", value) });
+            None
+        }
+    }
+}
+
+fn main() {
+    // 离线工具：chain replay <节点ID> 重新执行本地区块并比对检查点的状态摘要；
+    // loadgen 在进程内启动集群并施加负载，报告吞吐量和延迟；
+    // multisig 离线生成多签提案、收集并合并签名；doctor 检查配置、数据目录、时钟和对等节点的连通性；
+    // report 汇总各节点的指标快照，生成运行报告；capture 解码抓包文件，逐帧打印其中的PBFT消息；
+    // peers list 列出节点地址簿中记录的对等节点；keys 输出节点公钥、签发和检查验证者公钥清单
+    let raw: Vec<String> = std::env::args().collect();
+    match raw.get(1).map(|s| s.as_str()) {
+        Some("chain") => std::process::exit(replay::run(&raw[2..])),
+        Some("client") => std::process::exit(client::run(&raw[2..])),
+        Some("loadgen") => std::process::exit(loadgen::run(&raw[2..])),
+        Some("multisig") => std::process::exit(multisig::run(&raw[2..])),
+        Some("doctor") => std::process::exit(doctor::run(&raw[2..])),
+        Some("report") => std::process::exit(report::run(&raw[2..])),
+        Some("capture") => std::process::exit(capture::run(&raw[2..])),
+        Some("peers") => std::process::exit(address_book::run(&raw[2..])),
+        Some("keys") => std::process::exit(key_registry::run(&raw[2..])),
+        _ => {}
+    }
     println!("Node started");
-    // Parse command-line arguments
-    let (node_id, is_byzantine) = parse_args();
+    // 指标快照中的运行时长从此刻算起
+    lazy_static::initialize(&metrics::STARTED_AT);
+    // Parse command-line arguments. 参数或配置无效时列出所有问题后退出
+    let checked = parse_args().and_then(|args| {
+        let genesis = preflight::check(args.node_id, args.role, args.key_file.as_deref())?;
+        Ok((args, genesis))
+    });
+    let (args, genesis) = checked.unwrap_or_else(|errors| {
+        preflight::report(&errors);
+        std::process::exit(1);
+    });
+    // 启动自检：数据目录损坏或时钟被回拨时不启动，有警告时打印后继续
+    if args.self_test {
+        let report = doctor::self_test(args.node_id, &genesis);
+        if report.findings.iter().any(|finding| finding.status != doctor::Status::Pass) {
+            report.print(args.node_id);
+        }
+        if report.failed() {
+            eprintln!("启动自检失败，运行 pbft-blockchain doctor {} 查看完整报告，或以--skip-self-test跳过", args.node_id);
+            std::process::exit(1);
+        }
+    }
+    let runtime = args.runtime.build().unwrap_or_else(|e| {
+        eprintln!("无法创建tokio运行时: {}", e);
+        std::process::exit(1);
+    });
+    runtime.block_on(run(args, genesis));
+}
+
+async fn run(args: Args, genesis: Genesis) {
+    let (node_id, strategy, role) = (args.node_id, args.strategy, args.role);
+
+    // Initialize logger. 日志级别等非共识配置可在运行中热加载
+    init_logger(node_id, args.clock);
+    let node_config = reload::NodeConfig::load().unwrap_or_default();
+    node_config.apply();
+    let node_config = Arc::new(Mutex::new(node_config));
+    args.runtime.log();
 
-    // Initialize logger
-    init_logger(node_id);
+    info!("启动节点{}，角色: {:?}，拜占庭策略: {:?}", node_id, role, strategy);
+    info!("时钟偏移: {}ms，漂移: {}ppm，出站延迟: {}ms", args.clock.offset_ms, args.clock.drift_ppm, args.latency_ms);
+
+    info!("链ID: {}，网络魔数: {}", genesis.chain_id, hex::encode(genesis.network_magic()));
+    info!("共识参数哈希: {}，各节点须一致", genesis.consensus_parameters().hash());
+
+    for (kind, authentication) in genesis.signing_policy.weakened() {
+        warn!("签名策略: {}消息使用{:?}认证，不能作为证书或证据转交第三方", kind, authentication);
+    }
+
+    // 配置了故障注入时，内存网络按概率丢弃、重复和推迟消息
+    network::set_faults(network::NetworkFaults::load());
+    // 传输层防火墙：拒绝未获准的对等节点和IP，运行时可经RPC调整
+    let firewall = Arc::new(Mutex::new(firewall::Firewall::load(&genesis.validators)));
+    network::set_firewall(node_id, firewall.clone());
+    if let Some(path) = &args.capture {
+        if let Err(e) = capture::start(node_id, std::path::Path::new(path)) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        info!("节点{}收发的每一帧写入抓包文件{}", node_id, path);
+    }
+
+    // Load or generate the signing key. 只在进程启动时加载一次：没有--key-file时，
+    // 崩溃后重启的节点也沿用同一把临时私钥，已记住其公钥的对等节点仍接受它的握手
+    let secret = match &args.key_file {
+        Some(path) => SigningKey::load_or_generate(path).unwrap_or_else(|reason| {
+            error!("加载签名私钥失败: {}", reason);
+            eprintln!("加载签名私钥失败: {}", reason);
+            std::process::exit(1);
+        }),
+        None => SigningKey::generate(),
+    }.secret_bytes();
+
+    // 节点panic时由监督者从磁盘状态重启，收到关闭信号正常退出后进程结束
+    let args = Rc::new(args);
+    let supervisor = supervisor::Supervisor::new(node_id);
+    let (mut firewall, mut node_config) = (firewall, node_config);
+    tokio::task::LocalSet::new().run_until(supervisor.run(|restarts| {
+        if supervisor::recover_poisoned(&mut firewall) {
+            warn!("节点{}崩溃时防火墙配置的锁中毒，已换用新锁", node_id);
+            network::set_firewall(node_id, firewall.clone());
+        }
+        if supervisor::recover_poisoned(&mut node_config) {
+            warn!("节点{}崩溃时节点配置的锁中毒，已换用新锁", node_id);
+        }
+        let signing_key = SigningKey::from_secret_bytes(&secret[..]).expect("启动时加载的私钥有效");
+        start_node(args.clone(), genesis.clone(), signing_key, firewall.clone(), node_config.clone(), restarts)
+    })).await;
+}
 
-    info!("启动节点{}，是否为拜占庭节点: {}", node_id, is_byzantine);
+// 创建并运行一次节点；restarts大于0时是崩溃后的重启，通道、关闭信号和RPC服务重新建立
+async fn start_node(args: Rc<Args>, genesis: Genesis, signing_key: SigningKey, firewall: Arc<Mutex<firewall::Firewall>>, node_config: Arc<Mutex<reload::NodeConfig>>, restarts: u32) {
+    let (node_id, strategy, role) = (args.node_id, args.strategy, args.role);
 
     // Create communication channel
     let (tx, rx) = mpsc::channel(100);
-    register_node(node_id, tx.clone());
+    if args.relay_via.is_empty() {
+        register_node(node_id, genesis.network_magic(), tx.clone());
+    } else {
+        // 位于NAT之后：不登记可直接连接的入口，只向中继节点建立出站连接
+        for relay in &args.relay_via {
+            network::connect_via_relay(*relay, node_id, genesis.network_magic(), tx.clone());
+        }
+    }
 
     // Initialize node state
     let _node_state = Arc::new(Mutex::new(NodeState::load(node_id)));
 
-    // Generate keypair
-    let mut csprng = OsRng;
-    let keypair = Keypair::generate(&mut csprng);
-
-    // Collect public keys (in practice, exchange over the network)
-    let mut public_keys = HashMap::new();
-    public_keys.insert(node_id, keypair.public);
+    // Collect public keys：有签名清单时预先载入各节点的公钥，握手时只认清单中的公钥；否则在握手时得知
+    let mut public_keys = match key_registry::load(&genesis) {
+        Ok(keys) => keys.unwrap_or_default(),
+        Err(reason) => {
+            error!("加载验证者公钥清单失败: {}", reason);
+            eprintln!("加载验证者公钥清单失败: {}", reason);
+            std::process::exit(1);
+        }
+    };
+    public_keys.insert(node_id, signing_key.public_key());
 
     // Create node instance
     let mut node = Node::new(
         node_id,
         0,
-        keypair,
+        signing_key,
         public_keys,
         rx,
-        is_byzantine,
+        strategy,
+        genesis,
     );
+    node.role = role;
+    node.clock = args.clock;
+    node.send_latency = std::time::Duration::from_millis(args.latency_ms);
+    node.relay_enabled = args.relay;
+    node.relays = args.relay_via.clone();
+    node.coalesce_messages |= args.coalesce_messages;
+    // 治理提案和投票只在首次启动时提交
+    if restarts == 0 {
+        node.governance_actions = args.governance.clone();
+    }
+    // 热加载的配置：启动时的值直接写入节点，之后的修改由监视任务经通道送达
+    let config = node_config.lock().unwrap().clone();
+    node.advertised_addresses = config.advertised_addresses;
+    if config.otlp_endpoint.is_some() {
+        node.otlp_endpoint = config.otlp_endpoint;
+    }
+    node.alerts.configure(config.alerts);
+    node.latency_budget.set_budget(config.latency_budget_ms);
+    node.set_zones(config.zones);
+    let (config_tx, config_rx) = mpsc::channel(1);
+    node.config_reload = Some(config_rx);
+    let _config_watcher = AbortOnDrop(tokio::spawn(reload::watch(node_id, node_config.clone(), config_tx)));
+    let _metrics_writer = AbortOnDrop(tokio::spawn(report::write_periodically(node_id, node.chain.clone())));
+    if role == Role::Archive {
+        let index = ArchiveIndex::build(&node.chain.lock().unwrap());
+        node.archive_index = Some(Arc::new(Mutex::new(index)));
+    }
+    if role == Role::Observer {
+        node.auditor = Some(Arc::new(Mutex::new(crate::observer::Auditor::default())));
+    }
+
+    // 未完成的状态同步在重启后自动续传
+    node.state_sync = match StateSync::load(node_id) {
+        Some(sync) => {
+            info!("节点{}恢复未完成的状态同步，已下载{}个分块", node_id, sync.chunks.len());
+            Some(sync)
+        }
+        None if args.state_sync => Some(StateSync::default()),
+        None => None,
+    };
+    if args.headers_first && node.state_sync.is_none() {
+        node.header_sync = Some(HeaderSync::new(node_id));
+    }
+
+    // 交互式控制台（需以 --features console 编译）
+    #[cfg(feature = "console")]
+    {
+        let (console_tx, console_rx) = mpsc::channel(16);
+        node.console = Some(console_rx);
+        tokio::spawn(console::run(node_id, console_tx));
+    }
+
+    // Ctrl+C或SIGTERM（例如docker stop）时节点先保存内存池再退出
+    let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+    node.shutdown = Some(shutdown_rx);
+    // 计划退出由管理员经RPC的Exit方法触发
+    let (exit_tx, exit_rx) = mpsc::channel(1);
+    node.exit = Some(exit_rx);
+    let (maintenance_tx, maintenance_rx) = mpsc::channel(1);
+    node.maintenance_toggle = Some(maintenance_rx);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(()).await;
+    });
+
+    // Start RPC server (listeners are bound before the node announces its addresses)
+    let listeners = rpc::bind(node_id).await;
+    let rpc_server = tokio::spawn(rpc::serve(rpc::RpcContext {
+        node_id,
+        chain: node.chain.clone(),
+        archive_index: node.archive_index.clone(),
+        auditor: node.auditor.clone(),
+        execution: node.execution.clone(),
+        view: node.current_view.clone(),
+        primary: node.current_primary.clone(),
+        reputation: node.reputation.clone(),
+        clock_sync: node.clock_sync.clone(),
+        request_status: node.request_status.clone(),
+        view_stats: node.view_stats.clone(),
+        durable_height: node.storage.subscribe(),
+        node: tx.clone(),
+        auth: Arc::new(rpc_auth::RpcAuth::load()),
+        firewall,
+        events: node.events.clone(),
+        exit: exit_tx,
+        maintenance: maintenance_tx,
+        genesis: node.genesis.clone(),
+        config: node_config,
+        connections: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+    }, listeners));
 
     // If primary node, simulate client request
-    if node.is_primary() {
+    if role != Role::Validator {
+        info!("节点{}是{:?}节点，从验证者同步已提交的区块", node_id, role);
+    } else if restarts > 0 {
+        info!("节点{}重启后恢复到视图{}，等待消息", node_id, node.core.view);
+    } else if node.is_primary() {
         info!("节点{}是主节点，模拟发送客户端请求", node_id);
         let request = crate::message::PBFTMessage::Request {
-            operation: format!("操作{}", node.sequence_number + 1),
+            operation: format!("操作{}", node.core.sequence_number + 1),
+            priority: crate::qos::Priority::Normal,
+            client_id: None,
+            expires_at: None,
+            session: None,
+            timestamp: None,
         };
         node.handle_request(request).await;
     } else {
         info!("节点{}是副本节点，等待消息", node_id);
     }
 
-    // Run node
+    // Run node. 节点崩溃时RPC服务随之停止，释放监听地址供重启后的节点绑定
+    let _rpc_server = AbortOnDrop(rpc_server);
     node.run().await;
 }
 
-fn init_logger(node_id: usize) {
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+fn init_logger(node_id: usize, clock: Clock) {
     use std::fs::File;
     use std::io::Write;
-    use chrono::Local;
     use env_logger::Builder;
     use log::LevelFilter;
 
@@ -88,12 +489,12 @@ fn init_logger(node_id: usize) {
             writeln!(
                 &mut file.try_clone().unwrap(),
                 "{} [{}] - {}",
-                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                clock.wall_time().format("%Y-%m-%d %H:%M:%S"),
                 record.level(),
                 record.args()
             ).unwrap();
             Ok(())
         })
-        .filter(None, LevelFilter::Info)
+        .filter(None, LevelFilter::Trace)
         .init();
 }
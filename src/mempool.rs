@@ -0,0 +1,143 @@
+// src/mempool.rs
+
+// 内存池快照：节点关闭时把已接受但尚未排序的客户端请求写入node_{id}_mempool.bin，下次启动时读回并重新提交，
+// 重启不会悄悄丢掉客户端以为已经送达的请求。
+// 文件格式：魔数"PBMP"、版本号（1字节）、请求数（u32），每个请求为长度（u32）加JSON编码，
+// 最后是此前全部字节的SHA-256。整数均为大端序。校验和不符说明文件写到一半或已损坏，整个快照作废
+use crate::hash::{Hasher, Sha256};
+use crate::message::PBFTMessage;
+
+const MAGIC: &[u8; 4] = b"PBMP";
+const VERSION: u8 = 2; // 版本2：请求按带类型标签的信封编码
+const CHECKSUM_LEN: usize = 32;
+
+fn filename(node_id: usize) -> String {
+    format!("node_{}_mempool.bin", node_id)
+}
+
+pub fn encode(requests: &[PBFTMessage]) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    data.push(VERSION);
+    data.extend_from_slice(&(requests.len() as u32).to_be_bytes());
+    for request in requests {
+        let bytes = serde_json::to_vec(request).unwrap();
+        data.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        data.extend_from_slice(&bytes);
+    }
+    let checksum = Sha256.digest(&data);
+    data.extend_from_slice(&checksum);
+    data
+}
+
+pub fn decode(data: &[u8]) -> Result<Vec<PBFTMessage>, String> {
+    if data.len() < MAGIC.len() + 1 + 4 + CHECKSUM_LEN {
+        return Err(format!("快照只有{}字节", data.len()));
+    }
+    let (body, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+    if Sha256.digest(body) != checksum {
+        return Err("校验和不符".to_string());
+    }
+    if &body[..4] != MAGIC {
+        return Err("魔数不符".to_string());
+    }
+    if body[4] != VERSION {
+        return Err(format!("不支持的版本{}", body[4]));
+    }
+
+    let mut rest = &body[5..];
+    let mut take = |len: usize| -> Result<&[u8], String> {
+        if rest.len() < len {
+            return Err("快照被截断".to_string());
+        }
+        let (head, tail) = rest.split_at(len);
+        rest = tail;
+        Ok(head)
+    };
+    let read_u32 = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let count = read_u32(take(4)?);
+    let mut requests = Vec::with_capacity(count.min(body.len()));
+    for i in 0..count {
+        let len = read_u32(take(4)?);
+        let request = serde_json::from_slice(take(len)?).map_err(|e| format!("第{}个请求无法解析: {}", i, e))?;
+        requests.push(request);
+    }
+    Ok(requests)
+}
+
+pub fn save(node_id: usize, requests: &[PBFTMessage]) -> Result<(), String> {
+    std::fs::write(filename(node_id), encode(requests)).map_err(|e| format!("写入{}失败: {}", filename(node_id), e))
+}
+
+// 读取并删除快照，之后再次重启不会把同一批请求又提交一遍。没有快照时返回空列表
+pub fn take(node_id: usize) -> Result<Vec<PBFTMessage>, String> {
+    let data = match std::fs::read(filename(node_id)) {
+        Ok(data) => data,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let _ = std::fs::remove_file(filename(node_id));
+    decode(&data).map_err(|reason| format!("{}无效: {}", filename(node_id), reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::network::{self, LinkFaults, LinkOverride, NetworkFaults};
+    use crate::qos::Priority;
+    use crate::testing::TestCluster;
+
+    fn request(operation: &str, client_id: Option<&str>) -> PBFTMessage {
+        PBFTMessage::Request {
+            operation: operation.to_string(),
+            priority: Priority::High,
+            client_id: client_id.map(String::from),
+            expires_at: None,
+            session: None,
+            timestamp: client_id.map(|_| 7),
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trips_and_rejects_damage() {
+        let requests = vec![request("SET a 1", Some("alice")), request("APPEND b 2", None)];
+        let data = encode(&requests);
+        assert_eq!(&data[..4], b"PBMP");
+        let decoded = decode(&data).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&requests).unwrap());
+        assert!(decode(&encode(&[])).unwrap().is_empty());
+
+        let mut flipped = data.clone();
+        flipped[12] ^= 1;
+        assert!(decode(&flipped).is_err());
+        assert!(decode(&data[..data.len() - 1]).is_err());
+    }
+
+    // 主节点的PrePrepare全部丢失，只有它知道的请求停在内存池中。主节点关闭时写下快照，
+    // 重启后读回并重新提议，请求最终提交且只执行一次
+    #[tokio::test]
+    async fn primary_restart_keeps_unordered_requests() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            network::set_faults(NetworkFaults {
+                global: LinkFaults::default(),
+                links: (1..N).map(|to| LinkOverride { from: 0, to, faults: LinkFaults { drop: 1.0, ..Default::default() } }).collect(),
+            });
+            cluster.submit_to(0, request("APPEND k x", None)).await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            network::set_faults(NetworkFaults::default());
+            cluster.restart(0).await;
+
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.executions[id].lock().unwrap().get("k").is_some())
+            }).await;
+            assert!(committed, "重启后未重新提交快照中的请求");
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            for id in 0..N {
+                assert_eq!(cluster.executions[id].lock().unwrap().get("k"), Some(&"x".to_string()), "节点{}", id);
+            }
+            assert!(take(0).unwrap().is_empty(), "快照读取后应删除");
+        }).await;
+    }
+}
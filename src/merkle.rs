@@ -0,0 +1,59 @@
+// src/merkle.rs
+
+use serde::{Serialize, Deserialize};
+use crate::hash::Hasher;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProofStep {
+    pub sibling: String,
+    pub sibling_on_left: bool,
+}
+
+// 叶子与内部节点使用不同前缀，防止第二原像攻击
+pub fn hash_leaf(hasher: &dyn Hasher, leaf: &str) -> Vec<u8> {
+    let mut data = vec![0u8];
+    data.extend_from_slice(leaf.as_bytes());
+    hasher.digest(&data)
+}
+
+pub fn hash_node(hasher: &dyn Hasher, left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut data = vec![1u8];
+    data.extend_from_slice(left);
+    data.extend_from_slice(right);
+    hasher.digest(&data)
+}
+
+// 奇数个节点时最后一个节点直接提升到上一层
+fn next_level(hasher: &dyn Hasher, level: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    level.chunks(2).map(|pair| {
+        if pair.len() == 2 { hash_node(hasher, &pair[0], &pair[1]) } else { pair[0].clone() }
+    }).collect()
+}
+
+pub fn merkle_root(hasher: &dyn Hasher, leaves: &[String]) -> String {
+    if leaves.is_empty() {
+        return hasher.hex_digest(&[]);
+    }
+    let mut level: Vec<Vec<u8>> = leaves.iter().map(|l| hash_leaf(hasher, l)).collect();
+    while level.len() > 1 {
+        level = next_level(hasher, &level);
+    }
+    hex::encode(&level[0])
+}
+
+pub fn merkle_proof(hasher: &dyn Hasher, leaves: &[String], mut index: usize) -> Vec<ProofStep> {
+    let mut proof = Vec::new();
+    let mut level: Vec<Vec<u8>> = leaves.iter().map(|l| hash_leaf(hasher, l)).collect();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            proof.push(ProofStep {
+                sibling: hex::encode(&level[sibling]),
+                sibling_on_left: sibling < index,
+            });
+        }
+        level = next_level(hasher, &level);
+        index /= 2;
+    }
+    proof
+}
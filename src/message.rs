@@ -1,16 +1,98 @@
 // src/message.rs
 
 use serde::{Serialize, Deserialize};
+use crate::qos::Priority;
+use crate::chain::{Block, BlockHeader, CommitCertificate};
+use crate::state_sync::SnapshotManifest;
+use crate::trace::TraceContext;
+use crate::crypto::{PublicKey, Signature};
+use crate::evidence::Evidence;
+use crate::execution::ExecutionStatus;
+use crate::genesis::ConsensusParameters;
+use crate::session::SessionTag;
+use crate::signing_policy::Authenticator;
 
+// 批次中的一笔交易：操作内容及提交它的客户端
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub operation: String,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    // 客户端会话中的序号，同一序号只执行一次
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionTag>,
+    // 客户端请求时间戳，与client_id一起标识请求，用于答复缓存
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+impl Transaction {
+    // 规范编码，用于计算批次摘要和Merkle叶子
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+// ViewChange中携带的已准备请求（PBFT中的P集合）
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PreparedEntry {
+    pub view: u64,
+    pub sequence_number: u64,
+    pub digest: String,
+    pub transactions: Vec<Transaction>,
+    // 主节点之外2f个副本对该请求的Prepare签名，证明它确实已准备，单个拜占庭节点无法凭空捏造
+    #[serde(default)]
+    pub prepares: Vec<(usize, Signature)>,
+}
+
+// 节点对客户端请求的最终答复
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ReplyOutcome {
+    Executed(ExecutionStatus),
+    Expired, // 请求在被提议前已过期，已从待处理队列中删除
+    Rejected(String), // 请求未被接受排序，例如使用了尚未激活的协议特性
+}
+
+// 编码为带类型标签的信封：{"kind":"Prepare","view":0,...}。旧版本节点不认识的类型解码为Unknown，
+// 由入站流水线计数后跳过，滚动升级时新版本可以引入新的消息类型而不打断旧节点
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind")]
 pub enum PBFTMessage {
     Request {
         operation: String,
+        #[serde(default)]
+        priority: Priority,
+        #[serde(default)]
+        client_id: Option<String>,
+        // 过期时间（Unix毫秒）；到期仍未被提议的请求被丢弃并通知客户端
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_at: Option<i64>,
+        // 会话ID和序号；重发同一序号的请求得到第一次执行的结果，不会重复执行
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session: Option<SessionTag>,
+        // 客户端单调递增的请求时间戳；重发的请求由副本直接返回缓存的答复
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<u64>,
+    },
+    Reply {
+        node_id: usize,
+        client_id: String,
+        operation: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session: Option<SessionTag>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<u64>,
+        outcome: ReplyOutcome,
+        // 答复时节点的执行高度，请求已执行时不低于它所在的区块；客户端据此要求之后的读至少读到该高度
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        height: Option<u64>,
     },
     PrePrepare {
         view: u64,
         sequence_number: u64,
         digest: String,
+        #[serde(default)]
+        transactions: Vec<Transaction>, // 批次中的交易，副本据此校验摘要
     },
     Prepare {
         view: u64,
@@ -27,22 +109,244 @@ pub enum PBFTMessage {
         view: u64,
         last_sequence_number: u64,
         node_id: usize, // Added node_id field
+        #[serde(default)]
+        prepared: Vec<PreparedEntry>,
     },
     NewView {
         view: u64,
-        view_change_messages: Vec<PBFTMessage>, // Added view_change_messages field
-    },
-    PubKey {
-        node_id: usize,
-        public_key: Vec<u8>,
+        view_change_messages: Vec<PBFTMessage>, // 带签名的ViewChange消息
+        #[serde(default)]
+        pre_prepares: Vec<PBFTMessage>, // O集合：新视图中重新提议的PrePrepare
     },
     SignedMessage {
         message: Box<PBFTMessage>,
-        signature: Vec<u8>,
+        signature: Signature,
         sender_id: usize,
+        // 分布式追踪上下文，不在签名范围内
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trace: Option<TraceContext>,
+    },
+    // 签名策略允许以MAC认证或不认证的消息，认证范围与SignedMessage的签名相同
+    AuthenticatedMessage {
+        message: Box<PBFTMessage>,
+        authenticator: Authenticator,
+        sender_id: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        trace: Option<TraceContext>,
     },
     ByzantineVote {
         suspected_id: usize,
         sender_id: usize,
+        evidence: Evidence, // 接收方验证证据后才计票
+    },
+    // 被指控者出示主节点为其Prepare摘要签名的PrePrepare，证明分叉的是主节点。须签名发送
+    Appeal {
+        node_id: usize,
+        pre_prepare: Box<PBFTMessage>,
+    },
+    ClientRequest {
+        request: Box<PBFTMessage>,
+        client_id: String,
+        signature: Signature,
+    },
+    SubscribeBlocks {
+        node_id: usize,
+        from_height: u64,
+    },
+    BlockAnnouncement {
+        block: Block,
+    },
+    SubscribeConsensus {
+        node_id: usize,
+    },
+    Bundle {
+        messages: Vec<PBFTMessage>, // 传输层合并的多条消息，按顺序处理
+    },
+    HandshakeChallenge {
+        node_id: usize,
+        nonce: Vec<u8>,
+    },
+    HandshakeResponse {
+        node_id: usize,
+        public_key: PublicKey,
+        signature: Signature,
+        // 应答方的共识参数，挑战方与自己的比对，不一致时拒绝认证。旧版本节点不发送
+        #[serde(default)]
+        parameters: Option<Box<ConsensusParameters>>,
+        // 应答方的协议版本，记入挑战方的地址簿。旧版本节点不发送
+        #[serde(default)]
+        protocol_version: Option<u32>,
+    },
+    SnapshotRequest {
+        node_id: usize,
+    },
+    SnapshotOffer {
+        node_id: usize,
+        manifest: SnapshotManifest, // 须签名发送，下载方据此统计一致的提供方
+    },
+    ChunkRequest {
+        node_id: usize,
+        height: u64,
+        index: usize,
+    },
+    ChunkResponse {
+        height: u64,
+        index: usize,
+        data: Vec<u8>, // 按清单中的分块哈希校验，无需签名
+    },
+    // 执行到检查点高度后的状态摘要，由验证者签名广播
+    Checkpoint {
+        node_id: usize,
+        height: u64,
+        state_digest: String,
     },
+    // 发现序列号缺口的副本向对等节点请求缺失高度的区块
+    FetchRange {
+        node_id: usize,
+        from_height: u64,
+        to_height: u64,
+    },
+    RangeBlocks {
+        blocks: Vec<Block>, // 每个区块带提交证书，接收方逐个校验，无需签名
+    },
+    // 区块头优先同步：请求from_height起的区块头及其提交证书
+    FetchHeaders {
+        node_id: usize,
+        from_height: u64,
+    },
+    Headers {
+        headers: Vec<(BlockHeader, CommitCertificate)>, // 接收方校验哈希链接和证书，无需签名；少于一批说明已到发送方的链顶
+    },
+    RelayConnect {
+        node_id: usize, // 请求中继转发的、没有公网地址的节点
+    },
+    // 估计时钟偏差：Ping带发送方的墙上时间（Unix毫秒），Pong原样带回并附上应答方的墙上时间。须签名发送
+    Ping {
+        node_id: usize,
+        sent_at: i64,
+    },
+    Pong {
+        node_id: usize,
+        ping_sent_at: i64,
+        peer_time: i64,
+    },
+    Relay {
+        from: usize,
+        to: usize,
+        message: Box<PBFTMessage>, // 由中继节点原样转交，签名仍由接收方校验
+    },
+    // 摘要模式下发给副本的PrePrepare，批次中的交易只以摘要（chain_index::transaction_digest）给出。
+    // signature是主节点对完整PrePrepare的签名，副本补齐交易后据此还原并验证；整条消息也须签名发送
+    PrePrepareDigests {
+        view: u64,
+        sequence_number: u64,
+        digest: String,
+        payload: Vec<String>,
+        signature: Signature,
+    },
+    // 线性投票：主节点汇集的各副本对同一摘要的Prepare签名，副本逐个校验后计入法定人数。整条消息也须签名发送
+    PrepareCertificate {
+        view: u64,
+        sequence_number: u64,
+        digest: String,
+        signatures: Vec<(usize, Signature)>,
+    },
+    // 副本向主节点和对等节点索取本地缺少的交易
+    FetchPayload {
+        node_id: usize,
+        digests: Vec<String>,
+    },
+    Payload {
+        node_id: usize,
+        transactions: Vec<Transaction>, // 接收方按所请求的摘要校验，无需签名
+    },
+    // 副本收到尚未接受PrePrepare的实例的Commit，向对等节点索取主节点签名的PrePrepare；
+    // 对等节点原样转发主节点签名的消息，无需再签名
+    FetchPrePrepare {
+        node_id: usize,
+        view: u64,
+        sequence_number: u64,
+    },
+    // 验证者计划退出（例如停机维护），由其签名广播；对等节点不再等它担任主节点
+    Leave {
+        node_id: usize,
+        view: u64,
+    },
+    // 验证者进入或退出维护模式，由其签名广播；维护期间它不参与共识，对等节点不等它担任主节点
+    Maintenance {
+        node_id: usize,
+        enabled: bool,
+    },
+    // 本版本不认识的消息类型，内容被丢弃
+    #[serde(other)]
+    Unknown,
+}
+
+impl PBFTMessage {
+    // 消息类型名称，签名消息返回内部消息的类型
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PBFTMessage::Request { .. } => "Request",
+            PBFTMessage::Reply { .. } => "Reply",
+            PBFTMessage::PrePrepare { .. } => "PrePrepare",
+            PBFTMessage::Prepare { .. } => "Prepare",
+            PBFTMessage::Commit { .. } => "Commit",
+            PBFTMessage::ViewChange { .. } => "ViewChange",
+            PBFTMessage::NewView { .. } => "NewView",
+            PBFTMessage::SignedMessage { message, .. } | PBFTMessage::AuthenticatedMessage { message, .. } => message.kind(),
+            PBFTMessage::ByzantineVote { .. } => "ByzantineVote",
+            PBFTMessage::Appeal { .. } => "Appeal",
+            PBFTMessage::ClientRequest { .. } => "ClientRequest",
+            PBFTMessage::SubscribeBlocks { .. } => "SubscribeBlocks",
+            PBFTMessage::BlockAnnouncement { .. } => "BlockAnnouncement",
+            PBFTMessage::SubscribeConsensus { .. } => "SubscribeConsensus",
+            PBFTMessage::Bundle { .. } => "Bundle",
+            PBFTMessage::HandshakeChallenge { .. } => "HandshakeChallenge",
+            PBFTMessage::HandshakeResponse { .. } => "HandshakeResponse",
+            PBFTMessage::SnapshotRequest { .. } => "SnapshotRequest",
+            PBFTMessage::SnapshotOffer { .. } => "SnapshotOffer",
+            PBFTMessage::ChunkRequest { .. } => "ChunkRequest",
+            PBFTMessage::ChunkResponse { .. } => "ChunkResponse",
+            PBFTMessage::Checkpoint { .. } => "Checkpoint",
+            PBFTMessage::FetchRange { .. } => "FetchRange",
+            PBFTMessage::RangeBlocks { .. } => "RangeBlocks",
+            PBFTMessage::FetchHeaders { .. } => "FetchHeaders",
+            PBFTMessage::Headers { .. } => "Headers",
+            PBFTMessage::RelayConnect { .. } => "RelayConnect",
+            PBFTMessage::Ping { .. } => "Ping",
+            PBFTMessage::Pong { .. } => "Pong",
+            PBFTMessage::Relay { .. } => "Relay",
+            PBFTMessage::PrePrepareDigests { .. } => "PrePrepareDigests",
+            PBFTMessage::PrepareCertificate { .. } => "PrepareCertificate",
+            PBFTMessage::FetchPayload { .. } => "FetchPayload",
+            PBFTMessage::Payload { .. } => "Payload",
+            PBFTMessage::FetchPrePrepare { .. } => "FetchPrePrepare",
+            PBFTMessage::Leave { .. } => "Leave",
+            PBFTMessage::Maintenance { .. } => "Maintenance",
+            PBFTMessage::Unknown => "Unknown",
+        }
+    }
+
+    // 本版本无法处理的消息：类型未知，或签名消息内部的类型未知。后者的签名无法校验
+    // （重新编码的内容与签名时不同），不能当作签名无效而惩罚发送者
+    pub fn is_unknown(&self) -> bool {
+        match self {
+            PBFTMessage::Unknown => true,
+            PBFTMessage::SignedMessage { message, .. } | PBFTMessage::AuthenticatedMessage { message, .. } => message.is_unknown(),
+            _ => false,
+        }
+    }
+
+    // 客户端请求进入批次时对应的交易
+    pub fn to_transaction(&self) -> Option<Transaction> {
+        match self {
+            PBFTMessage::Request { operation, client_id, session, timestamp, .. } => Some(Transaction {
+                operation: operation.clone(),
+                client_id: client_id.clone(),
+                session: session.clone(),
+                timestamp: *timestamp,
+            }),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,51 @@
+// src/metrics.rs
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use serde::{Serialize, Deserialize};
+use crate::config::METRICS_HISTOGRAM_SAMPLES;
+
+lazy_static::lazy_static! {
+    pub static ref METRICS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+    // 直方图：观测总数和最近METRICS_HISTOGRAM_SAMPLES个观测值
+    static ref HISTOGRAMS: Mutex<BTreeMap<String, (u64, VecDeque<u64>)>> = Mutex::new(BTreeMap::new());
+    pub static ref STARTED_AT: SystemTime = SystemTime::now();
+}
+
+pub fn inc_counter(name: &str, value: u64) {
+    let mut metrics = METRICS.lock().unwrap();
+    *metrics.entry(name.to_string()).or_insert(0) += value;
+}
+
+pub fn snapshot() -> BTreeMap<String, u64> {
+    METRICS.lock().unwrap().clone()
+}
+
+pub fn observe(name: &str, value: u64) {
+    let mut histograms = HISTOGRAMS.lock().unwrap();
+    let (count, samples) = histograms.entry(name.to_string()).or_default();
+    *count += 1;
+    if samples.len() == METRICS_HISTOGRAM_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back(value);
+}
+
+// 直方图的分位数，按最近的观测值计算
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Percentiles {
+    pub count: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+pub fn percentiles() -> BTreeMap<String, Percentiles> {
+    HISTOGRAMS.lock().unwrap().iter().map(|(name, (count, samples))| {
+        let mut sorted: Vec<u64> = samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let at = |p: f64| sorted.get(((sorted.len() as f64 * p).ceil() as usize).max(1) - 1).copied().unwrap_or(0);
+        (name.clone(), Percentiles { count: *count, p50: at(0.5), p90: at(0.9), p99: at(0.99), max: at(1.0) })
+    }).collect()
+}
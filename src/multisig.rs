@@ -0,0 +1,342 @@
+// src/multisig.rs
+
+// 多重签名账户（m-of-n授权），例如三把密钥中任意两把同意才能动用的托管资产。
+// 账户保存在复制状态中，键为 multisig/<账户>，记录门限、公钥列表和已执行的授权次数；
+// 账户名下的数据保存在 custody/<账户>/ 下，只能由收集够门限个签名的 MULTISIG 操作修改。
+// - 创建账户须由全部公钥签名，证明创建者持有这些密钥，账户名先到先得，创建后不可更改
+// - 授权操作按nonce顺序执行（账户已执行次数+1），同一份签名不能重放
+// 节点在准入时按当前状态检查签名，执行时再按执行前的状态检查一次，各副本结果一致。
+// 签名在客户端离线收集：pbft-blockchain multisig propose 生成提案文件，各持有者用 sign 加上
+// 自己的签名，merge 合并分头收集的签名，operation 输出可直接提交的操作
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use zeroize::Zeroizing;
+use crate::crypto::{PublicKey, Signature, SigningKey};
+use crate::domain;
+use crate::quota::Sandbox;
+
+pub const KEY_PREFIX: &str = "multisig/";
+pub const CUSTODY_PREFIX: &str = "custody/";
+pub const MULTISIG_COMMAND: &str = "MULTISIG";
+pub const MAX_ACCOUNT_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub threshold: usize,
+    pub public_keys: Vec<String>, // 十六进制编码的ed25519公钥
+    pub nonce: u64, // 已执行的授权操作数
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create { threshold: usize, public_keys: Vec<String> },
+    Execute { operation: String }, // 对 custody/<账户>/ 下的键的 SET、DEL、APPEND 或 GET
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Proposal {
+    pub account: String,
+    pub nonce: u64, // 创建账户为0，授权操作为账户已执行次数+1
+    pub action: Action,
+    #[serde(default)]
+    pub signatures: BTreeMap<usize, String>, // 公钥在账户公钥列表中的下标 -> 十六进制签名
+}
+
+pub fn key(account: &str) -> String {
+    format!("{}{}", KEY_PREFIX, account)
+}
+
+pub fn custody_prefix(account: &str) -> String {
+    format!("{}{}/", CUSTODY_PREFIX, account)
+}
+
+pub fn lookup(store: &BTreeMap<String, String>, account: &str) -> Option<Account> {
+    store.get(&key(account)).and_then(|data| serde_json::from_str(data).ok())
+}
+
+impl Proposal {
+    pub fn new(account: &str, nonce: u64, action: Action) -> Self {
+        Proposal { account: account.to_string(), nonce, action, signatures: BTreeMap::new() }
+    }
+
+    fn signing_payload(&self) -> Vec<u8> {
+        domain::tagged(domain::MULTISIG, &serde_json::to_vec(&(&self.account, self.nonce, &self.action)).unwrap())
+    }
+
+    // 持有账户第index把密钥的一方加上自己的签名
+    pub fn sign(&mut self, index: usize, signing_key: &SigningKey) {
+        let signature = signing_key.sign(&self.signing_payload());
+        self.signatures.insert(index, signature.to_hex());
+    }
+
+    // 合并另一份相同提案上收集到的签名，返回新增的签名数
+    pub fn merge(&mut self, other: &Proposal) -> Result<usize, String> {
+        if (&self.account, self.nonce, &self.action) != (&other.account, other.nonce, &other.action) {
+            return Err("两份提案的内容不同，不能合并签名".to_string());
+        }
+        let before = self.signatures.len();
+        for (index, signature) in &other.signatures {
+            self.signatures.entry(*index).or_insert_with(|| signature.clone());
+        }
+        Ok(self.signatures.len() - before)
+    }
+
+    // 提交给集群的操作：MULTISIG <提案JSON>
+    pub fn operation(&self) -> String {
+        format!("{} {}", MULTISIG_COMMAND, serde_json::to_string(self).unwrap())
+    }
+
+    // 按账户的当前状态检查提案，返回执行后的账户
+    pub fn verify(&self, existing: Option<&Account>) -> Result<Account, String> {
+        let account = match (&self.action, existing) {
+            (Action::Create { .. }, Some(_)) => return Err(format!("账户{}已存在", self.account)),
+            (Action::Create { threshold, public_keys }, None) => {
+                if self.account.is_empty() || self.account.len() > MAX_ACCOUNT_LEN || self.account.contains('/') || self.account.contains(char::is_whitespace) {
+                    return Err(format!("账户名'{}'无效", self.account));
+                }
+                if *threshold == 0 || *threshold > public_keys.len() {
+                    return Err(format!("门限{}不在1到{}之间", threshold, public_keys.len()));
+                }
+                for public_key in public_keys {
+                    PublicKey::from_hex(public_key)?;
+                }
+                if self.nonce != 0 {
+                    return Err("创建账户的nonce必须为0".to_string());
+                }
+                // 创建时由全部密钥签名
+                Account { threshold: public_keys.len(), public_keys: public_keys.clone(), nonce: 0 }
+            }
+            (Action::Execute { .. }, None) => return Err(format!("账户{}不存在", self.account)),
+            (Action::Execute { operation }, Some(account)) => {
+                if self.nonce != account.nonce + 1 {
+                    return Err(format!("nonce应为{}，提案为{}", account.nonce + 1, self.nonce));
+                }
+                check_operation(&self.account, operation)?;
+                account.clone()
+            }
+        };
+
+        let payload = self.signing_payload();
+        let mut valid = 0;
+        for (index, signature) in &self.signatures {
+            let public_key = account.public_keys.get(*index).ok_or_else(|| format!("账户没有第{}把密钥", index))?;
+            if !PublicKey::from_hex(public_key)?.verify(&payload, &Signature::from_hex(signature)?) {
+                return Err(format!("第{}把密钥的签名无效", index));
+            }
+            valid += 1;
+        }
+        if valid < account.threshold {
+            return Err(format!("只有{}个有效签名，需要{}个", valid, account.threshold));
+        }
+
+        Ok(match &self.action {
+            Action::Create { threshold, public_keys } => Account { threshold: *threshold, public_keys: public_keys.clone(), nonce: 0 },
+            Action::Execute { .. } => Account { nonce: self.nonce, ..account },
+        })
+    }
+}
+
+// 授权操作只能读写本账户名下的键
+fn check_operation(account: &str, operation: &str) -> Result<(), String> {
+    let mut parts = operation.splitn(3, ' ');
+    let command = parts.next().unwrap_or("");
+    let key = parts.next().unwrap_or("");
+    let has_value = parts.next().is_some();
+    match command {
+        "SET" | "APPEND" if has_value => {}
+        "DEL" | "GET" if !has_value => {}
+        _ => return Err(format!("授权操作'{}'不是有效的键值操作", operation)),
+    }
+    if !key.starts_with(&custody_prefix(account)) {
+        return Err(format!("授权操作只能访问{}下的键", custody_prefix(account)));
+    }
+    Ok(())
+}
+
+// 准入检查：按当前状态验证提案，不修改状态
+pub fn check(store: &BTreeMap<String, String>, payload: &str) -> Result<(), String> {
+    let proposal: Proposal = serde_json::from_str(payload).map_err(|e| format!("无法解析多签提案: {}", e))?;
+    proposal.verify(lookup(store, &proposal.account).as_ref()).map(|_| ())
+}
+
+// 执行提案：更新账户，返回需要执行的授权操作（创建账户时为None）
+pub fn apply(store: &mut Sandbox, payload: &str) -> Result<Option<String>, String> {
+    let proposal: Proposal = serde_json::from_str(payload).map_err(|e| format!("无法解析多签提案: {}", e))?;
+    let account = proposal.verify(lookup(store, &proposal.account).as_ref())?;
+    store.insert(key(&proposal.account), serde_json::to_string(&account).unwrap());
+    match proposal.action {
+        Action::Create { .. } => Ok(None),
+        Action::Execute { operation } => Ok(Some(operation)),
+    }
+}
+
+// 离线工具：pbft-blockchain multisig <子命令>，提案在文件之间传递
+//   create <提案文件> <账户> <门限> <公钥>...    生成创建账户的提案
+//   propose <提案文件> <账户> <nonce> <操作>     生成授权操作的提案
+//   sign <提案文件> <密钥下标> <私钥文件>         加上自己的签名并写回
+//   merge <提案文件> <其他提案文件>...            合并其他持有者收集的签名
+//   operation <提案文件>                         输出可提交的MULTISIG操作
+pub fn run(args: &[String]) -> i32 {
+    match command(args) {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(reason) => {
+            eprintln!("{}", reason);
+            2
+        }
+    }
+}
+
+fn command(args: &[String]) -> Result<String, String> {
+    let usage = "用法: pbft-blockchain multisig create|propose|sign|merge|operation <提案文件> ...";
+    let (subcommand, path, rest) = match args {
+        [subcommand, path, rest @ ..] => (subcommand.as_str(), path.as_str(), rest),
+        _ => return Err(usage.to_string()),
+    };
+    let load = |path: &str| -> Result<Proposal, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| format!("无法读取{}: {}", path, e))?;
+        serde_json::from_str(&data).map_err(|e| format!("{}不是有效的提案: {}", path, e))
+    };
+    let save = |proposal: &Proposal| -> Result<String, String> {
+        std::fs::write(path, serde_json::to_string_pretty(proposal).unwrap()).map_err(|e| format!("无法写入{}: {}", path, e))?;
+        Ok(format!("提案{}已有{}个签名", path, proposal.signatures.len()))
+    };
+    let number = |value: &str| value.parse::<u64>().map_err(|_| format!("'{}'不是有效的数字", value));
+
+    match (subcommand, rest) {
+        ("create", [account, threshold, keys @ ..]) => {
+            let action = Action::Create { threshold: number(threshold)? as usize, public_keys: keys.to_vec() };
+            save(&Proposal::new(account, 0, action))
+        }
+        ("propose", [account, nonce, operation @ ..]) if !operation.is_empty() => {
+            let action = Action::Execute { operation: operation.join(" ") };
+            save(&Proposal::new(account, number(nonce)?, action))
+        }
+        ("sign", [index, key_file]) => {
+            let mut proposal = load(path)?;
+            // 私钥文件必须已存在，不像节点启动时那样自动生成
+            let secret = Zeroizing::new(std::fs::read_to_string(key_file).map_err(|e| format!("无法读取{}: {}", key_file, e))?);
+            let bytes = Zeroizing::new(hex::decode(secret.trim()).map_err(|_| format!("{}不是有效的十六进制", key_file))?);
+            proposal.sign(number(index)? as usize, &SigningKey::from_secret_bytes(&bytes)?);
+            save(&proposal)
+        }
+        ("merge", others) if !others.is_empty() => {
+            let mut proposal = load(path)?;
+            for other in others {
+                proposal.merge(&load(other)?)?;
+            }
+            save(&proposal)
+        }
+        ("operation", []) => Ok(load(path)?.operation()),
+        _ => Err(usage.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::metrics;
+    use crate::quota::Quota;
+    use crate::testing::TestCluster;
+
+    fn keys() -> (Vec<SigningKey>, Vec<String>) {
+        let keys: Vec<SigningKey> = (0..3).map(|_| SigningKey::generate()).collect();
+        let public_keys = keys.iter().map(|key| key.public_key().to_hex()).collect();
+        (keys, public_keys)
+    }
+
+    fn signed(account: &str, nonce: u64, action: Action, keys: &[(usize, &SigningKey)]) -> Proposal {
+        let mut proposal = Proposal::new(account, nonce, action);
+        for (index, key) in keys {
+            proposal.sign(*index, key);
+        }
+        proposal
+    }
+
+    #[test]
+    fn two_of_three_custody() {
+        let (keys, public_keys) = keys();
+        let mut state = BTreeMap::new();
+        let mut store = Sandbox::new(&mut state, Quota::default());
+        let create = Action::Create { threshold: 2, public_keys };
+
+        // 创建须由全部密钥签名
+        let partial = signed("vault", 0, create.clone(), &[(0, &keys[0]), (1, &keys[1])]);
+        assert!(apply(&mut store, &serde_json::to_string(&partial).unwrap()).is_err());
+        let created = signed("vault", 0, create, &[(0, &keys[0]), (1, &keys[1]), (2, &keys[2])]);
+        assert_eq!(apply(&mut store, &serde_json::to_string(&created).unwrap()), Ok(None));
+        assert_eq!(lookup(&store, "vault").unwrap().threshold, 2);
+        assert!(apply(&mut store, &serde_json::to_string(&created).unwrap()).is_err());
+
+        // 两个持有者分头签名，合并后达到门限
+        let transfer = Action::Execute { operation: "SET custody/vault/balance 90".to_string() };
+        let mut first = signed("vault", 1, transfer.clone(), &[(0, &keys[0])]);
+        assert!(check(&store, &serde_json::to_string(&first).unwrap()).unwrap_err().contains("需要2个"));
+        let second = signed("vault", 1, transfer.clone(), &[(2, &keys[2])]);
+        assert_eq!(first.merge(&second), Ok(1));
+        assert_eq!(first.merge(&second), Ok(0));
+        assert!(first.merge(&Proposal::new("vault", 2, transfer.clone())).is_err());
+        let payload = serde_json::to_string(&first).unwrap();
+        assert!(check(&store, &payload).is_ok());
+        assert_eq!(apply(&mut store, &payload), Ok(Some("SET custody/vault/balance 90".to_string())));
+        assert_eq!(lookup(&store, "vault").unwrap().nonce, 1);
+
+        // 重放、错配密钥下标、越界访问都被拒绝
+        assert!(apply(&mut store, &payload).is_err());
+        let misattributed = signed("vault", 2, transfer, &[(0, &keys[1]), (1, &keys[0])]);
+        assert!(check(&store, &serde_json::to_string(&misattributed).unwrap()).is_err());
+        let outside = signed("vault", 2, Action::Execute { operation: "SET custody/other/balance 0".to_string() }, &[(0, &keys[0]), (1, &keys[1])]);
+        assert!(check(&store, &serde_json::to_string(&outside).unwrap()).is_err());
+    }
+
+    fn counter(name: &str) -> u64 {
+        metrics::snapshot().get(name).copied().unwrap_or(0)
+    }
+
+    fn executed(cluster: &TestCluster, key: &str) -> Vec<Option<String>> {
+        cluster.executions.iter().map(|e| e.lock().unwrap().get(key).cloned()).collect()
+    }
+
+    // 2-of-3账户：签名不足的操作在准入时被拒绝，不进入共识；签名足够的操作在各副本上执行
+    #[tokio::test]
+    async fn cluster_executes_only_authorized_operations() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let (keys, public_keys) = keys();
+            let create = signed("vault", 0, Action::Create { threshold: 2, public_keys }, &[(0, &keys[0]), (1, &keys[1]), (2, &keys[2])]);
+            cluster.submit(&create.operation()).await;
+            let created = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.executions[id].lock().unwrap().get(&key("vault")).is_some())
+            }).await;
+            assert!(created, "多签账户未创建");
+
+            let rejected = counter("multisig_rejected_total");
+            let action = Action::Execute { operation: "SET custody/vault/balance 90".to_string() };
+            let insufficient = signed("vault", 1, action.clone(), &[(1, &keys[1])]);
+            cluster.submit(&insufficient.operation()).await;
+            let dropped = cluster.wait_until(Duration::from_secs(2), |_| counter("multisig_rejected_total") >= rejected + N as u64).await;
+            assert!(dropped, "签名不足的多签操作未在准入时被拒绝");
+
+            let authorized = signed("vault", 1, action, &[(0, &keys[0]), (2, &keys[2])]);
+            cluster.submit(&authorized.operation()).await;
+            let applied = cluster.wait_until(Duration::from_secs(5), |c| {
+                executed(c, "custody/vault/balance").iter().all(|value| value.as_deref() == Some("90"))
+            }).await;
+            assert!(applied, "达到门限的多签操作未执行");
+
+            // 直接写入托管键的普通操作在执行时失败，之后的请求照常执行
+            cluster.submit("SET custody/vault/balance 0").await;
+            cluster.submit("SET after x").await;
+            let done = cluster.wait_until(Duration::from_secs(5), |c| {
+                executed(c, "after").iter().all(|value| value.is_some())
+            }).await;
+            assert!(done, "后续请求未执行");
+            assert!(executed(&cluster, "custody/vault/balance").iter().all(|value| value.as_deref() == Some("90")));
+        }).await;
+    }
+}
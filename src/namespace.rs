@@ -0,0 +1,182 @@
+// src/namespace.rs
+
+// 多租户命名空间：多个应用共用一个共识集群时，各自的数据放在 ns/<命名空间>/ 下，互不覆盖。
+// 命名空间登记在复制状态中，键为 namespace/<命名空间>，记录所有者和获准写入的客户端：
+// - NAMESPACE CREATE <命名空间>：发起请求的客户端创建并成为所有者，名称先到先得
+// - NAMESPACE GRANT <命名空间> <客户端> / NAMESPACE REVOKE <命名空间> <客户端>：所有者增删写入者
+// ns/<命名空间>/ 下的键只有所有者和写入者能修改，读取不受限制；命名空间之外的键仍是共享空间，
+// 未登记的命名空间下的键任何人都不能写入。权限按交易携带的客户端ID判断，
+// 只有启用 clients.json 时客户端ID才经过签名认证，匿名请求不能创建或写入命名空间
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Serialize, Deserialize};
+use crate::quota::Sandbox;
+
+pub const KEY_PREFIX: &str = "namespace/";
+pub const DATA_PREFIX: &str = "ns/";
+pub const NAMESPACE_COMMAND: &str = "NAMESPACE";
+pub const MAX_NAMESPACE_LEN: usize = 64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    pub owner: String,
+    pub writers: BTreeSet<String>, // 所有者之外获准写入的客户端
+}
+
+impl Namespace {
+    pub fn can_write(&self, client: &str) -> bool {
+        self.owner == client || self.writers.contains(client)
+    }
+}
+
+pub fn key(name: &str) -> String {
+    format!("{}{}", KEY_PREFIX, name)
+}
+
+pub fn lookup(store: &BTreeMap<String, String>, name: &str) -> Option<Namespace> {
+    store.get(&key(name)).and_then(|data| serde_json::from_str(data).ok())
+}
+
+// 键所属的命名空间，不在 ns/ 下时为None
+pub fn of_key(key: &str) -> Option<&str> {
+    key.strip_prefix(DATA_PREFIX).map(|rest| rest.split('/').next().unwrap_or(rest))
+}
+
+// 操作是否用到命名空间：NAMESPACE操作，或读写 ns/ 下的键
+pub fn touches(operation: &str) -> bool {
+    let mut parts = operation.splitn(3, ' ');
+    let command = parts.next().unwrap_or("");
+    command == NAMESPACE_COMMAND || parts.next().is_some_and(|key| key.starts_with(DATA_PREFIX))
+}
+
+// 检查客户端能否修改键；命名空间之外的键不受限制
+pub fn check_write(store: &BTreeMap<String, String>, client: Option<&str>, key: &str) -> Result<(), String> {
+    let name = match of_key(key) {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    let namespace = lookup(store, name).ok_or_else(|| format!("命名空间{}未登记", name))?;
+    match client {
+        Some(client) if namespace.can_write(client) => Ok(()),
+        Some(client) => Err(format!("客户端{}无权写入命名空间{}", client, name)),
+        None => Err(format!("匿名请求不能写入命名空间{}", name)),
+    }
+}
+
+// 执行NAMESPACE操作，client为发起请求的客户端
+pub fn apply(store: &mut Sandbox, client: Option<&str>, payload: &str) -> Result<(), String> {
+    let client = client.ok_or("匿名请求不能管理命名空间")?;
+    let parts: Vec<&str> = payload.split(' ').collect();
+    match parts.as_slice() {
+        ["CREATE", name] => {
+            if name.is_empty() || name.len() > MAX_NAMESPACE_LEN || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(format!("命名空间名'{}'无效", name));
+            }
+            if lookup(store, name).is_some() {
+                return Err(format!("命名空间{}已存在", name));
+            }
+            let namespace = Namespace { owner: client.to_string(), writers: BTreeSet::new() };
+            store.insert(key(name), serde_json::to_string(&namespace).unwrap());
+            Ok(())
+        }
+        [action @ ("GRANT" | "REVOKE"), name, writer] => {
+            let mut namespace = lookup(store, name).ok_or_else(|| format!("命名空间{}不存在", name))?;
+            if namespace.owner != client {
+                return Err(format!("只有所有者{}能修改命名空间{}的写入者", namespace.owner, name));
+            }
+            if *action == "GRANT" {
+                namespace.writers.insert(writer.to_string());
+            } else {
+                namespace.writers.remove(*writer);
+            }
+            store.insert(key(name), serde_json::to_string(&namespace).unwrap());
+            Ok(())
+        }
+        _ => Err(format!("无法识别的命名空间操作: {}", payload)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::execution::{ExecutionEngine, ExecutionStatus};
+    use crate::message::{PBFTMessage, Transaction};
+    use crate::qos::Priority;
+    use crate::testing::TestCluster;
+
+    fn transaction(client: Option<&str>, operation: &str) -> Transaction {
+        Transaction { operation: operation.to_string(), client_id: client.map(str::to_string), session: None, timestamp: None }
+    }
+
+    #[test]
+    fn only_owner_and_writers_modify_namespace() {
+        let mut engine = ExecutionEngine::new();
+        let results = engine.execute_block(1, &[
+            transaction(Some("alice"), "NAMESPACE CREATE shop"),
+            transaction(Some("bob"), "NAMESPACE CREATE shop"),
+            transaction(None, "NAMESPACE CREATE anon"),
+            transaction(Some("alice"), "SET ns/shop/stock 10"),
+            transaction(Some("bob"), "SET ns/shop/stock 0"),
+            transaction(None, "DEL ns/shop/stock"),
+            transaction(Some("bob"), "SET ns/blog/title x"),
+            transaction(Some("bob"), "SET namespace/shop {}"),
+            transaction(Some("bob"), "GET ns/shop/stock"),
+            transaction(Some("bob"), "NAMESPACE GRANT shop bob"),
+        ]);
+        let failed: Vec<bool> = results.iter().map(|r| matches!(r.status, ExecutionStatus::Failed(_))).collect();
+        assert_eq!(failed, [false, true, true, false, true, true, true, true, false, true]);
+        assert_eq!(engine.get("ns/shop/stock"), Some(&"10".to_string()));
+
+        // 所有者授权后写入者可以修改，撤销后不能
+        engine.execute_block(2, &[
+            transaction(Some("alice"), "NAMESPACE GRANT shop bob"),
+            transaction(Some("bob"), "APPEND ns/shop/stock 0"),
+        ]);
+        assert_eq!(engine.get("ns/shop/stock"), Some(&"100".to_string()));
+        engine.execute_block(3, &[transaction(Some("alice"), "NAMESPACE REVOKE shop bob")]);
+        let results = engine.execute_block(4, &[transaction(Some("bob"), "DEL ns/shop/stock"), transaction(Some("bob"), "SET shared x")]);
+        assert!(matches!(results[0].status, ExecutionStatus::Failed(_)));
+        assert_eq!(results[1].status, ExecutionStatus::Success(None));
+        assert_eq!(lookup(engine.state(), "shop").unwrap().writers, BTreeSet::new());
+    }
+
+    // 两个应用各自的命名空间在集群中互不干扰，对方的写入在各副本上都失败
+    #[tokio::test]
+    async fn tenants_cannot_clobber_each_other() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let submit = |client: &str, operation: &str| PBFTMessage::Request {
+                operation: operation.to_string(),
+                priority: Priority::Normal,
+                client_id: Some(client.to_string()),
+                expires_at: None,
+                session: None,
+                timestamp: None,
+            };
+            for (client, operation) in [
+                ("alice", "NAMESPACE CREATE shop"),
+                ("bob", "NAMESPACE CREATE blog"),
+                ("alice", "SET ns/shop/config a"),
+                ("bob", "SET ns/blog/config b"),
+                ("bob", "SET ns/shop/config clobbered"),
+                ("alice", "SET done yes"),
+            ] {
+                cluster.submit_request(submit(client, operation)).await;
+                let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                    (0..N).all(|id| c.committed_view(id, operation).is_some())
+                }).await;
+                assert!(committed, "{}未提交", operation);
+            }
+            let executed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.executions[id].lock().unwrap().get("done").is_some())
+            }).await;
+            assert!(executed, "请求未执行");
+            for id in 0..N {
+                let execution = cluster.executions[id].lock().unwrap();
+                assert_eq!(execution.get("ns/shop/config").map(String::as_str), Some("a"), "节点{}的命名空间被覆盖", id);
+                assert_eq!(execution.get("ns/blog/config").map(String::as_str), Some("b"));
+            }
+        }).await;
+    }
+}
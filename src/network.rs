@@ -1,26 +1,497 @@
 // src/network.rs
 use tokio::sync::mpsc::Sender;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+use crate::capture::{self, Direction};
+use crate::firewall::Firewall;
 use crate::message::PBFTMessage;
-use std::collections::HashMap;
+use crate::metrics;
+use crate::config::{RPC_BASE_PORT, LISTEN_HOSTS, LISTEN_ADDRESSES_ENV, DIAL_TIMEOUT_MS, NETWORK_FAULTS_FILE, MAX_REORDER_DELAY_MS};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
-use log::debug;
+use serde::{Serialize, Deserialize};
+use rand::Rng;
+use log::{debug, error, info, warn};
+
+pub struct Peer {
+    pub magic: [u8; 4],
+    pub sender: Sender<PBFTMessage>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MessageTraffic {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct PeerTraffic {
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    pub sent_by_type: BTreeMap<String, MessageTraffic>,
+    pub received_by_type: BTreeMap<String, MessageTraffic>,
+}
+
+pub type Outbox = HashMap<(usize, usize), Vec<PBFTMessage>>;
+
+// 一条链路上的故障概率，取值0到1
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkFaults {
+    #[serde(default)]
+    pub drop: f64, // 消息丢失
+    #[serde(default)]
+    pub duplicate: f64, // 消息被投递两次
+    #[serde(default)]
+    pub reorder: f64, // 消息被推迟，之后发送的消息可能先到
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LinkOverride {
+    pub from: usize,
+    pub to: usize,
+    #[serde(flatten)]
+    pub faults: LinkFaults,
+}
+
+// 模拟不可靠的网络：单独配置的链路使用自己的概率，其余链路使用全局概率
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NetworkFaults {
+    #[serde(default)]
+    pub global: LinkFaults,
+    #[serde(default)]
+    pub links: Vec<LinkOverride>,
+}
+
+impl NetworkFaults {
+    pub fn load() -> Self {
+        match std::fs::read_to_string(NETWORK_FAULTS_FILE) {
+            Ok(data) => {
+                let faults: NetworkFaults = serde_json::from_str(&data).unwrap();
+                warn!("从{}加载网络故障配置，全局: {:?}，单独配置的链路{}条", NETWORK_FAULTS_FILE, faults.global, faults.links.len());
+                faults
+            }
+            Err(_) => NetworkFaults::default(),
+        }
+    }
+
+    pub fn link(&self, from: usize, to: usize) -> LinkFaults {
+        self.links.iter()
+            .find(|link| link.from == from && link.to == to)
+            .map(|link| link.faults)
+            .unwrap_or(self.global)
+    }
+}
+
+// 本次发送的命运，按丢失、重复、乱序的顺序抽取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivery {
+    Dropped,
+    Normal { copies: usize, delayed: bool },
+}
+
+impl LinkFaults {
+    fn sample(&self) -> Delivery {
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f64>() < self.drop {
+            return Delivery::Dropped;
+        }
+        let copies = if rng.gen::<f64>() < self.duplicate { 2 } else { 1 };
+        Delivery::Normal { copies, delayed: rng.gen::<f64>() < self.reorder }
+    }
+}
 
 lazy_static::lazy_static! {
-    pub static ref NETWORK: Arc<Mutex<HashMap<usize, Sender<PBFTMessage>>>> = Arc::new(Mutex::new(HashMap::new()));
+    pub static ref NETWORK: Arc<Mutex<HashMap<usize, Peer>>> = Arc::new(Mutex::new(HashMap::new()));
+    // (发送节点, 接收节点) -> 待刷新的消息
+    pub static ref OUTBOX: Arc<Mutex<Outbox>> = Arc::new(Mutex::new(HashMap::new()));
+    // (中继节点, 被中继节点) -> 被中继节点主动建立的出站连接
+    pub static ref RELAY_CONNECTIONS: Arc<Mutex<HashMap<(usize, usize), Peer>>> = Arc::new(Mutex::new(HashMap::new()));
+    // 被中继节点 -> 已接受其连接的中继节点，按优先级排列
+    pub static ref RELAY_ROUTES: Arc<Mutex<HashMap<usize, Vec<usize>>>> = Arc::new(Mutex::new(HashMap::new()));
+    // 客户端ID -> 接收答复的连接
+    pub static ref CLIENTS: Arc<Mutex<HashMap<String, Sender<PBFTMessage>>>> = Arc::new(Mutex::new(HashMap::new()));
+    // 本地节点ID -> 对端节点ID -> 流量统计
+    pub static ref TRAFFIC: Arc<Mutex<HashMap<usize, BTreeMap<usize, PeerTraffic>>>> = Arc::new(Mutex::new(HashMap::new()));
+    // 注入的链路故障
+    pub static ref FAULTS: Mutex<NetworkFaults> = Mutex::new(NetworkFaults::default());
+    // 本地节点ID -> 传输层防火墙，未设置的节点接受所有对等节点
+    pub static ref FIREWALLS: Mutex<HashMap<usize, Arc<Mutex<Firewall>>>> = Mutex::new(HashMap::new());
 }
 
-pub async fn send_message(node_id: usize, msg: PBFTMessage) {
-    let network = NETWORK.lock().unwrap();
-    if let Some(sender) = network.get(&node_id) {
-        debug!("发送消息到节点{}: {:?}", node_id, msg);
-        let _ = sender.send(msg).await;
+pub fn set_faults(faults: NetworkFaults) {
+    *FAULTS.lock().unwrap() = faults;
+}
+
+// 防火墙由节点和RPC服务共享，RPC可在运行时修改规则
+pub fn set_firewall(node_id: usize, firewall: Arc<Mutex<Firewall>>) {
+    FIREWALLS.lock().unwrap().insert(node_id, firewall);
+}
+
+// 接收节点的防火墙是否放行来自from的连接
+fn admits(node_id: usize, from: usize) -> bool {
+    if from == node_id {
+        return true;
+    }
+    let firewall = FIREWALLS.lock().unwrap().get(&node_id).cloned();
+    let admitted = firewall.map_or(true, |firewall| firewall.lock().unwrap().admits_peer(from));
+    if !admitted {
+        debug!("节点{}的防火墙拒绝节点{}的消息", node_id, from);
+        metrics::inc_counter("firewall_rejected_total", 1);
+    }
+    admitted
+}
+
+pub async fn send_message(magic: [u8; 4], from: usize, node_id: usize, msg: PBFTMessage) {
+    // 没有公网地址的节点无法直接连接，消息交给它的中继节点转发
+    let (node_id, msg) = match relay_route(node_id) {
+        Some(relay) => {
+            debug!("经由中继节点{}向节点{}发送消息", relay, node_id);
+            (relay, PBFTMessage::Relay { from, to: node_id, message: Box::new(msg) })
+        }
+        None => (node_id, msg),
+    };
+    if !admits(node_id, from) {
+        return;
+    }
+
+    let sender = {
+        let network = NETWORK.lock().unwrap();
+        match network.get(&node_id) {
+            Some(peer) if peer.magic == magic => Some(peer.sender.clone()),
+            Some(peer) => {
+                // 握手时登记的网络魔数不一致，说明对端属于其他链
+                error!("拒绝发送到节点{}：网络魔数不匹配（本地{}，对端{}）",
+                    node_id, hex::encode(magic), hex::encode(peer.magic));
+                None
+            }
+            None => {
+                debug!("节点{}的发送器未注册", node_id);
+                None
+            }
+        }
+    };
+
+    if let Some(sender) = sender {
+        let faults = FAULTS.lock().unwrap().link(from, node_id);
+        dispatch(sender, from, node_id, msg, faults).await;
+    }
+}
+
+// 按链路的故障概率投递消息
+async fn dispatch(sender: Sender<PBFTMessage>, from: usize, node_id: usize, msg: PBFTMessage, faults: LinkFaults) {
+    let (copies, delayed) = match faults.sample() {
+        Delivery::Dropped => {
+            debug!("模拟丢包：节点{}发往节点{}的{}消息被丢弃", from, node_id, msg.kind());
+            metrics::inc_counter("network_faults_dropped_total", 1);
+            return;
+        }
+        Delivery::Normal { copies, delayed } => (copies, delayed),
+    };
+    if copies > 1 {
+        metrics::inc_counter("network_faults_duplicated_total", 1);
+    }
+    debug!("发送消息到节点{}: {:?}", node_id, msg);
+    if delayed {
+        // 推迟投递，之后发送的消息可能先到达
+        metrics::inc_counter("network_faults_reordered_total", 1);
+        let delay = Duration::from_millis(rand::thread_rng().gen_range(1, MAX_REORDER_DELAY_MS + 1));
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            deliver(&sender, from, node_id, msg, copies).await;
+        });
     } else {
-        debug!("节点{}的发送器未注册", node_id);
+        deliver(&sender, from, node_id, msg, copies).await;
     }
 }
 
-pub fn register_node(node_id: usize, sender: Sender<PBFTMessage>) {
+async fn deliver(sender: &Sender<PBFTMessage>, from: usize, node_id: usize, msg: PBFTMessage, copies: usize) {
+    let kind = msg.kind();
+    let frame = serde_json::to_vec(&msg).unwrap_or_default();
+    for _ in 1..copies {
+        if sender.send(msg.clone()).await.is_ok() {
+            record_frame(from, node_id, kind, &frame);
+        }
+    }
+    if sender.send(msg).await.is_ok() {
+        record_frame(from, node_id, kind, &frame);
+    }
+}
+
+// 缓存消息，直到发送节点显式调用flush
+pub fn queue_message(from: usize, node_id: usize, msg: PBFTMessage) {
+    OUTBOX.lock().unwrap().entry((from, node_id)).or_default().push(msg);
+}
+
+// 将发送节点缓存的消息按接收节点合并，每个接收节点只写一次
+pub async fn flush(magic: [u8; 4], from: usize) {
+    let pending: Vec<(usize, Vec<PBFTMessage>)> = {
+        let mut outbox = OUTBOX.lock().unwrap();
+        let keys: Vec<(usize, usize)> = outbox.keys().filter(|(f, _)| *f == from).cloned().collect();
+        keys.into_iter().filter_map(|key| outbox.remove(&key).map(|msgs| (key.1, msgs))).collect()
+    };
+
+    for (node_id, mut messages) in pending {
+        let msg = if messages.len() == 1 {
+            messages.pop().unwrap()
+        } else {
+            debug!("节点{}向节点{}合并发送{}条消息", from, node_id, messages.len());
+            PBFTMessage::Bundle { messages }
+        };
+        send_message(magic, from, node_id, msg).await;
+    }
+}
+
+pub fn register_node(node_id: usize, magic: [u8; 4], sender: Sender<PBFTMessage>) {
     let mut network = NETWORK.lock().unwrap();
-    network.insert(node_id, sender);
-    debug!("节点{}已注册到网络中", node_id);
+    network.insert(node_id, Peer { magic, sender });
+    debug!("节点{}已注册到网络中，网络魔数: {}", node_id, hex::encode(magic));
+}
+
+pub fn register_client(client_id: &str, sender: Sender<PBFTMessage>) {
+    CLIENTS.lock().unwrap().insert(client_id.to_string(), sender);
+    debug!("客户端{}已注册到网络中", client_id);
+}
+
+// 把答复交给客户端的连接；客户端不在线或接收队列已满时丢弃，客户端可重发请求
+pub fn send_reply(client_id: &str, msg: PBFTMessage) -> Result<(), String> {
+    let sender = CLIENTS.lock().unwrap().get(client_id).cloned();
+    match sender {
+        Some(sender) => sender.try_send(msg).map_err(|_| format!("客户端{}的连接已关闭或接收队列已满", client_id)),
+        None => Err(format!("客户端{}未连接", client_id)),
+    }
+}
+
+// 位于NAT之后的节点向中继节点建立出站连接，中继节点通过该连接把消息转交给它
+pub fn connect_via_relay(relay_id: usize, node_id: usize, magic: [u8; 4], sender: Sender<PBFTMessage>) {
+    RELAY_CONNECTIONS.lock().unwrap().insert((relay_id, node_id), Peer { magic, sender });
+    debug!("节点{}建立到中继节点{}的出站连接", node_id, relay_id);
+}
+
+// 中继节点接受连接后，发往该节点的消息才会经由它转发
+pub fn accept_relay(relay_id: usize, node_id: usize) -> bool {
+    if !RELAY_CONNECTIONS.lock().unwrap().contains_key(&(relay_id, node_id)) {
+        return false;
+    }
+    let mut routes = RELAY_ROUTES.lock().unwrap();
+    let relays = routes.entry(node_id).or_default();
+    if !relays.contains(&relay_id) {
+        relays.push(relay_id);
+    }
+    true
+}
+
+pub fn reject_relay(relay_id: usize, node_id: usize) {
+    RELAY_CONNECTIONS.lock().unwrap().remove(&(relay_id, node_id));
+}
+
+// 目标节点不可直接连接时，选择第一个仍在线的中继节点
+fn relay_route(node_id: usize) -> Option<usize> {
+    if NETWORK.lock().unwrap().contains_key(&node_id) {
+        return None;
+    }
+    let relays = RELAY_ROUTES.lock().unwrap().get(&node_id).cloned()?;
+    let network = NETWORK.lock().unwrap();
+    relays.into_iter().find(|relay| network.contains_key(relay))
+}
+
+// 中继节点把消息转交给通过出站连接接入的节点，返回是否转发成功
+pub async fn forward_relayed(magic: [u8; 4], relay_id: usize, from: usize, to: usize, msg: PBFTMessage) -> bool {
+    // 被中继节点的防火墙按消息的原始发送者过滤，被拒绝的节点不能借中继绕过
+    if !admits(to, from) {
+        return false;
+    }
+    let sender = match RELAY_CONNECTIONS.lock().unwrap().get(&(relay_id, to)) {
+        Some(peer) if peer.magic == magic => peer.sender.clone(),
+        _ => return false,
+    };
+    let kind = msg.kind();
+    let frame = serde_json::to_vec(&msg).unwrap_or_default();
+    if sender.send(msg).await.is_err() {
+        return false;
+    }
+    record_frame(relay_id, to, kind, &frame);
+    metrics::inc_counter("relay_forwarded_total", 1);
+    true
+}
+
+// 投递成功的一帧：计入流量统计，并写入两端的抓包文件
+fn record_frame(from: usize, to: usize, kind: &str, frame: &[u8]) {
+    record_traffic(from, to, kind, frame.len() as u64);
+    capture::record(from, Direction::Sent, to, frame);
+    capture::record(to, Direction::Received, from, frame);
+}
+
+fn record_traffic(from: usize, to: usize, kind: &str, bytes: u64) {
+    let mut traffic = TRAFFIC.lock().unwrap();
+
+    let sent = traffic.entry(from).or_default().entry(to).or_default();
+    sent.messages_sent += 1;
+    sent.bytes_sent += bytes;
+    let by_type = sent.sent_by_type.entry(kind.to_string()).or_default();
+    by_type.messages += 1;
+    by_type.bytes += bytes;
+
+    let received = traffic.entry(to).or_default().entry(from).or_default();
+    received.messages_received += 1;
+    received.bytes_received += bytes;
+    let by_type = received.received_by_type.entry(kind.to_string()).or_default();
+    by_type.messages += 1;
+    by_type.bytes += bytes;
+    drop(traffic);
+
+    metrics::inc_counter("network_messages_sent_total", 1);
+    metrics::inc_counter("network_bytes_sent_total", bytes);
+    metrics::inc_counter(&format!("network_messages_sent_total{{type=\"{}\"}}", kind), 1);
+    metrics::inc_counter(&format!("network_bytes_sent_total{{type=\"{}\"}}", kind), bytes);
+}
+
+pub fn traffic_stats(node_id: usize) -> BTreeMap<usize, PeerTraffic> {
+    TRAFFIC.lock().unwrap().get(&node_id).cloned().unwrap_or_default()
+}
+
+// 节点的监听地址：优先使用环境变量中配置的地址，否则为各默认主机加上节点的RPC端口
+pub fn listen_addresses(node_id: usize) -> Vec<String> {
+    if let Ok(value) = std::env::var(LISTEN_ADDRESSES_ENV) {
+        let addresses: Vec<String> = value.split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if !addresses.is_empty() {
+            return addresses;
+        }
+    }
+    default_addresses(node_id)
+}
+
+// 未配置地址时节点监听的默认地址
+pub fn default_addresses(node_id: usize) -> Vec<String> {
+    let port = RPC_BASE_PORT + node_id as u16;
+    LISTEN_HOSTS.iter().map(|host| format_address(host, port)).collect()
+}
+
+// IPv6地址需要加方括号才能与端口拼接
+pub fn format_address(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+// 按顺序尝试各地址（域名会解析出全部IP），返回第一个可连接的地址
+pub async fn dial(addresses: &[String]) -> Option<(String, TcpStream)> {
+    for address in addresses {
+        let connect = timeout(Duration::from_millis(DIAL_TIMEOUT_MS), TcpStream::connect(address.as_str()));
+        match connect.await {
+            Ok(Ok(stream)) => {
+                debug!("连接{}成功", address);
+                return Some((address.clone(), stream));
+            }
+            Ok(Err(e)) => info!("无法连接{}: {}", address, e),
+            Err(_) => info!("连接{}超时", address),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    #[test]
+    fn links_override_global_faults() {
+        let faults: NetworkFaults = serde_json::from_str(
+            r#"{"global":{"drop":0.1,"reorder":0.2},"links":[{"from":0,"to":3,"drop":1.0}]}"#,
+        ).unwrap();
+        assert_eq!(faults.link(0, 1), LinkFaults { drop: 0.1, duplicate: 0.0, reorder: 0.2 });
+        assert_eq!(faults.link(0, 3), LinkFaults { drop: 1.0, duplicate: 0.0, reorder: 0.0 });
+        assert_eq!(faults.link(0, 3).sample(), Delivery::Dropped);
+        let duplicated = LinkFaults { drop: 0.0, duplicate: 1.0, reorder: 1.0 };
+        assert_eq!(duplicated.sample(), Delivery::Normal { copies: 2, delayed: true });
+        assert_eq!(LinkFaults::default().sample(), Delivery::Normal { copies: 1, delayed: false });
+    }
+
+    #[tokio::test]
+    async fn faulty_links_drop_duplicate_and_delay() {
+        let (sender, mut receiver) = mpsc::channel(10);
+        let message = |from| PBFTMessage::SnapshotRequest { node_id: from };
+
+        dispatch(sender.clone(), 1, 0, message(1), LinkFaults { drop: 1.0, ..Default::default() }).await;
+        dispatch(sender.clone(), 2, 0, message(2), LinkFaults { duplicate: 1.0, ..Default::default() }).await;
+        assert!(matches!(receiver.try_recv(), Ok(PBFTMessage::SnapshotRequest { node_id: 2 })));
+        assert!(matches!(receiver.try_recv(), Ok(PBFTMessage::SnapshotRequest { node_id: 2 })));
+        assert!(receiver.try_recv().is_err(), "丢弃的消息不应到达");
+
+        // 推迟的消息被之后发送的消息超过
+        dispatch(sender.clone(), 3, 0, message(3), LinkFaults { reorder: 1.0, ..Default::default() }).await;
+        dispatch(sender, 4, 0, message(4), LinkFaults::default()).await;
+        assert!(matches!(receiver.recv().await, Some(PBFTMessage::SnapshotRequest { node_id: 4 })));
+        assert!(matches!(receiver.recv().await, Some(PBFTMessage::SnapshotRequest { node_id: 3 })));
+    }
+    // 合并发送的捆绑与逐条发送到达的是同样的消息、同样的顺序，只是合成一次写入；只有一条时不包装
+    #[tokio::test]
+    async fn coalesced_flush_delivers_the_same_messages() {
+        tokio::task::LocalSet::new().run_until(async {
+            // 借用集群隔离的网络，节点0和1的入口由测试接管
+            let cluster = TestCluster::builder().nodes(0).build().await;
+            let magic = cluster.genesis.network_magic();
+            let (direct_tx, mut direct) = mpsc::channel(10);
+            let (coalesced_tx, mut coalesced) = mpsc::channel(10);
+            register_node(0, magic, direct_tx);
+            register_node(1, magic, coalesced_tx);
+            let messages = vec![
+                PBFTMessage::Prepare { view: 0, sequence_number: 1, digest: "d".to_string(), sender_id: 2 },
+                PBFTMessage::Commit { view: 0, sequence_number: 1, digest: "d".to_string() },
+                PBFTMessage::SnapshotRequest { node_id: 2 },
+            ];
+            let encode = |messages: &[PBFTMessage]| messages.iter().map(|m| serde_json::to_string(m).unwrap()).collect::<Vec<_>>();
+
+            for msg in &messages {
+                send_message(magic, 2, 0, msg.clone()).await;
+                queue_message(2, 1, msg.clone());
+            }
+            flush(magic, 2).await;
+            let sent: Vec<PBFTMessage> = (0..messages.len()).map(|_| direct.try_recv().unwrap()).collect();
+            let bundled = match coalesced.try_recv() {
+                Ok(PBFTMessage::Bundle { messages }) => messages,
+                other => panic!("应合并为一个捆绑: {:?}", other),
+            };
+            assert!(coalesced.try_recv().is_err(), "合并后只应写入一次");
+            assert_eq!(encode(&bundled), encode(&sent));
+            assert_eq!(encode(&bundled), encode(&messages));
+
+            queue_message(2, 1, messages[0].clone());
+            flush(magic, 2).await;
+            assert!(matches!(coalesced.try_recv(), Ok(PBFTMessage::Prepare { .. })));
+            flush(magic, 2).await;
+            assert!(coalesced.try_recv().is_err(), "空的发送缓存不应产生消息");
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn consensus_survives_unreliable_links() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            // 所有链路会重复和乱序，节点3发出的消息全部丢失
+            set_faults(NetworkFaults {
+                global: LinkFaults { drop: 0.0, duplicate: 0.2, reorder: 0.3 },
+                links: (0..N - 1).map(|to| LinkOverride { from: N - 1, to, faults: LinkFaults { drop: 1.0, ..Default::default() } }).collect(),
+            });
+            for i in 0..5 {
+                let operation = format!("SET k{} v", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(10), |c| {
+                    (0..N - 1).all(|id| c.committed_view(id, &operation).is_some())
+                }).await;
+                assert!(committed, "操作{}未提交", operation);
+            }
+            assert!(metrics::snapshot().get("network_faults_dropped_total").copied().unwrap_or(0) > 0);
+        }).await;
+    }
 }
@@ -1,24 +1,102 @@
 // src/node.rs
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc::Receiver;
-use tokio::time::{sleep, Duration, Instant};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::mpsc::{self, Receiver};
+use tokio::time::{Duration, Instant};
 use tokio::select;
-use crate::message::PBFTMessage;
-use crate::network::send_message;
-use crate::config::{F, N};
-use log::{info, error, debug};
-use ed25519_dalek::{Keypair, Signature, Signer, Verifier, PublicKey};
+use crate::message::{PBFTMessage, PreparedEntry, ReplyOutcome, Transaction};
+use crate::network::{self, send_message};
+use crate::quorum::{BLACKLIST_QUORUM, PREPARE_QUORUM, VIEW_CHANGE_QUORUM, WEAK_QUORUM};
+use crate::config::{N, MAX_REPUTATION, OTLP_ENDPOINT_ENV, VOTE_AGGREGATION, VOTE_AGGREGATION_TIMEOUT_MS, DIGEST_PREPREPARE, PAYLOAD_FETCH_TIMEOUT_MS, MAX_VIEW_CHANGE_TIMEOUT_MS, COALESCE_MESSAGES, PEER_DIRECTORY, SNAPSHOT_CACHE_SIZE, CHECKPOINT_INTERVAL, MAX_FETCH_RANGE, HEADER_SYNC_BATCH, HANDSHAKE_RETRY_MS, HANDSHAKE_BUFFER_MS, HANDSHAKE_BUFFER_SIZE, PROTOCOL_VERSION, CLOCK_PING_INTERVAL_MS, SIGNED_PREPREPARE_HISTORY, EXIT_DRAIN_TIMEOUT_MS, STATE_LOG_WINDOW, MAX_VIEW_CHANGE_MESSAGES, MAX_TRACKED_SUSPECTS, BYZANTINE_VOTE_VIEWS, MAX_PENDING_REQUESTS, STORAGE_PIPELINE_DEPTH, STORAGE_FLUSH_TIMEOUT_MS};
+use crate::genesis::{ConsensusParameters, Genesis};
+use crate::batching::BatchController;
+use crate::qos::QosScheduler;
+use crate::metrics;
+use crate::acl::ClientRegistry;
+use crate::address_book::AddressBook;
+use crate::admission::{AdmissionPolicy, DefaultAdmissionPolicy};
+use crate::reply_sink::{ReplySink, TransportSink};
+use crate::checkpoint::{CheckpointEvent, CheckpointTracker, StateRoots};
+use crate::chain::{self, Block, BlockHeader, Chain, CommitCertificate};
+use crate::byzantine::Strategy;
+use crate::clock::Clock;
+use crate::clock_sync::ClockSync;
+use crate::hash::Hasher;
+use crate::trace::{self, InstanceTrace, TraceContext};
+use crate::audit::{AuditEvent, AuditLog};
+use crate::consensus::{Action, ConsensusCore, Input, Record, Timer};
+use crate::phase::Phase;
+use crate::pipeline::{self, Inbound, KeyTable};
+use crate::mempool;
+use crate::multisig::{self, MULTISIG_COMMAND};
+use crate::beacon::{self, BEACON_COMMAND};
+use crate::archive::ArchiveIndex;
+use crate::chain_index::{self, ChainIndex};
+use crate::payload::PayloadFetch;
+use crate::evidence::Evidence;
+use crate::signing_policy::{Authentication, Authenticator, MacKeys};
+use crate::observer::{Auditor, Violation, ViolationKind};
+use crate::events::{ConsensusEvent, EventBus};
+use crate::reload::NodeConfig;
+use crate::execution::{ExecutionEngine, ExecutionStatus};
+use crate::reply_cache::Lookup;
+use crate::request_status::{RequestStatus, RequestTracker};
+use crate::state_sync::{SnapshotManifest, StateSnapshot, StateSync};
+use crate::header_sync::HeaderSync;
+use crate::directory::{self, DirectoryEntry, SignedEntry};
+use crate::governance::{self, ParameterChange};
+use crate::qos::Priority;
+use crate::leader::{self, LeaderElection, PerformanceTracker};
+use crate::latency_budget::{LatencyBudget, Vote};
+use crate::reputation::{self, Reputation};
+use crate::view_stats::ViewStats;
+use crate::storage::{self, StorageWriter};
+use crate::vote_aggregation;
+use crate::zones::Zones;
+use crate::report;
+use crate::alerts::{AlertKind, Alerts};
+use crate::domain;
+use log::{info, warn, error, debug};
+use crate::crypto::{PublicKey, Signature, SigningKey};
 use serde::{Serialize, Deserialize};
+use rand::rngs::OsRng;
+use rand::RngCore;
+#[cfg(feature = "console")]
+use crate::console::{Command, ConsoleRequest};
+
+// 未启用console特性时控制台不存在，也不会产生任何请求
+#[cfg(not(feature = "console"))]
+pub enum ConsoleRequest {}
+
+// 验证者参与共识；全节点只接收、验证并保存已提交的区块；
+// 归档节点在全节点基础上维护完整历史的二级索引；
+// 观察者节点订阅全部共识消息，被动审计协议合规性
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Validator,
+    FullNode,
+    Archive,
+    Observer,
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct NodeState {
     pub prepared: HashSet<(u64, String)>,
     pub committed: HashSet<(u64, String)>,
-    pub messages: Vec<PBFTMessage>,
     pub view_change_messages: Vec<PBFTMessage>,
-    pub byzantine_votes: HashMap<usize, HashSet<usize>>,
+    #[serde(default, deserialize_with = "scoped_votes")]
+    pub byzantine_votes: ByzantineVotes,
+}
+
+// 被指控节点 → 证据所属的视图 → 投票者
+pub type ByzantineVotes = HashMap<usize, BTreeMap<u64, HashSet<usize>>>;
+
+// 旧版本按被指控节点记录、不分视图的投票无法归入视图，加载时丢弃
+fn scoped_votes<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<ByzantineVotes, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).unwrap_or_default())
 }
 
 impl NodeState {
@@ -36,80 +114,427 @@ impl NodeState {
             NodeState {
                 prepared: HashSet::new(),
                 committed: HashSet::new(),
-                messages: Vec::new(),
                 view_change_messages: Vec::new(),
                 byzantine_votes: HashMap::new(),
             }
         }
     }
+
+    // 把各日志限制在上限内：Prepared/Committed只保留最新序列号往前STATE_LOG_WINDOW个序列号的记录，
+    // ViewChange消息超出上限时淘汰最早记录的非当前视图消息，当前视图正在收集的消息最后才淘汰。
+    // 每淘汰一条计入node_state_evicted_total
+    pub fn enforce_limits(&mut self, current_view: u64) {
+        let latest = self.prepared.iter().chain(&self.committed).map(|(seq, _)| *seq).max().unwrap_or(0);
+        let low = latest.saturating_sub(STATE_LOG_WINDOW);
+        for (kind, log) in [("prepared", &mut self.prepared), ("committed", &mut self.committed)] {
+            let before = log.len();
+            log.retain(|(seq, _)| *seq > low);
+            record_evictions(kind, before - log.len());
+        }
+        let mut evicted = 0;
+        while self.view_change_messages.len() > MAX_VIEW_CHANGE_MESSAGES {
+            let oldest = self.view_change_messages.iter()
+                .position(|m| !matches!(m, PBFTMessage::ViewChange { view, .. } if *view == current_view))
+                .unwrap_or(0);
+            self.view_change_messages.remove(oldest);
+            evicted += 1;
+        }
+        record_evictions("view_change", evicted);
+    }
+
+    // 记录voter凭view中的证据对suspect的拜占庭投票，返回suspect在该视图的票数；不同视图的票不合并。
+    // 被指控节点数达到上限时，先淘汰单个视图最高票数最少的其他节点（票数相同时淘汰ID最大的），返回被淘汰的节点
+    pub fn record_byzantine_vote(&mut self, suspect: usize, view: u64, voter: usize) -> (usize, Option<usize>) {
+        let mut evicted = None;
+        if !self.byzantine_votes.contains_key(&suspect) && self.byzantine_votes.len() >= MAX_TRACKED_SUSPECTS {
+            evicted = self.byzantine_votes.iter()
+                .min_by_key(|(id, views)| (views.values().map(HashSet::len).max().unwrap_or(0), std::cmp::Reverse(**id)))
+                .map(|(id, _)| *id);
+            if let Some(id) = evicted {
+                self.byzantine_votes.remove(&id);
+                record_evictions("byzantine_votes", 1);
+            }
+        }
+        let votes = self.byzantine_votes.entry(suspect).or_default().entry(view).or_default();
+        votes.insert(voter);
+        (votes.len(), evicted)
+    }
+
+    // 进入view时删除证据早于view - BYZANTINE_VOTE_VIEWS的投票，返回删除的票数
+    pub fn expire_byzantine_votes(&mut self, view: u64) -> usize {
+        let oldest = view.saturating_sub(BYZANTINE_VOTE_VIEWS);
+        let mut expired = 0;
+        for views in self.byzantine_votes.values_mut() {
+            let kept = views.split_off(&oldest);
+            expired += views.values().map(HashSet::len).sum::<usize>();
+            *views = kept;
+        }
+        self.byzantine_votes.retain(|_, views| !views.is_empty());
+        record_evictions("byzantine_votes_expired", expired);
+        expired
+    }
+}
+
+fn record_evictions(kind: &str, count: usize) {
+    if count > 0 {
+        metrics::inc_counter(&format!("node_state_evicted_total{{kind=\"{}\"}}", kind), count as u64);
+    }
 }
 
 pub struct Node {
     pub id: usize,
-    pub view: u64,
-    pub sequence_number: u64,
-    pub digest: String,
+    pub core: ConsensusCore, // 视图、当前实例及其投票，决策逻辑不涉及I/O
     pub state: Arc<Mutex<NodeState>>,
     pub receiver: Receiver<PBFTMessage>,
+    key_table: KeyTable, // public_keys的副本，供入站流水线的验签任务读取
     pub timeout_duration: Duration,
     pub last_message_time: Instant,
     pub view_change_in_progress: bool,
-    pub keypair: Keypair,
+    pub signing_key: SigningKey,
     pub public_keys: HashMap<usize, PublicKey>,
-    pub is_byzantine: bool,
-    pub suspected_nodes: HashSet<usize>,
+    pub role: Role,
+    pub reputation: Arc<Mutex<Reputation>>, // 各节点的信誉分数，与RPC共享
     pub blacklist: HashSet<usize>,
+    vote_evidence: HashMap<(usize, u64, usize), Evidence>, // (被指控者, 证据所属的视图, 投票者) → 投票附带的证据，申诉时逐条核对
+    signed_preprepares: BTreeMap<(u64, u64, String), PBFTMessage>, // 主节点签名的PrePrepare，用于组装证据和申诉
+    mac_keys: Mutex<MacKeys>, // 签名策略允许MAC认证时与各节点共享的密钥
     pub pending_requests: Vec<PBFTMessage>,
-    pub new_view_timer: Option<tokio::task::JoinHandle<()>>,
+    pub new_view_deadline: Option<Instant>,
+    pub view_change_timeout: Duration,
+    pub genesis: Genesis,
+    pub authenticated_peers: HashSet<usize>,
+    pub pending_challenges: HashMap<usize, (Vec<u8>, Instant)>, // 未应答的挑战及最近一次发送的时间
+    unauthenticated: HashMap<usize, VecDeque<(Instant, PBFTMessage)>>, // 握手完成前收到的签名消息
+    released: VecDeque<PBFTMessage>, // 握手完成后待重新处理的缓存消息
+    pub batch_controller: BatchController,
+    pub batch_queue: QosScheduler,
+    pub batch_started: Option<Instant>,
+    pub proposal_times: HashMap<u64, Instant>,
+    pub client_registry: ClientRegistry,
+    pub admission_policy: Box<dyn AdmissionPolicy>,
+    pub reply_sink: Box<dyn ReplySink>, // 答复如何送达客户端，嵌入式部署可替换
+    pub chain: Arc<Mutex<Chain>>,
+    pub storage: StorageWriter, // 后台把链写入磁盘，提交的区块先进入预写日志
+    pub commit_signatures: HashMap<(u64, u64, String), BTreeMap<usize, Signature>>,
+    // 各节点对同一摘要的PrePrepare/Prepare签名，组成ViewChange中的准备证书和线性投票的Prepare证书
+    pub prepare_signatures: HashMap<(u64, u64, String), BTreeMap<usize, Signature>>,
+    aggregation_deadline: Option<(u64, Instant)>, // 线性投票：(序列号, 改为广播Prepare的时间)
+    pub digest_preprepares: bool,
+    payload_fetch: Option<PayloadFetch>, // 正在补齐内容的摘要模式PrePrepare
+    pub block_subscribers: HashSet<usize>,
+    pub archive_index: Option<Arc<Mutex<ArchiveIndex>>>,
+    pub consensus_observers: HashSet<usize>,
+    pub auditor: Option<Arc<Mutex<Auditor>>>,
+    pub signed_view_changes: HashMap<(u64, usize), PBFTMessage>,
+    o_set: Vec<PBFTMessage>, // 当前视图的NewView中须重新执行的PrePrepare，对应序列号只接受这些提议
+    prepared_entry: Option<PreparedEntry>, // 最近一个已准备而未提交的请求及其证明，提交前每次ViewChange都携带
+    pub coalesce_messages: bool,
+    pub peer_directory: bool,
+    pub execution: Arc<Mutex<ExecutionEngine>>,
+    pub state_sync: Option<StateSync>,
+    pub header_sync: Option<HeaderSync>, // 区块头优先同步的进度，补齐全部区块体后清除
+    pub snapshot_cache: BTreeMap<u64, (SnapshotManifest, Vec<u8>)>,
+    pub current_view: Arc<AtomicU64>, // 与RPC共享的当前视图
+    pub current_primary: Arc<AtomicUsize>,
+    pub events: EventBus, // 共识事件总线，RPC等子系统订阅
+    pub leader_election: Box<dyn LeaderElection>,
+    pub performance: PerformanceTracker,
+    pub relay_enabled: bool, // 是否为没有公网地址的节点转发消息
+    pub relays: Vec<usize>, // 本节点位于NAT之后时使用的中继节点
+    pub console: Option<Receiver<ConsoleRequest>>, // 交互式控制台的命令（console特性）
+    pub shutdown: Option<Receiver<()>>, // 关闭信号，收到后保存内存池并退出事件循环
+    pub exit: Option<Receiver<()>>, // 计划退出的信号，收到后先交接再关闭
+    exit_deadline: Option<Instant>, // 正在计划退出时，最晚的关闭时间
+    pub departed: HashSet<usize>, // 宣布计划退出、尚未重新握手的验证者
+    misconfigured_peers: HashMap<usize, String>, // 共识参数与本节点不一致的对等节点及其参数哈希
+    pub maintenance_toggle: Option<Receiver<bool>>, // 管理员开关维护模式的信号
+    pub in_maintenance: bool, // 维护模式：继续提供查询和状态证明，但不参与共识
+    pub maintenance_peers: HashSet<usize>, // 处于维护模式的对等节点
+    pub config_reload: Option<Receiver<NodeConfig>>, // 热加载后的非共识配置
+    pub advertised_addresses: Vec<String>, // 在节点目录中公告的拨号地址，为空时使用监听地址
+    zones: Zones, // 验证者所在的故障域
+    broadcast_order: Vec<usize>, // 广播的发送顺序，其他故障域在前
+    pub clock: Clock, // 本地时钟，可模拟偏移和漂移
+    pub clock_sync: Arc<Mutex<ClockSync>>, // 各对等节点的时钟偏差估计，与RPC共享
+    next_ping: Instant, // 下一次向对等节点发送Ping的时间
+    pub request_status: Arc<Mutex<RequestTracker>>, // 各客户端请求的进度，与RPC共享
+    pub view_stats: Arc<Mutex<ViewStats>>, // 各视图的时长、提交的区块数和超时次数，与RPC共享
+    pub alerts: Alerts, // 严重事件的运维告警
+    pub send_latency: Duration, // 注入的出站消息延迟
+    pub trace: Option<InstanceTrace>, // 当前共识实例的追踪状态
+    pub latency_budget: LatencyBudget, // 各实例的阶段耗时和投票到达时间，超出预算时记录诊断
+    pub incoming_trace: Option<TraceContext>, // 正在处理的消息所携带的追踪上下文
+    pub otlp_endpoint: Option<String>,
+    pub audit_log: AuditLog, // 签名的哈希链，记录本节点的每个共识决策
+    pub checkpoints: CheckpointTracker, // 各验证者在检查点高度的状态摘要
+    pub governance_applied: HashSet<String>, // 已经生效的治理提案
+    pub governance_actions: Vec<governance::Action>, // 启动后提交的治理提案和投票
+    pub range_fetch: Option<(u64, Instant)>, // 正在补齐的缺口的最高高度及请求时间
+    pub deferred_commit: Option<CommitCertificate>, // 缺口补齐前暂缓提交的当前实例
+    pub address_book: AddressBook, // 对等节点的地址、最近握手和最近消息的时间，随Ping周期写盘
 }
 
 impl Node {
     pub fn new(
         id: usize,
         view: u64,
-        keypair: Keypair,
+        signing_key: SigningKey,
         public_keys: HashMap<usize, PublicKey>,
         receiver: Receiver<PBFTMessage>,
-        is_byzantine: bool,
+        strategy: Strategy,
+        genesis: Genesis,
     ) -> Self {
+        // 重放已保存的区块，恢复执行状态
+        let mut chain = Chain::load(id);
+        chain.hash_function = genesis.hash_function;
+        // 链文件之后已提交的区块在预写日志中
+        // 数据目录取绝对路径：后台写入链文件时当前目录可能已经改变（例如测试集群结束后恢复当前目录）
+        let directory = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
+        let wal = storage::recover(&directory, id, &mut chain);
+        chain.index = ChainIndex::load(id, &chain);
+        let mut execution = ExecutionEngine::new();
+        // 启动检查已确认应用存在；测试集群等绕过检查的调用方配置了不存在的应用时记录错误，按键值存储执行
+        if let Err(e) = execution.configure(&genesis) {
+            error!("节点{}无法加载复制应用: {}", id, e);
+        }
+        info!("节点{}运行复制应用{}", id, execution.application());
+        if chain.base.is_some() {
+            // 通过状态同步加入的节点先恢复快照，再重放其后的区块
+            if let Some(snapshot) = StateSnapshot::load(id) {
+                execution.restore(snapshot.header.map(|header| header.height).unwrap_or(0), snapshot.store);
+            }
+        }
+        for block in &chain.blocks {
+            execution.execute_block(block.header.height, &block.transactions);
+        }
+
+        let execution = Arc::new(Mutex::new(execution));
+        let chain = Arc::new(Mutex::new(chain));
+        let leader_election = leader::from_config(&genesis.chain_id, execution.clone());
+        let reputation = Reputation::load(id);
+        let view_stats = ViewStats::load(id, view, leader_election.leader(view));
+        let mut performance = PerformanceTracker::default();
+        for (node_id, score) in reputation.scores() {
+            performance.set_reputation(node_id, score as f64 / MAX_REPUTATION as f64);
+        }
+
+        let mut core = ConsensusCore::new(id, view, leader_election.leader(view), strategy, genesis.hash_function);
+        core.aggregate_votes = VOTE_AGGREGATION;
+        // 重启后从链尖恢复本视图已用到的序列号，重启的主节点不会再次提议已提交的序列号
+        if let Some(tip) = chain.lock().unwrap().tip().filter(|tip| tip.view == view) {
+            core.sequence_number = tip.sequence_number;
+        }
+
         Node {
             id,
-            view,
-            sequence_number: 0,
-            digest: String::new(),
+            core,
             state: Arc::new(Mutex::new(NodeState::load(id))),
             receiver,
+            key_table: Arc::new(std::sync::RwLock::new(public_keys.clone())),
             timeout_duration: Duration::from_secs(5),
             last_message_time: Instant::now(),
             view_change_in_progress: false,
-            keypair,
+            signing_key,
             public_keys,
-            is_byzantine,
-            suspected_nodes: HashSet::new(),
+            role: Role::Validator,
+            reputation: Arc::new(Mutex::new(reputation)),
             blacklist: HashSet::new(),
+            vote_evidence: HashMap::new(),
+            signed_preprepares: BTreeMap::new(),
+            mac_keys: Mutex::new(MacKeys::default()),
             pending_requests: Vec::new(),
-            new_view_timer: None,
+            new_view_deadline: None,
+            view_change_timeout: Duration::from_secs(5),
+            genesis,
+            authenticated_peers: HashSet::new(),
+            pending_challenges: HashMap::new(),
+            unauthenticated: HashMap::new(),
+            released: VecDeque::new(),
+            batch_controller: BatchController::new(),
+            batch_queue: QosScheduler::new(),
+            batch_started: None,
+            proposal_times: HashMap::new(),
+            client_registry: ClientRegistry::load(),
+            admission_policy: Box::new(DefaultAdmissionPolicy),
+            reply_sink: Box::new(TransportSink),
+            chain: chain.clone(),
+            storage: StorageWriter::new(id, directory, chain, wal),
+            commit_signatures: HashMap::new(),
+            prepare_signatures: HashMap::new(),
+            aggregation_deadline: None,
+            digest_preprepares: DIGEST_PREPREPARE,
+            payload_fetch: None,
+            block_subscribers: HashSet::new(),
+            archive_index: None,
+            consensus_observers: HashSet::new(),
+            auditor: None,
+            signed_view_changes: HashMap::new(),
+            o_set: Vec::new(),
+            prepared_entry: None,
+            coalesce_messages: COALESCE_MESSAGES,
+            peer_directory: PEER_DIRECTORY,
+            execution,
+            state_sync: None,
+            header_sync: None,
+            snapshot_cache: BTreeMap::new(),
+            checkpoints: CheckpointTracker::new(id, CHECKPOINT_INTERVAL),
+            governance_applied: HashSet::new(),
+            governance_actions: Vec::new(),
+            range_fetch: None,
+            deferred_commit: None,
+            address_book: AddressBook::load(std::path::Path::new("."), id),
+            current_view: Arc::new(AtomicU64::new(view)),
+            current_primary: Arc::new(AtomicUsize::new(leader_election.leader(view))),
+            events: EventBus::new(),
+            leader_election,
+            performance,
+            relay_enabled: false,
+            relays: Vec::new(),
+            console: None,
+            shutdown: None,
+            exit: None,
+            exit_deadline: None,
+            departed: HashSet::new(),
+            misconfigured_peers: HashMap::new(),
+            maintenance_toggle: None,
+            config_reload: None,
+            advertised_addresses: Vec::new(),
+            zones: Zones::default(),
+            broadcast_order: Zones::default().broadcast_order(id),
+            in_maintenance: false,
+            maintenance_peers: HashSet::new(),
+            clock: Clock::default(),
+            clock_sync: Arc::new(Mutex::new(ClockSync::default())),
+            next_ping: Instant::now(),
+            request_status: Arc::new(Mutex::new(RequestTracker::default())),
+            view_stats: Arc::new(Mutex::new(view_stats)),
+            alerts: Alerts::new(id, Instant::now()),
+            send_latency: Duration::ZERO,
+            trace: None,
+            latency_budget: LatencyBudget::new(id),
+            incoming_trace: None,
+            otlp_endpoint: std::env::var(OTLP_ENDPOINT_ENV).ok(),
+            audit_log: AuditLog::open(id),
         }
     }
 
     pub async fn run(&mut self) {
-        info!("节点{}开始运行", self.id);
+        info!("节点{}开始运行，角色: {:?}", self.id, self.role);
+        self.storage.start();
+        // 重放的区块中已经通过的治理提案
+        self.apply_governance();
+
+        if self.core.strategy == Strategy::Silent {
+            // 模拟崩溃：保留接收端以免发送方报错，但不处理也不应答任何消息
+            info!("节点{}模拟崩溃，不再处理任何消息", self.id);
+            while self.receiver.recv().await.is_some() {}
+            return;
+        }
 
-        // 广播公钥
-        let pubkey_msg = PBFTMessage::PubKey {
-            node_id: self.id,
-            public_key: self.keypair.public.to_bytes().to_vec(),
-        };
-        self.broadcast(&pubkey_msg).await;
+        // 位于NAT之后时，先请求中继节点为本节点转发入站消息
+        let magic = self.genesis.network_magic();
+        for relay in self.relays.clone() {
+            info!("节点{}请求节点{}为其中继消息", self.id, relay);
+            send_message(magic, self.id, relay, PBFTMessage::RelayConnect { node_id: self.id }).await;
+        }
+
+        // 与所有对等节点进行挑战-应答握手，公钥随应答交换
+        self.start_handshakes().await;
+
+        if self.state_sync.is_some() {
+            self.request_snapshots().await;
+        }
+        if self.header_sync.is_some() {
+            self.request_headers().await;
+        }
+
+        if self.peer_directory {
+            self.register_in_directory().await;
+        }
+        // 投票资格按目录中的公钥校验，治理操作排在目录登记之后
+        self.submit_governance().await;
+
+        if self.role != Role::Validator {
+            self.subscribe_blocks().await;
+        } else {
+            self.restore_mempool().await;
+        }
+
+        // 解包和验签在独立的任务中进行，事件循环只处理带验签结论的消息
+        let receiver = std::mem::replace(&mut self.receiver, mpsc::channel(1).1);
+        let mut inbound = pipeline::spawn(receiver, self.key_table.clone(), self.genesis.chain_id.clone());
 
         loop {
-            let timeout = sleep(self.timeout_duration);
+            // 每处理完一个事件刷新一次发送缓存，同一事件产生的消息（例如Prepare及随后的Commit）合并发送
+            self.flush_outbox().await;
+            if self.exit_completed() {
+                info!("节点{}完成交接，计划退出", self.id);
+                self.save_mempool();
+                return;
+            }
+
+            let timeout = self.clock.sleep(self.timeout_duration);
             tokio::pin!(timeout);
 
+            // 未满的批次在批超时后也要发出
+            let batch_deadline = self.batch_started
+                .map(|started| started + self.batch_controller.batch_timeout())
+                .unwrap_or_else(|| self.clock.now() + self.timeout_duration);
+            let batch_timer = self.clock.sleep_until(batch_deadline);
+            tokio::pin!(batch_timer);
+
+            // 主节点迟迟不发Prepare证书时改为全互联
+            let aggregation_timer = self.clock.sleep_until(self.aggregation_deadline.map(|(_, deadline)| deadline).unwrap_or(batch_deadline));
+            tokio::pin!(aggregation_timer);
+
+            // 补不齐PrePrepare引用的交易时按主节点作恶处理
+            let payload_timer = self.clock.sleep_until(self.payload_fetch.as_ref().map(|fetch| fetch.deadline).unwrap_or(batch_deadline));
+            tokio::pin!(payload_timer);
+
+            let ping_timer = self.clock.sleep_until(self.next_ping);
+            tokio::pin!(ping_timer);
+
             select! {
-                Some(msg) = self.receiver.recv() => {
-                    self.last_message_time = Instant::now();
-                    self.handle_message(msg).await;
+                Some(event) = inbound.recv() => {
+                    self.last_message_time = self.clock.now();
+                    self.handle_message(event).await;
+                }
+                () = &mut batch_timer, if self.batch_started.is_some() => {
+                    self.propose_batch().await;
+                }
+                () = &mut aggregation_timer, if self.aggregation_deadline.is_some() => {
+                    if let Some((sequence_number, _)) = self.aggregation_deadline.take() {
+                        let actions = self.core.handle(Input::AggregationTimeout { view: self.core.view, sequence_number });
+                        self.apply(actions).await;
+                    }
+                }
+                () = &mut payload_timer, if self.payload_fetch.is_some() => {
+                    self.payload_fetch_expired().await;
+                }
+                () = &mut ping_timer => {
+                    self.send_pings().await;
+                }
+                Some(request) = next_event(&mut self.console) => {
+                    self.handle_console(request).await;
+                }
+                Some(()) = next_event(&mut self.shutdown) => {
+                    self.save_mempool();
+                    self.save_address_book();
+                    self.flush_storage().await;
+                    let snapshot = report::capture(self.id, self.chain.lock().unwrap().height());
+                    if let Err(e) = report::save(std::path::Path::new("."), &snapshot) {
+                        error!("节点{}写入指标快照失败: {}", self.id, e);
+                    }
+                    return;
+                }
+                Some(()) = next_event(&mut self.exit), if self.exit_deadline.is_none() => {
+                    self.begin_exit().await;
+                }
+                Some(enabled) = next_event(&mut self.maintenance_toggle) => {
+                    self.set_maintenance(enabled).await;
+                }
+                Some(config) = next_event(&mut self.config_reload) => {
+                    self.apply_config(config).await;
                 }
                 () = &mut timeout => {
                     self.handle_timeout().await;
@@ -118,60 +543,378 @@ impl Node {
         }
     }
 
-    pub async fn handle_message(&mut self, msg: PBFTMessage) {
-        let mut message_queue = vec![msg];
+    async fn handle_message(&mut self, event: Inbound) {
+        // 第二项是已确认的发送者：签名或认证包装解开后的内部消息才有，网络直接送来的消息为None
+        let mut message_queue = vec![(event, None)];
 
-        while let Some(current_msg) = message_queue.pop() {
-            // 检查发送者是否在黑名单中
-            let sender_id = match &current_msg {
-                PBFTMessage::SignedMessage { sender_id, .. } => *sender_id,
-                PBFTMessage::ByzantineVote { sender_id, .. } => *sender_id,
-                PBFTMessage::PubKey { node_id, .. } => *node_id,
-                _ => self.id, // 自己发送的消息
+        // 握手完成后放行的缓存消息排在当前消息之后处理
+        while let Some((event, verified)) = message_queue.pop().or_else(|| self.released.pop_front().map(|msg| (Inbound::Message(msg), None))) {
+            let (current_msg, checked) = match event {
+                Inbound::Message(msg) => (msg, None),
+                Inbound::Checked { message, key, valid } => (message, Some((key, valid))),
             };
-
-            if self.blacklist.contains(&sender_id) {
-                info!("节点{}忽略来自拜占庭节点{}的消息", self.id, sender_id);
+            // 不带签名或认证包装的共识消息不可信，不能当作任何节点（包括本节点）发出的消息处理
+            if verified.is_none() && pipeline::requires_authentication(&current_msg) {
+                warn!("节点{}丢弃未经签名或认证的{}消息", self.id, current_msg.kind());
+                metrics::inc_counter("unauthenticated_messages_dropped_total", 1);
                 continue;
             }
+            // 内部消息的发送者是验证过的，其余消息取声称的发送者；客户端请求等没有发送者
+            let sender = verified.or_else(|| pipeline::claimed_sender(&current_msg));
+
+            // 申诉自带证据，被拉黑的节点仍可申诉
+            if let Some(sender_id) = sender.filter(|sender_id| self.blacklist.contains(sender_id)) {
+                if current_msg.kind() != "Appeal" {
+                    info!("节点{}忽略来自拜占庭节点{}的消息", self.id, sender_id);
+                    continue;
+                }
+            }
+            // 按发送者的信誉限流；包装在入站时已经计过一次，内部消息不再重复计
+            if let Some(sender_id) = sender.filter(|sender_id| verified.is_none() && *sender_id != self.id) {
+                if !self.reputation.lock().unwrap().admit(sender_id) {
+                    debug!("节点{}对节点{}的消息限流，丢弃", self.id, sender_id);
+                    metrics::inc_counter("reputation_rate_limited_total", 1);
+                    continue;
+                }
+            }
 
             debug!("节点{}收到消息: {:?}", self.id, current_msg);
             match current_msg {
-                PBFTMessage::SignedMessage { message, signature, sender_id } => {
-                    // 验证签名
-                    if let Some(pubkey) = self.public_keys.get(&sender_id) {
-                        let message_bytes = serde_json::to_vec(&message).unwrap();
-                        let signature = Signature::from_bytes(&signature).unwrap();
+                PBFTMessage::Bundle { messages } => {
+                    // 逆序压栈，保证按发送顺序处理；捆绑在认证包装内的消息同样是已确认发送者的
+                    message_queue.extend(messages.into_iter().rev().map(|msg| (Inbound::Message(msg), verified)));
+                }
+                PBFTMessage::Relay { from, to, message } => {
+                    if !self.relay_enabled {
+                        debug!("节点{}未启用中继，丢弃节点{}发往节点{}的消息", self.id, from, to);
+                    } else if !network::forward_relayed(self.genesis.network_magic(), self.id, from, to, *message).await {
+                        error!("节点{}无法把节点{}的消息转发给节点{}", self.id, from, to);
+                    }
+                }
+                PBFTMessage::SignedMessage { message, signature, sender_id, trace } => {
+                    // 未完成握手的连接不接受任何PBFT消息，先缓存一段时间，握手完成后重新处理
+                    if !self.authenticated_peers.contains(&sender_id) {
+                        self.buffer_unauthenticated(sender_id, PBFTMessage::SignedMessage { message, signature, sender_id, trace }).await;
+                        continue;
+                    }
 
-                        if pubkey.verify(&message_bytes, &signature).is_ok() {
+                    // 验签任务所用的公钥仍是当前公钥时采用其结论，否则在这里重新验证
+                    let valid = match (checked, self.public_keys.get(&sender_id)) {
+                        (Some((key, valid)), Some(pubkey)) if key == *pubkey => Some(valid),
+                        (_, Some(pubkey)) => {
+                            Some(pubkey.verify(&self.genesis.message_payload(&message), &signature))
+                        }
+                        (_, None) => None,
+                    };
+                    if let Some(valid) = valid {
+                        if valid {
                             debug!("节点{}验证签名成功，来自节点{}", self.id, sender_id);
-                            // 将内部消息加入队列
-                            message_queue.push(*message);
+                            if let Some(inner) = self.accept_verified(message, Some(signature), sender_id, trace).await {
+                                message_queue.push((Inbound::Message(inner), Some(sender_id)));
+                            }
                         } else {
                             error!("节点{}验证签名失败，来自节点{}", self.id, sender_id);
+                            self.penalize(sender_id, reputation::Event::InvalidSignature).await;
                         }
                     } else {
                         error!("节点{}没有节点{}的公钥，无法验证签名", self.id, sender_id);
                     }
                 }
+                PBFTMessage::AuthenticatedMessage { message, authenticator, sender_id, trace } => {
+                    if !self.authenticated_peers.contains(&sender_id) {
+                        self.buffer_unauthenticated(sender_id, PBFTMessage::AuthenticatedMessage { message, authenticator, sender_id, trace }).await;
+                        continue;
+                    }
+                    match self.check_authenticator(&message, &authenticator, sender_id) {
+                        Ok(()) => {
+                            if let Some(inner) = self.accept_verified(message, None, sender_id, trace).await {
+                                message_queue.push((Inbound::Message(inner), Some(sender_id)));
+                            }
+                        }
+                        Err(reason) => {
+                            error!("节点{}拒绝节点{}的{}消息: {}", self.id, sender_id, message.kind(), reason);
+                            metrics::inc_counter("authentication_rejected_total", 1);
+                            if authenticator.strength() >= self.genesis.signing_policy.for_kind(message.kind()) {
+                                self.penalize(sender_id, reputation::Event::InvalidSignature).await;
+                            }
+                        }
+                    }
+                }
                 _ => {
                     // 调用相应的处理函数
-                    self.process_message(current_msg).await;
+                    self.process_message(current_msg, verified).await;
+                    self.incoming_trace = None;
+                }
+            }
+        }
+    }
+
+    // 已确认发送者身份的消息。signature为发送者的签名；按签名策略以MAC认证或不认证的消息没有签名，
+    // 不记入证书和证据，也不交给观察者审计。返回需要紧接着处理的内部消息
+    async fn accept_verified(&mut self, message: Box<PBFTMessage>, signature: Option<Signature>, sender_id: usize, trace: Option<TraceContext>) -> Option<PBFTMessage> {
+        if sender_id != self.id {
+            self.address_book.seen(sender_id, self.clock.wall_time().timestamp_millis());
+        }
+        // 快照清单只接受确认了发送者的，下载方据此统计提供方
+        if let PBFTMessage::SnapshotOffer { node_id, manifest } = *message {
+            if node_id == sender_id {
+                self.handle_snapshot_offer(sender_id, manifest).await;
+            } else {
+                error!("节点{}收到节点{}冒充节点{}的快照清单", self.id, sender_id, node_id);
+            }
+            return None;
+        }
+        if let PBFTMessage::Checkpoint { node_id, height, state_digest } = *message {
+            if node_id == sender_id {
+                self.address_book.seen_height(node_id, height);
+                self.handle_checkpoint(node_id, height, state_digest).await;
+            } else {
+                error!("节点{}收到节点{}冒充节点{}的检查点", self.id, sender_id, node_id);
+            }
+            return None;
+        }
+        if let PBFTMessage::Leave { node_id, view } = *message {
+            if node_id == sender_id {
+                self.handle_leave(node_id, view).await;
+            } else {
+                error!("节点{}收到节点{}冒充节点{}的退出通知", self.id, sender_id, node_id);
+            }
+            return None;
+        }
+        if let PBFTMessage::Maintenance { node_id, enabled } = *message {
+            if node_id == sender_id {
+                self.handle_peer_maintenance(node_id, enabled).await;
+            } else {
+                error!("节点{}收到节点{}冒充节点{}的维护通知", self.id, sender_id, node_id);
+            }
+            return None;
+        }
+        if self.in_maintenance && matches!(*message, PBFTMessage::PrePrepareDigests { .. }) {
+            return None;
+        }
+        match *message {
+            PBFTMessage::Ping { node_id, sent_at } if node_id == sender_id => {
+                let pong = PBFTMessage::Pong { node_id: self.id, ping_sent_at: sent_at, peer_time: self.clock.wall_time().timestamp_millis() };
+                self.send_to(sender_id, pong).await;
+                return None;
+            }
+            PBFTMessage::Pong { node_id, ping_sent_at, peer_time } if node_id == sender_id => {
+                self.handle_pong(node_id, ping_sent_at, peer_time);
+                return None;
+            }
+            PBFTMessage::Ping { node_id, .. } | PBFTMessage::Pong { node_id, .. } => {
+                error!("节点{}收到节点{}冒充节点{}的时钟同步消息", self.id, sender_id, node_id);
+                return None;
+            }
+            PBFTMessage::ByzantineVote { sender_id: voter, .. } if voter != sender_id => {
+                error!("节点{}收到节点{}冒充节点{}的拜占庭投票", self.id, sender_id, voter);
+                return None;
+            }
+            PBFTMessage::PrePrepareDigests { view, sequence_number, digest, payload, signature: full_signature } => {
+                if sender_id == self.leader(view) {
+                    let deadline = self.clock.now() + Duration::from_millis(PAYLOAD_FETCH_TIMEOUT_MS);
+                    let fetch = PayloadFetch { view, sequence_number, digest, payload, signature: full_signature, primary: sender_id, trace, found: HashMap::new(), deadline };
+                    self.handle_preprepare_digests(fetch).await;
+                } else {
+                    error!("节点{}收到非主节点{}发送的视图{}的PrePrepare，拒绝", self.id, sender_id, view);
+                }
+                return None;
+            }
+            _ => {}
+        }
+        // 观察者只审计，不处理共识消息
+        if let Some(auditor) = &self.auditor {
+            match signature {
+                Some(signature) => {
+                    let signed = PBFTMessage::SignedMessage {
+                        message,
+                        signature,
+                        sender_id,
+                        trace: None,
+                    };
+                    auditor.lock().unwrap().audit(sender_id, signed, &*self.leader_election, self.genesis.hasher());
+                }
+                None => debug!("观察者节点{}无法审计节点{}未签名的{}消息", self.id, sender_id, message.kind()),
+            }
+            return None;
+        }
+        // 保存带签名的ViewChange消息，新主节点用它们构造NewView
+        if let PBFTMessage::ViewChange { view, node_id, .. } = &*message {
+            if *node_id != sender_id {
+                error!("节点{}收到节点{}冒充节点{}的ViewChange消息", self.id, sender_id, node_id);
+                return None;
+            }
+            if let Some(signature) = signature {
+                let signed = PBFTMessage::SignedMessage {
+                    message: message.clone(),
+                    signature,
+                    sender_id,
+                    trace: None,
+                };
+                self.signed_view_changes.insert((*view, sender_id), signed);
+            }
+        }
+        if let PBFTMessage::Prepare { sender_id: claimed, .. } = &*message {
+            if *claimed != sender_id {
+                error!("节点{}收到节点{}冒充节点{}的Prepare消息", self.id, sender_id, claimed);
+                return None;
+            }
+        }
+        // NewView只能由该视图的主节点发送
+        if let PBFTMessage::NewView { view, .. } = &*message {
+            if sender_id != self.leader(*view) {
+                error!("节点{}收到非主节点{}发送的视图{}的NewView消息，拒绝", self.id, sender_id, view);
+                return None;
+            }
+        }
+        // Prepare证书同样只能由该视图的主节点汇集
+        if let PBFTMessage::PrepareCertificate { view, .. } = &*message {
+            if sender_id != self.leader(*view) {
+                error!("节点{}收到非主节点{}发送的视图{}的Prepare证书，拒绝", self.id, sender_id, view);
+                return None;
+            }
+        }
+        // 保存Commit签名，用于构造提交证书
+        if let (PBFTMessage::Commit { view, sequence_number, digest }, Some(signature)) = (&*message, signature) {
+            self.commit_signatures
+                .entry((*view, *sequence_number, digest.clone()))
+                .or_default()
+                .insert(sender_id, signature);
+        }
+        // 保存PrePrepare和Prepare签名，用于构造准备证书
+        match (&*message, signature) {
+            (PBFTMessage::PrePrepare { view, sequence_number, digest, .. }, Some(signature)) => {
+                self.prepare_signatures
+                    .entry((*view, *sequence_number, digest.clone()))
+                    .or_default()
+                    .insert(sender_id, signature);
+                if sender_id == self.leader(*view) {
+                    let signed = PBFTMessage::SignedMessage { message: message.clone(), signature, sender_id, trace: None };
+                    if let Some(evidence) = self.remember_preprepare(signed) {
+                        self.accuse(evidence).await;
+                    }
                 }
             }
+            (PBFTMessage::Prepare { view, sequence_number, digest, sender_id: claimed }, Some(signature)) if *claimed == sender_id => {
+                self.prepare_signatures
+                    .entry((*view, *sequence_number, digest.clone()))
+                    .or_default()
+                    .insert(sender_id, signature);
+            }
+            _ => {}
+        }
+        // 记录投票的到达时间，实例超出延迟预算时据此找出拖慢的节点
+        match &*message {
+            PBFTMessage::Prepare { view, sequence_number, sender_id: claimed, .. } if *claimed == sender_id => {
+                self.latency_budget.vote(Vote::Prepare, *view, *sequence_number, sender_id, self.clock.now());
+            }
+            PBFTMessage::Commit { view, sequence_number, .. } => {
+                self.latency_budget.vote(Vote::Commit, *view, *sequence_number, sender_id, self.clock.now());
+            }
+            _ => {}
+        }
+        // 按故障域统计投票相对实例开始的到达时间
+        if !self.zones.is_empty() {
+            let vote = match &*message {
+                PBFTMessage::Prepare { sequence_number, .. } => Some(("Prepare", *sequence_number)),
+                PBFTMessage::Commit { sequence_number, .. } => Some(("Commit", *sequence_number)),
+                _ => None,
+            };
+            if let Some((kind, started)) = vote.and_then(|(kind, seq)| self.proposal_times.get(&seq).map(|started| (kind, *started))) {
+                let labels = format!("{{zone=\"{}\",kind=\"{}\"}}", self.zones.label(sender_id), kind);
+                metrics::inc_counter(&format!("vote_arrival_ms_total{}", labels), self.clock.now().duration_since(started).as_millis() as u64);
+                metrics::inc_counter(&format!("vote_arrival_samples_total{}", labels), 1);
+            }
+        }
+        // 收到的共识消息超前于本节点已提交的序列号，说明中间的实例被错过了
+        match &*message {
+            PBFTMessage::PrePrepare { view, sequence_number, .. }
+            | PBFTMessage::Prepare { view, sequence_number, .. }
+            | PBFTMessage::Commit { view, sequence_number, .. } => {
+                self.detect_gap(*view, *sequence_number, Some(sender_id)).await;
+            }
+            _ => {}
         }
+        // 内部消息紧接着处理，追踪上下文随之生效
+        self.incoming_trace = trace;
+        Some(*message)
     }
 
-    async fn process_message(&mut self, msg: PBFTMessage) {
+    // sender为认证包装确认的发送者，网络直接送来的消息为None
+    async fn process_message(&mut self, msg: PBFTMessage, sender: Option<usize>) {
+        // 任何角色都可以提供或下载状态快照
+        match msg {
+            PBFTMessage::SnapshotRequest { node_id } => {
+                self.handle_snapshot_request(node_id).await;
+                return;
+            }
+            PBFTMessage::ChunkRequest { node_id, height, index } => {
+                self.handle_chunk_request(node_id, height, index).await;
+                return;
+            }
+            PBFTMessage::ChunkResponse { height, index, data } => {
+                self.handle_chunk_response(height, index, data).await;
+                return;
+            }
+            PBFTMessage::FetchRange { node_id, from_height, to_height } => {
+                self.handle_fetch_range(node_id, from_height, to_height).await;
+                return;
+            }
+            PBFTMessage::FetchHeaders { node_id, from_height } => {
+                self.handle_fetch_headers(node_id, from_height).await;
+                return;
+            }
+            PBFTMessage::Headers { headers } => {
+                self.handle_headers(headers).await;
+                return;
+            }
+            PBFTMessage::FetchPayload { node_id, digests } => {
+                self.handle_fetch_payload(node_id, digests).await;
+                return;
+            }
+            PBFTMessage::Payload { node_id, transactions } => {
+                self.handle_payload(node_id, transactions);
+                return;
+            }
+            PBFTMessage::FetchPrePrepare { node_id, view, sequence_number } => {
+                self.handle_fetch_preprepare(node_id, view, sequence_number).await;
+                return;
+            }
+            PBFTMessage::RelayConnect { node_id } => {
+                if self.relay_enabled && network::accept_relay(self.id, node_id) {
+                    info!("节点{}开始为节点{}中继消息", self.id, node_id);
+                } else {
+                    info!("节点{}拒绝为节点{}中继消息", self.id, node_id);
+                    network::reject_relay(self.id, node_id);
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        if self.role != Role::Validator {
+            self.process_full_node_message(msg).await;
+            return;
+        }
+
+        // 维护模式下不投票也不发起视图切换，只跟随新视图，以免恢复时视图落后
+        if self.in_maintenance && matches!(msg, PBFTMessage::PrePrepare { .. } | PBFTMessage::Prepare { .. } | PBFTMessage::PrepareCertificate { .. } | PBFTMessage::Commit { .. } | PBFTMessage::ViewChange { .. }) {
+            debug!("节点{}处于维护模式，不处理{}消息", self.id, msg.kind());
+            return;
+        }
+
         match msg {
             PBFTMessage::PrePrepare { .. } => {
-                self.handle_preprepare(msg).await;
+                self.handle_preprepare(msg, sender).await;
             }
             PBFTMessage::Prepare { .. } => {
                 self.handle_prepare(msg).await;
             }
+            PBFTMessage::PrepareCertificate { .. } => {
+                self.handle_prepare_certificate(msg).await;
+            }
             PBFTMessage::Commit { .. } => {
-                self.handle_commit(msg).await;
+                self.handle_commit(msg, sender).await;
             }
             PBFTMessage::ViewChange { .. } => {
                 self.handle_view_change(msg).await;
@@ -179,17 +922,44 @@ impl Node {
             PBFTMessage::NewView { .. } => {
                 self.handle_new_view(msg).await;
             }
-            PBFTMessage::ByzantineVote { suspected_id, sender_id } => {
-                self.handle_byzantine_vote(suspected_id, sender_id).await;
+            PBFTMessage::ByzantineVote { suspected_id, sender_id, evidence } => {
+                self.handle_byzantine_vote(suspected_id, sender_id, evidence).await;
             }
-            PBFTMessage::PubKey { node_id, public_key } => {
-                // 处理公钥消息
-                let pubkey = PublicKey::from_bytes(&public_key).unwrap();
-                self.public_keys.insert(node_id, pubkey);
-                info!("节点{}收到节点{}的公钥", self.id, node_id);
+            PBFTMessage::Appeal { node_id, pre_prepare } => {
+                self.handle_appeal(node_id, *pre_prepare).await;
             }
             PBFTMessage::Request { .. } => {
-                self.handle_request(msg).await;
+                // 启用ACL后不接受匿名请求
+                if self.client_registry.is_enabled() {
+                    info!("节点{}拒绝未签名的客户端请求", self.id);
+                    metrics::inc_counter("acl_rejected_total", 1);
+                } else {
+                    self.handle_request(msg).await;
+                }
+            }
+            PBFTMessage::ClientRequest { request, client_id, signature } => {
+                self.handle_client_request(*request, client_id, signature).await;
+            }
+            PBFTMessage::SubscribeBlocks { node_id, from_height } => {
+                self.handle_subscribe_blocks(node_id, from_height).await;
+            }
+            PBFTMessage::RangeBlocks { blocks } => {
+                self.handle_range_blocks(blocks).await;
+            }
+            PBFTMessage::SubscribeConsensus { node_id } => {
+                info!("节点{}收到观察者{}的共识消息订阅", self.id, node_id);
+                self.consensus_observers.insert(node_id);
+            }
+            PBFTMessage::HandshakeChallenge { node_id, nonce } => {
+                // 计划退出或维护中的节点重新启动后先发起握手
+                if self.departed.remove(&node_id) | self.maintenance_peers.remove(&node_id) {
+                    info!("节点{}发现计划退出或维护中的节点{}已重新上线", self.id, node_id);
+                    self.performance.clear_departed(node_id);
+                }
+                self.handle_handshake_challenge(node_id, nonce).await;
+            }
+            PBFTMessage::HandshakeResponse { node_id, public_key, signature, parameters, protocol_version } => {
+                self.handle_handshake_response(node_id, public_key, signature, parameters, protocol_version);
             }
             _ => {
                 debug!("节点{}收到未处理的消息类型: {:?}", self.id, msg);
@@ -197,300 +967,2146 @@ impl Node {
         }
     }
 
-    pub async fn handle_request(&mut self, msg: PBFTMessage) {
-        if let PBFTMessage::Request { operation } = msg.clone() {
-            // 将请求加入待处理队列
-            self.pending_requests.push(msg.clone());
-
-            if self.is_primary() && !self.view_change_in_progress {
-                info!("节点{}（主节点）处理客户端请求: {}", self.id, operation);
-                self.sequence_number += 1;
-                let digest = self.compute_digest(&operation);
-                self.digest = digest.clone();
-
-                let preprepare_msg = PBFTMessage::PrePrepare {
-                    view: self.view,
-                    sequence_number: self.sequence_number,
-                    digest: digest.clone(),
-                };
-
-                debug!("节点{}广播PrePrepare消息: {:?}", self.id, preprepare_msg);
-                self.broadcast(&preprepare_msg).await;
-            } else {
-                info!("节点{}不是主节点，等待主节点处理请求", self.id);
+    async fn process_full_node_message(&mut self, msg: PBFTMessage) {
+        match msg {
+            PBFTMessage::BlockAnnouncement { block } => {
+                self.handle_block_announcement(block);
+            }
+            PBFTMessage::HandshakeChallenge { node_id, nonce } => {
+                self.handle_handshake_challenge(node_id, nonce).await;
+            }
+            PBFTMessage::HandshakeResponse { node_id, public_key, signature, parameters, protocol_version } => {
+                self.handle_handshake_response(node_id, public_key, signature, parameters, protocol_version);
+            }
+            _ => {
+                debug!("全节点{}忽略共识消息: {}", self.id, msg.kind());
             }
         }
     }
 
-    async fn handle_preprepare(&mut self, msg: PBFTMessage) {
-        if let PBFTMessage::PrePrepare { view, sequence_number, digest } = msg.clone() {
-            info!("节点{}处理PrePrepare消息: view={}, seq={}, digest={}", self.id, view, sequence_number, digest);
-
-            if view == self.view && !self.is_primary() {
-                self.sequence_number = sequence_number;
-                self.digest = digest.clone();
-
-                let prepare_digest = if self.is_byzantine {
-                    // 拜占庭节点发送错误的摘要
-                    let wrong_digest = "错误的摘要".to_string();
-                    info!("拜占庭节点{}发送错误的Prepare摘要", self.id);
-                    wrong_digest
-                } else {
-                    digest.clone()
-                };
-
-                let prepare_msg = PBFTMessage::Prepare {
-                    view,
-                    sequence_number,
-                    digest: prepare_digest,
-                    sender_id: self.id,
-                };
-
-                debug!("节点{}广播Prepare消息: {:?}", self.id, prepare_msg);
-                self.broadcast(&prepare_msg).await;
-            } else {
-                debug!("节点{}收到的PrePrepare消息视图不匹配或自身为主节点，忽略", self.id);
+    async fn subscribe_blocks(&mut self) {
+        let from_height = self.chain.lock().unwrap().height() + 1;
+        let magic = self.genesis.network_magic();
+        for i in 0..N {
+            let subscribe = PBFTMessage::SubscribeBlocks {
+                node_id: self.id,
+                from_height,
+            };
+            debug!("全节点{}向验证者{}订阅区块，起始高度{}", self.id, i, from_height);
+            send_message(magic, self.id, i, subscribe).await;
+            if self.role == Role::Observer {
+                send_message(magic, self.id, i, PBFTMessage::SubscribeConsensus { node_id: self.id }).await;
             }
         }
     }
 
-    async fn handle_prepare(&mut self, msg: PBFTMessage) {
-        info!("节点{}处理Prepare消息: {:?}", self.id, msg);
-
-        let mut state = self.state.lock().unwrap();
-        state.messages.push(msg.clone());
+    async fn handle_subscribe_blocks(&mut self, node_id: usize, from_height: u64) {
+        info!("节点{}收到节点{}的区块订阅，起始高度{}", self.id, node_id, from_height);
+        self.block_subscribers.insert(node_id);
 
-        // 收集不同节点发送的摘要
-        let mut digest_counts: HashMap<String, HashSet<usize>> = HashMap::new();
-        for m in &state.messages {
-            if let PBFTMessage::Prepare { view, sequence_number, digest, .. } = m {
-                if *view == self.view && *sequence_number == self.sequence_number {
-                    digest_counts.entry(digest.clone()).or_insert_with(HashSet::new).insert(self.id);
-                }
-            }
+        // 先补发订阅者缺失的历史区块
+        let blocks: Vec<Block> = {
+            let chain = self.chain.lock().unwrap();
+            chain.blocks.iter().filter(|b| b.header.height >= from_height).cloned().collect()
+        };
+        let magic = self.genesis.network_magic();
+        for block in blocks {
+            send_message(magic, self.id, node_id, PBFTMessage::BlockAnnouncement { block }).await;
         }
+    }
 
-        // 检测是否存在不一致的摘要
-        if digest_counts.len() > 1 {
-            info!("节点{}检测到摘要不一致，可能存在拜占庭节点", self.id);
-            let messages = state.messages.clone(); // 克隆消息列表
-            drop(state); // 释放锁
-            self.detect_byzantine_nodes(&messages).await;
-        } else {
-            drop(state); // 释放锁
+    fn handle_block_announcement(&mut self, block: Block) {
+        let mut chain = self.chain.lock().unwrap();
+        let height = block.header.height;
+        if height <= chain.height() {
+            debug!("全节点{}已有高度{}的区块，忽略", self.id, height);
+            return;
         }
 
-        // 找到收到最多的摘要
-        let max_count = digest_counts.values().map(|s| s.len()).max().unwrap_or(0);
-        if max_count >= 2 * F {
-            // 找到正确的摘要
-            let correct_digest = digest_counts.iter().find(|(_, s)| s.len() == max_count).unwrap().0.clone();
-
-            let mut state = self.state.lock().unwrap();
-            if !state.prepared.contains(&(self.sequence_number, correct_digest.clone())) {
-                state.prepared.insert((self.sequence_number, correct_digest.clone()));
-                state.save(self.id);
-                info!("节点{}进入Prepared状态，序列号: {}", self.id, self.sequence_number);
-
-                let commit_msg = PBFTMessage::Commit {
-                    view: self.view,
-                    sequence_number: self.sequence_number,
-                    digest: correct_digest,
-                };
-
-                debug!("节点{}广播Commit消息: {:?}", self.id, commit_msg);
-                self.broadcast(&commit_msg).await;
+        let validators = self.validator_keys();
+        let prev = chain.tip().cloned();
+        match chain::verify_block(&block, prev.as_ref(), &validators, &self.genesis) {
+            Ok(()) => {
+                if let Some(index) = &self.archive_index {
+                    index.lock().unwrap().index_block(&block);
+                }
+                self.execute_block(&block);
+                chain.push_verified(block);
+                self.storage.persist();
+                info!("全节点{}验证并保存区块，高度: {}", self.id, height);
+            }
+            Err(reason) => {
+                error!("全节点{}拒绝高度{}的区块: {}", self.id, height, reason);
+                metrics::inc_counter("block_rejected_total", 1);
+                if let Some(auditor) = &self.auditor {
+                    auditor.lock().unwrap().record(Violation {
+                        kind: ViolationKind::InvalidCertificate,
+                        node_id: self.leader(block.certificate.view),
+                        view: block.certificate.view,
+                        sequence_number: block.certificate.sequence_number,
+                        description: reason,
+                        evidence: vec![PBFTMessage::BlockAnnouncement { block }],
+                    });
+                }
             }
         }
     }
 
-    async fn detect_byzantine_nodes(&mut self, messages: &Vec<PBFTMessage>) {
-        let mut digest_map: HashMap<String, HashSet<usize>> = HashMap::new();
+    fn validator_keys(&self) -> HashMap<usize, PublicKey> {
+        self.public_keys.iter()
+            .filter(|(id, _)| **id < N)
+            .map(|(id, key)| (*id, *key))
+            .collect()
+    }
 
-        for m in messages {
-            if let PBFTMessage::Prepare { digest, sender_id, .. } = m {
-                digest_map.entry(digest.clone()).or_insert_with(HashSet::new).insert(*sender_id);
-            }
+    // 同一视图内，消息的序列号比已提交的最高序列号大1以上时，返回缺失区块的高度范围。
+    // 跨视图的缺口无法从序列号推算，由检查点和状态同步处理
+    fn missing_range(&self, view: u64, sequence_number: u64) -> Option<(u64, u64)> {
+        // 空链相当于停在视图0的序列号0
+        let (tip_view, tip_sequence, tip_height) = self.chain.lock().unwrap().tip()
+            .map(|tip| (tip.view, tip.sequence_number, tip.height))
+            .unwrap_or((0, 0, 0));
+        // 区块头优先同步已校验的高度，不论视图都须先补齐区块体
+        if let Some(range) = self.header_sync.as_ref().and_then(|sync| sync.missing(tip_height)) {
+            return Some(range);
         }
+        if tip_view != view || sequence_number <= tip_sequence + 1 {
+            return None;
+        }
+        let missing = (sequence_number - tip_sequence - 1).min(MAX_FETCH_RANGE);
+        Some((tip_height + 1, tip_height + missing))
+    }
 
-        // 假设正确的摘要是收到最多的那个
-        let correct_digest = digest_map.iter().max_by_key(|&(_, senders)| senders.len()).unwrap().0.clone();
-
-        for (digest, senders) in digest_map {
-            if digest != correct_digest {
-                for sender_id in senders {
-                    self.suspected_nodes.insert(sender_id);
-                    info!("节点{}将节点{}标记为可疑", self.id, sender_id);
+    // 发现缺口时向对等节点请求缺失的区块；同一缺口在超时前不重复请求，超时后改向所有验证者请求
+    async fn detect_gap(&mut self, view: u64, sequence_number: u64, peer: Option<usize>) {
+        let (from_height, to_height) = match self.missing_range(view, sequence_number) {
+            Some(range) => range,
+            None => return,
+        };
+        if self.request_range(from_height, to_height, peer).await {
+            info!("节点{}在视图{}收到序列号{}的消息，缺少高度{}..={}的区块，请求补齐", self.id, view, sequence_number, from_height, to_height);
+        }
+    }
 
-                    // 广播投票消息
-                    let vote_msg = PBFTMessage::ByzantineVote {
-                        suspected_id: sender_id,
-                        sender_id: self.id,
-                    };
-                    self.broadcast(&vote_msg).await;
+    // 请求一段区块，先只问peer；同一范围在超时前不重复请求，超时后改向所有验证者请求。返回是否发出了请求
+    async fn request_range(&mut self, from_height: u64, to_height: u64, peer: Option<usize>) -> bool {
+        let now = self.clock.now();
+        let retry = match self.range_fetch {
+            Some((requested, since)) if requested >= to_height => {
+                if now.duration_since(since) < self.timeout_duration {
+                    return false;
+                }
+                true
+            }
+            _ => false,
+        };
+        self.range_fetch = Some((to_height, now));
+        metrics::inc_counter("fetch_range_requests_total", 1);
+        let request = PBFTMessage::FetchRange { node_id: self.id, from_height, to_height };
+        let magic = self.genesis.network_magic();
+        match peer.filter(|_| !retry) {
+            Some(peer) => send_message(magic, self.id, peer, request).await,
+            None => {
+                for i in (0..N).filter(|i| *i != self.id) {
+                    send_message(magic, self.id, i, request.clone()).await;
                 }
             }
         }
+        true
     }
 
-    async fn handle_byzantine_vote(&mut self, suspected_id: usize, sender_id: usize) {
-        info!("节点{}收到来自节点{}的拜占庭投票，怀疑节点{}", self.id, sender_id, suspected_id);
-
-        let mut state = self.state.lock().unwrap();
-        let entry = state.byzantine_votes.entry(suspected_id).or_insert_with(HashSet::new);
-        entry.insert(sender_id);
-
-        if entry.len() >= 2 * F + 1 {
-            self.blacklist.insert(suspected_id);
-            info!("节点{}确定节点{}为拜占庭节点，将其加入黑名单", self.id, suspected_id);
+    async fn handle_fetch_range(&self, node_id: usize, from_height: u64, to_height: u64) {
+        let to_height = to_height.min(from_height.saturating_add(MAX_FETCH_RANGE - 1));
+        let blocks: Vec<Block> = {
+            let chain = self.chain.lock().unwrap();
+            (from_height..=to_height).map_while(|height| chain.get_block(height).cloned()).collect()
+        };
+        if blocks.is_empty() {
+            debug!("节点{}没有节点{}请求的高度{}..={}的区块", self.id, node_id, from_height, to_height);
+            return;
         }
+        debug!("节点{}向节点{}发送高度{}..={}的{}个区块", self.id, node_id, from_height, to_height, blocks.len());
+        send_message(self.genesis.network_magic(), self.id, node_id, PBFTMessage::RangeBlocks { blocks }).await;
     }
 
-    async fn handle_commit(&mut self, msg: PBFTMessage) {
-        info!("节点{}处理Commit消息: {:?}", self.id, msg);
-
-        // 收集Commit消息
-        let mut state = self.state.lock().unwrap();
-        state.messages.push(msg.clone());
+    // 区块头优先同步：向当前对端请求下一批区块头，已有请求在途且未超时时不重复请求
+    async fn request_headers(&mut self) {
+        let local_height = self.chain.lock().unwrap().height();
+        let now = self.clock.now();
+        let id = self.id;
+        let request = self.header_sync.as_mut().and_then(|sync| sync.next_request(id, local_height, now));
+        if let Some((peer, from_height)) = request {
+            debug!("节点{}向节点{}请求从高度{}起的区块头", self.id, peer, from_height);
+            send_message(self.genesis.network_magic(), self.id, peer, PBFTMessage::FetchHeaders { node_id: self.id, from_height }).await;
+        }
+    }
 
-        let commit_count = state.messages.iter().filter(|m| {
-            if let PBFTMessage::Commit { view, sequence_number, digest } = m {
-                *view == self.view && *sequence_number == self.sequence_number && *digest == self.digest
-            } else {
-                false
+    // 已到链顶时回复空的一批；本节点没有这些区块（经状态同步加入）时不应答，由下载方超时后改问其他节点
+    async fn handle_fetch_headers(&self, node_id: usize, from_height: u64) {
+        let headers: Vec<(BlockHeader, CommitCertificate)> = {
+            let chain = self.chain.lock().unwrap();
+            let headers: Vec<_> = (from_height..from_height.saturating_add(HEADER_SYNC_BATCH))
+                .map_while(|height| chain.get_block(height).map(|block| (block.header.clone(), block.certificate.clone())))
+                .collect();
+            if headers.is_empty() && from_height <= chain.height() {
+                debug!("节点{}没有节点{}请求的高度{}起的区块头", self.id, node_id, from_height);
+                return;
             }
-        }).count();
-
-        debug!("节点{}收到的匹配的Commit消息数量: {}", self.id, commit_count);
+            headers
+        };
+        debug!("节点{}向节点{}发送高度{}起的{}个区块头", self.id, node_id, from_height, headers.len());
+        send_message(self.genesis.network_magic(), self.id, node_id, PBFTMessage::Headers { headers }).await;
+    }
 
-        if commit_count >= 2 * F + 1 {
-            if !state.committed.contains(&(self.sequence_number, self.digest.clone())) {
-                state.committed.insert((self.sequence_number, self.digest.clone()));
-                state.save(self.id);
-                info!("节点{}已提交请求，序列号: {}", self.id, self.sequence_number);
-                // 执行操作或回复客户端
+    async fn handle_headers(&mut self, headers: Vec<(BlockHeader, CommitCertificate)>) {
+        let validators = self.validator_keys();
+        let local_tip = self.chain.lock().unwrap().tip().cloned();
+        let sync = match &mut self.header_sync {
+            Some(sync) if !sync.is_complete() => sync,
+            _ => return,
+        };
+        match sync.accept(headers, local_tip.as_ref(), &validators, &self.genesis) {
+            Ok(accepted) => {
+                debug!("节点{}校验并接受{}个区块头", self.id, accepted);
+                metrics::inc_counter("header_sync_headers_total", accepted as u64);
             }
+            Err(reason) => {
+                error!("节点{}丢弃一批区块头: {}", self.id, reason);
+                metrics::inc_counter("header_sync_rejected_total", 1);
+            }
+        }
+        if sync.is_complete() {
+            self.finish_header_download().await;
+        } else {
+            self.request_headers().await;
         }
     }
 
-    async fn handle_timeout(&mut self) {
-        if Instant::now().duration_since(self.last_message_time) >= self.timeout_duration {
-            if !self.view_change_in_progress {
-                info!("节点{}检测到超时，触发视图切换", self.id);
-                self.start_view_change().await;
-            }
+    // 已找到链顶：直接进入其所在的视图参与排序，再开始补齐区块体。
+    // 带常规证书的区块头证明法定人数已在该视图提交，无需经过视图切换；本节点是该视图的主节点时除外，
+    // 主节点不知道该视图已分配的序列号，由其他节点超时后切换视图
+    async fn finish_header_download(&mut self) {
+        let sync = self.header_sync.as_ref().unwrap();
+        let local_height = self.chain.lock().unwrap().height();
+        let tip = sync.tip().filter(|tip| tip.height > local_height).cloned();
+        match &tip {
+            Some(tip) => info!("节点{}用{:?}校验了到高度{}（视图{}）的区块头，开始补齐{}个区块体",
+                self.id, sync.started.elapsed(), tip.height, tip.view, tip.height - local_height),
+            None => info!("节点{}没有落后于对等节点的区块", self.id),
+        }
+        if let Some(tip) = tip.filter(|tip| tip.view > self.core.view && self.leader(tip.view) != self.id) {
+            info!("节点{}直接进入已有区块提交的视图{}", self.id, tip.view);
+            self.enter_view(tip.view);
+            self.view_change_in_progress = false;
+            self.new_view_deadline = None;
+            self.last_message_time = self.clock.now();
         }
+        self.backfill_bodies().await;
     }
 
-    async fn start_view_change(&mut self) {
-        self.view_change_in_progress = true;
-        self.view += 1;
-        self.sequence_number = 0;
-        self.digest.clear();
-
-        let view_change_msg = PBFTMessage::ViewChange {
-            view: self.view,
-            last_sequence_number: self.sequence_number,
-            node_id: self.id,
+    // 按已校验的区块头请求下一段区块体；全部补齐后结束区块头优先同步
+    async fn backfill_bodies(&mut self) {
+        let height = self.chain.lock().unwrap().height();
+        let (missing, peer) = match &self.header_sync {
+            Some(sync) if sync.is_complete() => (sync.missing(height), sync.peer()),
+            _ => return,
         };
-
-        self.broadcast(&view_change_msg).await;
-        self.state.lock().unwrap().view_change_messages.push(view_change_msg.clone());
-
-        // 启动新视图定时器
-        let timeout_duration = self.timeout_duration;
-        let node_id = self.id;
-        let view = self.view;
-        self.new_view_timer = Some(tokio::spawn(async move {
-            tokio::time::sleep(timeout_duration).await;
-            info!("节点{}的新视图定时器超时，视图{}", node_id, view);
-            // 可以在这里处理新视图超时逻辑
-        }));
+        match missing {
+            Some((from_height, to_height)) => {
+                if self.request_range(from_height, to_height, Some(peer)).await {
+                    debug!("节点{}请求高度{}..={}的区块体", self.id, from_height, to_height);
+                }
+            }
+            None => {
+                let started = self.header_sync.take().unwrap().started;
+                info!("节点{}补齐全部区块体，区块头优先同步共用时{:?}", self.id, started.elapsed());
+                metrics::inc_counter("header_sync_completed_total", 1);
+            }
+        }
     }
 
-    async fn handle_view_change(&mut self, msg: PBFTMessage) {
-        if let PBFTMessage::ViewChange { view, node_id, .. } = msg {
-            if view == self.view {
-                info!("节点{}收到来自节点{}的ViewChange消息，视图{}", self.id, node_id, view);
-                self.state.lock().unwrap().view_change_messages.push(msg.clone());
-
-                let count = self.state.lock().unwrap().view_change_messages.iter().filter(|m| {
-                    if let PBFTMessage::ViewChange { view: v, .. } = m {
-                        *v == self.view
-                    } else {
-                        false
-                    }
-                }).count();
-
-                if count >= 2 * F && self.is_primary() {
-                    // 作为新主节点，发送NewView消息
-                    self.send_new_view().await;
+    // 逐个校验补齐的区块并执行，之后完成因缺口而暂缓的提交
+    async fn handle_range_blocks(&mut self, blocks: Vec<Block>) {
+        let validators = self.validator_keys();
+        for block in blocks {
+            let height = block.header.height;
+            {
+                let mut chain = self.chain.lock().unwrap();
+                if height != chain.height() + 1 {
+                    continue;
+                }
+                let prev = chain.tip().cloned();
+                if let Err(reason) = chain::verify_block(&block, prev.as_ref(), &validators, &self.genesis) {
+                    error!("节点{}拒绝补齐的高度{}的区块: {}", self.id, height, reason);
+                    metrics::inc_counter("block_rejected_total", 1);
+                    return;
+                }
+                if self.header_sync.as_ref().is_some_and(|sync| !sync.matches(&block.header, self.genesis.hasher())) {
+                    error!("节点{}拒绝补齐的高度{}的区块: 与已校验的区块头不一致", self.id, height);
+                    metrics::inc_counter("block_rejected_total", 1);
+                    return;
                 }
+                chain.push_verified(block.clone());
+                self.storage.persist();
             }
+            if let Some(sync) = &mut self.header_sync {
+                sync.filled(height);
+            }
+            info!("节点{}补齐高度{}的区块", self.id, height);
+            metrics::inc_counter("fetch_range_blocks_total", 1);
+            self.execute_block(&block);
+            self.forget_committed(&block);
+            self.apply_governance();
+            self.announce_block(block).await;
+            self.checkpoint(height).await;
         }
-    }
+        if self.range_fetch.is_some_and(|(to_height, _)| to_height <= self.chain.lock().unwrap().height()) {
+            self.range_fetch = None;
+        }
+        self.backfill_bodies().await;
 
-    async fn send_new_view(&mut self) {
-        let view_change_messages = self.state.lock().unwrap().view_change_messages.clone();
+        let certificate = match self.deferred_commit.take() {
+            Some(certificate) => certificate,
+            None => return,
+        };
+        // 当前实例已被新的PrePrepare取代时放弃，它的区块会作为下一个缺口补齐
+        if (certificate.view, certificate.sequence_number, &certificate.digest) != (self.core.view, self.core.sequence_number, &self.core.digest) {
+            return;
+        }
+        if self.missing_range(certificate.view, certificate.sequence_number).is_none() {
+            self.finish_commit(certificate).await;
+        } else {
+            self.deferred_commit = Some(certificate);
+        }
+    }
+
+    pub async fn handle_request(&mut self, msg: PBFTMessage) {
+        if let PBFTMessage::Request { operation, priority, client_id, expires_at, timestamp, .. } = msg.clone() {
+            let transaction = msg.to_transaction().unwrap();
+            if expires_at.is_some_and(|at| at <= self.network_time_ms()) {
+                info!("节点{}丢弃已过期的请求'{}'", self.id, operation);
+                metrics::inc_counter("requests_expired_total", 1);
+                self.track(std::slice::from_ref(&transaction), RequestStatus::Failed { reason: "请求已过期".to_string() });
+                self.reply(&transaction, ReplyOutcome::Expired);
+                return;
+            }
+
+            if self.exit_deadline.is_some() || self.in_maintenance {
+                info!("节点{}正在退出或维护，拒绝请求'{}'", self.id, operation);
+                let reason = format!("节点{}正在退出或维护", self.id);
+                self.track(std::slice::from_ref(&transaction), RequestStatus::Failed { reason: reason.clone() });
+                self.reply(&transaction, ReplyOutcome::Rejected(reason));
+                return;
+            }
+
+            // 特性只会由未激活变为激活，此时能通过检查的请求之后也能进入区块
+            let height = self.chain.lock().unwrap().height() + 1;
+            if let Err(reason) = self.genesis.features.check(&transaction, height) {
+                info!("节点{}拒绝请求: {}", self.id, reason);
+                metrics::inc_counter("feature_rejected_total", 1);
+                self.track(std::slice::from_ref(&transaction), RequestStatus::Failed { reason: reason.clone() });
+                self.reply(&transaction, ReplyOutcome::Rejected(reason));
+                return;
+            }
+
+            // 已执行的请求被重发时直接返回缓存的答复；被更新请求取代的旧请求丢弃
+            if let (Some(client), Some(timestamp)) = (&client_id, timestamp) {
+                let cached = self.execution.lock().unwrap().reply_cache.lookup(client, timestamp);
+                match cached {
+                    Lookup::Hit(executed, status) => {
+                        debug!("节点{}用缓存答复客户端{}重发的请求'{}'", self.id, client, operation);
+                        metrics::inc_counter("reply_cache_hits_total", 1);
+                        self.reply(&executed, ReplyOutcome::Executed(status));
+                        return;
+                    }
+                    Lookup::Stale(latest) => {
+                        info!("节点{}丢弃客户端{}的旧请求'{}'：时间戳{}早于已执行的{}", self.id, client, operation, timestamp, latest);
+                        return;
+                    }
+                    Lookup::Miss => {}
+                }
+            }
+
+            // 应用层准入检查，未通过的操作不占用共识带宽
+            let admitted = self.admission_policy.check(&operation, &self.state.lock().unwrap());
+            if let Err(reason) = admitted {
+                info!("节点{}拒绝请求'{}': {}", self.id, operation, reason);
+                metrics::inc_counter("admission_rejected_total", 1);
+                self.track(std::slice::from_ref(&transaction), RequestStatus::Failed { reason });
+                return;
+            }
+
+            if operation.strip_prefix(BEACON_COMMAND).is_some_and(|rest| rest.starts_with(' ')) {
+                info!("节点{}拒绝请求: 信标证明只能由主节点在提议时加入", self.id);
+                metrics::inc_counter("admission_rejected_total", 1);
+                self.track(std::slice::from_ref(&transaction), RequestStatus::Failed { reason: "信标证明只能由主节点加入".to_string() });
+                return;
+            }
+
+            // 多签操作按当前状态检查签名，未达到门限的不进入共识；执行时还会再检查一次
+            if let Some(payload) = operation.strip_prefix(MULTISIG_COMMAND).and_then(|rest| rest.strip_prefix(' ')) {
+                let checked = multisig::check(self.execution.lock().unwrap().state(), payload);
+                if let Err(reason) = checked {
+                    info!("节点{}拒绝多签操作: {}", self.id, reason);
+                    metrics::inc_counter("multisig_rejected_total", 1);
+                    self.track(std::slice::from_ref(&transaction), RequestStatus::Failed { reason: reason.clone() });
+                    self.reply(&transaction, ReplyOutcome::Rejected(reason));
+                    return;
+                }
+            }
+
+            // 将请求加入待处理队列，达到上限时淘汰最早的请求
+            if self.pending_requests.len() >= MAX_PENDING_REQUESTS {
+                let evicted = self.pending_requests.remove(0);
+                if let Some(evicted) = evicted.to_transaction() {
+                    info!("节点{}的待处理队列已满，淘汰最早的请求: {}", self.id, evicted.operation);
+                    self.track(std::slice::from_ref(&evicted), RequestStatus::Failed { reason: "待处理队列已满，请求被淘汰".to_string() });
+                }
+                record_evictions("pending_requests", 1);
+            }
+            self.pending_requests.push(msg.clone());
+            self.track(std::slice::from_ref(&transaction), RequestStatus::Pending);
+
+            if self.is_primary() && !self.view_change_in_progress {
+                info!("节点{}（主节点）处理客户端请求: {}，优先级: {:?}", self.id, operation, priority);
+                let was_empty = self.batch_queue.is_empty();
+                if !self.batch_queue.enqueue(transaction.clone(), priority, expires_at) {
+                    info!("节点{}拒绝请求：优先级{:?}超出速率限制", self.id, priority);
+                    self.track(std::slice::from_ref(&transaction), RequestStatus::Failed { reason: format!("优先级{:?}超出速率限制", priority) });
+                    metrics::inc_counter(&format!("qos_rejected_total{{priority=\"{:?}\"}}", priority), 1);
+                    self.pending_requests.pop();
+                    return;
+                }
+                if was_empty {
+                    self.batch_started = Some(self.clock.now());
+                }
+
+                if self.batch_queue.len() >= self.batch_controller.batch_size() {
+                    self.propose_batch().await;
+                }
+            } else {
+                info!("节点{}不是主节点，等待主节点处理请求", self.id);
+            }
+        }
+    }
+
+    async fn handle_client_request(&mut self, request: PBFTMessage, client_id: String, signature: Signature) {
+        let operation = match &request {
+            PBFTMessage::Request { operation, .. } => operation.clone(),
+            _ => {
+                error!("节点{}收到客户端{}的无效请求: {:?}", self.id, client_id, request);
+                return;
+            }
+        };
+
+        // 准入检查：验证客户端签名，再检查操作类型权限
+        let payload = self.genesis.message_payload(&request);
+        let admitted = self.client_registry.verify(&client_id, &payload, &signature)
+            .and_then(|_| self.client_registry.authorize(&client_id, &operation));
+
+        match admitted {
+            Ok(()) => {
+                debug!("节点{}接受客户端{}的请求: {}", self.id, client_id, operation);
+                // 记录经过认证的客户端身份，随交易一起进入批次
+                if let PBFTMessage::Request { operation, priority, expires_at, session, timestamp, .. } = request {
+                    let request = PBFTMessage::Request { operation, priority, client_id: Some(client_id), expires_at, session, timestamp };
+                    self.handle_request(request).await;
+                }
+            }
+            Err(e) => {
+                info!("节点{}拒绝客户端请求: {}", self.id, e);
+                metrics::inc_counter("acl_rejected_total", 1);
+            }
+        }
+    }
+
+    // 删除待处理队列和批处理队列中到期的请求，并通知提交它们的客户端
+    fn expire_requests(&mut self) {
+        let now = self.network_time_ms();
+        self.batch_queue.remove_expired(now);
+        let (expired, live): (Vec<PBFTMessage>, Vec<PBFTMessage>) = std::mem::take(&mut self.pending_requests)
+            .into_iter()
+            .partition(|request| matches!(request, PBFTMessage::Request { expires_at: Some(at), .. } if *at <= now));
+        self.pending_requests = live;
+        for transaction in expired.iter().filter_map(PBFTMessage::to_transaction) {
+            info!("节点{}的待处理请求'{}'已过期，删除", self.id, transaction.operation);
+            metrics::inc_counter("requests_expired_total", 1);
+            self.track(std::slice::from_ref(&transaction), RequestStatus::Failed { reason: "请求已过期".to_string() });
+            self.reply(&transaction, ReplyOutcome::Expired);
+        }
+    }
+
+    fn track(&self, transactions: &[Transaction], status: RequestStatus) {
+        self.request_status.lock().unwrap().update_all(transactions, status);
+    }
+
+    // 通过答复通道通知客户端；匿名请求无处答复
+    fn reply(&self, transaction: &Transaction, outcome: ReplyOutcome) {
+        if let Some(client_id) = &transaction.client_id {
+            let height = matches!(outcome, ReplyOutcome::Executed(_)).then(|| self.execution.lock().unwrap().applied_height());
+            let reply = PBFTMessage::Reply {
+                node_id: self.id,
+                client_id: client_id.clone(),
+                operation: transaction.operation.clone(),
+                session: transaction.session.clone(),
+                timestamp: transaction.timestamp,
+                outcome,
+                height,
+            };
+            if let Err(e) = self.reply_sink.deliver(client_id, reply) {
+                debug!("节点{}丢弃给客户端{}的答复: {}", self.id, client_id, e);
+            }
+        }
+    }
+
+    async fn propose_batch(&mut self) {
+        self.expire_requests();
+        if self.batch_queue.is_empty() {
+            self.batch_started = None;
+            return;
+        }
+        // 核心一次只跟踪一个实例，上一个实例提交前不提议新批次，请求在队列中累积为下一个批次
+        if matches!(self.core.phase, Phase::PrePrepared | Phase::Prepared) {
+            self.batch_started = None;
+            return;
+        }
+
+        let mut batch = self.batch_queue.next_batch(self.batch_controller.batch_size());
+        self.batch_started = if self.batch_queue.is_empty() { None } else { Some(self.clock.now()) };
+        // 已执行到链尖时，以对最新信标的VRF证明作为批次的第一笔交易
+        let previous = {
+            let execution = self.execution.lock().unwrap();
+            (execution.applied_height() == self.chain.lock().unwrap().height()).then(|| beacon::latest(execution.state(), &self.genesis.chain_id))
+        };
+        if let Some(previous) = previous {
+            let operation = beacon::operation(self.id, &self.signing_key, &previous);
+            batch.insert(0, Transaction { operation, client_id: None, session: None, timestamp: None });
+        }
+
+        let digest = self.compute_digest(&batch);
+        let actions = self.core.handle(Input::Propose { digest, transactions: batch });
+        self.apply(actions).await;
+    }
+
+    async fn handle_preprepare(&mut self, msg: PBFTMessage, sender: Option<usize>) {
+        if let PBFTMessage::PrePrepare { view, sequence_number, digest, transactions } = msg {
+            info!("节点{}处理PrePrepare消息: view={}, seq={}, digest={}", self.id, view, sequence_number, digest);
+
+            // 只有该视图的主节点能提议
+            if sender != Some(self.leader(view)) {
+                error!("节点{}收到非主节点{:?}发送的视图{}的PrePrepare，拒绝", self.id, sender, view);
+                metrics::inc_counter("preprepare_rejected_total", 1);
+                return;
+            }
+
+            if view == self.core.view && !self.is_primary() {
+                if let Err(reason) = self.validate_preprepare(view, sequence_number, &digest, &transactions) {
+                    error!("节点{}拒绝PrePrepare消息（序列号{}）: {}，主节点可能存在恶意行为", self.id, sequence_number, reason);
+                    metrics::inc_counter("preprepare_rejected_total", 1);
+                    return;
+                }
+            }
+
+            let actions = self.core.handle(Input::PrePrepare { view, sequence_number, digest, transactions });
+            self.apply(actions).await;
+        }
+    }
+
+    fn validate_preprepare(&self, view: u64, sequence_number: u64, digest: &str, transactions: &[Transaction]) -> Result<(), String> {
+        let expected = self.compute_digest(transactions);
+        if expected != digest {
+            return Err(format!("摘要与批次内容不符（期望{}）", expected));
+        }
+        let reproposed = self.o_set.iter().find_map(|pre_prepare| match pre_prepare {
+            PBFTMessage::PrePrepare { sequence_number: s, digest, .. } if *s == sequence_number => Some(digest),
+            _ => None,
+        });
+        if reproposed.is_some_and(|reproposed| reproposed != digest) {
+            return Err("与NewView的O集合不符".to_string());
+        }
+
+        // 信标证明只能由本视图的主节点放在批次第一笔，其内容在执行时验证
+        for (i, tx) in transactions.iter().enumerate() {
+            if let Some(payload) = tx.operation.strip_prefix(BEACON_COMMAND).and_then(|rest| rest.strip_prefix(' ')) {
+                if i != 0 || beacon::proposer(payload) != Some(self.leader(view)) {
+                    return Err("批次中的信标证明不是主节点放在第一笔的".to_string());
+                }
+            }
+        }
+
+        let height = self.chain.lock().unwrap().height() + 1;
+        self.genesis.features.check_all(transactions, height)?;
+
+        let state = self.state.lock().unwrap();
+        for tx in transactions {
+            self.admission_policy.check(&tx.operation, &state)
+                .map_err(|reason| format!("操作'{}'未通过准入检查: {}", tx.operation, reason))?;
+        }
+        Ok(())
+    }
+
+    // 摘要模式的PrePrepare：先用本地待处理的请求补齐交易，缺少的立即向主节点和对等节点索取
+    async fn handle_preprepare_digests(&mut self, mut fetch: PayloadFetch) {
+        if fetch.view != self.core.view {
+            debug!("节点{}忽略视图{}的摘要模式PrePrepare，当前视图为{}", self.id, fetch.view, self.core.view);
+            return;
+        }
+        let local: Vec<Transaction> = self.pending_requests.iter().filter_map(PBFTMessage::to_transaction).collect();
+        fetch.offer(self.genesis.hash_function, local);
+        if let Some(expanded) = fetch.expand() {
+            self.released.push_back(expanded);
+            return;
+        }
+
+        let missing = fetch.missing();
+        info!("节点{}缺少PrePrepare（序列号{}）引用的{}笔交易，向主节点和对等节点索取", self.id, fetch.sequence_number, missing.len());
+        metrics::inc_counter("payload_fetch_requests_total", 1);
+        let request = PBFTMessage::FetchPayload { node_id: self.id, digests: missing };
+        let magic = self.genesis.network_magic();
+        // 主节点一定有这些交易，先向它索取
+        send_message(magic, self.id, fetch.primary, request.clone()).await;
+        for i in (0..N).filter(|i| *i != self.id && *i != fetch.primary) {
+            send_message(magic, self.id, i, request.clone()).await;
+        }
+        self.payload_fetch = Some(fetch);
+    }
+
+    async fn handle_fetch_payload(&self, node_id: usize, digests: Vec<String>) {
+        if self.core.strategy == Strategy::WithholdPayload {
+            debug!("节点{}按故障策略不应答节点{}的交易索取", self.id, node_id);
+            return;
+        }
+        let hash_function = self.genesis.hash_function;
+        let fetched = self.payload_fetch.iter().flat_map(|fetch| fetch.found.values().cloned());
+        let transactions: Vec<Transaction> = self.pending_requests.iter()
+            .filter_map(PBFTMessage::to_transaction)
+            .chain(self.core.batch.iter().cloned())
+            .chain(fetched)
+            .filter(|tx| digests.contains(&chain_index::transaction_digest(hash_function, tx)))
+            .take(digests.len())
+            .collect();
+        if transactions.is_empty() {
+            debug!("节点{}没有节点{}索取的交易", self.id, node_id);
+            return;
+        }
+        debug!("节点{}向节点{}发送{}笔交易", self.id, node_id, transactions.len());
+        send_message(self.genesis.network_magic(), self.id, node_id, PBFTMessage::Payload { node_id: self.id, transactions }).await;
+    }
+
+    // 原样转发本节点保存的、主节点为该实例签名的PrePrepare；主节点分叉时全部转发，索取方据此得到证据
+    async fn handle_fetch_preprepare(&self, node_id: usize, view: u64, sequence_number: u64) {
+        let signed: Vec<PBFTMessage> = self.signed_preprepares.range((view, sequence_number, String::new())..)
+            .take_while(|((v, s, _), _)| (*v, *s) == (view, sequence_number))
+            .map(|(_, signed)| signed.clone())
+            .collect();
+        if signed.is_empty() {
+            debug!("节点{}没有节点{}索取的PrePrepare（视图{}，序列号{}）", self.id, node_id, view, sequence_number);
+            return;
+        }
+        debug!("节点{}向节点{}转发视图{}序列号{}的PrePrepare", self.id, node_id, view, sequence_number);
+        for message in signed {
+            send_message(self.genesis.network_magic(), self.id, node_id, message).await;
+        }
+    }
+
+    // 只收下与所索取的摘要一致的交易，补齐后按主节点签名的完整PrePrepare重新处理
+    fn handle_payload(&mut self, node_id: usize, transactions: Vec<Transaction>) {
+        let fetch = match &mut self.payload_fetch {
+            Some(fetch) => fetch,
+            None => return,
+        };
+        let accepted = fetch.offer(self.genesis.hash_function, transactions);
+        debug!("节点{}从节点{}收到{}笔所需的交易", self.id, node_id, accepted);
+        if let Some(expanded) = fetch.expand() {
+            self.payload_fetch = None;
+            self.released.push_back(expanded);
+        }
+    }
+
+    // 主节点签名了PrePrepare却拿不出其中的交易，对等节点也没有，无法验证也无法执行该批次
+    async fn payload_fetch_expired(&mut self) {
+        let fetch = match self.payload_fetch.take() {
+            Some(fetch) if fetch.view == self.core.view => fetch,
+            _ => return,
+        };
+        warn!("节点{}在{}ms内未能补齐主节点{}的PrePrepare（序列号{}）", self.id, PAYLOAD_FETCH_TIMEOUT_MS, fetch.primary, fetch.sequence_number);
+        metrics::inc_counter("payload_fetch_timeouts_total", 1);
+        self.start_view_change(fetch.view + 1, "主节点不提供PrePrepare引用的交易").await;
+    }
+
+    async fn handle_prepare(&mut self, msg: PBFTMessage) {
+        info!("节点{}处理Prepare消息: {:?}", self.id, msg);
+
+        if let PBFTMessage::Prepare { view, sequence_number, digest, sender_id } = msg {
+            let actions = self.core.handle(Input::Prepare { view, sequence_number, digest, sender_id });
+            self.apply(actions).await;
+        }
+    }
+
+    // 主节点汇集的Prepare：逐个验签后当作各副本直接发来的Prepare处理
+    async fn handle_prepare_certificate(&mut self, msg: PBFTMessage) {
+        let (view, sequence_number, digest) = match &msg {
+            PBFTMessage::PrepareCertificate { view, sequence_number, digest, .. } => (*view, *sequence_number, digest.clone()),
+            _ => return,
+        };
+        let primary = self.leader(view);
+        let signers = match vote_aggregation::verify(&msg, self.id, primary, &self.public_keys, &self.genesis) {
+            Ok(signers) => signers,
+            Err(reason) => {
+                error!("节点{}收到主节点{}的无效Prepare证书: {}", self.id, primary, reason);
+                self.penalize(primary, reputation::Event::InvalidSignature).await;
+                return;
+            }
+        };
+        debug!("节点{}收到主节点{}汇集的{}个Prepare，序列号: {}", self.id, primary, signers.len(), sequence_number);
+        for (signer, signature) in signers {
+            self.prepare_signatures
+                .entry((view, sequence_number, digest.clone()))
+                .or_default()
+                .insert(signer, signature);
+            let actions = self.core.handle(Input::Prepare { view, sequence_number, digest: digest.clone(), sender_id: signer });
+            self.apply(actions).await;
+        }
+    }
+
+    // 更新节点信誉并持久化，返回该节点是否因此首次变为可疑
+    fn record_reputation(&mut self, node_id: usize, event: reputation::Event) -> bool {
+        if node_id == self.id {
+            return false;
+        }
+        let mut reputation = self.reputation.lock().unwrap();
+        let (changed, newly_suspected) = reputation.record(node_id, event);
+        if changed {
+            reputation.save(self.id);
+            self.performance.set_reputation(node_id, reputation.score(node_id) as f64 / MAX_REPUTATION as f64);
+        }
+        newly_suspected
+    }
+
+    // 扣分。信誉只影响本地的限流和选举权重，拉黑须凭证据投票
+    async fn penalize(&mut self, node_id: usize, event: reputation::Event) {
+        if self.record_reputation(node_id, event) {
+            info!("节点{}将节点{}标记为可疑，信誉分数{}", self.id, node_id, self.reputation.lock().unwrap().score(node_id));
+        }
+    }
+
+    // 保存主节点签名的PrePrepare；同一实例已保存过另一个摘要的，两条合起来就是主节点分叉的证据
+    fn remember_preprepare(&mut self, signed: PBFTMessage) -> Option<Evidence> {
+        let (view, sequence_number, digest) = match &signed {
+            PBFTMessage::SignedMessage { message, .. } => match &**message {
+                PBFTMessage::PrePrepare { view, sequence_number, digest, .. } => (*view, *sequence_number, digest.clone()),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let conflicting = self.signed_preprepares.range((view, sequence_number, String::new())..)
+            .take_while(|((v, s, _), _)| (*v, *s) == (view, sequence_number))
+            .find(|((_, _, d), _)| *d != digest)
+            .map(|(_, first)| first.clone());
+        self.signed_preprepares.insert((view, sequence_number, digest), signed.clone());
+        while self.signed_preprepares.len() > SIGNED_PREPREPARE_HISTORY {
+            self.signed_preprepares.pop_first();
+        }
+        conflicting.map(|first| Evidence::Equivocation { first: Box::new(first), second: Box::new(signed) })
+    }
+
+    // 当前实例中某副本的Prepare与主节点签名的PrePrepare不符：两条签名消息构成证据
+    fn divergent_prepare_evidence(&self, sender_id: usize, digest: String) -> Option<Evidence> {
+        let (view, sequence_number) = (self.core.view, self.core.sequence_number);
+        let pre_prepare = self.signed_preprepares.get(&(view, sequence_number, self.core.digest.clone()))?.clone();
+        let signature = *self.prepare_signatures.get(&(view, sequence_number, digest.clone()))?.get(&sender_id)?;
+        let prepare = PBFTMessage::SignedMessage {
+            message: Box::new(PBFTMessage::Prepare { view, sequence_number, digest, sender_id }),
+            signature,
+            sender_id,
+            trace: None,
+        };
+        Some(Evidence::DivergentPrepare { pre_prepare: Box::new(pre_prepare), prepare: Box::new(prepare) })
+    }
+
+    fn verify_evidence(&self, evidence: &Evidence) -> Result<usize, String> {
+        evidence.verify(&self.public_keys, &self.genesis, |view| self.leader(view))
+    }
+
+    // 凭证据投票拉黑：广播附带证据的拜占庭投票，本节点的一票直接计入。对同一节点只投一次
+    async fn accuse(&mut self, evidence: Evidence) {
+        let suspected_id = match self.verify_evidence(&evidence) {
+            Ok(suspected_id) if suspected_id != self.id => suspected_id,
+            _ => return,
+        };
+        if self.vote_evidence.contains_key(&(suspected_id, evidence.view(), self.id)) {
+            return;
+        }
+        info!("节点{}凭证据投票拉黑节点{}", self.id, suspected_id);
+        let vote_msg = PBFTMessage::ByzantineVote {
+            suspected_id,
+            sender_id: self.id,
+            evidence: evidence.clone(),
+        };
+        self.broadcast(&vote_msg).await;
+        // 广播不发给自己，本节点的投票直接计入
+        self.handle_byzantine_vote(suspected_id, self.id, evidence).await;
+    }
+
+    async fn handle_byzantine_vote(&mut self, suspected_id: usize, sender_id: usize, evidence: Evidence) {
+        info!("节点{}收到来自节点{}的拜占庭投票，怀疑节点{}", self.id, sender_id, suspected_id);
+        match self.verify_evidence(&evidence) {
+            Ok(accused) if accused == suspected_id => {}
+            result => {
+                let reason = result.err().unwrap_or_else(|| "证据指向其他节点".to_string());
+                error!("节点{}不计入节点{}对节点{}的拜占庭投票: {}", self.id, sender_id, suspected_id, reason);
+                metrics::inc_counter("byzantine_votes_rejected_total", 1);
+                return;
+            }
+        }
+        // 很久以前（或远在未来）视图中的证据不计票，过期的指控不能与新的指控凑成法定人数
+        let view = evidence.view();
+        if view + BYZANTINE_VOTE_VIEWS < self.core.view || view > self.core.view + BYZANTINE_VOTE_VIEWS {
+            info!("节点{}不计入节点{}对节点{}的拜占庭投票: 证据属于视图{}，当前视图{}", self.id, sender_id, suspected_id, view, self.core.view);
+            metrics::inc_counter("byzantine_votes_out_of_window_total", 1);
+            return;
+        }
+        if suspected_id == self.id {
+            self.appeal(&evidence).await;
+            return;
+        }
+        self.vote_evidence.insert((suspected_id, view, sender_id), evidence);
+
+        let mut state = self.state.lock().unwrap();
+        let (votes, evicted) = state.record_byzantine_vote(suspected_id, view, sender_id);
+        if let Some(evicted) = evicted {
+            info!("节点{}记录的被指控节点已达上限，淘汰对节点{}的投票", self.id, evicted);
+            self.vote_evidence.retain(|(suspect, _, _), _| *suspect != evicted);
+        }
+
+        if votes >= BLACKLIST_QUORUM && self.blacklist.insert(suspected_id) {
+            let mut voters: Vec<usize> = state.byzantine_votes[&suspected_id][&view].iter().copied().collect();
+            voters.sort_unstable();
+            drop(state);
+            self.performance.mark_blacklisted(suspected_id);
+            self.events.publish(ConsensusEvent::PeerBlacklisted { node_id: suspected_id });
+            info!("节点{}确定节点{}为拜占庭节点，将其加入黑名单", self.id, suspected_id);
+            let message = format!("节点{}经{:?}的拜占庭投票被拉黑", suspected_id, voters);
+            self.alerts.raise(AlertKind::PeerBlacklisted, Some(suspected_id), message, self.clock.now());
+            self.audit(AuditEvent::Blacklisted { node_id: suspected_id, voters });
+        }
+    }
+
+    // 本节点被指控Prepare与主节点的PrePrepare不符：出示主节点为本节点的Prepare摘要签名的PrePrepare
+    async fn appeal(&mut self, evidence: &Evidence) {
+        let pre_prepare = match evidence.appealable().and_then(|key| self.signed_preprepares.get(&key)) {
+            Some(pre_prepare) => pre_prepare.clone(),
+            None => {
+                warn!("节点{}被指控作恶，但没有可以申诉的PrePrepare", self.id);
+                return;
+            }
+        };
+        info!("节点{}出示主节点签名的PrePrepare，申诉对自己的指控", self.id);
+        metrics::inc_counter("byzantine_appeals_sent_total", 1);
+        self.broadcast(&PBFTMessage::Appeal { node_id: self.id, pre_prepare: Box::new(pre_prepare) }).await;
+    }
+
+    // 申诉出示的PrePrepare与某张投票的证据合成主节点分叉的证据时，该投票撤销，改为凭新证据指控主节点
+    async fn handle_appeal(&mut self, node_id: usize, pre_prepare: PBFTMessage) {
+        let mut withdrawn = Vec::new();
+        let mut counter_evidence = None;
+        for ((accused, view, voter), evidence) in &self.vote_evidence {
+            if *accused != node_id {
+                continue;
+            }
+            if let Some(equivocation) = evidence.answered_by(&pre_prepare) {
+                if self.verify_evidence(&equivocation).is_ok_and(|primary| primary != node_id) {
+                    withdrawn.push((*view, *voter));
+                    counter_evidence = Some(equivocation);
+                }
+            }
+        }
+        if withdrawn.is_empty() {
+            debug!("节点{}的申诉不能推翻任何投票", node_id);
+            return;
+        }
+
+        withdrawn.sort_unstable();
+        for (view, voter) in &withdrawn {
+            self.vote_evidence.remove(&(node_id, *view, *voter));
+        }
+        // 撤销后各视图中剩余的最高票数
+        let remaining = {
+            let mut state = self.state.lock().unwrap();
+            let views = state.byzantine_votes.entry(node_id).or_default();
+            for (view, voter) in &withdrawn {
+                if let Some(votes) = views.get_mut(view) {
+                    votes.remove(voter);
+                }
+            }
+            views.values().map(HashSet::len).max().unwrap_or(0)
+        };
+        let mut voters: Vec<usize> = withdrawn.into_iter().map(|(_, voter)| voter).collect();
+        voters.sort_unstable();
+        voters.dedup();
+        info!("节点{}接受节点{}的申诉，撤销节点{:?}的拜占庭投票", self.id, node_id, voters);
+        metrics::inc_counter("byzantine_appeals_accepted_total", 1);
+        self.audit(AuditEvent::AccusationWithdrawn { node_id, voters });
+        if remaining < BLACKLIST_QUORUM && self.blacklist.remove(&node_id) {
+            info!("节点{}将节点{}移出黑名单", self.id, node_id);
+            self.performance.clear_blacklisted(node_id);
+        }
+        if let Some(evidence) = counter_evidence {
+            self.accuse(evidence).await;
+        }
+    }
+
+    async fn handle_commit(&mut self, msg: PBFTMessage, sender: Option<usize>) {
+        info!("节点{}处理Commit消息: {:?}", self.id, msg);
+
+        // Commit没有发送者字段，按认证包装确认的发送者计票
+        if let (PBFTMessage::Commit { view, sequence_number, digest }, Some(sender_id)) = (msg, sender) {
+            let actions = self.core.handle(Input::Commit { view, sequence_number, digest, sender_id });
+            self.apply(actions).await;
+        }
+    }
+
+    // 执行共识核心产生的动作
+    async fn apply(&mut self, actions: Vec<Action>) {
+        for action in actions {
+            match action {
+                Action::Broadcast(msg) => {
+                    self.endorse(&msg);
+                    self.audit_vote(&msg, None);
+                    self.broadcast(&msg).await;
+                }
+                Action::Send(to, msg) => {
+                    self.endorse(&msg);
+                    self.audit_vote(&msg, Some(to));
+                    self.send_to(to, msg).await;
+                }
+                Action::Persist(record) => {
+                    if let (Record::Prepared(..), Some(trace)) = (&record, &mut self.trace) {
+                        trace.end_phase("Prepare", self.clock.unix_nanos());
+                    }
+                    if let Record::Prepared(sequence_number, digest) = &record {
+                        self.latency_budget.prepared(self.core.view, *sequence_number, self.clock.now());
+                        self.track(&self.core.batch, RequestStatus::Prepared { view: self.core.view, sequence_number: *sequence_number });
+                        self.events.publish(ConsensusEvent::Prepared { view: self.core.view, sequence_number: *sequence_number, digest: digest.clone() });
+                    }
+                    let mut state = self.state.lock().unwrap();
+                    match record {
+                        Record::Prepared(seq, digest) => state.prepared.insert((seq, digest)),
+                        Record::Committed(seq, digest) => state.committed.insert((seq, digest)),
+                    };
+                    state.enforce_limits(self.core.view);
+                    state.save(self.id);
+                }
+                Action::Execute { view, sequence_number, digest } => {
+                    self.latency_budget.committed(view, sequence_number, self.clock.now());
+                    let certificate = self.commit_certificate(view, sequence_number, digest);
+                    self.finish_commit(certificate).await;
+                }
+                Action::SetTimer(Timer::Aggregation { sequence_number }) => {
+                    self.aggregation_deadline = Some((sequence_number, self.clock.now() + Duration::from_millis(VOTE_AGGREGATION_TIMEOUT_MS)));
+                }
+                Action::AggregatePrepares { view, sequence_number, digest } => {
+                    let signatures = self.prepare_signatures.get(&(view, sequence_number, digest.clone())).cloned().unwrap_or_default();
+                    let certificate = vote_aggregation::certificate(self.id, view, sequence_number, digest, &signatures);
+                    metrics::inc_counter("vote_aggregation_certificates_total", 1);
+                    self.broadcast(&certificate).await;
+                }
+                Action::SetTimer(Timer::Proposal { sequence_number }) => {
+                    if !self.is_primary() {
+                        self.audit(AuditEvent::AcceptedPrePrepare {
+                            view: self.core.view,
+                            sequence_number,
+                            digest: self.core.digest.clone(),
+                            primary: self.core.primary,
+                        });
+                    }
+                    self.track(&self.core.batch, RequestStatus::Ordered { view: self.core.view, sequence_number });
+                    self.events.publish(ConsensusEvent::ProposalAccepted {
+                        view: self.core.view,
+                        sequence_number,
+                        digest: self.core.digest.clone(),
+                        primary: self.core.primary,
+                    });
+                    // 从提议（或收到提议）开始计时，用于评估主节点的表现
+                    self.proposal_times.insert(sequence_number, self.clock.now());
+                    self.latency_budget.accepted(self.core.view, sequence_number, self.core.primary, self.clock.now());
+                    // 主节点开启新的trace，副本加入PrePrepare所属的trace
+                    self.trace = Some(InstanceTrace::start(sequence_number, self.incoming_trace.take(), self.clock.unix_nanos()));
+                }
+                Action::DivergentPrepare { sender_id, digest } => {
+                    self.penalize(sender_id, reputation::Event::ProtocolViolation).await;
+                    if let Some(evidence) = self.divergent_prepare_evidence(sender_id, digest) {
+                        self.accuse(evidence).await;
+                    }
+                }
+                Action::FetchPrePrepare { view, sequence_number } => {
+                    metrics::inc_counter("preprepare_fetch_requests_total", 1);
+                    let request = PBFTMessage::FetchPrePrepare { node_id: self.id, view, sequence_number };
+                    for i in (0..N).filter(|i| *i != self.id) {
+                        send_message(self.genesis.network_magic(), self.id, i, request.clone()).await;
+                    }
+                }
+                Action::Suspect(sender_id) => {
+                    self.penalize(sender_id, reputation::Event::ProtocolViolation).await;
+                }
+                Action::ViewChange(target_view) => {
+                    if !self.view_change_in_progress {
+                        info!("节点{}因主节点作恶发起视图切换，目标视图{}", self.id, target_view);
+                        self.start_view_change(target_view, "主节点作恶").await;
+                    }
+                }
+            }
+        }
+    }
+
+    // 记录本节点对PrePrepare或Prepare的签名
+    fn endorse(&mut self, msg: &PBFTMessage) {
+        if let PBFTMessage::PrePrepare { view, sequence_number, digest, .. }
+            | PBFTMessage::Prepare { view, sequence_number, digest, .. } = msg
+        {
+            let payload = self.genesis.message_payload(msg);
+            let signature = self.signing_key.sign(&payload);
+            self.prepare_signatures
+                .entry((*view, *sequence_number, digest.clone()))
+                .or_default()
+                .insert(self.id, signature);
+            // 主节点保留自己签名的PrePrepare，副本的Prepare与之不符时据此组装证据
+            if let PBFTMessage::PrePrepare { .. } = msg {
+                self.remember_preprepare(PBFTMessage::SignedMessage { message: Box::new(msg.clone()), signature, sender_id: self.id, trace: None });
+            }
+        }
+    }
+
+    // 提交当前实例：生成区块、执行操作、通知订阅者
+    async fn finish_commit(&mut self, certificate: CommitCertificate) {
+        // 之前的实例没有提交就不能生成本实例的区块，先补齐缺口
+        if self.missing_range(certificate.view, certificate.sequence_number).is_some() {
+            info!("节点{}在序列号{}之前有未提交的实例，暂缓提交", self.id, certificate.sequence_number);
+            let (view, sequence_number) = (certificate.view, certificate.sequence_number);
+            self.deferred_commit = Some(certificate);
+            self.detect_gap(view, sequence_number, None).await;
+            return;
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.end_phase("Commit", self.clock.unix_nanos());
+        }
+        // 签名及时计入证书的节点恢复信誉
+        for (signer, _) in &certificate.signatures {
+            self.record_reputation(*signer, reputation::Event::CorrectVote);
+        }
+        let block = self.append_block(certificate);
+        let height = block.header.height;
+        // 核心一次只运行一个实例，此前已准备的请求或已提交，或已在新视图中重新提交
+        self.prepared_entry = None;
+        // 已提交而未持久的区块过多时等待写入任务，预写日志不会无限增长
+        let durable_target = height.saturating_sub(STORAGE_PIPELINE_DEPTH);
+        if self.storage.durable_height() < durable_target {
+            metrics::inc_counter("storage_backpressure_total", 1);
+            self.storage.wait_for(durable_target).await;
+        }
+        self.view_stats.lock().unwrap().record_block();
+        // 执行操作或回复客户端
+        self.execute_block(&block);
+        self.latency_budget.executed(block.header.view, block.header.sequence_number, &self.genesis.validators, self.clock.now());
+        self.events.publish(ConsensusEvent::Committed {
+            view: block.header.view,
+            sequence_number: block.header.sequence_number,
+            height,
+            transactions: block.transactions.len(),
+        });
+        self.forget_committed(&block);
+        self.apply_governance();
+        self.announce_block(block).await;
+        self.checkpoint(height).await;
+        self.finish_trace();
+
+        if let Some(proposed_at) = self.proposal_times.remove(&self.core.sequence_number) {
+            let latency = self.clock.now().duration_since(proposed_at);
+            metrics::inc_counter("commit_latency_ms_total", latency.as_millis() as u64);
+            metrics::inc_counter("commit_latency_samples_total", 1);
+            metrics::observe("commit_latency_ms", latency.as_millis() as u64);
+            self.performance.record_commit(self.leader(self.core.view), latency);
+            // 主节点根据提交延迟调整批处理参数
+            if self.is_primary() {
+                self.batch_controller.observe_commit(latency, self.batch_queue.len());
+            }
+        }
+        // 主节点计划退出时，进行中的实例提交后立即交接
+        self.hand_off_if_leaving().await;
+        // 上一个实例已提交，排队的请求立即作为下一个批次提议
+        if self.is_primary() && !self.batch_queue.is_empty() {
+            let now = self.clock.now();
+            self.batch_started = Some(now.checked_sub(self.batch_controller.batch_timeout()).unwrap_or(now));
+        }
+    }
+
+    // 结束当前实例的trace，配置了OTLP接收端时在后台导出
+    fn finish_trace(&mut self) {
+        let mut trace = match self.trace.take() {
+            Some(trace) if trace.sequence_number == self.core.sequence_number => trace,
+            _ => return,
+        };
+        trace.end_phase("Reply", self.clock.unix_nanos());
+        let spans = trace.finish(self.clock.unix_nanos());
+        if let Some(endpoint) = &self.otlp_endpoint {
+            metrics::inc_counter("trace_spans_exported_total", spans.len() as u64);
+            tokio::spawn(trace::export(endpoint.clone(), format!("pbft-node-{}", self.id), spans));
+        }
+    }
+
+    // 应用在当前高度新生效的治理提案
+    fn apply_governance(&mut self) {
+        let height = self.chain.lock().unwrap().height();
+        let active = governance::active_proposals(self.execution.lock().unwrap().state(), height);
+        for proposal in active {
+            if !self.governance_applied.insert(proposal.id.clone()) {
+                continue;
+            }
+            match proposal.change {
+                ParameterChange::MaxBatchSize(size) => self.batch_controller.set_max_batch_size(size),
+                ParameterChange::RequestTimeoutMs(ms) => {
+                    self.timeout_duration = Duration::from_millis(ms);
+                    if !self.view_change_in_progress {
+                        self.view_change_timeout = self.timeout_duration;
+                    }
+                }
+                ParameterChange::ActivateFeature(feature) => self.genesis.features.activate(feature, proposal.activation_height),
+            }
+            info!("节点{}在高度{}应用治理提案{}: {:?}", self.id, height, proposal.id, proposal.change);
+            metrics::inc_counter("governance_changes_applied_total", 1);
+        }
+    }
+
+    fn execute_block(&self, block: &Block) {
+        let height = block.header.height;
+        self.track(&block.transactions, RequestStatus::Committed { height });
+        if self.is_repairing() {
+            debug!("节点{}正在重新同步状态，区块{}在同步完成后执行", self.id, block.header.height);
+            return;
+        }
+        let (results, deferred) = {
+            let mut execution = self.execution.lock().unwrap();
+            if execution.applied_height() >= height {
+                debug!("节点{}已执行过区块{}，跳过", self.id, height);
+                return;
+            }
+            let results = execution.execute_block(height, &block.transactions);
+            (results, std::mem::take(&mut execution.deferred))
+        };
+        let gas_used: u64 = results.iter().chain(deferred.iter().map(|(_, result)| result)).map(|r| r.gas_used).sum();
+        // 到期的定时交易先于区块内的交易执行，答复顺序与执行顺序一致
+        let executed = deferred.iter().map(|(tx, result)| (tx, result)).chain(block.transactions.iter().zip(&results));
+        for (tx, result) in executed {
+            self.track(std::slice::from_ref(tx), RequestStatus::Executed { height, status: result.status.clone() });
+            if self.role == Role::Validator {
+                self.reply(tx, ReplyOutcome::Executed(result.status.clone()));
+            }
+            match &result.status {
+                ExecutionStatus::Success(output) => {
+                    debug!("节点{}执行操作'{}'成功，输出: {:?}，gas: {}", self.id, tx.operation, output, result.gas_used);
+                }
+                ExecutionStatus::Scheduled(at) => {
+                    debug!("节点{}登记定时操作'{}'，在高度{}执行", self.id, tx.operation, at);
+                }
+                status => {
+                    info!("节点{}执行操作'{}'失败: {:?}", self.id, tx.operation, status);
+                }
+            }
+        }
+        info!("节点{}执行区块{}，共{}笔交易、{}笔到期的定时交易，消耗gas: {}", self.id, block.header.height, results.len(), deferred.len(), gas_used);
+        metrics::inc_counter("execution_gas_used_total", gas_used);
+    }
+
+    // 执行到检查点高度后广播本节点的状态摘要
+    async fn checkpoint(&mut self, height: u64) {
+        if !self.checkpoints.is_checkpoint(height) || self.is_repairing() {
+            return;
+        }
+        let state_digest = self.execution.lock().unwrap().state_digest(self.genesis.hasher());
+        debug!("节点{}在高度{}的状态摘要: {}", self.id, height, state_digest);
+        self.broadcast(&PBFTMessage::Checkpoint { node_id: self.id, height, state_digest: state_digest.clone() }).await;
+        self.handle_checkpoint(self.id, height, state_digest).await;
+    }
+
+    async fn handle_checkpoint(&mut self, node_id: usize, height: u64, state_digest: String) {
+        if self.role != Role::Validator || node_id >= N {
+            return;
+        }
+        match self.checkpoints.record(height, node_id, state_digest) {
+            Some(CheckpointEvent::Stable { height, digest }) => {
+                info!("节点{}的检查点{}已稳定，状态摘要: {}", self.id, height, digest);
+                StateRoots::record(self.id, height, digest);
+                self.prune_signatures(height);
+            }
+            Some(CheckpointEvent::Diverged { height, local, quorum }) => {
+                error!("节点{}在检查点{}的状态摘要{}与法定人数的{}不一致，执行状态已分叉", self.id, height, local, quorum);
+                metrics::inc_counter("state_divergence_total", 1);
+                let message = format!("检查点{}的状态摘要{}与法定人数的{}不一致，开始修复", height, local, quorum);
+                self.alerts.raise(AlertKind::StateDivergence, None, message, self.clock.now());
+                self.audit(AuditEvent::StateDivergence { height, local_digest: local, quorum_digest: quorum });
+                self.start_repair().await;
+            }
+            None => {}
+        }
+    }
+
+    // 检查点稳定后，该高度及以前的实例不再需要Prepare/Commit签名（P集合、提交证书），
+    // 按区块的(视图, 序列号)删除；否则同一视图内的签名随实例数无限增长
+    fn prune_signatures(&mut self, height: u64) {
+        let low = match self.chain.lock().unwrap().header(height) {
+            Some(header) => (header.view, header.sequence_number),
+            None => return,
+        };
+        let before = self.prepare_signatures.len() + self.commit_signatures.len();
+        self.prepare_signatures.retain(|(v, s, _), _| (*v, *s) > low);
+        self.commit_signatures.retain(|(v, s, _), _| (*v, *s) > low);
+        record_evictions("signatures", before - self.prepare_signatures.len() - self.commit_signatures.len());
+    }
+
+    // 作废本地执行状态，从法定人数一致的快照重新同步；同步期间只排序不执行
+    async fn start_repair(&mut self) {
+        if self.state_sync.is_some() {
+            return;
+        }
+        info!("节点{}丢弃本地执行状态，重新同步", self.id);
+        metrics::inc_counter("state_repairs_started_total", 1);
+        self.execution.lock().unwrap().restore(0, BTreeMap::new());
+        self.snapshot_cache.clear();
+        self.state_sync = Some(StateSync::for_repair());
+        self.request_snapshots().await;
+    }
+
+    fn is_repairing(&self) -> bool {
+        self.state_sync.as_ref().is_some_and(|sync| sync.repair)
+    }
+
+    async fn announce_block(&self, block: Block) {
+        let magic = self.genesis.network_magic();
+        for subscriber in &self.block_subscribers {
+            send_message(magic, self.id, *subscriber, PBFTMessage::BlockAnnouncement { block: block.clone() }).await;
+        }
+    }
+
+    fn commit_certificate(&mut self, view: u64, sequence_number: u64, digest: String) -> CommitCertificate {
+        let mut signatures = self.commit_signatures.remove(&(view, sequence_number, digest.clone())).unwrap_or_default();
+
+        // 加入自己对同一Commit消息的签名
+        let commit_msg = PBFTMessage::Commit { view, sequence_number, digest: digest.clone() };
+        let payload = self.genesis.message_payload(&commit_msg);
+        signatures.insert(self.id, self.signing_key.sign(&payload));
+
+        CommitCertificate {
+            view,
+            sequence_number,
+            digest,
+            signatures: signatures.into_iter().collect(),
+        }
+    }
+
+    fn append_block(&mut self, certificate: CommitCertificate) -> Block {
+        let mut chain = self.chain.lock().unwrap();
+        let block = chain.append(self.core.view, self.core.sequence_number, self.core.digest.clone(), self.core.batch.clone(), certificate);
+        info!("节点{}生成区块，高度: {}，哈希: {}", self.id, block.header.height, block.header.hash(self.genesis.hasher()));
+        let block = block.clone();
+        self.storage.append(&block);
+        block
+    }
+
+    // 正常关闭前等待后台写入，下次启动不必重放预写日志
+    async fn flush_storage(&self) {
+        let height = self.chain.lock().unwrap().height();
+        if tokio::time::timeout(Duration::from_millis(STORAGE_FLUSH_TIMEOUT_MS), self.storage.wait_for(height)).await.is_err() {
+            warn!("节点{}关闭时链文件仍未持久到高度{}，下次启动从预写日志恢复", self.id, height);
+        }
+    }
+
+    async fn handle_timeout(&mut self) {
+        self.expire_requests();
+        self.expire_unauthenticated();
+        // 启动时的挑战可能在对方上线前就发出了，重新向未认证的验证者发起握手
+        for peer in 0..N {
+            if peer != self.id && !self.authenticated_peers.contains(&peer) {
+                self.challenge(peer, Duration::from_millis(HANDSHAKE_RETRY_MS)).await;
+            }
+        }
+        // 补齐缺口的请求没有得到答复时改向所有验证者重新请求
+        if let Some((view, sequence_number)) = self.deferred_commit.as_ref().map(|c| (c.view, c.sequence_number)) {
+            self.detect_gap(view, sequence_number, None).await;
+        }
+        if let Some(sync) = &self.state_sync {
+            // 尚无可用的快照提供方时重新请求清单，否则补发超时的分块请求
+            if sync.providers().is_empty() {
+                self.request_snapshots().await;
+            }
+            self.drive_state_sync().await;
+        }
+        if self.header_sync.is_some() {
+            // 区块头请求超时后改问下一个对等节点，区块体请求超时后改向所有验证者请求
+            self.request_headers().await;
+            self.backfill_bodies().await;
+        }
+
+        if self.role != Role::Validator {
+            // 全节点不参与视图切换，超时后重新订阅以追赶缺失的区块
+            self.subscribe_blocks().await;
+            return;
+        }
+        // 维护期间收不到共识消息是预期的，不因此切换视图
+        if self.in_maintenance {
+            return;
+        }
+
+        if self.view_change_in_progress {
+            // 新视图未能按时建立：切换到下一个视图，并将等待时间加倍，避免各节点频繁切换
+            if self.new_view_deadline.is_some_and(|deadline| self.clock.now() >= deadline) {
+                let max_timeout = Duration::from_millis(MAX_VIEW_CHANGE_TIMEOUT_MS);
+                self.view_change_timeout = (self.view_change_timeout * 2).min(max_timeout);
+                info!("节点{}在视图{}的新视图定时器超时，切换到视图{}，下次等待{:?}",
+                    self.id, self.core.view, self.core.view + 1, self.view_change_timeout);
+                self.view_stats.lock().unwrap().record_timeout();
+                self.start_view_change(self.core.view + 1, "新视图超时").await;
+            }
+            return;
+        }
+
+        if self.clock.now().duration_since(self.last_message_time) >= self.timeout_duration {
+            info!("节点{}检测到超时，触发视图切换", self.id);
+            self.view_stats.lock().unwrap().record_timeout();
+            self.start_view_change(self.core.view + 1, "请求超时").await;
+        }
+    }
+
+    async fn start_view_change(&mut self, target_view: u64, reason: &str) {
+        self.audit(AuditEvent::ViewChangeTriggered { from_view: self.core.view, to_view: target_view, reason: reason.to_string() });
+
+        // 当前实例已准备而未提交时，在ViewChange中携带它和副本的Prepare签名；已提交的不必在新视图中重新执行。
+        // 新视图的重新提议没能准备时，继续携带之前的证明
+        if self.core.phase == Phase::Prepared
+            && self.state.lock().unwrap().prepared.contains(&(self.core.sequence_number, self.core.digest.clone()))
+        {
+            let key = (self.core.view, self.core.sequence_number, self.core.digest.clone());
+            let prepares = self.prepare_signatures.get(&key).map_or_else(Vec::new, |signatures| {
+                signatures.iter().filter(|(signer, _)| **signer != self.core.primary).map(|(signer, signature)| (*signer, *signature)).collect()
+            });
+            self.prepared_entry = Some(PreparedEntry {
+                view: self.core.view,
+                sequence_number: self.core.sequence_number,
+                digest: self.core.digest.clone(),
+                transactions: self.core.batch.clone(),
+                prepares,
+            });
+        }
+        let prepared: Vec<PreparedEntry> = self.prepared_entry.iter().cloned().collect();
+
+        self.view_change_in_progress = true;
+        self.view_stats.lock().unwrap().set_ended_by(reason);
+        self.enter_view(target_view);
+
+        // 未提议的批次保留在pending_requests中，由新主节点重新处理
+        self.batch_queue.clear();
+        self.batch_started = None;
+        self.proposal_times.clear();
+
+        let view_change_msg = PBFTMessage::ViewChange {
+            view: self.core.view,
+            last_sequence_number: self.core.sequence_number,
+            node_id: self.id,
+            prepared,
+        };
+
+        let signed = self.sign_message(view_change_msg.clone());
+        self.signed_view_changes.insert((self.core.view, self.id), signed);
+        self.broadcast(&view_change_msg).await;
+        self.record_view_change(view_change_msg);
+
+        // 启动新视图定时器
+        self.new_view_deadline = Some(self.clock.now() + self.view_change_timeout);
+    }
+
+    // 记录ViewChange消息，同一节点对同一视图只记录一次
+    fn record_view_change(&mut self, msg: PBFTMessage) {
+        if let PBFTMessage::ViewChange { view, node_id, .. } = &msg {
+            let mut state = self.state.lock().unwrap();
+            let duplicate = state.view_change_messages.iter().any(|m| {
+                matches!(m, PBFTMessage::ViewChange { view: v, node_id: n, .. } if v == view && n == node_id)
+            });
+            if !duplicate {
+                state.view_change_messages.push(msg);
+                state.enforce_limits(self.core.view);
+            }
+        }
+    }
+
+    // 每个发送者请求的高于当前视图的最大视图
+    fn higher_view_requests(&self) -> HashMap<usize, u64> {
+        let mut requests: HashMap<usize, u64> = HashMap::new();
+        for m in &self.state.lock().unwrap().view_change_messages {
+            if let PBFTMessage::ViewChange { view, node_id, .. } = m {
+                if *view > self.core.view && *node_id != self.id {
+                    let entry = requests.entry(*node_id).or_insert(*view);
+                    *entry = (*entry).max(*view);
+                }
+            }
+        }
+        requests
+    }
+
+    async fn handle_view_change(&mut self, msg: PBFTMessage) {
+        if let PBFTMessage::ViewChange { view, node_id, .. } = msg {
+            if view < self.core.view {
+                debug!("节点{}忽略来自节点{}的过期ViewChange消息，视图{}", self.id, node_id, view);
+                return;
+            }
+
+            info!("节点{}收到来自节点{}的ViewChange消息，视图{}", self.id, node_id, view);
+            self.record_view_change(msg);
+
+            // 视图同步：f+1个节点请求更高视图时，至少有一个诚实节点已超时，
+            // 加入其中最小的视图，使各节点收敛到同一目标视图
+            let requests = self.higher_view_requests();
+            if requests.len() >= WEAK_QUORUM {
+                let target_view = *requests.values().min().unwrap();
+                info!("节点{}收到{}个节点的更高视图请求，加入视图{}", self.id, requests.len(), target_view);
+                self.start_view_change(target_view, "视图同步").await;
+            }
+
+            let senders: HashSet<usize> = self.state.lock().unwrap().view_change_messages.iter().filter_map(|m| {
+                match m {
+                    PBFTMessage::ViewChange { view: v, node_id, .. } if *v == self.core.view => Some(*node_id),
+                    _ => None,
+                }
+            }).collect();
+
+            if senders.len() >= VIEW_CHANGE_QUORUM && self.is_primary() && self.view_change_in_progress {
+                // 作为新主节点，发送NewView消息
+                self.send_new_view().await;
+            }
+        }
+    }
+
+    async fn send_new_view(&mut self) {
+        let view_change_messages: Vec<PBFTMessage> = self.signed_view_changes.iter()
+            .filter(|((view, _), _)| *view == self.core.view)
+            .map(|(_, signed)| signed.clone())
+            .collect();
+        let view_changes: Vec<PBFTMessage> = view_change_messages.iter().filter_map(|m| match m {
+            PBFTMessage::SignedMessage { message, .. } => Some((**message).clone()),
+            _ => None,
+        }).collect();
+        let pre_prepares = compute_o_set(self.genesis.hasher(), self.core.view, &view_changes, |entry| self.prepare_certificate_valid(entry));
         let new_view_msg = PBFTMessage::NewView {
-            view: self.view,
+            view: self.core.view,
             view_change_messages,
+            pre_prepares: pre_prepares.clone(),
+        };
+
+        info!("新主节点{}发送NewView消息，视图{}", self.id, self.core.view);
+        self.broadcast(&new_view_msg).await;
+
+        // 取消新视图定时器
+        self.new_view_deadline = None;
+        self.view_change_timeout = self.timeout_duration;
+
+        self.view_change_in_progress = false;
+        self.o_set = pre_prepares;
+        self.propose_o_set().await;
+        self.repropose_pending().await;
+    }
+
+    // 新主节点先按原样重新提议O集合中已准备而未提交的请求，再处理待处理的请求
+    async fn propose_o_set(&mut self) {
+        let (digest, transactions) = match self.o_set.first() {
+            Some(PBFTMessage::PrePrepare { digest, transactions, .. }) => (digest.clone(), transactions.clone()),
+            _ => return,
+        };
+        info!("新主节点{}在视图{}重新提议已准备的请求（{}笔交易）", self.id, self.core.view, transactions.len());
+        metrics::inc_counter("o_set_reproposed_total", 1);
+        self.pending_requests.retain(|request| !request.to_transaction().is_some_and(|tx| transactions.contains(&tx)));
+        let actions = self.core.handle(Input::Propose { digest, transactions });
+        self.apply(actions).await;
+    }
+
+    // PreparedEntry的证明：摘要与交易一致，且有2f个不同副本对它的有效Prepare签名
+    fn prepare_certificate_valid(&self, entry: &PreparedEntry) -> bool {
+        if chain::digest_transactions(self.genesis.hasher(), &entry.transactions) != entry.digest {
+            return false;
+        }
+        let signers: HashSet<usize> = entry.prepares.iter().filter(|(signer, signature)| {
+            let prepare = PBFTMessage::Prepare { view: entry.view, sequence_number: entry.sequence_number, digest: entry.digest.clone(), sender_id: *signer };
+            self.verify_signature(&prepare, signature, *signer)
+        }).map(|(signer, _)| *signer).collect();
+        signers.len() >= PREPARE_QUORUM
+    }
+
+    // 验证NewView：2f+1个不同节点的有效签名ViewChange，且O集合可由其重新计算得到
+    fn validate_new_view(&self, view: u64, view_change_messages: &[PBFTMessage], pre_prepares: &[PBFTMessage]) -> Result<(), String> {
+        let mut senders = HashSet::new();
+        let mut view_changes = Vec::new();
+        for m in view_change_messages {
+            let (message, signature, sender_id) = match m {
+                PBFTMessage::SignedMessage { message, signature, sender_id, .. } => (message, signature, *sender_id),
+                _ => return Err("包含未签名的ViewChange消息".to_string()),
+            };
+            match &**message {
+                PBFTMessage::ViewChange { view: v, node_id, .. } if *v == view && *node_id == sender_id => {}
+                _ => return Err(format!("节点{}的ViewChange消息与视图{}不匹配", sender_id, view)),
+            }
+            if !self.verify_signature(message, signature, sender_id) {
+                return Err(format!("节点{}的ViewChange签名无效", sender_id));
+            }
+            if senders.insert(sender_id) {
+                view_changes.push((**message).clone());
+            }
+        }
+
+        if senders.len() < VIEW_CHANGE_QUORUM {
+            return Err(format!("只包含{}个有效ViewChange，需要{}个", senders.len(), VIEW_CHANGE_QUORUM));
+        }
+
+        let expected = compute_o_set(self.genesis.hasher(), view, &view_changes, |entry| self.prepare_certificate_valid(entry));
+        let expected_json = serde_json::to_string(&expected).unwrap();
+        if serde_json::to_string(pre_prepares).unwrap() != expected_json {
+            return Err("O集合与ViewChange消息不一致".to_string());
+        }
+        Ok(())
+    }
+
+    async fn handle_new_view(&mut self, msg: PBFTMessage) {
+        if let PBFTMessage::NewView { view, view_change_messages, pre_prepares } = msg {
+            // 已进入的视图再收到NewView（重复或重放）时不重置当前实例
+            if view > self.core.view || (view == self.core.view && self.view_change_in_progress) {
+                if let Err(reason) = self.validate_new_view(view, &view_change_messages, &pre_prepares) {
+                    let primary = self.leader(view);
+                    error!("节点{}拒绝视图{}的NewView消息: {}，主节点{}存在恶意行为", self.id, view, reason, primary);
+                    metrics::inc_counter("new_view_rejected_total", 1);
+                    self.penalize(primary, reputation::Event::ProtocolViolation).await;
+                    self.start_view_change(view + 1, "NewView无效").await;
+                    return;
+                }
+
+                info!("节点{}收到NewView消息，切换到视图{}", self.id, view);
+                self.enter_view(view);
+                self.view_change_in_progress = false;
+                self.state.lock().unwrap().view_change_messages.clear();
+                self.signed_view_changes.retain(|(v, _), _| *v > view);
+
+                // 取消新视图定时器
+                self.new_view_deadline = None;
+                self.view_change_timeout = self.timeout_duration;
+
+                // 新主节点随后按O集合重新提议已准备的请求，本视图对应序列号只接受与之一致的PrePrepare
+                self.o_set = pre_prepares;
+
+                self.repropose_pending().await;
+                self.hand_off_if_leaving().await;
+            }
+        }
+    }
+
+    // 如果自己是新主节点，且有未处理的请求，重新发起请求
+    async fn repropose_pending(&mut self) {
+        if self.is_primary() && !self.pending_requests.is_empty() {
+            let pending_requests = std::mem::take(&mut self.pending_requests);
+            for request in pending_requests {
+                self.handle_request(request).await;
+            }
+        }
+    }
+
+    // 按地址簿中最近握手成功的先后发起握手，重启后先连上次在线的节点
+    async fn start_handshakes(&mut self) {
+        for peer in self.address_book.reconnect_order((0..N).filter(|peer| *peer != self.id)) {
+            self.challenge(peer, Duration::ZERO).await;
+        }
+    }
+
+    // 向对等节点发送握手挑战。未应答的挑战沿用原来的随机数重发，在途的应答仍然有效；
+    // 距上次发送不足retry_after时不重发
+    async fn challenge(&mut self, peer: usize, retry_after: Duration) {
+        let now = self.clock.now();
+        let nonce = match self.pending_challenges.get(&peer) {
+            Some((_, sent)) if now.duration_since(*sent) < retry_after => return,
+            Some((nonce, _)) => nonce.clone(),
+            None => {
+                let mut nonce = vec![0u8; 32];
+                OsRng.fill_bytes(&mut nonce);
+                nonce
+            }
         };
+        self.pending_challenges.insert(peer, (nonce.clone(), now));
+
+        let challenge = PBFTMessage::HandshakeChallenge {
+            node_id: self.id,
+            nonce,
+        };
+        debug!("节点{}向节点{}发送握手挑战", self.id, peer);
+        send_message(self.genesis.network_magic(), self.id, peer, challenge).await;
+    }
+
+    // 缓存握手完成前收到的签名消息，并确保已向发送者发起握手。只缓存验证者的消息，
+    // 每个发送者最多HANDSHAKE_BUFFER_SIZE条，超过HANDSHAKE_BUFFER_MS仍未认证的丢弃
+    async fn buffer_unauthenticated(&mut self, sender_id: usize, msg: PBFTMessage) {
+        if sender_id >= N {
+            error!("节点{}尚未完成与节点{}的握手认证，丢弃消息", self.id, sender_id);
+            return;
+        }
+        self.expire_unauthenticated();
+        let buffer = self.unauthenticated.entry(sender_id).or_default();
+        if buffer.len() >= HANDSHAKE_BUFFER_SIZE {
+            debug!("节点{}缓存的节点{}未认证消息已满，丢弃", self.id, sender_id);
+            metrics::inc_counter("handshake_buffer_dropped_total", 1);
+            return;
+        }
+        buffer.push_back((self.clock.now(), msg));
+        metrics::inc_counter("handshake_buffered_total", 1);
+        self.challenge(sender_id, Duration::from_millis(HANDSHAKE_RETRY_MS)).await;
+    }
+
+    fn expire_unauthenticated(&mut self) {
+        let window = Duration::from_millis(HANDSHAKE_BUFFER_MS);
+        let now = self.clock.now();
+        let mut expired = 0;
+        self.unauthenticated.retain(|_, buffer| {
+            while buffer.front().is_some_and(|(received, _)| now.duration_since(*received) >= window) {
+                buffer.pop_front();
+                expired += 1;
+            }
+            !buffer.is_empty()
+        });
+        if expired > 0 {
+            error!("节点{}丢弃{}条超时仍未完成握手认证的消息", self.id, expired);
+            metrics::inc_counter("handshake_buffer_expired_total", expired);
+        }
+    }
+
+    async fn handle_handshake_challenge(&mut self, challenger_id: usize, nonce: Vec<u8>) {
+        // 握手是双向的：对方尚未认证时同时向它发起挑战，后上线的节点由此得到先上线节点的公钥
+        if challenger_id < N && !self.authenticated_peers.contains(&challenger_id) {
+            self.challenge(challenger_id, Duration::ZERO).await;
+        }
+
+        // 用私钥对挑战签名，证明自己持有该节点ID对应的密钥
+        let payload = self.handshake_payload(challenger_id, self.id, &nonce);
+        let signature = self.signing_key.sign(&payload);
+
+        let response = PBFTMessage::HandshakeResponse {
+            node_id: self.id,
+            public_key: self.signing_key.public_key(),
+            signature,
+            parameters: Some(Box::new(self.genesis.consensus_parameters())),
+            protocol_version: Some(PROTOCOL_VERSION),
+        };
+        debug!("节点{}应答节点{}的握手挑战", self.id, challenger_id);
+        send_message(self.genesis.network_magic(), self.id, challenger_id, response).await;
+    }
+
+    fn handle_handshake_response(&mut self, node_id: usize, pubkey: PublicKey, signature: Signature, parameters: Option<Box<ConsensusParameters>>, protocol_version: Option<u32>) {
+        let nonce = match self.pending_challenges.get(&node_id) {
+            Some((nonce, _)) => nonce.clone(),
+            None => {
+                error!("节点{}收到节点{}未经请求的握手应答，忽略", self.id, node_id);
+                return;
+            }
+        };
+
+        // 已知公钥的节点必须使用同一把密钥完成握手
+        if let Some(known) = self.public_keys.get(&node_id) {
+            if *known != pubkey {
+                error!("节点{}拒绝节点{}的握手：公钥与已知公钥不一致", self.id, node_id);
+                return;
+            }
+        }
+
+        // 目录中登记的公钥与握手公钥不一致时告警。节点每次启动都会生成新密钥，这里不拒绝握手
+        let entry = directory::lookup(self.execution.lock().unwrap().state(), node_id);
+        if let Some(entry) = &entry {
+            if entry.public_key != pubkey.to_hex() {
+                error!("节点{}发现节点{}的握手公钥与节点目录中的登记不一致", self.id, node_id);
+                metrics::inc_counter("directory_key_mismatch_total", 1);
+            }
+        }
+
+        let payload = self.handshake_payload(self.id, node_id, &nonce);
+        if pubkey.verify(&payload, &signature) {
+            // 共识参数不一致的节点即使互联也无法形成法定人数，拒绝认证并指出不同的参数；
+            // 挑战保留，对方修正配置重启后的下一次握手即可成功
+            let differences = parameters.as_ref()
+                .map(|parameters| self.genesis.consensus_parameters().differences(parameters))
+                .unwrap_or_default();
+            if !differences.is_empty() {
+                let hash = parameters.unwrap().hash();
+                if self.misconfigured_peers.insert(node_id, hash.clone()).as_ref() != Some(&hash) {
+                    error!("节点{}拒绝与节点{}互联：共识参数不一致（本节点 ≠ 节点{}），请比对两个节点的genesis.json和config.rs：{}",
+                        self.id, node_id, node_id, differences.join("；"));
+                    metrics::inc_counter("handshake_config_mismatch_total", 1);
+                } else {
+                    debug!("节点{}与节点{}的共识参数仍不一致", self.id, node_id);
+                }
+                return;
+            }
+            if self.misconfigured_peers.remove(&node_id).is_some() {
+                info!("节点{}与节点{}的共识参数已一致", self.id, node_id);
+            }
+            self.pending_challenges.remove(&node_id);
+            self.learn_public_key(node_id, pubkey);
+            self.authenticated_peers.insert(node_id);
+            info!("节点{}完成与节点{}的握手认证", self.id, node_id);
+            let addresses = entry.map(|entry| entry.addresses).unwrap_or_else(|| network::default_addresses(node_id));
+            self.address_book.dialed(node_id, addresses, self.clock.wall_time().timestamp_millis(), protocol_version);
+            // 立即测一次时钟偏差，不必等到下一个Ping周期
+            self.next_ping = self.clock.now();
+            if let Some(buffer) = self.unauthenticated.remove(&node_id) {
+                debug!("节点{}重新处理节点{}在握手完成前发来的{}条消息", self.id, node_id, buffer.len());
+                self.released.extend(buffer.into_iter().map(|(_, msg)| msg));
+            }
+        } else {
+            error!("节点{}验证节点{}的握手签名失败", self.id, node_id);
+        }
+    }
 
-        info!("新主节点{}发送NewView消息，视图{}", self.id, self.view);
-        self.broadcast(&new_view_msg).await;
+    // 计划退出：广播退出通知，不再接受新请求；是主节点时在进行中的实例提交后发起视图切换交接主节点，
+    // 等本节点的投票不再被进行中的法定人数需要后关闭，最多等待EXIT_DRAIN_TIMEOUT_MS
+    async fn begin_exit(&mut self) {
+        info!("节点{}开始计划退出", self.id);
+        metrics::inc_counter("planned_exits_total", 1);
+        self.exit_deadline = Some(self.clock.now() + Duration::from_millis(EXIT_DRAIN_TIMEOUT_MS));
+        self.batch_queue.clear();
+        self.batch_started = None;
+        self.broadcast(&PBFTMessage::Leave { node_id: self.id, view: self.core.view }).await;
+        self.hand_off_if_leaving().await;
+    }
 
-        // 取消新视图定时器
-        if let Some(handle) = &self.new_view_timer {
-            handle.abort();
-            self.new_view_timer = None;
+    // 本节点不再担任主节点、没有未提交的实例，或已超过最晚关闭时间
+    fn exit_completed(&self) -> bool {
+        self.exit_deadline.is_some_and(|deadline| {
+            self.clock.now() >= deadline
+                || (!matches!(self.core.phase, Phase::PrePrepared | Phase::Prepared) && !self.view_change_in_progress && !self.is_primary())
+        })
+    }
+
+    // 对等节点计划退出：之后不再安排它担任主节点，它是当前主节点时交接
+    async fn handle_leave(&mut self, node_id: usize, view: u64) {
+        info!("节点{}收到节点{}在视图{}的退出通知", self.id, node_id, view);
+        self.departed.insert(node_id);
+        self.performance.mark_departed(node_id);
+        self.hand_off_if_leaving().await;
+    }
+
+    fn is_leaving(&self, node_id: usize) -> bool {
+        if node_id == self.id {
+            self.exit_deadline.is_some() || self.in_maintenance
+        } else {
+            self.departed.contains(&node_id) || self.maintenance_peers.contains(&node_id)
         }
+    }
 
-        self.view_change_in_progress = false;
+    // 管理员开关维护模式：广播通知，进入时是主节点则先交接。维护期间RPC查询、状态证明和快照照常提供，
+    // 恢复后从后续实例发现序列号缺口，补齐维护期间提交的区块
+    async fn set_maintenance(&mut self, enabled: bool) {
+        if enabled == self.in_maintenance || self.role != Role::Validator {
+            return;
+        }
+        info!("节点{}{}维护模式", self.id, if enabled { "进入" } else { "退出" });
+        self.in_maintenance = enabled;
+        self.broadcast(&PBFTMessage::Maintenance { node_id: self.id, enabled }).await;
+        if enabled {
+            metrics::inc_counter("maintenance_entered_total", 1);
+            self.batch_queue.clear();
+            self.batch_started = None;
+            self.hand_off_if_leaving().await;
+        }
     }
 
-    async fn handle_new_view(&mut self, msg: PBFTMessage) {
-        if let PBFTMessage::NewView { view, .. } = msg {
-            if view >= self.view {
-                info!("节点{}收到NewView消息，切换到视图{}", self.id, view);
-                self.view = view;
-                self.view_change_in_progress = false;
-                self.sequence_number = 0;
-                self.digest.clear();
-                self.state.lock().unwrap().view_change_messages.clear();
+    async fn handle_peer_maintenance(&mut self, node_id: usize, enabled: bool) {
+        info!("节点{}：节点{}{}维护模式", self.id, node_id, if enabled { "进入" } else { "退出" });
+        if enabled {
+            self.maintenance_peers.insert(node_id);
+            self.performance.mark_departed(node_id);
+            self.hand_off_if_leaving().await;
+        } else if self.maintenance_peers.remove(&node_id) && !self.departed.contains(&node_id) {
+            self.performance.clear_departed(node_id);
+        }
+    }
 
-                // 取消新视图定时器
-                if let Some(handle) = &self.new_view_timer {
-                    handle.abort();
-                    self.new_view_timer = None;
+    // 当前主节点计划退出且没有进行中的实例时，所有节点立即切换到主节点不会退出的下一个视图，不必等请求超时
+    async fn hand_off_if_leaving(&mut self) {
+        let primary = self.leader(self.core.view);
+        if self.is_leaving(primary) && !self.view_change_in_progress && !matches!(self.core.phase, Phase::PrePrepared | Phase::Prepared) {
+            let target = (self.core.view + 1..=self.core.view + N as u64)
+                .find(|view| !self.is_leaving(self.leader(*view)))
+                .unwrap_or(self.core.view + 1);
+            info!("节点{}：主节点{}计划退出，切换到视图{}", self.id, primary, target);
+            metrics::inc_counter("planned_handoffs_total", 1);
+            self.start_view_change(target, "主节点计划退出").await;
+        }
+    }
+
+    // 关闭前保存已接受但尚未提交的请求，重启后重新提交
+    fn save_mempool(&self) {
+        match mempool::save(self.id, &self.pending_requests) {
+            Ok(()) => info!("节点{}关闭，保存了{}个尚未提交的请求", self.id, self.pending_requests.len()),
+            Err(reason) => error!("节点{}保存内存池失败: {}", self.id, reason),
+        }
+    }
+
+    async fn restore_mempool(&mut self) {
+        match mempool::take(self.id) {
+            Ok(requests) => {
+                if !requests.is_empty() {
+                    info!("节点{}重新提交上次关闭时保存的{}个请求", self.id, requests.len());
+                    metrics::inc_counter("mempool_restored_total", requests.len() as u64);
                 }
+                // 重新经过过期、答复缓存和准入检查
+                for request in requests {
+                    self.handle_request(request).await;
+                }
+            }
+            Err(reason) => error!("节点{}丢弃内存池快照: {}", self.id, reason),
+        }
+    }
+
+    // 已提交区块中的交易不再是待处理请求；同一交易提交一次只删除一个待处理请求
+    fn forget_committed(&mut self, block: &Block) {
+        for tx in &block.transactions {
+            if let Some(position) = self.pending_requests.iter().position(|request| request.to_transaction().as_ref() == Some(tx)) {
+                self.pending_requests.remove(position);
+            }
+        }
+    }
+
+    // 向已认证的验证者发送Ping，Pong中带回对方的墙上时间
+    async fn send_pings(&mut self) {
+        self.next_ping = self.clock.now() + Duration::from_millis(CLOCK_PING_INTERVAL_MS);
+        if self.role == Role::Validator {
+            self.alerts.check_health(self.clock.now());
+        }
+        let sent_at = self.clock.wall_time().timestamp_millis();
+        let mut peers: Vec<usize> = self.authenticated_peers.iter().copied().filter(|peer| *peer < N).collect();
+        peers.sort_unstable();
+        for peer in peers {
+            self.send_to(peer, PBFTMessage::Ping { node_id: self.id, sent_at }).await;
+        }
+        self.save_address_book();
+    }
+
+    fn save_address_book(&mut self) {
+        if let Err(e) = self.address_book.save(std::path::Path::new("."), self.id) {
+            error!("节点{}写入地址簿失败: {}", self.id, e);
+        }
+    }
+
+    fn handle_pong(&mut self, node_id: usize, ping_sent_at: i64, peer_time: i64) {
+        let received_at = self.clock.wall_time().timestamp_millis();
+        self.alerts.peer_alive(node_id, self.clock.now());
+        let mut clock_sync = self.clock_sync.lock().unwrap();
+        clock_sync.record(node_id, ping_sent_at, peer_time, received_at);
+        if let Some(offset) = clock_sync.dangerous_skew() {
+            let direction = if offset < 0 { "快" } else { "慢" };
+            warn!("节点{}的时钟比多数节点{}{}ms，请检查时间同步；判断请求是否过期时按多数节点的时间修正", self.id, direction, offset.abs());
+            metrics::inc_counter("clock_skew_warnings_total", 1);
+        }
+    }
+
+    // 按多数节点的时钟修正后的本地墙上时间（Unix毫秒），用于判断客户端给出的过期时间。
+    // 本地时钟偏快时，不修正会把刚发出的请求当作已经过期
+    fn network_time_ms(&self) -> i64 {
+        self.clock.wall_time().timestamp_millis() + self.clock_sync.lock().unwrap().cluster_offset()
+    }
+
+    fn handshake_payload(&self, challenger_id: usize, responder_id: usize, nonce: &[u8]) -> Vec<u8> {
+        let mut data = (challenger_id as u64).to_be_bytes().to_vec();
+        data.extend_from_slice(&(responder_id as u64).to_be_bytes());
+        data.extend_from_slice(nonce);
+        self.genesis.signing_payload(domain::HANDSHAKE, &data)
+    }
+
+    // 向链上节点目录登记本节点的地址、公钥和角色；地址变化时以更大的序号重新公告
+    async fn register_in_directory(&mut self) {
+        // 可连通的地址排在前面，拨号方按顺序尝试
+        let mut addresses = Vec::new();
+        let mut unreachable = Vec::new();
+        let candidates = if self.advertised_addresses.is_empty() {
+            network::listen_addresses(self.id)
+        } else {
+            self.advertised_addresses.clone()
+        };
+        for address in candidates {
+            if network::dial(std::slice::from_ref(&address)).await.is_some() {
+                addresses.push(address);
+            } else {
+                unreachable.push(address);
+            }
+        }
+        addresses.extend(unreachable);
+
+        let existing = directory::lookup(self.execution.lock().unwrap().state(), self.id);
+        let mut entry = DirectoryEntry {
+            node_id: self.id,
+            addresses,
+            public_key: self.signing_key.public_key().to_hex(),
+            role: self.role,
+            sequence: existing.as_ref().map(|e| e.sequence).unwrap_or(0),
+        };
+        if existing.as_ref() == Some(&entry) {
+            debug!("节点{}的目录条目已是最新", self.id);
+            return;
+        }
+        if existing.is_some() {
+            entry.sequence += 1;
+        }
+
+        info!("节点{}向节点目录登记自身信息", self.id);
+        self.submit_own_request(SignedEntry::sign(entry, &self.signing_key).registration_operation()).await;
+    }
+
+    // 应用热加载的配置：trace导出地址从下一个共识实例起生效，延迟预算对尚未执行完的实例立即生效，公告地址变化时重新登记节点目录
+    async fn apply_config(&mut self, config: NodeConfig) {
+        self.otlp_endpoint = config.otlp_endpoint.or_else(|| std::env::var(OTLP_ENDPOINT_ENV).ok());
+        self.alerts.configure(config.alerts);
+        self.latency_budget.set_budget(config.latency_budget_ms);
+        if config.advertised_addresses != self.advertised_addresses {
+            info!("节点{}的公告地址改为{:?}", self.id, config.advertised_addresses);
+            self.advertised_addresses = config.advertised_addresses;
+            if self.peer_directory {
+                self.register_in_directory().await;
+            }
+        }
+        if config.zones != self.zones {
+            info!("节点{}的故障域改为{:?}", self.id, config.zones.groups);
+            self.set_zones(config.zones);
+        }
+    }
+
+    // 故障域变化时重新计算广播顺序
+    pub fn set_zones(&mut self, zones: Zones) {
+        self.broadcast_order = zones.broadcast_order(self.id);
+        self.zones = zones;
+    }
+
+    // 以本节点的私钥签名启动参数中的治理提案和投票，作为普通请求提交
+    async fn submit_governance(&mut self) {
+        for action in std::mem::take(&mut self.governance_actions) {
+            info!("节点{}提交治理操作: {:?}", self.id, action);
+            self.submit_own_request(governance::SignedAction::sign(self.id, action, &self.signing_key).operation()).await;
+        }
+    }
+
+    // 本节点发起的请求：主节点直接处理，其他节点转交主节点
+    async fn submit_own_request(&mut self, operation: String) {
+        let request = PBFTMessage::Request {
+            operation,
+            priority: Priority::Normal,
+            client_id: None,
+            expires_at: None,
+            session: None,
+            timestamp: None,
+        };
+        if self.role == Role::Validator && self.is_primary() {
+            self.handle_request(request).await;
+        } else {
+            let primary = self.leader(self.core.view);
+            send_message(self.genesis.network_magic(), self.id, primary, request).await;
+        }
+    }
+
+    async fn request_snapshots(&self) {
+        let magic = self.genesis.network_magic();
+        for i in 0..N {
+            if i != self.id {
+                debug!("节点{}向节点{}请求快照清单", self.id, i);
+                send_message(magic, self.id, i, PBFTMessage::SnapshotRequest { node_id: self.id }).await;
+            }
+        }
+    }
+
+    async fn handle_snapshot_request(&mut self, node_id: usize) {
+        // 分叉的状态不能提供给其他节点
+        if self.is_repairing() {
+            return;
+        }
+        let manifest = self.current_snapshot();
+        info!("节点{}向节点{}提供高度{}的快照，共{}个分块", self.id, node_id, manifest.height, manifest.chunk_hashes.len());
+        let offer = self.sign_message(PBFTMessage::SnapshotOffer { node_id: self.id, manifest });
+        send_message(self.genesis.network_magic(), self.id, node_id, offer).await;
+    }
+
+    // 生成执行高度的快照并缓存，同一高度的请求复用同一份快照。
+    // 状态和区块头取自同一高度：已提交而尚未执行的区块不计入快照
+    fn current_snapshot(&mut self) -> SnapshotManifest {
+        let view = self.execution.lock().unwrap().view(None).expect("当前执行高度的视图总是存在");
+        let height = view.height();
+        if let Some((manifest, _)) = self.snapshot_cache.get(&height) {
+            return manifest.clone();
+        }
+
+        let snapshot = StateSnapshot {
+            header: self.chain.lock().unwrap().header(height).cloned(),
+            store: view.store().clone(),
+        };
+        let data = snapshot.encode();
+        let manifest = SnapshotManifest::build(self.genesis.hasher(), height, &data);
+        self.snapshot_cache.insert(height, (manifest.clone(), data));
+        while self.snapshot_cache.len() > SNAPSHOT_CACHE_SIZE {
+            let oldest = *self.snapshot_cache.keys().next().unwrap();
+            self.snapshot_cache.remove(&oldest);
+        }
+        manifest
+    }
+
+    async fn handle_chunk_request(&self, node_id: usize, height: u64, index: usize) {
+        let data = match self.snapshot_cache.get(&height).and_then(|(manifest, data)| manifest.chunk(data, index)) {
+            Some(chunk) => chunk.to_vec(),
+            None => {
+                debug!("节点{}没有高度{}的快照分块{}，忽略节点{}的请求", self.id, height, index, node_id);
+                return;
+            }
+        };
+        send_message(self.genesis.network_magic(), self.id, node_id, PBFTMessage::ChunkResponse { height, index, data }).await;
+    }
+
+    async fn handle_snapshot_offer(&mut self, peer: usize, manifest: SnapshotManifest) {
+        let sync = match &mut self.state_sync {
+            Some(sync) => sync,
+            None => return,
+        };
+        if sync.offer(peer, manifest) {
+            let manifest = sync.manifest.as_ref().unwrap();
+            info!("节点{}采用高度{}的快照清单，共{}个分块，已下载{}个",
+                self.id, manifest.height, manifest.chunk_hashes.len(), sync.chunks.len());
+            sync.save(self.id);
+            self.drive_state_sync().await;
+        }
+    }
+
+    async fn handle_chunk_response(&mut self, height: u64, index: usize, data: Vec<u8>) {
+        let sync = match &mut self.state_sync {
+            Some(sync) => sync,
+            None => return,
+        };
+        match sync.accept_chunk(self.genesis.hasher(), height, index, data) {
+            Ok(()) => {
+                debug!("节点{}校验并保存快照分块{}", self.id, index);
+                sync.save(self.id);
+            }
+            Err(reason) => {
+                error!("节点{}丢弃快照分块{}: {}", self.id, index, reason);
+                metrics::inc_counter("state_sync_chunk_rejected_total", 1);
+            }
+        }
+        self.drive_state_sync().await;
+    }
+
+    // 分块收齐后完成同步，否则向各提供方补足在途的分块请求
+    async fn drive_state_sync(&mut self) {
+        let sync = match &mut self.state_sync {
+            Some(sync) => sync,
+            None => return,
+        };
+        if sync.is_complete() {
+            self.finish_state_sync().await;
+            return;
+        }
+
+        let height = match &sync.manifest {
+            Some(manifest) => manifest.height,
+            None => return,
+        };
+        let magic = self.genesis.network_magic();
+        for (peer, index) in sync.next_requests() {
+            debug!("节点{}向节点{}请求快照分块{}", self.id, peer, index);
+            send_message(magic, self.id, peer, PBFTMessage::ChunkRequest { node_id: self.id, height, index }).await;
+        }
+    }
 
-                // 处理从ViewChange消息中恢复的状态（简化处理）
+    async fn finish_state_sync(&mut self) {
+        let sync = self.state_sync.take().unwrap();
+        StateSync::remove(self.id);
+        let snapshot = match sync.assemble(self.genesis.hasher()) {
+            Ok(snapshot) => snapshot,
+            Err(reason) => {
+                // 各分块均已校验，整体校验失败说明清单本身有问题，重新开始
+                error!("节点{}状态同步失败: {}，重新请求快照", self.id, reason);
+                self.state_sync = Some(StateSync::default());
+                return;
+            }
+        };
+
+        if sync.repair {
+            self.finish_repair(snapshot);
+            return;
+        }
 
-                // 如果自己是新主节点，且有未处理的请求，可以重新发起请求
-                if self.is_primary() && !self.pending_requests.is_empty() {
-                    let pending_requests = self.pending_requests.clone();
-                    for request in pending_requests {
-                        self.handle_request(request).await;
+        let local_height = self.chain.lock().unwrap().height();
+        match snapshot.header.clone() {
+            Some(header) if header.height > local_height => {
+                let height = header.height;
+                self.execution.lock().unwrap().restore(height, snapshot.store.clone());
+                {
+                    let mut chain = self.chain.lock().unwrap();
+                    chain.reset_to(header);
+                    self.storage.persist();
+                    if let Some(index) = &self.archive_index {
+                        *index.lock().unwrap() = ArchiveIndex::build(&chain);
                     }
                 }
+                snapshot.save(self.id);
+                metrics::inc_counter("state_sync_completed_total", 1);
+                info!("节点{}完成状态同步，从高度{}继续", self.id, height);
+            }
+            _ => {
+                info!("节点{}本地高度{}不低于快照高度，忽略快照", self.id, local_height);
+            }
+        }
+
+        if self.role != Role::Validator {
+            self.subscribe_blocks().await;
+        }
+    }
+
+    // 用快照替换执行状态，再重放本地链上快照之后的区块
+    fn finish_repair(&mut self, snapshot: StateSnapshot) {
+        let snapshot_height = snapshot.header.as_ref().map(|header| header.height).unwrap_or(0);
+        let mut chain = self.chain.lock().unwrap();
+        let base_height = chain.base.as_ref().map(|header| header.height).unwrap_or(0);
+        if snapshot_height < base_height {
+            // 本地没有快照与基点之间的区块，无法重放
+            error!("节点{}的快照高度{}低于本地链的起点{}，重新请求快照", self.id, snapshot_height, base_height);
+            self.state_sync = Some(StateSync::for_repair());
+            return;
+        }
+        if let Some(header) = snapshot.header.clone().filter(|header| header.height > chain.height()) {
+            chain.reset_to(header);
+            self.storage.persist();
+            if let Some(index) = &self.archive_index {
+                *index.lock().unwrap() = ArchiveIndex::build(&chain);
             }
+            snapshot.save(self.id);
+        }
+
+        let mut execution = self.execution.lock().unwrap();
+        execution.restore(snapshot_height, snapshot.store);
+        let replayed: Vec<&Block> = chain.blocks.iter().filter(|block| block.header.height > snapshot_height).collect();
+        for block in &replayed {
+            execution.execute_block(block.header.height, &block.transactions);
         }
+        metrics::inc_counter("state_repairs_completed_total", 1);
+        info!("节点{}从高度{}的快照恢复执行状态，重放{}个区块", self.id, snapshot_height, replayed.len());
     }
 
     async fn broadcast(&self, msg: &PBFTMessage) {
         // 更新消息的视图编号
         let msg_with_view = match msg {
-            PBFTMessage::PrePrepare { sequence_number, digest, .. } => {
+            PBFTMessage::PrePrepare { sequence_number, digest, transactions, .. } => {
                 PBFTMessage::PrePrepare {
-                    view: self.view,
+                    view: self.core.view,
                     sequence_number: *sequence_number,
                     digest: digest.clone(),
+                    transactions: transactions.clone(),
                 }
             }
             PBFTMessage::Prepare { sequence_number, digest, sender_id, .. } => {
                 PBFTMessage::Prepare {
-                    view: self.view,
+                    view: self.core.view,
                     sequence_number: *sequence_number,
                     digest: digest.clone(),
                     sender_id: *sender_id,
@@ -498,7 +3114,7 @@ impl Node {
             }
             PBFTMessage::Commit { sequence_number, digest, .. } => {
                 PBFTMessage::Commit {
-                    view: self.view,
+                    view: self.core.view,
                     sequence_number: *sequence_number,
                     digest: digest.clone(),
                 }
@@ -507,32 +3123,750 @@ impl Node {
         };
 
         // 对消息进行签名
-        let message_bytes = serde_json::to_vec(&msg_with_view).unwrap();
-        let signature = self.keypair.sign(&message_bytes);
+        let relay = self.preprepare_relay(&msg_with_view);
+        let signed_msg = self.sign_message(msg_with_view);
+        let replica_msg = self.replica_form(&signed_msg);
+
+        let magic = self.genesis.network_magic();
+        for &i in &self.broadcast_order {
+            if self.core.strategy.drops_message() {
+                debug!("节点{}按故障策略丢弃发往节点{}的消息", self.id, i);
+                continue;
+            }
+            debug!("节点{}向节点{}发送签名消息", self.id, i);
+            if let Some(delay) = self.outgoing_delay() {
+                self.send_delayed(i, replica_msg.clone(), delay);
+            } else if self.coalesce_messages {
+                if let Some(relay) = relay.as_ref().filter(|_| i != self.core.primary) {
+                    network::queue_message(self.id, i, relay.clone());
+                    metrics::inc_counter("preprepare_relayed_total", 1);
+                }
+                network::queue_message(self.id, i, replica_msg.clone());
+            } else {
+                send_message(magic, self.id, i, replica_msg.clone()).await;
+            }
+        }
 
-        let signed_msg = PBFTMessage::SignedMessage {
-            message: Box::new(msg_with_view),
-            signature: signature.to_bytes().to_vec(),
-            sender_id: self.id,
+        // 同时转发给订阅了共识消息的观察者
+        for observer in &self.consensus_observers {
+            send_message(magic, self.id, *observer, signed_msg.clone()).await;
+        }
+    }
+
+    // 只发给单个节点的签名消息，例如作恶主节点发给部分副本的PrePrepare
+    async fn send_to(&self, to: usize, msg: PBFTMessage) {
+        if self.core.strategy.drops_message() {
+            return;
+        }
+        let signed_msg = self.replica_form(&self.sign_message(msg));
+        if let Some(delay) = self.outgoing_delay() {
+            self.send_delayed(to, signed_msg, delay);
+        } else if self.coalesce_messages {
+            network::queue_message(self.id, to, signed_msg);
+        } else {
+            send_message(self.genesis.network_magic(), self.id, to, signed_msg).await;
+        }
+    }
+
+    // 出站消息的延迟：注入的网络延迟，加上延迟攻击按本地时钟换算出的等待时间
+    fn outgoing_delay(&self) -> Option<Duration> {
+        let attack = self.core.strategy.message_delay(self.timeout_duration);
+        if attack.is_some() {
+            metrics::inc_counter("byzantine_delayed_messages_total", 1);
+        }
+        let delay = self.send_latency + attack.map(|d| self.clock.real_duration(d)).unwrap_or_default();
+        (!delay.is_zero()).then_some(delay)
+    }
+
+    // 消息在后台等待一段时间再发出，不阻塞本节点的事件循环
+    fn send_delayed(&self, to: usize, signed_msg: PBFTMessage, delay: Duration) {
+        let (magic, from) = (self.genesis.network_magic(), self.id);
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            send_message(magic, from, to, signed_msg).await;
+        });
+    }
+
+    // 合并发送时，副本的Prepare搭载主节点签名的PrePrepare一起发出：PrePrepare排在捆绑的前面，
+    // 没收到主节点PrePrepare的副本也能先处理它再计Prepare，主节点分叉时其他副本也能据此得到证据。
+    // 摘要模式下不转发，否则完整的交易又要在副本之间传一遍
+    fn preprepare_relay(&self, msg: &PBFTMessage) -> Option<PBFTMessage> {
+        match msg {
+            PBFTMessage::Prepare { view, sequence_number, digest, sender_id }
+                if self.coalesce_messages && !self.digest_preprepares && *sender_id == self.id && !self.is_primary() =>
+            {
+                self.signed_preprepares.get(&(*view, *sequence_number, digest.clone())).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    async fn flush_outbox(&self) {
+        if self.coalesce_messages {
+            network::flush(self.genesis.network_magic(), self.id).await;
+        }
+    }
+
+    // 发给副本的形式：摘要模式下PrePrepare中的交易换成摘要，附上主节点对完整消息的签名
+    fn replica_form(&self, signed_msg: &PBFTMessage) -> PBFTMessage {
+        if let PBFTMessage::SignedMessage { message, signature, .. } = signed_msg {
+            if let PBFTMessage::PrePrepare { view, sequence_number, digest, transactions } = &**message {
+                if self.digest_preprepares {
+                    return self.sign_message(PBFTMessage::PrePrepareDigests {
+                        view: *view,
+                        sequence_number: *sequence_number,
+                        digest: digest.clone(),
+                        payload: transactions.iter().map(|tx| chain_index::transaction_digest(self.genesis.hash_function, tx)).collect(),
+                        signature: *signature,
+                    });
+                }
+            }
+        }
+        signed_msg.clone()
+    }
+
+    // 不签名的消息：认证方式不得弱于签名策略，MAC须是发送者为本节点计算的
+    fn check_authenticator(&self, message: &PBFTMessage, authenticator: &Authenticator, sender_id: usize) -> Result<(), String> {
+        let required = self.genesis.signing_policy.for_kind(message.kind());
+        if authenticator.strength() < required {
+            return Err(format!("签名策略要求{:?}认证", required));
+        }
+        match authenticator {
+            Authenticator::Mac { tags } => {
+                let sender_key = self.public_keys.get(&sender_id).ok_or("没有发送者的公钥")?;
+                let payload = self.genesis.message_payload(message);
+                if !self.mac_keys.lock().unwrap().verify(&self.signing_key, self.id, sender_id, sender_key, tags, &payload) {
+                    return Err("MAC无效".to_string());
+                }
+                metrics::inc_counter("mac_verified_total", 1);
+                Ok(())
+            }
+            Authenticator::Unauthenticated => Ok(()),
+        }
+    }
+
+    // 按签名策略认证消息：签名，或为每个已知节点计算MAC，或不认证
+    fn sign_message(&self, msg: PBFTMessage) -> PBFTMessage {
+        let payload = self.genesis.message_payload(&msg);
+
+        // 当前实例的共识消息携带本节点的追踪上下文
+        let trace = match (&msg, &self.trace) {
+            (PBFTMessage::PrePrepare { sequence_number, .. }, Some(trace))
+            | (PBFTMessage::PrePrepareDigests { sequence_number, .. }, Some(trace))
+            | (PBFTMessage::Prepare { sequence_number, .. }, Some(trace))
+            | (PBFTMessage::Commit { sequence_number, .. }, Some(trace)) if *sequence_number == trace.sequence_number => Some(trace.context()),
+            _ => None,
         };
 
-        for i in 0..N {
-            if i != self.id {
-                debug!("节点{}向节点{}发送签名消息", self.id, i);
-                send_message(i, signed_msg.clone()).await;
+        let authenticator = match self.genesis.signing_policy.for_kind(msg.kind()) {
+            Authentication::Signature => {
+                return PBFTMessage::SignedMessage {
+                    message: Box::new(msg),
+                    signature: self.signing_key.sign(&payload),
+                    sender_id: self.id,
+                    trace,
+                };
             }
+            Authentication::Mac => self.mac_keys.lock().unwrap().authenticate(&self.signing_key, self.id, &self.public_keys, &payload),
+            Authentication::Unauthenticated => Authenticator::Unauthenticated,
+        };
+        PBFTMessage::AuthenticatedMessage {
+            message: Box::new(msg),
+            authenticator,
+            sender_id: self.id,
+            trace,
+        }
+    }
+
+    fn audit(&mut self, event: AuditEvent) {
+        let timestamp = self.clock.wall_time().to_rfc3339();
+        self.audit_log.record(event, &self.signing_key, timestamp);
+    }
+
+    // 记录本节点签名发出的共识投票，作恶主节点分别发给各副本的PrePrepare逐条记录
+    fn audit_vote(&mut self, msg: &PBFTMessage, to: Option<usize>) {
+        if let PBFTMessage::PrePrepare { sequence_number, digest, .. }
+            | PBFTMessage::Prepare { sequence_number, digest, .. }
+            | PBFTMessage::Commit { sequence_number, digest, .. } = msg
+        {
+            self.audit(AuditEvent::SentVote {
+                kind: msg.kind().to_string(),
+                view: self.core.view,
+                sequence_number: *sequence_number,
+                digest: digest.clone(),
+                to,
+            });
         }
     }
 
+    // 公钥同时写入验签任务读取的公钥表
+    fn learn_public_key(&mut self, node_id: usize, public_key: PublicKey) {
+        self.public_keys.insert(node_id, public_key);
+        self.key_table.write().unwrap().insert(node_id, public_key);
+    }
+
+    fn verify_signature(&self, message: &PBFTMessage, signature: &Signature, sender_id: usize) -> bool {
+        let pubkey = match self.public_keys.get(&sender_id) {
+            Some(pubkey) => pubkey,
+            None => return false,
+        };
+        let payload = self.genesis.message_payload(message);
+        pubkey.verify(&payload, signature)
+    }
+
+    // 在事件循环中执行控制台命令，直接读写节点状态
+    #[cfg(feature = "console")]
+    async fn handle_console(&mut self, request: ConsoleRequest) {
+        let output = match request.command {
+            Command::Status => format!(
+                "节点{}，角色: {:?}，策略: {:?}\n视图: {}，主节点: {}，视图切换中: {}\n序列号: {}，阶段: {:?}，摘要: {}\n区块高度: {}，怀疑节点: {:?}，黑名单: {:?}",
+                self.id, self.role, self.core.strategy,
+                self.core.view, self.core.primary, self.view_change_in_progress,
+                self.core.sequence_number, self.core.phase, self.core.digest,
+                self.chain.lock().unwrap().height(), self.reputation.lock().unwrap().suspected(), self.blacklist,
+            ),
+            Command::Mempool => {
+                let operations: Vec<&str> = self.pending_requests.iter().filter_map(|request| match request {
+                    PBFTMessage::Request { operation, .. } => Some(operation.as_str()),
+                    _ => None,
+                }).collect();
+                format!("待处理请求{}个: {:?}\n批处理队列: {}个", operations.len(), operations, self.batch_queue.len())
+            }
+            Command::Submit(operation) => {
+                let request = PBFTMessage::Request { operation: operation.clone(), priority: Priority::Normal, client_id: None, expires_at: None, session: None, timestamp: None };
+                self.handle_request(request).await;
+                format!("已提交请求'{}'", operation)
+            }
+            Command::ViewChange if self.view_change_in_progress => "视图切换正在进行中".to_string(),
+            Command::ViewChange => {
+                let target_view = self.core.view + 1;
+                info!("节点{}通过控制台强制切换到视图{}", self.id, target_view);
+                self.start_view_change(target_view, "控制台命令").await;
+                format!("已发起到视图{}的视图切换", target_view)
+            }
+        };
+        let _ = request.reply.send(output);
+    }
+
+    #[cfg(not(feature = "console"))]
+    async fn handle_console(&mut self, request: ConsoleRequest) {
+        match request {}
+    }
+
     pub fn is_primary(&self) -> bool {
-        self.id == (self.view as usize % N)
+        self.id == self.leader(self.core.view)
+    }
+
+    pub fn leader(&self, view: u64) -> usize {
+        self.leader_election.leader(view)
     }
 
-    fn compute_digest(&self, operation: &str) -> String {
-        // 使用SHA-256计算摘要
-        let digest = ring::digest::digest(&ring::digest::SHA256, operation.as_bytes());
-        let hex_digest = hex::encode(digest.as_ref());
-        debug!("节点{}计算操作'{}'的摘要: {}", self.id, operation, hex_digest);
+    // 切换视图：结算上一视图主节点的表现，更新选举策略，并同步给RPC
+    fn enter_view(&mut self, view: u64) {
+        if view != self.core.view {
+            self.performance.view_ended(self.leader(self.core.view));
+            self.leader_election.on_new_view(view, &self.performance);
+        }
+        let primary = self.leader(view);
+        if view != self.core.view {
+            self.events.publish(ConsensusEvent::ViewChanged { view, primary });
+            self.view_stats.lock().unwrap().enter_view(view, primary, self.clock.now());
+            self.alerts.view_changed(view, self.clock.now());
+        }
+        self.core.enter_view(view, primary);
+        self.current_view.store(view, Ordering::Relaxed);
+        self.aggregation_deadline = None;
+        self.payload_fetch = None;
+        self.trace = None;
+        self.prepare_signatures.retain(|(v, _, _), _| *v >= view);
+        self.commit_signatures.retain(|(v, _, _), _| *v >= view);
+        self.o_set.clear();
+        self.current_primary.store(primary, Ordering::Relaxed);
+        // 过期的拜占庭投票连同其证据一起删除
+        if self.state.lock().unwrap().expire_byzantine_votes(view) > 0 {
+            debug!("节点{}进入视图{}，删除过期的拜占庭投票", self.id, view);
+        }
+        self.vote_evidence.retain(|(_, evidence_view, _), _| *evidence_view + BYZANTINE_VOTE_VIEWS >= view);
+    }
+
+    fn compute_digest(&self, transactions: &[Transaction]) -> String {
+        // 使用创世配置选定的哈希函数计算批次摘要
+        let hex_digest = chain::digest_transactions(self.genesis.hasher(), transactions);
+        debug!("节点{}计算批次（{}笔交易）的摘要: {}", self.id, transactions.len(), hex_digest);
         hex_digest
     }
 }
+
+// 根据ViewChange中的P集合计算新视图的O集合。序列号在每个视图从1开始，核心一次只运行一个实例，
+// 主节点在上一个实例提交后才提议下一个，所以只有视图和序列号最高的已准备请求可能尚未提交：
+// 取证明有效的条目中最高的一个，作为新视图的序列号1重新执行。旧主节点的信标证明在新视图中无效，
+// 去掉后重新计算摘要；certified检查条目附带的Prepare签名，主节点和副本据此得到同样的结果
+fn compute_o_set(hasher: &dyn Hasher, new_view: u64, view_changes: &[PBFTMessage], certified: impl Fn(&PreparedEntry) -> bool) -> Vec<PBFTMessage> {
+    let best = view_changes.iter()
+        .filter_map(|m| match m {
+            PBFTMessage::ViewChange { prepared, .. } => Some(prepared),
+            _ => None,
+        })
+        .flatten()
+        .filter(|entry| entry.view < new_view && certified(entry))
+        .max_by(|a, b| (a.view, a.sequence_number).cmp(&(b.view, b.sequence_number)));
+    let transactions: Vec<Transaction> = match best {
+        Some(entry) => entry.transactions.iter().filter(|tx| !tx.operation.starts_with(BEACON_COMMAND)).cloned().collect(),
+        None => return Vec::new(),
+    };
+    if transactions.is_empty() {
+        return Vec::new();
+    }
+    vec![PBFTMessage::PrePrepare {
+        view: new_view,
+        sequence_number: 1,
+        digest: chain::digest_transactions(hasher, &transactions),
+        transactions,
+    }]
+}
+
+// 可选的事件来源，没有配置时永远不就绪
+async fn next_event<T>(receiver: &mut Option<Receiver<T>>) -> Option<T> {
+    match receiver {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::hash::Sha256;
+    use crate::network::{self, LinkFaults, LinkOverride, NetworkFaults};
+    use crate::testing::{TestCluster, NodeSetup};
+    use super::*;
+
+    // 长时间运行后NodeState的各日志保持在上限内，超出部分按水位、视图和票数淘汰
+    #[test]
+    fn node_state_evicts_beyond_limits() {
+        let evicted = |kind: &str| metrics::snapshot().get(&format!("node_state_evicted_total{{kind=\"{}\"}}", kind)).copied().unwrap_or(0);
+        let before = evicted("prepared");
+        let mut state = NodeState { prepared: HashSet::new(), committed: HashSet::new(), view_change_messages: Vec::new(), byzantine_votes: HashMap::new() };
+        for seq in 1..=STATE_LOG_WINDOW * 2 {
+            state.prepared.insert((seq, format!("d{}", seq)));
+            state.committed.insert((seq, format!("d{}", seq)));
+            state.enforce_limits(0);
+        }
+        assert_eq!(state.prepared.len() as u64, STATE_LOG_WINDOW);
+        assert!(!state.committed.contains(&(STATE_LOG_WINDOW, format!("d{}", STATE_LOG_WINDOW))));
+        assert!(state.committed.contains(&(STATE_LOG_WINDOW + 1, format!("d{}", STATE_LOG_WINDOW + 1))));
+        assert!(evicted("prepared") - before >= STATE_LOG_WINDOW);
+
+        // 当前视图5的消息最先记录，此后涌入的高视图消息只能挤掉彼此中最早记录的
+        for view in 5..MAX_VIEW_CHANGE_MESSAGES as u64 + 8 {
+            state.view_change_messages.push(PBFTMessage::ViewChange { view, last_sequence_number: 0, node_id: 1, prepared: Vec::new() });
+            state.enforce_limits(5);
+        }
+        assert_eq!(state.view_change_messages.len(), MAX_VIEW_CHANGE_MESSAGES);
+        assert!(matches!(state.view_change_messages[0], PBFTMessage::ViewChange { view: 5, .. }), "当前视图的消息不应被淘汰");
+        assert!(state.view_change_messages[1..].iter().all(|m| matches!(m, PBFTMessage::ViewChange { view, .. } if *view >= 9)));
+
+        state.record_byzantine_vote(100, 0, 1);
+        state.record_byzantine_vote(100, 0, 2);
+        for suspect in 0..MAX_TRACKED_SUSPECTS - 1 {
+            assert_eq!(state.record_byzantine_vote(suspect, 0, 1), (1, None));
+        }
+        // 达到上限后新的被指控节点淘汰票数最少的节点，票数多的保留
+        assert_eq!(state.record_byzantine_vote(200, 0, 3), (1, Some(MAX_TRACKED_SUSPECTS - 2)));
+        assert_eq!(state.byzantine_votes.len(), MAX_TRACKED_SUSPECTS);
+        assert_eq!(state.byzantine_votes[&100][&0].len(), 2);
+    }
+
+    // 同一视图内连续提交大量实例：检查点稳定时删除其高度及以前的Prepare/Commit签名，两张表的大小不随实例数增长
+    #[tokio::test]
+    async fn vote_signatures_are_pruned_at_stable_checkpoints() {
+        tokio::task::LocalSet::new().run_until(async {
+            // 只借用集群的临时目录，节点0由测试直接驱动
+            let cluster = TestCluster::builder().nodes(0).build().await;
+            let signing_key = SigningKey::generate();
+            let mut public_keys = cluster.public_keys.clone();
+            public_keys.insert(0, signing_key.public_key());
+            let (_tx, rx) = mpsc::channel(1);
+            let mut node = Node::new(0, 0, signing_key, public_keys, rx, Strategy::Honest, cluster.genesis.clone());
+            let signature = node.signing_key.sign(b"vote");
+
+            let mut largest = 0;
+            for seq in 1..=CHECKPOINT_INTERVAL * 5 {
+                let key = (0, seq, format!("d{}", seq));
+                node.prepare_signatures.insert(key.clone(), (0..N).map(|id| (id, signature)).collect());
+                node.commit_signatures.insert(key.clone(), (0..N).map(|id| (id, signature)).collect());
+                let certificate = CommitCertificate { view: 0, sequence_number: seq, digest: key.2.clone(), signatures: Vec::new() };
+                let height = node.chain.lock().unwrap().append(0, seq, key.2, Vec::new(), certificate).header.height;
+                if node.checkpoints.is_checkpoint(height) {
+                    for id in 0..N {
+                        node.handle_checkpoint(id, height, "state".to_string()).await;
+                    }
+                }
+                largest = largest.max(node.prepare_signatures.len()).max(node.commit_signatures.len());
+            }
+            assert!(largest <= CHECKPOINT_INTERVAL as usize, "签名表增长到{}项", largest);
+            assert!(node.prepare_signatures.is_empty() && node.commit_signatures.is_empty());
+        }).await;
+    }
+
+    // 节点2进入视图1并开始处理新主节点的提议后，再收到同一视图的NewView不重置当前实例
+    #[tokio::test]
+    async fn duplicate_new_view_keeps_the_current_instance() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().nodes(0).build().await;
+            let (_tx, rx) = mpsc::channel(1);
+            let mut node = Node::new(2, 0, cluster.signing_key(2), cluster.public_keys.clone(), rx, Strategy::Honest, cluster.genesis.clone());
+            node.start_view_change(1, "测试").await;
+            let view_change_messages = (1..N)
+                .map(|id| cluster.signed(id, PBFTMessage::ViewChange { view: 1, last_sequence_number: 0, node_id: id, prepared: Vec::new() }))
+                .collect();
+            let new_view = PBFTMessage::NewView { view: 1, view_change_messages, pre_prepares: Vec::new() };
+            node.handle_new_view(new_view.clone()).await;
+            assert_eq!(node.core.view, 1);
+            assert!(!node.view_change_in_progress);
+
+            let transactions = vec![Transaction { operation: "SET after view change".to_string(), client_id: None, session: None, timestamp: None }];
+            let digest = chain::digest_transactions(cluster.genesis.hasher(), &transactions);
+            node.handle_preprepare(PBFTMessage::PrePrepare { view: 1, sequence_number: 1, digest: digest.clone(), transactions }, Some(1)).await;
+            let phase = node.core.phase;
+            assert_eq!((node.core.sequence_number, node.core.digest.as_str()), (1, digest.as_str()));
+
+            node.handle_new_view(new_view).await;
+            assert_eq!(node.core.view, 1);
+            assert_eq!((node.core.sequence_number, node.core.digest.as_str()), (1, digest.as_str()), "重复的NewView重置了当前实例");
+            assert_eq!(node.core.phase, phase);
+        }).await;
+    }
+
+    // 合并发送时副本的Prepare搭载主节点签名的PrePrepare：主节点到节点3的链路断开，节点3从其他副本的捆绑中
+    // 得到PrePrepare，所有请求仍在视图0提交
+    #[tokio::test]
+    async fn coalesced_prepares_relay_the_preprepare() {
+        tokio::task::LocalSet::new().run_until(async {
+            let coalesce = NodeSetup { configure: Some(|node: &mut Node| node.coalesce_messages = true), ..NodeSetup::default() };
+            let mut builder = TestCluster::builder();
+            for id in 0..N {
+                builder = builder.setup(id, coalesce.clone());
+            }
+            let cluster = builder.build().await;
+            let relayed = || metrics::snapshot().get("preprepare_relayed_total").copied().unwrap_or(0);
+            let before = relayed();
+            network::set_faults(NetworkFaults {
+                links: vec![LinkOverride { from: 0, to: 3, faults: LinkFaults { drop: 1.0, ..Default::default() } }],
+                ..NetworkFaults::default()
+            });
+            for i in 0..3 {
+                let operation = format!("SET coalesced{} v", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(10), |c| (0..N).all(|id| c.committed_view(id, &operation).is_some())).await;
+                assert!(committed, "操作{}未提交", operation);
+                assert!((0..N).all(|id| cluster.committed_view(id, &operation) == Some(0)), "不应发生视图切换");
+            }
+            assert!(relayed() > before, "副本没有转发PrePrepare");
+        }).await;
+    }
+
+    // 拜占庭投票按证据所属的视图分别计数，过期的视图整体删除；旧格式的投票加载时丢弃
+    #[test]
+    fn byzantine_votes_are_scoped_to_views() {
+        let mut state = NodeState { prepared: HashSet::new(), committed: HashSet::new(), view_change_messages: Vec::new(), byzantine_votes: HashMap::new() };
+        assert_eq!(state.record_byzantine_vote(3, 0, 0), (1, None));
+        assert_eq!(state.record_byzantine_vote(3, 1, 1), (1, None));
+        // 不同视图的票不合并
+        assert_eq!(state.record_byzantine_vote(3, 1, 2), (2, None));
+
+        let restored: NodeState = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        assert_eq!(restored.byzantine_votes, state.byzantine_votes);
+
+        assert_eq!(state.expire_byzantine_votes(BYZANTINE_VOTE_VIEWS), 0);
+        assert_eq!(state.expire_byzantine_votes(BYZANTINE_VOTE_VIEWS + 1), 1);
+        assert_eq!(state.byzantine_votes[&3].keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(state.expire_byzantine_votes(BYZANTINE_VOTE_VIEWS + 2), 2);
+        assert!(state.byzantine_votes.is_empty());
+
+        let old: NodeState = serde_json::from_str(r#"{"prepared":[],"committed":[],"view_change_messages":[],"byzantine_votes":{"3":[1,2]}}"#).unwrap();
+        assert!(old.byzantine_votes.is_empty());
+    }
+
+    // 节点收到的拜占庭投票按证据的视图分组，不同视图的票凑不成法定人数；远离当前视图的证据不计票
+    #[tokio::test]
+    async fn cluster_scopes_byzantine_votes_to_views() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let equivocation = |view: u64| {
+                let prepare = |digest: &str| PBFTMessage::Prepare { view, sequence_number: 1, digest: digest.to_string(), sender_id: 3 };
+                Evidence::Equivocation { first: Box::new(cluster.signed(3, prepare("a"))), second: Box::new(cluster.signed(3, prepare("b"))) }
+            };
+            let vote = |voter: usize, view: u64| cluster.signed(voter, PBFTMessage::ByzantineVote { suspected_id: 3, sender_id: voter, evidence: equivocation(view) });
+            let out_of_window = || metrics::snapshot().get("byzantine_votes_out_of_window_total").copied().unwrap_or(0);
+            let before = out_of_window();
+
+            cluster.inject(0, vote(1, 0)).await;
+            cluster.inject(0, vote(2, 1)).await;
+            cluster.inject(0, vote(2, BYZANTINE_VOTE_VIEWS + 1)).await;
+            let recorded = cluster.wait_until(Duration::from_secs(5), |c| {
+                let state = c.states[0].lock().unwrap();
+                state.byzantine_votes.get(&3).is_some_and(|views| views.len() == 2 && views.values().all(|voters| voters.len() == 1))
+            }).await;
+            assert!(recorded, "投票没有按视图分组");
+            assert!(out_of_window() > before, "远离当前视图的证据被计票");
+            assert!(!cluster.states[0].lock().unwrap().byzantine_votes[&3].contains_key(&(BYZANTINE_VOTE_VIEWS + 1)));
+        }).await;
+    }
+
+    // 主节点的PrePrepare只送到节点1和2：节点3收到它们的Commit后向对等节点索取PrePrepare，补齐后三个副本都能提交
+    #[tokio::test]
+    async fn cluster_fetches_missing_preprepare_before_counting_commits() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let fetches = || metrics::snapshot().get("preprepare_fetch_requests_total").copied().unwrap_or(0);
+            let before = fetches();
+            let transactions = vec![Transaction { operation: "SET fetched yes".to_string(), client_id: None, session: None, timestamp: None }];
+            let pre_prepare = PBFTMessage::PrePrepare {
+                view: 0,
+                sequence_number: 1,
+                digest: chain::digest_transactions(cluster.genesis.hasher(), &transactions),
+                transactions,
+            };
+            for id in [1, 2] {
+                cluster.inject(id, cluster.signed(0, pre_prepare.clone())).await;
+            }
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (1..N).all(|id| c.committed_view(id, "SET fetched yes").is_some())).await;
+            assert!(committed, "节点3没有补齐PrePrepare");
+            assert!(fetches() > before, "没有索取PrePrepare");
+        }).await;
+    }
+
+    // 主节点计划退出：在远小于超时的时间内把主节点交给节点1后关闭，之后的请求在新视图中提交
+    #[tokio::test]
+    async fn planned_exit_hands_off_primary_without_timeout() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut cluster = TestCluster::builder().timeout(Duration::from_secs(10)).build().await;
+            cluster.submit("SET before v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET before v").is_some())
+            }).await;
+            assert!(committed, "退出前的请求未提交");
+
+            let exited = tokio::time::timeout(Duration::from_secs(5), cluster.exit(0)).await;
+            assert!(exited.is_ok(), "主节点未能完成交接并退出");
+
+            cluster.submit("SET after v").await;
+            let committed = cluster.wait_until(Duration::from_secs(3), |c| {
+                (1..N).all(|id| c.committed_view(id, "SET after v") == Some(1))
+            }).await;
+            assert!(committed, "交接后的请求未在新视图中提交");
+        }).await;
+    }
+
+    // 节点3进入维护模式：不投票，也不因此引起视图切换，其余节点照常提交；退出维护后重新参与共识，
+    // 此时停掉节点1，只有节点3投票才能凑齐法定人数
+    #[tokio::test]
+    async fn maintenance_node_abstains_and_rejoins() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().timeout(Duration::from_secs(2)).build().await;
+            let resting = N - 1;
+            cluster.set_maintenance(resting, true).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            for i in 0..3 {
+                let operation = format!("SET during{} v", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                    (0..N - 1).all(|id| c.committed_view(id, &operation).is_some())
+                }).await;
+                assert!(committed, "{}未提交", operation);
+            }
+            let voted = cluster.chains[0].lock().unwrap().blocks.iter()
+                .any(|block| block.certificate.signatures.iter().any(|(signer, _)| *signer == resting));
+            assert!(!voted, "维护中的节点不应参与投票");
+            assert!((0..N).all(|id| cluster.view(id) == 0), "维护不应引起视图切换: {:?}", (0..N).map(|id| cluster.view(id)).collect::<Vec<_>>());
+
+            cluster.set_maintenance(resting, false).await;
+            cluster.crash(1);
+            cluster.submit("SET after v").await;
+            let caught_up = cluster.wait_until(Duration::from_secs(10), |c| {
+                [0, 2, resting].iter().all(|id| c.committed_view(*id, "SET after v") == Some(0))
+            }).await;
+            assert!(caught_up, "退出维护的节点未重新投票");
+            assert_eq!(cluster.executions[resting].lock().unwrap().get("during2"), Some(&"v".to_string()));
+        }).await;
+    }
+
+    // 节点3短暂断网错过若干实例，恢复后从后续实例的序列号发现缺口并补齐区块
+    #[tokio::test]
+    async fn lagging_replica_fetches_missed_blocks() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(2000)).await;
+            let lagging = N - 1;
+            network::set_faults(NetworkFaults {
+                global: LinkFaults::default(),
+                links: (0..N - 1).map(|from| LinkOverride { from, to: lagging, faults: LinkFaults { drop: 1.0, ..Default::default() } }).collect(),
+            });
+            for i in 0..4 {
+                let operation = format!("SET missed{} v", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(10), |c| {
+                    (0..N - 1).all(|id| c.committed_view(id, &operation).is_some())
+                }).await;
+                assert!(committed, "操作{}未提交", operation);
+            }
+            assert_eq!(cluster.chains[lagging].lock().unwrap().height(), 0);
+
+            network::set_faults(NetworkFaults::default());
+            cluster.submit("SET after v").await;
+            let caught_up = cluster.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET after v").is_some())
+            }).await;
+            assert!(caught_up, "落后的节点未补齐区块");
+            let hash = |id: usize| cluster.chains[id].lock().unwrap().tip().unwrap().hash(&Sha256);
+            assert_eq!(hash(lagging), hash(0));
+            assert_eq!(cluster.view(lagging), 0, "补齐缺口不应触发视图切换");
+            assert_eq!(cluster.executions[lagging].lock().unwrap().get("missed3"), Some(&"v".to_string()));
+        }).await;
+    }
+
+    // 节点3在其他节点发出握手挑战之后才上线。它发起的挑战得到应答的同时，对方也向它发起挑战，
+    // 双方都完成认证。节点1停掉后只有接受节点3的投票才能凑齐法定人数
+    #[tokio::test]
+    async fn late_node_completes_handshake_in_both_directions() {
+        tokio::task::LocalSet::new().run_until(async {
+            let late = N - 1;
+            let mut setups = vec![NodeSetup::default(); N];
+            setups[late].start_delay = Duration::from_millis(300);
+            let cluster = TestCluster::start_with(&setups, Duration::from_millis(2000)).await;
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            cluster.crash(1);
+            cluster.submit("SET late v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                [0, 2, late].iter().all(|id| c.committed_view(*id, "SET late v") == Some(0))
+            }).await;
+            assert!(committed, "节点0和2未接受后上线节点的投票");
+        }).await;
+    }
+
+    // 主节点发出PrePrepare后、任何Commit之前被杀死：副本之间的消息在此期间全部丢弃，
+    // 保证它们停在PrePrepared阶段。其余节点切换视图，由新主节点重新提议并恰好提交一次
+    #[tokio::test]
+    async fn primary_crash_after_preprepare_commits_request_once() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            network::set_faults(NetworkFaults {
+                global: LinkFaults::default(),
+                links: (1..N).flat_map(|from| (0..N).map(move |to| LinkOverride { from, to, faults: LinkFaults { drop: 1.0, ..Default::default() } })).collect(),
+            });
+            cluster.submit("APPEND k x").await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cluster.crash(0);
+            network::set_faults(NetworkFaults::default());
+
+            let committed = cluster.wait_until(Duration::from_secs(15), |c| {
+                (1..N).all(|id| c.committed_view(id, "APPEND k x").is_some_and(|view| view >= 1))
+            }).await;
+            assert!(committed, "主节点崩溃后未能切换视图并提交请求");
+            assert_eq!(cluster.committed_view(0, "APPEND k x"), None, "崩溃的主节点不应提交");
+            for id in 1..N {
+                let chain = cluster.chains[id].lock().unwrap();
+                let count = chain.blocks.iter().flat_map(|block| &block.transactions).filter(|tx| tx.operation == "APPEND k x").count();
+                assert_eq!(count, 1, "节点{}提交了{}次请求", id, count);
+                assert_eq!(cluster.executions[id].lock().unwrap().get("k"), Some(&"x".to_string()));
+            }
+        }).await;
+    }
+
+    // O集合只取证明有效的条目中视图和序列号最高的一个，旧主节点的信标证明去掉后重新计算摘要
+    #[test]
+    fn o_set_keeps_the_highest_certified_entry() {
+        let tx = |operation: &str| Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None };
+        let entry = |view: u64, sequence_number: u64, transactions: Vec<Transaction>| PreparedEntry {
+            view,
+            sequence_number,
+            digest: chain::digest_transactions(&Sha256, &transactions),
+            transactions,
+            prepares: Vec::new(),
+        };
+        let view_change = |node_id: usize, prepared: Vec<PreparedEntry>| PBFTMessage::ViewChange { view: 2, last_sequence_number: 0, node_id, prepared };
+        let view_changes = vec![
+            view_change(0, vec![entry(1, 3, vec![tx(&format!("{} proof", BEACON_COMMAND)), tx("SET a 1")])]),
+            view_change(1, vec![entry(0, 7, vec![tx("SET b 2")])]),
+            view_change(2, vec![entry(1, 4, vec![tx("SET forged 3")])]),
+        ];
+        let o_set = compute_o_set(&Sha256, 2, &view_changes, |entry| entry.transactions.iter().all(|tx| tx.operation != "SET forged 3"));
+        let expected = vec![tx("SET a 1")];
+        assert!(matches!(&o_set[..], [PBFTMessage::PrePrepare { view: 2, sequence_number: 1, digest, transactions }]
+            if *digest == chain::digest_transactions(&Sha256, &expected) && *transactions == expected));
+        assert!(compute_o_set(&Sha256, 2, &view_changes, |_| false).is_empty());
+    }
+
+    // 节点1和2收到主节点0的PrePrepare后进入Prepared，但发往节点3的消息全部丢失，凑不成Commit法定人数；
+    // 请求不在任何节点的待处理队列中，只能经ViewChange携带的P集合和NewView的O集合在新视图中恢复
+    #[tokio::test]
+    async fn prepared_request_survives_view_change() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            cluster.crash(0);
+            network::set_faults(NetworkFaults {
+                global: LinkFaults::default(),
+                links: (1..3).map(|from| LinkOverride { from, to: 3, faults: LinkFaults { drop: 1.0, ..Default::default() } }).collect(),
+            });
+            let transactions = vec![Transaction { operation: "SET prepared yes".to_string(), client_id: None, session: None, timestamp: None }];
+            let digest = chain::digest_transactions(cluster.genesis.hasher(), &transactions);
+            let pre_prepare = PBFTMessage::PrePrepare { view: 0, sequence_number: 1, digest: digest.clone(), transactions };
+            for id in 1..3 {
+                cluster.inject(id, cluster.signed(0, pre_prepare.clone())).await;
+            }
+            let prepared = cluster.wait_until(Duration::from_secs(5), |c| {
+                (1..3).all(|id| c.states[id].lock().unwrap().prepared.contains(&(1, digest.clone())))
+            }).await;
+            assert!(prepared, "节点1和2未能进入Prepared");
+            assert!((1..N).all(|id| cluster.committed_view(id, "SET prepared yes").is_none()), "请求不应在视图0提交");
+            network::set_faults(NetworkFaults::default());
+
+            let committed = cluster.wait_until(Duration::from_secs(15), |c| {
+                (1..N).all(|id| c.committed_view(id, "SET prepared yes").is_some_and(|view| view >= 1))
+            }).await;
+            assert!(committed, "已准备的请求未能在新视图中提交");
+            for id in 1..N {
+                let chain = cluster.chains[id].lock().unwrap();
+                let count = chain.blocks.iter().flat_map(|block| &block.transactions).filter(|tx| tx.operation == "SET prepared yes").count();
+                assert_eq!(count, 1, "节点{}提交了{}次请求", id, count);
+            }
+        }).await;
+    }
+
+    // 不带签名包装的PrePrepare、Prepare和Commit直接丢弃，不当作本节点的消息；
+    // 非主节点签名的PrePrepare同样拒绝。伪造的请求不被提交，主节点的正常提议照常提交
+    #[tokio::test]
+    async fn unauthenticated_and_non_primary_preprepares_are_rejected() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let dropped = || metrics::snapshot().get("unauthenticated_messages_dropped_total").copied().unwrap_or(0);
+            let before = dropped();
+            let forged = |operation: &str| {
+                let transactions = vec![Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None }];
+                let digest = chain::digest_transactions(cluster.genesis.hasher(), &transactions);
+                (PBFTMessage::PrePrepare { view: 0, sequence_number: 1, digest: digest.clone(), transactions }, digest)
+            };
+            let (unsigned, digest) = forged("SET forged yes");
+            let (impersonated, _) = forged("SET impersonated yes");
+            for id in 1..N {
+                cluster.inject(id, unsigned.clone()).await;
+                cluster.inject(id, cluster.signed(2, impersonated.clone())).await;
+                for sender_id in 1..N {
+                    cluster.inject(id, PBFTMessage::Prepare { view: 0, sequence_number: 1, digest: digest.clone(), sender_id }).await;
+                    cluster.inject(id, PBFTMessage::Commit { view: 0, sequence_number: 1, digest: digest.clone() }).await;
+                }
+            }
+            cluster.submit("SET honest yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(10), |c| (0..N).all(|id| c.committed_view(id, "SET honest yes").is_some())).await;
+            assert!(committed, "主节点的正常提议未能提交");
+            for id in 0..N {
+                assert_eq!(cluster.committed_view(id, "SET forged yes"), None, "节点{}提交了未签名的PrePrepare", id);
+                assert_eq!(cluster.committed_view(id, "SET impersonated yes"), None, "节点{}提交了非主节点的PrePrepare", id);
+            }
+            assert!(dropped() - before >= (N as u64 - 1) * (2 * N as u64 - 1));
+        }).await;
+    }
+}
@@ -0,0 +1,101 @@
+// src/observer.rs
+
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use log::error;
+use crate::chain;
+use crate::hash::Hasher;
+use crate::message::PBFTMessage;
+use crate::leader::LeaderElection;
+use crate::metrics;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ViolationKind {
+    Equivocation,       // 同一节点在同一视图和序列号上发送了不同摘要
+    NonPrimaryProposal, // 非主节点发送PrePrepare
+    DigestMismatch,     // PrePrepare摘要与批次内容不符
+    InvalidCertificate, // 区块或提交证书验证失败
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub node_id: usize,
+    pub view: u64,
+    pub sequence_number: u64,
+    pub description: String,
+    pub evidence: Vec<PBFTMessage>, // 带签名的原始消息
+}
+
+// 观察者节点的被动审计器：只验证收到的共识消息，不参与共识
+#[derive(Default)]
+pub struct Auditor {
+    seen: HashMap<(usize, &'static str, u64, u64), (String, PBFTMessage)>,
+    pub violations: Vec<Violation>,
+}
+
+impl Auditor {
+    // signed 为已通过签名验证的 SignedMessage
+    pub fn audit(&mut self, sender_id: usize, signed: PBFTMessage, leader_election: &dyn LeaderElection, hasher: &dyn Hasher) {
+        let inner = match &signed {
+            PBFTMessage::SignedMessage { message, .. } => (**message).clone(),
+            _ => return,
+        };
+
+        let (view, sequence_number, digest) = match &inner {
+            PBFTMessage::PrePrepare { view, sequence_number, digest, .. }
+            | PBFTMessage::Prepare { view, sequence_number, digest, .. }
+            | PBFTMessage::Commit { view, sequence_number, digest } => (*view, *sequence_number, digest.clone()),
+            _ => return,
+        };
+
+        if let PBFTMessage::PrePrepare { transactions, .. } = &inner {
+            if sender_id != leader_election.leader(view) {
+                self.record(Violation {
+                    kind: ViolationKind::NonPrimaryProposal,
+                    node_id: sender_id,
+                    view,
+                    sequence_number,
+                    description: format!("节点{}不是视图{}的主节点却发送了PrePrepare", sender_id, view),
+                    evidence: vec![signed.clone()],
+                });
+            }
+            if chain::digest_transactions(hasher, transactions) != digest {
+                self.record(Violation {
+                    kind: ViolationKind::DigestMismatch,
+                    node_id: sender_id,
+                    view,
+                    sequence_number,
+                    description: "PrePrepare摘要与批次内容不符".to_string(),
+                    evidence: vec![signed.clone()],
+                });
+            }
+        }
+
+        let key = (sender_id, inner.kind(), view, sequence_number);
+        match self.seen.get(&key) {
+            Some((seen_digest, earlier)) if *seen_digest != digest => {
+                let earlier = earlier.clone();
+                self.record(Violation {
+                    kind: ViolationKind::Equivocation,
+                    node_id: sender_id,
+                    view,
+                    sequence_number,
+                    description: format!("节点{}在视图{}序列号{}发送了两个不同摘要的{}消息",
+                        sender_id, view, sequence_number, inner.kind()),
+                    evidence: vec![earlier, signed],
+                });
+            }
+            Some(_) => {}
+            None => {
+                self.seen.insert(key, (digest, signed));
+            }
+        }
+    }
+
+    pub fn record(&mut self, violation: Violation) {
+        error!("观察者发现协议违规 {:?}（节点{}）: {}", violation.kind, violation.node_id, violation.description);
+        metrics::inc_counter(&format!("observer_violations_total{{kind=\"{:?}\"}}", violation.kind), 1);
+        self.violations.push(violation);
+    }
+}
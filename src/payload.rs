@@ -0,0 +1,172 @@
+// src/payload.rs
+
+// 按摘要补齐PrePrepare引用的交易。摘要模式下主节点发给副本的PrePrepare只带各交易的摘要，
+// 副本通常已从客户端收到同样的请求，据此还原完整批次；本地缺少的交易立即向主节点和对等节点索取
+// （不等超时，谁有谁回），收到的交易须与所请求的摘要一致。主节点签名了这条PrePrepare，
+// 却在PAYLOAD_FETCH_TIMEOUT_MS内连同对等节点都拿不出内容，视为主节点作恶，触发视图切换
+use std::collections::HashMap;
+use tokio::time::Instant;
+use crate::chain_index::transaction_digest;
+use crate::crypto::Signature;
+use crate::hash::HashFunction;
+use crate::message::{PBFTMessage, Transaction};
+use crate::trace::TraceContext;
+
+// 正在补齐内容的PrePrepare
+pub struct PayloadFetch {
+    pub view: u64,
+    pub sequence_number: u64,
+    pub digest: String,
+    pub payload: Vec<String>,
+    pub signature: Signature, // 主节点对完整PrePrepare的签名
+    pub primary: usize,
+    pub trace: Option<TraceContext>,
+    pub found: HashMap<String, Transaction>,
+    pub deadline: Instant,
+}
+
+impl PayloadFetch {
+    // 收下候选交易中属于本批次、尚未找到的部分，返回收下的个数
+    pub fn offer(&mut self, hash_function: HashFunction, candidates: impl IntoIterator<Item = Transaction>) -> usize {
+        let mut accepted = 0;
+        for tx in candidates {
+            let digest = transaction_digest(hash_function, &tx);
+            if self.payload.contains(&digest) && !self.found.contains_key(&digest) {
+                self.found.insert(digest, tx);
+                accepted += 1;
+            }
+        }
+        accepted
+    }
+
+    pub fn missing(&self) -> Vec<String> {
+        let mut missing: Vec<String> = self.payload.iter().filter(|d| !self.found.contains_key(*d)).cloned().collect();
+        missing.dedup();
+        missing
+    }
+
+    // 交易齐全时还原主节点签名的完整PrePrepare，交给签名消息的常规流程验证
+    pub fn expand(&self) -> Option<PBFTMessage> {
+        let transactions = self.payload.iter().map(|d| self.found.get(d).cloned()).collect::<Option<Vec<_>>>()?;
+        Some(PBFTMessage::SignedMessage {
+            message: Box::new(PBFTMessage::PrePrepare {
+                view: self.view,
+                sequence_number: self.sequence_number,
+                digest: self.digest.clone(),
+                transactions,
+            }),
+            signature: self.signature,
+            sender_id: self.primary,
+            trace: self.trace.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::byzantine::Strategy;
+    use crate::config::{N, PAYLOAD_FETCH_TIMEOUT_MS};
+    use crate::crypto::SigningKey;
+    use crate::metrics;
+    use crate::qos::Priority;
+    use crate::testing::{TestCluster, NodeSetup};
+
+    fn transaction(operation: &str) -> Transaction {
+        Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None }
+    }
+
+    fn request(operation: &str) -> PBFTMessage {
+        PBFTMessage::Request {
+            operation: operation.to_string(),
+            priority: Priority::Normal,
+            client_id: None,
+            expires_at: None,
+            session: None,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn accepts_only_requested_transactions() {
+        let hash_function = HashFunction::default();
+        let (a, b) = (transaction("SET a 1"), transaction("SET b 2"));
+        let mut fetch = PayloadFetch {
+            view: 0,
+            sequence_number: 1,
+            digest: "batch".to_string(),
+            payload: vec![transaction_digest(hash_function, &a), transaction_digest(hash_function, &b)],
+            signature: SigningKey::generate().sign(b"batch"),
+            primary: 0,
+            trace: None,
+            found: HashMap::new(),
+            deadline: Instant::now(),
+        };
+        assert_eq!(fetch.offer(hash_function, vec![a.clone(), transaction("SET c 3")]), 1);
+        assert_eq!(fetch.missing(), vec![fetch.payload[1].clone()]);
+        assert!(fetch.expand().is_none());
+
+        assert_eq!(fetch.offer(hash_function, vec![b.clone(), b.clone()]), 1);
+        assert!(fetch.missing().is_empty());
+        match fetch.expand() {
+            Some(PBFTMessage::SignedMessage { message, sender_id: 0, .. }) => {
+                assert!(matches!(*message, PBFTMessage::PrePrepare { transactions, .. } if transactions == vec![a, b]));
+            }
+            other => panic!("未还原出PrePrepare: {:?}", other),
+        }
+    }
+
+    fn counter(name: &str) -> u64 {
+        metrics::snapshot().get(name).copied().unwrap_or(0)
+    }
+
+    fn digest_mode() -> NodeSetup {
+        NodeSetup { configure: Some(|node| node.digest_preprepares = true), ..NodeSetup::default() }
+    }
+
+    // 请求只发给了主节点，副本收到只带摘要的PrePrepare后向主节点索取内容，照常提交
+    #[tokio::test]
+    async fn replicas_fetch_payload_they_never_received() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut builder = TestCluster::builder();
+            for id in 0..N {
+                builder = builder.setup(id, digest_mode());
+            }
+            let cluster = builder.build().await;
+            let fetched = counter("payload_fetch_requests_total");
+            cluster.submit_to(0, request("SET k v")).await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET k v") == Some(0))
+            }).await;
+            assert!(committed, "副本未能补齐PrePrepare的内容");
+            assert!(counter("payload_fetch_requests_total") > fetched);
+        }).await;
+    }
+
+    // 主节点提议了只有自己知道的请求，却不应答索取。副本在超时后发起视图切换，新主节点照常提交后续请求
+    #[tokio::test]
+    async fn withheld_payload_triggers_view_change() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut builder = TestCluster::builder();
+            for id in 0..N {
+                builder = builder.setup(id, digest_mode());
+            }
+            // 请求超时远长于索取超时，视图切换只能由扣留内容引起
+            let cluster = builder.byzantine(0, Strategy::WithholdPayload).timeout(Duration::from_secs(5)).build().await;
+            let timeouts = counter("payload_fetch_timeouts_total");
+            cluster.submit_to(0, request("SET hidden v")).await;
+            let changed = cluster.wait_until(Duration::from_millis(PAYLOAD_FETCH_TIMEOUT_MS * 3), |c| {
+                (1..N).all(|id| c.view(id) >= 1)
+            }).await;
+            assert!(changed, "主节点扣留内容未触发视图切换");
+            assert!(counter("payload_fetch_timeouts_total") > timeouts);
+
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (1..N).all(|id| c.committed_view(id, "SET k v").is_some())
+            }).await;
+            assert!(committed, "视图切换后未能提交新请求");
+        }).await;
+    }
+}
@@ -0,0 +1,75 @@
+// src/phase.rs
+
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+// 单个共识实例（视图 + 序列号）所处的阶段
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Phase {
+    #[default]
+    Idle,
+    PrePrepared,
+    Prepared,
+    Committed,
+}
+
+// 推动阶段前进的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhaseEvent {
+    PrePrepare,    // 主节点提议或副本接受了PrePrepare
+    PrepareQuorum, // 收到2f个匹配的Prepare
+    CommitQuorum,  // 收到2f+1个匹配的Commit
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IllegalTransition {
+    pub from: Phase,
+    pub event: PhaseEvent,
+}
+
+impl fmt::Display for IllegalTransition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "阶段{:?}不能处理事件{:?}", self.from, self.event)
+    }
+}
+
+// 转移表：唯一允许改变实例阶段的地方，表外的组合一律视为非法
+pub fn transition(from: Phase, event: PhaseEvent) -> Result<Phase, IllegalTransition> {
+    match (from, event) {
+        (Phase::Idle, PhaseEvent::PrePrepare) => Ok(Phase::PrePrepared),
+        (Phase::PrePrepared, PhaseEvent::PrepareQuorum) => Ok(Phase::Prepared),
+        (Phase::Prepared, PhaseEvent::CommitQuorum) => Ok(Phase::Committed),
+        _ => Err(IllegalTransition { from, event }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHASES: [Phase; 4] = [Phase::Idle, Phase::PrePrepared, Phase::Prepared, Phase::Committed];
+    const EVENTS: [PhaseEvent; 3] = [PhaseEvent::PrePrepare, PhaseEvent::PrepareQuorum, PhaseEvent::CommitQuorum];
+
+    // 转移表之外的每个组合都被拒绝，例如同一实例内已提交后再接受PrePrepare
+    #[test]
+    fn every_phase_event_pair_follows_the_table() {
+        let allowed = [
+            (Phase::Idle, PhaseEvent::PrePrepare, Phase::PrePrepared),
+            (Phase::PrePrepared, PhaseEvent::PrepareQuorum, Phase::Prepared),
+            (Phase::Prepared, PhaseEvent::CommitQuorum, Phase::Committed),
+        ];
+        for from in PHASES.iter().copied() {
+            for event in EVENTS.iter().copied() {
+                let expected = allowed.iter()
+                    .find(|(f, e, _)| (*f, *e) == (from, event))
+                    .map(|(_, _, to)| *to)
+                    .ok_or(IllegalTransition { from, event });
+                assert_eq!(transition(from, event), expected, "{:?} + {:?}", from, event);
+            }
+        }
+        assert_eq!(
+            transition(Phase::Committed, PhaseEvent::PrePrepare),
+            Err(IllegalTransition { from: Phase::Committed, event: PhaseEvent::PrePrepare })
+        );
+    }
+}
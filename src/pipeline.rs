@@ -0,0 +1,200 @@
+// src/pipeline.rs
+
+// 入站消息流水线：解包 → 验签 → 分发，各阶段是独立的任务，之间用有界队列连接。
+// 解包阶段展开Bundle，按发送者把消息分给固定数量的验签任务；同一发送者的消息总由同一个
+// 验签任务按到达顺序处理，不同发送者的签名并行校验。共识任务只接收带验签结论的事件，
+// 单线程的共识循环不再为不可信的输入做椭圆曲线运算
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use log::debug;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use crate::config::{PIPELINE_QUEUE_SIZE, PIPELINE_WORKERS};
+use crate::crypto::PublicKey;
+use crate::genesis;
+use crate::message::PBFTMessage;
+use crate::metrics;
+
+// 验签任务与共识任务共享的公钥表，由共识任务在得知公钥时更新
+pub type KeyTable = Arc<RwLock<HashMap<usize, PublicKey>>>;
+
+pub enum Inbound {
+    // 未签名的消息，或验签时还没有发送者公钥的签名消息，由共识任务自行处理
+    Message(PBFTMessage),
+    // 签名消息及验签所用的公钥和结论；共识任务中的公钥已经变化时重新验证
+    Checked { message: PBFTMessage, key: PublicKey, valid: bool },
+}
+
+// 消息声称的发送者，用于黑名单、限流和分配验签任务；客户端请求等没有发送者
+pub fn claimed_sender(msg: &PBFTMessage) -> Option<usize> {
+    match msg {
+        PBFTMessage::SignedMessage { sender_id, .. } | PBFTMessage::AuthenticatedMessage { sender_id, .. } => Some(*sender_id),
+        PBFTMessage::ByzantineVote { sender_id, .. } => Some(*sender_id),
+        PBFTMessage::HandshakeChallenge { node_id, .. } => Some(*node_id),
+        PBFTMessage::HandshakeResponse { node_id, .. } => Some(*node_id),
+        PBFTMessage::RelayConnect { node_id } => Some(*node_id),
+        PBFTMessage::Relay { from, .. } => Some(*from),
+        PBFTMessage::SnapshotRequest { node_id } => Some(*node_id),
+        PBFTMessage::ChunkRequest { node_id, .. } => Some(*node_id),
+        PBFTMessage::FetchRange { node_id, .. } => Some(*node_id),
+        PBFTMessage::FetchHeaders { node_id, .. } => Some(*node_id),
+        PBFTMessage::FetchPayload { node_id, .. } => Some(*node_id),
+        PBFTMessage::Payload { node_id, .. } => Some(*node_id),
+        PBFTMessage::FetchPrePrepare { node_id, .. } => Some(*node_id),
+        _ => None,
+    }
+}
+
+// 节点发送这些消息时总是经签名或认证包装（SignedMessage/AuthenticatedMessage），
+// 网络上不带包装送达的只可能是伪造的，共识任务直接丢弃
+pub fn requires_authentication(msg: &PBFTMessage) -> bool {
+    matches!(msg,
+        PBFTMessage::PrePrepare { .. }
+        | PBFTMessage::PrePrepareDigests { .. }
+        | PBFTMessage::Prepare { .. }
+        | PBFTMessage::PrepareCertificate { .. }
+        | PBFTMessage::Commit { .. }
+        | PBFTMessage::ViewChange { .. }
+        | PBFTMessage::NewView { .. }
+        | PBFTMessage::Checkpoint { .. }
+        | PBFTMessage::SnapshotOffer { .. }
+        | PBFTMessage::ByzantineVote { .. }
+        | PBFTMessage::Appeal { .. }
+        | PBFTMessage::Ping { .. }
+        | PBFTMessage::Pong { .. }
+        | PBFTMessage::Leave { .. }
+        | PBFTMessage::Maintenance { .. })
+}
+
+// 接管网络送来的原始消息，返回送往共识任务的事件队列
+pub fn spawn(receiver: Receiver<PBFTMessage>, keys: KeyTable, chain_id: String) -> Receiver<Inbound> {
+    let (output, events) = mpsc::channel(PIPELINE_QUEUE_SIZE);
+    let workers = (0..PIPELINE_WORKERS).map(|_| {
+        let (tx, rx) = mpsc::channel(PIPELINE_QUEUE_SIZE);
+        tokio::spawn(verify(rx, output.clone(), keys.clone(), chain_id.clone()));
+        tx
+    }).collect();
+    tokio::spawn(decode(receiver, workers));
+    events
+}
+
+async fn decode(mut receiver: Receiver<PBFTMessage>, workers: Vec<Sender<PBFTMessage>>) {
+    while let Some(msg) = receiver.recv().await {
+        // 逆序压栈，保证按发送顺序分发
+        let mut pending = vec![msg];
+        while let Some(msg) = pending.pop() {
+            if let PBFTMessage::Bundle { messages } = msg {
+                pending.extend(messages.into_iter().rev());
+                continue;
+            }
+            if msg.is_unknown() {
+                debug!("入站流水线跳过未知类型的消息");
+                metrics::inc_counter("messages_unknown_kind_total", 1);
+                continue;
+            }
+            let worker = claimed_sender(&msg).unwrap_or(0) % workers.len();
+            // 共识任务退出后流水线随之结束
+            if workers[worker].send(msg).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn verify(mut receiver: Receiver<PBFTMessage>, output: Sender<Inbound>, keys: KeyTable, chain_id: String) {
+    while let Some(msg) = receiver.recv().await {
+        let verdict = match &msg {
+            PBFTMessage::SignedMessage { message, signature, sender_id, .. } => {
+                let key = keys.read().unwrap().get(sender_id).copied();
+                key.map(|key| {
+                    let payload = genesis::message_payload(&chain_id, message);
+                    (key, key.verify(&payload, signature))
+                })
+            }
+            _ => None,
+        };
+        let event = match verdict {
+            Some((key, valid)) => {
+                metrics::inc_counter(if valid { "pipeline_signatures_verified_total" } else { "pipeline_signatures_rejected_total" }, 1);
+                Inbound::Checked { message: msg, key, valid }
+            }
+            None => Inbound::Message(msg),
+        };
+        if output.send(event).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::SigningKey;
+
+    fn signed(key: &SigningKey, sender_id: usize, sequence_number: u64) -> PBFTMessage {
+        let message = PBFTMessage::Commit { view: 0, sequence_number, digest: "d".to_string() };
+        let signature = key.sign(&genesis::message_payload("pipeline-test", &message));
+        PBFTMessage::SignedMessage { message: Box::new(message), signature, sender_id, trace: None }
+    }
+
+    fn sequence(event: &Inbound) -> (usize, u64, Option<bool>) {
+        let (message, valid) = match event {
+            Inbound::Message(message) => (message, None),
+            Inbound::Checked { message, valid, .. } => (message, Some(*valid)),
+        };
+        match message {
+            PBFTMessage::SignedMessage { message, sender_id, .. } => match **message {
+                PBFTMessage::Commit { sequence_number, .. } => (*sender_id, sequence_number, valid),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    // 展开Bundle后逐条验签；同一发送者保持顺序，没有公钥的消息原样交给共识任务
+    #[tokio::test]
+    async fn verifies_signatures_off_the_consensus_task() {
+        let keys: Vec<SigningKey> = (0..3).map(|_| SigningKey::generate()).collect();
+        let table: KeyTable = Arc::new(RwLock::new(keys.iter().enumerate().take(2).map(|(id, key)| (id, key.public_key())).collect()));
+        let (tx, rx) = mpsc::channel(16);
+        let mut events = spawn(rx, table, "pipeline-test".to_string());
+
+        let bundle = (1..=3).flat_map(|seq| vec![signed(&keys[0], 0, seq), signed(&keys[1], 1, seq)]).collect();
+        tx.send(PBFTMessage::Bundle { messages: bundle }).await.unwrap();
+        tx.send(signed(&keys[2], 0, 4)).await.unwrap(); // 冒充节点0
+        tx.send(signed(&keys[2], 2, 1)).await.unwrap(); // 公钥未知
+
+        let mut received = Vec::new();
+        for _ in 0..8 {
+            received.push(sequence(&events.recv().await.unwrap()));
+        }
+        let from = |sender: usize| received.iter().filter(|(id, _, _)| *id == sender).map(|(_, seq, valid)| (*seq, *valid)).collect::<Vec<_>>();
+        assert_eq!(from(0), vec![(1, Some(true)), (2, Some(true)), (3, Some(true)), (4, Some(false))]);
+        assert_eq!(from(1), vec![(1, Some(true)), (2, Some(true)), (3, Some(true))]);
+        assert_eq!(from(2), vec![(1, None)]);
+    }
+
+    // 新版本节点发来的未知类型（包括签名消息内部的）被跳过，同一Bundle中认识的消息照常处理，
+    // 也不会因为无法验签而被当作伪造签名
+    #[tokio::test]
+    async fn skips_unknown_message_kinds() {
+        let key = SigningKey::generate();
+        let table: KeyTable = Arc::new(RwLock::new(std::iter::once((0, key.public_key())).collect()));
+        let (tx, rx) = mpsc::channel(16);
+        let mut events = spawn(rx, table, "pipeline-test".to_string());
+
+        let known = serde_json::to_value(signed(&key, 0, 1)).unwrap();
+        let mut future = known.clone();
+        future["message"] = serde_json::json!({ "kind": "FutureVote", "view": 0, "weight": [1, 2] });
+        let bundle = serde_json::json!({
+            "kind": "Bundle",
+            "messages": [{ "kind": "FutureHint", "hint": "x" }, future, known],
+        });
+        let decoded: PBFTMessage = serde_json::from_value(bundle).unwrap();
+        let before = metrics::snapshot().get("messages_unknown_kind_total").copied().unwrap_or(0);
+        tx.send(decoded).await.unwrap();
+
+        assert_eq!(sequence(&events.recv().await.unwrap()), (0, 1, Some(true)));
+        assert!(metrics::snapshot()["messages_unknown_kind_total"] >= before + 2);
+        assert!(serde_json::from_value::<PBFTMessage>(serde_json::json!({ "kind": "FutureHint" })).unwrap().is_unknown());
+    }
+}
@@ -0,0 +1,312 @@
+// src/preflight.rs
+
+// 启动前的配置检查：交叉核对创世文件中的验证者集合与容错参数、签名私钥文件、监听地址、
+// 各JSON配置文件和数据目录的写权限。所有问题一次报告，每条附带修改建议，有任何问题时进程以非零状态退出，
+// 不会等到某个unwrap处panic才暴露第一个问题，改正后重启又遇到下一个
+use std::collections::HashSet;
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use crate::acl::{ClientConfig, CLIENTS_FILE};
+use crate::application;
+use crate::config::{N, F, RPC_BASE_PORT, LISTEN_ADDRESSES_ENV, FIREWALL_FILE, NETWORK_FAULTS_FILE, NODE_CONFIG_FILE};
+use crate::crypto::{PublicKey, SigningKey};
+use crate::firewall::Firewall;
+use crate::genesis::{Genesis, GENESIS_FILE};
+use crate::key_registry::{self, KEY_BUNDLE_FILE};
+use crate::network::{self, NetworkFaults};
+use crate::node::Role;
+use crate::quorum;
+use crate::reload::NodeConfig;
+use crate::rpc_auth::{RpcAuth, RPC_AUTH_FILE};
+use crate::vote_aggregation;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    InvalidArgument { flag: String, reason: String },
+    InvalidFile { file: String, reason: String },
+    Quorum(String),
+    ValidatorCount { found: usize },
+    ValidatorOutOfRange(usize),
+    DuplicateValidator(usize),
+    NotAValidator { node_id: usize, validators: Vec<usize> },
+    SigningPolicy(String),
+    Application(String),
+    KeyFile { path: String, reason: String },
+    KeyFilePermissions { path: String, mode: u32 },
+    KeyBundle(String),
+    ListenAddress { address: String, reason: String },
+    DataDirectory { path: String, reason: String },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::InvalidArgument { flag, reason } => write!(f, "参数{}无效: {}", flag, reason),
+            ConfigError::InvalidFile { file, reason } => write!(f, "无法解析{}: {}", file, reason),
+            ConfigError::Quorum(reason) => write!(f, "容错参数N={}、F={}不可用: {}", N, F, reason),
+            ConfigError::ValidatorCount { found } => write!(f, "验证者集合有{}个节点，与N={}不一致", found, N),
+            ConfigError::ValidatorOutOfRange(id) => write!(f, "验证者ID {}超出范围0..{}", id, N),
+            ConfigError::DuplicateValidator(id) => write!(f, "验证者ID {}重复", id),
+            ConfigError::NotAValidator { node_id, validators } => write!(f, "节点{}不在验证者集合{:?}中，无法以验证者身份启动", node_id, validators),
+            ConfigError::SigningPolicy(reason) => write!(f, "签名策略无效: {}", reason),
+            ConfigError::Application(reason) => write!(f, "复制应用无效: {}", reason),
+            ConfigError::KeyFile { path, reason } => write!(f, "签名私钥文件{}不可用: {}", path, reason),
+            ConfigError::KeyFilePermissions { path, mode } => write!(f, "签名私钥文件{}的权限{:o}允许其他用户访问", path, mode),
+            ConfigError::KeyBundle(reason) => write!(f, "验证者公钥清单{}不可用: {}", KEY_BUNDLE_FILE, reason),
+            ConfigError::ListenAddress { address, reason } => write!(f, "监听地址'{}'无效: {}", address, reason),
+            ConfigError::DataDirectory { path, reason } => write!(f, "数据目录{}不可写: {}", path, reason),
+        }
+    }
+}
+
+impl ConfigError {
+    // 给运维人员的修改建议
+    pub fn suggestion(&self) -> String {
+        match self {
+            ConfigError::InvalidArgument { .. } => "检查命令行参数的拼写和取值，参见readme中的启动示例".to_string(),
+            ConfigError::InvalidFile { file, .. } => format!("修正{}的JSON格式和字段，或删除该文件使用默认配置", file),
+            ConfigError::Quorum(_) => "修改config.rs中的F，使N = 3F + 1后重新编译".to_string(),
+            ConfigError::ValidatorCount { .. } => format!("在{}的validators中列出{}个验证者ID，或删除该字段使用0..{}", GENESIS_FILE, N, N),
+            ConfigError::ValidatorOutOfRange(_) | ConfigError::DuplicateValidator(_) => format!("{}的validators应为0..{}中互不相同的ID", GENESIS_FILE, N),
+            ConfigError::NotAValidator { .. } => format!("以full、archive或observer角色启动，或先把该节点加入{}的validators", GENESIS_FILE),
+            ConfigError::SigningPolicy(_) => format!("修改{}的signing_policy，消息类型名区分大小写", GENESIS_FILE),
+            ConfigError::Application(_) => format!("把{}的application改为编译进节点程序的应用，或启用对应的cargo特性重新编译", GENESIS_FILE),
+            ConfigError::KeyFile { .. } => "确认--key-file的路径和所在目录存在，文件内容应为32字节私钥的十六进制编码".to_string(),
+            ConfigError::KeyFilePermissions { path, .. } => format!("执行 chmod 600 {}", path),
+            ConfigError::KeyBundle(_) => format!("用与{}的operator_key对应的运维私钥执行 pbft-blockchain keys sign 重新签发清单，节点以清单中公钥对应的私钥文件（--key-file）启动", GENESIS_FILE),
+            ConfigError::ListenAddress { .. } => format!("{}和{}的advertised_addresses中的地址应为host:port，端口在1..65535之间且互不重复", LISTEN_ADDRESSES_ENV, NODE_CONFIG_FILE),
+            ConfigError::DataDirectory { .. } => "在当前用户有写权限的目录中启动节点，节点的状态、区块和日志文件都写在这里".to_string(),
+        }
+    }
+}
+
+// 运行全部检查，通过时返回创世配置
+pub fn check(node_id: usize, role: Role, key_file: Option<&str>) -> Result<Genesis, Vec<ConfigError>> {
+    let mut errors = Vec::new();
+    let genesis = match Genesis::load() {
+        Ok(genesis) => {
+            errors.extend(check_genesis(node_id, role, &genesis));
+            errors.extend(check_key_bundle(node_id, &genesis, key_file));
+            Some(genesis)
+        }
+        Err(reason) => {
+            errors.push(ConfigError::InvalidFile { file: GENESIS_FILE.to_string(), reason });
+            None
+        }
+    };
+    errors.extend(check_config_files());
+    if let Some(path) = key_file {
+        errors.extend(check_key_file(path));
+    }
+    if std::env::var(LISTEN_ADDRESSES_ENV).is_err() && node_id > (u16::MAX - RPC_BASE_PORT) as usize {
+        errors.push(ConfigError::InvalidArgument {
+            flag: "节点ID".to_string(),
+            reason: format!("RPC端口{}+{}超过65535", RPC_BASE_PORT, node_id),
+        });
+    } else {
+        errors.extend(check_listen_addresses(&network::listen_addresses(node_id)));
+    }
+    errors.extend(check_data_directory(Path::new("."), node_id));
+    match genesis {
+        Some(genesis) if errors.is_empty() => Ok(genesis),
+        _ => Err(errors),
+    }
+}
+
+// 打印所有问题和建议
+pub fn report(errors: &[ConfigError]) {
+    eprintln!("配置检查发现{}个问题:", errors.len());
+    for error in errors {
+        eprintln!("  - {}", error);
+        eprintln!("    建议: {}", error.suggestion());
+    }
+}
+
+fn check_genesis(node_id: usize, role: Role, genesis: &Genesis) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    if let Err(reason) = quorum::check(N as u64, F as u64) {
+        errors.push(ConfigError::Quorum(reason));
+    }
+    if genesis.validators.len() != N {
+        errors.push(ConfigError::ValidatorCount { found: genesis.validators.len() });
+    }
+    let mut seen = HashSet::new();
+    for id in &genesis.validators {
+        if *id >= N {
+            errors.push(ConfigError::ValidatorOutOfRange(*id));
+        } else if !seen.insert(*id) {
+            errors.push(ConfigError::DuplicateValidator(*id));
+        }
+    }
+    if role == Role::Validator && !genesis.validators.contains(&node_id) {
+        errors.push(ConfigError::NotAValidator { node_id, validators: genesis.validators.clone() });
+    }
+    if let Err(reason) = genesis.signing_policy.validate().and_then(|()| vote_aggregation::check(&genesis.signing_policy)) {
+        errors.push(ConfigError::SigningPolicy(reason));
+    }
+    if let Some(Err(reason)) = genesis.application.as_deref().map(application::create) {
+        errors.push(ConfigError::Application(reason));
+    }
+    errors
+}
+
+// 创世配置了operator_key时，清单必须由运维私钥签发；清单中登记了本节点的公钥时，本节点的私钥必须与之对应
+fn check_key_bundle(node_id: usize, genesis: &Genesis, key_file: Option<&str>) -> Vec<ConfigError> {
+    let keys = match key_registry::load(genesis) {
+        Ok(Some(keys)) => keys,
+        Ok(None) => return Vec::new(),
+        Err(reason) => return vec![ConfigError::KeyBundle(reason)],
+    };
+    let listed = match keys.get(&node_id) {
+        Some(listed) => listed,
+        None => return Vec::new(),
+    };
+    let reason = match key_file.filter(|path| Path::new(path).exists()).map(SigningKey::load_or_generate) {
+        None => format!("清单中登记了节点{}的公钥，须以--key-file指定对应的已有私钥文件启动", node_id),
+        Some(Ok(key)) if key.public_key() == *listed => return Vec::new(),
+        Some(Ok(key)) => format!("节点{}的私钥对应公钥{}，清单中登记的是{}", node_id, key.public_key().to_hex(), listed.to_hex()),
+        // 私钥文件本身的问题由check_key_file报告
+        Some(Err(_)) => return Vec::new(),
+    };
+    vec![ConfigError::KeyBundle(reason)]
+}
+
+// 读取并解析可选的配置文件，文件不存在时返回None
+fn parse_file<T: DeserializeOwned>(file: &str) -> Result<Option<T>, ConfigError> {
+    match std::fs::read_to_string(file) {
+        Ok(data) => serde_json::from_str(&data).map(Some).map_err(|e| e.to_string()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }.map_err(|reason| ConfigError::InvalidFile { file: file.to_string(), reason })
+}
+
+fn check_config_files() -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    errors.extend(parse_file::<Firewall>(FIREWALL_FILE).err());
+    errors.extend(parse_file::<RpcAuth>(RPC_AUTH_FILE).err());
+    errors.extend(parse_file::<NetworkFaults>(NETWORK_FAULTS_FILE).err());
+    match NodeConfig::load() {
+        Ok(config) => errors.extend(check_listen_addresses(&config.advertised_addresses)),
+        Err(reason) => errors.push(ConfigError::InvalidFile { file: NODE_CONFIG_FILE.to_string(), reason }),
+    }
+    match parse_file::<Vec<ClientConfig>>(CLIENTS_FILE) {
+        Ok(clients) => {
+            for client in clients.unwrap_or_default() {
+                if let Err(reason) = PublicKey::from_hex(&client.public_key) {
+                    errors.push(ConfigError::InvalidFile {
+                        file: CLIENTS_FILE.to_string(),
+                        reason: format!("客户端{}的公钥无效: {}", client.client_id, reason),
+                    });
+                }
+            }
+        }
+        Err(error) => errors.push(error),
+    }
+    errors
+}
+
+// 已存在的私钥文件必须能解析且只有所有者可读；不存在时节点会生成新私钥，所在目录必须存在
+fn check_key_file(path: &str) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let error = |reason: String| ConfigError::KeyFile { path: path.to_string(), reason };
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            if let Err(reason) = SigningKey::load_or_generate(path) {
+                errors.push(error(reason));
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = metadata.permissions().mode() & 0o777;
+                if mode & 0o077 != 0 {
+                    errors.push(ConfigError::KeyFilePermissions { path: path.to_string(), mode });
+                }
+            }
+            #[cfg(not(unix))]
+            let _ = metadata;
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let directory = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            if !directory.is_dir() {
+                errors.push(error(format!("文件不存在，所在目录{}也不存在，无法生成新私钥", directory.display())));
+            }
+        }
+        Err(e) => errors.push(error(e.to_string())),
+    }
+    errors
+}
+
+fn check_listen_addresses(addresses: &[String]) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+    for address in addresses {
+        let error = |reason: &str| ConfigError::ListenAddress { address: address.clone(), reason: reason.to_string() };
+        match address.rsplit_once(':') {
+            None => errors.push(error("缺少端口")),
+            Some((host, _)) if host.is_empty() || host == "[]" => errors.push(error("缺少主机名")),
+            Some((_, port)) => match port.parse::<u16>() {
+                Ok(0) => errors.push(error("端口0由系统随机分配，其他节点无法拨号")),
+                Ok(_) if !seen.insert(address.as_str()) => errors.push(error("地址重复")),
+                Ok(_) => {}
+                Err(_) => errors.push(error("端口不是1..65535之间的整数")),
+            },
+        }
+    }
+    errors
+}
+
+// 节点的状态、区块和日志文件写在当前目录，启动前先试写一个临时文件
+fn check_data_directory(directory: &Path, node_id: usize) -> Vec<ConfigError> {
+    let probe = directory.join(format!(".node_{}_preflight", node_id));
+    let written = std::fs::write(&probe, b"").and_then(|()| std::fs::remove_file(&probe));
+    match written {
+        Ok(()) => Vec::new(),
+        Err(e) => vec![ConfigError::DataDirectory { path: directory.display().to_string(), reason: e.to_string() }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing_policy::SigningPolicy;
+
+    // 一次检查报告所有问题，而不是停在第一个
+    #[test]
+    fn reports_every_problem_with_suggestions() {
+        let valid = Genesis { chain_id: "preflight-test".to_string(), ..Genesis::default() };
+        let mut genesis = valid.clone();
+        genesis.validators = vec![0, 1, 1, 9, 2];
+        genesis.signing_policy = serde_json::from_str::<SigningPolicy>(r#"{"kinds": {"Preprare": "mac"}}"#).unwrap();
+        genesis.application = Some("chess".to_string());
+        let errors = check_genesis(3, Role::Validator, &genesis);
+        assert_eq!(errors[..4], [
+            ConfigError::ValidatorCount { found: 5 },
+            ConfigError::DuplicateValidator(1),
+            ConfigError::ValidatorOutOfRange(9),
+            ConfigError::NotAValidator { node_id: 3, validators: vec![0, 1, 1, 9, 2] },
+        ]);
+        assert!(matches!(errors[4], ConfigError::SigningPolicy(_)));
+        assert!(matches!(errors[5], ConfigError::Application(_)));
+        assert!(check_genesis(3, Role::Validator, &valid).is_empty());
+
+        let addresses: Vec<String> = ["127.0.0.1:9000", "[::1]:9000", "127.0.0.1:9000", "localhost", ":9001", "host:0", "host:70000"]
+            .iter().map(|s| s.to_string()).collect();
+        let rejected: Vec<String> = check_listen_addresses(&addresses).into_iter().map(|error| match error {
+            ConfigError::ListenAddress { address, .. } => address,
+            other => panic!("意外的错误: {}", other),
+        }).collect();
+        assert_eq!(rejected, addresses[2..]);
+
+        let directory = std::env::temp_dir().join(format!("pbft-preflight-{}-{}", std::process::id(), rand::random::<u32>()));
+        std::fs::create_dir(&directory).unwrap();
+        assert!(check_data_directory(&directory, 0).is_empty());
+        let key = directory.join("node.key");
+        let key = key.to_str().unwrap();
+        std::fs::write(key, "not hex").unwrap();
+        let errors = check_key_file(key);
+        assert!(matches!(&errors[0], ConfigError::KeyFile { .. }));
+        assert!(errors.iter().all(|error| !error.suggestion().is_empty()));
+        assert!(check_key_file(directory.join("missing/node.key").to_str().unwrap()).len() == 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
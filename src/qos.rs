@@ -0,0 +1,225 @@
+// src/qos.rs
+
+use std::collections::VecDeque;
+use tokio::time::Instant;
+use serde::{Serialize, Deserialize};
+use crate::message::Transaction;
+use crate::config::{
+    HIGH_PRIORITY_BATCH_SHARE, HIGH_PRIORITY_RATE_LIMIT, NORMAL_PRIORITY_RATE_LIMIT, BULK_PRIORITY_RATE_LIMIT,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Bulk,
+}
+
+impl Priority {
+    fn index(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Bulk => 2,
+        }
+    }
+}
+
+// 令牌桶限流器，容量等于每秒速率
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64) -> Self {
+        TokenBucket { rate, tokens: rate, last_refill: Instant::now() }
+    }
+
+    // 调整速率，已积累的令牌不超过新的容量
+    pub fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+        self.tokens = self.tokens.min(rate);
+    }
+
+    pub fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// 队列中的交易及其过期时间（Unix毫秒）
+type Queued = (Transaction, Option<i64>);
+
+pub struct QosScheduler {
+    queues: [VecDeque<Queued>; 3],
+    limiters: [TokenBucket; 3],
+}
+
+impl QosScheduler {
+    pub fn new() -> Self {
+        QosScheduler {
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            limiters: [
+                TokenBucket::new(HIGH_PRIORITY_RATE_LIMIT),
+                TokenBucket::new(NORMAL_PRIORITY_RATE_LIMIT),
+                TokenBucket::new(BULK_PRIORITY_RATE_LIMIT),
+            ],
+        }
+    }
+
+    // 按优先级类别限流，超限的请求被拒绝
+    pub fn enqueue(&mut self, transaction: Transaction, priority: Priority, expires_at: Option<i64>) -> bool {
+        if !self.limiters[priority.index()].try_acquire() {
+            return false;
+        }
+        self.queues[priority.index()].push_back((transaction, expires_at));
+        true
+    }
+
+    // 删除到期的交易并返回它们
+    pub fn remove_expired(&mut self, now_ms: i64) -> Vec<Transaction> {
+        let mut expired = Vec::new();
+        for queue in self.queues.iter_mut() {
+            let (live, dead): (VecDeque<Queued>, VecDeque<Queued>) = queue.drain(..)
+                .partition(|(_, expires_at)| expires_at.map_or(true, |at| at > now_ms));
+            *queue = live;
+            expired.extend(dead.into_iter().map(|(transaction, _)| transaction));
+        }
+        expired
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        for queue in self.queues.iter_mut() {
+            queue.clear();
+        }
+    }
+
+    // 先为高优先级请求取出预留份额，再按优先级顺序填满剩余位置
+    pub fn next_batch(&mut self, batch_size: usize) -> Vec<Transaction> {
+        let reserved = ((batch_size as f64) * HIGH_PRIORITY_BATCH_SHARE).ceil() as usize;
+        let mut batch = Vec::with_capacity(batch_size);
+
+        Self::take(&mut self.queues[Priority::High.index()], reserved.min(batch_size), &mut batch);
+        for priority in [Priority::Normal, Priority::Bulk, Priority::High] {
+            let remaining = batch_size - batch.len();
+            Self::take(&mut self.queues[priority.index()], remaining, &mut batch);
+        }
+        batch
+    }
+
+    fn take(queue: &mut VecDeque<Queued>, count: usize, batch: &mut Vec<Transaction>) {
+        let count = count.min(queue.len());
+        batch.extend(queue.drain(..count).map(|(transaction, _)| transaction));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::execution::ExecutionStatus;
+    use crate::message::{PBFTMessage, ReplyOutcome};
+    use crate::network;
+    use crate::testing::TestCluster;
+
+    fn transaction(operation: &str) -> Transaction {
+        Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None }
+    }
+
+    #[test]
+    fn expired_transactions_leave_the_queue() {
+        let mut scheduler = QosScheduler::new();
+        scheduler.enqueue(transaction("SET a 1"), Priority::High, Some(100));
+        scheduler.enqueue(transaction("SET b 2"), Priority::Normal, None);
+        scheduler.enqueue(transaction("SET c 3"), Priority::Bulk, Some(200));
+
+        let expired = scheduler.remove_expired(150);
+        assert_eq!(expired, vec![transaction("SET a 1")]);
+        let batch = scheduler.next_batch(10);
+        assert_eq!(batch, vec![transaction("SET b 2"), transaction("SET c 3")]);
+    }
+
+    fn request(operation: &str, expires_at: i64) -> PBFTMessage {
+        PBFTMessage::Request {
+            operation: operation.to_string(),
+            priority: Priority::Normal,
+            client_id: Some("alice".to_string()),
+            expires_at: Some(expires_at),
+            session: None,
+            timestamp: None,
+        }
+    }
+
+    async fn next_reply(replies: &mut mpsc::Receiver<PBFTMessage>, operation: &str) -> ReplyOutcome {
+        loop {
+            match tokio::time::timeout(Duration::from_secs(10), replies.recv()).await {
+                Ok(Some(PBFTMessage::Reply { operation: op, outcome, .. })) if op == operation => return outcome,
+                Ok(Some(_)) => continue,
+                _ => panic!("未收到'{}'的答复", operation),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_hear_about_expired_and_executed_requests() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            let (sender, mut replies) = mpsc::channel(100);
+            network::register_client("alice", sender);
+            let now = chrono::Local::now().timestamp_millis();
+
+            cluster.submit_request(request("SET stale 1", now - 1)).await;
+            assert_eq!(next_reply(&mut replies, "SET stale 1").await, ReplyOutcome::Expired);
+
+            cluster.submit_request(request("SET fresh 1", now + 60_000)).await;
+            assert_eq!(next_reply(&mut replies, "SET fresh 1").await, ReplyOutcome::Executed(ExecutionStatus::Success(None)));
+            assert!((0..N).all(|id| cluster.committed_view(id, "SET stale 1").is_none()));
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn requests_expire_while_primary_is_down() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut strategies = vec![Strategy::Honest; N];
+            strategies[0] = Strategy::Silent;
+            let cluster = TestCluster::start(&strategies, Duration::from_millis(300)).await;
+            let (sender, mut replies) = mpsc::channel(100);
+            network::register_client("alice", sender);
+
+            // 请求在新主节点产生之前过期，不再被提议
+            let expires_at = chrono::Local::now().timestamp_millis() + 100;
+            cluster.submit_request(request("SET k v", expires_at)).await;
+            assert_eq!(next_reply(&mut replies, "SET k v").await, ReplyOutcome::Expired);
+
+            cluster.submit("SET after 1").await;
+            let committed = cluster.wait_until(Duration::from_secs(30), |c| {
+                (1..N).all(|id| c.committed_view(id, "SET after 1").is_some())
+            }).await;
+            assert!(committed);
+            assert!((1..N).all(|id| cluster.committed_view(id, "SET k v").is_none()), "过期请求仍被提交");
+        }).await;
+    }
+}
@@ -0,0 +1,120 @@
+// src/quorum.rs
+
+// 法定人数的唯一来源。阈值按投票权重计算，权重全为1时即为节点数：
+// 总权重为total、其中最多faulty可能作恶时，
+// - 法定人数 ⌈(total+faulty+1)/2⌉：任意两个法定人数至少重叠faulty+1，其中必有诚实的投票
+// - 弱法定人数 faulty+1：其中至少有一个诚实的投票
+use crate::config::{F, N};
+
+pub const PREPARE_QUORUM: usize = quorum(N as u64, F as u64) as usize - 1; // PrePrepare已代表主节点的一票
+pub const COMMIT_QUORUM: usize = quorum(N as u64, F as u64) as usize;
+pub const VIEW_CHANGE_QUORUM: usize = quorum(N as u64, F as u64) as usize; // 新主节点发送NewView所需的ViewChange
+pub const BLACKLIST_QUORUM: usize = quorum(N as u64, F as u64) as usize; // 拉黑一个节点所需的拜占庭投票
+pub const WEAK_QUORUM: usize = weak_quorum(F as u64) as usize; // 视图同步、分叉检测、快照清单
+
+// 总权重下最多能容忍的作恶权重：total ≥ 3*faulty + 1
+pub const fn max_faulty(total: u64) -> u64 {
+    total.saturating_sub(1) / 3
+}
+
+pub const fn quorum(total: u64, faulty: u64) -> u64 {
+    (total + faulty + 2) / 2
+}
+
+pub const fn weak_quorum(faulty: u64) -> u64 {
+    faulty + 1
+}
+
+// 检查给定参数下的法定人数同时满足安全性和活性
+pub fn check(total: u64, faulty: u64) -> Result<(), String> {
+    if faulty > max_faulty(total) {
+        return Err(format!("总权重{}不足以容忍{}的作恶权重，至少需要{}", total, faulty, 3 * faulty + 1));
+    }
+    let quorum = quorum(total, faulty);
+    if quorum > total - faulty {
+        return Err(format!("法定人数{}超过诚实权重{}，无法保证活性", quorum, total - faulty));
+    }
+    if 2 * quorum < total + weak_quorum(faulty) {
+        return Err(format!("两个法定人数{}的交集少于{}，无法保证安全性", quorum, weak_quorum(faulty)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 一组投票者的总权重，未列出的节点权重为0
+    fn voting_power(weights: &[u64], voters: impl IntoIterator<Item = usize>) -> u64 {
+        voters.into_iter().map(|id| weights.get(id).copied().unwrap_or(0)).sum()
+    }
+
+    #[test]
+    fn classic_cluster_sizes() {
+        for f in 0..20 {
+            let n = 3 * f + 1;
+            assert_eq!(max_faulty(n), f);
+            assert_eq!(quorum(n, f), 2 * f + 1);
+            assert_eq!(weak_quorum(f), f + 1);
+        }
+        assert_eq!(PREPARE_QUORUM, 2 * F);
+        assert_eq!(COMMIT_QUORUM, 2 * F + 1);
+        assert_eq!(VIEW_CHANGE_QUORUM, 2 * F + 1);
+        assert_eq!(BLACKLIST_QUORUM, 2 * F + 1);
+        assert_eq!(WEAK_QUORUM, F + 1);
+    }
+
+    #[test]
+    fn max_faulty_boundaries() {
+        assert_eq!(max_faulty(0), 0);
+        assert_eq!(max_faulty(1), 0);
+        assert_eq!(max_faulty(3), 0);
+        assert_eq!(max_faulty(4), 1);
+        assert_eq!(max_faulty(6), 1);
+        assert_eq!(max_faulty(7), 2);
+        for total in 1..200 {
+            let f = max_faulty(total);
+            assert!(total > 3 * f);
+            assert!(total <= 3 * (f + 1));
+        }
+    }
+
+    // 对所有合法参数穷举：法定人数可由诚实权重凑齐，任意两个法定人数的交集包含诚实的投票，且阈值是满足条件的最小值
+    #[test]
+    fn quorums_intersect_and_stay_live() {
+        for total in 1..200 {
+            for faulty in 0..=max_faulty(total) {
+                let q = quorum(total, faulty);
+                assert!(check(total, faulty).is_ok(), "total={} faulty={}", total, faulty);
+                assert!(q <= total - faulty);
+                assert!(2 * q - total > faulty);
+                assert!(2 * (q - 1) < total + faulty + 1, "total={} faulty={}的法定人数不是最小值", total, faulty);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_faults() {
+        assert!(check(3, 1).is_err());
+        assert!(check(6, 2).is_err());
+        assert!(check(7, 2).is_ok());
+        assert!(check(0, 0).is_err());
+    }
+
+    #[test]
+    fn weighted_votes() {
+        // 权重不均时按权重计票：总权重10，最多容忍3
+        let weights = [4, 3, 2, 1];
+        let total = voting_power(&weights, 0..weights.len());
+        assert_eq!(total, 10);
+        let faulty = max_faulty(total);
+        assert_eq!(faulty, 3);
+        assert_eq!(quorum(total, faulty), 7);
+
+        assert!(voting_power(&weights, [0, 1]) >= quorum(total, faulty));
+        assert!(voting_power(&weights, [1, 2, 3]) < quorum(total, faulty));
+        assert!(voting_power(&weights, [1, 3]) >= weak_quorum(faulty));
+        assert!(voting_power(&weights, [2, 3]) < weak_quorum(faulty));
+        assert_eq!(voting_power(&weights, [0, 9]), 4);
+    }
+}
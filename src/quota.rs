@@ -0,0 +1,181 @@
+// src/quota.rs
+
+// 单个操作的执行配额：gas只按操作本身的字节数计费，APPEND和不断增长的登记记录却能让一个值无限变大，
+// 单个病态的交易就可能让所有副本同时耗尽内存。每个操作在Sandbox中执行，记录它修改的键数、
+// 写入的字节数和写入的最大值，任一项超出配额时撤销该操作的全部修改，操作以QuotaExceeded失败。
+// 配额只取决于复制状态和操作内容，各副本对同一操作的判定一致
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use crate::config::{OPERATION_WRITE_KEYS, OPERATION_WRITE_BYTES, MAX_VALUE_SIZE};
+use crate::state_view::Diff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub write_keys: usize, // 单个操作最多修改的不同键数
+    pub write_bytes: usize, // 单个操作最多写入的字节数（键和新值）
+    pub value_size: usize, // 状态中单个值的最大字节数
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Quota { write_keys: OPERATION_WRITE_KEYS, write_bytes: OPERATION_WRITE_BYTES, value_size: MAX_VALUE_SIZE }
+    }
+}
+
+impl Quota {
+    // 执行引擎自己的写入（信标、到期的定时交易、会话记录）不受配额限制
+    pub fn unlimited() -> Self {
+        Quota { write_keys: usize::MAX, write_bytes: usize::MAX, value_size: usize::MAX }
+    }
+}
+
+// 操作执行期间的状态视图：读取直接访问状态，写入经insert/remove记入日志并计量
+pub struct Sandbox<'a> {
+    store: &'a mut BTreeMap<String, String>,
+    quota: Quota,
+    journal: Vec<(String, Option<String>)>, // 每次写入的键及写入前的值，按逆序恢复即可撤销
+    keys: usize,
+    bytes: usize,
+    exceeded: Option<String>, // 第一次超出配额的原因
+}
+
+impl Deref for Sandbox<'_> {
+    type Target = BTreeMap<String, String>;
+
+    fn deref(&self) -> &Self::Target {
+        self.store
+    }
+}
+
+impl<'a> Sandbox<'a> {
+    pub fn new(store: &'a mut BTreeMap<String, String>, quota: Quota) -> Self {
+        Sandbox { store, quota, journal: Vec::new(), keys: 0, bytes: 0, exceeded: None }
+    }
+
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    pub fn insert(&mut self, key: String, value: String) -> Option<String> {
+        self.charge(&key, value.len());
+        let previous = self.store.insert(key.clone(), value);
+        self.journal.push((key, previous.clone()));
+        previous
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.charge(key, 0);
+        let previous = self.store.remove(key)?;
+        self.journal.push((key.to_string(), Some(previous.clone())));
+        Some(previous)
+    }
+
+    fn charge(&mut self, key: &str, value_size: usize) {
+        if !self.journal.iter().any(|(written, _)| written == key) {
+            self.keys += 1;
+        }
+        self.bytes += key.len() + value_size;
+        if self.exceeded.is_some() {
+            return;
+        }
+        self.exceeded = if value_size > self.quota.value_size {
+            Some(format!("键{}的值{}字节，超过上限{}", key, value_size, self.quota.value_size))
+        } else if self.keys > self.quota.write_keys {
+            Some(format!("修改了{}个键，超过上限{}", self.keys, self.quota.write_keys))
+        } else if self.bytes > self.quota.write_bytes {
+            Some(format!("写入{}字节，超过上限{}", self.bytes, self.quota.write_bytes))
+        } else {
+            None
+        };
+    }
+
+    // 操作执行完毕：未超出配额时保留修改，并把每个键修改前的值记入diff（diff中已有的键保留更早的值）；
+    // 否则按逆序撤销全部修改并返回原因
+    pub fn finish(self, diff: &mut Diff) -> Result<(), String> {
+        let reason = match self.exceeded {
+            Some(reason) => reason,
+            None => {
+                for (key, previous) in self.journal {
+                    diff.entry(key).or_insert(previous);
+                }
+                return Ok(());
+            }
+        };
+        for (key, previous) in self.journal.into_iter().rev() {
+            match previous {
+                Some(value) => self.store.insert(key, value),
+                None => self.store.remove(&key),
+            };
+        }
+        Err(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::execution::{ExecutionEngine, ExecutionStatus};
+    use crate::message::Transaction;
+    use crate::testing::TestCluster;
+
+    #[test]
+    fn breaching_operations_leave_no_writes() {
+        let mut store = BTreeMap::new();
+        store.insert("a".to_string(), "old".to_string());
+        let quota = Quota { write_keys: 2, write_bytes: 100, value_size: 10 };
+
+        let mut sandbox = Sandbox::new(&mut store, quota);
+        sandbox.insert("a".to_string(), "new".to_string());
+        sandbox.remove("a");
+        sandbox.insert("b".to_string(), "x".to_string());
+        assert_eq!(sandbox.get("b").map(String::as_str), Some("x"));
+        let mut diff = Diff::new();
+        assert_eq!(sandbox.finish(&mut diff), Ok(()));
+        assert_eq!(store.get("a"), None);
+        // 差异记下每个键第一次修改前的值
+        assert_eq!(diff, Diff::from([("a".to_string(), Some("old".to_string())), ("b".to_string(), None)]));
+
+        let mut sandbox = Sandbox::new(&mut store, quota);
+        sandbox.insert("a".to_string(), "y".to_string());
+        sandbox.insert("b".to_string(), "y".to_string());
+        sandbox.insert("c".to_string(), "y".to_string());
+        assert!(sandbox.finish(&mut diff).unwrap_err().contains("3个键"));
+        assert_eq!(diff.len(), 2, "撤销的修改不应记入差异");
+        assert_eq!((store.get("a"), store.get("b").map(String::as_str), store.get("c")), (None, Some("x"), None));
+
+        let mut sandbox = Sandbox::new(&mut store, quota);
+        sandbox.insert("b".to_string(), "0123456789a".to_string());
+        assert!(sandbox.finish(&mut Diff::new()).is_err());
+        assert_eq!(store.get("b").map(String::as_str), Some("x"));
+
+        // 执行引擎在APPEND前检查结果的大小，超出时操作失败
+        let mut engine = ExecutionEngine::new();
+        let append = Transaction { operation: format!("APPEND big {}", "x".repeat(8000)), client_id: None, session: None, timestamp: None };
+        let results = engine.execute_block(1, &vec![append; MAX_VALUE_SIZE / 8000 + 1]);
+        assert!(matches!(results.last().unwrap().status, ExecutionStatus::QuotaExceeded(_)));
+        assert!(results.iter().rev().skip(1).all(|r| r.status == ExecutionStatus::Success(None)));
+        assert_eq!(engine.get("big").map(String::len), Some(MAX_VALUE_SIZE / 8000 * 8000));
+    }
+
+    // 不断APPEND同一个键：值达到上限后，后续的APPEND在每个副本上都失败且不修改状态
+    #[tokio::test]
+    async fn replicas_reject_unbounded_appends() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            for i in 0..MAX_VALUE_SIZE / 8000 + 2 {
+                cluster.submit(&format!("APPEND big {}{}", i % 10, "y".repeat(7999))).await;
+            }
+            cluster.submit("SET done yes").await;
+            let executed = cluster.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|id| c.executions[id].lock().unwrap().get("done").is_some())
+            }).await;
+            assert!(executed, "请求未执行");
+            for id in 0..N {
+                let execution = cluster.executions[id].lock().unwrap();
+                assert_eq!(execution.get("big").map(String::len), Some(MAX_VALUE_SIZE / 8000 * 8000), "节点{}", id);
+            }
+        }).await;
+    }
+}
@@ -0,0 +1,172 @@
+// src/reload.rs
+
+// 可热加载的非共识配置 node_config.json：节点每CONFIG_POLL_MS检查一次文件的修改时间，
+// 也可以发送SIGHUP立即重新加载，修改日志级别、RPC限制、trace导出地址、公告的拨号地址和延迟预算都不需要重启。
+// 链ID、验证者集合、哈希函数、签名策略等共识参数必须在所有节点上一致，只能在genesis.json或config.rs中修改；
+// 文件中出现这些字段、字段未知或取值无效时整次加载被拒绝，当前配置保持不变
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use log::{error, info, LevelFilter};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use tokio::sync::mpsc::{self, Sender};
+use crate::alerts::AlertConfig;
+use crate::config::{NODE_CONFIG_FILE, CONFIG_POLL_MS, LATENCY_BUDGET_MS};
+use crate::metrics;
+use crate::zones::Zones;
+
+// 出现在文件中即拒绝加载的共识参数
+pub const CONSENSUS_KEYS: &[&str] = &["chain_id", "validators", "hash_function", "features", "bridges", "signing_policy", "n", "f", "quorum"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    #[serde(default = "default_log_level")]
+    pub log_level: String, // error、warn、info、debug或trace
+    #[serde(default)]
+    pub rpc_max_connections: usize, // 同时打开的RPC连接数上限，0为不限
+    #[serde(default)]
+    pub rpc_requests_per_second: f64, // 每个RPC连接每秒的请求数上限，0为不限
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>, // trace的导出地址，缺省时使用OTEL_EXPORTER_OTLP_ENDPOINT
+    #[serde(default)]
+    pub advertised_addresses: Vec<String>, // 在节点目录中公告、供其他节点拨号的地址，为空时使用监听地址
+    #[serde(default)]
+    pub alerts: AlertConfig, // 严重事件的告警去向：webhook和邮件
+    #[serde(default)]
+    pub zones: Zones, // 验证者所在的故障域，广播时先发往其他域
+    #[serde(default = "default_latency_budget")]
+    pub latency_budget_ms: u64, // 共识实例从PrePrepare到执行完毕的延迟预算，超出时记录诊断，0为不检查
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_latency_budget() -> u64 {
+    LATENCY_BUDGET_MS
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            log_level: default_log_level(),
+            rpc_max_connections: 0,
+            rpc_requests_per_second: 0.0,
+            otlp_endpoint: None,
+            advertised_addresses: Vec::new(),
+            alerts: AlertConfig::default(),
+            zones: Zones::default(),
+            latency_budget_ms: default_latency_budget(),
+        }
+    }
+}
+
+impl NodeConfig {
+    pub fn parse(data: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
+        let consensus: Vec<&str> = CONSENSUS_KEYS.iter().copied().filter(|key| value.get(key).is_some()).collect();
+        if !consensus.is_empty() {
+            return Err(format!("共识参数{:?}不能热加载，只能在genesis.json或config.rs中修改", consensus));
+        }
+        let config: NodeConfig = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        config.level()?;
+        config.alerts.validate()?;
+        config.zones.validate()?;
+        if !(config.rpc_requests_per_second >= 0.0 && config.rpc_requests_per_second.is_finite()) {
+            return Err(format!("rpc_requests_per_second {}无效", config.rpc_requests_per_second));
+        }
+        Ok(config)
+    }
+
+    // 文件不存在时使用默认配置
+    pub fn load() -> Result<Self, String> {
+        match std::fs::read_to_string(NODE_CONFIG_FILE) {
+            Ok(data) => Self::parse(&data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(NodeConfig::default()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn level(&self) -> Result<LevelFilter, String> {
+        self.log_level.parse().map_err(|_| format!("日志级别'{}'无效", self.log_level))
+    }
+
+    // 进程级的设置（日志级别）在此生效，其余设置由RPC和节点各自读取
+    pub fn apply(&self) {
+        if let Ok(level) = self.level() {
+            log::set_max_level(level);
+        }
+    }
+}
+
+fn modified_time() -> Option<SystemTime> {
+    std::fs::metadata(NODE_CONFIG_FILE).and_then(|metadata| metadata.modified()).ok()
+}
+
+// 监视配置文件：文件修改或收到SIGHUP时重新加载，更新与RPC共享的配置，并把新配置发给节点
+pub async fn watch(node_id: usize, shared: Arc<Mutex<NodeConfig>>, node: Sender<NodeConfig>) {
+    let (hangup_sender, mut hangups) = mpsc::channel(1);
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = signal(SignalKind::hangup()).unwrap();
+        while hangup.recv().await.is_some() {
+            let _ = hangup_sender.send(()).await;
+        }
+    });
+    #[cfg(not(unix))]
+    drop(hangup_sender);
+
+    let mut modified = modified_time();
+    let mut poll = tokio::time::interval(Duration::from_millis(CONFIG_POLL_MS));
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {
+                let current = modified_time();
+                if current == modified {
+                    continue;
+                }
+                modified = current;
+            }
+            Some(()) = hangups.recv() => info!("节点{}收到SIGHUP，重新加载{}", node_id, NODE_CONFIG_FILE),
+        }
+        let config = match NodeConfig::load() {
+            Ok(config) => config,
+            Err(reason) => {
+                error!("节点{}拒绝重新加载{}，保持当前配置: {}", node_id, NODE_CONFIG_FILE, reason);
+                metrics::inc_counter("config_reload_rejected_total", 1);
+                continue;
+            }
+        };
+        if *shared.lock().unwrap() == config {
+            continue;
+        }
+        info!("节点{}重新加载{}: {:?}", node_id, NODE_CONFIG_FILE, config);
+        config.apply();
+        *shared.lock().unwrap() = config.clone();
+        metrics::inc_counter("config_reloads_total", 1);
+        if node.send(config).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_consensus_and_unknown_settings() {
+        let config = NodeConfig::parse(r#"{"log_level": "debug", "rpc_requests_per_second": 50}"#).unwrap();
+        assert_eq!(config.level(), Ok(LevelFilter::Debug));
+        assert_eq!((config.rpc_requests_per_second, config.rpc_max_connections), (50.0, 0));
+        assert_eq!(NodeConfig::parse("{}").unwrap(), NodeConfig::default());
+
+        let rejected = NodeConfig::parse(r#"{"log_level": "debug", "validators": [0, 1, 2, 3]}"#).unwrap_err();
+        assert!(rejected.contains("validators"), "{}", rejected);
+        assert!(NodeConfig::parse(r#"{"log_levle": "debug"}"#).is_err());
+        assert!(NodeConfig::parse(r#"{"log_level": "loud"}"#).is_err());
+        assert!(NodeConfig::parse(r#"{"rpc_requests_per_second": -1}"#).is_err());
+    }
+}
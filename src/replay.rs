@@ -0,0 +1,147 @@
+// src/replay.rs
+
+// 确定性重放：用全新的执行引擎从创世（或状态同步的快照）开始重新执行本地保存的全部区块，
+// 在每个检查点高度把得到的状态摘要与当时法定人数确认的摘要比对。
+// 不一致说明本地区块已损坏，或者执行结果依赖了区块以外的东西（非确定性执行）
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::chain::Chain;
+use crate::checkpoint::StateRoots;
+use crate::execution::ExecutionEngine;
+use crate::genesis::{Genesis, GENESIS_FILE};
+use crate::state_sync::StateSnapshot;
+use crate::storage;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub height: u64,
+    pub expected: String, // 检查点稳定时保存的摘要
+    pub actual: String,   // 重放得到的摘要
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub blocks: u64,           // 重新执行的区块数
+    pub checked: Vec<u64>,     // 比对过的检查点高度
+    pub mismatches: Vec<Mismatch>,
+}
+
+// 重放区块并在保存了摘要的高度比对；通过状态同步加入的链须提供起点的快照
+pub fn replay(chain: &Chain, genesis: &Genesis, snapshot: Option<StateSnapshot>, roots: &BTreeMap<u64, String>) -> Result<ReplayReport, String> {
+    let mut execution = ExecutionEngine::new();
+    execution.configure(genesis)?;
+    if let Some(base) = &chain.base {
+        let snapshot = snapshot.ok_or_else(|| format!("链从高度{}的快照开始，但找不到快照文件", base.height))?;
+        execution.restore(base.height, snapshot.store);
+    }
+
+    let mut report = ReplayReport::default();
+    for block in &chain.blocks {
+        execution.execute_block(block.header.height, &block.transactions);
+        report.blocks += 1;
+        let height = block.header.height;
+        if let Some(expected) = roots.get(&height) {
+            let actual = execution.state_digest(genesis.hasher());
+            report.checked.push(height);
+            if &actual != expected {
+                report.mismatches.push(Mismatch { height, expected: expected.clone(), actual });
+            }
+        }
+    }
+    Ok(report)
+}
+
+// 命令行入口：chain replay <节点ID>，在节点的工作目录中运行，发现不一致时以非零状态退出
+pub fn run(args: &[String]) -> i32 {
+    let node_id: usize = match args {
+        [command, node_id] if command == "replay" => match node_id.parse() {
+            Ok(node_id) => node_id,
+            Err(_) => {
+                eprintln!("节点ID'{}'无效", node_id);
+                return 2;
+            }
+        },
+        _ => {
+            eprintln!("用法: pbft-blockchain chain replay <节点ID>");
+            return 2;
+        }
+    };
+
+    let genesis = match Genesis::load() {
+        Ok(genesis) => genesis,
+        Err(reason) => {
+            eprintln!("无法解析{}: {}", GENESIS_FILE, reason);
+            return 2;
+        }
+    };
+    let mut chain = Chain::load(node_id);
+    chain.hash_function = genesis.hash_function;
+    storage::recover(Path::new("."), node_id, &mut chain);
+    let roots = StateRoots::load(node_id).roots;
+    let report = match replay(&chain, &genesis, StateSnapshot::load(node_id), &roots) {
+        Ok(report) => report,
+        Err(reason) => {
+            eprintln!("重放失败: {}", reason);
+            return 2;
+        }
+    };
+
+    for mismatch in &report.mismatches {
+        println!("高度{}: 保存的状态摘要{}，重放得到{}", mismatch.height, mismatch.expected, mismatch.actual);
+    }
+    println!("重放了节点{}的{}个区块，比对{}个检查点，{}个不一致", node_id, report.blocks, report.checked.len(), report.mismatches.len());
+    if report.mismatches.is_empty() { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::CommitCertificate;
+    use crate::message::Transaction;
+
+    fn genesis() -> Genesis {
+        Genesis { chain_id: "replay-test".to_string(), ..Genesis::default() }
+    }
+
+    // 生成height个区块，返回链和每5个区块记录一次的状态摘要
+    fn chain_with_roots(height: u64) -> (Chain, BTreeMap<u64, String>) {
+        let genesis = genesis();
+        let mut chain = Chain::default();
+        let mut execution = ExecutionEngine::new();
+        execution.configure(&genesis).unwrap();
+        let mut roots = BTreeMap::new();
+        for seq in 1..=height {
+            let transactions = vec![Transaction { operation: format!("SET k{} v{}", seq, seq), client_id: None, session: None, timestamp: None }];
+            let certificate = CommitCertificate { view: 0, sequence_number: seq, digest: String::new(), signatures: Vec::new() };
+            chain.append(0, seq, String::new(), transactions.clone(), certificate);
+            execution.execute_block(seq, &transactions);
+            if seq % 5 == 0 {
+                roots.insert(seq, execution.state_digest(genesis.hasher()));
+            }
+        }
+        (chain, roots)
+    }
+
+    #[test]
+    fn replay_matches_recorded_state_roots() {
+        let (chain, roots) = chain_with_roots(12);
+        let report = replay(&chain, &genesis(), None, &roots).unwrap();
+        assert_eq!(report.blocks, 12);
+        assert_eq!(report.checked, vec![5, 10]);
+        assert!(report.mismatches.is_empty());
+    }
+
+    // 篡改一个已提交区块的交易后，之后的检查点全部不一致，之前的不受影响
+    #[test]
+    fn replay_detects_corrupted_blocks() {
+        let (mut chain, roots) = chain_with_roots(12);
+        chain.blocks[6].transactions[0].operation = "SET k7 forged".to_string();
+        let report = replay(&chain, &genesis(), None, &roots).unwrap();
+        assert_eq!(report.mismatches.iter().map(|m| m.height).collect::<Vec<_>>(), vec![10]);
+        assert_eq!(report.mismatches[0].expected, roots[&10]);
+
+        let header = chain.blocks[0].header.clone();
+        chain.reset_to(header);
+        assert!(replay(&chain, &genesis(), None, &roots).is_err(), "从快照开始的链需要快照");
+    }
+}
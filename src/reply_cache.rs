@@ -0,0 +1,141 @@
+// src/reply_cache.rs
+
+// 答复缓存：按PBFT的要求，为每个客户端保存最近一次已执行请求的时间戳和结果。
+// 客户端超时后把请求重发给所有副本，任一副本都能立即返回缓存的答复，不必再走一轮共识；
+// 时间戳更早的请求已被更新的请求取代，直接丢弃
+use std::collections::{BTreeMap, HashMap};
+use crate::config::REPLY_CACHE_CLIENTS;
+use crate::execution::ExecutionStatus;
+use crate::message::Transaction;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup {
+    Miss,
+    Hit(Transaction, ExecutionStatus), // 同一请求已执行
+    Stale(u64),                        // 已执行过时间戳更新的请求，附该时间戳
+}
+
+struct Entry {
+    timestamp: u64,
+    transaction: Transaction,
+    status: ExecutionStatus,
+    touched: u64,
+}
+
+#[derive(Default)]
+pub struct ReplyCache {
+    entries: HashMap<String, Entry>,
+    recency: BTreeMap<u64, String>, // 最近更新次序，客户端过多时淘汰最久未更新的
+    counter: u64,
+}
+
+impl ReplyCache {
+    pub fn lookup(&self, client_id: &str, timestamp: u64) -> Lookup {
+        match self.entries.get(client_id) {
+            Some(entry) if entry.timestamp == timestamp => Lookup::Hit(entry.transaction.clone(), entry.status.clone()),
+            Some(entry) if entry.timestamp > timestamp => Lookup::Stale(entry.timestamp),
+            _ => Lookup::Miss,
+        }
+    }
+
+    // 记录带客户端ID和时间戳的交易的执行结果
+    pub fn record(&mut self, transaction: &Transaction, status: &ExecutionStatus) {
+        let (client_id, timestamp) = match (&transaction.client_id, transaction.timestamp) {
+            (Some(client_id), Some(timestamp)) => (client_id, timestamp),
+            _ => return,
+        };
+        if let Some(entry) = self.entries.get(client_id) {
+            if entry.timestamp > timestamp {
+                return;
+            }
+            self.recency.remove(&entry.touched);
+        }
+        self.counter += 1;
+        self.recency.insert(self.counter, client_id.clone());
+        self.entries.insert(client_id.clone(), Entry {
+            timestamp,
+            transaction: transaction.clone(),
+            status: status.clone(),
+            touched: self.counter,
+        });
+        while self.entries.len() > REPLY_CACHE_CLIENTS {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::message::{PBFTMessage, ReplyOutcome};
+    use crate::network;
+    use crate::qos::Priority;
+    use crate::testing::TestCluster;
+
+    fn transaction(client_id: &str, timestamp: u64) -> Transaction {
+        Transaction { operation: "SET k v".to_string(), client_id: Some(client_id.to_string()), session: None, timestamp: Some(timestamp) }
+    }
+
+    #[test]
+    fn keeps_latest_reply_per_client() {
+        let mut cache = ReplyCache::default();
+        assert_eq!(cache.lookup("alice", 1), Lookup::Miss);
+        cache.record(&transaction("alice", 2), &ExecutionStatus::Success(None));
+        cache.record(&transaction("alice", 1), &ExecutionStatus::OutOfGas);
+        assert_eq!(cache.lookup("alice", 2), Lookup::Hit(transaction("alice", 2), ExecutionStatus::Success(None)));
+        assert_eq!(cache.lookup("alice", 1), Lookup::Stale(2));
+        assert_eq!(cache.lookup("alice", 3), Lookup::Miss);
+
+        for id in 0..REPLY_CACHE_CLIENTS {
+            cache.record(&transaction(&format!("client{}", id), 1), &ExecutionStatus::Success(None));
+        }
+        assert_eq!(cache.lookup("alice", 2), Lookup::Miss);
+        assert_eq!(cache.entries.len(), REPLY_CACHE_CLIENTS);
+    }
+
+    #[tokio::test]
+    async fn retransmission_is_answered_from_cache() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            let (sender, mut replies) = mpsc::channel(100);
+            network::register_client("alice", sender);
+            let request = PBFTMessage::Request {
+                operation: "APPEND k a".to_string(),
+                priority: Priority::Normal,
+                client_id: Some("alice".to_string()),
+                expires_at: None,
+                session: None,
+                timestamp: Some(7),
+            };
+            cluster.submit_request(request.clone()).await;
+            let executed = cluster.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|id| c.executions[id].lock().unwrap().get("k").is_some())
+            }).await;
+            assert!(executed);
+            let height = cluster.chains[0].lock().unwrap().height();
+
+            // 重发的请求由每个副本直接答复，不产生新的区块，也不再次执行
+            while replies.try_recv().is_ok() {}
+            cluster.submit_request(request).await;
+            let mut answered = std::collections::HashSet::new();
+            while answered.len() < N {
+                match tokio::time::timeout(Duration::from_secs(5), replies.recv()).await {
+                    Ok(Some(PBFTMessage::Reply { node_id, timestamp: Some(7), outcome, .. })) => {
+                        assert_eq!(outcome, ReplyOutcome::Executed(ExecutionStatus::Success(None)));
+                        answered.insert(node_id);
+                    }
+                    Ok(Some(_)) => continue,
+                    _ => panic!("只收到{:?}的缓存答复", answered),
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            assert_eq!(cluster.chains[0].lock().unwrap().height(), height);
+            assert_eq!(cluster.executions[0].lock().unwrap().get("k"), Some(&"a".to_string()));
+        }).await;
+    }
+}
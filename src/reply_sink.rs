@@ -0,0 +1,175 @@
+// src/reply_sink.rs
+
+// 答复通道：节点通过ReplySink把Reply交给客户端，嵌入式部署可以换成自己的实现，把结果送进自己的系统。
+// 默认实现按客户端提交请求时选择的方式（Submit的reply_via）送达：
+//   stream   在提交请求的RPC连接上推送（缺省）
+//   callback 节点连接客户端给出的地址，写入一行JSON后断开
+//   poll     保存在节点的信箱中，客户端用PollReplies取走
+// 节点没有内置WebSocket服务，需要WebSocket推送的部署实现ReplySink即可
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use log::debug;
+use serde::{Serialize, Deserialize};
+use tokio::io::AsyncWriteExt;
+use crate::config::{REPLY_MAILBOX_CLIENTS, REPLY_MAILBOX_SIZE};
+use crate::message::PBFTMessage;
+use crate::{metrics, network};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "transport", rename_all = "lowercase")]
+pub enum Transport {
+    Stream,
+    Callback { address: String },
+    Poll,
+}
+
+impl Transport {
+    fn label(&self) -> &'static str {
+        match self {
+            Transport::Stream => "stream",
+            Transport::Callback { .. } => "callback",
+            Transport::Poll => "poll",
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    // 各客户端选择的答复方式，未登记的客户端使用stream
+    static ref ROUTES: Mutex<HashMap<String, Transport>> = Mutex::new(HashMap::new());
+    // 轮询方式的客户端尚未取走的答复
+    static ref MAILBOXES: Mutex<HashMap<String, VecDeque<PBFTMessage>>> = Mutex::new(HashMap::new());
+}
+
+pub trait ReplySink: Send {
+    // 把答复交给客户端；返回错误时答复被丢弃，客户端可重发请求
+    fn deliver(&self, client_id: &str, reply: PBFTMessage) -> Result<(), String>;
+}
+
+pub fn register(client_id: &str, transport: Transport) {
+    debug!("客户端{}的答复方式: {:?}", client_id, transport);
+    ROUTES.lock().unwrap().insert(client_id.to_string(), transport);
+}
+
+// 取走客户端信箱中的全部答复
+pub fn poll(client_id: &str) -> Vec<PBFTMessage> {
+    MAILBOXES.lock().unwrap().remove(client_id).map(Vec::from).unwrap_or_default()
+}
+
+// 默认实现：按客户端登记的方式送达
+pub struct TransportSink;
+
+impl ReplySink for TransportSink {
+    fn deliver(&self, client_id: &str, reply: PBFTMessage) -> Result<(), String> {
+        let transport = ROUTES.lock().unwrap().get(client_id).cloned().unwrap_or(Transport::Stream);
+        let result = match &transport {
+            Transport::Stream => network::send_reply(client_id, reply),
+            Transport::Callback { address } => {
+                tokio::spawn(call_back(address.clone(), reply));
+                Ok(())
+            }
+            Transport::Poll => deposit(client_id, reply),
+        };
+        if result.is_ok() {
+            metrics::inc_counter(&format!("replies_delivered_total{{transport=\"{}\"}}", transport.label()), 1);
+        }
+        result
+    }
+}
+
+fn deposit(client_id: &str, reply: PBFTMessage) -> Result<(), String> {
+    let mut mailboxes = MAILBOXES.lock().unwrap();
+    if !mailboxes.contains_key(client_id) && mailboxes.len() >= REPLY_MAILBOX_CLIENTS {
+        return Err(format!("信箱已满{}个客户端", REPLY_MAILBOX_CLIENTS));
+    }
+    let mailbox = mailboxes.entry(client_id.to_string()).or_default();
+    if mailbox.len() == REPLY_MAILBOX_SIZE {
+        mailbox.pop_front();
+        metrics::inc_counter("reply_mailbox_dropped_total", 1);
+    }
+    mailbox.push_back(reply);
+    Ok(())
+}
+
+// 连接客户端的回调地址，写入一行JSON
+async fn call_back(address: String, reply: PBFTMessage) {
+    let line = format!("{}\n", serde_json::to_string(&reply).unwrap());
+    let written = match network::dial(std::slice::from_ref(&address)).await {
+        Some((_, mut stream)) => stream.write_all(line.as_bytes()).await.map_err(|e| e.to_string()),
+        None => Err("无法连接".to_string()),
+    };
+    if let Err(e) = written {
+        debug!("向回调地址{}发送答复失败: {}", address, e);
+        metrics::inc_counter("reply_delivery_failures_total{transport=\"callback\"}", 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::TcpListener;
+    use crate::config::F;
+    use crate::qos::Priority;
+    use crate::testing::TestCluster;
+
+    fn reply(client_id: &str, operation: &str) -> PBFTMessage {
+        PBFTMessage::Reply {
+            node_id: 0,
+            client_id: client_id.to_string(),
+            operation: operation.to_string(),
+            session: None,
+            timestamp: None,
+            outcome: crate::message::ReplyOutcome::Expired,
+            height: None,
+        }
+    }
+
+    // 回调地址和信箱各自送达，信箱满时丢弃最早的答复；未连接的stream客户端收不到答复
+    #[tokio::test]
+    async fn delivers_over_the_chosen_transport() {
+        assert!(TransportSink.deliver("sink-unknown", reply("sink-unknown", "a")).is_err());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        register("sink-callback", Transport::Callback { address: listener.local_addr().unwrap().to_string() });
+        TransportSink.deliver("sink-callback", reply("sink-callback", "b")).unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let line = BufReader::new(socket).lines().next_line().await.unwrap().unwrap();
+        assert!(line.contains("\"operation\":\"b\""), "{}", line);
+
+        register("sink-poll", Transport::Poll);
+        for i in 0..=REPLY_MAILBOX_SIZE {
+            TransportSink.deliver("sink-poll", reply("sink-poll", &i.to_string())).unwrap();
+        }
+        let polled = poll("sink-poll");
+        assert_eq!(polled.len(), REPLY_MAILBOX_SIZE);
+        assert!(matches!(&polled[0], PBFTMessage::Reply { operation, .. } if operation == "1"));
+        assert!(poll("sink-poll").is_empty());
+    }
+
+    // 选择轮询方式的客户端从信箱取到各节点的答复
+    #[tokio::test]
+    async fn cluster_replies_land_in_the_mailbox() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            register("poller", Transport::Poll);
+            let request = PBFTMessage::Request {
+                operation: "SET polled yes".to_string(),
+                priority: Priority::Normal,
+                client_id: Some("poller".to_string()),
+                expires_at: None,
+                session: None,
+                timestamp: Some(1),
+            };
+            cluster.submit_request(request).await;
+            let answered = cluster.wait_until(Duration::from_secs(5), |_| {
+                MAILBOXES.lock().unwrap().get("poller").is_some_and(|mailbox| mailbox.len() > F)
+            }).await;
+            assert!(answered, "信箱中没有足够的答复");
+            let replies = poll("poller");
+            assert!(replies.iter().all(|reply| matches!(reply, PBFTMessage::Reply { client_id, .. } if client_id == "poller")));
+            // 答复带执行高度，客户端据此要求之后的读读到这次写入
+            assert!(replies.iter().all(|reply| matches!(reply, PBFTMessage::Reply { height: Some(height), .. } if *height >= 1)));
+        }).await;
+    }
+}
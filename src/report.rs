@@ -0,0 +1,217 @@
+// src/report.rs
+
+// 指标快照与运行报告：节点每METRICS_SNAPSHOT_SECS、以及正常关闭时把本进程的计数器和直方图分位数
+// 写入工作目录中的node_<ID>_metrics.json（先写临时文件再改名）。
+// report子命令读取一个目录中全部节点的快照，汇总成一份可读的性能报告，不需要Prometheus等外部设施
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::{debug, error};
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use crate::chain::Chain;
+use crate::config::METRICS_SNAPSHOT_SECS;
+use crate::metrics::{self, Percentiles};
+use crate::storage;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub node_id: usize,
+    pub height: u64, // 写快照时的链高度
+    pub started_at: u64, // 进程启动的Unix时间（秒）
+    pub written_at: u64, // 写快照的Unix时间（秒）
+    pub counters: BTreeMap<String, u64>,
+    pub histograms: BTreeMap<String, Percentiles>,
+}
+
+impl Snapshot {
+    fn uptime_secs(&self) -> u64 {
+        self.written_at.saturating_sub(self.started_at)
+    }
+
+    fn counter(&self, name: &str) -> u64 {
+        self.counters.get(name).copied().unwrap_or(0)
+    }
+}
+
+pub fn filename(directory: &Path, node_id: usize) -> PathBuf {
+    directory.join(format!("node_{}_metrics.json", node_id))
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+pub fn capture(node_id: usize, height: u64) -> Snapshot {
+    Snapshot {
+        node_id,
+        height,
+        started_at: unix_secs(*metrics::STARTED_AT),
+        written_at: unix_secs(SystemTime::now()),
+        counters: metrics::snapshot(),
+        histograms: metrics::percentiles(),
+    }
+}
+
+pub fn save(directory: &Path, snapshot: &Snapshot) -> Result<(), String> {
+    let data = serde_json::to_vec_pretty(snapshot).map_err(|e| e.to_string())?;
+    storage::write_atomically(&filename(directory, snapshot.node_id), &data, false)
+}
+
+// 节点运行期间定期写快照
+pub async fn write_periodically(node_id: usize, chain: Arc<Mutex<Chain>>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(METRICS_SNAPSHOT_SECS));
+    loop {
+        interval.tick().await;
+        let snapshot = capture(node_id, chain.lock().unwrap().height());
+        match save(Path::new("."), &snapshot) {
+            Ok(()) => debug!("节点{}写入指标快照", node_id),
+            Err(e) => error!("节点{}写入指标快照失败: {}", node_id, e),
+        }
+    }
+}
+
+// 读取目录中全部节点的快照，按节点ID排序
+pub fn load_all(directory: &Path) -> Result<Vec<Snapshot>, String> {
+    let entries = std::fs::read_dir(directory).map_err(|e| format!("无法读取目录{}: {}", directory.display(), e))?;
+    let mut snapshots = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_snapshot = name.strip_prefix("node_")
+            .and_then(|rest| rest.strip_suffix("_metrics.json"))
+            .is_some_and(|id| id.parse::<usize>().is_ok());
+        if !is_snapshot {
+            continue;
+        }
+        let data = std::fs::read_to_string(entry.path()).map_err(|e| format!("无法读取{}: {}", name, e))?;
+        let snapshot: Snapshot = serde_json::from_str(&data).map_err(|e| format!("{}不是有效的指标快照: {}", name, e))?;
+        snapshots.push(snapshot);
+    }
+    snapshots.sort_by_key(|snapshot| snapshot.node_id);
+    Ok(snapshots)
+}
+
+// 各节点的计数器之和
+pub fn totals(snapshots: &[Snapshot]) -> BTreeMap<String, u64> {
+    let mut totals = BTreeMap::new();
+    for snapshot in snapshots {
+        for (name, value) in &snapshot.counters {
+            *totals.entry(name.clone()).or_insert(0) += value;
+        }
+    }
+    totals
+}
+
+// 可读的性能报告：各节点的高度、吞吐、提交延迟和发送量，直方图分位数，以及计数器合计
+pub fn render(snapshots: &[Snapshot]) -> String {
+    let mut lines = vec![format!("运行报告：{}个节点的指标快照", snapshots.len()), String::new()];
+    lines.push(format!("{:<6}{:>8}{:>10}{:>10}{:>10}{:>12}{:>14}", "节点", "高度", "运行(s)", "提交区块", "区块/秒", "发送消息", "发送字节"));
+    for snapshot in snapshots {
+        let committed = snapshot.counter("commit_latency_samples_total");
+        let rate = committed as f64 / snapshot.uptime_secs().max(1) as f64;
+        lines.push(format!("{:<6}{:>8}{:>10}{:>10}{:>10.2}{:>12}{:>14}",
+            snapshot.node_id, snapshot.height, snapshot.uptime_secs(), committed, rate,
+            snapshot.counter("network_messages_sent_total"), snapshot.counter("network_bytes_sent_total")));
+    }
+    let totals = totals(snapshots);
+    let samples = totals.get("commit_latency_samples_total").copied().unwrap_or(0);
+    if samples > 0 {
+        let average = totals.get("commit_latency_ms_total").copied().unwrap_or(0) as f64 / samples as f64;
+        lines.push(format!("平均提交延迟: {:.1}ms（{}次提交）", average, samples));
+    }
+
+    lines.push(String::new());
+    lines.push("直方图（最近的观测值）:".to_string());
+    for snapshot in snapshots {
+        for (name, p) in &snapshot.histograms {
+            lines.push(format!("  节点{} {}: 次数 {}  p50 {}  p90 {}  p99 {}  最大 {}", snapshot.node_id, name, p.count, p.p50, p.p90, p.p99, p.max));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("计数器（各节点合计）:".to_string());
+    for (name, value) in totals.iter().filter(|(_, value)| **value > 0) {
+        lines.push(format!("  {} {}", name, value));
+    }
+    lines.join("\n")
+}
+
+// 命令行入口：report [目录] [--json]，目录缺省为当前目录
+pub fn run(args: &[String]) -> i32 {
+    let directory = args.iter().find(|arg| !arg.starts_with("--")).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+    let snapshots = match load_all(&directory) {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    if snapshots.is_empty() {
+        eprintln!("{}中没有指标快照（node_<ID>_metrics.json）", directory.display());
+        return 1;
+    }
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", json!({ "nodes": snapshots, "totals": totals(&snapshots) }));
+    } else {
+        println!("{}", render(&snapshots));
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    fn snapshot(node_id: usize, committed: u64) -> Snapshot {
+        let mut counters = BTreeMap::new();
+        counters.insert("commit_latency_samples_total".to_string(), committed);
+        counters.insert("commit_latency_ms_total".to_string(), committed * 10);
+        counters.insert("network_messages_sent_total".to_string(), 100);
+        let mut histograms = BTreeMap::new();
+        histograms.insert("commit_latency_ms".to_string(), Percentiles { count: committed, p50: 10, p90: 12, p99: 15, max: 15 });
+        Snapshot { node_id, height: committed, started_at: 1000, written_at: 1010, counters, histograms }
+    }
+
+    // 读取目录中各节点的快照，忽略其他文件，合计计数器并生成报告
+    #[test]
+    fn aggregates_snapshots_into_a_report() {
+        let directory = std::env::temp_dir().join(format!("pbft-report-{}-{}", std::process::id(), rand::random::<u32>()));
+        std::fs::create_dir_all(&directory).unwrap();
+        save(&directory, &snapshot(1, 20)).unwrap();
+        save(&directory, &snapshot(0, 10)).unwrap();
+        std::fs::write(directory.join("node_0_chain.json"), "{}").unwrap();
+
+        let snapshots = load_all(&directory).unwrap();
+        assert_eq!(snapshots.iter().map(|s| s.node_id).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(totals(&snapshots)["network_messages_sent_total"], 200);
+        let report = render(&snapshots);
+        assert!(report.contains("平均提交延迟: 10.0ms（30次提交）"), "{}", report);
+        assert!(report.contains("节点1 commit_latency_ms: 次数 20  p50 10  p90 12  p99 15"), "{}", report);
+
+        std::fs::write(filename(&directory, 2), "not json").unwrap();
+        assert!(load_all(&directory).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // 集群提交后，快照带有提交延迟的分位数，写入后可以读回
+    #[tokio::test]
+    async fn cluster_snapshot_records_commit_latency() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            cluster.submit("SET reported yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, "SET reported yes").is_some())).await;
+            assert!(committed, "请求未提交");
+
+            let height = cluster.chains[0].lock().unwrap().height();
+            save(Path::new("."), &capture(0, height)).unwrap();
+            let snapshots = load_all(Path::new(".")).unwrap();
+            let snapshot = snapshots.iter().find(|s| s.node_id == 0).unwrap();
+            assert_eq!(snapshot.height, height);
+            let latency = &snapshot.histograms["commit_latency_ms"];
+            assert!(latency.count > 0 && latency.p50 <= latency.p99 && latency.p99 <= latency.max);
+        }).await;
+    }
+}
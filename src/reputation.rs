@@ -0,0 +1,164 @@
+// src/reputation.rs
+
+// 节点信誉：把“是否可疑”推广为0到100的分数。协议违规和无效签名扣分，
+// 及时投出并计入提交证书的正确票缓慢恢复分数。分数保存在本地文件中，重启后保留；
+// 分数决定对该节点入站消息的限流速率和它在加权选举中的权重，分数低于阈值即视为可疑
+use std::collections::{BTreeMap, HashMap};
+use serde::{Serialize, Deserialize};
+use crate::config::{
+    N, MAX_REPUTATION, SUSPICION_THRESHOLD, INVALID_SIGNATURE_PENALTY, PROTOCOL_VIOLATION_PENALTY,
+    CORRECT_VOTE_REWARD, PEER_MESSAGE_RATE_LIMIT, MIN_PEER_RATE_SHARE,
+};
+use crate::qos::TokenBucket;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    InvalidSignature,
+    ProtocolViolation, // 发送了与法定人数冲突的摘要、无效的NewView等
+    CorrectVote,       // 签名计入了本节点构造的提交证书
+}
+
+impl Event {
+    fn change(self) -> i64 {
+        match self {
+            Event::InvalidSignature => -INVALID_SIGNATURE_PENALTY,
+            Event::ProtocolViolation => -PROTOCOL_VIOLATION_PENALTY,
+            Event::CorrectVote => CORRECT_VOTE_REWARD,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Reputation {
+    scores: BTreeMap<usize, i64>, // 只保存偏离满分的节点
+    #[serde(skip)]
+    limiters: HashMap<usize, TokenBucket>,
+}
+
+impl Reputation {
+    pub fn load(node_id: usize) -> Self {
+        std::fs::read_to_string(format!("node_{}_reputation.json", node_id)).ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, node_id: usize) {
+        let data = serde_json::to_string(self).unwrap();
+        std::fs::write(format!("node_{}_reputation.json", node_id), data).unwrap();
+    }
+
+    pub fn score(&self, node_id: usize) -> i64 {
+        self.scores.get(&node_id).copied().unwrap_or(MAX_REPUTATION)
+    }
+
+    pub fn is_suspected(&self, node_id: usize) -> bool {
+        self.score(node_id) < SUSPICION_THRESHOLD
+    }
+
+    pub fn suspected(&self) -> Vec<usize> {
+        self.scores.keys().copied().filter(|id| self.is_suspected(*id)).collect()
+    }
+
+    // 全部验证者及其他有记录节点的分数
+    pub fn scores(&self) -> BTreeMap<usize, i64> {
+        let mut scores: BTreeMap<usize, i64> = (0..N).map(|id| (id, MAX_REPUTATION)).collect();
+        scores.extend(&self.scores);
+        scores
+    }
+
+    // 记录一次事件，返回分数是否变化以及节点是否因此首次变为可疑
+    pub fn record(&mut self, node_id: usize, event: Event) -> (bool, bool) {
+        let before = self.score(node_id);
+        let after = (before + event.change()).clamp(0, MAX_REPUTATION);
+        if after == MAX_REPUTATION {
+            self.scores.remove(&node_id);
+        } else {
+            self.scores.insert(node_id, after);
+        }
+        if let Some(limiter) = self.limiters.get_mut(&node_id) {
+            limiter.set_rate(rate_for(after));
+        }
+        (after != before, before >= SUSPICION_THRESHOLD && after < SUSPICION_THRESHOLD)
+    }
+
+    // 入站消息限流：速率随分数线性下降，但保留一个下限，让低分节点仍有机会恢复
+    pub fn admit(&mut self, node_id: usize) -> bool {
+        let rate = rate_for(self.score(node_id));
+        self.limiters.entry(node_id).or_insert_with(|| TokenBucket::new(rate)).try_acquire()
+    }
+}
+
+fn rate_for(score: i64) -> f64 {
+    let share = (score as f64 / MAX_REPUTATION as f64).max(MIN_PEER_RATE_SHARE);
+    PEER_MESSAGE_RATE_LIMIT * share
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::byzantine::Strategy;
+    use crate::testing::TestCluster;
+
+    #[test]
+    fn penalties_cross_threshold_once_and_votes_recover() {
+        let mut reputation = Reputation::default();
+        assert_eq!(reputation.score(3), MAX_REPUTATION);
+        assert_eq!(reputation.record(3, Event::CorrectVote), (false, false));
+
+        assert_eq!(reputation.record(3, Event::ProtocolViolation), (true, true));
+        assert!(reputation.is_suspected(3));
+        assert_eq!(reputation.record(3, Event::InvalidSignature), (true, false));
+        assert_eq!(reputation.suspected(), vec![3]);
+
+        let penalized = reputation.score(3);
+        let votes = (SUSPICION_THRESHOLD - penalized + CORRECT_VOTE_REWARD - 1) / CORRECT_VOTE_REWARD;
+        for _ in 0..votes {
+            reputation.record(3, Event::CorrectVote);
+        }
+        assert!(!reputation.is_suspected(3));
+        for _ in 0..10 {
+            reputation.record(3, Event::ProtocolViolation);
+        }
+        assert_eq!(reputation.score(3), 0);
+    }
+
+    #[test]
+    fn low_score_lowers_rate_limit() {
+        let mut reputation = Reputation::default();
+        for _ in 0..10 {
+            reputation.record(2, Event::ProtocolViolation);
+        }
+        let admitted = |reputation: &mut Reputation, id| (0..PEER_MESSAGE_RATE_LIMIT as usize).filter(|_| reputation.admit(id)).count();
+        assert_eq!(admitted(&mut reputation, 1), PEER_MESSAGE_RATE_LIMIT as usize);
+        assert_eq!(admitted(&mut reputation, 2), (PEER_MESSAGE_RATE_LIMIT * MIN_PEER_RATE_SHARE) as usize);
+    }
+
+    #[test]
+    fn scores_survive_restart() {
+        let mut reputation = Reputation::default();
+        reputation.record(1, Event::ProtocolViolation);
+        reputation.admit(1);
+        let restored: Reputation = serde_json::from_str(&serde_json::to_string(&reputation).unwrap()).unwrap();
+        assert_eq!(restored.score(1), MAX_REPUTATION - PROTOCOL_VIOLATION_PENALTY);
+        assert_eq!(restored.scores().len(), N);
+    }
+
+    #[tokio::test]
+    async fn wrong_digest_replica_loses_reputation() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut strategies = [Strategy::Honest; N];
+            strategies[N - 1] = Strategy::WrongDigest;
+            let cluster = TestCluster::start(&strategies, Duration::from_millis(1000)).await;
+            cluster.submit("SET k v").await;
+            // 诚实节点把分数写入各自的信誉文件
+            let suspected = cluster.wait_until(Duration::from_secs(10), |_| {
+                (0..N - 1).all(|id| Reputation::load(id).is_suspected(N - 1))
+            }).await;
+            assert!(suspected, "诚实节点未降低作恶节点的信誉");
+            for id in 0..N - 1 {
+                assert_eq!(Reputation::load(id).suspected(), vec![N - 1], "节点{}错误地怀疑了诚实节点", id);
+            }
+        }).await;
+    }
+}
@@ -0,0 +1,135 @@
+// src/request_status.rs
+
+// 请求状态：记录每个请求在本节点走到了哪一步，供客户端和监控面板通过RPC查询，
+// 区分"请求丢了"、"在等主节点提议"、"已准备"和"已在高度H执行，结果为R"。
+// 请求ID由客户端ID和请求时间戳组成（"client_id/timestamp"），匿名请求和不带时间戳的请求无法查询
+use std::collections::{HashMap, VecDeque};
+use serde::Serialize;
+use crate::config::REQUEST_STATUS_CAPACITY;
+use crate::execution::ExecutionStatus;
+use crate::message::Transaction;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum RequestStatus {
+    Unknown,                                      // 本节点没有收到过，或记录已被淘汰
+    Pending,                                      // 已接受，等待主节点提议
+    Ordered { view: u64, sequence_number: u64 },  // 已进入PrePrepare
+    Prepared { view: u64, sequence_number: u64 }, // 已收齐Prepare
+    Committed { height: u64 },                    // 已进入区块，尚未执行（例如正在重新同步状态）
+    Executed { height: u64, status: ExecutionStatus },
+    Failed { reason: String },                    // 过期或被拒绝，客户端重发前不会被执行
+}
+
+pub fn request_id(client_id: &str, timestamp: u64) -> String {
+    format!("{}/{}", client_id, timestamp)
+}
+
+fn transaction_id(transaction: &Transaction) -> Option<String> {
+    match (&transaction.client_id, transaction.timestamp) {
+        (Some(client_id), Some(timestamp)) => Some(request_id(client_id, timestamp)),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+pub struct RequestTracker {
+    statuses: HashMap<String, RequestStatus>,
+    order: VecDeque<String>, // 首次记录的先后，超出容量时淘汰最早的
+}
+
+impl RequestTracker {
+    pub fn get(&self, request_id: &str) -> RequestStatus {
+        self.statuses.get(request_id).cloned().unwrap_or(RequestStatus::Unknown)
+    }
+
+    // 更新交易的状态。执行结果是终态，之后视图切换中重新提议的同一请求不会把它改回去；
+    // 失败不是终态，客户端可以重发被拒绝或已过期的请求
+    pub fn update(&mut self, transaction: &Transaction, status: RequestStatus) {
+        let id = match transaction_id(transaction) {
+            Some(id) => id,
+            None => return,
+        };
+        match self.statuses.get_mut(&id) {
+            Some(RequestStatus::Executed { .. }) => {}
+            Some(current) => *current = status,
+            None => {
+                self.order.push_back(id.clone());
+                self.statuses.insert(id, status);
+                while self.statuses.len() > REQUEST_STATUS_CAPACITY {
+                    let oldest = self.order.pop_front().unwrap();
+                    self.statuses.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    pub fn update_all(&mut self, transactions: &[Transaction], status: RequestStatus) {
+        for transaction in transactions {
+            self.update(transaction, status.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::message::PBFTMessage;
+    use crate::network;
+    use crate::qos::Priority;
+    use crate::testing::TestCluster;
+
+    fn transaction(client_id: &str, timestamp: u64) -> Transaction {
+        Transaction { operation: "SET k v".to_string(), client_id: Some(client_id.to_string()), session: None, timestamp: Some(timestamp) }
+    }
+
+    #[test]
+    fn executed_is_terminal() {
+        let mut tracker = RequestTracker::default();
+        tracker.update(&transaction("alice", 1), RequestStatus::Pending);
+        tracker.update(&transaction("alice", 1), RequestStatus::Committed { height: 3 });
+        assert_eq!(tracker.get("alice/1"), RequestStatus::Committed { height: 3 });
+        tracker.update(&transaction("alice", 1), RequestStatus::Executed { height: 3, status: ExecutionStatus::Success(None) });
+        tracker.update(&transaction("alice", 1), RequestStatus::Ordered { view: 1, sequence_number: 4 });
+        assert_eq!(tracker.get("alice/1"), RequestStatus::Executed { height: 3, status: ExecutionStatus::Success(None) });
+        tracker.update(&transaction("alice", 2), RequestStatus::Failed { reason: "请求已过期".to_string() });
+        tracker.update(&transaction("alice", 2), RequestStatus::Pending);
+        assert_eq!(tracker.get("alice/2"), RequestStatus::Pending);
+        assert_eq!(tracker.get("alice/3"), RequestStatus::Unknown);
+
+        // 没有时间戳的请求无法标识，不记录
+        tracker.update(&Transaction { timestamp: None, ..transaction("bob", 0) }, RequestStatus::Pending);
+        assert_eq!(tracker.statuses.len(), 2);
+    }
+
+    // 提交后每个副本都报告请求在同一高度执行成功
+    #[tokio::test]
+    async fn committed_request_reports_execution_height() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            let (replies, mut received) = mpsc::channel(N);
+            network::register_client("status-client", replies);
+            cluster.submit_request(PBFTMessage::Request {
+                operation: "SET k v".to_string(),
+                priority: Priority::Normal,
+                client_id: Some("status-client".to_string()),
+                expires_at: None,
+                session: None,
+                timestamp: Some(7),
+            }).await;
+            for _ in 0..N {
+                tokio::time::timeout(Duration::from_secs(5), received.recv()).await.expect("未收到答复");
+            }
+
+            let height = cluster.chains[0].lock().unwrap().height();
+            for id in 0..N {
+                let status = cluster.request_statuses[id].lock().unwrap().get("status-client/7");
+                assert!(matches!(status, RequestStatus::Executed { height: h, status: ExecutionStatus::Success(_) } if h == height), "节点{}: {:?}", id, status);
+            }
+        }).await;
+    }
+}
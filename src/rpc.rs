@@ -0,0 +1,746 @@
+// src/rpc.rs
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::watch;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use log::{info, error, debug, warn};
+use crate::chain::{self, BlockHeader, Chain, CommitCertificate, ValidatorSet};
+use crate::genesis::Genesis;
+use crate::archive::ArchiveIndex;
+use crate::observer::Auditor;
+use crate::clock_sync::ClockSync;
+use crate::request_status::RequestTracker;
+use crate::view_stats::ViewStats;
+use crate::config::{READ_MIN_HEIGHT_WAIT_MS, SCAN_MAX_PAGE_SIZE, SCAN_PAGE_SIZE, VIEW_STATS_HISTORY};
+use crate::events::{self, EventBus};
+use crate::execution::ExecutionEngine;
+use crate::firewall::Firewall;
+use crate::qos::TokenBucket;
+use crate::reload::NodeConfig;
+use crate::reputation::Reputation;
+use crate::rpc_auth::{RpcAuth, RpcRole};
+use crate::message::PBFTMessage;
+use crate::{audit, beacon, bridge, directory, governance, multisig, namespace, schedule, session};
+use crate::{metrics, network, reply_sink};
+use crate::reply_sink::Transport;
+
+const REPLY_QUEUE_SIZE: usize = 64; // 每个连接缓存的待推送答复数
+const READ_POLL_MS: u64 = 10; // 等待执行追上读请求要求的高度时的检查间隔
+
+// 每行一个JSON请求，例如 {"method":"TrafficStats"}
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "method")]
+pub enum RpcRequest {
+    // 用令牌认证本连接，之后的请求按令牌的角色授权
+    Authenticate { token: String },
+    TrafficStats,
+    Metrics,
+    // 查询已提交的操作，返回Merkle证明、区块头和提交证书
+    QueryOperation { height: u64, index: usize },
+    // 读取执行引擎中的键值，答复带读取时的执行高度。带min_height时只从执行到该高度的状态读：
+    // 尚未执行到时最多等待READ_MIN_HEIGHT_WAIT_MS，仍未执行到则答复redirect，客户端改读其他副本
+    Get { key: String, #[serde(default)] min_height: Option<u64> },
+    // 按前缀分页扫描执行状态，每页最多limit个键（缺省SCAN_PAGE_SIZE，至多SCAN_MAX_PAGE_SIZE）。
+    // 答复带所读的height和下一页的起点next；之后的页带上同一height和start_after=next，
+    // 各页读同一高度的状态，不受其间执行的新区块影响。只保留最近STATE_VIEW_HISTORY个高度
+    Scan {
+        #[serde(default)]
+        prefix: String,
+        #[serde(default)]
+        start_after: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+        #[serde(default)]
+        height: Option<u64>,
+    },
+    // 执行状态最后完整应用的区块高度、本地链的高度（已提交），以及链文件已持久的高度；
+    // 链高度与执行高度之差是已提交但尚未执行的区块数，与持久高度之差是只在预写日志中的区块数
+    AppliedHeight,
+    // 按交易摘要（交易规范编码的哈希）查找已提交的位置，内容相同的交易可能有多个位置
+    LocateTransaction { digest: String },
+    // 客户端已提交的全部交易及其位置
+    TransactionsByClient { client_id: String },
+    // 以下查询仅归档节点提供
+    TransactionsByType { operation_type: String },
+    OperationTypeStats,
+    // 观察者节点发现的协议违规及证据
+    Violations,
+    // 链上节点目录：节点ID、地址、公钥和角色
+    Directory,
+    // 验证者集合（链ID和公钥），外部审计方据此独立验证提交证书
+    ValidatorSet,
+    // 本节点的共识参数（创世配置和config.rs中的共识常量）及其哈希，各节点须一致
+    ConsensusParameters,
+    // 为中继者生成本链区块内一条EMIT交易的跨链证明，附可直接提交到目标链的BRIDGE操作
+    BridgeProof { height: u64, index: usize },
+    // 用本节点目录中的验证者集合验证一个区块头的提交证书
+    VerifyCommitCertificate { header: BlockHeader, certificate: CommitCertificate },
+    // 链上治理提案、投票的验证者及是否已通过
+    Proposals,
+    // 多签账户的门限、公钥和已执行次数，下一个提案的nonce为已执行次数+1
+    MultisigAccount { account: String },
+    // 命名空间的所有者和获准写入的客户端
+    Namespace { name: String },
+    // 指定高度的随机信标（只保留最近BEACON_HISTORY个区块），不指定时为最新的信标
+    Beacon { height: Option<u64> },
+    // 尚未到期的定时交易及其执行高度
+    ScheduledTransactions,
+    // 当前视图的主节点及其目录条目，供客户端发现主节点
+    Primary,
+    // 校验本节点审计日志的哈希链和签名
+    VerifyAuditLog,
+    // 本节点记录的各节点信誉分数及可疑节点
+    Reputation,
+    // 本节点估计的各对等节点时钟偏差，以及本地时钟相对多数节点的修正量
+    ClockSkew,
+    // 各视图的主节点、时长、本节点提交的区块数、超时次数和结束原因：进行中的视图、最近结束的limit个视图
+    // （缺省为内存中保留的全部），以及按主节点汇总的全部历史
+    ViewStats { limit: Option<usize> },
+    // 请求在本节点的进度：pending、ordered、prepared、committed、executed（附高度和结果）或failed；
+    // request_id为"客户端ID/时间戳"，本节点没有记录时为unknown
+    GetRequestStatus { request_id: String },
+    // 提交Request或ClientRequest。带客户端ID的请求的答复按reply_via送达：缺省在同一连接上推送，
+    // {"transport":"callback","address":"host:port"}由节点连接该地址送达，{"transport":"poll"}存入信箱
+    Submit { message: Box<PBFTMessage>, #[serde(default)] reply_via: Option<Transport> },
+    // 取走信箱中该客户端的全部答复（提交时选择了poll）
+    PollReplies { client_id: String },
+    // 订阅本节点的共识事件（接受提议、Prepared、提交、视图切换、拉黑），之后的事件在同一连接上推送
+    SubscribeEvents,
+    // 开启或恢复客户端会话：不带session_id时分配新会话，带上时返回该会话已执行的序号及结果
+    OpenSession { session_id: Option<String> },
+    // 本节点传输层防火墙的当前规则
+    Firewall,
+    // 在运行时封禁或解封对等节点和IP（传输层，与协议层的黑名单无关），返回规则是否有变化；
+    // 封禁IP后，来自该IP的已有连接在下一个请求时断开
+    BlockPeer { node_id: usize },
+    UnblockPeer { node_id: usize },
+    BlockAddress { address: IpAddr },
+    UnblockAddress { address: IpAddr },
+    // 计划退出：节点交接主节点、等进行中的实例提交后关闭
+    Exit,
+    // 开关维护模式：维护期间节点照常答复查询，但不参与共识
+    Maintenance { enabled: bool },
+}
+
+#[derive(Clone)]
+pub struct RpcContext {
+    pub node_id: usize,
+    pub chain: Arc<Mutex<Chain>>,
+    pub archive_index: Option<Arc<Mutex<ArchiveIndex>>>,
+    pub auditor: Option<Arc<Mutex<Auditor>>>,
+    pub execution: Arc<Mutex<ExecutionEngine>>,
+    pub view: Arc<AtomicU64>,
+    pub primary: Arc<AtomicUsize>,
+    pub reputation: Arc<Mutex<Reputation>>,
+    pub clock_sync: Arc<Mutex<ClockSync>>,
+    pub request_status: Arc<Mutex<RequestTracker>>,
+    pub view_stats: Arc<Mutex<ViewStats>>,
+    pub durable_height: watch::Receiver<u64>,
+    pub node: Sender<PBFTMessage>, // 节点的消息通道，用于转交客户端请求
+    pub auth: Arc<RpcAuth>,
+    pub firewall: Arc<Mutex<Firewall>>,
+    pub events: EventBus,
+    pub exit: Sender<()>, // 节点的计划退出信号
+    pub maintenance: Sender<bool>,
+    pub genesis: Genesis,
+    pub config: Arc<Mutex<NodeConfig>>, // 可热加载的配置，RPC连接数和请求速率上限随之调整
+    pub connections: Arc<AtomicUsize>, // 当前打开的RPC连接数
+}
+
+// 调用各方法所需的最低角色
+fn required_role(request: &RpcRequest) -> RpcRole {
+    match request {
+        RpcRequest::Authenticate { .. } => RpcRole::None,
+        RpcRequest::Submit { .. } | RpcRequest::PollReplies { .. } | RpcRequest::OpenSession { .. } => RpcRole::Submitter,
+        RpcRequest::VerifyAuditLog
+        | RpcRequest::Firewall
+        | RpcRequest::BlockPeer { .. }
+        | RpcRequest::UnblockPeer { .. }
+        | RpcRequest::BlockAddress { .. }
+        | RpcRequest::UnblockAddress { .. }
+        | RpcRequest::Exit
+        | RpcRequest::Maintenance { .. } => RpcRole::Admin,
+        _ => RpcRole::Reader,
+    }
+}
+
+// 绑定节点的全部监听地址，个别地址（例如未启用IPv6）绑定失败不影响其他地址
+pub async fn bind(node_id: usize) -> Vec<TcpListener> {
+    let mut listeners = Vec::new();
+    for addr in network::listen_addresses(node_id) {
+        match TcpListener::bind(&addr).await {
+            Ok(listener) => {
+                info!("节点{}的RPC服务监听于{}", node_id, addr);
+                listeners.push(listener);
+            }
+            Err(e) => error!("节点{}的RPC服务绑定{}失败: {}", node_id, addr, e),
+        }
+    }
+    if listeners.is_empty() {
+        error!("节点{}没有可用的RPC监听地址", node_id);
+    }
+    listeners
+}
+
+pub async fn serve(ctx: RpcContext, listeners: Vec<TcpListener>) {
+    let accept_loops: Vec<_> = listeners.into_iter()
+        .map(|listener| tokio::spawn(accept_loop(ctx.clone(), listener)))
+        .collect();
+    for accept in accept_loops {
+        let _ = accept.await;
+    }
+}
+
+async fn accept_loop(ctx: RpcContext, listener: TcpListener) {
+    let node_id = ctx.node_id;
+    loop {
+        match listener.accept().await {
+            Ok((_, peer)) if !admitted(&ctx, peer) => {
+                info!("节点{}的防火墙拒绝来自{}的RPC连接", node_id, peer);
+            }
+            Ok((_, peer)) if at_connection_limit(&ctx) => {
+                info!("节点{}的RPC连接数已达上限，拒绝来自{}的连接", node_id, peer);
+                metrics::inc_counter("rpc_connections_rejected_total", 1);
+            }
+            Ok((stream, peer)) => {
+                debug!("节点{}接受RPC连接: {}", node_id, peer);
+                ctx.connections.fetch_add(1, Ordering::Relaxed);
+                let connections = ctx.connections.clone();
+                let connection = handle_connection(ctx.clone(), stream, peer);
+                tokio::spawn(async move {
+                    connection.await;
+                    connections.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            Err(e) => error!("节点{}接受RPC连接失败: {}", node_id, e),
+        }
+    }
+}
+
+fn at_connection_limit(ctx: &RpcContext) -> bool {
+    let limit = ctx.config.lock().unwrap().rpc_max_connections;
+    limit > 0 && ctx.connections.load(Ordering::Relaxed) >= limit
+}
+
+fn admitted(ctx: &RpcContext, peer: SocketAddr) -> bool {
+    let admitted = ctx.firewall.lock().unwrap().admits_address(peer.ip());
+    if !admitted {
+        metrics::inc_counter("firewall_rejected_connections_total", 1);
+    }
+    admitted
+}
+
+async fn handle_connection(ctx: RpcContext, stream: TcpStream, peer: SocketAddr) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let (reply_sender, mut replies) = mpsc::channel(REPLY_QUEUE_SIZE);
+    let mut role = ctx.auth.anonymous;
+    let mut subscription = None;
+    // 请求速率上限在每个请求时读取，热加载的新上限对已有连接立即生效
+    let mut rate = 0.0;
+    let mut bucket = TokenBucket::new(rate);
+
+    loop {
+        let response = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(_)) if !admitted(&ctx, peer) => {
+                    info!("节点{}的防火墙封禁了{}，断开RPC连接", ctx.node_id, peer);
+                    break;
+                }
+                Ok(Some(_)) if !within_rate(&ctx, &mut rate, &mut bucket) => {
+                    metrics::inc_counter("rpc_rate_limited_total", 1);
+                    json!({ "error": format!("请求过于频繁，每秒最多{}个", rate) })
+                }
+                Ok(Some(line)) => match serde_json::from_str::<RpcRequest>(&line) {
+                    Ok(RpcRequest::Authenticate { token }) => match ctx.auth.authenticate(&token) {
+                        Some((name, granted)) => {
+                            info!("节点{}的RPC连接以令牌{}认证，角色: {:?}", ctx.node_id, name, granted);
+                            role = granted;
+                            json!({ "authenticated": true, "role": granted })
+                        }
+                        None => {
+                            metrics::inc_counter("rpc_auth_failed_total", 1);
+                            json!({ "error": "令牌无效" })
+                        }
+                    },
+                    Ok(request) if role < required_role(&request) => {
+                        metrics::inc_counter("rpc_forbidden_total", 1);
+                        json!({ "error": format!("角色{:?}无权调用该方法，需要{:?}", role, required_role(&request)) })
+                    }
+                    Ok(RpcRequest::SubscribeEvents) => {
+                        subscription = Some(ctx.events.subscribe());
+                        json!({ "subscribed": true })
+                    }
+                    Ok(request) => handle_request(&ctx, request, &reply_sender).await,
+                    Err(e) => json!({ "error": format!("无效的RPC请求: {}", e) }),
+                },
+                _ => break,
+            },
+            Some(reply) = replies.recv() => json!(reply),
+            Some(event) = events::next(&mut subscription) => json!(event),
+        };
+
+        let mut data = response.to_string();
+        data.push('\n');
+        if writer.write_all(data.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn within_rate(ctx: &RpcContext, rate: &mut f64, bucket: &mut TokenBucket) -> bool {
+    let limit = ctx.config.lock().unwrap().rpc_requests_per_second;
+    if limit != *rate {
+        *rate = limit;
+        *bucket = TokenBucket::new(limit);
+    }
+    limit == 0.0 || bucket.try_acquire()
+}
+
+async fn handle_request(ctx: &RpcContext, request: RpcRequest, replies: &Sender<PBFTMessage>) -> Value {
+    match request {
+        RpcRequest::TrafficStats => json!(network::traffic_stats(ctx.node_id)),
+        RpcRequest::Metrics => json!(metrics::snapshot()),
+        RpcRequest::QueryOperation { height, index } => {
+            match ctx.chain.lock().unwrap().prove_operation(height, index) {
+                Some(bundle) => json!(bundle),
+                None => json!({ "error": format!("高度{}不存在第{}个操作", height, index) }),
+            }
+        }
+        RpcRequest::Get { key, min_height } => read(ctx, key, min_height.unwrap_or(0)).await,
+        RpcRequest::Scan { prefix, start_after, limit, height } => {
+            // 取得视图后即释放执行引擎的锁，遍历不阻塞执行
+            let view = ctx.execution.lock().unwrap().view(height);
+            match view {
+                Ok(view) => json!(view.scan(&prefix, start_after.as_deref(), limit.unwrap_or(SCAN_PAGE_SIZE).clamp(1, SCAN_MAX_PAGE_SIZE))),
+                Err(e) => json!({ "error": e }),
+            }
+        }
+        RpcRequest::AppliedHeight => {
+            let applied_height = ctx.execution.lock().unwrap().applied_height();
+            let durable_height = *ctx.durable_height.borrow();
+            json!({ "applied_height": applied_height, "chain_height": ctx.chain.lock().unwrap().height(), "durable_height": durable_height })
+        }
+        RpcRequest::LocateTransaction { digest } => {
+            json!({ "digest": digest, "locations": ctx.chain.lock().unwrap().index.by_digest(&digest) })
+        }
+        RpcRequest::TransactionsByClient { client_id } => {
+            let chain = ctx.chain.lock().unwrap();
+            let transactions: Vec<Value> = chain.index.by_client(&client_id).into_iter()
+                .filter_map(|location| {
+                    let transaction = chain.get_block(location.height)?.transactions.get(location.index)?.clone();
+                    Some(json!({ "height": location.height, "index": location.index, "transaction": transaction }))
+                })
+                .collect();
+            json!(transactions)
+        }
+        RpcRequest::TransactionsByType { operation_type } => {
+            with_archive(ctx, |index| json!(index.by_operation_type(&operation_type)))
+        }
+        RpcRequest::OperationTypeStats => {
+            with_archive(ctx, |index| json!(index.operation_type_counts()))
+        }
+        RpcRequest::Violations => match &ctx.auditor {
+            Some(auditor) => json!(auditor.lock().unwrap().violations),
+            None => json!({ "error": "该节点不是观察者节点" }),
+        },
+        RpcRequest::Directory => json!(directory::entries(ctx.execution.lock().unwrap().state())),
+        RpcRequest::ValidatorSet => json!(ValidatorSet::from_directory(&ctx.genesis, ctx.execution.lock().unwrap().state())),
+        RpcRequest::ConsensusParameters => {
+            let parameters = ctx.genesis.consensus_parameters();
+            json!({ "hash": parameters.hash(), "parameters": parameters })
+        }
+        RpcRequest::VerifyCommitCertificate { header, certificate } => {
+            let validator_set = ValidatorSet::from_directory(&ctx.genesis, ctx.execution.lock().unwrap().state());
+            match chain::verify_commit_certificate(&header, &certificate, &validator_set) {
+                Ok(()) => json!({ "valid": true, "validator_set": validator_set }),
+                Err(reason) => json!({ "valid": false, "error": reason, "validator_set": validator_set }),
+            }
+        }
+        RpcRequest::BridgeProof { height, index } => {
+            match bridge::prove(&ctx.chain.lock().unwrap(), &ctx.genesis.chain_id, height, index) {
+                Some(proof) => json!({ "operation": proof.operation(), "proof": proof }),
+                None => json!({ "error": format!("高度{}不存在第{}个操作", height, index) }),
+            }
+        }
+        RpcRequest::Proposals => json!(governance::proposals(ctx.execution.lock().unwrap().state())),
+        RpcRequest::MultisigAccount { account } => json!({ "account": account, "state": multisig::lookup(ctx.execution.lock().unwrap().state(), &account) }),
+        RpcRequest::Namespace { name } => json!({ "name": name, "namespace": namespace::lookup(ctx.execution.lock().unwrap().state(), &name) }),
+        RpcRequest::Beacon { height } => {
+            let execution = ctx.execution.lock().unwrap();
+            match height {
+                Some(height) => json!(beacon::get(execution.state(), height)),
+                None => json!(beacon::latest(execution.state(), &ctx.genesis.chain_id)),
+            }
+        }
+        RpcRequest::ScheduledTransactions => {
+            let pending: Vec<Value> = schedule::pending(ctx.execution.lock().unwrap().state()).into_iter()
+                .map(|(height, transaction)| json!({ "height": height, "transaction": transaction }))
+                .collect();
+            json!(pending)
+        }
+        RpcRequest::Primary => {
+            let view = ctx.view.load(Ordering::Relaxed);
+            let primary = ctx.primary.load(Ordering::Relaxed);
+            let entry = directory::lookup(ctx.execution.lock().unwrap().state(), primary);
+            json!({ "view": view, "primary": primary, "entry": entry })
+        }
+        RpcRequest::VerifyAuditLog => {
+            // 读取整个日志并逐条验签，放到阻塞线程池中执行
+            let node_id = ctx.node_id;
+            match tokio::task::spawn_blocking(move || audit::verify_file(node_id)).await {
+                Ok(Ok(entries)) => json!({ "valid": true, "entries": entries }),
+                Ok(Err(reason)) => json!({ "valid": false, "error": reason }),
+                Err(e) => json!({ "error": format!("校验任务失败: {}", e) }),
+            }
+        }
+        RpcRequest::Reputation => {
+            let reputation = ctx.reputation.lock().unwrap();
+            json!({ "scores": reputation.scores(), "suspected": reputation.suspected() })
+        }
+        RpcRequest::ClockSkew => {
+            let clock_sync = ctx.clock_sync.lock().unwrap();
+            json!({ "peers": clock_sync.estimates(), "cluster_offset_ms": clock_sync.cluster_offset() })
+        }
+        RpcRequest::ViewStats { limit } => {
+            let stats = ctx.view_stats.lock().unwrap();
+            json!({
+                "current": stats.current(tokio::time::Instant::now()),
+                "recent": stats.recent(limit.unwrap_or(VIEW_STATS_HISTORY)),
+                "leaders": stats.leaders(),
+            })
+        }
+        RpcRequest::GetRequestStatus { request_id } => {
+            json!({ "request_id": request_id, "status": ctx.request_status.lock().unwrap().get(&request_id) })
+        }
+        RpcRequest::Authenticate { .. } => json!({ "error": "认证由连接处理" }),
+        RpcRequest::SubscribeEvents => json!({ "error": "订阅由连接处理" }),
+        RpcRequest::Exit => {
+            let started = ctx.exit.try_send(()).is_ok();
+            json!({ "exiting": started })
+        }
+        RpcRequest::Maintenance { enabled } => match ctx.maintenance.send(enabled).await {
+            Ok(()) => json!({ "maintenance": enabled }),
+            Err(_) => json!({ "error": "节点已停止" }),
+        },
+        RpcRequest::Submit { message, reply_via } => submit(ctx, *message, reply_via, replies),
+        RpcRequest::PollReplies { client_id } => json!({ "replies": reply_sink::poll(&client_id) }),
+        RpcRequest::OpenSession { session_id } => {
+            let session_id = session_id.unwrap_or_else(session::new_session_id);
+            let state = session::load(ctx.execution.lock().unwrap().state(), &session_id);
+            json!({
+                "session_id": session_id,
+                "next_sequence": state.next_sequence(),
+                "results": state.results,
+                "evicted_through": state.evicted_through,
+            })
+        }
+        RpcRequest::Firewall => json!(*ctx.firewall.lock().unwrap()),
+        RpcRequest::BlockPeer { node_id } => update_firewall(ctx, |firewall| firewall.block_peer(node_id)),
+        RpcRequest::UnblockPeer { node_id } => update_firewall(ctx, |firewall| firewall.unblock_peer(node_id)),
+        RpcRequest::BlockAddress { address } => update_firewall(ctx, |firewall| firewall.block_address(address)),
+        RpcRequest::UnblockAddress { address } => update_firewall(ctx, |firewall| firewall.unblock_address(address)),
+    }
+}
+
+fn update_firewall(ctx: &RpcContext, change: impl FnOnce(&mut Firewall) -> bool) -> Value {
+    let mut firewall = ctx.firewall.lock().unwrap();
+    let changed = change(&mut firewall);
+    if changed {
+        warn!("节点{}的防火墙规则已更新: {:?}", ctx.node_id, *firewall);
+    }
+    json!({ "changed": changed, "firewall": *firewall })
+}
+
+// 读一个键。客户端要求读到自己写入的结果时带上最低高度：副本落后时等待执行追上，
+// 等待READ_MIN_HEIGHT_WAIT_MS仍未追上则答复redirect和本副本的执行高度，不返回旧状态
+async fn read(ctx: &RpcContext, key: String, min_height: u64) -> Value {
+    let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_millis(READ_MIN_HEIGHT_WAIT_MS);
+    let mut delayed = false;
+    loop {
+        {
+            let execution = ctx.execution.lock().unwrap();
+            let height = execution.applied_height();
+            if height >= min_height {
+                return json!({ "key": key, "value": execution.get(&key), "height": height });
+            }
+            if tokio::time::Instant::now() >= deadline {
+                debug!("节点{}执行到高度{}，低于读请求要求的{}，让客户端改读其他副本", ctx.node_id, height, min_height);
+                metrics::inc_counter("reads_redirected_total", 1);
+                return json!({ "key": key, "redirect": true, "applied_height": height, "min_height": min_height });
+            }
+        }
+        if !delayed {
+            metrics::inc_counter("reads_delayed_total", 1);
+            delayed = true;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(READ_POLL_MS)).await;
+    }
+}
+
+// 把客户端请求交给节点，并让该客户端的答复经由本连接返回
+fn submit(ctx: &RpcContext, message: PBFTMessage, reply_via: Option<Transport>, replies: &Sender<PBFTMessage>) -> Value {
+    let client_id = match &message {
+        PBFTMessage::Request { client_id, .. } => client_id.clone(),
+        PBFTMessage::ClientRequest { client_id, .. } => Some(client_id.clone()),
+        _ => return json!({ "error": "只能提交Request或ClientRequest" }),
+    };
+    if let Some(client_id) = &client_id {
+        let transport = reply_via.unwrap_or(Transport::Stream);
+        if transport == Transport::Stream {
+            network::register_client(client_id, replies.clone());
+        }
+        reply_sink::register(client_id, transport);
+    }
+    match ctx.node.try_send(message) {
+        Ok(()) => json!({ "submitted": true }),
+        Err(e) => json!({ "error": format!("节点无法接收请求: {}", e) }),
+    }
+}
+
+fn with_archive(ctx: &RpcContext, query: impl FnOnce(&ArchiveIndex) -> Value) -> Value {
+    match &ctx.archive_index {
+        Some(index) => query(&index.lock().unwrap()),
+        None => json!({ "error": "该节点不是归档节点" }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Transaction;
+    use crate::rpc_auth::TokenConfig;
+
+    fn token(name: &str, token: &str, role: RpcRole) -> TokenConfig {
+        let token_sha256 = hex::encode(ring::digest::digest(&ring::digest::SHA256, token.as_bytes()));
+        TokenConfig { name: name.to_string(), token_sha256, role }
+    }
+
+    fn context(node: Sender<PBFTMessage>) -> RpcContext {
+        RpcContext {
+            node_id: 0,
+            chain: Arc::new(Mutex::new(Chain::default())),
+            archive_index: None,
+            auditor: None,
+            execution: Arc::new(Mutex::new(ExecutionEngine::new())),
+            view: Arc::new(AtomicU64::new(0)),
+            primary: Arc::new(AtomicUsize::new(0)),
+            reputation: Arc::new(Mutex::new(Reputation::default())),
+            clock_sync: Arc::new(Mutex::new(ClockSync::default())),
+            request_status: Arc::new(Mutex::new(RequestTracker::default())),
+            view_stats: Arc::new(Mutex::new(ViewStats::load(0, 0, 0))),
+            durable_height: watch::channel(0).1,
+            node,
+            auth: Arc::new(RpcAuth {
+                anonymous: RpcRole::Reader,
+                tokens: vec![token("client", "submit-token", RpcRole::Submitter), token("ops", "admin-token", RpcRole::Admin)],
+            }),
+            genesis: Genesis { chain_id: "rpc-test".to_string(), ..Genesis::default() },
+            firewall: Arc::new(Mutex::new(Firewall::default())),
+            events: EventBus::new(),
+            exit: mpsc::channel(1).0,
+            maintenance: mpsc::channel(1).0,
+            config: Arc::new(Mutex::new(NodeConfig::default())),
+            connections: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    #[tokio::test]
+    async fn methods_require_role() {
+        let (node, mut submitted) = mpsc::channel(10);
+        let ctx = context(node);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept_loop(ctx, listener));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut responses = Vec::new();
+        for request in [
+            r#"{"method":"Get","key":"k"}"#,
+            r#"{"method":"Submit","message":{"kind":"Request","operation":"SET k v"}}"#,
+            r#"{"method":"Authenticate","token":"wrong"}"#,
+            r#"{"method":"Authenticate","token":"submit-token"}"#,
+            r#"{"method":"Submit","message":{"kind":"Request","operation":"SET k v"}}"#,
+            r#"{"method":"VerifyAuditLog"}"#,
+        ].iter() {
+            writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+            let line = lines.next_line().await.unwrap().unwrap();
+            responses.push(serde_json::from_str::<Value>(&line).unwrap());
+        }
+
+        assert_eq!(responses[0]["key"], "k");
+        assert!(responses[1]["error"].as_str().unwrap().contains("Submitter"));
+        assert!(responses[2]["error"].is_string());
+        assert_eq!(responses[3]["role"], "submitter");
+        assert_eq!(responses[4]["submitted"], true);
+        assert!(responses[5]["error"].as_str().unwrap().contains("Admin"));
+        assert!(matches!(submitted.recv().await, Some(PBFTMessage::Request { .. })));
+        assert!(submitted.try_recv().is_err());
+    }
+
+    // 管理员封禁本机地址后，已有连接在下一个请求时断开，新连接被直接拒绝；读者角色无权修改规则
+    #[tokio::test]
+    async fn blocked_address_is_disconnected() {
+        let (node, _submitted) = mpsc::channel(10);
+        let ctx = context(node);
+        let firewall = ctx.firewall.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept_loop(ctx, listener));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut responses = Vec::new();
+        for request in [
+            r#"{"method":"BlockAddress","address":"127.0.0.1"}"#,
+            r#"{"method":"Authenticate","token":"admin-token"}"#,
+            r#"{"method":"BlockPeer","node_id":5}"#,
+            r#"{"method":"BlockAddress","address":"127.0.0.1"}"#,
+        ].iter() {
+            writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+            let line = lines.next_line().await.unwrap().unwrap();
+            responses.push(serde_json::from_str::<Value>(&line).unwrap());
+        }
+        assert!(responses[0]["error"].as_str().unwrap().contains("Admin"));
+        assert_eq!(responses[2]["changed"], true);
+        assert_eq!(responses[2]["firewall"]["deny_peers"], json!([5]));
+        assert_eq!(responses[3]["firewall"]["deny_addresses"], json!(["127.0.0.1"]));
+        assert!(!firewall.lock().unwrap().admits_peer(5));
+
+        writer.write_all(b"{\"method\":\"Metrics\"}\n").await.unwrap();
+        assert!(lines.next_line().await.unwrap_or(None).is_none(), "被封禁地址的连接未断开");
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert!(lines.next_line().await.unwrap_or(None).is_none(), "被封禁地址的新连接未被拒绝");
+    }
+
+    // 热加载的RPC限制：速率上限对已打开的连接立即生效，连接数达到上限后新连接被拒绝
+    #[tokio::test]
+    async fn reloaded_limits_apply_to_connections() {
+        let (node, _submitted) = mpsc::channel(10);
+        let ctx = context(node);
+        let config = ctx.config.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept_loop(ctx, listener));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut limited = Vec::new();
+        for i in 0..6 {
+            if i == 2 {
+                config.lock().unwrap().rpc_requests_per_second = 2.0;
+            }
+            writer.write_all(b"{\"method\":\"AppliedHeight\"}\n").await.unwrap();
+            let line = lines.next_line().await.unwrap().unwrap();
+            limited.push(serde_json::from_str::<Value>(&line).unwrap()["error"].is_string());
+        }
+        assert_eq!(limited, [false, false, false, false, true, true]);
+
+        config.lock().unwrap().rpc_max_connections = 1;
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut rejected = BufReader::new(stream).lines();
+        assert!(rejected.next_line().await.unwrap_or(None).is_none(), "超过连接数上限的连接未被拒绝");
+    }
+
+    // 带最低高度的读等执行追上后才答复；等不到时答复redirect，不返回旧状态
+    #[tokio::test]
+    async fn reads_wait_for_the_required_height() {
+        let (node, _submitted) = mpsc::channel(10);
+        let ctx = context(node);
+        let execution = ctx.execution.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept_loop(ctx, listener));
+        tokio::spawn(async move {
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            let transaction = Transaction { operation: "SET k written".to_string(), client_id: None, session: None, timestamp: None };
+            execution.lock().unwrap().execute_block(1, &[transaction]);
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut responses = Vec::new();
+        for request in [
+            r#"{"method":"Get","key":"k"}"#,
+            r#"{"method":"Get","key":"k","min_height":1}"#,
+            r#"{"method":"Get","key":"k","min_height":2}"#,
+        ].iter() {
+            writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+            let line = lines.next_line().await.unwrap().unwrap();
+            responses.push(serde_json::from_str::<Value>(&line).unwrap());
+        }
+        assert_eq!(responses[0], json!({ "key": "k", "value": null, "height": 0 }));
+        assert_eq!(responses[1], json!({ "key": "k", "value": "written", "height": 1 }));
+        assert_eq!(responses[2], json!({ "key": "k", "redirect": true, "applied_height": 1, "min_height": 2 }));
+    }
+
+    // 分页扫描的各页读第一页的高度，其间执行的新区块不影响后续的页；超前的高度被拒绝
+    #[tokio::test]
+    async fn scan_pages_read_one_height() {
+        let (node, _submitted) = mpsc::channel(10);
+        let ctx = context(node);
+        let execution = ctx.execution.clone();
+        let set = |key: &str, value: &str| Transaction { operation: format!("SET {} {}", key, value), client_id: None, session: None, timestamp: None };
+        execution.lock().unwrap().execute_block(1, &[set("a/1", "old"), set("a/2", "old"), set("a/3", "old"), set("b/1", "old")]);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept_loop(ctx, listener));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut responses = Vec::new();
+        for request in [
+            r#"{"method":"Scan","prefix":"a/","limit":2}"#,
+            r#"{"method":"Scan","prefix":"a/","limit":2,"height":1,"start_after":"a/2"}"#,
+            r#"{"method":"Scan","prefix":"a/"}"#,
+            r#"{"method":"Scan","height":3}"#,
+        ].iter() {
+            writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+            let line = lines.next_line().await.unwrap().unwrap();
+            responses.push(serde_json::from_str::<Value>(&line).unwrap());
+            if responses.len() == 1 {
+                execution.lock().unwrap().execute_block(2, &[set("a/3", "new"), set("a/4", "new")]);
+            }
+        }
+        assert_eq!(responses[0], json!({ "height": 1, "entries": { "a/1": "old", "a/2": "old" }, "next": "a/2" }));
+        assert_eq!(responses[1], json!({ "height": 1, "entries": { "a/3": "old" }, "next": null }));
+        assert_eq!(responses[2]["height"], 2);
+        assert_eq!(responses[2]["entries"]["a/4"], "new");
+        assert!(responses[3]["error"].as_str().unwrap().contains("尚未执行到高度3"));
+    }
+
+    // 订阅后节点发布的事件推送到同一连接，与请求的答复交错
+    #[tokio::test]
+    async fn subscribed_connection_receives_events() {
+        let (node, _submitted) = mpsc::channel(10);
+        let ctx = context(node);
+        let bus = ctx.events.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept_loop(ctx, listener));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"{\"method\":\"SubscribeEvents\"}\n").await.unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&line).unwrap()["subscribed"], true);
+
+        bus.publish(events::ConsensusEvent::PeerBlacklisted { node_id: 3 });
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&line).unwrap(), json!({ "event": "PeerBlacklisted", "node_id": 3 }));
+    }
+}
@@ -0,0 +1,86 @@
+// src/rpc_auth.rs
+
+// RPC访问控制：连接先用令牌认证，取得角色后才能调用该角色允许的方法。
+// 配置文件只保存令牌的SHA-256摘要，泄露配置文件不会泄露令牌本身
+use serde::{Serialize, Deserialize};
+use log::{info, warn};
+
+pub const RPC_AUTH_FILE: &str = "rpc_auth.json";
+
+// 角色按权限从低到高排列，高权限角色可以调用低权限角色的全部方法
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum RpcRole {
+    None,      // 未认证且配置不允许匿名访问
+    Reader,    // 查询链、状态和统计信息
+    Submitter, // 另外可以提交请求、使用客户端会话
+    Admin,     // 另外可以调用运维方法
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenConfig {
+    pub name: String,         // 仅用于日志
+    pub token_sha256: String, // 令牌的SHA-256摘要，十六进制
+    pub role: RpcRole,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RpcAuth {
+    #[serde(default = "default_anonymous")]
+    pub anonymous: RpcRole, // 未认证连接的角色
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+}
+
+fn default_anonymous() -> RpcRole {
+    RpcRole::Reader
+}
+
+impl Default for RpcAuth {
+    fn default() -> Self {
+        RpcAuth { anonymous: default_anonymous(), tokens: Vec::new() }
+    }
+}
+
+impl RpcAuth {
+    // 没有配置文件时匿名连接只能查询，提交和运维方法不可用
+    pub fn load() -> Self {
+        match std::fs::read_to_string(RPC_AUTH_FILE) {
+            Ok(data) => {
+                let auth: RpcAuth = serde_json::from_str(&data).unwrap();
+                info!("从{}加载了{}个RPC令牌，匿名连接的角色: {:?}", RPC_AUTH_FILE, auth.tokens.len(), auth.anonymous);
+                auth
+            }
+            Err(_) => {
+                warn!("未找到{}，RPC只接受匿名的查询请求", RPC_AUTH_FILE);
+                RpcAuth::default()
+            }
+        }
+    }
+
+    // 返回令牌的名称和角色
+    pub fn authenticate(&self, token: &str) -> Option<(&str, RpcRole)> {
+        let digest = hex::encode(ring::digest::digest(&ring::digest::SHA256, token.as_bytes()));
+        self.tokens.iter()
+            .find(|entry| entry.token_sha256.eq_ignore_ascii_case(&digest))
+            .map(|entry| (entry.name.as_str(), entry.role))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_map_to_roles() {
+        let auth: RpcAuth = serde_json::from_str(&format!(
+            r#"{{"anonymous":"none","tokens":[{{"name":"ops","token_sha256":"{}","role":"admin"}}]}}"#,
+            hex::encode(ring::digest::digest(&ring::digest::SHA256, b"secret")),
+        )).unwrap();
+        assert_eq!(auth.anonymous, RpcRole::None);
+        assert_eq!(auth.authenticate("secret"), Some(("ops", RpcRole::Admin)));
+        assert_eq!(auth.authenticate("guess"), None);
+        assert!(RpcRole::Admin > RpcRole::Submitter && RpcRole::Submitter > RpcRole::Reader && RpcRole::Reader > RpcRole::None);
+        assert_eq!(RpcAuth::default().anonymous, RpcRole::Reader);
+    }
+}
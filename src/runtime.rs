@@ -0,0 +1,134 @@
+// src/runtime.rs
+
+// tokio运行时参数：工作线程数、阻塞线程池大小（存储读写和签名校验等同步任务在其中运行，
+// 不占用工作线程）以及是否把工作线程绑定到固定的CPU核心
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use log::{info, warn};
+use crate::config::{WORKER_THREADS, MAX_BLOCKING_THREADS, PIN_WORKER_THREADS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    pub worker_threads: usize,
+    pub max_blocking_threads: usize,
+    pub pin_workers: bool,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        RuntimeConfig {
+            worker_threads: WORKER_THREADS,
+            max_blocking_threads: MAX_BLOCKING_THREADS,
+            pin_workers: PIN_WORKER_THREADS,
+        }
+    }
+}
+
+impl RuntimeConfig {
+    // --worker-threads 4 --blocking-threads 16 --pin-workers，未给出的参数取config.rs中的默认值
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let flag = |name: &str| args.iter().position(|s| s == name).and_then(|i| args.get(i + 1));
+        let number = |name: &str, default: usize| match flag(name) {
+            Some(value) => value.parse::<usize>().map_err(|_| format!("{}的值无效: {}", name, value)),
+            None => Ok(default),
+        };
+        let mut config = RuntimeConfig::default();
+        config.worker_threads = number("--worker-threads", config.worker_threads)?;
+        config.max_blocking_threads = number("--blocking-threads", config.max_blocking_threads)?;
+        config.pin_workers |= args.iter().any(|s| s == "--pin-workers");
+        if config.max_blocking_threads == 0 {
+            return Err("--blocking-threads至少为1".to_string());
+        }
+        Ok(config)
+    }
+
+    // 工作线程数为0时使用CPU核心数
+    pub fn effective_worker_threads(&self) -> usize {
+        if self.worker_threads > 0 {
+            self.worker_threads
+        } else {
+            available_cpus()
+        }
+    }
+
+    pub fn build(&self) -> std::io::Result<tokio::runtime::Runtime> {
+        let workers = self.effective_worker_threads();
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder
+            .enable_all()
+            .worker_threads(workers)
+            .max_blocking_threads(self.max_blocking_threads)
+            .thread_name("pbft-runtime");
+        if self.pin_workers {
+            // 运行时创建时最先启动的workers个线程就是工作线程，依次绑定到各个核心
+            let started = Arc::new(AtomicUsize::new(0));
+            let cpus = available_cpus();
+            builder.on_thread_start(move || {
+                let index = started.fetch_add(1, Ordering::Relaxed);
+                if index < workers {
+                    if let Err(reason) = pin_current_thread(index % cpus) {
+                        warn!("工作线程{}绑定CPU失败: {}", index, reason);
+                    }
+                }
+            });
+        }
+        builder.build()
+    }
+
+    pub fn log(&self) {
+        info!(
+            "运行时配置: 工作线程{}个（配置值{}），阻塞线程池上限{}，绑定CPU: {}，可用CPU核心{}个",
+            self.effective_worker_threads(), self.worker_threads, self.max_blocking_threads, self.pin_workers, available_cpus(),
+        );
+    }
+}
+
+fn available_cpus() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) -> Result<(), String> {
+    // 安全性：cpu_set_t是普通的位图，全零即为空集合；sched_setaffinity只读取传入的集合
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpu: usize) -> Result<(), String> {
+    Err("当前平台不支持绑定CPU".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_flags_and_defaults() {
+        assert_eq!(RuntimeConfig::from_args(&args(&["pbft", "0"])), Ok(RuntimeConfig::default()));
+        let config = RuntimeConfig::from_args(&args(&["pbft", "0", "--worker-threads", "2", "--blocking-threads", "8", "--pin-workers"])).unwrap();
+        assert_eq!(config, RuntimeConfig { worker_threads: 2, max_blocking_threads: 8, pin_workers: true });
+        assert_eq!(config.effective_worker_threads(), 2);
+        assert!(RuntimeConfig::from_args(&args(&["pbft", "0", "--worker-threads", "many"])).is_err());
+        assert!(RuntimeConfig::from_args(&args(&["pbft", "0", "--blocking-threads", "0"])).is_err());
+        assert!(RuntimeConfig::default().effective_worker_threads() >= 1);
+    }
+
+    #[test]
+    fn built_runtime_runs_blocking_tasks() {
+        let config = RuntimeConfig { worker_threads: 2, max_blocking_threads: 1, pin_workers: true };
+        let runtime = config.build().unwrap();
+        let sum = runtime.block_on(async { tokio::task::spawn_blocking(|| (1..=10).sum::<u32>()).await.unwrap() });
+        assert_eq!(sum, 55);
+    }
+}
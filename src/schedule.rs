@@ -0,0 +1,131 @@
+// src/schedule.rs
+
+// 定时交易：SCHEDULE <高度> <操作> 在提交时只登记，链到达指定高度时才执行其中的操作，
+// 可用于时间锁和延迟生效的治理操作。等待队列保存在复制状态中，键为 schedule/<高度>/<登记序号>，
+// 执行区块时先按高度、再按登记顺序执行所有到期的交易，然后才执行区块本身的交易，各副本顺序一致。
+// 指定的高度不晚于当前区块时立即执行；定时交易中不能再嵌套定时交易
+use std::collections::BTreeMap;
+use crate::config::MAX_SCHEDULE_DELAY;
+use crate::message::Transaction;
+use crate::quota::Sandbox;
+
+pub const KEY_PREFIX: &str = "schedule/";
+pub const SCHEDULE_COMMAND: &str = "SCHEDULE";
+const SEQUENCE_KEY: &str = "schedule/sequence"; // 已登记的定时交易数，排在所有队列键之后
+
+// 解析SCHEDULE的参数：<高度> <操作>
+pub fn parse(payload: &str) -> Result<(u64, &str), String> {
+    let (height, operation) = payload.split_once(' ').ok_or("定时交易缺少操作")?;
+    let height = height.parse().map_err(|_| format!("定时高度'{}'无效", height))?;
+    if operation.strip_prefix(SCHEDULE_COMMAND).is_some_and(|rest| rest.starts_with(' ')) {
+        return Err("定时交易不能嵌套".to_string());
+    }
+    Ok((height, operation))
+}
+
+fn queue_key(height: u64, sequence: u64) -> String {
+    format!("{}{:020}/{:020}", KEY_PREFIX, height, sequence)
+}
+
+// 在高度current登记一笔在height执行的交易
+pub fn enqueue(store: &mut Sandbox, current: u64, height: u64, transaction: &Transaction) -> Result<(), String> {
+    if height > current + MAX_SCHEDULE_DELAY {
+        return Err(format!("定时高度{}超过当前高度{}加上限{}", height, current, MAX_SCHEDULE_DELAY));
+    }
+    let sequence: u64 = store.get(SEQUENCE_KEY).and_then(|s| s.parse().ok()).unwrap_or(0) + 1;
+    store.insert(SEQUENCE_KEY.to_string(), sequence.to_string());
+    store.insert(queue_key(height, sequence), transaction.encode());
+    Ok(())
+}
+
+// 取出在height及之前到期的交易，按执行顺序排列
+pub fn take_due(store: &mut Sandbox, height: u64) -> Vec<Transaction> {
+    let end = format!("{}{:020}/~", KEY_PREFIX, height);
+    let due: Vec<String> = store.range(KEY_PREFIX.to_string()..end).map(|(key, _)| key.clone()).collect();
+    due.iter()
+        .filter_map(|key| store.remove(key))
+        .filter_map(|data| serde_json::from_str(&data).ok())
+        .collect()
+}
+
+// 尚未执行的定时交易：(执行高度, 交易)
+pub fn pending(store: &BTreeMap<String, String>) -> Vec<(u64, Transaction)> {
+    store.range(KEY_PREFIX.to_string()..SEQUENCE_KEY.to_string())
+        .filter_map(|(key, data)| {
+            let height = key.strip_prefix(KEY_PREFIX)?.split('/').next()?.parse().ok()?;
+            Some((height, serde_json::from_str(data).ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::config::N;
+    use crate::execution::{ExecutionEngine, ExecutionStatus};
+    use crate::quota::Quota;
+    use crate::testing::TestCluster;
+
+    fn transaction(operation: &str) -> Transaction {
+        Transaction { operation: operation.to_string(), client_id: None, session: None, timestamp: None }
+    }
+
+    #[test]
+    fn scheduled_operations_run_at_their_height() {
+        let mut engine = ExecutionEngine::new();
+        let results = engine.execute_block(1, &[
+            transaction("SCHEDULE 3 SET lock released"),
+            transaction("SCHEDULE 2 APPEND log a"),
+            transaction("SCHEDULE 2 APPEND log b"),
+            transaction("SCHEDULE 1 SET now 1"),
+            transaction("SCHEDULE 4 SCHEDULE 5 SET k v"),
+            transaction(&format!("DEL {}{:020}/{:020}", KEY_PREFIX, 3, 1)),
+        ]);
+        let statuses: Vec<ExecutionStatus> = results.into_iter().map(|r| r.status).collect();
+        assert_eq!(statuses[..4], [ExecutionStatus::Scheduled(3), ExecutionStatus::Scheduled(2), ExecutionStatus::Scheduled(2), ExecutionStatus::Success(None)]);
+        assert!(matches!(statuses[4], ExecutionStatus::Failed(_)));
+        assert!(matches!(statuses[5], ExecutionStatus::Failed(_)), "普通操作不能删除队列中的交易");
+        assert_eq!(engine.get("now"), Some(&"1".to_string()));
+        assert_eq!(pending(engine.state()).iter().map(|(height, _)| *height).collect::<Vec<_>>(), vec![2, 2, 3]);
+
+        // 到期的交易在区块自身的交易之前、按登记顺序执行
+        engine.execute_block(2, &[transaction("APPEND log c")]);
+        assert_eq!(engine.get("log"), Some(&"abc".to_string()));
+        assert_eq!(engine.deferred.len(), 2);
+        assert_eq!(engine.get("lock"), None);
+        // 跳过的高度上到期的交易在下一个区块执行
+        engine.execute_block(4, &[]);
+        assert_eq!(engine.get("lock"), Some(&"released".to_string()));
+        assert!(pending(engine.state()).is_empty());
+        assert!(enqueue(&mut Sandbox::new(&mut BTreeMap::new(), Quota::default()), 1, 2 + MAX_SCHEDULE_DELAY, &transaction("SET k v")).is_err());
+    }
+
+    // 登记在三个区块之后的操作在链到达该高度时才在各节点上执行
+    #[tokio::test]
+    async fn cluster_executes_scheduled_operation_at_height() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            let applied = |c: &TestCluster| c.executions[0].lock().unwrap().applied_height();
+            let target = applied(&cluster) + 3;
+            cluster.submit(&format!("SCHEDULE {} SET timelock open", target)).await;
+            for i in 0.. {
+                let operation = format!("SET filler {}", i);
+                cluster.submit(&operation).await;
+                let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                    (0..N).all(|id| c.committed_view(id, &operation).is_some())
+                }).await;
+                assert!(committed, "{}未提交", operation);
+                let height = applied(&cluster);
+                if height >= target {
+                    break;
+                }
+                assert_eq!(cluster.executions[0].lock().unwrap().get("timelock"), None, "高度{}就执行了定时交易", height);
+            }
+            let opened = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.executions[id].lock().unwrap().get("timelock").map(String::as_str) == Some("open"))
+            }).await;
+            assert!(opened, "定时交易未在指定高度执行");
+        }).await;
+    }
+}
@@ -0,0 +1,163 @@
+// src/session.rs
+
+// 客户端会话：请求携带会话ID和会话内递增的序号，执行引擎记录每个会话已执行的序号及其结果。
+// 重复提交同一序号的请求不会再次执行，而是得到第一次执行的结果，因此客户端可以放心重试；
+// 会话记录保存在复制状态中，客户端断线或重启后凭会话ID查询哪些请求已经提交
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::config::SESSION_RESULT_WINDOW;
+use crate::execution::ExecutionStatus;
+use crate::quota::Sandbox;
+
+// 会话记录保存在复制状态机中，键为 session/<会话ID>，普通操作不能读写这些键
+pub const KEY_PREFIX: &str = "session/";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionTag {
+    pub session_id: String,
+    pub sequence: u64, // 从1开始
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionState {
+    pub results: BTreeMap<u64, ExecutionStatus>, // 最近SESSION_RESULT_WINDOW个已执行序号的结果
+    pub evicted_through: u64, // 不大于该值且不在results中的序号都已执行，结果已淘汰
+}
+
+impl SessionState {
+    // 序号已执行时返回其结果
+    pub fn lookup(&self, sequence: u64) -> Option<ExecutionStatus> {
+        if let Some(status) = self.results.get(&sequence) {
+            return Some(status.clone());
+        }
+        if sequence <= self.evicted_through {
+            return Some(ExecutionStatus::Failed(format!("会话序号{}已执行，结果已淘汰", sequence)));
+        }
+        None
+    }
+
+    pub fn record(&mut self, sequence: u64, status: ExecutionStatus) {
+        self.results.insert(sequence, status);
+        while self.results.len() > SESSION_RESULT_WINDOW {
+            let (oldest, _) = self.results.pop_first().unwrap();
+            self.evicted_through = self.evicted_through.max(oldest);
+        }
+    }
+
+    // 客户端恢复会话时应使用的下一个序号
+    pub fn next_sequence(&self) -> u64 {
+        self.results.keys().next_back().copied().unwrap_or(0).max(self.evicted_through) + 1
+    }
+}
+
+pub fn key(session_id: &str) -> String {
+    format!("{}{}", KEY_PREFIX, session_id)
+}
+
+pub fn load(store: &BTreeMap<String, String>, session_id: &str) -> SessionState {
+    store.get(&key(session_id))
+        .and_then(|data| serde_json::from_str(data).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(store: &mut Sandbox, session_id: &str, state: &SessionState) {
+    store.insert(key(session_id), serde_json::to_string(state).unwrap());
+}
+
+pub fn new_session_id() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::execution::ExecutionEngine;
+    use crate::message::{PBFTMessage, ReplyOutcome, Transaction};
+    use crate::network;
+    use crate::qos::Priority;
+    use crate::testing::TestCluster;
+
+    fn tagged(operation: &str, sequence: u64) -> Transaction {
+        let session = Some(SessionTag { session_id: "s1".to_string(), sequence });
+        Transaction { operation: operation.to_string(), client_id: None, session, timestamp: None }
+    }
+
+    #[test]
+    fn retried_sequence_executes_once() {
+        let mut engine = ExecutionEngine::new();
+        let results = engine.execute_block(1, &[tagged("APPEND k a", 1), tagged("APPEND k a", 1), tagged("GET k", 2)]);
+        assert_eq!(results[1].status, ExecutionStatus::Success(None));
+        assert_eq!(results[1].gas_used, 0);
+        assert_eq!(results[2].status, ExecutionStatus::Success(Some("a".to_string())));
+        // 后来的区块里重试同一序号，仍得到第一次执行的结果
+        let retried = engine.execute_block(2, &[tagged("GET k", 2)]);
+        assert_eq!(retried[0].status, ExecutionStatus::Success(Some("a".to_string())));
+        assert_eq!(load(engine.state(), "s1").next_sequence(), 3);
+        // 会话记录不能被普通操作改写
+        let forged = engine.execute_block(3, &[tagged(&format!("DEL {}", key("s1")), 3)]);
+        assert!(matches!(forged[0].status, ExecutionStatus::Failed(_)));
+    }
+
+    #[test]
+    fn evicted_results_still_count_as_executed() {
+        let mut state = SessionState::default();
+        for sequence in 1..=(SESSION_RESULT_WINDOW as u64 + 2) {
+            state.record(sequence, ExecutionStatus::Success(None));
+        }
+        assert_eq!(state.results.len(), SESSION_RESULT_WINDOW);
+        assert_eq!(state.evicted_through, 2);
+        assert!(matches!(state.lookup(1), Some(ExecutionStatus::Failed(_))));
+        assert_eq!(state.lookup(3), Some(ExecutionStatus::Success(None)));
+        assert_eq!(state.lookup(SESSION_RESULT_WINDOW as u64 + 3), None);
+        assert_eq!(state.next_sequence(), SESSION_RESULT_WINDOW as u64 + 3);
+    }
+
+    fn request(operation: &str, sequence: u64) -> PBFTMessage {
+        PBFTMessage::Request {
+            operation: operation.to_string(),
+            priority: Priority::Normal,
+            client_id: Some("alice".to_string()),
+            expires_at: None,
+            session: Some(SessionTag { session_id: "s1".to_string(), sequence }),
+            timestamp: None,
+        }
+    }
+
+    async fn reply(replies: &mut mpsc::Receiver<PBFTMessage>, sequence: u64) -> ReplyOutcome {
+        loop {
+            match tokio::time::timeout(Duration::from_secs(10), replies.recv()).await {
+                Ok(Some(PBFTMessage::Reply { session: Some(tag), outcome, .. })) if tag.sequence == sequence => return outcome,
+                Ok(Some(_)) => continue,
+                _ => panic!("未收到序号{}的答复", sequence),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn client_restart_resends_without_double_execution() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            let (sender, mut replies) = mpsc::channel(100);
+            network::register_client("alice", sender);
+            cluster.submit_request(request("APPEND k a", 1)).await;
+            assert_eq!(reply(&mut replies, 1).await, ReplyOutcome::Executed(ExecutionStatus::Success(None)));
+
+            // 客户端重启：用新连接恢复会话，查询已提交的序号，再重发它不确定是否提交的请求
+            let (sender, mut replies) = mpsc::channel(100);
+            network::register_client("alice", sender);
+            let state = load(cluster.executions[0].lock().unwrap().state(), "s1");
+            assert_eq!(state.next_sequence(), 2);
+            cluster.submit_request(request("APPEND k a", 1)).await;
+            assert_eq!(reply(&mut replies, 1).await, ReplyOutcome::Executed(ExecutionStatus::Success(None)));
+            cluster.submit_request(request("GET k", 2)).await;
+            assert_eq!(reply(&mut replies, 2).await, ReplyOutcome::Executed(ExecutionStatus::Success(Some("a".to_string()))));
+            for id in 0..N {
+                assert_eq!(cluster.executions[id].lock().unwrap().get("k"), Some(&"a".to_string()));
+            }
+        }).await;
+    }
+}
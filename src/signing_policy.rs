@@ -0,0 +1,227 @@
+// src/signing_policy.rs
+
+// 按消息类型选择认证方式，用于在同一套代码上比较认证开销。策略写在创世配置中，全网一致：
+//   "signing_policy": {"default": "signature", "kinds": {"Ping": "mac", "Pong": "mac", "Leave": "none"}}
+// - signature：Ed25519签名，任何人都能验证，可以转交第三方作为证书或证据
+// - mac：发送者为每个接收方计算一个HMAC-SHA256（PBFT论文中的认证向量），密钥由双方的签名密钥经
+//   Diffie-Hellman导出。MAC只能向接收方本人证明发送者，不能记入提交证书或拉黑证据
+// - none：不认证，只适用于封闭的测试网络
+// 发送方按策略认证，接收方拒绝弱于策略的消息；比策略更强的认证总是接受。
+// PrePrepare、Prepare和Commit的签名组成视图切换时转交的准备证书和提交证书，ViewChange和NewView中的
+// 签名是视图切换安全性的依据，这五类消息必须签名
+use std::collections::{BTreeMap, HashMap};
+use serde::{Serialize, Deserialize};
+use ring::hmac;
+use crate::crypto::{PublicKey, SigningKey};
+
+// 经由sign_message发出的消息类型
+const POLICY_KINDS: &[&str] = &[
+    "PrePrepare", "PrePrepareDigests", "Prepare", "PrepareCertificate", "Commit", "ViewChange", "NewView",
+    "Checkpoint", "SnapshotOffer", "Ping", "Pong", "ByzantineVote", "Appeal", "Leave",
+    "Maintenance",
+];
+const SIGNATURE_REQUIRED: &[&str] = &["PrePrepare", "Prepare", "Commit", "ViewChange", "NewView"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Authentication {
+    #[serde(rename = "none")]
+    Unauthenticated,
+    Mac,
+    #[default]
+    Signature,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SigningPolicy {
+    #[serde(default)]
+    pub default: Authentication,
+    #[serde(default)]
+    pub kinds: BTreeMap<String, Authentication>,
+}
+
+impl SigningPolicy {
+    pub fn for_kind(&self, kind: &str) -> Authentication {
+        self.kinds.get(kind).copied().unwrap_or(self.default)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(kind) = self.kinds.keys().find(|kind| !POLICY_KINDS.contains(&kind.as_str())) {
+            return Err(format!("签名策略中的{}不是签名发出的消息类型", kind));
+        }
+        match SIGNATURE_REQUIRED.iter().find(|kind| self.for_kind(kind) != Authentication::Signature) {
+            Some(kind) => Err(format!("{}必须签名", kind)),
+            None => Ok(()),
+        }
+    }
+
+    // 被弱化为MAC或不认证的消息类型，启动时提示
+    pub fn weakened(&self) -> Vec<(&'static str, Authentication)> {
+        POLICY_KINDS.iter()
+            .map(|kind| (*kind, self.for_kind(kind)))
+            .filter(|(_, authentication)| *authentication != Authentication::Signature)
+            .collect()
+    }
+}
+
+// 不签名的消息附带的认证信息
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "scheme", rename_all = "snake_case")]
+pub enum Authenticator {
+    Mac { tags: BTreeMap<usize, String> }, // 接收节点ID -> 十六进制的HMAC-SHA256
+    #[serde(rename = "none")]
+    Unauthenticated,
+}
+
+impl Authenticator {
+    pub fn strength(&self) -> Authentication {
+        match self {
+            Authenticator::Mac { .. } => Authentication::Mac,
+            Authenticator::Unauthenticated => Authentication::Unauthenticated,
+        }
+    }
+}
+
+// 与各对等节点共享的MAC密钥，对方的公钥变化时重新导出
+#[derive(Default)]
+pub struct MacKeys {
+    keys: HashMap<usize, (PublicKey, hmac::Key)>,
+}
+
+impl MacKeys {
+    fn key(&mut self, own: &SigningKey, peer: usize, public_key: &PublicKey) -> &hmac::Key {
+        let stale = self.keys.get(&peer).map_or(true, |(known, _)| known != public_key);
+        if stale {
+            let secret = own.shared_secret(public_key);
+            self.keys.insert(peer, (*public_key, hmac::Key::new(hmac::HMAC_SHA256, &secret[..])));
+        }
+        &self.keys[&peer].1
+    }
+
+    // 为除自己以外的每个已知节点计算一个MAC
+    pub fn authenticate(&mut self, own: &SigningKey, own_id: usize, peers: &HashMap<usize, PublicKey>, payload: &[u8]) -> Authenticator {
+        let tags = peers.iter()
+            .filter(|(peer, _)| **peer != own_id)
+            .map(|(peer, public_key)| (*peer, hex::encode(hmac::sign(self.key(own, *peer, public_key), payload).as_ref())))
+            .collect();
+        Authenticator::Mac { tags }
+    }
+
+    // 校验认证向量中发给本节点的MAC
+    pub fn verify(&mut self, own: &SigningKey, own_id: usize, sender: usize, sender_key: &PublicKey, tags: &BTreeMap<usize, String>, payload: &[u8]) -> bool {
+        let tag = match tags.get(&own_id).and_then(|tag| hex::decode(tag).ok()) {
+            Some(tag) => tag,
+            None => return false,
+        };
+        hmac::verify(self.key(own, sender, sender_key), payload, &tag).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::chain;
+    use crate::config::N;
+    use crate::message::{PBFTMessage, Transaction};
+    use crate::metrics;
+    use crate::network::{self, LinkFaults, LinkOverride, NetworkFaults};
+    use crate::testing::TestCluster;
+
+    #[test]
+    fn policy_validation_and_mac_vectors() {
+        let policy: SigningPolicy = serde_json::from_str(r#"{"kinds": {"Ping": "mac", "Leave": "none"}}"#).unwrap();
+        assert_eq!(policy.for_kind("Ping"), Authentication::Mac);
+        assert_eq!(policy.for_kind("Leave"), Authentication::Unauthenticated);
+        assert_eq!(policy.for_kind("Commit"), Authentication::Signature);
+        assert!(policy.validate().is_ok());
+        // 弱化的投票无法证明已准备或已提交的请求，视图切换后会丢失
+        for kind in ["PrePrepare", "Prepare", "Commit"].iter() {
+            let weakened = SigningPolicy { kinds: std::iter::once((kind.to_string(), Authentication::Mac)).collect(), ..SigningPolicy::default() };
+            assert!(weakened.validate().unwrap_err().contains(kind));
+        }
+        assert!(serde_json::from_str::<SigningPolicy>(r#"{"kinds": {"Preprare": "mac"}}"#).unwrap().validate().is_err());
+        assert!(serde_json::from_str::<SigningPolicy>(r#"{"default": "mac"}"#).unwrap().validate().is_err());
+
+        let keys: Vec<SigningKey> = (0..3).map(|_| SigningKey::generate()).collect();
+        let public_keys: HashMap<usize, PublicKey> = keys.iter().enumerate().map(|(id, k)| (id, k.public_key())).collect();
+        let tags = match MacKeys::default().authenticate(&keys[0], 0, &public_keys, b"Prepare") {
+            Authenticator::Mac { tags } => tags,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(tags.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+        let mut receiver = MacKeys::default();
+        assert!(receiver.verify(&keys[1], 1, 0, &public_keys[&0], &tags, b"Prepare"));
+        assert!(!receiver.verify(&keys[1], 1, 0, &public_keys[&0], &tags, b"Commit"));
+        // 发给节点2的MAC不能让节点1相信，第三方也无法冒充发送者
+        let mut forwarded = tags.clone();
+        forwarded.insert(1, tags[&2].clone());
+        assert!(!receiver.verify(&keys[1], 1, 0, &public_keys[&0], &forwarded, b"Prepare"));
+        assert!(!receiver.verify(&keys[1], 1, 2, &public_keys[&2], &tags, b"Prepare"));
+    }
+
+    fn counter(name: &str) -> u64 {
+        metrics::snapshot().get(name).copied().unwrap_or(0)
+    }
+
+    // Ping和Pong只用MAC认证时，集群照常提交
+    #[tokio::test]
+    async fn cluster_commits_with_mac_authenticated_pings() {
+        tokio::task::LocalSet::new().run_until(async {
+            let policy: SigningPolicy = serde_json::from_str(r#"{"kinds": {"Ping": "mac", "Pong": "mac"}}"#).unwrap();
+            let cluster = TestCluster::builder().signing_policy(policy).build().await;
+            let verified = counter("mac_verified_total");
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| c.committed_view(id, "SET k v") == Some(0))
+            }).await;
+            assert!(committed, "使用MAC认证的集群未能提交");
+            let authenticated = cluster.wait_until(Duration::from_secs(5), |_| counter("mac_verified_total") > verified).await;
+            assert!(authenticated, "未收到MAC认证的Ping");
+
+            // 弱于策略的认证被拒绝
+            let rejected = counter("authentication_rejected_total");
+            let forged = PBFTMessage::AuthenticatedMessage {
+                message: Box::new(PBFTMessage::Commit { view: 0, sequence_number: 2, digest: "forged".to_string() }),
+                authenticator: Authenticator::Unauthenticated,
+                sender_id: 0,
+                trace: None,
+            };
+            cluster.inject(1, forged).await;
+            let dropped = cluster.wait_until(Duration::from_secs(2), |_| counter("authentication_rejected_total") > rejected).await;
+            assert!(dropped, "未认证的Commit未被拒绝");
+        }).await;
+    }
+
+    // 策略不允许弱化Prepare，其余消息弱化时，视图0中已准备的请求在新视图中以同一摘要重新提议
+    #[tokio::test]
+    async fn prepared_digest_is_reproposed_under_weakened_policy() {
+        tokio::task::LocalSet::new().run_until(async {
+            let rejected: SigningPolicy = serde_json::from_str(r#"{"kinds": {"Prepare": "mac"}}"#).unwrap();
+            assert!(rejected.validate().is_err(), "MAC认证的Prepare凑不成准备证书");
+            let policy: SigningPolicy = serde_json::from_str(r#"{"kinds": {"Ping": "mac", "Pong": "mac", "Leave": "none"}}"#).unwrap();
+            let cluster = TestCluster::builder().signing_policy(policy).build().await;
+            cluster.crash(0);
+            network::set_faults(NetworkFaults {
+                global: LinkFaults::default(),
+                links: (1..3).map(|from| LinkOverride { from, to: 3, faults: LinkFaults { drop: 1.0, ..Default::default() } }).collect(),
+            });
+            let transactions = vec![Transaction { operation: "SET prepared yes".to_string(), client_id: None, session: None, timestamp: None }];
+            let digest = chain::digest_transactions(cluster.genesis.hasher(), &transactions);
+            let pre_prepare = PBFTMessage::PrePrepare { view: 0, sequence_number: 1, digest: digest.clone(), transactions };
+            for id in 1..3 {
+                cluster.inject(id, cluster.signed(0, pre_prepare.clone())).await;
+            }
+            let prepared = cluster.wait_until(Duration::from_secs(5), |c| {
+                (1..3).all(|id| c.states[id].lock().unwrap().prepared.contains(&(1, digest.clone())))
+            }).await;
+            assert!(prepared, "节点1和2未能进入Prepared");
+            network::set_faults(NetworkFaults::default());
+
+            let reproposed = cluster.wait_until(Duration::from_secs(15), |c| {
+                (1..N).all(|id| c.chains[id].lock().unwrap().blocks.iter().any(|block| block.header.view >= 1 && block.header.digest == digest))
+            }).await;
+            assert!(reproposed, "已准备的摘要未在新视图中重新提议");
+        }).await;
+    }
+}
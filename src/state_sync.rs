@@ -0,0 +1,198 @@
+// src/state_sync.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use crate::chain::BlockHeader;
+use crate::domain;
+use crate::hash::Hasher;
+use crate::quorum::WEAK_QUORUM;
+use crate::config::{SNAPSHOT_CHUNK_SIZE, STATE_SYNC_MAX_IN_FLIGHT, STATE_SYNC_CHUNK_TIMEOUT_MS};
+
+// 应用状态快照：键值存储及其对应的区块头（锚点）
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StateSnapshot {
+    pub header: Option<BlockHeader>,
+    pub store: BTreeMap<String, String>,
+}
+
+impl StateSnapshot {
+    pub fn save(&self, node_id: usize) {
+        let filename = format!("node_{}_snapshot.json", node_id);
+        let data = serde_json::to_string(self).unwrap();
+        std::fs::write(filename, data).unwrap();
+    }
+
+    pub fn load(node_id: usize) -> Option<Self> {
+        let filename = format!("node_{}_snapshot.json", node_id);
+        let data = std::fs::read_to_string(filename).ok()?;
+        Some(serde_json::from_str(&data).unwrap())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+}
+
+// 快照清单：整体哈希及每个定长分块的哈希
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotManifest {
+    pub height: u64,
+    pub snapshot_hash: String,
+    pub chunk_size: usize,
+    pub chunk_hashes: Vec<String>,
+}
+
+impl SnapshotManifest {
+    pub fn build(hasher: &dyn Hasher, height: u64, data: &[u8]) -> Self {
+        SnapshotManifest {
+            height,
+            snapshot_hash: hasher.domain_digest(domain::SNAPSHOT, data),
+            chunk_size: SNAPSHOT_CHUNK_SIZE,
+            chunk_hashes: data.chunks(SNAPSHOT_CHUNK_SIZE).map(|chunk| hasher.domain_digest(domain::SNAPSHOT_CHUNK, chunk)).collect(),
+        }
+    }
+
+    pub fn chunk<'a>(&self, data: &'a [u8], index: usize) -> Option<&'a [u8]> {
+        data.chunks(self.chunk_size).nth(index)
+    }
+}
+
+// 下载方的同步进度。已校验的分块持久化到磁盘，中断后从缺失的分块继续
+#[derive(Serialize, Deserialize, Default)]
+pub struct StateSync {
+    pub manifest: Option<SnapshotManifest>,
+    pub chunks: BTreeMap<usize, Vec<u8>>,
+    // 对端节点ID -> 其提供的快照清单
+    #[serde(skip)]
+    pub offers: HashMap<usize, SnapshotManifest>,
+    // 分块序号 -> (负责的对端节点, 请求时间)
+    #[serde(skip)]
+    pub in_flight: HashMap<usize, (usize, Instant)>,
+    #[serde(skip)]
+    next_peer: usize,
+    // 因状态分叉而重新同步：本地执行状态已作废，快照不必高于本地高度
+    #[serde(skip)]
+    pub repair: bool,
+}
+
+impl StateSync {
+    pub fn for_repair() -> Self {
+        StateSync { repair: true, ..StateSync::default() }
+    }
+
+    pub fn save(&self, node_id: usize) {
+        let filename = format!("node_{}_sync.json", node_id);
+        let data = serde_json::to_string(self).unwrap();
+        std::fs::write(filename, data).unwrap();
+    }
+
+    // 仅在存在未完成的同步进度时返回
+    pub fn load(node_id: usize) -> Option<Self> {
+        let filename = format!("node_{}_sync.json", node_id);
+        let data = std::fs::read_to_string(filename).ok()?;
+        Some(serde_json::from_str(&data).unwrap())
+    }
+
+    pub fn remove(node_id: usize) {
+        let _ = std::fs::remove_file(format!("node_{}_sync.json", node_id));
+    }
+
+    // 记录对端提供的清单。单个对端可能作恶，只有超过F个对端给出相同清单时才采用；
+    // 多个清单同时满足条件时取高度最高的。返回是否采用了新的清单
+    pub fn offer(&mut self, peer: usize, manifest: SnapshotManifest) -> bool {
+        self.offers.insert(peer, manifest);
+        // 当前清单仍有对端提供时继续使用，避免下载途中频繁切换
+        if !self.providers().is_empty() {
+            return false;
+        }
+
+        let agreed = self.offers.values()
+            .filter(|candidate| self.offers.values().filter(|o| o == candidate).count() >= WEAK_QUORUM)
+            .max_by_key(|candidate| candidate.height)
+            .cloned();
+        match agreed {
+            Some(manifest) => {
+                // 续传时若清单已变化，之前下载的分块作废
+                if self.manifest.as_ref() != Some(&manifest) {
+                    self.chunks.clear();
+                }
+                self.manifest = Some(manifest);
+                self.in_flight.clear();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 提供当前清单的对端，只向它们请求分块
+    pub fn providers(&self) -> Vec<usize> {
+        let mut peers: Vec<usize> = self.offers.iter()
+            .filter(|(_, o)| Some(*o) == self.manifest.as_ref())
+            .map(|(peer, _)| *peer)
+            .collect();
+        peers.sort_unstable();
+        peers
+    }
+
+    // 为缺失的分块分配对端：轮流使用各提供者以并行下载，超时的请求改向下一个提供者
+    pub fn next_requests(&mut self) -> Vec<(usize, usize)> {
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest,
+            None => return Vec::new(),
+        };
+        let providers = self.providers();
+        if providers.is_empty() {
+            return Vec::new();
+        }
+
+        let timeout = Duration::from_millis(STATE_SYNC_CHUNK_TIMEOUT_MS);
+        self.in_flight.retain(|_, (_, requested)| requested.elapsed() < timeout);
+
+        let mut requests = Vec::new();
+        for index in 0..manifest.chunk_hashes.len() {
+            if self.in_flight.len() >= STATE_SYNC_MAX_IN_FLIGHT {
+                break;
+            }
+            if self.chunks.contains_key(&index) || self.in_flight.contains_key(&index) {
+                continue;
+            }
+            let peer = providers[self.next_peer % providers.len()];
+            self.next_peer += 1;
+            self.in_flight.insert(index, (peer, Instant::now()));
+            requests.push((peer, index));
+        }
+        requests
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.manifest.as_ref().is_some_and(|m| self.chunks.len() == m.chunk_hashes.len())
+    }
+
+    // 按清单校验分块哈希，通过后保存
+    pub fn accept_chunk(&mut self, hasher: &dyn Hasher, height: u64, index: usize, data: Vec<u8>) -> Result<(), String> {
+        let manifest = self.manifest.as_ref().ok_or("尚未确定快照清单")?;
+        if height != manifest.height {
+            return Err(format!("分块高度{}与清单高度{}不符", height, manifest.height));
+        }
+        let expected = manifest.chunk_hashes.get(index)
+            .ok_or_else(|| format!("分块序号{}超出范围", index))?;
+        if hasher.domain_digest(domain::SNAPSHOT_CHUNK, &data) != *expected {
+            return Err(format!("分块{}哈希校验失败", index));
+        }
+
+        self.in_flight.remove(&index);
+        self.chunks.insert(index, data);
+        Ok(())
+    }
+
+    // 拼接全部分块，校验整体哈希后解码快照
+    pub fn assemble(&self, hasher: &dyn Hasher) -> Result<StateSnapshot, String> {
+        let manifest = self.manifest.as_ref().ok_or("尚未确定快照清单")?;
+        let data: Vec<u8> = self.chunks.values().flatten().copied().collect();
+        if hasher.domain_digest(domain::SNAPSHOT, &data) != manifest.snapshot_hash {
+            return Err("快照整体哈希校验失败".to_string());
+        }
+        serde_json::from_slice(&data).map_err(|e| format!("快照解码失败: {}", e))
+    }
+}
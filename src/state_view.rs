@@ -0,0 +1,115 @@
+// src/state_view.rs
+
+// 状态快照视图：StateView持有某一高度的状态的共享引用，取得时只需短暂持有执行引擎的锁，之后的读取和遍历不持锁，
+// 执行继续推进也不会读到只执行了一部分的区块。执行引擎在当前状态上原地执行区块，状态仍被视图引用时才先复制一份；
+// 每个高度只记下被修改的键修改前的值（Diff），最近STATE_VIEW_HISTORY个高度的视图在被读取时由当前状态逐个退回还原。
+// 分页扫描的各页和状态快照的导出都读同一高度
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+
+// 一个区块对状态的修改：键 -> 执行该区块前的值，None表示此前不存在
+pub type Diff = BTreeMap<String, Option<String>>;
+
+// 撤销一个区块的修改，把状态退回上一高度
+pub fn revert(store: &mut BTreeMap<String, String>, diff: &Diff) {
+    for (key, previous) in diff {
+        match previous {
+            Some(value) => store.insert(key.clone(), value.clone()),
+            None => store.remove(key),
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateView {
+    height: u64,
+    store: Arc<BTreeMap<String, String>>,
+}
+
+// 扫描的一页：next为下一页的start_after，已扫描到最后时为空
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub height: u64,
+    pub entries: BTreeMap<String, String>,
+    pub next: Option<String>,
+}
+
+impl StateView {
+    pub fn new(height: u64, store: Arc<BTreeMap<String, String>>) -> Self {
+        StateView { height, store }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn store(&self) -> &BTreeMap<String, String> {
+        &self.store
+    }
+
+    // 按键的顺序遍历以prefix开头、大于start_after的键值
+    pub fn range<'a>(&'a self, prefix: &'a str, start_after: Option<&'a str>) -> impl Iterator<Item = (&'a String, &'a String)> + 'a {
+        let start = match start_after {
+            Some(after) if after >= prefix => Bound::Excluded(after),
+            _ => Bound::Included(prefix),
+        };
+        self.store.range::<str, _>((start, Bound::Unbounded)).take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
+    pub fn scan(&self, prefix: &str, start_after: Option<&str>, limit: usize) -> Page {
+        let mut entries = self.range(prefix, start_after);
+        let page: BTreeMap<String, String> = entries.by_ref().take(limit).map(|(key, value)| (key.clone(), value.clone())).collect();
+        let next = match entries.next() {
+            Some(_) => page.keys().next_back().cloned(),
+            None => None,
+        };
+        Page { height: self.height, entries: page, next }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::execution::ExecutionEngine;
+    use crate::message::Transaction;
+
+    fn set(key: &str, value: &str) -> Transaction {
+        Transaction { operation: format!("SET {} {}", key, value), client_id: None, session: None, timestamp: None }
+    }
+
+    // 分页扫描期间执行继续推进：各页都读第一页的高度，新写入的键和修改的值不出现在扫描结果中
+    #[test]
+    fn pages_read_one_height_while_execution_continues() {
+        let mut engine = ExecutionEngine::new();
+        engine.execute_block(1, &(0..5).map(|i| set(&format!("user/{}", i), "old")).collect::<Vec<_>>());
+        engine.execute_block(2, &[set("other", "x")]);
+
+        let first = engine.view(None).unwrap().scan("user/", None, 2);
+        assert_eq!(first.height, 2);
+        assert_eq!(first.entries.keys().collect::<Vec<_>>(), ["user/0", "user/1"]);
+        assert_eq!(first.next.as_deref(), Some("user/1"));
+
+        engine.execute_block(3, &[set("user/3", "new"), set("user/10", "new")]);
+        let mut scanned = first.entries;
+        let mut next = first.next;
+        while let Some(after) = next {
+            let page = engine.view(Some(2)).unwrap().scan("user/", Some(&after), 2);
+            assert_eq!(page.height, 2);
+            scanned.extend(page.entries);
+            next = page.next;
+        }
+        assert_eq!(scanned.len(), 5);
+        assert!(scanned.values().all(|value| value == "old"), "{:?}", scanned);
+        assert_eq!(engine.view(None).unwrap().scan("user/", None, 100).entries.len(), 6);
+        assert_eq!(engine.view(None).unwrap().scan("user/", Some("user/9"), 100).next, None);
+        assert_eq!(engine.view(Some(1)).unwrap().store().get("other"), None);
+        assert!(engine.view(Some(4)).is_err());
+
+        for height in 4..4 + crate::config::STATE_VIEW_HISTORY as u64 {
+            engine.execute_block(height, &[]);
+        }
+        let evicted = engine.view(Some(2)).unwrap_err();
+        assert!(evicted.contains("已淘汰"), "{}", evicted);
+    }
+}
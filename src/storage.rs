@@ -0,0 +1,287 @@
+// src/storage.rs
+
+// 共识与存储之间的流水线：提交区块时只把区块追加到预写日志node_<ID>_blocks.wal（每行一个区块），
+// 整条链由有序的存储写入任务在后台写入node_<ID>_chain.json并fsync，共识不必等待落盘。
+// 已提交高度是内存中链的高度，持久高度是链文件已fsync到的高度，两者之间的区块都在预写日志中，
+// 进程崩溃后启动时从日志重放。写入任务跟不上时，已提交而未持久的区块最多STORAGE_PIPELINE_DEPTH个，
+// 再提交时等待写入完成（背压）。写入请求经容量为1的队列合并：每次写入都写出写入开始时的整条链，
+// 排队中的请求由下一次写入覆盖
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use log::{debug, error, info};
+use tokio::sync::{mpsc, watch};
+use tokio::time::Instant;
+use crate::chain::{Block, Chain};
+use crate::chain_index;
+use crate::metrics;
+
+pub fn wal_file(directory: &Path, node_id: usize) -> PathBuf {
+    directory.join(format!("node_{}_blocks.wal", node_id))
+}
+
+// 预写日志：尚未写入链文件的区块，按高度排列
+pub struct Wal {
+    path: PathBuf,
+    entries: Vec<(u64, String)>,
+}
+
+impl Wal {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // 追加一个区块。只写入操作系统缓冲区，进程崩溃不会丢失；断电时靠共识日志发现缺口并从对端补齐
+    pub fn append(&mut self, block: &Block) -> Result<(), String> {
+        let line = serde_json::to_string(block).map_err(|e| e.to_string())?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+        self.entries.push((block.header.height, line));
+        Ok(())
+    }
+
+    // 链文件已持久到height，丢弃此前的区块
+    pub fn truncate_through(&mut self, height: u64) -> Result<(), String> {
+        let before = self.entries.len();
+        self.entries.retain(|(h, _)| *h > height);
+        if self.entries.len() == before {
+            return Ok(());
+        }
+        if self.entries.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+                _ => Ok(()),
+            };
+        }
+        let data: String = self.entries.iter().map(|(_, line)| format!("{}\n", line)).collect();
+        write_atomically(&self.path, data.as_bytes(), false)
+    }
+}
+
+// 从预写日志重放链文件之后的区块，只接受高度连续、与前一个区块哈希链接的区块；
+// 返回的日志保留全部尚未写入链文件的区块
+pub fn recover(directory: &Path, node_id: usize, chain: &mut Chain) -> Wal {
+    let path = wal_file(directory, node_id);
+    let mut wal = Wal { path, entries: Vec::new() };
+    let data = match std::fs::read_to_string(&wal.path) {
+        Ok(data) => data,
+        Err(_) => return wal,
+    };
+    let hasher = chain.hash_function.hasher();
+    for line in data.lines() {
+        let block: Block = match serde_json::from_str(line) {
+            Ok(block) => block,
+            // 崩溃时写了一半的最后一行
+            Err(_) => break,
+        };
+        if block.header.height <= chain.height() {
+            continue;
+        }
+        let prev_hash = chain.tip().map(|header| header.hash(hasher)).unwrap_or_else(|| "0".repeat(64));
+        if block.header.height != chain.height() + 1 || block.header.prev_hash != prev_hash {
+            error!("节点{}预写日志中高度{}的区块与本地链不连续，停止重放", node_id, block.header.height);
+            break;
+        }
+        wal.entries.push((block.header.height, line.to_string()));
+        chain.push_verified(block);
+    }
+    if !wal.entries.is_empty() {
+        info!("节点{}从预写日志恢复{}个区块，链高度: {}", node_id, wal.entries.len(), chain.height());
+        metrics::inc_counter("storage_wal_recovered_blocks_total", wal.entries.len() as u64);
+    }
+    wal
+}
+
+// 有序的存储写入任务的句柄
+pub struct StorageWriter {
+    node_id: usize,
+    directory: PathBuf, // 与预写日志相同的数据目录，写入任务不受之后改变的当前目录影响
+    chain: Arc<Mutex<Chain>>,
+    wal: Arc<Mutex<Wal>>,
+    requests: mpsc::Sender<()>,
+    pending: Option<(mpsc::Receiver<()>, watch::Sender<u64>)>, // 任务启动前的接收端
+    durable: watch::Receiver<u64>,
+}
+
+impl StorageWriter {
+    pub fn new(node_id: usize, directory: PathBuf, chain: Arc<Mutex<Chain>>, wal: Wal) -> Self {
+        let durable_height = chain.lock().unwrap().height() - wal.len() as u64;
+        let (requests, receiver) = mpsc::channel(1);
+        let (durable_sender, durable) = watch::channel(durable_height);
+        let writer = StorageWriter {
+            node_id,
+            directory,
+            chain,
+            wal: Arc::new(Mutex::new(wal)),
+            requests,
+            pending: Some((receiver, durable_sender)),
+            durable,
+        };
+        // 恢复出的区块尽快写入链文件
+        if durable_height < writer.chain.lock().unwrap().height() {
+            writer.persist();
+        }
+        writer
+    }
+
+    // 在节点的运行时中启动写入任务
+    pub fn start(&mut self) {
+        if let Some((receiver, durable)) = self.pending.take() {
+            tokio::spawn(write_loop(self.node_id, self.directory.clone(), self.chain.clone(), self.wal.clone(), receiver, durable));
+        }
+    }
+
+    // 共识提交的区块：先写预写日志，再请求后台写入
+    pub fn append(&self, block: &Block) {
+        if let Err(e) = self.wal.lock().unwrap().append(block) {
+            error!("节点{}写入预写日志失败: {}", self.node_id, e);
+            metrics::inc_counter("storage_wal_failures_total", 1);
+        }
+        self.persist();
+    }
+
+    // 请求把内存中的整条链写入链文件
+    pub fn persist(&self) {
+        match self.requests.try_send(()) {
+            Ok(()) => {}
+            // 已有写入在排队，它会写出这次的修改
+            Err(mpsc::error::TrySendError::Full(())) => metrics::inc_counter("storage_writes_coalesced_total", 1),
+            Err(mpsc::error::TrySendError::Closed(())) => error!("节点{}的存储写入任务已退出", self.node_id),
+        }
+    }
+
+    pub fn durable_height(&self) -> u64 {
+        *self.durable.borrow()
+    }
+
+    // 供RPC读取持久高度
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.durable.clone()
+    }
+
+    // 等待链文件持久到height
+    pub async fn wait_for(&self, height: u64) {
+        let mut durable = self.durable.clone();
+        while *durable.borrow_and_update() < height {
+            if durable.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn write_loop(node_id: usize, directory: PathBuf, chain: Arc<Mutex<Chain>>, wal: Arc<Mutex<Wal>>, mut requests: mpsc::Receiver<()>, durable: watch::Sender<u64>) {
+    while requests.recv().await.is_some() {
+        // 在锁内序列化，锁外写盘
+        let (height, data, index) = {
+            let chain = chain.lock().unwrap();
+            (chain.height(), serde_json::to_string(&*chain).unwrap(), serde_json::to_string(&chain.index).unwrap())
+        };
+        let started = Instant::now();
+        let directory = directory.clone();
+        let written = tokio::task::spawn_blocking(move || {
+            write_atomically(&directory.join(format!("node_{}_chain.json", node_id)), data.as_bytes(), true)?;
+            std::fs::write(chain_index::filename(&directory, node_id), index).map_err(|e| e.to_string())
+        }).await.unwrap_or_else(|e| Err(e.to_string()));
+        match written {
+            Ok(()) => {
+                debug!("节点{}的链文件已持久到高度{}，耗时{}ms", node_id, height, started.elapsed().as_millis());
+                metrics::inc_counter("storage_writes_total", 1);
+                metrics::inc_counter("storage_write_ms_total", started.elapsed().as_millis() as u64);
+                metrics::observe("storage_write_ms", started.elapsed().as_millis() as u64);
+                if let Err(e) = wal.lock().unwrap().truncate_through(height) {
+                    error!("节点{}截断预写日志失败: {}", node_id, e);
+                }
+                durable.send_replace(height);
+            }
+            Err(e) => {
+                error!("节点{}写入链文件失败: {}", node_id, e);
+                metrics::inc_counter("storage_write_failures_total", 1);
+            }
+        }
+    }
+}
+
+// 先写临时文件再改名，崩溃时旧文件保持完整；sync为true时改名前fsync
+pub fn write_atomically(path: &Path, data: &[u8], sync: bool) -> Result<(), String> {
+    let temporary = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&temporary).map_err(|e| e.to_string())?;
+    file.write_all(data).map_err(|e| e.to_string())?;
+    if sync {
+        file.sync_all().map_err(|e| e.to_string())?;
+    }
+    std::fs::rename(&temporary, path).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::chain::CommitCertificate;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    fn certificate() -> CommitCertificate {
+        CommitCertificate { view: 0, sequence_number: 0, digest: String::new(), signatures: Vec::new() }
+    }
+
+    // 链文件落后时从预写日志重放，写了一半的行和不连续的区块被忽略；截断后只保留未持久的区块
+    #[test]
+    fn recovers_blocks_from_the_wal() {
+        let directory = std::env::temp_dir().join(format!("pbft-storage-{}-{}", std::process::id(), rand::random::<u32>()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut chain = Chain::default();
+        for seq in 1..=3 {
+            chain.append(0, seq, format!("d{}", seq), Vec::new(), certificate());
+        }
+        let mut wal = recover(&directory, 7, &mut Chain::default());
+        for block in &chain.blocks {
+            wal.append(block).unwrap();
+        }
+        let mut other = Chain::default();
+        other.append(0, 9, "other".to_string(), Vec::new(), certificate());
+        let mut forked = other.blocks[0].clone();
+        forked.header.height = 4;
+        wal.append(&forked).unwrap();
+        let mut file = OpenOptions::new().append(true).open(wal_file(&directory, 7)).unwrap();
+        write!(file, "{{\"header\":").unwrap();
+
+        // 链文件只有第一个区块
+        let mut durable = Chain::default();
+        durable.push_verified(chain.blocks[0].clone());
+        let mut recovered = recover(&directory, 7, &mut durable);
+        assert_eq!(durable.height(), 3);
+        assert_eq!(durable.blocks[2].header.digest, "d3");
+        assert_eq!(recovered.len(), 2);
+
+        recovered.truncate_through(2).unwrap();
+        let mut restarted = Chain::default();
+        restarted.push_verified(chain.blocks[0].clone());
+        restarted.push_verified(chain.blocks[1].clone());
+        assert_eq!(recover(&directory, 7, &mut restarted).len(), 1);
+        recovered.truncate_through(3).unwrap();
+        assert!(!wal_file(&directory, 7).exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // 节点照常提交，链文件在后台持久，持久高度追上提交高度后预写日志被清空
+    #[tokio::test]
+    async fn cluster_persists_in_the_background() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().build().await;
+            for i in 0..3 {
+                cluster.submit(&format!("SET pipelined {}", i)).await;
+            }
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, "SET pipelined 2").is_some())).await;
+            assert!(committed, "请求未提交");
+            let persisted = cluster.wait_until(Duration::from_secs(5), |c| {
+                (0..N).all(|id| {
+                    let height = c.chains[id].lock().unwrap().height();
+                    Chain::load(id).height() == height && !wal_file(Path::new("."), id).exists()
+                })
+            }).await;
+            assert!(persisted, "链文件未持久到提交高度");
+        }).await;
+    }
+}
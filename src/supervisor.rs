@@ -0,0 +1,159 @@
+// src/supervisor.rs
+
+// 节点监督者：在独立的任务中运行节点，节点panic时把崩溃报告追加到 node_<ID>_crashes.jsonl，
+// 按指数退避等待后重新创建节点。新节点从磁盘上的区块、NodeState和内存池恢复，与进程重启相同，
+// 单次意外panic不会让验证者永久离线。连续运行超过SUPERVISOR_STABLE_SECS后退避时间复位；
+// 节点正常退出（收到关闭信号）时监督者随之返回。签名私钥和跨重启共享的配置由监督者之外的调用方持有，
+// 重启后的节点沿用同一身份
+use std::any::Any;
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use log::{error, info};
+use serde::{Serialize, Deserialize};
+use crate::config::{SUPERVISOR_INITIAL_BACKOFF_MS, SUPERVISOR_MAX_BACKOFF_MS, SUPERVISOR_STABLE_SECS};
+use crate::metrics;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrashReport {
+    pub node_id: usize,
+    pub time: String, // RFC 3339
+    pub restarts: u32, // 本次崩溃前已经重启的次数
+    pub uptime_ms: u64, // 本次运行的时长
+    pub message: String, // panic的信息
+    pub backoff_ms: u64, // 重启前等待的时间
+}
+
+pub struct Supervisor {
+    node_id: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    stable_after: Duration,
+    reports: PathBuf,
+}
+
+impl Supervisor {
+    pub fn new(node_id: usize) -> Self {
+        Supervisor {
+            node_id,
+            initial_backoff: Duration::from_millis(SUPERVISOR_INITIAL_BACKOFF_MS),
+            max_backoff: Duration::from_millis(SUPERVISOR_MAX_BACKOFF_MS),
+            stable_after: Duration::from_secs(SUPERVISOR_STABLE_SECS),
+            reports: PathBuf::from(format!("node_{}_crashes.jsonl", node_id)),
+        }
+    }
+
+    // start(重启次数)创建并运行一次节点；返回正常退出前的重启次数
+    pub async fn run<F, Fut>(&self, mut start: F) -> u32
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let mut restarts = 0;
+        let mut backoff = self.initial_backoff;
+        loop {
+            let started = Instant::now();
+            let panic = match tokio::task::spawn_local(start(restarts)).await {
+                Ok(()) => return restarts,
+                Err(e) if e.is_panic() => e.into_panic(),
+                Err(_) => return restarts,
+            };
+            let uptime = started.elapsed();
+            if uptime >= self.stable_after {
+                backoff = self.initial_backoff;
+            }
+            let report = CrashReport {
+                node_id: self.node_id,
+                time: chrono::Local::now().to_rfc3339(),
+                restarts,
+                uptime_ms: uptime.as_millis() as u64,
+                message: panic_message(&*panic),
+                backoff_ms: backoff.as_millis() as u64,
+            };
+            error!("节点{}崩溃: {}，{:?}后重启（第{}次）", self.node_id, report.message, backoff, restarts + 1);
+            if let Err(e) = self.save(&report) {
+                error!("节点{}保存崩溃报告失败: {}", self.node_id, e);
+            }
+            metrics::inc_counter("node_restarts_total", 1);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.max_backoff);
+            restarts += 1;
+            info!("节点{}从磁盘状态重启", self.node_id);
+        }
+    }
+
+    fn save(&self, report: &CrashReport) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.reports)?;
+        writeln!(file, "{}", serde_json::to_string(report).unwrap())
+    }
+}
+
+// 持锁时panic的节点使跨重启共享的锁中毒，之后每次lock().unwrap()都会panic。
+// 换成保存同样内容的新锁，返回是否替换；其他持有旧锁的地方须改用新锁
+pub fn recover_poisoned<T: Clone>(shared: &mut Arc<Mutex<T>>) -> bool {
+    if !shared.is_poisoned() {
+        return false;
+    }
+    let value = shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+    *shared = Arc::new(Mutex::new(value));
+    true
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "未知panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[tokio::test]
+    async fn restarts_after_panics_with_backoff() {
+        let reports = std::env::temp_dir().join(format!("pbft-crashes-{}-{}.jsonl", std::process::id(), rand::random::<u32>()));
+        let supervisor = Supervisor {
+            node_id: 7,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(25),
+            stable_after: Duration::from_secs(60),
+            reports: reports.clone(),
+        };
+        let started = Rc::new(Cell::new(0));
+        let counter = started.clone();
+        let restarts = tokio::task::LocalSet::new().run_until(supervisor.run(move |restarts| {
+            counter.set(counter.get() + 1);
+            async move {
+                if restarts < 3 {
+                    panic!("第{}次运行失败", restarts);
+                }
+            }
+        })).await;
+        assert_eq!((restarts, started.get()), (3, 4));
+
+        let data = std::fs::read_to_string(&reports).unwrap();
+        std::fs::remove_file(&reports).unwrap();
+        let reports: Vec<CrashReport> = data.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(reports.iter().map(|r| r.backoff_ms).collect::<Vec<_>>(), vec![10, 20, 25]);
+        assert_eq!(reports[1].message, "第1次运行失败");
+        assert!(reports.iter().enumerate().all(|(i, r)| r.restarts == i as u32 && r.node_id == 7));
+    }
+
+    #[test]
+    fn replaces_locks_poisoned_by_a_crashed_node() {
+        let mut shared = Arc::new(Mutex::new(vec![1, 2]));
+        assert!(!recover_poisoned(&mut shared));
+        let held = shared.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = held.lock().unwrap();
+            panic!("持锁时崩溃");
+        }).join();
+        assert!(shared.is_poisoned());
+        assert!(recover_poisoned(&mut shared));
+        assert_eq!(*shared.lock().unwrap(), vec![1, 2]);
+    }
+}
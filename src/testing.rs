@@ -0,0 +1,497 @@
+// src/testing.rs
+
+// 测试用的进程内集群：节点通过全局内存网络通信，数据文件写入独立的临时目录。
+// 网络和工作目录都是进程级的，同一时间只能运行一个集群。
+// 新测试用TestCluster::builder().nodes(4).byzantine(1, Strategy::WrongDigest).build()启动，
+// 之后可以注入消息、暂停和恢复节点、重启或杀死节点，并通过公开的句柄检查各节点的状态
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::task::JoinHandle;
+use zeroize::Zeroizing;
+use crate::byzantine::Strategy;
+use crate::chain::Chain;
+use crate::crypto::{PublicKey, SigningKey};
+use crate::directory::{self, DirectoryEntry, SignedEntry};
+use crate::clock::Clock;
+use crate::clock_sync::ClockSync;
+use crate::config::N;
+use crate::events::EventBus;
+use crate::execution::ExecutionEngine;
+use crate::genesis::Genesis;
+use crate::key_registry::{self, KeyBundle, KEY_BUNDLE_FILE};
+use crate::hash::HashFunction;
+use crate::message::PBFTMessage;
+use crate::network::{self, register_node};
+use crate::node::{Node, NodeState, Role};
+use crate::qos::Priority;
+use crate::request_status::RequestTracker;
+use crate::alerts::AlertConfig;
+use crate::view_stats::ViewStats;
+use crate::signing_policy::SigningPolicy;
+use crate::zones::Zones;
+
+lazy_static::lazy_static! {
+    static ref CLUSTER_LOCK: Mutex<()> = Mutex::new(());
+}
+
+// 单个节点的启动参数
+#[derive(Debug, Clone, Default)]
+pub struct NodeSetup {
+    pub strategy: Strategy,
+    pub clock: Clock,
+    pub latency: Duration, // 该节点发出的每条消息的网络延迟
+    pub otlp_endpoint: Option<String>, // 不读取环境变量，避免并行的测试互相影响
+    pub start_delay: Duration, // 延迟加入网络，模拟后上线的节点：此前发给它的消息全部丢失
+    pub hash_function: Option<HashFunction>, // 覆盖创世配置的哈希函数，模拟genesis.json与其他节点不一致的节点
+    pub alerts: AlertConfig, // 告警的去向，默认不发送
+    pub zones: Zones, // 验证者所在的故障域
+    // 节点创建后、开始运行前调用，设置节点的其他开关（例如digest_preprepares、header_sync）。
+    // 只改节点字段的选项经此设置，不必给NodeSetup逐个加字段
+    pub configure: Option<fn(&mut Node)>,
+}
+
+// 逐项配置集群，未配置的节点诚实、没有时钟偏差和网络延迟
+pub struct TestClusterBuilder {
+    nodes: usize,
+    setups: Vec<NodeSetup>,
+    timeout: Duration,
+    signing_policy: SigningPolicy,
+    application: Option<String>,
+    key_bundle: Option<Vec<usize>>,
+}
+
+impl TestClusterBuilder {
+    // 启动的验证者数。验证者集合固定为config::N个节点，ID不小于count的节点不启动，可稍后用restart让它上线
+    pub fn nodes(mut self, count: usize) -> Self {
+        assert!(count <= N, "集群最多{}个节点", N);
+        self.nodes = count;
+        self
+    }
+
+    pub fn byzantine(mut self, node_id: usize, strategy: Strategy) -> Self {
+        self.setups[node_id].strategy = strategy;
+        self
+    }
+
+    pub fn setup(mut self, node_id: usize, setup: NodeSetup) -> Self {
+        self.setups[node_id] = setup;
+        self
+    }
+
+    // 请求超时和视图切换超时
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // 写入创世配置的签名策略
+    pub fn signing_policy(mut self, policy: SigningPolicy) -> Self {
+        policy.validate().unwrap();
+        self.signing_policy = policy;
+        self
+    }
+
+    // 写入创世配置的复制应用，缺省为键值存储
+    #[cfg(feature = "app-ledger")]
+    pub fn application(mut self, name: &str) -> Self {
+        self.application = Some(name.to_string());
+        self
+    }
+
+    // 创世配置运维公钥，并签发验证者公钥清单；impostors中的节点在清单中登记的不是它实际使用的公钥
+    pub fn key_bundle(mut self, impostors: &[usize]) -> Self {
+        self.key_bundle = Some(impostors.to_vec());
+        self
+    }
+
+    // 须在tokio::task::LocalSet中调用
+    pub async fn build(self) -> TestCluster {
+        let cluster = TestCluster::launch(&self.setups, self.timeout, self.signing_policy, self.application, self.key_bundle).await;
+        for id in self.nodes..N {
+            cluster.crash(id);
+        }
+        cluster
+    }
+}
+
+// 暂停开关：暂停期间节点任务不再被轮询，相当于进程被SIGSTOP，计时器和发给它的消息在恢复后一并处理
+#[derive(Default)]
+struct Gate {
+    paused: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+struct Pausable<F> {
+    future: Pin<Box<F>>,
+    gate: Arc<Gate>,
+}
+
+impl<F: Future> Future for Pausable<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        if self.gate.paused.load(Ordering::SeqCst) {
+            *self.gate.waker.lock().unwrap() = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        self.future.as_mut().poll(cx)
+    }
+}
+
+pub struct TestCluster {
+    pub chains: Vec<Arc<Mutex<Chain>>>,
+    pub views: Vec<Arc<AtomicU64>>,
+    pub executions: Vec<Arc<Mutex<ExecutionEngine>>>,
+    pub clock_syncs: Vec<Arc<Mutex<ClockSync>>>,
+    pub request_statuses: Vec<Arc<Mutex<RequestTracker>>>,
+    pub view_stats: Vec<Arc<Mutex<ViewStats>>>,
+    pub states: Vec<Arc<Mutex<NodeState>>>,
+    pub events: Vec<EventBus>,
+    senders: Vec<Sender<PBFTMessage>>,
+    shutdowns: Vec<Sender<()>>,
+    exits: Vec<Sender<()>>,
+    maintenance: Vec<Sender<bool>>,
+    tasks: Vec<JoinHandle<()>>,
+    gates: Vec<Arc<Gate>>,
+    setups: Vec<NodeSetup>,
+    secret_keys: Vec<Zeroizing<[u8; 32]>>, // 重启的节点沿用原来的私钥，对等节点已知的公钥仍然有效
+    pub public_keys: HashMap<usize, PublicKey>,
+    pub genesis: Genesis,
+    timeout: Duration,
+    dir: PathBuf,
+    previous_dir: PathBuf,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl TestCluster {
+    pub fn builder() -> TestClusterBuilder {
+        TestClusterBuilder { nodes: N, setups: vec![NodeSetup::default(); N], timeout: Duration::from_millis(1000), signing_policy: SigningPolicy::default(), application: None, key_bundle: None }
+    }
+
+    // 启动N个节点，strategies[i]为节点i的行为策略。须在tokio::task::LocalSet中调用
+    pub async fn start(strategies: &[Strategy], timeout: Duration) -> Self {
+        let setups: Vec<NodeSetup> = strategies.iter().map(|strategy| NodeSetup { strategy: *strategy, ..NodeSetup::default() }).collect();
+        TestCluster::start_with(&setups, timeout).await
+    }
+
+    // 按setups[i]启动节点i，缺省的节点诚实且没有时钟偏差和网络延迟
+    pub async fn start_with(setups: &[NodeSetup], timeout: Duration) -> Self {
+        TestCluster::launch(setups, timeout, SigningPolicy::default(), None, None).await
+    }
+
+    async fn launch(setups: &[NodeSetup], timeout: Duration, signing_policy: SigningPolicy, application: Option<String>, key_bundle: Option<Vec<usize>>) -> Self {
+        let guard = CLUSTER_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let previous_dir = std::env::current_dir().unwrap();
+        let dir = std::env::temp_dir().join(format!("pbft-cluster-{}-{}", std::process::id(), rand::random::<u32>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+        reset_network();
+
+        let mut genesis = Genesis { chain_id: "test-cluster".to_string(), signing_policy, application, ..Genesis::default() };
+        let signing_keys: Vec<SigningKey> = (0..N).map(|_| SigningKey::generate()).collect();
+        let mut public_keys = signing_keys.iter().enumerate().map(|(id, k)| (id, k.public_key())).collect();
+        if let Some(impostors) = key_bundle {
+            let operator = SigningKey::generate();
+            genesis.operator_key = Some(operator.public_key().to_hex());
+            let listed = (0..N).map(|id| {
+                let key = if impostors.contains(&id) { SigningKey::generate().public_key() } else { signing_keys[id].public_key() };
+                (id, key.to_hex())
+            }).collect();
+            let bundle = KeyBundle::sign(&genesis.chain_id, listed, &operator);
+            std::fs::write(KEY_BUNDLE_FILE, serde_json::to_string(&bundle).unwrap()).unwrap();
+            public_keys = key_registry::load(&genesis).unwrap().unwrap();
+        }
+        let mut cluster = TestCluster {
+            chains: Vec::new(),
+            views: Vec::new(),
+            executions: Vec::new(),
+            clock_syncs: Vec::new(),
+            request_statuses: Vec::new(),
+            view_stats: Vec::new(),
+            states: Vec::new(),
+            events: Vec::new(),
+            senders: Vec::new(),
+            shutdowns: Vec::new(),
+            exits: Vec::new(),
+            maintenance: Vec::new(),
+            tasks: Vec::new(),
+            gates: Vec::new(),
+            setups: (0..N).map(|id| setups.get(id).cloned().unwrap_or_default()).collect(),
+            secret_keys: signing_keys.iter().map(|k| k.secret_bytes()).collect(),
+            public_keys,
+            genesis,
+            timeout,
+            dir,
+            previous_dir,
+            _guard: guard,
+        };
+        for id in 0..N {
+            cluster.spawn_node(id);
+        }
+        // 等节点之间完成握手认证，否则主节点的第一条PrePrepare会被丢弃
+        tokio::time::sleep(timeout / 5).await;
+        cluster
+    }
+
+    // 创建节点i并在后台运行，数据文件从当前目录加载；替换该节点原有的句柄
+    fn spawn_node(&mut self, id: usize) {
+        let setup = self.setups[id].clone();
+        let (tx, rx) = mpsc::channel(1000);
+        let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+        let (exit_tx, exit_rx) = mpsc::channel(1);
+        let (maintenance_tx, maintenance_rx) = mpsc::channel(1);
+        let signing_key = SigningKey::from_secret_bytes(&self.secret_keys[id][..]).unwrap();
+        let mut genesis = self.genesis.clone();
+        if let Some(hash_function) = setup.hash_function {
+            genesis.hash_function = hash_function;
+        }
+        // 节点总是知道自己实际使用的公钥，清单中的冒名节点也不例外
+        let mut public_keys = self.public_keys.clone();
+        public_keys.insert(id, signing_key.public_key());
+        let mut node = Node::new(id, 0, signing_key, public_keys, rx, setup.strategy, genesis);
+        node.clock = setup.clock;
+        node.send_latency = setup.latency;
+        node.otlp_endpoint = setup.otlp_endpoint;
+        node.set_zones(setup.zones);
+        node.alerts.configure(setup.alerts);
+        node.timeout_duration = self.timeout;
+        node.view_change_timeout = self.timeout;
+        node.shutdown = Some(shutdown_rx);
+        node.exit = Some(exit_rx);
+        node.maintenance_toggle = Some(maintenance_rx);
+        // 启动时的目录登记请求会与测试请求争用序列号，干扰时序相关的断言
+        node.peer_directory = false;
+        if let Some(configure) = setup.configure {
+            configure(&mut node);
+        }
+
+        let handles = (node.chain.clone(), node.current_view.clone(), node.execution.clone(), node.clock_sync.clone(), node.request_status.clone(), node.state.clone(), node.events.clone(), node.view_stats.clone());
+        let magic = self.genesis.network_magic();
+        let start_delay = setup.start_delay;
+        if start_delay.is_zero() {
+            register_node(id, magic, tx.clone());
+        }
+        let sender = tx.clone();
+        let gate = Arc::new(Gate::default());
+        let task = tokio::task::spawn_local(Pausable {
+            future: Box::pin(async move {
+                if !start_delay.is_zero() {
+                    tokio::time::sleep(start_delay).await;
+                    register_node(node.id, magic, sender);
+                }
+                node.run().await
+            }),
+            gate: gate.clone(),
+        });
+
+        if id < self.tasks.len() {
+            (self.chains[id], self.views[id], self.executions[id], self.clock_syncs[id], self.request_statuses[id], self.states[id], self.events[id], self.view_stats[id]) = handles;
+            self.senders[id] = tx;
+            self.shutdowns[id] = shutdown_tx;
+            self.exits[id] = exit_tx;
+            self.maintenance[id] = maintenance_tx;
+            self.tasks[id] = task;
+            self.gates[id] = gate;
+        } else {
+            self.chains.push(handles.0);
+            self.views.push(handles.1);
+            self.executions.push(handles.2);
+            self.clock_syncs.push(handles.3);
+            self.request_statuses.push(handles.4);
+            self.states.push(handles.5);
+            self.events.push(handles.6);
+            self.view_stats.push(handles.7);
+            self.senders.push(tx);
+            self.shutdowns.push(shutdown_tx);
+            self.exits.push(exit_tx);
+            self.maintenance.push(maintenance_tx);
+            self.tasks.push(task);
+            self.gates.push(gate);
+        }
+    }
+
+    // 用各节点自己的私钥把验证者登记到链上的节点目录，逐个等待提交
+    pub async fn register_validators(&self) {
+        for id in 0..N {
+            let key = SigningKey::from_secret_bytes(&self.secret_keys[id][..]).unwrap();
+            let entry = DirectoryEntry { node_id: id, addresses: Vec::new(), public_key: key.public_key().to_hex(), role: Role::Validator, sequence: 0 };
+            let operation = SignedEntry::sign(entry, &key).registration_operation();
+            self.submit(&operation).await;
+            let registered = self.wait_until(Duration::from_secs(10), |c| {
+                (0..N).all(|node| directory::lookup(c.executions[node].lock().unwrap().state(), id).is_some())
+            }).await;
+            assert!(registered, "节点{}未能登记到目录", id);
+        }
+    }
+
+    // 模拟节点正常关闭后重新启动：发出关闭信号并等节点退出，再用同一把私钥从磁盘上的状态启动
+    pub async fn restart(&mut self, node_id: usize) {
+        let _ = self.shutdowns[node_id].send(()).await;
+        let _ = (&mut self.tasks[node_id]).await;
+        self.spawn_node(node_id);
+    }
+
+    // 计划退出：发出退出信号并等节点完成交接、关闭，之后从网络中摘除
+    pub async fn exit(&mut self, node_id: usize) {
+        let _ = self.exits[node_id].send(()).await;
+        let _ = (&mut self.tasks[node_id]).await;
+        network::NETWORK.lock().unwrap().remove(&node_id);
+    }
+
+    // 开关节点的维护模式，与管理员调用RPC的Maintenance方法相同
+    pub async fn set_maintenance(&self, node_id: usize, enabled: bool) {
+        let _ = self.maintenance[node_id].send(enabled).await;
+    }
+
+    // 像客户端超时重发一样把请求发给所有节点，主节点切换后新主节点仍持有该请求
+    pub async fn submit(&self, operation: &str) {
+        self.submit_request(PBFTMessage::Request {
+            operation: operation.to_string(),
+            priority: Priority::Normal,
+            client_id: None,
+            expires_at: None,
+            session: None,
+            timestamp: None,
+        }).await;
+    }
+
+    // 只发给一个节点，其他节点不知道这个请求
+    pub async fn submit_to(&self, node_id: usize, request: PBFTMessage) {
+        self.inject(node_id, request).await;
+    }
+
+    // 绕过网络把任意消息（例如伪造的投票）直接放入节点的入站队列
+    pub async fn inject(&self, node_id: usize, msg: PBFTMessage) {
+        let _ = self.senders[node_id].send(msg).await;
+    }
+
+    pub async fn submit_request(&self, request: PBFTMessage) {
+        // 崩溃的节点收不到请求，忽略发送失败
+        for sender in &self.senders {
+            let _ = sender.send(request.clone()).await;
+        }
+    }
+
+    // 模拟节点进程被杀死：终止节点任务并从网络中摘除，此后发给它的消息全部丢失
+    pub fn crash(&self, node_id: usize) {
+        self.tasks[node_id].abort();
+        network::NETWORK.lock().unwrap().remove(&node_id);
+    }
+
+    // 冻结节点：不处理消息也不触发计时器，但仍在网络中，发给它的消息在队列中积压
+    pub fn pause(&self, node_id: usize) {
+        self.gates[node_id].paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self, node_id: usize) {
+        self.gates[node_id].paused.store(false, Ordering::SeqCst);
+        if let Some(waker) = self.gates[node_id].waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    // 节点的私钥，用于以该节点的名义签名注入的消息
+    pub fn signing_key(&self, node_id: usize) -> SigningKey {
+        SigningKey::from_secret_bytes(&self.secret_keys[node_id][..]).unwrap()
+    }
+
+    // 以节点的名义签名消息
+    pub fn signed(&self, node_id: usize, message: PBFTMessage) -> PBFTMessage {
+        let signature = self.signing_key(node_id).sign(&self.genesis.message_payload(&message));
+        PBFTMessage::SignedMessage { message: Box::new(message), signature, sender_id: node_id, trace: None }
+    }
+
+    pub fn view(&self, node_id: usize) -> u64 {
+        self.views[node_id].load(Ordering::Relaxed)
+    }
+
+    // 节点已提交的包含该操作的区块所在的视图
+    pub fn committed_view(&self, node_id: usize, operation: &str) -> Option<u64> {
+        let chain = self.chains[node_id].lock().unwrap();
+        chain.blocks.iter()
+            .find(|block| block.transactions.iter().any(|tx| tx.operation == operation))
+            .map(|block| block.header.view)
+    }
+
+    // 轮询直到条件成立或超时
+    pub async fn wait_until(&self, timeout: Duration, condition: impl Fn(&TestCluster) -> bool) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while tokio::time::Instant::now() < deadline {
+            if condition(self) {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        condition(self)
+    }
+}
+
+impl Drop for TestCluster {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        reset_network();
+        std::env::set_current_dir(&self.previous_dir).unwrap();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn reset_network() {
+    network::NETWORK.lock().unwrap().clear();
+    network::OUTBOX.lock().unwrap().clear();
+    network::RELAY_CONNECTIONS.lock().unwrap().clear();
+    network::RELAY_ROUTES.lock().unwrap().clear();
+    network::CLIENTS.lock().unwrap().clear();
+    network::FIREWALLS.lock().unwrap().clear();
+    network::set_faults(network::NetworkFaults::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 拜占庭节点1发送错误摘要，再暂停诚实节点2，剩下的节点凑不齐法定人数；恢复后积压的消息被处理，请求提交
+    #[tokio::test]
+    async fn paused_node_withholds_quorum_until_resumed() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::builder().nodes(N).byzantine(1, Strategy::WrongDigest).timeout(Duration::from_secs(3)).build().await;
+            cluster.pause(2);
+            cluster.submit("SET k v").await;
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(cluster.chains[0].lock().unwrap().blocks.is_empty(), "少了节点2仍然提交了");
+
+            cluster.resume(2);
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| {
+                [0, 2, 3].iter().all(|id| c.committed_view(*id, "SET k v").is_some())
+            }).await;
+            assert!(committed, "恢复节点2后请求未提交");
+            assert!(!cluster.states[2].lock().unwrap().committed.is_empty());
+        }).await;
+    }
+
+    // 只启动N-1个节点，仍能提交；剩下的节点上线后通过缺口补齐追上
+    #[tokio::test]
+    async fn offline_node_joins_later() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut cluster = TestCluster::builder().nodes(N - 1).build().await;
+            cluster.submit("SET k v").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N - 1).all(|id| c.committed_view(id, "SET k v").is_some())).await;
+            assert!(committed, "N-1个节点未能提交");
+            assert!(cluster.chains[N - 1].lock().unwrap().blocks.is_empty());
+
+            cluster.restart(N - 1).await;
+            cluster.submit("SET k2 v").await;
+            let caught_up = cluster.wait_until(Duration::from_secs(5), |c| c.committed_view(N - 1, "SET k v").is_some()).await;
+            assert!(caught_up, "后上线的节点未补齐区块");
+        }).await;
+    }
+}
@@ -0,0 +1,244 @@
+// src/trace.rs
+
+// 分布式追踪：一个共识实例在主节点和各副本上的处理过程记录为同一条trace。
+// 追踪上下文（W3C traceparent中的trace-id和parent-id）随签名信封在节点间传递，
+// 副本的实例span以主节点的实例span为父span；完成的span以OTLP/HTTP JSON格式导出
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use log::{debug, error};
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: String, // 16字节，十六进制
+    pub span_id: String,  // 发送方的span，8字节，十六进制
+}
+
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub start_unix_nano: i64,
+    pub end_unix_nano: i64,
+}
+
+// 一个共识实例在本节点上的追踪状态
+pub struct InstanceTrace {
+    pub sequence_number: u64,
+    context: TraceContext,
+    parent_span_id: Option<String>,
+    started: i64,
+    phase_started: i64,
+    spans: Vec<Span>,
+}
+
+fn random_id(bytes: usize) -> String {
+    hex::encode((0..bytes).map(|_| rand::random::<u8>()).collect::<Vec<u8>>())
+}
+
+impl InstanceTrace {
+    // 主节点开启新的trace；副本沿用PrePrepare携带的trace
+    pub fn start(sequence_number: u64, parent: Option<TraceContext>, now: i64) -> Self {
+        let (trace_id, parent_span_id) = match parent {
+            Some(parent) => (parent.trace_id, Some(parent.span_id)),
+            None => (random_id(16), None),
+        };
+        InstanceTrace {
+            sequence_number,
+            context: TraceContext { trace_id, span_id: random_id(8) },
+            parent_span_id,
+            started: now,
+            phase_started: now,
+            spans: Vec::new(),
+        }
+    }
+
+    // 随本节点发出的消息传递的上下文
+    pub fn context(&self) -> TraceContext {
+        self.context.clone()
+    }
+
+    // 结束当前阶段的span，下一阶段从此刻开始
+    pub fn end_phase(&mut self, name: &str, now: i64) {
+        self.spans.push(Span {
+            trace_id: self.context.trace_id.clone(),
+            span_id: random_id(8),
+            parent_span_id: Some(self.context.span_id.clone()),
+            name: name.to_string(),
+            start_unix_nano: self.phase_started,
+            end_unix_nano: now,
+        });
+        self.phase_started = now;
+    }
+
+    // 结束实例span，返回本实例的全部span
+    pub fn finish(mut self, now: i64) -> Vec<Span> {
+        self.spans.push(Span {
+            trace_id: self.context.trace_id,
+            span_id: self.context.span_id,
+            parent_span_id: self.parent_span_id,
+            name: format!("pbft seq={}", self.sequence_number),
+            start_unix_nano: self.started,
+            end_unix_nano: now,
+        });
+        self.spans
+    }
+}
+
+// OTLP/HTTP JSON编码，trace-id和span-id按规范使用十六进制
+pub fn otlp_json(service_name: &str, spans: &[Span]) -> Value {
+    let spans: Vec<Value> = spans.iter().map(|span| {
+        let mut value = json!({
+            "traceId": span.trace_id,
+            "spanId": span.span_id,
+            "name": span.name,
+            "kind": 1,
+            "startTimeUnixNano": span.start_unix_nano.to_string(),
+            "endTimeUnixNano": span.end_unix_nano.to_string(),
+        });
+        if let Some(parent) = &span.parent_span_id {
+            value["parentSpanId"] = json!(parent);
+        }
+        value
+    }).collect();
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [{ "key": "service.name", "value": { "stringValue": service_name } }] },
+            "scopeSpans": [{ "scope": { "name": "pbft-blockchain" }, "spans": spans }],
+        }]
+    })
+}
+
+// 把span发送到OTLP/HTTP接收端，endpoint形如 http://127.0.0.1:4318
+pub async fn export(endpoint: String, service_name: String, spans: Vec<Span>) {
+    let body = otlp_json(&service_name, &spans).to_string();
+    match tokio::time::timeout(EXPORT_TIMEOUT, post(&endpoint, &body)).await {
+        Ok(Ok(())) => debug!("{}导出{}个span到{}", service_name, spans.len(), endpoint),
+        Ok(Err(e)) => error!("{}导出span到{}失败: {}", service_name, endpoint, e),
+        Err(_) => error!("{}导出span到{}超时", service_name, endpoint),
+    }
+}
+
+async fn post(endpoint: &str, body: &str) -> Result<(), String> {
+    let authority = endpoint.strip_prefix("http://").ok_or("只支持http://地址")?.trim_end_matches('/');
+    let mut stream = TcpStream::connect(authority).await.map_err(|e| e.to_string())?;
+    let request = format!(
+        "POST /v1/traces HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        authority, body.len(), body
+    );
+    stream.write_all(request.as_bytes()).await.map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.map_err(|e| e.to_string())?;
+    let status_line = String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string();
+    if status_line.split_whitespace().nth(1).is_some_and(|code| code.starts_with('2')) {
+        Ok(())
+    } else {
+        Err(format!("接收端返回: {}", status_line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+    use crate::config::N;
+    use crate::testing::{TestCluster, NodeSetup};
+
+    // 最简单的OTLP/HTTP接收端：收下每个请求的JSON正文，按服务名归集span
+    async fn collector() -> (String, Arc<Mutex<HashMap<String, Vec<Value>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(HashMap::new()));
+        let sink = received.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let sink = sink.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    loop {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                        let text = String::from_utf8_lossy(&request).to_string();
+                        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                            let length: usize = head.lines()
+                                .find_map(|line| line.strip_prefix("Content-Length: "))
+                                .map_or(0, |v| v.trim().parse().unwrap());
+                            if body.len() >= length {
+                                let export: Value = serde_json::from_str(body).unwrap();
+                                let resource = &export["resourceSpans"][0];
+                                let service = resource["resource"]["attributes"][0]["value"]["stringValue"].as_str().unwrap().to_string();
+                                let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap().clone();
+                                sink.lock().unwrap().entry(service).or_insert_with(Vec::new).extend(spans);
+                                break;
+                            }
+                        }
+                        if n == 0 {
+                            return;
+                        }
+                    }
+                    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+                });
+            }
+        });
+        (endpoint, received)
+    }
+
+    fn instance_span(spans: &[Value]) -> &Value {
+        spans.iter().find(|span| span["name"].as_str().unwrap().starts_with("pbft seq=")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn request_is_one_trace_across_all_nodes() {
+        tokio::task::LocalSet::new().run_until(async {
+            let (endpoint, received) = collector().await;
+            let setups: Vec<NodeSetup> = (0..N).map(|_| NodeSetup { otlp_endpoint: Some(endpoint.clone()), ..NodeSetup::default() }).collect();
+            let cluster = TestCluster::start_with(&setups, Duration::from_millis(1000)).await;
+
+            cluster.submit("SET k v").await;
+            let exported = cluster.wait_until(Duration::from_secs(10), |_| received.lock().unwrap().len() == N).await;
+            assert!(exported, "未收到全部节点的span");
+
+            let received = received.lock().unwrap();
+            let primary = instance_span(&received["pbft-node-0"]);
+            assert!(primary.get("parentSpanId").is_none(), "主节点的实例span应为根span");
+            for id in 0..N {
+                let spans = &received[&format!("pbft-node-{}", id)];
+                let instance = instance_span(spans);
+                assert_eq!(instance["traceId"], primary["traceId"], "节点{}的span不在同一条trace中", id);
+                if id != 0 {
+                    assert_eq!(instance["parentSpanId"], primary["spanId"], "副本{}的实例span应以主节点的span为父span", id);
+                }
+                // Commit和Reply阶段挂在本节点的实例span之下
+                for phase in ["Commit", "Reply"] {
+                    let span = spans.iter().find(|span| span["name"] == phase).unwrap_or_else(|| panic!("节点{}缺少{}阶段", id, phase));
+                    assert_eq!(span["parentSpanId"], instance["spanId"]);
+                }
+            }
+        }).await;
+    }
+
+    #[test]
+    fn otlp_encoding_uses_hex_ids() {
+        let mut trace = InstanceTrace::start(7, None, 100);
+        trace.end_phase("Prepare", 150);
+        let spans = trace.finish(200);
+        let export = otlp_json("pbft-node-0", &spans);
+        let encoded = &export["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(encoded[0]["name"], "Prepare");
+        assert_eq!(encoded[0]["startTimeUnixNano"], "100");
+        assert_eq!(encoded[1]["name"], "pbft seq=7");
+        assert_eq!(encoded[1]["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(encoded[1]["spanId"].as_str().unwrap().len(), 16);
+        assert_eq!(encoded[0]["parentSpanId"], encoded[1]["spanId"]);
+    }
+}
@@ -0,0 +1,195 @@
+// src/view_stats.rs
+
+// 视图统计：记录每个视图的主节点、持续时长、本节点在其中提交的区块数、超时次数和结束原因，
+// 视图结束时追加到 node_<ID>_views.jsonl，长时间运行后可据此分析各主节点的质量和协议行为，
+// 也是按信誉选举主节点的原始数据。内存中保留最近VIEW_STATS_HISTORY个视图，
+// 按主节点的汇总覆盖文件中的全部历史，重启时从文件重建
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use log::error;
+use serde::{Serialize, Deserialize};
+use tokio::time::Instant;
+use crate::config::VIEW_STATS_HISTORY;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ViewRecord {
+    pub view: u64,
+    pub leader: usize,
+    pub started: String, // RFC 3339
+    pub duration_ms: u64, // 进行中的视图为到目前为止的时长
+    pub blocks: u64, // 本节点在该视图中提交的区块数
+    pub timeouts: u64, // 本节点在该视图中的超时次数
+    pub ended_by: Option<String>, // 本节点发起视图切换的原因；跟随其他节点进入新视图或视图仍在进行时为空
+}
+
+impl ViewRecord {
+    fn start(view: u64, leader: usize) -> Self {
+        ViewRecord {
+            view,
+            leader,
+            started: chrono::Local::now().to_rfc3339(),
+            duration_ms: 0,
+            blocks: 0,
+            timeouts: 0,
+            ended_by: None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LeaderSummary {
+    pub views: u64,
+    pub failed_views: u64, // 没有提交任何区块就结束的视图
+    pub blocks: u64,
+    pub timeouts: u64,
+    pub total_ms: u64,
+}
+
+pub struct ViewStats {
+    path: PathBuf,
+    current: ViewRecord,
+    since: Instant, // 当前视图开始的本地时间
+    recent: VecDeque<ViewRecord>,
+    leaders: BTreeMap<usize, LeaderSummary>,
+}
+
+impl ViewStats {
+    pub fn load(node_id: usize, view: u64, leader: usize) -> Self {
+        Self::at(PathBuf::from(format!("node_{}_views.jsonl", node_id)), view, leader)
+    }
+
+    fn at(path: PathBuf, view: u64, leader: usize) -> Self {
+        let mut stats = ViewStats {
+            path,
+            current: ViewRecord::start(view, leader),
+            since: Instant::now(),
+            recent: VecDeque::new(),
+            leaders: BTreeMap::new(),
+        };
+        let data = std::fs::read_to_string(&stats.path).unwrap_or_default();
+        for record in data.lines().filter_map(|line| serde_json::from_str(line).ok()) {
+            stats.remember(record);
+        }
+        stats
+    }
+
+    pub fn record_block(&mut self) {
+        self.current.blocks += 1;
+    }
+
+    pub fn record_timeout(&mut self) {
+        self.current.timeouts += 1;
+    }
+
+    pub fn set_ended_by(&mut self, reason: &str) {
+        self.current.ended_by = Some(reason.to_string());
+    }
+
+    // 进入新视图：结算当前视图，写入文件并计入汇总
+    pub fn enter_view(&mut self, view: u64, leader: usize, now: Instant) {
+        let mut finished = std::mem::replace(&mut self.current, ViewRecord::start(view, leader));
+        finished.duration_ms = now.duration_since(self.since).as_millis() as u64;
+        self.since = now;
+        if let Err(e) = self.append(&finished) {
+            error!("保存视图{}的统计失败: {}", finished.view, e);
+        }
+        self.remember(finished);
+    }
+
+    fn append(&self, record: &ViewRecord) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record).unwrap())
+    }
+
+    fn remember(&mut self, record: ViewRecord) {
+        let summary = self.leaders.entry(record.leader).or_default();
+        summary.views += 1;
+        summary.failed_views += (record.blocks == 0) as u64;
+        summary.blocks += record.blocks;
+        summary.timeouts += record.timeouts;
+        summary.total_ms += record.duration_ms;
+        self.recent.push_back(record);
+        if self.recent.len() > VIEW_STATS_HISTORY {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn current(&self, now: Instant) -> ViewRecord {
+        let mut current = self.current.clone();
+        current.duration_ms = now.duration_since(self.since).as_millis() as u64;
+        current
+    }
+
+    // 最近结束的limit个视图，按视图从早到晚排列
+    pub fn recent(&self, limit: usize) -> Vec<ViewRecord> {
+        self.recent.iter().skip(self.recent.len().saturating_sub(limit)).cloned().collect()
+    }
+
+    pub fn leaders(&self) -> &BTreeMap<usize, LeaderSummary> {
+        &self.leaders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::byzantine::Strategy;
+    use crate::config::N;
+    use crate::testing::TestCluster;
+
+    #[test]
+    fn summaries_survive_restart() {
+        let path = std::env::temp_dir().join(format!("pbft-views-{}-{}.jsonl", std::process::id(), rand::random::<u32>()));
+        let mut stats = ViewStats::at(path.clone(), 0, 0);
+        let start = stats.since;
+        stats.record_block();
+        stats.record_block();
+        stats.enter_view(1, 1, start + Duration::from_millis(300));
+        stats.record_timeout();
+        stats.set_ended_by("请求超时");
+        stats.enter_view(2, 2, start + Duration::from_millis(800));
+        assert_eq!(stats.current(start + Duration::from_millis(900)).duration_ms, 100);
+
+        let restarted = ViewStats::at(path.clone(), 2, 2);
+        std::fs::remove_file(&path).unwrap();
+        let recent = restarted.recent(10);
+        assert_eq!(recent.iter().map(|r| (r.view, r.leader, r.blocks)).collect::<Vec<_>>(), vec![(0, 0, 2), (1, 1, 0)]);
+        assert_eq!(recent[1].ended_by.as_deref(), Some("请求超时"));
+        assert_eq!(restarted.recent(1), recent[1..].to_vec());
+        assert_eq!(restarted.leaders()[&0], LeaderSummary { views: 1, failed_views: 0, blocks: 2, timeouts: 0, total_ms: 300 });
+        assert_eq!(restarted.leaders()[&1], LeaderSummary { views: 1, failed_views: 1, blocks: 0, timeouts: 1, total_ms: 500 });
+    }
+
+    // 主节点崩溃后其余节点超时切换视图：视图0记入主节点0的汇总，结束原因为请求超时
+    #[tokio::test]
+    async fn records_view_of_crashed_leader() {
+        tokio::task::LocalSet::new().run_until(async {
+            let cluster = TestCluster::start(&[Strategy::Honest; N], Duration::from_millis(1000)).await;
+            cluster.submit("SET before crash").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, "SET before crash").is_some())).await;
+            assert!(committed, "崩溃前的请求未提交");
+            cluster.crash(0);
+            cluster.submit("SET after crash").await;
+            let committed = cluster.wait_until(Duration::from_secs(15), |c| {
+                (1..N).all(|id| c.committed_view(id, "SET after crash").is_some_and(|view| view >= 1))
+            }).await;
+            assert!(committed, "主节点崩溃后未能切换视图并提交请求");
+
+            for id in 1..N {
+                let stats = cluster.view_stats[id].lock().unwrap();
+                let first = &stats.recent(VIEW_STATS_HISTORY)[0];
+                assert_eq!((first.view, first.leader), (0, 0), "节点{}", id);
+                assert!(first.blocks >= 1 && first.duration_ms > 0, "节点{}: {:?}", id, first);
+                assert_eq!(stats.leaders()[&0].views, 1);
+                let persisted = std::fs::read_to_string(format!("node_{}_views.jsonl", id)).unwrap();
+                assert!(persisted.lines().count() >= 1);
+            }
+            let timed_out = (1..N).filter(|&id| {
+                cluster.view_stats[id].lock().unwrap().recent(VIEW_STATS_HISTORY).iter().any(|r| r.ended_by.as_deref() == Some("请求超时"))
+            }).count();
+            assert!(timed_out >= 1, "至少一个节点因请求超时发起视图切换");
+        }).await;
+    }
+}
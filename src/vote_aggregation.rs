@@ -0,0 +1,143 @@
+// src/vote_aggregation.rs
+
+// 线性投票（HotStuff式的投票汇集）：副本只把Prepare发给主节点，主节点收齐法定人数后
+// 把这些Prepare的签名打包成PrepareCertificate广播，每个副本逐个验签后计入法定人数。
+// Prepare阶段的消息数从N²降到2N；Commit阶段仍是全互联。
+// 主节点停滞时副本在VOTE_AGGREGATION_TIMEOUT_MS后改为广播Prepare，回到原来的通信模式
+use std::collections::{BTreeMap, HashMap};
+use crate::config::{N, VOTE_AGGREGATION};
+use crate::crypto::{PublicKey, Signature};
+use crate::genesis::Genesis;
+use crate::message::PBFTMessage;
+use crate::signing_policy::{Authentication, SigningPolicy};
+
+// 证书由各副本的Prepare签名组成，MAC或不认证的Prepare无法转交
+pub fn check(policy: &SigningPolicy) -> Result<(), String> {
+    if VOTE_AGGREGATION && policy.for_kind("Prepare") != Authentication::Signature {
+        return Err("启用线性投票时Prepare必须签名".to_string());
+    }
+    Ok(())
+}
+
+// 主节点用收到的Prepare签名组装证书；主节点自己的签名属于PrePrepare，不放入证书
+pub fn certificate(primary: usize, view: u64, sequence_number: u64, digest: String, signatures: &BTreeMap<usize, Signature>) -> PBFTMessage {
+    let signatures = signatures.iter()
+        .filter(|(signer, _)| **signer != primary)
+        .map(|(signer, signature)| (*signer, *signature))
+        .collect();
+    PBFTMessage::PrepareCertificate { view, sequence_number, digest, signatures }
+}
+
+// 校验证书中的每个签名，返回签名有效的副本（不含主节点和本节点）。任何一个签名无效都说明主节点作恶
+pub fn verify(
+    certificate: &PBFTMessage,
+    own_id: usize,
+    primary: usize,
+    public_keys: &HashMap<usize, PublicKey>,
+    genesis: &Genesis,
+) -> Result<Vec<(usize, Signature)>, String> {
+    let (view, sequence_number, digest, signatures) = match certificate {
+        PBFTMessage::PrepareCertificate { view, sequence_number, digest, signatures } => (*view, *sequence_number, digest, signatures),
+        other => return Err(format!("{}不是Prepare证书", other.kind())),
+    };
+    let mut valid = Vec::new();
+    for (signer, signature) in signatures {
+        if *signer >= N || *signer == primary {
+            return Err(format!("证书中的签名者{}不是副本", signer));
+        }
+        if *signer == own_id || valid.iter().any(|(seen, _)| seen == signer) {
+            continue;
+        }
+        let prepare = PBFTMessage::Prepare { view, sequence_number, digest: digest.clone(), sender_id: *signer };
+        let public_key = public_keys.get(signer).ok_or_else(|| format!("缺少节点{}的公钥", signer))?;
+        if !public_key.verify(&genesis.message_payload(&prepare), signature) {
+            return Err(format!("节点{}的Prepare签名无效", signer));
+        }
+        valid.push((*signer, *signature));
+    }
+    Ok(valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::byzantine::Strategy;
+    use crate::consensus::{Action, ConsensusCore, Input, Timer};
+    use crate::crypto::SigningKey;
+    use crate::hash::HashFunction;
+    use crate::metrics;
+    use crate::network;
+    use crate::quorum::PREPARE_QUORUM;
+    use crate::testing::{NodeSetup, TestCluster};
+
+    // 副本把Prepare只发给主节点，主节点在法定人数时要求汇集；超时后副本改为广播
+    #[test]
+    fn replicas_send_prepares_to_the_primary() {
+        let mut primary = ConsensusCore::new(0, 0, 0, Strategy::Honest, HashFunction::default());
+        primary.aggregate_votes = true;
+        let mut replica = ConsensusCore::new(1, 0, 0, Strategy::Honest, HashFunction::default());
+        replica.aggregate_votes = true;
+
+        primary.handle(Input::Propose { digest: "d".to_string(), transactions: Vec::new() });
+        let actions = replica.handle(Input::PrePrepare { view: 0, sequence_number: 1, digest: "d".to_string(), transactions: Vec::new() });
+        assert!(actions.iter().any(|action| matches!(action, Action::Send(0, PBFTMessage::Prepare { sender_id: 1, .. }))));
+        assert!(actions.iter().any(|action| matches!(action, Action::SetTimer(Timer::Aggregation { sequence_number: 1 }))));
+        assert!(!actions.iter().any(|action| matches!(action, Action::Broadcast(PBFTMessage::Prepare { .. }))));
+
+        let mut aggregated = Vec::new();
+        for sender_id in 1..=PREPARE_QUORUM {
+            aggregated.extend(primary.handle(Input::Prepare { view: 0, sequence_number: 1, digest: "d".to_string(), sender_id }));
+        }
+        assert!(aggregated.iter().any(|action| matches!(action, Action::AggregatePrepares { sequence_number: 1, .. })));
+
+        let fallback = replica.handle(Input::AggregationTimeout { view: 0, sequence_number: 1 });
+        assert!(matches!(fallback.as_slice(), [Action::Broadcast(PBFTMessage::Prepare { sender_id: 1, .. })]));
+        // 只回退一次
+        assert!(replica.handle(Input::AggregationTimeout { view: 0, sequence_number: 1 }).is_empty());
+
+        // 伪造的签名使整张证书无效
+        let genesis: Genesis = serde_json::from_str(r#"{"chain_id": "aggregation-test"}"#).unwrap();
+        let keys: Vec<SigningKey> = (0..N).map(|_| SigningKey::generate()).collect();
+        let public_keys: HashMap<usize, PublicKey> = keys.iter().enumerate().map(|(id, key)| (id, key.public_key())).collect();
+        let mut signatures = BTreeMap::new();
+        for (id, key) in keys.iter().enumerate().skip(1) {
+            let prepare = PBFTMessage::Prepare { view: 0, sequence_number: 1, digest: "d".to_string(), sender_id: id };
+            signatures.insert(id, key.sign(&genesis.message_payload(&prepare)));
+        }
+        let good = certificate(0, 0, 1, "d".to_string(), &signatures);
+        assert_eq!(verify(&good, 1, 0, &public_keys, &genesis).unwrap().len(), N - 2);
+        signatures.insert(2, keys[3].sign(b"forged"));
+        let forged = certificate(0, 0, 1, "d".to_string(), &signatures);
+        assert!(verify(&forged, 1, 0, &public_keys, &genesis).is_err());
+    }
+
+    fn prepares_sent() -> u64 {
+        (0..N)
+            .flat_map(|id| network::traffic_stats(id).into_values())
+            .filter_map(|peer| peer.sent_by_type.get("Prepare").map(|traffic| traffic.messages))
+            .sum()
+    }
+
+    // 启用线性投票的集群照常提交，副本之间不再互发Prepare
+    #[tokio::test]
+    async fn cluster_commits_with_aggregated_prepares() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut builder = TestCluster::builder();
+            for id in 0..N {
+                builder = builder.setup(id, NodeSetup { configure: Some(|node| node.core.aggregate_votes = true), ..NodeSetup::default() });
+            }
+            let cluster = builder.build().await;
+            let certificates = metrics::snapshot().get("vote_aggregation_certificates_total").copied().unwrap_or(0);
+            let prepares_before = prepares_sent();
+
+            cluster.submit("SET linear yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, "SET linear yes").is_some())).await;
+            assert!(committed, "请求未提交");
+            assert!(metrics::snapshot().get("vote_aggregation_certificates_total").copied().unwrap_or(0) > certificates);
+            // 每个副本只向主节点发出一条Prepare，全互联时为(N-1)²条
+            let sent = prepares_sent() - prepares_before;
+            assert!(sent <= (N - 1) as u64, "集群发出了{}条Prepare", sent);
+        }).await;
+    }
+}
@@ -0,0 +1,120 @@
+// src/zones.rs
+
+// 故障域（机架、可用区）：node_config.json中的"zones"把验证者分组，例如
+//   "zones": {"rack-a": [0, 1], "rack-b": [2, 3]}
+// 广播时先发往其他故障域、最后才发往本节点所在的域，并在各域之间轮流发送：
+// 整个域变慢时，其他域的节点仍能最先收到消息、凑成法定人数。
+// 各域的投票到达时间（相对本节点开始该实例）记入vote_arrival_ms_total和vote_arrival_samples_total，按域和消息类型分别统计。
+// 未列入任何域的节点各自视为一个单独的域
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::config::N;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Zones {
+    pub groups: BTreeMap<String, Vec<usize>>,
+}
+
+impl Zones {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let mut seen = BTreeMap::new();
+        for (zone, members) in &self.groups {
+            if zone.is_empty() {
+                return Err("故障域的名称不能为空".to_string());
+            }
+            for node in members {
+                if *node >= N {
+                    return Err(format!("故障域{}中的节点{}不是验证者", zone, node));
+                }
+                if let Some(other) = seen.insert(*node, zone) {
+                    return Err(format!("节点{}同时属于故障域{}和{}", node, other, zone));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn zone_of(&self, node: usize) -> Option<&str> {
+        self.groups.iter().find(|(_, members)| members.contains(&node)).map(|(zone, _)| zone.as_str())
+    }
+
+    // 指标中的域标签，未列入任何域的节点为none
+    pub fn label(&self, node: usize) -> &str {
+        self.zone_of(node).unwrap_or("none")
+    }
+
+    // 广播的发送顺序：其他域在前，各域轮流各发一个节点，本节点所在的域在最后
+    pub fn broadcast_order(&self, own_id: usize) -> Vec<usize> {
+        let own_zone = self.zone_of(own_id);
+        let mut remote: BTreeMap<(&str, usize), Vec<usize>> = BTreeMap::new();
+        let mut local = Vec::new();
+        for peer in (0..N).filter(|peer| *peer != own_id) {
+            match self.zone_of(peer) {
+                Some(zone) if Some(zone) == own_zone => local.push(peer),
+                Some(zone) => remote.entry((zone, 0)).or_default().push(peer),
+                None => remote.entry(("", peer)).or_default().push(peer),
+            }
+        }
+        let mut groups: Vec<Vec<usize>> = remote.into_values().collect();
+        let mut order = Vec::with_capacity(N);
+        let rounds = groups.iter().map(Vec::len).max().unwrap_or(0);
+        for round in 0..rounds {
+            order.extend(groups.iter_mut().filter_map(|group| group.get(round).copied()));
+        }
+        order.extend(local);
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::metrics;
+    use crate::testing::{NodeSetup, TestCluster};
+
+    fn zones(json: &str) -> Zones {
+        serde_json::from_str(json).unwrap()
+    }
+
+    // 先发其他域，域之间轮流，本域最后；配置不合法时拒绝
+    #[test]
+    fn sends_across_zones_first() {
+        assert_eq!(Zones::default().broadcast_order(1), vec![0, 2, 3]);
+        let two = zones(r#"{"a": [0, 1], "b": [2, 3]}"#);
+        assert_eq!(two.broadcast_order(0), vec![2, 3, 1]);
+        assert_eq!(two.label(3), "b");
+        let spread = zones(r#"{"a": [0], "b": [1, 2]}"#);
+        // 节点3不属于任何域，与域b轮流
+        assert_eq!(spread.broadcast_order(0), vec![3, 1, 2]);
+        assert_eq!(spread.label(3), "none");
+
+        assert!(zones(r#"{"a": [0, 1], "b": [1]}"#).validate().is_err());
+        assert!(zones(&format!(r#"{{"a": [{}]}}"#, N)).validate().is_err());
+        assert!(two.validate().is_ok());
+    }
+
+    // 配置了故障域的集群照常提交，并按域记录投票到达时间
+    #[tokio::test]
+    async fn cluster_records_vote_arrival_per_zone() {
+        tokio::task::LocalSet::new().run_until(async {
+            let mut builder = TestCluster::builder();
+            for id in 0..N {
+                builder = builder.setup(id, NodeSetup { zones: zones(r#"{"near": [0, 1], "far": [2, 3]}"#), ..NodeSetup::default() });
+            }
+            let cluster = builder.build().await;
+            let samples = |zone: &str| metrics::snapshot().get(&format!("vote_arrival_samples_total{{zone=\"{}\",kind=\"Prepare\"}}", zone)).copied().unwrap_or(0);
+            let before = (samples("near"), samples("far"));
+
+            cluster.submit("SET zoned yes").await;
+            let committed = cluster.wait_until(Duration::from_secs(5), |c| (0..N).all(|id| c.committed_view(id, "SET zoned yes").is_some())).await;
+            assert!(committed, "请求未提交");
+            assert!(samples("near") > before.0 && samples("far") > before.1, "没有按域记录投票到达时间");
+        }).await;
+    }
+}